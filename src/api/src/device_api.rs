@@ -5,6 +5,10 @@ use axum::{
 };
 use types::{
     devices::{
+        device::opcua::{
+            BrowseNextReq, BrowseReq, BrowseResp, ClientCertificateResp, TranslateBrowsePathReq,
+            TranslateBrowsePathResp,
+        },
         DeviceSourceGroupListResp, DeviceSourceGroupQueryParams, DeviceSourceGroupReadResp,
         DeviceSourceGroupUpdateReq, ListSourcesSinksResp, ReadSourceSinkResp,
         SourceSinkCreateUpdateReq, SourceSinkQueryParams,
@@ -27,6 +31,10 @@ pub fn routes() -> Router {
         .nest(
             "/:device_id",
             Router::new()
+                .route("/browse", post(browse))
+                .route("/browse_next", post(browse_next))
+                .route("/translate_browse_path", post(translate_browse_path))
+                .route("/client_certificate", get(get_client_certificate))
                 .nest(
                     "/source",
                     Router::new()
@@ -180,6 +188,37 @@ async fn delete_device(Path(device_id): Path<String>) -> AppResult<()> {
     Ok(())
 }
 
+async fn browse(
+    Path(device_id): Path<String>,
+    Json(req): Json<BrowseReq>,
+) -> AppResult<Json<BrowseResp>> {
+    let resp = devices::browse(device_id, req).await?;
+    Ok(Json(resp))
+}
+
+async fn browse_next(
+    Path(device_id): Path<String>,
+    Json(req): Json<BrowseNextReq>,
+) -> AppResult<Json<BrowseResp>> {
+    let resp = devices::browse_next(device_id, req).await?;
+    Ok(Json(resp))
+}
+
+async fn translate_browse_path(
+    Path(device_id): Path<String>,
+    Json(req): Json<TranslateBrowsePathReq>,
+) -> AppResult<Json<TranslateBrowsePathResp>> {
+    let resp = devices::translate_browse_path(device_id, req).await?;
+    Ok(Json(resp))
+}
+
+async fn get_client_certificate(
+    Path(device_id): Path<String>,
+) -> AppResult<Json<ClientCertificateResp>> {
+    let resp = devices::get_client_certificate(device_id).await?;
+    Ok(Json(resp))
+}
+
 async fn create_source(
     Path(device_id): Path<String>,
     Json(req): Json<SourceSinkCreateUpdateReq>,
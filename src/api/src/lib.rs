@@ -68,8 +68,10 @@ impl From<HaliaError> for AppError {
             HaliaError::AddressExists => todo!(),
             HaliaError::Disconnect => todo!(),
             HaliaError::Error(e) => AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-            HaliaError::NotSupportResource => todo!(),
-            HaliaError::Base64DecodeErr(_) => todo!(),
+            HaliaError::NotSupportResource => {
+                AppError::new(StatusCode::BAD_REQUEST, err.to_string())
+            }
+            HaliaError::Base64DecodeErr(e) => AppError::new(StatusCode::BAD_REQUEST, e),
             HaliaError::Form(e) => AppError::new(StatusCode::BAD_REQUEST, e),
         }
     }
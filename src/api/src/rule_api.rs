@@ -9,7 +9,9 @@ use axum::{
 };
 use futures_util::Stream;
 use types::{
-    rules::{CreateUpdateRuleReq, ListRulesResp, QueryParams, ReadRuleResp},
+    rules::{
+        CreateUpdateRuleReq, CreateUpdateRuleSqlReq, ListRulesResp, QueryParams, ReadRuleResp,
+    },
     Pagination, Summary,
 };
 
@@ -19,9 +21,11 @@ pub fn routes() -> Router {
     Router::new()
         .route("/summary", get(get_summary))
         .route("/", post(create))
+        .route("/sql", post(create_by_sql))
         .route("/list", get(list_rules))
         .route("/:id", get(read))
         .route("/:id", put(update))
+        .route("/:id/sql", put(update_by_sql))
         .route("/:id/start", put(start))
         .route("/:id/stop", put(stop))
         .route("/:id", routing::delete(delete))
@@ -41,6 +45,11 @@ async fn create(Json(req): Json<CreateUpdateRuleReq>) -> AppResult<()> {
     Ok(())
 }
 
+async fn create_by_sql(Json(req): Json<CreateUpdateRuleSqlReq>) -> AppResult<()> {
+    rule::create_by_sql(req).await?;
+    Ok(())
+}
+
 async fn list_rules(
     Query(pagination): Query<Pagination>,
     Query(query_params): Query<QueryParams>,
@@ -90,6 +99,14 @@ async fn update(Path(id): Path<String>, Json(req): Json<CreateUpdateRuleReq>) ->
     Ok(())
 }
 
+async fn update_by_sql(
+    Path(id): Path<String>,
+    Json(req): Json<CreateUpdateRuleSqlReq>,
+) -> AppResult<()> {
+    rule::update_by_sql(id, req).await?;
+    Ok(())
+}
+
 async fn start_log(Path(id): Path<String>) -> AppResult<()> {
     rule::start_log(id).await?;
     Ok(())
@@ -0,0 +1,449 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use coap_protocol::{
+    request::{CoapRequest, Method, Status},
+    server::{RequestHandler, Server},
+};
+use common::error::{HaliaError, HaliaResult};
+use dashmap::DashMap;
+use futures::lock::BiLock;
+use halia_derive::ResourceErr;
+use message::RuleMessageBatch;
+use sink::Sink;
+use source::Source;
+use tokio::{
+    select,
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::warn;
+use types::apps::coap_server::{CoapServerConf, SinkConf, SourceConf};
+use utils::ErrorManager;
+
+use crate::{
+    server_task::{normalize_path, send_mb, ServerTask},
+    App,
+};
+
+mod sink;
+mod source;
+
+// 内置coap服务器，设备通过POST/PUT推送数据至源，规则输出的最新数据可通过GET/Observe读取
+#[derive(ResourceErr)]
+pub struct CoapServer {
+    err: BiLock<Option<Arc<String>>>,
+    stop_signal_tx: watch::Sender<()>,
+    sources: Arc<DashMap<String, Source>>,
+    sinks: DashMap<String, Sink>,
+    // 路径及规则输出的最新数据
+    resources: Arc<DashMap<String, Vec<u8>>>,
+    resource_tx: UnboundedSender<(String, Vec<u8>)>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+}
+
+struct TaskLoop {
+    conf: CoapServerConf,
+    server_task: ServerTask,
+    sources: Arc<DashMap<String, Source>>,
+    resources: Arc<DashMap<String, Vec<u8>>>,
+    resource_rx: UnboundedReceiver<(String, Vec<u8>)>,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            loop {
+                let addr = format!("{}:{}", self.conf.host, self.conf.port);
+                let server = match self
+                    .server_task
+                    .bind(&addr, |addr| async move { Server::new_udp(addr) })
+                    .await
+                {
+                    Some(server) => server,
+                    None => return self,
+                };
+
+                // 新启动的服务器中没有资源，需重新写入，否则客户端无法observe
+                let observe_handle = server.observe_handle();
+                for resource in self.resources.iter() {
+                    observe_handle
+                        .resource_changed(resource.key(), resource.value().clone())
+                        .await;
+                }
+
+                let run = server.run(Handler {
+                    sources: self.sources.clone(),
+                    resources: self.resources.clone(),
+                });
+                tokio::pin!(run);
+
+                loop {
+                    select! {
+                        _ = self.server_task.stop_signal_rx.changed() => {
+                            return self;
+                        }
+
+                        res = &mut run => {
+                            if let Err(e) = res {
+                                self.server_task.set_err(e).await;
+                            }
+                            break;
+                        }
+
+                        Some((path, payload)) = self.resource_rx.recv() => {
+                            observe_handle.resource_changed(&path, payload).await;
+                        }
+                    }
+                }
+
+                if self.server_task.wait_retry().await {
+                    return self;
+                }
+            }
+        })
+    }
+}
+
+struct Handler {
+    sources: Arc<DashMap<String, Source>>,
+    resources: Arc<DashMap<String, Vec<u8>>>,
+}
+
+#[async_trait]
+impl RequestHandler for Handler {
+    async fn handle_request(
+        &self,
+        mut request: Box<CoapRequest<SocketAddr>>,
+    ) -> Box<CoapRequest<SocketAddr>> {
+        let path = request.get_path();
+        let (status, payload) = match request.get_method() {
+            Method::Post | Method::Put => {
+                (self.handle_write(&path, &request.message.payload), None)
+            }
+            Method::Get => match self.resources.get(&path) {
+                Some(resource) => (Status::Content, Some(resource.value().clone())),
+                None => (Status::NotFound, None),
+            },
+            _ => (Status::MethodNotAllowed, None),
+        };
+
+        if let Some(response) = request.response.as_mut() {
+            response.set_status(status);
+            if let Some(payload) = payload {
+                response.message.payload = payload;
+            }
+        }
+        request
+    }
+}
+
+impl Handler {
+    // 同一路径的源解码方式可能不同，某个源解码失败不影响其余的源，全部失败时才返回BadRequest
+    fn handle_write(&self, path: &str, payload: &[u8]) -> Status {
+        let payload = Bytes::copy_from_slice(payload);
+        let mut matched = false;
+        let mut delivered = false;
+        for mut source in self.sources.iter_mut() {
+            if source.path != path {
+                continue;
+            }
+            matched = true;
+
+            let mb = match source.decoder.decode(payload.clone()) {
+                Ok(mb) => mb,
+                Err(e) => {
+                    warn!("decode err :{}", e);
+                    continue;
+                }
+            };
+
+            send_mb(&mut source.mb_txs, mb);
+            delivered = true;
+        }
+
+        match (matched, delivered) {
+            (false, _) => Status::NotFound,
+            (true, true) => Status::Changed,
+            (true, false) => Status::BadRequest,
+        }
+    }
+}
+
+pub fn new(app_id: String, conf: serde_json::Value) -> Box<dyn App> {
+    let conf: CoapServerConf = serde_json::from_value(conf).unwrap();
+    let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let (resource_tx, resource_rx) = mpsc::unbounded_channel();
+    let sources = Arc::new(DashMap::new());
+    let resources = Arc::new(DashMap::new());
+    let (app_err1, app_err2) = BiLock::new(None);
+
+    let error_manager =
+        ErrorManager::new(utils::error_manager::ResourceType::App, app_id, app_err1);
+    let task_loop = TaskLoop {
+        conf,
+        server_task: ServerTask::new("coap", stop_signal_rx, error_manager),
+        sources: sources.clone(),
+        resources: resources.clone(),
+        resource_rx,
+    };
+    let join_handle = task_loop.start();
+
+    Box::new(CoapServer {
+        err: app_err2,
+        stop_signal_tx,
+        sources,
+        sinks: DashMap::new(),
+        resources,
+        resource_tx,
+        join_handle: Some(join_handle),
+    })
+}
+
+pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let _conf: CoapServerConf = serde_json::from_value(conf.clone())?;
+    Ok(())
+}
+
+pub async fn process_source_conf(
+    app_id: &String,
+    source_id: &String,
+    conf: &serde_json::Value,
+) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::process_conf(app_id, source_id, &conf).await
+}
+
+// 应用未启动时没有运行中的动作，路径是否重复需与已保存的动作比较，更新时排除自身
+pub async fn process_sink_conf(
+    app_id: &String,
+    sink_id: Option<&String>,
+    conf: &serde_json::Value,
+) -> HaliaResult<()> {
+    let conf: SinkConf = serde_json::from_value(conf.clone())?;
+    Sink::validate_conf(&conf)?;
+
+    let path = normalize_path(&conf.path);
+    let db_sinks = storage::app::source_sink::read_all_sinks_by_app_id(app_id).await?;
+    for db_sink in db_sinks {
+        if Some(&db_sink.id) == sink_id {
+            continue;
+        }
+        let db_conf: SinkConf = serde_json::from_value(db_sink.conf)?;
+        if normalize_path(&db_conf.path) == path {
+            return Err(HaliaError::Common(format!("路径{}已存在！", path)));
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl App for CoapServer {
+    async fn read_app_err(&self) -> Option<Arc<String>> {
+        self.read_err().await
+    }
+
+    async fn update(
+        &mut self,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: CoapServerConf = serde_json::from_value(new_conf)?;
+
+        self.stop_signal_tx.send(()).unwrap();
+        let mut task_loop = self.join_handle.take().unwrap().await.unwrap();
+        task_loop.conf = new_conf;
+        self.join_handle = Some(task_loop.start());
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        for mut sink in self.sinks.iter_mut() {
+            sink.stop().await;
+        }
+        self.stop_signal_tx.send(()).unwrap();
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.await;
+        }
+    }
+
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        let source = Source::new(conf).await?;
+        self.sources.insert(source_id, source);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: String,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: SourceConf = serde_json::from_value(new_conf)?;
+        match self.sources.get_mut(&source_id) {
+            Some(mut source) => source.update_conf(new_conf).await,
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn delete_source(&mut self, source_id: String) -> HaliaResult<()> {
+        match self.sources.remove(&source_id) {
+            Some(_) => Ok(()),
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
+        let conf: SinkConf = serde_json::from_value(conf)?;
+        let sink = Sink::new(conf, self.resources.clone(), self.resource_tx.clone()).await?;
+        self.sinks.insert(sink_id, sink);
+        Ok(())
+    }
+
+    async fn update_sink(
+        &mut self,
+        sink_id: String,
+        old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let old_conf: SinkConf = serde_json::from_value(old_conf)?;
+        let new_conf: SinkConf = serde_json::from_value(new_conf)?;
+        match self.sinks.get_mut(&sink_id) {
+            Some(mut sink) => sink.update_conf(old_conf, new_conf).await,
+            None => Err(HaliaError::NotFound(sink_id)),
+        }
+    }
+
+    async fn delete_sink(&mut self, sink_id: String) -> HaliaResult<()> {
+        match self.sinks.remove(&sink_id) {
+            Some((_, mut sink)) => {
+                sink.stop().await;
+                self.resources.remove(&sink.path);
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(sink_id)),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt)),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn get_sink_txs(
+        &self,
+        sink_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedSender<RuleMessageBatch>>> {
+        match self.sinks.get(sink_id) {
+            Some(sink) => Ok(sink.get_txs(cnt)),
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coap_protocol::request::{CoapResponse, RequestBuilder};
+    use types::schema::DecodeType;
+
+    use super::*;
+
+    fn new_request(path: &str, method: Method, payload: &[u8]) -> Box<CoapRequest<SocketAddr>> {
+        let mut request = RequestBuilder::new(path, method)
+            .data(Some(payload.to_vec()))
+            .build();
+        request.response = CoapResponse::new(&request.message);
+        Box::new(request)
+    }
+
+    fn get_status(request: &CoapRequest<SocketAddr>) -> &Status {
+        request.response.as_ref().unwrap().get_status()
+    }
+
+    #[tokio::test]
+    async fn test_handle_request() {
+        let sources = Arc::new(DashMap::new());
+        let resources = Arc::new(DashMap::new());
+        let mut source = Source::new(SourceConf {
+            path: "/sensors/temp/".to_owned(),
+            decode_type: DecodeType::Json,
+            schema_id: None,
+        })
+        .await
+        .unwrap();
+        let mut rx = source.get_rxs(1).pop().unwrap();
+        sources.insert("source".to_owned(), source);
+        resources.insert("out".to_owned(), b"42".to_vec());
+
+        let handler = Handler { sources, resources };
+
+        let request = handler
+            .handle_request(new_request("sensors/temp", Method::Post, br#"{"t":1}"#))
+            .await;
+        assert_eq!(get_status(&request), &Status::Changed);
+        assert_eq!(rx.recv().await.unwrap().take_mb().len(), 1);
+
+        let request = handler
+            .handle_request(new_request("sensors/temp", Method::Put, b"not json"))
+            .await;
+        assert_eq!(get_status(&request), &Status::BadRequest);
+
+        let request = handler
+            .handle_request(new_request("unknown", Method::Put, b"{}"))
+            .await;
+        assert_eq!(get_status(&request), &Status::NotFound);
+
+        let request = handler
+            .handle_request(new_request("out", Method::Get, b""))
+            .await;
+        assert_eq!(get_status(&request), &Status::Content);
+        assert_eq!(request.response.unwrap().message.payload, b"42");
+    }
+
+    #[tokio::test]
+    async fn test_handle_write_multiple_sources() {
+        let sources = Arc::new(DashMap::new());
+        let mut rxs = vec![];
+        for (id, decode_type) in [("json", DecodeType::Json), ("raw", DecodeType::Raw)] {
+            let mut source = Source::new(SourceConf {
+                path: "data".to_owned(),
+                decode_type,
+                schema_id: None,
+            })
+            .await
+            .unwrap();
+            rxs.push(source.get_rxs(1).pop().unwrap());
+            sources.insert(id.to_owned(), source);
+        }
+        let handler = Handler {
+            sources,
+            resources: Arc::new(DashMap::new()),
+        };
+
+        // json源解码失败时raw源仍能收到数据
+        let request = handler
+            .handle_request(new_request("data", Method::Post, b"not json"))
+            .await;
+        assert_eq!(get_status(&request), &Status::Changed);
+        assert!(rxs[0].try_recv().is_err());
+        assert_eq!(rxs[1].try_recv().unwrap().take_mb().len(), 1);
+    }
+}
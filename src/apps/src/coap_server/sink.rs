@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use common::error::{HaliaError, HaliaResult};
+use dashmap::DashMap;
+use halia_derive::{ResourceStop, SinkTxs};
+use message::RuleMessageBatch;
+use schema::Encoder;
+use tokio::{
+    select,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::warn;
+use types::apps::coap_server::SinkConf;
+
+use crate::server_task::normalize_path;
+
+#[derive(ResourceStop, SinkTxs)]
+pub struct Sink {
+    pub path: String,
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_tx: UnboundedSender<RuleMessageBatch>,
+}
+
+pub struct TaskLoop {
+    path: String,
+    encoder: Box<dyn Encoder>,
+    resources: Arc<DashMap<String, Vec<u8>>>,
+    resource_tx: UnboundedSender<(String, Vec<u8>)>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_rx: UnboundedReceiver<RuleMessageBatch>,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    Some(mb) = self.mb_rx.recv() => {
+                        self.handle_data(mb);
+                    }
+                }
+            }
+        })
+    }
+
+    fn handle_data(&mut self, rmb: RuleMessageBatch) {
+        let mb = rmb.take_mb();
+        if mb.get_messages().is_empty() {
+            return;
+        }
+
+        let payload = match self.encoder.encode(mb) {
+            Ok(data) => data.to_vec(),
+            Err(e) => {
+                warn!("{:?}", e);
+                return;
+            }
+        };
+
+        self.resources.insert(self.path.clone(), payload.clone());
+        // 通知观察该路径的客户端
+        let _ = self.resource_tx.send((self.path.clone(), payload));
+    }
+}
+
+impl Sink {
+    pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
+        if normalize_path(&conf.path).is_empty() {
+            return Err(HaliaError::Common("路径不能为空！".to_owned()));
+        }
+
+        Ok(())
+    }
+
+    pub async fn new(
+        sink_conf: SinkConf,
+        resources: Arc<DashMap<String, Vec<u8>>>,
+        resource_tx: UnboundedSender<(String, Vec<u8>)>,
+    ) -> HaliaResult<Self> {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (mb_tx, mb_rx) = unbounded_channel();
+
+        let encoder = schema::new_encoder(&sink_conf.encode_type, &sink_conf.schema_id).await?;
+        let path = normalize_path(&sink_conf.path);
+        let task_loop = TaskLoop {
+            path: path.clone(),
+            encoder,
+            resources,
+            resource_tx,
+            stop_signal_rx,
+            mb_rx,
+        };
+        let join_handle = task_loop.start();
+
+        Ok(Self {
+            path,
+            mb_tx,
+            stop_signal_tx,
+            join_handle: Some(join_handle),
+        })
+    }
+
+    pub async fn update_conf(&mut self, old_conf: SinkConf, new_conf: SinkConf) -> HaliaResult<()> {
+        let mut task_loop = self.stop().await;
+        if old_conf.encode_type != new_conf.encode_type || old_conf.schema_id != new_conf.schema_id
+        {
+            match schema::new_encoder(&new_conf.encode_type, &new_conf.schema_id).await {
+                Ok(encoder) => task_loop.encoder = encoder,
+                Err(e) => {
+                    self.join_handle = Some(task_loop.start());
+                    return Err(e);
+                }
+            }
+        }
+
+        let path = normalize_path(&new_conf.path);
+        if path != self.path {
+            task_loop.resources.remove(&self.path);
+            task_loop.path = path.clone();
+            self.path = path;
+        }
+        self.join_handle = Some(task_loop.start());
+        Ok(())
+    }
+}
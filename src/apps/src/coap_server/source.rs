@@ -0,0 +1,79 @@
+use common::error::{HaliaError, HaliaResult};
+use message::RuleMessageBatch;
+use schema::Decoder;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use types::apps::coap_server::SourceConf;
+
+use crate::server_task::normalize_path;
+
+pub struct Source {
+    pub source_conf: SourceConf,
+    // 与请求中Uri-Path拼接后的格式一致，不含首尾的/
+    pub path: String,
+    pub mb_txs: Vec<UnboundedSender<RuleMessageBatch>>,
+    pub decoder: Box<dyn Decoder>,
+}
+
+impl Source {
+    pub async fn new(source_conf: SourceConf) -> HaliaResult<Self> {
+        let decoder = schema::new_decoder(&source_conf.decode_type, &source_conf.schema_id).await?;
+
+        Ok(Source {
+            path: normalize_path(&source_conf.path),
+            source_conf,
+            mb_txs: vec![],
+            decoder,
+        })
+    }
+
+    pub async fn update_conf(&mut self, new_conf: SourceConf) -> HaliaResult<()> {
+        if self.source_conf.decode_type != new_conf.decode_type
+            || self.source_conf.schema_id != new_conf.schema_id
+        {
+            self.decoder = schema::new_decoder(&new_conf.decode_type, &new_conf.schema_id).await?;
+        }
+        self.path = normalize_path(&new_conf.path);
+        self.source_conf = new_conf;
+        Ok(())
+    }
+
+    pub async fn process_conf(
+        app_id: &String,
+        source_id: &String,
+        conf: &SourceConf,
+    ) -> HaliaResult<()> {
+        if normalize_path(&conf.path).is_empty() {
+            return Err(HaliaError::Common("路径不能为空！".to_owned()));
+        }
+
+        match conf.decode_type {
+            types::schema::DecodeType::Protobuf => match &conf.schema_id {
+                Some(schema_id) => {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+                None => return Err(HaliaError::Common("请填写schema_id".to_owned())),
+            },
+            types::schema::DecodeType::Csv | types::schema::DecodeType::Avro => {
+                if let Some(schema_id) = &conf.schema_id {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+            }
+            types::schema::DecodeType::Raw
+            | types::schema::DecodeType::Yaml
+            | types::schema::DecodeType::Json
+            | types::schema::DecodeType::Toml => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn get_rxs(&mut self, cnt: usize) -> Vec<UnboundedReceiver<RuleMessageBatch>> {
+        let mut rxs = vec![];
+        for _ in 0..cnt {
+            let (tx, rx) = unbounded_channel();
+            self.mb_txs.push(tx);
+            rxs.push(rx);
+        }
+        rxs
+    }
+}
@@ -0,0 +1,410 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header::WWW_AUTHENTICATE, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use common::error::{HaliaError, HaliaResult};
+use dashmap::DashMap;
+use futures::lock::BiLock;
+use halia_derive::ResourceErr;
+use message::RuleMessageBatch;
+use source::Source;
+use tokio::{
+    net::TcpListener,
+    select,
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::warn;
+use types::apps::http_server::{AuthMethod, HttpServerConf, SourceConf};
+use utils::ErrorManager;
+
+use crate::{
+    server_task::{normalize_path, send_mb, ServerTask},
+    App,
+};
+
+mod source;
+
+// 内置http服务器，外部系统通过POST/PUT以webhook的形式推送数据至源
+#[derive(ResourceErr)]
+pub struct HttpServer {
+    err: BiLock<Option<Arc<String>>>,
+    stop_signal_tx: watch::Sender<()>,
+    sources: Arc<DashMap<String, Source>>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+}
+
+struct TaskLoop {
+    conf: HttpServerConf,
+    server_task: ServerTask,
+    sources: Arc<DashMap<String, Source>>,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            loop {
+                let addr = format!("{}:{}", self.conf.host, self.conf.port);
+                let listener = match self
+                    .server_task
+                    .bind(&addr, |addr| async move { TcpListener::bind(addr).await })
+                    .await
+                {
+                    Some(listener) => listener,
+                    None => return self,
+                };
+
+                let router = Router::new()
+                    .fallback(handle_request)
+                    .with_state(self.sources.clone());
+
+                select! {
+                    _ = self.server_task.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    res = axum::serve(listener, router) => {
+                        if let Err(e) = res {
+                            self.server_task.set_err(e).await;
+                        }
+                    }
+                }
+
+                if self.server_task.wait_retry().await {
+                    return self;
+                }
+            }
+        })
+    }
+}
+
+async fn handle_request(
+    State(sources): State<Arc<DashMap<String, Source>>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    handle_write(&sources, &method, uri.path(), &headers, body)
+}
+
+fn handle_write(
+    sources: &DashMap<String, Source>,
+    method: &Method,
+    path: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = normalize_path(path);
+    let mut source = match sources.iter_mut().find(|source| source.path == path) {
+        Some(source) => source,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    if method != Method::POST && method != Method::PUT {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    if !source.authorize(headers) {
+        return match source.source_conf.auth_method {
+            AuthMethod::Basic => {
+                (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Basic")]).into_response()
+            }
+            _ => StatusCode::UNAUTHORIZED.into_response(),
+        };
+    }
+
+    let mb = match source.decoder.decode(body) {
+        Ok(mb) => mb,
+        Err(e) => {
+            warn!("decode err :{}", e);
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    };
+
+    send_mb(&mut source.mb_txs, mb);
+
+    source.response()
+}
+
+pub fn new(app_id: String, conf: serde_json::Value) -> Box<dyn App> {
+    let conf: HttpServerConf = serde_json::from_value(conf).unwrap();
+    let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let sources = Arc::new(DashMap::new());
+    let (app_err1, app_err2) = BiLock::new(None);
+
+    let error_manager =
+        ErrorManager::new(utils::error_manager::ResourceType::App, app_id, app_err1);
+    let task_loop = TaskLoop {
+        conf,
+        server_task: ServerTask::new("http", stop_signal_rx, error_manager),
+        sources: sources.clone(),
+    };
+    let join_handle = task_loop.start();
+
+    Box::new(HttpServer {
+        err: app_err2,
+        stop_signal_tx,
+        sources,
+        join_handle: Some(join_handle),
+    })
+}
+
+pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let _conf: HttpServerConf = serde_json::from_value(conf.clone())?;
+    Ok(())
+}
+
+pub async fn process_source_conf(
+    app_id: &String,
+    source_id: &String,
+    conf: &serde_json::Value,
+) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    check_source_path(app_id, source_id, &conf).await?;
+    Source::process_conf(app_id, source_id, &conf).await
+}
+
+// 应用停止时源只写入数据库，需要与数据库中的源比较，避免启动应用时路径冲突
+pub async fn check_source_path(
+    app_id: &String,
+    source_id: &String,
+    conf: &SourceConf,
+) -> HaliaResult<()> {
+    let path = normalize_path(&conf.path);
+    let db_sources = storage::app::source_sink::read_all_sources_by_app_id(app_id).await?;
+    for db_source in db_sources {
+        if db_source.id == *source_id {
+            continue;
+        }
+        let db_conf: SourceConf = serde_json::from_value(db_source.conf)?;
+        if normalize_path(&db_conf.path) == path {
+            return Err(HaliaError::Common(format!("路径{}已存在！", path)));
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl App for HttpServer {
+    async fn read_app_err(&self) -> Option<Arc<String>> {
+        self.read_err().await
+    }
+
+    async fn update(
+        &mut self,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: HttpServerConf = serde_json::from_value(new_conf)?;
+
+        self.stop_signal_tx.send(()).unwrap();
+        let mut task_loop = self.join_handle.take().unwrap().await.unwrap();
+        task_loop.conf = new_conf;
+        self.join_handle = Some(task_loop.start());
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        self.stop_signal_tx.send(()).unwrap();
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.await;
+        }
+    }
+
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        let source = Source::new(conf).await?;
+        self.sources.insert(source_id, source);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: String,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: SourceConf = serde_json::from_value(new_conf)?;
+        match self.sources.get_mut(&source_id) {
+            Some(mut source) => source.update_conf(new_conf).await,
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn delete_source(&mut self, source_id: String) -> HaliaResult<()> {
+        match self.sources.remove(&source_id) {
+            Some(_) => Ok(()),
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt)),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn create_sink(&mut self, _sink_id: String, _conf: serde_json::Value) -> HaliaResult<()> {
+        Err(HaliaError::NotSupportResource)
+    }
+
+    async fn update_sink(
+        &mut self,
+        _sink_id: String,
+        _old_conf: serde_json::Value,
+        _new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        Err(HaliaError::NotSupportResource)
+    }
+
+    async fn delete_sink(&mut self, _sink_id: String) -> HaliaResult<()> {
+        Err(HaliaError::NotSupportResource)
+    }
+
+    async fn get_sink_txs(
+        &self,
+        _sink_id: &String,
+        _cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedSender<RuleMessageBatch>>> {
+        Err(HaliaError::NotSupportResource)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header::AUTHORIZATION, HeaderValue};
+    use types::{
+        apps::http_server::{BasicAuth, ResponseConf},
+        schema::DecodeType,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handle_write() {
+        let sources = DashMap::new();
+        let mut source = Source::new(SourceConf {
+            path: "/webhook/mes/".to_owned(),
+            auth_method: AuthMethod::Basic,
+            auth_token: None,
+            auth_basic: Some(BasicAuth {
+                username: "mes".to_owned(),
+                password: "secret".to_owned(),
+            }),
+            decode_type: DecodeType::Json,
+            schema_id: None,
+            response: ResponseConf {
+                status: 202,
+                headers: vec![("content-type".to_owned(), "application/json".to_owned())],
+                body: r#"{"code":0}"#.to_owned(),
+            },
+        })
+        .await
+        .unwrap();
+        let mut rx = source.get_rxs(1).pop().unwrap();
+        sources.insert("source".to_owned(), source);
+
+        let mut headers = HeaderMap::new();
+        let body = Bytes::from_static(br#"{"t":1}"#);
+
+        let resp = handle_write(
+            &sources,
+            &Method::POST,
+            "/webhook/mes",
+            &headers,
+            body.clone(),
+        );
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(resp.headers().contains_key(WWW_AUTHENTICATE));
+
+        // mes:secret
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic bWVzOnNlY3JldA=="),
+        );
+        let resp = handle_write(
+            &sources,
+            &Method::POST,
+            "/webhook/mes",
+            &headers,
+            body.clone(),
+        );
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        assert_eq!(rx.recv().await.unwrap().take_mb().len(), 1);
+
+        // 认证方案不区分大小写
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("basic bWVzOnNlY3JldA=="),
+        );
+        let resp = handle_write(
+            &sources,
+            &Method::POST,
+            "/webhook/mes",
+            &headers,
+            body.clone(),
+        );
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        assert_eq!(rx.recv().await.unwrap().take_mb().len(), 1);
+
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic bWVzOnNlY3JldA=a"),
+        );
+        let resp = handle_write(
+            &sources,
+            &Method::POST,
+            "/webhook/mes",
+            &headers,
+            body.clone(),
+        );
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("basic bWVzOnNlY3JldA=="),
+        );
+        let resp = handle_write(
+            &sources,
+            &Method::PUT,
+            "webhook/mes",
+            &headers,
+            Bytes::from_static(b"not json"),
+        );
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = handle_write(
+            &sources,
+            &Method::GET,
+            "/webhook/mes",
+            &headers,
+            body.clone(),
+        );
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let resp = handle_write(&sources, &Method::POST, "/unknown", &headers, body);
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,175 @@
+use axum::{
+    http::{header::AUTHORIZATION, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use common::error::{HaliaError, HaliaResult};
+use message::RuleMessageBatch;
+use schema::Decoder;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use types::apps::http_server::{AuthMethod, ResponseConf, SourceConf};
+
+use crate::server_task::normalize_path;
+
+pub struct Source {
+    pub source_conf: SourceConf,
+    // 不含首尾的/
+    pub path: String,
+    pub mb_txs: Vec<UnboundedSender<RuleMessageBatch>>,
+    pub decoder: Box<dyn Decoder>,
+    // 请求中Authorization头需要与之一致，为None时不鉴权
+    authorization: Option<String>,
+    response_status: StatusCode,
+    response_headers: HeaderMap,
+}
+
+impl Source {
+    pub async fn new(source_conf: SourceConf) -> HaliaResult<Self> {
+        let decoder = schema::new_decoder(&source_conf.decode_type, &source_conf.schema_id).await?;
+        let (response_status, response_headers) = parse_response(&source_conf.response)?;
+
+        Ok(Source {
+            path: normalize_path(&source_conf.path),
+            authorization: get_authorization(&source_conf),
+            response_status,
+            response_headers,
+            source_conf,
+            mb_txs: vec![],
+            decoder,
+        })
+    }
+
+    pub async fn update_conf(&mut self, new_conf: SourceConf) -> HaliaResult<()> {
+        if self.source_conf.decode_type != new_conf.decode_type
+            || self.source_conf.schema_id != new_conf.schema_id
+        {
+            self.decoder = schema::new_decoder(&new_conf.decode_type, &new_conf.schema_id).await?;
+        }
+        (self.response_status, self.response_headers) = parse_response(&new_conf.response)?;
+        self.path = normalize_path(&new_conf.path);
+        self.authorization = get_authorization(&new_conf);
+        self.source_conf = new_conf;
+        Ok(())
+    }
+
+    pub async fn process_conf(
+        app_id: &String,
+        source_id: &String,
+        conf: &SourceConf,
+    ) -> HaliaResult<()> {
+        if normalize_path(&conf.path).is_empty() {
+            return Err(HaliaError::Common("路径不能为空！".to_owned()));
+        }
+
+        match conf.auth_method {
+            AuthMethod::None => {}
+            AuthMethod::Token => match &conf.auth_token {
+                Some(token) if !token.is_empty() => {}
+                _ => return Err(HaliaError::Common("token不能为空！".to_owned())),
+            },
+            AuthMethod::Basic => match &conf.auth_basic {
+                Some(basic) if !basic.username.is_empty() => {}
+                _ => return Err(HaliaError::Common("用户名不能为空！".to_owned())),
+            },
+        }
+
+        parse_response(&conf.response)?;
+
+        match conf.decode_type {
+            types::schema::DecodeType::Protobuf => match &conf.schema_id {
+                Some(schema_id) => {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+                None => return Err(HaliaError::Common("请填写schema_id".to_owned())),
+            },
+            types::schema::DecodeType::Csv | types::schema::DecodeType::Avro => {
+                if let Some(schema_id) = &conf.schema_id {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+            }
+            types::schema::DecodeType::Raw
+            | types::schema::DecodeType::Yaml
+            | types::schema::DecodeType::Json
+            | types::schema::DecodeType::Toml => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn get_rxs(&mut self, cnt: usize) -> Vec<UnboundedReceiver<RuleMessageBatch>> {
+        let mut rxs = vec![];
+        for _ in 0..cnt {
+            let (tx, rx) = unbounded_channel();
+            self.mb_txs.push(tx);
+            rxs.push(rx);
+        }
+        rxs
+    }
+
+    pub fn authorize(&self, headers: &HeaderMap) -> bool {
+        match &self.authorization {
+            Some(authorization) => headers.get(AUTHORIZATION).is_some_and(|value| {
+                match_authorization(value.as_bytes(), authorization.as_bytes())
+            }),
+            None => true,
+        }
+    }
+
+    pub fn response(&self) -> Response {
+        (
+            self.response_status,
+            self.response_headers.clone(),
+            self.source_conf.response.body.clone(),
+        )
+            .into_response()
+    }
+}
+
+// 比较耗时与首个不一致字节的位置无关，避免通过响应时间逐字节猜测凭证
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 认证方案不区分大小写，凭证部分按常量时间比较
+fn match_authorization(value: &[u8], authorization: &[u8]) -> bool {
+    if value.len() != authorization.len() {
+        return false;
+    }
+    let scheme_len = authorization
+        .iter()
+        .position(|b| *b == b' ')
+        .unwrap_or(authorization.len());
+    value[..scheme_len].eq_ignore_ascii_case(&authorization[..scheme_len])
+        && constant_time_eq(&value[scheme_len..], &authorization[scheme_len..])
+}
+
+fn get_authorization(conf: &SourceConf) -> Option<String> {
+    match conf.auth_method {
+        AuthMethod::None => None,
+        AuthMethod::Token => conf
+            .auth_token
+            .as_ref()
+            .map(|token| format!("Bearer {}", token)),
+        AuthMethod::Basic => conf.auth_basic.as_ref().map(|basic| {
+            let credentials = format!("{}:{}", basic.username, basic.password);
+            format!("Basic {}", BASE64_STANDARD.encode(credentials))
+        }),
+    }
+}
+
+fn parse_response(conf: &ResponseConf) -> HaliaResult<(StatusCode, HeaderMap)> {
+    let status = StatusCode::from_u16(conf.status)
+        .map_err(|_| HaliaError::Common(format!("响应状态码{}不合法！", conf.status)))?;
+    let mut headers = HeaderMap::new();
+    for (key, value) in &conf.headers {
+        let key = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|_| HaliaError::Common(format!("响应头{}不合法！", key)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| HaliaError::Common(format!("响应头{}的值不合法！", key)))?;
+        headers.append(key, value);
+    }
+    Ok((status, headers))
+}
@@ -1,13 +1,19 @@
-use std::sync::Arc;
+use std::{io::Write as _, sync::Arc};
 
 use async_trait::async_trait;
 use common::error::{HaliaError, HaliaResult};
 use dashmap::DashMap;
+use flate2::{write::GzEncoder, Compression};
 use futures::lock::BiLock;
 use halia_derive::ResourceErr;
 use influxdb::Client;
 use message::RuleMessageBatch;
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_ENCODING},
+    StatusCode,
+};
 use sink::Sink;
+use source::Source;
 use tokio::{
     select,
     sync::{
@@ -16,16 +22,18 @@ use tokio::{
     },
     task::JoinHandle,
 };
-use types::apps::influxdb_v1::{InfluxdbConf, SinkConf};
+use types::apps::influxdb_v1::{InfluxdbConf, SinkConf, SourceConf};
 use utils::ErrorManager;
 
 use crate::App;
 
 mod sink;
+mod source;
 
 #[derive(ResourceErr)]
 pub struct Influxdb {
     err: BiLock<Option<Arc<String>>>,
+    sources: DashMap<String, Source>,
     sinks: DashMap<String, Sink>,
     influxdb_conf: Arc<InfluxdbConf>,
     app_err_tx: UnboundedSender<Option<Arc<String>>>,
@@ -43,6 +51,7 @@ pub fn new(app_id: String, app_conf: serde_json::Value) -> Box<dyn App> {
 
     Box::new(Influxdb {
         err: err2,
+        sources: DashMap::new(),
         sinks: DashMap::new(),
         influxdb_conf: Arc::new(influxdb_conf),
         app_err_tx,
@@ -120,6 +129,12 @@ pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     Ok(())
 }
 
+pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::validate_conf(&conf)?;
+    Ok(())
+}
+
 pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     let conf: SinkConf = serde_json::from_value(conf.clone())?;
     Sink::validate_conf(&conf)?;
@@ -139,6 +154,11 @@ impl App for Influxdb {
     ) -> HaliaResult<()> {
         let new_conf: InfluxdbConf = serde_json::from_value(new_conf)?;
         self.influxdb_conf = Arc::new(new_conf);
+        for mut source in self.sources.iter_mut() {
+            source
+                .update_influxdb_client(self.influxdb_conf.clone())
+                .await;
+        }
         for mut sink in self.sinks.iter_mut() {
             sink.update_influxdb_client(self.influxdb_conf.clone())
                 .await;
@@ -149,11 +169,67 @@ impl App for Influxdb {
 
     async fn stop(&mut self) {
         self.stop_signal_tx.send(()).unwrap();
+        for mut source in self.sources.iter_mut() {
+            source.stop().await;
+        }
         for mut sink in self.sinks.iter_mut() {
             sink.stop().await;
         }
     }
 
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        let source = Source::new(
+            source_id.clone(),
+            conf,
+            self.influxdb_conf.clone(),
+            self.app_err_tx.clone(),
+        );
+        self.sources.insert(source_id, source);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: String,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: SourceConf = serde_json::from_value(new_conf)?;
+        match self.sources.get_mut(&source_id) {
+            Some(mut source) => {
+                source.update_conf(new_conf).await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn delete_source(&mut self, source_id: String) -> HaliaResult<()> {
+        match self.sources.remove(&source_id) {
+            Some((_, mut source)) => {
+                source.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt).await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let conf: SinkConf = serde_json::from_value(conf)?;
         let sink = Sink::new(
@@ -161,11 +237,18 @@ impl App for Influxdb {
             conf,
             self.influxdb_conf.clone(),
             self.app_err_tx.clone(),
-        );
+        )?;
         self.sinks.insert(sink_id, sink);
         Ok(())
     }
 
+    async fn read_source_err(&self, source_id: &String) -> HaliaResult<Option<Arc<String>>> {
+        match self.sources.get(source_id) {
+            Some(source) => Ok(source.read_err().await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
     async fn read_sink_err(&self, sink_id: &String) -> HaliaResult<Option<Arc<String>>> {
         match self.sinks.get(sink_id) {
             Some(sink) => Ok(sink.read_err().await),
@@ -181,10 +264,7 @@ impl App for Influxdb {
     ) -> HaliaResult<()> {
         let new_conf: SinkConf = serde_json::from_value(new_conf)?;
         match self.sinks.get_mut(&sink_id) {
-            Some(mut sink) => {
-                sink.update_conf(new_conf).await;
-                Ok(())
-            }
+            Some(mut sink) => sink.update_conf(&sink_id, new_conf).await,
             None => Err(HaliaError::NotFound(sink_id)),
         }
     }
@@ -211,18 +291,19 @@ impl App for Influxdb {
     }
 }
 
-fn new_influxdb_client(influxdb_conf: &Arc<InfluxdbConf>, sink_conf: &SinkConf) -> Client {
+fn get_url(influxdb_conf: &InfluxdbConf) -> String {
     let schema = match &influxdb_conf.ssl_enable {
         true => "https",
         false => "http",
     };
-    let mut client = Client::new(
-        format!(
-            "{}://{}:{}",
-            schema, &influxdb_conf.host, influxdb_conf.port
-        ),
-        &sink_conf.database,
-    );
+    format!(
+        "{}://{}:{}",
+        schema, &influxdb_conf.host, influxdb_conf.port
+    )
+}
+
+fn new_influxdb_client(influxdb_conf: &Arc<InfluxdbConf>, database: &str) -> Client {
+    let mut client = Client::new(get_url(influxdb_conf), database);
 
     match influxdb_conf.auth_method {
         types::apps::influxdb_v1::AuthMethod::None => {}
@@ -256,3 +337,148 @@ fn new_influxdb_client(influxdb_conf: &Arc<InfluxdbConf>, sink_conf: &SinkConf)
 
     client
 }
+
+// influxdb库写入时不支持压缩，且无法区分响应状态码，直接请求/write接口
+async fn write_lines(
+    http_client: &reqwest::Client,
+    influxdb_conf: &InfluxdbConf,
+    database: &str,
+    precision: &str,
+    lines: String,
+    gzip: bool,
+) -> Result<(), influxdb::Error> {
+    let mut builder = http_client
+        .post(format!("{}/write", get_url(influxdb_conf)))
+        .query(&[("db", database), ("precision", precision)]);
+    builder = match gzip {
+        true => {
+            let body = gzip_encode(&lines).map_err(|e| influxdb::Error::InvalidQueryError {
+                error: e.to_string(),
+            })?;
+            builder.header(CONTENT_ENCODING, "gzip").body(body)
+        }
+        false => builder.body(lines),
+    };
+    match influxdb_conf.auth_method {
+        types::apps::influxdb_v1::AuthMethod::None => {}
+        types::apps::influxdb_v1::AuthMethod::Password => {
+            let auth_password = influxdb_conf.auth_password.as_ref().unwrap();
+            builder = builder.query(&[
+                ("u", &auth_password.username),
+                ("p", &auth_password.password),
+            ]);
+        }
+        types::apps::influxdb_v1::AuthMethod::ApiToken => {
+            builder = builder.header(
+                AUTHORIZATION,
+                format!(
+                    "Token {}",
+                    influxdb_conf.auth_api_token.as_ref().unwrap().api_token
+                ),
+            );
+        }
+    }
+
+    let resp = builder
+        .send()
+        .await
+        .map_err(|e| influxdb::Error::ConnectionError {
+            error: e.to_string(),
+        })?;
+    match resp.status() {
+        StatusCode::UNAUTHORIZED => Err(influxdb::Error::AuthorizationError),
+        StatusCode::FORBIDDEN => Err(influxdb::Error::AuthenticationError),
+        status if status.is_success() => Ok(()),
+        // 限流或服务端错误时按连接错误处理，由调用方保留消息等待重发
+        status if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() => {
+            Err(influxdb::Error::ConnectionError {
+                error: format!("{}: {}", status, resp.text().await.unwrap_or_default()),
+            })
+        }
+        status => Err(influxdb::Error::DatabaseError {
+            error: format!("{}: {}", status, resp.text().await.unwrap_or_default()),
+        }),
+    }
+}
+
+fn gzip_encode(lines: &str) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(lines.as_bytes())?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read as _;
+
+    use flate2::read::GzDecoder;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    // 读取一次请求并返回指定状态码，返回收到的请求头
+    async fn serve_once(listener: TcpListener, status: &'static str) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status).as_bytes())
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&buf[..n]).to_lowercase()
+    }
+
+    #[test]
+    fn test_gzip_encode() {
+        let lines = "cpu,host=a value=1.5 1700000000000";
+        let body = gzip_encode(lines).unwrap();
+        let mut decoded = String::new();
+        GzDecoder::new(body.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, lines);
+    }
+
+    #[tokio::test]
+    async fn test_write_lines() {
+        for (status, gzip, retryable) in [
+            ("204 No Content", true, None),
+            ("429 Too Many Requests", false, Some(true)),
+            ("503 Service Unavailable", true, Some(true)),
+            ("400 Bad Request", false, Some(false)),
+        ] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let conf: InfluxdbConf = serde_json::from_value(serde_json::json!({
+                "host": "127.0.0.1",
+                "port": listener.local_addr().unwrap().port(),
+                "auth_method": "none",
+                "auth_password": null,
+                "auth_api_token": null,
+                "ssl_enable": false,
+                "ssl_conf": null,
+            }))
+            .unwrap();
+            let server = tokio::spawn(serve_once(listener, status));
+            let res = write_lines(
+                &reqwest::Client::new(),
+                &conf,
+                "db",
+                "ms",
+                "cpu value=1 1".to_owned(),
+                gzip,
+            )
+            .await;
+            let req = server.await.unwrap();
+            assert_eq!(req.contains("content-encoding: gzip"), gzip);
+            match (res, retryable) {
+                (Ok(_), None) => {}
+                (Err(influxdb::Error::ConnectionError { .. }), Some(true)) => {}
+                (Err(influxdb::Error::DatabaseError { .. }), Some(false)) => {}
+                (res, _) => panic!("{}: {:?}", status, res),
+            }
+        }
+    }
+}
@@ -1,14 +1,14 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use common::{
-    error::HaliaResult,
+    error::{HaliaError, HaliaResult},
     get_dynamic_value_from_json,
     sink_message_retain::{self, SinkMessageRetain},
 };
 use futures::lock::BiLock;
 use halia_derive::{ResourceErr, ResourceStop, SinkTxs};
-use influxdb::{Client, InfluxDbWriteable as _, Timestamp, Type};
-use message::RuleMessageBatch;
+use influxdb::{InfluxDbWriteable as _, Query as _, Timestamp, Type, WriteQuery};
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
 use tokio::{
     select,
     sync::{
@@ -16,12 +16,16 @@ use tokio::{
         watch,
     },
     task::JoinHandle,
+    time::{self, Instant},
 };
-use tracing::debug;
-use types::apps::influxdb_v1::{InfluxdbConf, SinkConf};
+use tracing::{debug, warn};
+use types::apps::influxdb_v1::{InfluxdbConf, Precision, SinkConf};
 use utils::ErrorManager;
 
-use super::new_influxdb_client;
+use super::write_lines;
+
+// 停止时写入剩余消息的最长等待时间
+const STOP_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(ResourceErr, ResourceStop, SinkTxs)]
 pub struct Sink {
@@ -32,7 +36,14 @@ pub struct Sink {
 }
 
 impl Sink {
-    pub fn validate_conf(_conf: &SinkConf) -> HaliaResult<()> {
+    pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
+        if conf.fields.is_empty() {
+            return Err(HaliaError::Common("fields不能为空！".to_owned()));
+        }
+        if conf.batch_size == 0 {
+            return Err(HaliaError::Common("batch_size必须大于0！".to_owned()));
+        }
+
         Ok(())
     }
 
@@ -41,7 +52,7 @@ impl Sink {
         sink_conf: SinkConf,
         influxdb_conf: Arc<InfluxdbConf>,
         app_err_tx: UnboundedSender<Option<Arc<String>>>,
-    ) -> Self {
+    ) -> HaliaResult<Self> {
         let (stop_signal_tx, stop_signal_rx) = watch::channel(());
         let (mb_tx, mb_rx) = unbounded_channel();
         let (err1, err2) = BiLock::new(None);
@@ -54,22 +65,29 @@ impl Sink {
             app_err_tx,
             stop_signal_rx,
             mb_rx,
-        );
+        )?;
         let join_handle = task_loop.start();
 
-        Sink {
+        Ok(Sink {
             stop_signal_tx,
             mb_tx,
             err: err2,
             join_handle: Some(join_handle),
-        }
+        })
     }
 
-    pub async fn update_conf(&mut self, sink_conf: SinkConf) {
+    pub async fn update_conf(&mut self, sink_id: &str, sink_conf: SinkConf) -> HaliaResult<()> {
         let mut task_loop = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut task_loop.message_retainer,
+            &task_loop.sink_conf.message_retain,
+            &sink_conf.message_retain,
+            sink_id,
+        );
         task_loop.sink_conf = sink_conf;
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
+        res
     }
 
     pub async fn update_influxdb_client(&mut self, influxdb_conf: Arc<InfluxdbConf>) {
@@ -88,6 +106,10 @@ pub struct TaskLoop {
     stop_signal_rx: watch::Receiver<()>,
     mb_rx: UnboundedReceiver<RuleMessageBatch>,
     error_manager: ErrorManager,
+    // 等待批量写入的消息及需要写入的截止时间
+    pending_mb: Option<MessageBatch>,
+    flush_deadline: Option<Instant>,
+    http_client: reqwest::Client,
 }
 
 impl TaskLoop {
@@ -99,14 +121,14 @@ impl TaskLoop {
         app_err_tx: UnboundedSender<Option<Arc<String>>>,
         stop_signal_rx: watch::Receiver<()>,
         mb_rx: UnboundedReceiver<RuleMessageBatch>,
-    ) -> Self {
-        let message_retainer = sink_message_retain::new(&sink_conf.message_retain);
+    ) -> HaliaResult<Self> {
+        let message_retainer = sink_message_retain::new(&sink_conf.message_retain, &sink_id)?;
         let error_manager = ErrorManager::new(
             utils::error_manager::ResourceType::AppSink,
             sink_id,
             sink_err,
         );
-        Self {
+        Ok(Self {
             sink_conf,
             influxdb_conf,
             app_err_tx,
@@ -114,120 +136,348 @@ impl TaskLoop {
             stop_signal_rx,
             mb_rx,
             error_manager,
-        }
+            pending_mb: None,
+            flush_deadline: None,
+            http_client: reqwest::Client::new(),
+        })
     }
 
     fn start(mut self) -> JoinHandle<Self> {
-        let influxdb_client = new_influxdb_client(&self.influxdb_conf, &self.sink_conf);
+        let mut replay_interval = time::interval(sink_message_retain::REPLAY_INTERVAL);
 
         tokio::spawn(async move {
             loop {
+                let flush_deadline = self.flush_deadline.unwrap_or_else(Instant::now);
                 select! {
                     _ = self.stop_signal_rx.changed() => {
+                        self.flush_on_stop().await;
                         return self;
                     }
 
                     Some(rmb) = self.mb_rx.recv() => {
-                        self.send_msg_to_influxdb(&influxdb_client, rmb).await;
+                        self.handle_data(rmb.take_mb()).await;
+                    }
+
+                    _ = time::sleep_until(flush_deadline), if self.flush_deadline.is_some() => {
+                        self.flush().await;
+                    }
+
+                    _ = replay_interval.tick() => {
+                        self.replay_retained_mbs().await;
                     }
                 }
             }
         })
     }
 
-    async fn send_msg_to_influxdb(&mut self, influxdb_client: &Client, rmb: RuleMessageBatch) {
-        debug!("{:?}", rmb);
-        let mb = rmb.take_mb();
-        let mut querys = vec![];
-        let ts = chrono::Local::now().timestamp() as u128;
-        let timestamp = match &self.sink_conf.precision {
-            types::apps::influxdb_v1::Precision::Nanoseconds => Timestamp::Nanoseconds(ts),
-            types::apps::influxdb_v1::Precision::Microseconds => Timestamp::Microseconds(ts),
-            types::apps::influxdb_v1::Precision::Milliseconds => Timestamp::Milliseconds(ts),
-            types::apps::influxdb_v1::Precision::Seconds => Timestamp::Seconds(ts),
-            types::apps::influxdb_v1::Precision::Minutes => Timestamp::Minutes(ts),
-            types::apps::influxdb_v1::Precision::Hours => Timestamp::Hours(ts),
+    async fn handle_data(&mut self, mb: MessageBatch) {
+        match &mut self.pending_mb {
+            Some(pending_mb) => pending_mb.extend(mb),
+            None => {
+                self.pending_mb = Some(mb);
+                self.flush_deadline =
+                    Some(Instant::now() + Duration::from_millis(self.sink_conf.batch_timeout));
+            }
+        }
+
+        if self.pending_mb.as_ref().unwrap().len() >= self.sink_conf.batch_size {
+            self.flush().await;
+        }
+    }
+
+    async fn flush(&mut self) {
+        self.flush_deadline = None;
+        let mb = match self.pending_mb.take() {
+            Some(mb) => mb,
+            None => return,
         };
-        for msg in mb.get_messages() {
-            let mut query = timestamp.into_query(&self.sink_conf.mesaurement);
-            for (field, field_value) in &self.sink_conf.fields {
-                let value = match get_dynamic_value_from_json(field_value) {
-                    common::DynamicValue::Const(value) => value,
-                    common::DynamicValue::Field(s) => match msg.get(&s) {
-                        Some(value) => value.clone().into(),
-                        None => serde_json::Value::Null,
-                    },
-                };
-
-                match value {
-                    serde_json::Value::Bool(b) => query = query.add_field(field, Type::Boolean(b)),
-                    serde_json::Value::Number(number) => {
-                        if let Some(i) = number.as_i64() {
-                            query = query.add_field(field, Type::SignedInteger(i));
-                        } else if let Some(u) = number.as_u64() {
-                            query = query.add_field(field, Type::UnsignedInteger(u));
-                        } else if let Some(f) = number.as_f64() {
-                            query = query.add_field(field, Type::Float(f));
-                        }
-                    }
-                    serde_json::Value::String(s) => {
-                        query = query.add_field(field, Type::Text(s));
-                    }
-                    _ => {}
-                }
+
+        // 仍有待重发的消息时先保留，保证消息的写入顺序
+        if !self.message_retainer.is_empty() {
+            self.message_retainer.push(mb);
+            self.replay_retained_mbs().await;
+            return;
+        }
+
+        if !self.send_msg_to_influxdb(&mb).await {
+            self.message_retainer.push(mb);
+        }
+    }
+
+    // 停止前写入未达到批量条件的消息，写入失败或超时时保留
+    async fn flush_on_stop(&mut self) {
+        self.flush_deadline = None;
+        let mb = match self.pending_mb.take() {
+            Some(mb) => mb,
+            None => return,
+        };
+
+        if !self.message_retainer.is_empty() {
+            self.message_retainer.push(mb);
+            return;
+        }
+
+        match time::timeout(STOP_FLUSH_TIMEOUT, self.send_msg_to_influxdb(&mb)).await {
+            Ok(true) => {}
+            Ok(false) | Err(_) => self.message_retainer.push(mb),
+        }
+    }
+
+    async fn replay_retained_mbs(&mut self) {
+        while let Some(mb) = self.message_retainer.peek() {
+            if !self.send_msg_to_influxdb(&mb).await {
+                break;
             }
+            self.message_retainer.pop();
+        }
+    }
 
-            if let Some(tags) = &self.sink_conf.tags {
-                for (tag, tag_value) in tags {
-                    let value = match get_dynamic_value_from_json(tag_value) {
-                        common::DynamicValue::Const(value) => value,
-                        common::DynamicValue::Field(s) => match msg.get(&s) {
-                            Some(value) => value.clone().into(),
-                            None => serde_json::Value::Null,
-                        },
-                    };
-
-                    match value {
-                        serde_json::Value::Bool(b) => query = query.add_tag(tag, Type::Boolean(b)),
-                        serde_json::Value::Number(number) => {
-                            if let Some(i) = number.as_i64() {
-                                query = query.add_tag(tag, Type::SignedInteger(i));
-                            } else if let Some(u) = number.as_u64() {
-                                query = query.add_tag(tag, Type::UnsignedInteger(u));
-                            } else if let Some(f) = number.as_f64() {
-                                query = query.add_tag(tag, Type::Float(f));
-                            }
-                        }
-                        serde_json::Value::String(s) => {
-                            query = query.add_tag(tag, Type::Text(s));
-                        }
-                        _ => {}
-                    }
+    // 连接失败、限流或服务端错误时返回false，由调用方保留消息等待重发
+    async fn send_msg_to_influxdb(&mut self, mb: &MessageBatch) -> bool {
+        debug!("{:?}", mb);
+        let querys: Vec<WriteQuery> = mb
+            .get_messages()
+            .iter()
+            .filter_map(|msg| self.build_query(msg))
+            .collect();
+        if querys.is_empty() {
+            return true;
+        }
+
+        let res = match querys.build() {
+            Ok(lines) => {
+                write_lines(
+                    &self.http_client,
+                    &self.influxdb_conf,
+                    &self.sink_conf.database,
+                    &querys[0].get_precision(),
+                    lines.get(),
+                    self.sink_conf.gzip,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(_) => {
+                let status_changed = self.error_manager.set_ok().await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(None);
                 }
+                true
             }
-            querys.push(query);
-        }
-        if let Err(e) = influxdb_client.query(querys).await {
-            match e {
-                influxdb::Error::InvalidQueryError { error } => todo!(),
-                influxdb::Error::UrlConstructionError { error } => todo!(),
-                influxdb::Error::ProtocolError { error } => todo!(),
-                influxdb::Error::DeserializationError { error } => todo!(),
-                influxdb::Error::DatabaseError { error } => {
-                    // TODO database error 说明已连接
-                    let err = Arc::new(error);
-                    self.error_manager.set_err(err.clone()).await;
+            Err(influxdb::Error::ConnectionError { error }) => {
+                let err = Arc::new(error);
+                let status_changed = self.error_manager.set_err(err.clone()).await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(Some(err));
                 }
-                influxdb::Error::AuthenticationError => todo!(),
-                influxdb::Error::AuthorizationError => todo!(),
-                influxdb::Error::ConnectionError { error } => {
-                    let err = Arc::new(error);
-                    let status_changed = self.error_manager.set_err(err.clone()).await;
-                    if status_changed {
-                        let _ = self.app_err_tx.send(Some(err));
-                    }
+                false
+            }
+            // 已连接但写入失败，重发无意义，直接丢弃
+            Err(e) => {
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+                true
+            }
+        }
+    }
+
+    // 没有有效字段的消息无法写入，返回None
+    fn build_query(&self, msg: &Message) -> Option<WriteQuery> {
+        let mut query = self
+            .get_timestamp(msg)
+            .into_query(&self.sink_conf.mesaurement);
+        let mut has_field = false;
+        for (field, field_value) in &self.sink_conf.fields {
+            if let Some(value) = get_value(msg, field_value) {
+                query = query.add_field(field, value);
+                has_field = true;
+            }
+        }
+        if !has_field {
+            warn!("消息中没有可写入的字段，已丢弃");
+            return None;
+        }
+
+        if let Some(tags) = &self.sink_conf.tags {
+            for (tag, tag_value) in tags {
+                if let Some(value) = get_value(msg, tag_value) {
+                    query = query.add_tag(tag, value);
                 }
             }
         }
+
+        Some(query)
+    }
+
+    // 依次使用时间戳字段、消息元数据中的timestamp及当前时间
+    fn get_timestamp(&self, msg: &Message) -> Timestamp {
+        let ms = self
+            .sink_conf
+            .timestamp_field
+            .as_ref()
+            .and_then(|field| msg.get(field))
+            .and_then(get_timestamp_ms)
+            .or_else(|| msg.get_metadata("timestamp").and_then(get_timestamp_ms))
+            .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+        let ms = ms.max(0) as u128;
+        match &self.sink_conf.precision {
+            Precision::Nanoseconds => Timestamp::Nanoseconds(ms * 1_000_000),
+            Precision::Microseconds => Timestamp::Microseconds(ms * 1_000),
+            Precision::Milliseconds => Timestamp::Milliseconds(ms),
+            Precision::Seconds => Timestamp::Seconds(ms / 1_000),
+            Precision::Minutes => Timestamp::Minutes(ms / 60_000),
+            Precision::Hours => Timestamp::Hours(ms / 3_600_000),
+        }
+    }
+}
+
+fn get_timestamp_ms(value: &MessageValue) -> Option<i64> {
+    match value {
+        MessageValue::Int64(ms) => Some(*ms),
+        MessageValue::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|ts| ts.timestamp_millis()),
+        _ => None,
+    }
+}
+
+fn get_value(msg: &Message, value: &serde_json::Value) -> Option<Type> {
+    let value = match get_dynamic_value_from_json(value) {
+        common::DynamicValue::Const(value) => value,
+        common::DynamicValue::Field(s) => match msg.get(&s) {
+            Some(value) => value.clone().into(),
+            None => return None,
+        },
+    };
+
+    match value {
+        serde_json::Value::Bool(b) => Some(Type::Boolean(b)),
+        serde_json::Value::Number(number) => {
+            if let Some(i) = number.as_i64() {
+                Some(Type::SignedInteger(i))
+            } else if let Some(u) = number.as_u64() {
+                Some(Type::UnsignedInteger(u))
+            } else {
+                number.as_f64().map(Type::Float)
+            }
+        }
+        serde_json::Value::String(s) => Some(Type::Text(s)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_task_loop(sink_conf: serde_json::Value) -> TaskLoop {
+        let sink_conf: SinkConf = serde_json::from_value(sink_conf).unwrap();
+        let influxdb_conf: InfluxdbConf = serde_json::from_value(serde_json::json!({
+            "host": "127.0.0.1",
+            "port": 8086,
+            "auth_method": "none",
+            "auth_password": null,
+            "auth_api_token": null,
+            "ssl_enable": false,
+            "ssl_conf": null,
+        }))
+        .unwrap();
+        let (err, _) = BiLock::new(None);
+        let (app_err_tx, _) = unbounded_channel();
+        let (_, stop_signal_rx) = watch::channel(());
+        let (_, mb_rx) = unbounded_channel();
+        TaskLoop::new(
+            "sink".to_owned(),
+            sink_conf,
+            err,
+            Arc::new(influxdb_conf),
+            app_err_tx,
+            stop_signal_rx,
+            mb_rx,
+        )
+        .unwrap()
+    }
+
+    fn sink_conf(batch_size: usize) -> serde_json::Value {
+        serde_json::json!({
+            "database": "db",
+            "gzip": true,
+            "mesaurement": "cpu",
+            "fields": [["value", "${value}"], ["n", 1]],
+            "tags": [["host", "${host}"], ["region", "${region}"]],
+            "precision": "milliseconds",
+            "timestamp_field": "ts",
+            "batch_size": batch_size,
+            "batch_timeout": 1000,
+            "message_retain": {"type": "none", "count": null, "time": null, "size": null},
+        })
+    }
+
+    fn new_msg(value: f64) -> Message {
+        let mut msg = Message::default();
+        msg.add("value".to_owned(), MessageValue::Float64(value));
+        msg.add("host".to_owned(), MessageValue::String("a".to_owned()));
+        msg
+    }
+
+    #[test]
+    fn test_build_query() {
+        let mut task_loop = new_task_loop(sink_conf(1));
+        let mut msg = new_msg(1.5);
+        msg.add("ts".to_owned(), MessageValue::Int64(1_700_000_000_000));
+        let query = task_loop.build_query(&msg).unwrap();
+        // 消息中不存在的标签不写入
+        assert_eq!(
+            query.build().unwrap().get(),
+            "cpu,host=a value=1.5,n=1i 1700000000000"
+        );
+
+        let mut msg = Message::default();
+        msg.add("host".to_owned(), MessageValue::String("a".to_owned()));
+        task_loop.sink_conf.fields = vec![("value".to_owned(), serde_json::json!("${value}"))];
+        assert!(task_loop.build_query(&msg).is_none());
+    }
+
+    #[test]
+    fn test_get_timestamp() {
+        let mut task_loop = new_task_loop(sink_conf(1));
+        let mut msg = new_msg(1.0);
+        msg.insert_metadata("timestamp".to_owned(), MessageValue::Int64(2_000));
+        assert_eq!(
+            task_loop.get_timestamp(&msg),
+            Timestamp::Milliseconds(2_000)
+        );
+
+        msg.add(
+            "ts".to_owned(),
+            MessageValue::String("1970-01-01T00:00:03Z".to_owned()),
+        );
+        assert_eq!(
+            task_loop.get_timestamp(&msg),
+            Timestamp::Milliseconds(3_000)
+        );
+
+        task_loop.sink_conf.precision = Precision::Seconds;
+        assert_eq!(task_loop.get_timestamp(&msg), Timestamp::Seconds(3));
+        task_loop.sink_conf.precision = Precision::Nanoseconds;
+        assert_eq!(
+            task_loop.get_timestamp(&msg),
+            Timestamp::Nanoseconds(3_000_000_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batching() {
+        let mut task_loop = new_task_loop(sink_conf(3));
+        for i in 0..2 {
+            let mut mb = MessageBatch::default();
+            mb.push_message(new_msg(i as f64));
+            task_loop.handle_data(mb).await;
+        }
+        // 未达到batch_size时等待超时后再写入
+        assert_eq!(task_loop.pending_mb.as_ref().unwrap().len(), 2);
+        assert!(task_loop.flush_deadline.is_some());
+        assert!(task_loop.message_retainer.is_empty());
     }
 }
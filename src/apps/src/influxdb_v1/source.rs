@@ -0,0 +1,266 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use common::error::{HaliaError, HaliaResult};
+use futures::lock::BiLock;
+use halia_derive::{ResourceErr, ResourceStop, SourceRxs};
+use influxdb::{Client, ReadQuery};
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
+use serde::Deserialize;
+use tokio::{
+    select,
+    sync::{mpsc::UnboundedSender, watch},
+    task::JoinHandle,
+    time,
+};
+use tracing::warn;
+use types::apps::influxdb_v1::{InfluxdbConf, SourceConf};
+use utils::ErrorManager;
+
+use super::new_influxdb_client;
+
+#[derive(ResourceErr, ResourceStop, SourceRxs)]
+pub struct Source {
+    stop_signal_tx: watch::Sender<()>,
+    err: BiLock<Option<Arc<String>>>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+}
+
+impl Source {
+    pub fn validate_conf(conf: &SourceConf) -> HaliaResult<()> {
+        if conf.query.is_empty() {
+            return Err(HaliaError::Common("查询语句不能为空！".to_owned()));
+        }
+        if conf.interval == 0 {
+            return Err(HaliaError::Common("查询间隔必须大于0！".to_owned()));
+        }
+
+        Ok(())
+    }
+
+    pub fn new(
+        source_id: String,
+        source_conf: SourceConf,
+        influxdb_conf: Arc<InfluxdbConf>,
+        app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    ) -> Self {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (err1, err2) = BiLock::new(None);
+        let (mb_txs1, mb_txs2) = BiLock::new(vec![]);
+
+        let error_manager = ErrorManager::new(
+            utils::error_manager::ResourceType::AppSource,
+            source_id,
+            err1,
+        );
+        let task_loop = TaskLoop {
+            source_conf,
+            influxdb_conf,
+            start: None,
+            app_err_tx,
+            stop_signal_rx,
+            mb_txs: mb_txs1,
+            error_manager,
+        };
+        let join_handle = task_loop.start();
+
+        Self {
+            stop_signal_tx,
+            err: err2,
+            join_handle: Some(join_handle),
+            mb_txs: mb_txs2,
+        }
+    }
+
+    pub async fn update_conf(&mut self, source_conf: SourceConf) {
+        let mut task_loop = self.stop().await;
+        // 查询变化后游标重新从回溯时间开始
+        if task_loop.source_conf.database != source_conf.database
+            || task_loop.source_conf.query != source_conf.query
+        {
+            task_loop.start = None;
+        }
+        task_loop.source_conf = source_conf;
+        self.join_handle = Some(task_loop.start());
+    }
+
+    pub async fn update_influxdb_client(&mut self, influxdb_conf: Arc<InfluxdbConf>) {
+        let mut task_loop = self.stop().await;
+        task_loop.influxdb_conf = influxdb_conf;
+        self.join_handle = Some(task_loop.start());
+    }
+}
+
+pub struct TaskLoop {
+    source_conf: SourceConf,
+    influxdb_conf: Arc<InfluxdbConf>,
+    // 下一次查询的起始时间，查询成功后前移到本次查询的结束时间
+    start: Option<DateTime<Utc>>,
+    app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+    error_manager: ErrorManager,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        let influxdb_client = new_influxdb_client(&self.influxdb_conf, &self.source_conf.database);
+        let mut interval = time::interval(Duration::from_millis(self.source_conf.interval));
+
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    _ = interval.tick() => {
+                        self.query(&influxdb_client).await;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn query(&mut self, influxdb_client: &Client) {
+        // 没有规则引用时不查询，游标保持不动
+        if self.mb_txs.lock().await.is_empty() {
+            return;
+        }
+
+        let end = Utc::now() - chrono::Duration::seconds(self.source_conf.delay as i64);
+        let start = *self.start.get_or_insert(
+            end - chrono::Duration::seconds(self.source_conf.initial_lookback as i64),
+        );
+        if end <= start {
+            return;
+        }
+
+        let query = self
+            .source_conf
+            .query
+            .replace(
+                "$start",
+                &start.timestamp_nanos_opt().unwrap_or(0).to_string(),
+            )
+            .replace("$end", &end.timestamp_nanos_opt().unwrap_or(0).to_string());
+        match influxdb_client.query(ReadQuery::new(query)).await {
+            Ok(resp) => {
+                let status_changed = self.error_manager.set_ok().await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(None);
+                }
+                self.start = Some(end);
+                match parse_query_resp(&resp) {
+                    Ok(mb) => self.send_mb(mb).await,
+                    Err(e) => warn!("influxdb query resp parse err: {}", e),
+                }
+            }
+            Err(influxdb::Error::ConnectionError { error }) => {
+                let err = Arc::new(error);
+                let status_changed = self.error_manager.set_err(err.clone()).await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(Some(err));
+                }
+            }
+            // 查询语句错误等，游标不前移，修正配置后重新查询
+            Err(e) => {
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+            }
+        }
+    }
+
+    async fn send_mb(&mut self, mb: MessageBatch) {
+        if mb.len() == 0 {
+            return;
+        }
+
+        let mut mb_txs = self.mb_txs.lock().await;
+        match mb_txs.len() {
+            0 => {}
+            1 => {
+                let mb = RuleMessageBatch::Owned(mb);
+                if mb_txs[0].send(mb).is_err() {
+                    mb_txs.remove(0);
+                }
+            }
+            _ => {
+                let mb = RuleMessageBatch::Arc(Arc::new(mb));
+                mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct QueryResp {
+    #[serde(default)]
+    results: Vec<StatementResult>,
+}
+
+#[derive(Deserialize)]
+struct StatementResult {
+    #[serde(default)]
+    series: Vec<Series>,
+}
+
+#[derive(Deserialize)]
+struct Series {
+    name: String,
+    #[serde(default)]
+    tags: serde_json::Map<String, serde_json::Value>,
+    columns: Vec<String>,
+    #[serde(default)]
+    values: Vec<Vec<serde_json::Value>>,
+}
+
+// 每一行结果转换为一条消息，GROUP BY的tag作为字段，measurement写入元数据
+fn parse_query_resp(resp: &str) -> HaliaResult<MessageBatch> {
+    let resp: QueryResp = serde_json::from_str(resp)?;
+    let mut mb = MessageBatch::default();
+    for series in resp.results.into_iter().flat_map(|result| result.series) {
+        for row in series.values {
+            let mut msg = Message::default();
+            msg.insert_metadata(
+                "measurement".to_owned(),
+                MessageValue::String(series.name.clone()),
+            );
+            for (tag, value) in &series.tags {
+                msg.add(tag.clone(), MessageValue::from(value.clone()));
+            }
+            for (column, value) in series.columns.iter().zip(row) {
+                msg.add(column.clone(), MessageValue::from(value));
+            }
+            mb.push_message(msg);
+        }
+    }
+    Ok(mb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query_resp() {
+        let resp = r#"{"results":[{"statement_id":0,"series":[
+            {"name":"cpu","tags":{"host":"a"},"columns":["time","mean"],"values":[["2024-01-01T00:00:00Z",1.5],["2024-01-01T00:01:00Z",2]]},
+            {"name":"cpu","tags":{"host":"b"},"columns":["time","mean"],"values":[["2024-01-01T00:00:00Z",null]]}
+        ]},{"statement_id":1}]}"#;
+        let mb = parse_query_resp(resp).unwrap();
+        assert_eq!(mb.len(), 3);
+        let msg = &mb.get_messages()[0];
+        assert_eq!(msg.get("host"), Some(&MessageValue::String("a".to_owned())));
+        assert_eq!(msg.get("mean"), Some(&MessageValue::Float64(1.5)));
+        assert_eq!(
+            msg.get_metadata("measurement"),
+            Some(&MessageValue::String("cpu".to_owned()))
+        );
+        assert_eq!(
+            mb.get_messages()[1].get("mean"),
+            Some(&MessageValue::Int64(2))
+        );
+        assert_eq!(mb.get_messages()[2].get("mean"), Some(&MessageValue::Null));
+    }
+}
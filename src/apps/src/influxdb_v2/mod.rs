@@ -5,26 +5,30 @@ use common::error::{HaliaError, HaliaResult};
 use dashmap::DashMap;
 use futures::lock::BiLock;
 use halia_derive::ResourceErr;
+use influxdb2::{Client, ClientBuilder};
 use message::RuleMessageBatch;
 use sink::Sink;
+use source::Source;
 use tokio::{
     select,
     sync::{
-        mpsc::{self, unbounded_channel, UnboundedSender},
+        mpsc::{self, unbounded_channel, UnboundedReceiver, UnboundedSender},
         watch,
     },
     task::JoinHandle,
 };
-use types::apps::influxdb_v2::{InfluxdbConf, SinkConf};
+use types::apps::influxdb_v2::{InfluxdbConf, SinkConf, SourceConf};
 use utils::ErrorManager;
 
 use crate::App;
 
 mod sink;
+mod source;
 
 #[derive(ResourceErr)]
 pub struct Influxdb {
     err: BiLock<Option<Arc<String>>>,
+    sources: DashMap<String, Source>,
     sinks: DashMap<String, Sink>,
     conf: Arc<InfluxdbConf>,
     app_err_tx: UnboundedSender<Option<Arc<String>>>,
@@ -42,6 +46,7 @@ pub fn new(id: String, conf: serde_json::Value) -> Box<dyn App> {
     let _join_handle = task_loop.start();
     Box::new(Influxdb {
         err: app_err2,
+        sources: DashMap::new(),
         sinks: DashMap::new(),
         conf: Arc::new(conf),
         app_err_tx,
@@ -55,6 +60,12 @@ pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     Ok(())
 }
 
+pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::validate_conf(&conf)?;
+    Ok(())
+}
+
 pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     let conf: SinkConf = serde_json::from_value(conf.clone())?;
     Sink::validate_conf(&conf)?;
@@ -115,6 +126,13 @@ impl App for Influxdb {
         self.read_err().await
     }
 
+    async fn read_source_err(&self, source_id: &String) -> HaliaResult<Option<Arc<String>>> {
+        match self.sources.get(source_id) {
+            Some(source) => Ok(source.read_err().await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
     async fn read_sink_err(&self, sink_id: &String) -> HaliaResult<Option<Arc<String>>> {
         match self.sinks.get(sink_id) {
             Some(sink) => Ok(sink.read_err().await),
@@ -129,6 +147,9 @@ impl App for Influxdb {
     ) -> HaliaResult<()> {
         let new_conf: InfluxdbConf = serde_json::from_value(new_conf)?;
         self.conf = Arc::new(new_conf);
+        for mut source in self.sources.iter_mut() {
+            source.update_influxdb_client(self.conf.clone()).await;
+        }
         for mut sink in self.sinks.iter_mut() {
             sink.update_influxdb_client(self.conf.clone()).await;
         }
@@ -137,12 +158,68 @@ impl App for Influxdb {
     }
 
     async fn stop(&mut self) {
+        for mut source in self.sources.iter_mut() {
+            source.stop().await;
+        }
         for mut sink in self.sinks.iter_mut() {
             sink.stop().await;
         }
         self.stop_signal_tx.send(()).unwrap();
     }
 
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        let source = Source::new(
+            source_id.clone(),
+            conf,
+            self.conf.clone(),
+            self.app_err_tx.clone(),
+        );
+        self.sources.insert(source_id, source);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: String,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: SourceConf = serde_json::from_value(new_conf)?;
+        match self.sources.get_mut(&source_id) {
+            Some(mut source) => {
+                source.update_conf(new_conf).await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn delete_source(&mut self, source_id: String) -> HaliaResult<()> {
+        match self.sources.remove(&source_id) {
+            Some((_, mut source)) => {
+                source.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt).await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let conf: SinkConf = serde_json::from_value(conf)?;
         let sink = Sink::new(
@@ -150,7 +227,7 @@ impl App for Influxdb {
             conf,
             self.conf.clone(),
             self.app_err_tx.clone(),
-        );
+        )?;
         self.sinks.insert(sink_id, sink);
         Ok(())
     }
@@ -163,10 +240,7 @@ impl App for Influxdb {
     ) -> HaliaResult<()> {
         let new_conf: SinkConf = serde_json::from_value(new_conf)?;
         match self.sinks.get_mut(&sink_id) {
-            Some(mut sink) => {
-                sink.update_conf(new_conf).await;
-                Ok(())
-            }
+            Some(mut sink) => sink.update_conf(&sink_id, new_conf).await,
             None => Err(HaliaError::NotFound(sink_id)),
         }
     }
@@ -192,3 +266,63 @@ impl App for Influxdb {
         }
     }
 }
+
+fn new_influxdb_client(influxdb_conf: &Arc<InfluxdbConf>, gzip: bool) -> HaliaResult<Client> {
+    let builder = ClientBuilder::new(
+        format!("http://{}:{}", &influxdb_conf.host, influxdb_conf.port),
+        &influxdb_conf.org,
+        &influxdb_conf.api_token,
+    );
+    // gzip(false)同样会开启压缩，只在需要时调用
+    let builder = match gzip {
+        true => builder.gzip(true),
+        false => builder,
+    };
+    builder
+        .build()
+        .map_err(|e| HaliaError::Common(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use influxdb2::models::DataPoint;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_new_influxdb_client_gzip() {
+        for gzip in [true, false] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let conf = Arc::new(InfluxdbConf {
+                host: "127.0.0.1".to_owned(),
+                port: listener.local_addr().unwrap().port(),
+                org: "org".to_owned(),
+                api_token: "token".to_owned(),
+            });
+            let server = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n")
+                    .await
+                    .unwrap();
+                String::from_utf8_lossy(&buf[..n]).to_lowercase()
+            });
+
+            let client = new_influxdb_client(&conf, gzip).unwrap();
+            let data_point = DataPoint::builder("cpu").field("value", 1).build().unwrap();
+            client
+                .write("b", stream::iter(vec![data_point]))
+                .await
+                .unwrap();
+            let req = server.await.unwrap();
+            assert_eq!(req.contains("content-encoding: gzip"), gzip);
+        }
+    }
+}
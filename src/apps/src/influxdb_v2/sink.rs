@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use common::{
-    error::HaliaResult,
+    error::{HaliaError, HaliaResult},
     get_dynamic_value_from_json,
     sink_message_retain::{self, SinkMessageRetain},
 };
@@ -12,7 +12,7 @@ use influxdb2::{
     models::{DataPoint, FieldValue},
     Client,
 };
-use message::RuleMessageBatch;
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
 use tokio::{
     select,
     sync::{
@@ -20,11 +20,17 @@ use tokio::{
         watch,
     },
     task::JoinHandle,
+    time::{self, Instant},
 };
 use tracing::{debug, warn};
-use types::apps::influxdb_v2::{InfluxdbConf, SinkConf};
+use types::apps::influxdb_v2::{InfluxdbConf, Precision, SinkConf};
 use utils::ErrorManager;
 
+use super::new_influxdb_client;
+
+// 停止时写入剩余消息的最长等待时间
+const STOP_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(ResourceErr, ResourceStop, SinkTxs)]
 pub struct Sink {
     stop_signal_tx: watch::Sender<()>,
@@ -34,7 +40,14 @@ pub struct Sink {
 }
 
 impl Sink {
-    pub fn validate_conf(_conf: &SinkConf) -> HaliaResult<()> {
+    pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
+        if conf.fields.is_empty() {
+            return Err(HaliaError::Common("fields不能为空！".to_owned()));
+        }
+        if conf.batch_size == 0 {
+            return Err(HaliaError::Common("batch_size必须大于0！".to_owned()));
+        }
+
         Ok(())
     }
 
@@ -43,7 +56,7 @@ impl Sink {
         sink_conf: SinkConf,
         influxdb_conf: Arc<InfluxdbConf>,
         app_err_tx: UnboundedSender<Option<Arc<String>>>,
-    ) -> Self {
+    ) -> HaliaResult<Self> {
         let (stop_signal_tx, stop_signal_rx) = watch::channel(());
         let (mb_tx, mb_rx) = unbounded_channel();
         let (sink_err1, sink_err2) = BiLock::new(None);
@@ -56,22 +69,29 @@ impl Sink {
             app_err_tx,
             stop_signal_rx,
             mb_rx,
-        );
+        )?;
         let join_handle = task_loop.start();
 
-        Sink {
+        Ok(Sink {
             stop_signal_tx,
             mb_tx,
             err: sink_err2,
             join_handle: Some(join_handle),
-        }
+        })
     }
 
-    pub async fn update_conf(&mut self, sink_conf: SinkConf) {
+    pub async fn update_conf(&mut self, sink_id: &str, sink_conf: SinkConf) -> HaliaResult<()> {
         let mut task_loop = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut task_loop.message_retainer,
+            &task_loop.sink_conf.message_retain,
+            &sink_conf.message_retain,
+            sink_id,
+        );
         task_loop.sink_conf = sink_conf;
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
+        res
     }
 
     pub async fn update_influxdb_client(&mut self, influxdb_conf: Arc<InfluxdbConf>) {
@@ -90,6 +110,9 @@ pub struct TaskLoop {
     stop_signal_rx: watch::Receiver<()>,
     mb_rx: UnboundedReceiver<RuleMessageBatch>,
     error_manager: ErrorManager,
+    // 等待批量写入的消息及需要写入的截止时间
+    pending_mb: Option<MessageBatch>,
+    flush_deadline: Option<Instant>,
 }
 
 impl TaskLoop {
@@ -101,14 +124,14 @@ impl TaskLoop {
         app_err_tx: UnboundedSender<Option<Arc<String>>>,
         stop_signal_rx: watch::Receiver<()>,
         mb_rx: UnboundedReceiver<RuleMessageBatch>,
-    ) -> Self {
-        let message_retainer = sink_message_retain::new(&sink_conf.message_retain);
+    ) -> HaliaResult<Self> {
+        let message_retainer = sink_message_retain::new(&sink_conf.message_retain, &sink_id)?;
         let error_manager = ErrorManager::new(
             utils::error_manager::ResourceType::AppSink,
             sink_id.clone(),
             sink_err,
         );
-        Self {
+        Ok(Self {
             sink_conf,
             influxdb_conf,
             app_err_tx,
@@ -116,86 +139,132 @@ impl TaskLoop {
             stop_signal_rx,
             mb_rx,
             error_manager,
-        }
+            pending_mb: None,
+            flush_deadline: None,
+        })
     }
 
     fn start(mut self) -> JoinHandle<Self> {
-        let influxdb_client = new_influxdb_client(&self.influxdb_conf);
-
-        let timestamp_precision = match &self.sink_conf.precision {
-            types::apps::influxdb_v2::Precision::Seconds => TimestampPrecision::Seconds,
-            types::apps::influxdb_v2::Precision::Milliseconds => TimestampPrecision::Milliseconds,
-            types::apps::influxdb_v2::Precision::Microseconds => TimestampPrecision::Microseconds,
-            types::apps::influxdb_v2::Precision::Nanoseconds => TimestampPrecision::Nanoseconds,
-        };
+        let influxdb_client = new_influxdb_client(&self.influxdb_conf, self.sink_conf.gzip);
+        let mut replay_interval = time::interval(sink_message_retain::REPLAY_INTERVAL);
 
         tokio::spawn(async move {
+            let influxdb_client = match influxdb_client {
+                Ok(influxdb_client) => influxdb_client,
+                Err(e) => {
+                    self.error_manager.set_err(Arc::new(e.to_string())).await;
+                    let _ = self.stop_signal_rx.changed().await;
+                    return self;
+                }
+            };
             loop {
+                let flush_deadline = self.flush_deadline.unwrap_or_else(Instant::now);
                 select! {
                     _ = self.stop_signal_rx.changed() => {
+                        self.flush_on_stop(&influxdb_client).await;
                         return self;
                     }
 
-                    Some(mb) = self.mb_rx.recv() => {
-                        // if !app_err {
-                       self.send_msg_to_influxdb(&influxdb_client, timestamp_precision.clone(), mb).await;
-                        // } else {
-                        //     join_handle_data.message_retainer.push(mb);
-                        // }
+                    Some(rmb) = self.mb_rx.recv() => {
+                        self.handle_data(&influxdb_client, rmb.take_mb()).await;
+                    }
+
+                    _ = time::sleep_until(flush_deadline), if self.flush_deadline.is_some() => {
+                        self.flush(&influxdb_client).await;
+                    }
+
+                    _ = replay_interval.tick() => {
+                        self.replay_retained_mbs(&influxdb_client).await;
                     }
                 }
             }
         })
     }
 
-    async fn send_msg_to_influxdb(
-        &mut self,
-        influxdb_client: &Client,
-        timestamp_precision: TimestampPrecision,
-        rmb: RuleMessageBatch,
-    ) {
-        let mb = rmb.take_mb();
-        let mut data_points = Vec::with_capacity(mb.len());
-        for msg in mb.get_messages() {
-            let mut data_point_builder = DataPoint::builder(&self.sink_conf.mesaurement);
-            for (field, field_value) in &self.sink_conf.fields {
-                let value = match get_dynamic_value_from_json(field_value) {
-                    common::DynamicValue::Const(value) => value,
-                    common::DynamicValue::Field(s) => match msg.get(&s) {
-                        Some(value) => value.clone().into(),
-                        None => serde_json::Value::Null,
-                    },
-                };
+    async fn handle_data(&mut self, influxdb_client: &Client, mb: MessageBatch) {
+        match &mut self.pending_mb {
+            Some(pending_mb) => pending_mb.extend(mb),
+            None => {
+                self.pending_mb = Some(mb);
+                self.flush_deadline =
+                    Some(Instant::now() + Duration::from_millis(self.sink_conf.batch_timeout));
+            }
+        }
 
-                let value = match value {
-                    serde_json::Value::Bool(b) => FieldValue::Bool(b),
-                    serde_json::Value::Number(number) => {
-                        if number.is_f64() {
-                            FieldValue::F64(number.as_f64().unwrap())
-                        } else {
-                            FieldValue::I64(number.as_i64().unwrap())
-                        }
-                    }
-                    serde_json::Value::String(s) => FieldValue::String(s),
-                    serde_json::Value::Null
-                    | serde_json::Value::Array(_)
-                    | serde_json::Value::Object(_) => {
-                        warn!("Unsupported field value: {:?}", value);
-                        break;
-                    }
-                };
+        if self.pending_mb.as_ref().unwrap().len() >= self.sink_conf.batch_size {
+            self.flush(influxdb_client).await;
+        }
+    }
 
-                debug!("{:?}", value);
+    async fn flush(&mut self, influxdb_client: &Client) {
+        self.flush_deadline = None;
+        let mb = match self.pending_mb.take() {
+            Some(mb) => mb,
+            None => return,
+        };
 
-                data_point_builder = data_point_builder.field(field, value);
-            }
+        // 仍有待重发的消息时先保留，保证消息的写入顺序
+        if !self.message_retainer.is_empty() {
+            self.message_retainer.push(mb);
+            self.replay_retained_mbs(influxdb_client).await;
+            return;
+        }
+
+        if !self.send_msg_to_influxdb(influxdb_client, &mb).await {
+            self.message_retainer.push(mb);
+        }
+    }
+
+    // 停止前写入未达到批量条件的消息，写入失败或超时时保留
+    async fn flush_on_stop(&mut self, influxdb_client: &Client) {
+        self.flush_deadline = None;
+        let mb = match self.pending_mb.take() {
+            Some(mb) => mb,
+            None => return,
+        };
+
+        if !self.message_retainer.is_empty() {
+            self.message_retainer.push(mb);
+            return;
+        }
+
+        match time::timeout(
+            STOP_FLUSH_TIMEOUT,
+            self.send_msg_to_influxdb(influxdb_client, &mb),
+        )
+        .await
+        {
+            Ok(true) => {}
+            Ok(false) | Err(_) => self.message_retainer.push(mb),
+        }
+    }
 
-            match data_point_builder.build() {
-                Ok(data_point) => data_points.push(data_point),
-                Err(e) => warn!("Failed to build point: {}", e),
+    async fn replay_retained_mbs(&mut self, influxdb_client: &Client) {
+        while let Some(mb) = self.message_retainer.peek() {
+            if !self.send_msg_to_influxdb(influxdb_client, &mb).await {
+                break;
             }
+            self.message_retainer.pop();
         }
+    }
 
+    // 连接失败、限流或服务端错误时返回false，由调用方保留消息等待重发
+    async fn send_msg_to_influxdb(&mut self, influxdb_client: &Client, mb: &MessageBatch) -> bool {
+        let data_points: Vec<DataPoint> = mb
+            .get_messages()
+            .iter()
+            .filter_map(|msg| self.build_data_point(msg))
+            .collect();
+        if data_points.is_empty() {
+            return true;
+        }
+
+        let timestamp_precision = match &self.sink_conf.precision {
+            Precision::Seconds => TimestampPrecision::Seconds,
+            Precision::Milliseconds => TimestampPrecision::Milliseconds,
+            Precision::Microseconds => TimestampPrecision::Microseconds,
+            Precision::Nanoseconds => TimestampPrecision::Nanoseconds,
+        };
         match influxdb_client
             .write_with_precision(
                 &self.sink_conf.bucket,
@@ -207,41 +276,233 @@ impl TaskLoop {
             Ok(_) => {
                 let status_changed = self.error_manager.set_ok().await;
                 if status_changed {
-                    self.app_err_tx.send(None).unwrap();
+                    let _ = self.app_err_tx.send(None);
+                }
+                true
+            }
+            Err(influxdb2::RequestError::ReqwestProcessing { source }) => {
+                let err = Arc::new(source.to_string());
+                let status_changed = self.error_manager.set_err(err.clone()).await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(Some(err));
                 }
+                false
             }
+            Err(e @ influxdb2::RequestError::Http { status, .. })
+                if is_retryable(status.as_u16()) =>
+            {
+                let err = Arc::new(e.to_string());
+                let status_changed = self.error_manager.set_err(err.clone()).await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(Some(err));
+                }
+                false
+            }
+            // 已连接但写入失败，重发无意义，直接丢弃
             Err(e) => {
-                self.message_retainer.push(mb);
-                match e {
-                    influxdb2::RequestError::ReqwestProcessing { source } => {
-                        let err = Arc::new(source.to_string());
-                        let status_changed = self.error_manager.set_err(err.clone()).await;
-                        if status_changed {
-                            self.app_err_tx.send(Some(err)).unwrap();
-                        }
-                    }
-                    influxdb2::RequestError::Http { status, text } => {
-                        // TODO sink err
-                        debug!("Failed to write to influxdb http: {}, {}", status, text);
-                    }
-                    // TODO sink err
-                    influxdb2::RequestError::Serializing { source } => {
-                        debug!("{}", source);
-                    }
-                    // TODO sink err
-                    influxdb2::RequestError::Deserializing { text } => {
-                        debug!("{}", text);
-                    }
+                debug!("Failed to write to influxdb: {}", e);
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+                true
+            }
+        }
+    }
+
+    fn build_data_point(&self, msg: &Message) -> Option<DataPoint> {
+        let mut data_point_builder =
+            DataPoint::builder(&self.sink_conf.mesaurement).timestamp(self.get_timestamp(msg));
+        for (field, field_value) in &self.sink_conf.fields {
+            let value = match get_value(msg, field_value) {
+                serde_json::Value::Bool(b) => FieldValue::Bool(b),
+                serde_json::Value::Number(number) => match number.as_i64() {
+                    Some(i) => FieldValue::I64(i),
+                    None => FieldValue::F64(number.as_f64().unwrap_or_default()),
+                },
+                serde_json::Value::String(s) => FieldValue::String(s),
+                serde_json::Value::Null => continue,
+                value @ (serde_json::Value::Array(_) | serde_json::Value::Object(_)) => {
+                    warn!("Unsupported field value: {:?}", value);
+                    continue;
                 }
+            };
+            data_point_builder = data_point_builder.field(field, value);
+        }
+
+        if let Some(tags) = &self.sink_conf.tags {
+            for (tag, tag_value) in tags {
+                let value = match get_value(msg, tag_value) {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Null => continue,
+                    value => value.to_string(),
+                };
+                data_point_builder = data_point_builder.tag(tag, value);
+            }
+        }
+
+        // 没有有效字段时构建失败
+        match data_point_builder.build() {
+            Ok(data_point) => Some(data_point),
+            Err(e) => {
+                warn!("Failed to build point: {}", e);
+                None
             }
         }
     }
+
+    // 依次使用时间戳字段、消息元数据中的timestamp及当前时间
+    fn get_timestamp(&self, msg: &Message) -> i64 {
+        let ms = self
+            .sink_conf
+            .timestamp_field
+            .as_ref()
+            .and_then(|field| msg.get(field))
+            .and_then(get_timestamp_ms)
+            .or_else(|| msg.get_metadata("timestamp").and_then(get_timestamp_ms))
+            .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+        match &self.sink_conf.precision {
+            Precision::Seconds => ms / 1_000,
+            Precision::Milliseconds => ms,
+            Precision::Microseconds => ms * 1_000,
+            Precision::Nanoseconds => ms * 1_000_000,
+        }
+    }
 }
 
-fn new_influxdb_client(influxdb_conf: &Arc<InfluxdbConf>) -> Client {
-    Client::new(
-        format!("http://{}:{}", &influxdb_conf.host, influxdb_conf.port),
-        &influxdb_conf.org,
-        &influxdb_conf.api_token,
-    )
+// 限流或服务端错误时保留消息等待重发
+fn is_retryable(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+fn get_timestamp_ms(value: &MessageValue) -> Option<i64> {
+    match value {
+        MessageValue::Int64(ms) => Some(*ms),
+        MessageValue::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|ts| ts.timestamp_millis()),
+        _ => None,
+    }
+}
+
+fn get_value(msg: &Message, value: &serde_json::Value) -> serde_json::Value {
+    match get_dynamic_value_from_json(value) {
+        common::DynamicValue::Const(value) => value,
+        common::DynamicValue::Field(s) => match msg.get(&s) {
+            Some(value) => value.clone().into(),
+            None => serde_json::Value::Null,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use influxdb2::models::WriteDataPoint as _;
+
+    use super::*;
+
+    fn new_task_loop(batch_size: usize) -> TaskLoop {
+        let sink_conf: SinkConf = serde_json::from_value(serde_json::json!({
+            "bucket": "b",
+            "mesaurement": "cpu",
+            "fields": [["value", "${value}"], ["n", 1]],
+            "tags": [["host", "${host}"], ["region", "${region}"]],
+            "precision": "milliseconds",
+            "timestamp_field": "ts",
+            "batch_size": batch_size,
+            "batch_timeout": 1000,
+            "message_retain": {"type": "none", "count": null, "time": null, "size": null},
+            "gzip": true,
+        }))
+        .unwrap();
+        let influxdb_conf = InfluxdbConf {
+            host: "127.0.0.1".to_owned(),
+            port: 8086,
+            org: "org".to_owned(),
+            api_token: "token".to_owned(),
+        };
+        let (err, _) = BiLock::new(None);
+        let (app_err_tx, _) = unbounded_channel();
+        let (_, stop_signal_rx) = watch::channel(());
+        let (_, mb_rx) = unbounded_channel();
+        TaskLoop::new(
+            "sink".to_owned(),
+            sink_conf,
+            err,
+            Arc::new(influxdb_conf),
+            app_err_tx,
+            stop_signal_rx,
+            mb_rx,
+        )
+        .unwrap()
+    }
+
+    fn new_msg(value: f64) -> Message {
+        let mut msg = Message::default();
+        msg.add("value".to_owned(), MessageValue::Float64(value));
+        msg.add("host".to_owned(), MessageValue::String("a".to_owned()));
+        msg
+    }
+
+    #[test]
+    fn test_build_data_point() {
+        let mut task_loop = new_task_loop(1);
+        let mut msg = new_msg(1.5);
+        msg.add("ts".to_owned(), MessageValue::Int64(1_700_000_000_000));
+        let mut line = Vec::new();
+        task_loop
+            .build_data_point(&msg)
+            .unwrap()
+            .write_data_point_to(&mut line)
+            .unwrap();
+        // 消息中不存在的标签不写入
+        assert_eq!(
+            String::from_utf8(line).unwrap(),
+            "cpu,host=a n=1i,value=1.5 1700000000000\n"
+        );
+
+        let mut msg = Message::default();
+        msg.add("host".to_owned(), MessageValue::String("a".to_owned()));
+        task_loop.sink_conf.fields = vec![("value".to_owned(), serde_json::json!("${value}"))];
+        assert!(task_loop.build_data_point(&msg).is_none());
+    }
+
+    #[test]
+    fn test_get_timestamp() {
+        let mut task_loop = new_task_loop(1);
+        let mut msg = new_msg(1.0);
+        msg.insert_metadata("timestamp".to_owned(), MessageValue::Int64(2_000));
+        assert_eq!(task_loop.get_timestamp(&msg), 2_000);
+
+        msg.add(
+            "ts".to_owned(),
+            MessageValue::String("1970-01-01T00:00:03Z".to_owned()),
+        );
+        assert_eq!(task_loop.get_timestamp(&msg), 3_000);
+
+        task_loop.sink_conf.precision = Precision::Seconds;
+        assert_eq!(task_loop.get_timestamp(&msg), 3);
+        task_loop.sink_conf.precision = Precision::Nanoseconds;
+        assert_eq!(task_loop.get_timestamp(&msg), 3_000_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_batching() {
+        let mut task_loop = new_task_loop(3);
+        let influxdb_client = new_influxdb_client(&task_loop.influxdb_conf, true).unwrap();
+        for i in 0..2 {
+            let mut mb = MessageBatch::default();
+            mb.push_message(new_msg(i as f64));
+            task_loop.handle_data(&influxdb_client, mb).await;
+        }
+        // 未达到batch_size时等待超时后再写入
+        assert_eq!(task_loop.pending_mb.as_ref().unwrap().len(), 2);
+        assert!(task_loop.flush_deadline.is_some());
+        assert!(task_loop.message_retainer.is_empty());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(429));
+        assert!(is_retryable(503));
+        assert!(!is_retryable(400));
+        assert!(!is_retryable(404));
+    }
 }
@@ -0,0 +1,223 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use common::error::{HaliaError, HaliaResult};
+use futures::lock::BiLock;
+use halia_derive::{ResourceErr, ResourceStop, SourceRxs};
+use influxdb2::{api::query::FluxRecord, models::Query, Client};
+use influxdb2_structmap::value::Value;
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
+use tokio::{
+    select,
+    sync::{mpsc::UnboundedSender, watch},
+    task::JoinHandle,
+    time,
+};
+use types::apps::influxdb_v2::{InfluxdbConf, SourceConf};
+use utils::ErrorManager;
+
+use super::new_influxdb_client;
+
+#[derive(ResourceErr, ResourceStop, SourceRxs)]
+pub struct Source {
+    stop_signal_tx: watch::Sender<()>,
+    err: BiLock<Option<Arc<String>>>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+}
+
+impl Source {
+    pub fn validate_conf(conf: &SourceConf) -> HaliaResult<()> {
+        if conf.query.is_empty() {
+            return Err(HaliaError::Common("查询语句不能为空！".to_owned()));
+        }
+        if conf.interval == 0 {
+            return Err(HaliaError::Common("查询间隔必须大于0！".to_owned()));
+        }
+
+        Ok(())
+    }
+
+    pub fn new(
+        source_id: String,
+        source_conf: SourceConf,
+        influxdb_conf: Arc<InfluxdbConf>,
+        app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    ) -> Self {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (err1, err2) = BiLock::new(None);
+        let (mb_txs1, mb_txs2) = BiLock::new(vec![]);
+
+        let error_manager = ErrorManager::new(
+            utils::error_manager::ResourceType::AppSource,
+            source_id,
+            err1,
+        );
+        let task_loop = TaskLoop {
+            source_conf,
+            influxdb_conf,
+            start: None,
+            app_err_tx,
+            stop_signal_rx,
+            mb_txs: mb_txs1,
+            error_manager,
+        };
+        let join_handle = task_loop.start();
+
+        Self {
+            stop_signal_tx,
+            err: err2,
+            join_handle: Some(join_handle),
+            mb_txs: mb_txs2,
+        }
+    }
+
+    pub async fn update_conf(&mut self, source_conf: SourceConf) {
+        let mut task_loop = self.stop().await;
+        // 查询变化后游标重新从回溯时间开始
+        if task_loop.source_conf.query != source_conf.query {
+            task_loop.start = None;
+        }
+        task_loop.source_conf = source_conf;
+        self.join_handle = Some(task_loop.start());
+    }
+
+    pub async fn update_influxdb_client(&mut self, influxdb_conf: Arc<InfluxdbConf>) {
+        let mut task_loop = self.stop().await;
+        task_loop.influxdb_conf = influxdb_conf;
+        self.join_handle = Some(task_loop.start());
+    }
+}
+
+pub struct TaskLoop {
+    source_conf: SourceConf,
+    influxdb_conf: Arc<InfluxdbConf>,
+    // 下一次查询的起始时间，查询成功后前移到本次查询的结束时间
+    start: Option<DateTime<Utc>>,
+    app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+    error_manager: ErrorManager,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        let influxdb_client = new_influxdb_client(&self.influxdb_conf, false);
+        let mut interval = time::interval(Duration::from_millis(self.source_conf.interval));
+
+        tokio::spawn(async move {
+            let influxdb_client = match influxdb_client {
+                Ok(influxdb_client) => influxdb_client,
+                Err(e) => {
+                    self.error_manager.set_err(Arc::new(e.to_string())).await;
+                    let _ = self.stop_signal_rx.changed().await;
+                    return self;
+                }
+            };
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    _ = interval.tick() => {
+                        self.query(&influxdb_client).await;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn query(&mut self, influxdb_client: &Client) {
+        // 没有规则引用时不查询，游标保持不动
+        if self.mb_txs.lock().await.is_empty() {
+            return;
+        }
+
+        let end = Utc::now() - chrono::Duration::seconds(self.source_conf.delay as i64);
+        let start = *self.start.get_or_insert(
+            end - chrono::Duration::seconds(self.source_conf.initial_lookback as i64),
+        );
+        if end <= start {
+            return;
+        }
+
+        let query = self
+            .source_conf
+            .query
+            .replace("$start", &start.to_rfc3339_opts(SecondsFormat::Nanos, true))
+            .replace("$end", &end.to_rfc3339_opts(SecondsFormat::Nanos, true));
+        match influxdb_client.query_raw(Some(Query::new(query))).await {
+            Ok(records) => {
+                let status_changed = self.error_manager.set_ok().await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(None);
+                }
+                self.start = Some(end);
+                self.send_mb(transfer_records(records)).await;
+            }
+            Err(influxdb2::RequestError::ReqwestProcessing { source }) => {
+                let err = Arc::new(source.to_string());
+                let status_changed = self.error_manager.set_err(err.clone()).await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(Some(err));
+                }
+            }
+            // 查询语句错误等，游标不前移，修正配置后重新查询
+            Err(e) => {
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+            }
+        }
+    }
+
+    async fn send_mb(&mut self, mb: MessageBatch) {
+        if mb.len() == 0 {
+            return;
+        }
+
+        let mut mb_txs = self.mb_txs.lock().await;
+        match mb_txs.len() {
+            0 => {}
+            1 => {
+                let mb = RuleMessageBatch::Owned(mb);
+                if mb_txs[0].send(mb).is_err() {
+                    mb_txs.remove(0);
+                }
+            }
+            _ => {
+                let mb = RuleMessageBatch::Arc(Arc::new(mb));
+                mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+            }
+        }
+    }
+}
+
+// 每一条记录转换为一条消息，字段名与Flux结果的列名一致
+fn transfer_records(records: Vec<FluxRecord>) -> MessageBatch {
+    let mut mb = MessageBatch::default();
+    for record in records {
+        let mut msg = Message::default();
+        for (column, value) in record.values {
+            msg.add(column, transfer_value(value));
+        }
+        mb.push_message(msg);
+    }
+    mb
+}
+
+fn transfer_value(value: Value) -> MessageValue {
+    match value {
+        Value::Unknown => MessageValue::Null,
+        Value::String(s) => MessageValue::String(s),
+        Value::Double(f) => MessageValue::Float64(f.into_inner()),
+        Value::Bool(b) => MessageValue::Boolean(b),
+        Value::Long(i) => MessageValue::Int64(i),
+        Value::UnsignedLong(u) => MessageValue::Int64(u as i64),
+        Value::Duration(d) => match d.num_nanoseconds() {
+            Some(ns) => MessageValue::Int64(ns),
+            None => MessageValue::Null,
+        },
+        Value::Base64Binary(bytes) => MessageValue::Bytes(bytes),
+        Value::TimeRFC(t) => MessageValue::String(t.to_rfc3339()),
+    }
+}
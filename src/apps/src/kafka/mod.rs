@@ -6,8 +6,16 @@ use dashmap::DashMap;
 use futures::lock::BiLock;
 use halia_derive::ResourceErr;
 use message::RuleMessageBatch;
-use rskafka::client::{Client, ClientBuilder};
+use rskafka::{
+    client::{
+        error::Error,
+        partition::{PartitionClient, UnknownTopicHandling},
+        Client, ClientBuilder, SaslConfig,
+    },
+    BackoffConfig,
+};
 use sink::Sink;
+use source::Source;
 use tokio::{
     select,
     sync::{
@@ -18,20 +26,28 @@ use tokio::{
     time,
 };
 use tracing::debug;
-use types::apps::kafka::{Conf, SinkConf};
+use types::apps::kafka::{Conf, SinkConf, SourceConf};
 use utils::ErrorManager;
 
 use crate::App;
 
 mod sink;
+mod source;
+mod ssl;
+
+// rskafka内部请求重试的最长时间，超时后返回错误并由app重连
+const BACKOFF_DEADLINE: Duration = Duration::from_secs(30);
+// source、sink获取分区客户端失败或拉取失败后的重试间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(3);
 
 #[derive(ResourceErr)]
 pub struct Kafka {
     err: BiLock<Option<Arc<String>>>,
     stop_signal_tx: watch::Sender<()>,
 
-    kafka_client: Arc<RwLock<Option<Client>>>,
+    kafka_client: Arc<RwLock<Option<Arc<Client>>>>,
 
+    sources: Arc<DashMap<String, Source>>,
     sinks: Arc<DashMap<String, Sink>>,
     app_err_tx: UnboundedSender<Option<Arc<String>>>,
     join_handle: Option<JoinHandle<TaskLoop>>,
@@ -42,59 +58,75 @@ pub fn new(id: String, conf: serde_json::Value) -> Box<dyn App> {
     let kafka_client = Arc::new(RwLock::new(None));
     let (stop_signal_tx, stop_signal_rx) = watch::channel(());
 
+    let sources = Arc::new(DashMap::new());
     let sinks = Arc::new(DashMap::new());
     let (app_err_tx, app_err_rx) = unbounded_channel();
 
     let (err1, err2) = BiLock::new(None);
 
-    // let jhd = JoinHandleData {
-    //     id,
-    //     conf,
-    //     err: err1,
-    //     kafka_client: kafka_client.clone(),
-    //     stop_signal_rx,
-    //     kafka_err_rx,
-    //     sinks: sinks.clone(),
-    // };
-
-    // let jh = Kafka::event_loop(jhd);
+    let task_loop = TaskLoop::new(
+        id,
+        conf,
+        err1,
+        kafka_client.clone(),
+        stop_signal_rx,
+        app_err_rx,
+        sources.clone(),
+        sinks.clone(),
+    );
+    let join_handle = task_loop.start();
 
     Box::new(Kafka {
         err: err2,
+        sources,
         sinks,
         kafka_client,
         stop_signal_tx,
         app_err_tx,
-        join_handle: todo!(),
+        join_handle: Some(join_handle),
     })
 }
 
 struct TaskLoop {
-    app_id: String,
     app_conf: Conf,
-    kafka_client: Arc<RwLock<Option<Client>>>,
+    kafka_client: Arc<RwLock<Option<Arc<Client>>>>,
     stop_signal_rx: watch::Receiver<()>,
     app_err_rx: UnboundedReceiver<Option<Arc<String>>>,
+    sources: Arc<DashMap<String, Source>>,
     sinks: Arc<DashMap<String, Sink>>,
     error_manager: ErrorManager,
 }
 
 impl TaskLoop {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         app_id: String,
         app_conf: Conf,
         app_err: BiLock<Option<Arc<String>>>,
+        kafka_client: Arc<RwLock<Option<Arc<Client>>>>,
+        stop_signal_rx: watch::Receiver<()>,
         app_err_rx: UnboundedReceiver<Option<Arc<String>>>,
+        sources: Arc<DashMap<String, Source>>,
+        sinks: Arc<DashMap<String, Sink>>,
     ) -> Self {
         let error_manager =
             ErrorManager::new(utils::error_manager::ResourceType::App, app_id, app_err);
-        todo!()
+        Self {
+            app_conf,
+            kafka_client,
+            stop_signal_rx,
+            app_err_rx,
+            sources,
+            sinks,
+            error_manager,
+        }
     }
 
     fn start(mut self) -> JoinHandle<Self> {
         tokio::spawn(async move {
-            self.connect_loop().await;
-            // Self::handle_connect_status_changed(&jhd.kafka_client, &jhd.sinks).await;
+            if !self.connect_loop().await {
+                return self;
+            }
 
             loop {
                 select! {
@@ -102,47 +134,49 @@ impl TaskLoop {
                         return self;
                     }
 
-                    e = self.app_err_rx.recv() => {
-                        debug!("Kafka error received, {:?}", e);
-                        self.connect_loop().await;
-                        // Self::handle_connect_status_changed(&jhd.kafka_client, &jhd.sinks).await;
+                    Some(err) = self.app_err_rx.recv() => {
+                        debug!("Kafka error received, {:?}", err);
+                        if let Some(err) = err {
+                            self.error_manager.set_err(err).await;
+                        }
+                        *self.kafka_client.write().await = None;
+                        self.handle_connect_status_changed().await;
+                        if !self.connect_loop().await {
+                            return self;
+                        }
                     }
                 }
             }
         })
     }
 
-    async fn connect_loop(&mut self) {
-        let mut bootstrap_brokers = vec![];
-        for (host, port) in &self.app_conf.bootstrap_brokers {
-            bootstrap_brokers.push(format!("{}:{}", host, port));
-        }
-        let reconnect = self.app_conf.reconnect;
+    // 被停止时返回false
+    async fn connect_loop(&mut self) -> bool {
         loop {
-            match ClientBuilder::new(bootstrap_brokers.clone()).build().await {
+            let res = select! {
+                _ = self.stop_signal_rx.changed() => {
+                    return false;
+                }
+
+                res = build_client(&self.app_conf) => res,
+            };
+
+            match res {
                 Ok(client) => {
-                    *self.kafka_client.write().await = Some(client);
-                    events::insert_connect_succeed(types::events::ResourceType::App, &self.app_id)
-                        .await;
-                    return;
+                    *self.kafka_client.write().await = Some(Arc::new(client));
+                    self.error_manager.set_ok().await;
+                    self.handle_connect_status_changed().await;
+                    // 同一次断连可能被多个source、sink上报，重连后丢弃旧的错误
+                    while self.app_err_rx.try_recv().is_ok() {}
+                    return true;
                 }
                 Err(e) => {
-                    events::insert_connect_failed(
-                        types::events::ResourceType::App,
-                        &self.app_id,
-                        e.to_string(),
-                    )
-                    .await;
-
-                    let err = Arc::new(e.to_string());
-                    let status_changed = self.error_manager.set_err(err).await;
-                    if status_changed {}
-
-                    let sleep = time::sleep(Duration::from_secs(reconnect));
+                    self.error_manager.set_err(Arc::new(e.to_string())).await;
+                    let sleep = time::sleep(Duration::from_secs(self.app_conf.reconnect));
                     tokio::pin!(sleep);
                     select! {
                         _ = self.stop_signal_rx.changed() => {
-                            return;
+                            return false;
                         }
 
                         _ = &mut sleep => {}
@@ -152,21 +186,110 @@ impl TaskLoop {
         }
     }
 
-    async fn handle_connect_status_changed(
-        kafka_client: &Arc<RwLock<Option<Client>>>,
-        sinks: &Arc<DashMap<String, Sink>>,
-    ) {
-        let kafka_client = kafka_client.read().await;
-        for mut sink in sinks.iter_mut() {
-            sink.update_kafka_client(kafka_client.as_ref()).await;
+    async fn handle_connect_status_changed(&self) {
+        let kafka_client = self.kafka_client.read().await.clone();
+        for mut source in self.sources.iter_mut() {
+            source.update_kafka_client(kafka_client.clone()).await;
+        }
+        for mut sink in self.sinks.iter_mut() {
+            sink.update_kafka_client(kafka_client.clone()).await;
+        }
+    }
+}
+
+async fn build_client(conf: &Conf) -> HaliaResult<Client> {
+    let mut bootstrap_brokers = vec![];
+    for (host, port) in &conf.bootstrap_brokers {
+        bootstrap_brokers.push(format!("{}:{}", host, port));
+    }
+
+    let mut builder = ClientBuilder::new(bootstrap_brokers).backoff_config(BackoffConfig {
+        deadline: Some(BACKOFF_DEADLINE),
+        ..Default::default()
+    });
+    if conf.ssl_enable {
+        if let Some(ssl_conf) = &conf.ssl_conf {
+            builder = builder.tls_config(Arc::new(ssl::get_ssl_config(ssl_conf)?));
+        }
+    }
+    if conf.sasl_enable {
+        if let Some(sasl_conf) = &conf.sasl_conf {
+            builder = builder.sasl_config(SaslConfig::Plain {
+                username: sasl_conf.username.clone(),
+                password: sasl_conf.password.clone(),
+            });
         }
     }
+
+    builder
+        .build()
+        .await
+        .map_err(|e| HaliaError::Common(e.to_string()))
+}
+
+async fn new_partition_client(
+    kafka_client: &Client,
+    topic: &String,
+    partition: i32,
+    unknown_topic_handling: &types::apps::kafka::UnknownTopicHandling,
+) -> Result<PartitionClient, Error> {
+    kafka_client
+        .partition_client(
+            topic,
+            partition,
+            transfer_unknown_topic_handling(unknown_topic_handling),
+        )
+        .await
+}
+
+fn transfer_unknown_topic_handling(
+    unknown_topic_handling: &types::apps::kafka::UnknownTopicHandling,
+) -> UnknownTopicHandling {
+    match unknown_topic_handling {
+        types::apps::kafka::UnknownTopicHandling::Error => UnknownTopicHandling::Error,
+        types::apps::kafka::UnknownTopicHandling::Retry => UnknownTopicHandling::Retry,
+    }
 }
 
-pub fn validate_conf(_conf: &serde_json::Value) -> HaliaResult<()> {
+// 连接类的错误需要app重新建立客户端
+fn is_connection_err(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Connection(_) | Error::Request(_) | Error::RetryFailed(_)
+    )
+}
+
+pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: Conf = serde_json::from_value(conf.clone())?;
+    if conf.bootstrap_brokers.is_empty() {
+        return Err(HaliaError::Common("broker地址不能为空！".to_owned()));
+    }
+    if conf.reconnect == 0 {
+        return Err(HaliaError::Common("重连间隔必须大于0！".to_owned()));
+    }
+    if conf.ssl_enable {
+        match &conf.ssl_conf {
+            Some(ssl_conf) => {
+                ssl::get_ssl_config(ssl_conf)?;
+            }
+            None => return Err(HaliaError::Common("ssl配置为空！".to_owned())),
+        }
+    }
+    if conf.sasl_enable && conf.sasl_conf.is_none() {
+        return Err(HaliaError::Common("sasl配置为空！".to_owned()));
+    }
     Ok(())
 }
 
+pub async fn process_source_conf(
+    app_id: &String,
+    source_id: &String,
+    conf: &serde_json::Value,
+) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::process_conf(app_id, source_id, &conf).await
+}
+
 pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     let conf: SinkConf = serde_json::from_value(conf.clone())?;
     Sink::validate_conf(&conf)?;
@@ -179,6 +302,20 @@ impl App for Kafka {
         self.read_err().await
     }
 
+    async fn read_source_err(&self, source_id: &String) -> HaliaResult<Option<Arc<String>>> {
+        match self.sources.get(source_id) {
+            Some(source) => Ok(source.read_err().await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn read_sink_err(&self, sink_id: &String) -> HaliaResult<Option<Arc<String>>> {
+        match self.sinks.get(sink_id) {
+            Some(sink) => Ok(sink.read_err().await),
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+
     async fn update(
         &mut self,
         _old_conf: serde_json::Value,
@@ -188,6 +325,8 @@ impl App for Kafka {
         self.stop_signal_tx.send(()).unwrap();
         let mut task_loop = self.join_handle.take().unwrap().await.unwrap();
         task_loop.app_conf = new_conf;
+        *task_loop.kafka_client.write().await = None;
+        task_loop.handle_connect_status_changed().await;
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
         Ok(())
@@ -195,21 +334,75 @@ impl App for Kafka {
 
     async fn stop(&mut self) {
         self.stop_signal_tx.send(()).unwrap();
+        self.join_handle.take().unwrap().await.unwrap();
 
+        for mut source in self.sources.iter_mut() {
+            source.stop().await;
+        }
         for mut sink in self.sinks.iter_mut() {
             sink.stop().await;
         }
     }
 
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        let source = Source::new(
+            source_id.clone(),
+            conf,
+            self.kafka_client.read().await.clone(),
+            self.app_err_tx.clone(),
+        )
+        .await?;
+        self.sources.insert(source_id, source);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: String,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: SourceConf = serde_json::from_value(new_conf)?;
+        match self.sources.get_mut(&source_id) {
+            Some(mut source) => source.update_conf(new_conf).await,
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn delete_source(&mut self, source_id: String) -> HaliaResult<()> {
+        match self.sources.remove(&source_id) {
+            Some((_, mut source)) => {
+                source.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt).await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let conf: SinkConf = serde_json::from_value(conf.clone())?;
         let sink = Sink::new(
             sink_id.clone(),
             conf,
-            self.kafka_client.read().await.as_ref(),
+            self.kafka_client.read().await.clone(),
             self.app_err_tx.clone(),
-        )
-        .await;
+        )?;
         self.sinks.insert(sink_id, sink);
 
         Ok(())
@@ -225,8 +418,7 @@ impl App for Kafka {
             Some(mut sink) => {
                 let old_conf: SinkConf = serde_json::from_value(old_conf)?;
                 let new_conf: SinkConf = serde_json::from_value(new_conf)?;
-                sink.update_conf(old_conf, new_conf).await;
-                Ok(())
+                sink.update_conf(&sink_id, old_conf, new_conf).await
             }
             None => Err(HaliaError::NotFound(sink_id)),
         }
@@ -1,16 +1,16 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
-use anyhow::Result;
-use chrono::{TimeZone, Utc};
+use chrono::Utc;
 use common::{
-    error::HaliaResult,
+    error::{HaliaError, HaliaResult},
     sink_message_retain::{self, SinkMessageRetain},
 };
 use futures::lock::BiLock;
-use message::RuleMessageBatch;
+use halia_derive::{ResourceErr, ResourceStop, SinkTxs};
+use message::{MessageBatch, RuleMessageBatch};
 use rskafka::{
     client::{
-        partition::{Compression, PartitionClient, UnknownTopicHandling},
+        partition::{Compression, PartitionClient},
         Client,
     },
     record::Record,
@@ -22,20 +22,24 @@ use tokio::{
         watch,
     },
     task::JoinHandle,
+    time,
 };
 use types::apps::kafka::SinkConf;
 use utils::ErrorManager;
 
+use super::{is_connection_err, new_partition_client};
+
+#[derive(ResourceErr, ResourceStop, SinkTxs)]
 pub struct Sink {
     err: BiLock<Option<Arc<String>>>,
     stop_signal_tx: watch::Sender<()>,
     join_handle: Option<JoinHandle<TaskLoop>>,
-    pub mb_tx: UnboundedSender<RuleMessageBatch>,
+    mb_tx: UnboundedSender<RuleMessageBatch>,
 }
 
 pub struct TaskLoop {
-    sink_id: String,
     sink_conf: SinkConf,
+    kafka_client: Option<Arc<Client>>,
     partition_client: Option<PartitionClient>,
     app_err_tx: UnboundedSender<Option<Arc<String>>>,
     stop_signal_rx: watch::Receiver<()>,
@@ -49,31 +53,32 @@ impl TaskLoop {
         sink_id: String,
         sink_conf: SinkConf,
         sink_err: BiLock<Option<Arc<String>>>,
-        partition_client: Option<PartitionClient>,
+        kafka_client: Option<Arc<Client>>,
         app_err_tx: UnboundedSender<Option<Arc<String>>>,
         stop_signal_rx: watch::Receiver<()>,
         mb_rx: UnboundedReceiver<RuleMessageBatch>,
-    ) -> Self {
-        let message_retainer = sink_message_retain::new(&sink_conf.message_retain);
+    ) -> HaliaResult<Self> {
+        let message_retainer = sink_message_retain::new(&sink_conf.message_retain, &sink_id)?;
         let error_manager = ErrorManager::new(
             utils::error_manager::ResourceType::AppSink,
-            sink_id.clone(),
+            sink_id,
             sink_err,
         );
-        Self {
-            sink_id,
+        Ok(Self {
             sink_conf,
-            partition_client,
+            kafka_client,
+            partition_client: None,
             app_err_tx,
             stop_signal_rx,
             mb_rx,
             message_retainer,
             error_manager,
-        }
+        })
     }
 
     fn start(mut self) -> JoinHandle<Self> {
         let compression = transfer_compression(&self.sink_conf.compression);
+        let mut replay_interval = time::interval(sink_message_retain::REPLAY_INTERVAL);
         tokio::spawn(async move {
             loop {
                 select! {
@@ -81,30 +86,76 @@ impl TaskLoop {
                         return self;
                     }
 
-                    Some(mb) = self.mb_rx.recv() => {
-                        match &self.partition_client {
-                            Some(partition_client) => {
-                                &self.send_msg_to_kafka(partition_client, mb, compression).await;
-                            }
-                            None => {
-                                let mb = mb.take_mb();
-                                self.message_retainer.push(mb);
-                            }
-                        }
+                    Some(rmb) = self.mb_rx.recv() => {
+                        self.handle_data(rmb.take_mb(), compression).await;
+                    }
 
+                    _ = replay_interval.tick() => {
+                        self.init_partition_client().await;
+                        self.replay_retained_mbs(compression).await;
                     }
                 }
             }
         })
     }
 
-    async fn send_msg_to_kafka(
-        &self,
-        partition_client: &PartitionClient,
-        rmb: RuleMessageBatch,
-        compression: Compression,
-    ) -> Result<()> {
-        let mb = rmb.take_mb();
+    async fn init_partition_client(&mut self) {
+        if self.partition_client.is_some() {
+            return;
+        }
+
+        if let Some(kafka_client) = &self.kafka_client {
+            match new_partition_client(
+                kafka_client,
+                &self.sink_conf.topic,
+                self.sink_conf.partition,
+                &self.sink_conf.unknown_topic_handling,
+            )
+            .await
+            {
+                Ok(partition_client) => {
+                    self.partition_client = Some(partition_client);
+                    self.error_manager.set_ok().await;
+                }
+                Err(e) => {
+                    self.error_manager.set_err(Arc::new(e.to_string())).await;
+                }
+            }
+        }
+    }
+
+    async fn handle_data(&mut self, mb: MessageBatch, compression: Compression) {
+        // 未连接或仍有待重发的消息时先保留，保证消息的发送顺序
+        if self.partition_client.is_none() || !self.message_retainer.is_empty() {
+            self.message_retainer.push(mb);
+            self.replay_retained_mbs(compression).await;
+            return;
+        }
+
+        if !self.send_msg_to_kafka(&mb, compression).await {
+            self.message_retainer.push(mb);
+        }
+    }
+
+    async fn replay_retained_mbs(&mut self, compression: Compression) {
+        if self.partition_client.is_none() {
+            return;
+        }
+
+        while let Some(mb) = self.message_retainer.peek() {
+            if !self.send_msg_to_kafka(&mb, compression).await {
+                break;
+            }
+            self.message_retainer.pop();
+        }
+    }
+
+    // 发送失败时返回false，连接类错误交由app重连
+    async fn send_msg_to_kafka(&mut self, mb: &MessageBatch, compression: Compression) -> bool {
+        let partition_client = match &self.partition_client {
+            Some(partition_client) => partition_client,
+            None => return false,
+        };
         let key = self.sink_conf.key.clone().map(|value| {
             let value: Vec<u8> = value.into();
             value
@@ -121,84 +172,109 @@ impl TaskLoop {
             key,
             value: Some(mb.to_json()),
             headers,
-            timestamp: Utc.timestamp_millis_opt(0).unwrap(),
+            timestamp: Utc::now(),
         };
-        partition_client.produce(vec![record], compression).await?;
-        Ok(())
+        // rskafka的produce请求固定使用acks=-1，此处仅限制等待确认的时长
+        let res = time::timeout(
+            Duration::from_millis(self.sink_conf.ack_timeout),
+            partition_client.produce(vec![record], compression),
+        )
+        .await;
+        match res {
+            Ok(Ok(_)) => {
+                self.error_manager.set_ok().await;
+                true
+            }
+            Ok(Err(e)) => {
+                let err = Arc::new(e.to_string());
+                if is_connection_err(&e) {
+                    let _ = self.app_err_tx.send(Some(err.clone()));
+                }
+                self.error_manager.set_err(err).await;
+                false
+            }
+            Err(_) => {
+                self.error_manager
+                    .set_err(Arc::new("等待kafka确认超时！".to_owned()))
+                    .await;
+                false
+            }
+        }
     }
 }
 
 impl Sink {
-    pub fn validate_conf(_conf: &SinkConf) -> HaliaResult<()> {
+    pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
+        if conf.topic.is_empty() {
+            return Err(HaliaError::Common("主题不能为空！".to_owned()));
+        }
+        if conf.ack_timeout == 0 {
+            return Err(HaliaError::Common("确认超时时间必须大于0！".to_owned()));
+        }
         Ok(())
     }
 
-    pub async fn new(
+    pub fn new(
         sink_id: String,
         sink_conf: SinkConf,
-        kafka_client: Option<&Client>,
+        kafka_client: Option<Arc<Client>>,
         app_err_tx: UnboundedSender<Option<Arc<String>>>,
-    ) -> Self {
+    ) -> HaliaResult<Self> {
         let (stop_signal_tx, stop_signal_rx) = watch::channel(());
         let (mb_tx, mb_rx) = unbounded_channel();
         let (sink_err1, sink_err2) = BiLock::new(None);
 
-        let partition_client = new_partition_client(kafka_client, &sink_conf).await;
         let task_loop = TaskLoop::new(
             sink_id,
             sink_conf,
             sink_err1,
-            partition_client,
+            kafka_client,
             app_err_tx,
             stop_signal_rx,
             mb_rx,
-        );
+        )?;
         let join_handle = task_loop.start();
 
-        Self {
+        Ok(Self {
             err: sink_err2,
             stop_signal_tx,
             mb_tx,
             join_handle: Some(join_handle),
-        }
+        })
     }
 
-    pub async fn update_conf(&mut self, _old_conf: SinkConf, sink_conf: SinkConf) {
+    pub async fn update_conf(
+        &mut self,
+        sink_id: &str,
+        old_conf: SinkConf,
+        sink_conf: SinkConf,
+    ) -> HaliaResult<()> {
         let mut task_loop = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut task_loop.message_retainer,
+            &old_conf.message_retain,
+            &sink_conf.message_retain,
+            sink_id,
+        );
+        if old_conf.topic != sink_conf.topic
+            || old_conf.partition != sink_conf.partition
+            || old_conf.unknown_topic_handling != sink_conf.unknown_topic_handling
+        {
+            task_loop.partition_client = None;
+        }
         task_loop.sink_conf = sink_conf;
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
+        res
     }
 
-    pub async fn update_kafka_client(&mut self, kafka_client: Option<&Client>) {
+    pub async fn update_kafka_client(&mut self, kafka_client: Option<Arc<Client>>) {
         let mut task_loop = self.stop().await;
-        let partition_client = new_partition_client(kafka_client, &task_loop.sink_conf).await;
-        task_loop.partition_client = partition_client;
+        task_loop.kafka_client = kafka_client;
+        task_loop.partition_client = None;
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
     }
-
-    pub async fn stop(&mut self) -> TaskLoop {
-        self.stop_signal_tx.send(()).unwrap();
-        self.join_handle.take().unwrap().await.unwrap()
-    }
-
-    pub fn get_txs(&self, cnt: usize) -> Vec<UnboundedSender<RuleMessageBatch>> {
-        let mut txs = vec![];
-        for _ in 0..cnt {
-            txs.push(self.mb_tx.clone());
-        }
-        txs
-    }
-}
-
-fn transfer_unknown_topic_handling(
-    unknown_topic_handling: &types::apps::kafka::UnknownTopicHandling,
-) -> UnknownTopicHandling {
-    match unknown_topic_handling {
-        types::apps::kafka::UnknownTopicHandling::Error => UnknownTopicHandling::Error,
-        types::apps::kafka::UnknownTopicHandling::Retry => UnknownTopicHandling::Retry,
-    }
 }
 
 fn transfer_compression(compression: &types::apps::kafka::Compression) -> Compression {
@@ -210,22 +286,3 @@ fn transfer_compression(compression: &types::apps::kafka::Compression) -> Compre
         types::apps::kafka::Compression::Zstd => Compression::Zstd,
     }
 }
-
-async fn new_partition_client(
-    kafka_client: Option<&Client>,
-    conf: &SinkConf,
-) -> Option<PartitionClient> {
-    match kafka_client {
-        Some(kafka_client) => Some(
-            kafka_client
-                .partition_client(
-                    &conf.topic,
-                    conf.partition,
-                    transfer_unknown_topic_handling(&conf.unknown_topic_handling),
-                )
-                .await
-                .unwrap(),
-        ),
-        None => None,
-    }
-}
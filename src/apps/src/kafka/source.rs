@@ -0,0 +1,378 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use common::error::{HaliaError, HaliaResult};
+use futures::lock::BiLock;
+use halia_derive::{ResourceErr, ResourceStop, SourceRxs};
+use message::RuleMessageBatch;
+use rskafka::{
+    client::{
+        error::{Error, ProtocolError},
+        partition::{OffsetAt, PartitionClient},
+        Client,
+    },
+    record::RecordAndOffset,
+};
+use schema::Decoder;
+use tokio::{
+    select,
+    sync::{mpsc::UnboundedSender, watch},
+    task::JoinHandle,
+    time,
+};
+use tracing::warn;
+use types::apps::kafka::{OffsetReset, SourceConf};
+use utils::ErrorManager;
+
+use super::{is_connection_err, new_partition_client, RETRY_INTERVAL};
+
+#[derive(ResourceErr, ResourceStop, SourceRxs)]
+pub struct Source {
+    stop_signal_tx: watch::Sender<()>,
+    err: BiLock<Option<Arc<String>>>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+}
+
+pub struct TaskLoop {
+    source_id: String,
+    source_conf: SourceConf,
+    kafka_client: Option<Arc<Client>>,
+    partition_client: Option<PartitionClient>,
+    // 下一条待消费消息的偏移量，为空时按offset_reset重新获取，消费后按源id持久化
+    offset: Option<i64>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+    app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    decoder: Box<dyn Decoder>,
+    error_manager: ErrorManager,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            loop {
+                // 没有规则引用时不消费，避免消息丢失
+                if self.partition_client.is_none() || self.mb_txs.lock().await.is_empty() {
+                    self.init_partition_client().await;
+                    select! {
+                        _ = self.stop_signal_rx.changed() => {
+                            return self;
+                        }
+
+                        _ = time::sleep(RETRY_INTERVAL) => {}
+                    }
+                    continue;
+                }
+
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    res = fetch_records(
+                        self.partition_client.as_ref().unwrap(),
+                        self.offset,
+                        &self.source_conf,
+                    ) => {
+                        if !self.handle_fetch_result(res).await {
+                            select! {
+                                _ = self.stop_signal_rx.changed() => {
+                                    return self;
+                                }
+
+                                _ = time::sleep(RETRY_INTERVAL) => {}
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    async fn init_partition_client(&mut self) {
+        if self.partition_client.is_some() {
+            return;
+        }
+
+        if let Some(kafka_client) = &self.kafka_client {
+            match new_partition_client(
+                kafka_client,
+                &self.source_conf.topic,
+                self.source_conf.partition,
+                &self.source_conf.unknown_topic_handling,
+            )
+            .await
+            {
+                Ok(partition_client) => {
+                    self.partition_client = Some(partition_client);
+                    self.error_manager.set_ok().await;
+                    if self.offset.is_none() {
+                        self.load_offset().await;
+                    }
+                }
+                Err(e) => {
+                    self.error_manager.set_err(Arc::new(e.to_string())).await;
+                }
+            }
+        }
+    }
+
+    // 从上次持久化的偏移量继续消费，主题或分区变化后按offset_reset重新获取
+    async fn load_offset(&mut self) {
+        match storage::app::source_offset::read(
+            &self.source_id,
+            &self.source_conf.topic,
+            self.source_conf.partition,
+        )
+        .await
+        {
+            Ok(offset) => self.offset = offset,
+            Err(e) => warn!("read kafka offset err: {}", e),
+        }
+    }
+
+    async fn save_offset(&self) {
+        if let Some(offset) = self.offset {
+            if let Err(e) = storage::app::source_offset::upsert(
+                &self.source_id,
+                &self.source_conf.topic,
+                self.source_conf.partition,
+                offset,
+            )
+            .await
+            {
+                warn!("save kafka offset err: {}", e);
+            }
+        }
+    }
+
+    // 返回false时等待一段时间后再拉取
+    async fn handle_fetch_result(
+        &mut self,
+        res: Result<(i64, Vec<RecordAndOffset>), Error>,
+    ) -> bool {
+        match res {
+            Ok((offset, records)) => {
+                self.error_manager.set_ok().await;
+                self.offset = Some(next_offset(offset, &records));
+                if records.is_empty() {
+                    return true;
+                }
+                for record in records {
+                    if let Some(value) = record.record.value {
+                        self.handle_value(value).await;
+                    }
+                }
+                self.save_offset().await;
+                true
+            }
+            Err(Error::ServerError {
+                protocol_error: ProtocolError::OffsetOutOfRange,
+                ..
+            }) => {
+                warn!("kafka offset out of range");
+                self.offset = None;
+                true
+            }
+            Err(e) => {
+                let err = Arc::new(e.to_string());
+                if is_connection_err(&e) {
+                    let _ = self.app_err_tx.send(Some(err.clone()));
+                }
+                self.error_manager.set_err(err).await;
+                false
+            }
+        }
+    }
+
+    async fn handle_value(&mut self, value: Vec<u8>) {
+        let mb = match self.decoder.decode(Bytes::from(value)) {
+            Ok(mb) => mb,
+            Err(e) => {
+                warn!("decode err :{}", e);
+                return;
+            }
+        };
+
+        let mut mb_txs = self.mb_txs.lock().await;
+        match mb_txs.len() {
+            0 => {}
+            1 => {
+                let mb = RuleMessageBatch::Owned(mb);
+                if mb_txs[0].send(mb).is_err() {
+                    mb_txs.remove(0);
+                }
+            }
+            _ => {
+                let mb = RuleMessageBatch::Arc(Arc::new(mb));
+                mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+            }
+        }
+    }
+}
+
+async fn fetch_records(
+    partition_client: &PartitionClient,
+    offset: Option<i64>,
+    conf: &SourceConf,
+) -> Result<(i64, Vec<RecordAndOffset>), Error> {
+    let offset = match offset {
+        Some(offset) => offset,
+        None => {
+            partition_client
+                .get_offset(transfer_offset_reset(&conf.offset_reset))
+                .await?
+        }
+    };
+    let (records, _high_watermark) = partition_client
+        .fetch_records(offset, 1..conf.max_bytes, conf.max_wait_ms)
+        .await?;
+    Ok((offset, records))
+}
+
+// 下一次拉取的偏移量，没有拉取到消息时保持本次拉取的偏移量
+fn next_offset(offset: i64, records: &[RecordAndOffset]) -> i64 {
+    match records.last() {
+        Some(record) => record.offset + 1,
+        None => offset,
+    }
+}
+
+fn transfer_offset_reset(offset_reset: &OffsetReset) -> OffsetAt {
+    match offset_reset {
+        OffsetReset::Earliest => OffsetAt::Earliest,
+        OffsetReset::Latest => OffsetAt::Latest,
+    }
+}
+
+impl Source {
+    pub async fn process_conf(
+        app_id: &String,
+        source_id: &String,
+        conf: &SourceConf,
+    ) -> HaliaResult<()> {
+        if conf.topic.is_empty() {
+            return Err(HaliaError::Common("主题不能为空！".to_owned()));
+        }
+        if conf.max_bytes <= 1 {
+            return Err(HaliaError::Common(
+                "单次拉取的最大字节数必须大于1！".to_owned(),
+            ));
+        }
+        if conf.max_wait_ms <= 0 {
+            return Err(HaliaError::Common("拉取等待时间必须大于0！".to_owned()));
+        }
+
+        match conf.decode_type {
+            types::schema::DecodeType::Protobuf => match &conf.schema_id {
+                Some(schema_id) => {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+                None => return Err(HaliaError::Common("请填写schema_id".to_owned())),
+            },
+            types::schema::DecodeType::Csv | types::schema::DecodeType::Avro => {
+                if let Some(schema_id) = &conf.schema_id {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+            }
+            types::schema::DecodeType::Raw
+            | types::schema::DecodeType::Yaml
+            | types::schema::DecodeType::Json
+            | types::schema::DecodeType::Toml => {}
+        }
+
+        Ok(())
+    }
+
+    pub async fn new(
+        source_id: String,
+        source_conf: SourceConf,
+        kafka_client: Option<Arc<Client>>,
+        app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    ) -> HaliaResult<Self> {
+        let decoder = schema::new_decoder(&source_conf.decode_type, &source_conf.schema_id).await?;
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (err1, err2) = BiLock::new(None);
+        let (mb_txs1, mb_txs2) = BiLock::new(vec![]);
+
+        let error_manager = ErrorManager::new(
+            utils::error_manager::ResourceType::AppSource,
+            source_id.clone(),
+            err1,
+        );
+        let task_loop = TaskLoop {
+            source_id,
+            source_conf,
+            kafka_client,
+            partition_client: None,
+            offset: None,
+            stop_signal_rx,
+            mb_txs: mb_txs1,
+            app_err_tx,
+            decoder,
+            error_manager,
+        };
+        let join_handle = task_loop.start();
+
+        Ok(Self {
+            stop_signal_tx,
+            err: err2,
+            join_handle: Some(join_handle),
+            mb_txs: mb_txs2,
+        })
+    }
+
+    pub async fn update_conf(&mut self, source_conf: SourceConf) -> HaliaResult<()> {
+        let decoder = schema::new_decoder(&source_conf.decode_type, &source_conf.schema_id).await?;
+        let mut task_loop = self.stop().await;
+        if task_loop.source_conf.topic != source_conf.topic
+            || task_loop.source_conf.partition != source_conf.partition
+            || task_loop.source_conf.unknown_topic_handling != source_conf.unknown_topic_handling
+        {
+            task_loop.partition_client = None;
+            task_loop.offset = None;
+        }
+        task_loop.source_conf = source_conf;
+        task_loop.decoder = decoder;
+        self.join_handle = Some(task_loop.start());
+        Ok(())
+    }
+
+    pub async fn update_kafka_client(&mut self, kafka_client: Option<Arc<Client>>) {
+        let mut task_loop = self.stop().await;
+        task_loop.kafka_client = kafka_client;
+        task_loop.partition_client = None;
+        self.join_handle = Some(task_loop.start());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rskafka::record::Record;
+
+    use super::*;
+
+    fn new_record(offset: i64) -> RecordAndOffset {
+        RecordAndOffset {
+            record: Record {
+                key: None,
+                value: Some(vec![]),
+                headers: BTreeMap::new(),
+                timestamp: chrono::Utc::now(),
+            },
+            offset,
+        }
+    }
+
+    #[test]
+    fn test_next_offset() {
+        // 没有新消息时从本次的偏移量继续拉取
+        assert_eq!(next_offset(10, &[]), 10);
+        // 从最后一条消息之后继续，压缩后的主题中偏移量可能不连续
+        assert_eq!(next_offset(10, &[new_record(10), new_record(11)]), 12);
+        assert_eq!(next_offset(10, &[new_record(13), new_record(20)]), 21);
+    }
+}
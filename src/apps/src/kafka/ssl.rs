@@ -0,0 +1,110 @@
+use std::{
+    io::{BufReader, Cursor},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use common::error::{HaliaError, HaliaResult};
+use kafka_rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+};
+use rustls_pemfile::Item;
+use types::SslConf;
+
+// rskafka使用rustls 0.21，与mqtt客户端的ssl配置不能共用
+pub(crate) fn get_ssl_config(ssl_conf: &SslConf) -> HaliaResult<ClientConfig> {
+    let client_auth = match (&ssl_conf.client_cert, &ssl_conf.client_key) {
+        (Some(client_cert), Some(client_key)) => {
+            Some((parse_certs(client_cert)?, parse_private_key(client_key)?))
+        }
+        (None, None) => None,
+        _ => {
+            return Err(HaliaError::Common(
+                "客户端证书与私钥必须同时填写！".to_owned(),
+            ))
+        }
+    };
+
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let config = match ssl_conf.verify {
+        true => {
+            let mut root_cert_store = RootCertStore::empty();
+            match &ssl_conf.ca_cert {
+                Some(ca_cert) => {
+                    for cert in parse_certs(ca_cert)? {
+                        root_cert_store
+                            .add(&cert)
+                            .map_err(|e| HaliaError::Common(format!("CA证书错误：{}", e)))?;
+                    }
+                }
+                None => root_cert_store.add_trust_anchors(
+                    webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                        OwnedTrustAnchor::from_subject_spki_name_constraints(
+                            ta.subject.as_ref(),
+                            ta.subject_public_key_info.as_ref(),
+                            ta.name_constraints.as_ref().map(|nc| nc.as_ref()),
+                        )
+                    }),
+                ),
+            }
+            let builder = builder.with_root_certificates(root_cert_store);
+            match client_auth {
+                Some((client_certs, key)) => builder.with_client_auth_cert(client_certs, key),
+                None => Ok(builder.with_no_client_auth()),
+            }
+        }
+        false => {
+            let builder =
+                builder.with_custom_certificate_verifier(Arc::new(ServerCertVerifierNo {}));
+            match client_auth {
+                Some((client_certs, key)) => builder.with_client_auth_cert(client_certs, key),
+                None => Ok(builder.with_no_client_auth()),
+            }
+        }
+    };
+
+    config.map_err(|e| HaliaError::Common(format!("客户端证书错误：{}", e)))
+}
+
+fn parse_certs(pem: &str) -> HaliaResult<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(Cursor::new(pem)))
+        .map(|cert| cert.map(|cert| Certificate(cert.to_vec())))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| HaliaError::Common(format!("证书解析错误：{}", e)))?;
+    if certs.is_empty() {
+        return Err(HaliaError::Common("证书内容为空！".to_owned()));
+    }
+    Ok(certs)
+}
+
+fn parse_private_key(pem: &str) -> HaliaResult<PrivateKey> {
+    let mut key_buffer = BufReader::new(Cursor::new(pem));
+    loop {
+        match rustls_pemfile::read_one(&mut key_buffer)
+            .map_err(|e| HaliaError::Common(format!("私钥解析错误：{}", e)))?
+        {
+            Some(Item::Sec1Key(key)) => return Ok(PrivateKey(key.secret_sec1_der().to_vec())),
+            Some(Item::Pkcs1Key(key)) => return Ok(PrivateKey(key.secret_pkcs1_der().to_vec())),
+            Some(Item::Pkcs8Key(key)) => return Ok(PrivateKey(key.secret_pkcs8_der().to_vec())),
+            None => return Err(HaliaError::Common("未找到有效的私钥！".to_owned())),
+            _ => {}
+        }
+    }
+}
+
+struct ServerCertVerifierNo {}
+
+impl ServerCertVerifier for ServerCertVerifierNo {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, kafka_rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
@@ -15,15 +15,24 @@ use types::{
     Pagination, Status, Summary,
 };
 
+mod coap_server;
 mod http;
+mod http_server;
 mod influxdb_v1;
 mod influxdb_v2;
 mod kafka;
 mod mqtt_client_ssl;
+mod mqtt_client_template;
+mod mqtt_client_topic;
+mod mqtt_server;
 mod mqtt_v311;
 mod mqtt_v50;
+mod opcua_server;
+mod server_task;
 mod tdengine;
 
+pub use mqtt_client_topic::match_topic;
+
 static GLOBAL_APP_MANAGER: LazyLock<DashMap<String, Box<dyn App>>> =
     LazyLock::new(|| DashMap::new());
 
@@ -152,6 +161,10 @@ pub async fn create_app(req: CreateAppReq) -> HaliaResult<()> {
         AppType::InfluxdbV1 => influxdb_v1::validate_conf(&req.conf)?,
         AppType::InfluxdbV2 => influxdb_v2::validate_conf(&req.conf)?,
         AppType::Tdengine => tdengine::validate_conf(&req.conf)?,
+        AppType::OpcuaServer => opcua_server::validate_conf(&req.conf)?,
+        AppType::MqttServer => mqtt_server::validate_conf(&req.conf)?,
+        AppType::CoapServer => coap_server::validate_conf(&req.conf)?,
+        AppType::HttpServer => http_server::validate_conf(&req.conf)?,
     }
 
     let app_id = common::get_id();
@@ -219,23 +232,39 @@ pub async fn start_app(app_id: String) -> HaliaResult<()> {
         AppType::InfluxdbV1 => influxdb_v1::new(app_id.clone(), db_app.conf),
         AppType::InfluxdbV2 => influxdb_v2::new(app_id.clone(), db_app.conf),
         AppType::Tdengine => tdengine::new(app_id.clone(), db_app.conf),
+        AppType::OpcuaServer => opcua_server::new(app_id.clone(), db_app.conf),
+        AppType::MqttServer => mqtt_server::new(app_id.clone(), db_app.conf),
+        AppType::CoapServer => coap_server::new(app_id.clone(), db_app.conf),
+        AppType::HttpServer => http_server::new(app_id.clone(), db_app.conf),
     };
     GLOBAL_APP_MANAGER.insert(app_id.clone(), app);
 
-    let mut app = GLOBAL_APP_MANAGER.get_mut(&app_id).unwrap();
-    let db_sources = storage::app::source_sink::read_all_sources_by_app_id(&app_id).await?;
+    // 源或动作创建失败时移除未完整启动的应用，避免之后无法重新启动
+    if let Err(e) = create_sources_sinks(&app_id).await {
+        if let Some((_, mut app)) = GLOBAL_APP_MANAGER.remove(&app_id) {
+            app.stop().await;
+        }
+        return Err(e);
+    }
+
+    // storage::app::update_status(&app_id, types::Status::Running).await?;
+    // storage::app::source_sink::update_status_by_app_id(&app_id, types::Status::Running).await?;
+
+    Ok(())
+}
+
+async fn create_sources_sinks(app_id: &String) -> HaliaResult<()> {
+    let mut app = GLOBAL_APP_MANAGER.get_mut(app_id).unwrap();
+    let db_sources = storage::app::source_sink::read_all_sources_by_app_id(app_id).await?;
     for db_source in db_sources {
         app.create_source(db_source.id, db_source.conf).await?;
     }
 
-    let db_sinks = storage::app::source_sink::read_all_sinks_by_app_id(&app_id).await?;
+    let db_sinks = storage::app::source_sink::read_all_sinks_by_app_id(app_id).await?;
     for db_sink in db_sinks {
         app.create_sink(db_sink.id, db_sink.conf).await?;
     }
 
-    // storage::app::update_status(&app_id, types::Status::Running).await?;
-    // storage::app::source_sink::update_status_by_app_id(&app_id, types::Status::Running).await?;
-
     Ok(())
 }
 
@@ -265,6 +294,10 @@ pub async fn delete_app(app_id: String) -> HaliaResult<()> {
 
     events::insert_delete(types::events::ResourceType::App, &app_id).await;
 
+    let db_sources = storage::app::source_sink::read_all_sources_by_app_id(&app_id).await?;
+    for db_source in db_sources {
+        storage::app::source_offset::delete_by_id(&db_source.id).await?;
+    }
     storage::app::delete_by_id(&app_id).await?;
     Ok(())
 }
@@ -274,11 +307,22 @@ pub async fn create_source(app_id: String, req: CreateUpdateSourceSinkReq) -> Ha
     let source_id = common::get_id();
     match app_type {
         AppType::MqttV311 => mqtt_v311::process_source_conf(&app_id, &source_id, &req.conf).await?,
-        AppType::MqttV50 => mqtt_v50::validate_source_conf(&req.conf)?,
+        AppType::MqttV50 => mqtt_v50::process_source_conf(&app_id, &source_id, &req.conf).await?,
         AppType::Http => http::validate_source_conf(&req.conf)?,
-        AppType::Kafka | AppType::InfluxdbV1 | AppType::InfluxdbV2 | AppType::Tdengine => {
-            return Err(HaliaError::NotSupportResource)
+        AppType::OpcuaServer => opcua_server::validate_source_conf(&req.conf)?,
+        AppType::MqttServer => {
+            mqtt_server::process_source_conf(&app_id, &source_id, &req.conf).await?
+        }
+        AppType::CoapServer => {
+            coap_server::process_source_conf(&app_id, &source_id, &req.conf).await?
+        }
+        AppType::HttpServer => {
+            http_server::process_source_conf(&app_id, &source_id, &req.conf).await?
         }
+        AppType::Kafka => kafka::process_source_conf(&app_id, &source_id, &req.conf).await?,
+        AppType::InfluxdbV1 => influxdb_v1::validate_source_conf(&req.conf)?,
+        AppType::InfluxdbV2 => influxdb_v2::validate_source_conf(&req.conf)?,
+        AppType::Tdengine => return Err(HaliaError::NotSupportResource),
     }
 
     if let Some(mut app) = GLOBAL_APP_MANAGER.get_mut(&app_id) {
@@ -321,7 +365,16 @@ pub async fn list_sources(
                 };
                 serde_json::to_value(conf)?
             }
-            AppType::MqttV50 => todo!(),
+            AppType::MqttV50 => {
+                let conf: types::apps::mqtt_client_v50::SourceConf =
+                    serde_json::from_value(db_source.conf)?;
+                let conf = types::apps::mqtt_client_v50::ListSourceConf {
+                    topic: conf.topic,
+                    qos: conf.qos,
+                    decode_type: conf.decode_type,
+                };
+                serde_json::to_value(conf)?
+            }
             AppType::Http => {
                 let conf: types::apps::http_client::SourceConf =
                     serde_json::from_value(db_source.conf)?;
@@ -331,9 +384,64 @@ pub async fn list_sources(
                 };
                 serde_json::to_value(conf)?
             }
-            AppType::Kafka | AppType::InfluxdbV1 | AppType::InfluxdbV2 | AppType::Tdengine => {
-                serde_json::Value::Null
+            AppType::OpcuaServer => db_source.conf,
+            AppType::MqttServer => {
+                let conf: types::apps::mqtt_server::SourceConf =
+                    serde_json::from_value(db_source.conf)?;
+                let conf = types::apps::mqtt_server::ListSourceConf {
+                    topic: conf.topic,
+                    decode_type: conf.decode_type,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::CoapServer => {
+                let conf: types::apps::coap_server::SourceConf =
+                    serde_json::from_value(db_source.conf)?;
+                let conf = types::apps::coap_server::ListSourceConf {
+                    path: conf.path,
+                    decode_type: conf.decode_type,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::HttpServer => {
+                let conf: types::apps::http_server::SourceConf =
+                    serde_json::from_value(db_source.conf)?;
+                let conf = types::apps::http_server::ListSourceConf {
+                    path: conf.path,
+                    auth_method: conf.auth_method,
+                    decode_type: conf.decode_type,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::Kafka => {
+                let conf: types::apps::kafka::SourceConf = serde_json::from_value(db_source.conf)?;
+                let conf = types::apps::kafka::ListSourceConf {
+                    topic: conf.topic,
+                    partition: conf.partition,
+                    decode_type: conf.decode_type,
+                };
+                serde_json::to_value(conf)?
             }
+            AppType::InfluxdbV1 => {
+                let conf: types::apps::influxdb_v1::SourceConf =
+                    serde_json::from_value(db_source.conf)?;
+                let conf = types::apps::influxdb_v1::ListSourceConf {
+                    database: conf.database,
+                    query: conf.query,
+                    interval: conf.interval,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::InfluxdbV2 => {
+                let conf: types::apps::influxdb_v2::SourceConf =
+                    serde_json::from_value(db_source.conf)?;
+                let conf = types::apps::influxdb_v2::ListSourceConf {
+                    query: conf.query,
+                    interval: conf.interval,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::Tdengine => serde_json::Value::Null,
         };
         list.push(ListSourcesSinksItem {
             id: db_source.id,
@@ -368,6 +476,7 @@ pub async fn read_source(app_id: String, source_id: String) -> HaliaResult<ReadS
         status: db_source.status,
         err,
         rule_ref_cnt,
+        retain_depth: None,
     })
 }
 
@@ -376,6 +485,16 @@ pub async fn update_source(
     source_id: String,
     req: CreateUpdateSourceSinkReq,
 ) -> HaliaResult<()> {
+    let app_type: AppType = storage::app::read_app_type(&app_id).await?;
+    if app_type == AppType::MqttV311 {
+        let conf = serde_json::from_value(req.conf.clone())?;
+        mqtt_v311::check_source_topic(&app_id, &source_id, &conf).await?;
+    }
+    if app_type == AppType::HttpServer {
+        let conf = serde_json::from_value(req.conf.clone())?;
+        http_server::check_source_path(&app_id, &source_id, &conf).await?;
+    }
+
     if let Some(mut app) = GLOBAL_APP_MANAGER.get_mut(&app_id) {
         let old_conf = storage::app::source_sink::read_conf(&source_id).await?;
         let new_conf = req.conf.clone();
@@ -394,6 +513,7 @@ pub async fn delete_source(app_id: String, source_id: String) -> HaliaResult<()>
     }
 
     storage::app::source_sink::delete_by_id(&source_id).await?;
+    storage::app::source_offset::delete_by_id(&source_id).await?;
     if let Some(mut app) = GLOBAL_APP_MANAGER.get_mut(&app_id) {
         app.delete_source(source_id).await?;
     }
@@ -424,6 +544,10 @@ pub async fn create_sink(app_id: String, req: CreateUpdateSourceSinkReq) -> Hali
         AppType::InfluxdbV1 => influxdb_v1::validate_sink_conf(&req.conf)?,
         AppType::InfluxdbV2 => influxdb_v2::validate_sink_conf(&req.conf)?,
         AppType::Tdengine => tdengine::validate_sink_conf(&req.conf)?,
+        AppType::OpcuaServer => opcua_server::validate_sink_conf(&req.conf)?,
+        AppType::MqttServer => mqtt_server::validate_sink_conf(&req.conf)?,
+        AppType::CoapServer => coap_server::process_sink_conf(&app_id, None, &req.conf).await?,
+        AppType::HttpServer => return Err(HaliaError::NotSupportResource),
     }
 
     let sink_id = common::get_id();
@@ -456,6 +580,7 @@ pub async fn read_sink(app_id: String, sink_id: String) -> HaliaResult<ReadSourc
         status: db_sink.status,
         err,
         rule_ref_cnt,
+        retain_depth: common::sink_message_retain::get_depth(&sink_id),
     })
 }
 
@@ -492,9 +617,45 @@ pub async fn list_sinks(
             }
             AppType::MqttV50 => todo!(),
             AppType::Http => todo!(),
-            AppType::Kafka | AppType::InfluxdbV1 | AppType::InfluxdbV2 | AppType::Tdengine => {
-                serde_json::Value::Null
+            AppType::OpcuaServer => db_sink.conf,
+            AppType::MqttServer => {
+                let conf: types::apps::mqtt_server::SinkConf =
+                    serde_json::from_value(db_sink.conf)?;
+                let conf = types::apps::mqtt_server::ListSinkConf {
+                    topic: conf.topic,
+                    encode_type: conf.encode_type,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::CoapServer => {
+                let conf: types::apps::coap_server::SinkConf =
+                    serde_json::from_value(db_sink.conf)?;
+                let conf = types::apps::coap_server::ListSinkConf {
+                    path: conf.path,
+                    encode_type: conf.encode_type,
+                };
+                serde_json::to_value(conf)?
             }
+            AppType::Kafka => {
+                let conf: types::apps::kafka::SinkConf = serde_json::from_value(db_sink.conf)?;
+                let conf = types::apps::kafka::ListSinkConf {
+                    topic: conf.topic,
+                    partition: conf.partition,
+                    compression: conf.compression,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::HttpServer => serde_json::Value::Null,
+            AppType::Tdengine => {
+                let conf: types::apps::tdengine::SinkConf = serde_json::from_value(db_sink.conf)?;
+                let conf = types::apps::tdengine::ListSinkConf {
+                    db: conf.db,
+                    mode: conf.mode,
+                    stable: conf.stable,
+                };
+                serde_json::to_value(conf)?
+            }
+            AppType::InfluxdbV1 | AppType::InfluxdbV2 => serde_json::Value::Null,
         };
         list.push(ListSourcesSinksItem {
             id: db_sink.id,
@@ -514,6 +675,11 @@ pub async fn update_sink(
     sink_id: String,
     req: CreateUpdateSourceSinkReq,
 ) -> HaliaResult<()> {
+    let app_type: AppType = storage::app::read_app_type(&app_id).await?;
+    if app_type == AppType::CoapServer {
+        coap_server::process_sink_conf(&app_id, Some(&sink_id), &req.conf).await?;
+    }
+
     if let Some(mut app) = GLOBAL_APP_MANAGER.get_mut(&app_id) {
         let old_conf = storage::app::source_sink::read_conf(&sink_id).await?;
         let new_conf = req.conf.clone();
@@ -534,8 +700,9 @@ pub async fn delete_sink(app_id: String, sink_id: String) -> HaliaResult<()> {
     storage::app::source_sink::delete_by_id(&sink_id).await?;
 
     if let Some(mut app) = GLOBAL_APP_MANAGER.get_mut(&app_id) {
-        app.delete_sink(sink_id).await?;
+        app.delete_sink(sink_id.clone()).await?;
     }
+    common::sink_message_retain::remove_disk_data(&sink_id);
 
     Ok(())
 }
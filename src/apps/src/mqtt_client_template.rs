@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+
+use message::{Message, MessageBatch, MessageValue};
+use regex::Regex;
+
+// ${metadata.xxx}从消息元数据中取值，其余从消息字段中取值
+const METADATA_FIELD_PREFIX: &str = "metadata.";
+
+pub(crate) fn get_message_value<'a>(msg: &'a Message, field: &str) -> Option<&'a MessageValue> {
+    match field.strip_prefix(METADATA_FIELD_PREFIX) {
+        Some(key) => msg.get_metadata(key),
+        None => msg.get(field),
+    }
+}
+
+// 支持${field}占位符的模板，发布时按每条消息替换
+pub(crate) enum Template {
+    Const(String),
+    Dynamic {
+        template: String,
+        fields: Vec<String>,
+    },
+}
+
+impl Template {
+    pub fn new(template: &str) -> Self {
+        let re = Regex::new(r"\$\{(.*?)\}").unwrap();
+        let mut fields = HashSet::new();
+        for cap in re.captures_iter(template) {
+            fields.insert(cap[1].to_owned());
+        }
+
+        if fields.is_empty() {
+            Self::Const(template.to_owned())
+        } else {
+            Self::Dynamic {
+                template: template.to_owned(),
+                fields: fields.into_iter().collect(),
+            }
+        }
+    }
+
+    pub fn is_const(&self) -> bool {
+        matches!(self, Self::Const(_))
+    }
+
+    // 消息中缺少模板引用的字段时返回None
+    pub fn render(&self, msg: &Message) -> Option<String> {
+        match self {
+            Self::Const(value) => Some(value.clone()),
+            Self::Dynamic { template, fields } => {
+                let mut value = template.clone();
+                for field in fields {
+                    let field_value = get_message_value(msg, field)?;
+                    value = value.replace(&format!("${{{}}}", field), &field_value.to_string());
+                }
+                Some(value)
+            }
+        }
+    }
+}
+
+// 按渲染结果将消息分组，保持组内消息的顺序，返回None的消息被丢弃
+pub(crate) fn group_messages<K: PartialEq>(
+    mut mb: MessageBatch,
+    key: impl Fn(&Message) -> Option<K>,
+) -> Vec<(K, MessageBatch)> {
+    let messages = std::mem::take(mb.get_messages_mut());
+    let mut groups: Vec<(K, MessageBatch)> = vec![];
+    for message in messages {
+        let k = match key(&message) {
+            Some(k) => k,
+            None => continue,
+        };
+        match groups.iter_mut().find(|(group_key, _)| *group_key == k) {
+            Some((_, group_mb)) => group_mb.push_message(message),
+            None => {
+                let mut group_mb = mb.clone();
+                group_mb.push_message(message);
+                groups.push((k, group_mb));
+            }
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic() {
+        let mut msg = Message::default();
+        msg.add(
+            "a".to_owned(),
+            message::MessageValue::String("hello".to_owned()),
+        );
+        msg.add(
+            "b".to_owned(),
+            message::MessageValue::String("world".to_owned()),
+        );
+
+        let topic = "hello/world";
+        let topic = Template::new(topic);
+        match &topic {
+            Template::Const(topic) => assert_eq!(topic, "hello/world"),
+            _ => panic!("不含占位符的主题应解析为 Template::Const"),
+        }
+        assert_eq!(topic.render(&msg).unwrap(), "hello/world");
+
+        let topic = "${a}/${b}";
+        let topic = Template::new(topic);
+        match &topic {
+            Template::Dynamic { template, fields } => {
+                assert_eq!(template, "${a}/${b}");
+                assert_eq!(fields.len(), 2);
+                assert!(fields.contains(&"a".to_owned()));
+                assert!(fields.contains(&"b".to_owned()));
+            }
+            _ => panic!("含占位符的主题应解析为 Template::Dynamic"),
+        }
+        assert_eq!(topic.render(&msg).unwrap(), "hello/world");
+
+        msg.insert_metadata(
+            "line".to_owned(),
+            message::MessageValue::String("l1".to_owned()),
+        );
+        let topic = Template::new("site/${metadata.line}/${a}/telemetry");
+        assert_eq!(topic.render(&msg).unwrap(), "site/l1/hello/telemetry");
+        assert!(Template::new("${c}").render(&msg).is_none());
+    }
+
+    #[test]
+    fn test_group_messages() {
+        let mut mb = MessageBatch::default();
+        for device in ["d1", "d2", "d1"] {
+            let mut msg = Message::default();
+            msg.add(
+                "device".to_owned(),
+                message::MessageValue::String(device.to_owned()),
+            );
+            mb.push_message(msg);
+        }
+        let mut msg = Message::default();
+        msg.add("other".to_owned(), message::MessageValue::Null);
+        mb.push_message(msg);
+
+        let topic = Template::new("site/${device}");
+        let groups = group_messages(mb, |msg| topic.render(msg));
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, "site/d1");
+        assert_eq!(groups[0].1.len(), 2);
+        assert_eq!(groups[1].0, "site/d2");
+        assert_eq!(groups[1].1.len(), 1);
+    }
+}
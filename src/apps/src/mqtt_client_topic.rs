@@ -0,0 +1,198 @@
+use common::error::{HaliaError, HaliaResult};
+use message::{MessageBatch, MessageValue};
+
+const SHARED_SUBSCRIPTION_PREFIX: &str = "$share/";
+
+// 消息元数据中的字段名
+pub(crate) const METADATA_TOPIC: &str = "topic";
+pub(crate) const METADATA_QOS: &str = "qos";
+pub(crate) const METADATA_RETAIN: &str = "retain";
+// 通配符匹配到的主题层级
+pub(crate) const METADATA_TOPIC_LEVELS: &str = "topic_levels";
+
+// 共享订阅格式为$share/{group}/{filter}，broker转发的消息主题与去掉前缀后的filter匹配
+pub(crate) fn strip_shared_subscription(filter: &str) -> &str {
+    match filter.strip_prefix(SHARED_SUBSCRIPTION_PREFIX) {
+        Some(rest) => match rest.split_once('/') {
+            Some((_, filter)) => filter,
+            None => filter,
+        },
+        None => filter,
+    }
+}
+
+// mqtt v3.1.1没有订阅标识符，共享订阅与其他订阅重叠时broker会分别投递，无法区分消息来自哪个订阅，
+// 因此不允许共享订阅与filter不同的其他订阅重叠
+pub(crate) fn check_shared_subscription_conflict<'a>(
+    filter: &str,
+    others: impl IntoIterator<Item = &'a str>,
+) -> HaliaResult<()> {
+    let shared = filter.starts_with(SHARED_SUBSCRIPTION_PREFIX);
+    for other in others {
+        if other == filter {
+            continue;
+        }
+        if !shared && !other.starts_with(SHARED_SUBSCRIPTION_PREFIX) {
+            continue;
+        }
+        if filters_overlap(
+            strip_shared_subscription(filter),
+            strip_shared_subscription(other),
+        ) {
+            return Err(HaliaError::Common(format!(
+                "topic {} 与 {} 重叠，共享订阅不能与其他订阅重叠！",
+                filter, other
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// 存在同时与两个filter匹配的主题时返回true
+fn filters_overlap(a: &str, b: &str) -> bool {
+    let wildcard_start = |filter: &str| filter.starts_with('+') || filter.starts_with('#');
+    // 以$开头的主题不能被以通配符开头的filter匹配
+    if (a.starts_with('$') && wildcard_start(b)) || (b.starts_with('$') && wildcard_start(a)) {
+        return false;
+    }
+
+    let mut a_levels = a.split('/');
+    let mut b_levels = b.split('/');
+    loop {
+        match (a_levels.next(), b_levels.next()) {
+            (Some("#"), _) | (_, Some("#")) | (None, None) => return true,
+            (Some(x), Some(y)) if x == "+" || y == "+" || x == y => {}
+            _ => return false,
+        }
+    }
+}
+
+pub(crate) fn validate_filter(filter: &str) -> HaliaResult<()> {
+    if let Some(rest) = filter.strip_prefix(SHARED_SUBSCRIPTION_PREFIX) {
+        match rest.split_once('/') {
+            Some((group, _)) if !group.is_empty() && !group.contains(['+', '#']) => {}
+            _ => {
+                return Err(HaliaError::Common(
+                    "共享订阅格式错误，应为$share/{group}/{topic}！".to_owned(),
+                ))
+            }
+        }
+    }
+
+    if !rumqttc::valid_filter(strip_shared_subscription(filter)) {
+        return Err(HaliaError::Common("topic错误！".to_owned()));
+    }
+
+    Ok(())
+}
+
+// 主题与filter匹配时返回通配符匹配到的主题层级，+对应一个层级，#对应剩余的所有层级
+pub fn match_topic(topic: &str, filter: &str) -> Option<Vec<String>> {
+    let filter = strip_shared_subscription(filter);
+    // 以$开头的主题不能被以通配符开头的filter匹配
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return None;
+    }
+
+    let mut topic_levels = vec![];
+    let mut topics = topic.split('/');
+    for f in filter.split('/') {
+        // "#" being the last element is validated with 'valid_filter'
+        if f == "#" {
+            topic_levels.push(topics.collect::<Vec<_>>().join("/"));
+            return Some(topic_levels);
+        }
+
+        match topics.next() {
+            Some(t) if f == "+" => topic_levels.push(t.to_owned()),
+            Some(t) if f == t => {}
+            _ => return None,
+        }
+    }
+
+    // topic has remaining elements and filter's last element isn't "#"
+    if topics.next().is_some() {
+        return None;
+    }
+
+    Some(topic_levels)
+}
+
+pub(crate) fn publish_metadatas(
+    topic: &str,
+    qos: u8,
+    retain: bool,
+    topic_levels: Vec<String>,
+) -> Vec<(String, MessageValue)> {
+    vec![
+        (
+            METADATA_TOPIC.to_owned(),
+            MessageValue::String(topic.to_owned()),
+        ),
+        (METADATA_QOS.to_owned(), MessageValue::Int64(qos as i64)),
+        (METADATA_RETAIN.to_owned(), MessageValue::Boolean(retain)),
+        (
+            METADATA_TOPIC_LEVELS.to_owned(),
+            MessageValue::Array(topic_levels.into_iter().map(MessageValue::String).collect()),
+        ),
+    ]
+}
+
+pub(crate) fn insert_metadatas(mb: &mut MessageBatch, metadatas: &[(String, MessageValue)]) {
+    for message in mb.get_messages_mut() {
+        message.insert_metadatas(metadatas.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_topic() {
+        assert_eq!(match_topic("a/b/c", "a/b/c"), Some(vec![]));
+        assert_eq!(
+            match_topic("a/b/c/d", "a/+/c/#"),
+            Some(vec!["b".to_owned(), "d".to_owned()])
+        );
+        assert_eq!(match_topic("a/b", "a/b/#"), Some(vec!["".to_owned()]));
+        assert_eq!(match_topic("a/b/c", "a/+"), None);
+        assert_eq!(match_topic("$SYS/a", "#"), None);
+        assert_eq!(match_topic("$SYS/a", "$SYS/+"), Some(vec!["a".to_owned()]));
+        assert_eq!(
+            match_topic("a/b", "$share/group/a/+"),
+            Some(vec!["b".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_filters_overlap() {
+        assert!(filters_overlap("a/b", "a/b"));
+        assert!(filters_overlap("a/+", "a/b"));
+        assert!(filters_overlap("a/#", "a"));
+        assert!(filters_overlap("+/b/#", "a/+/c"));
+        assert!(!filters_overlap("a/b", "a/c"));
+        assert!(!filters_overlap("a/+", "a/b/c"));
+        assert!(!filters_overlap("#", "$SYS/a"));
+    }
+
+    #[test]
+    fn test_check_shared_subscription_conflict() {
+        assert!(check_shared_subscription_conflict("a/+", ["a/b", "a/#"]).is_ok());
+        assert!(
+            check_shared_subscription_conflict("$share/g/a/+", ["$share/g/a/+", "b/c"]).is_ok()
+        );
+        assert!(check_shared_subscription_conflict("$share/g/a/+", ["a/b"]).is_err());
+        assert!(check_shared_subscription_conflict("a/b", ["$share/g/a/#"]).is_err());
+        assert!(check_shared_subscription_conflict("$share/g1/a", ["$share/g2/a"]).is_err());
+    }
+
+    #[test]
+    fn test_validate_filter() {
+        assert!(validate_filter("$share/group/a/#").is_ok());
+        assert!(validate_filter("$share//a").is_err());
+        assert!(validate_filter("$share/group").is_err());
+        assert!(validate_filter("a/#/b").is_err());
+    }
+}
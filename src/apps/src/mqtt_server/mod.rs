@@ -0,0 +1,290 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use common::error::{HaliaError, HaliaResult};
+use dashmap::DashMap;
+use futures::lock::BiLock;
+use halia_derive::ResourceErr;
+use message::RuleMessageBatch;
+use sink::Sink;
+use source::Source;
+use tokio::{
+    select,
+    sync::{
+        broadcast::error::RecvError,
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::warn;
+use types::apps::mqtt_server::{SinkConf, SourceConf};
+use utils::ErrorManager;
+
+use crate::{mqtt_client_topic::match_topic, App};
+
+mod sink;
+mod source;
+
+// 内置mqtt服务器，服务器本身由config.toml配置并随网关启动，应用仅负责本地主题的订阅与发布
+#[derive(ResourceErr)]
+pub struct MqttServer {
+    err: BiLock<Option<Arc<String>>>,
+    stop_signal_tx: watch::Sender<()>,
+    sources: Arc<DashMap<String, Source>>,
+    sinks: DashMap<String, Sink>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+}
+
+struct TaskLoop {
+    stop_signal_rx: watch::Receiver<()>,
+    sources: Arc<DashMap<String, Source>>,
+    error_manager: ErrorManager,
+}
+
+impl TaskLoop {
+    fn new(
+        app_id: String,
+        app_err: BiLock<Option<Arc<String>>>,
+        stop_signal_rx: watch::Receiver<()>,
+        sources: Arc<DashMap<String, Source>>,
+    ) -> Self {
+        let error_manager =
+            ErrorManager::new(utils::error_manager::ResourceType::App, app_id, app_err);
+        Self {
+            stop_signal_rx,
+            sources,
+            error_manager,
+        }
+    }
+
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            let mut publish_rx = match mqtt_server::subscribe() {
+                Ok(publish_rx) => {
+                    self.error_manager.set_ok().await;
+                    publish_rx
+                }
+                Err(e) => {
+                    self.error_manager.set_err(Arc::new(e.to_string())).await;
+                    let _ = self.stop_signal_rx.changed().await;
+                    return self;
+                }
+            };
+
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    res = publish_rx.recv() => {
+                        match res {
+                            Ok(publish) => self.handle_publish(publish),
+                            Err(RecvError::Lagged(n)) => warn!("mqtt server lagged {} messages", n),
+                            Err(RecvError::Closed) => {
+                                self.error_manager
+                                    .set_err(Arc::new("mqtt服务器已关闭！".to_owned()))
+                                    .await;
+                                let _ = self.stop_signal_rx.changed().await;
+                                return self;
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn handle_publish(&self, publish: mqtt_server::Publish) {
+        for mut source in self.sources.iter_mut() {
+            if match_topic(&publish.topic, &source.source_conf.topic).is_none() {
+                continue;
+            }
+
+            let mb = match source.decoder.decode(publish.payload.clone()) {
+                Ok(mb) => mb,
+                Err(e) => {
+                    warn!("decode err :{}", e);
+                    continue;
+                }
+            };
+
+            match source.mb_txs.len() {
+                0 => {}
+                1 => {
+                    let mb = RuleMessageBatch::Owned(mb);
+                    if let Err(e) = source.mb_txs[0].send(mb) {
+                        warn!("send err :{}", e);
+                        source.mb_txs.remove(0);
+                    }
+                }
+                _ => {
+                    let mb = RuleMessageBatch::Arc(Arc::new(mb));
+                    source.mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+                }
+            }
+        }
+    }
+}
+
+pub fn new(app_id: String, _conf: serde_json::Value) -> Box<dyn App> {
+    let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let sources = Arc::new(DashMap::new());
+    let (app_err1, app_err2) = BiLock::new(None);
+
+    let task_loop = TaskLoop::new(app_id, app_err1, stop_signal_rx, sources.clone());
+    let join_handle = task_loop.start();
+
+    Box::new(MqttServer {
+        err: app_err2,
+        stop_signal_tx,
+        sources,
+        sinks: DashMap::new(),
+        join_handle: Some(join_handle),
+    })
+}
+
+pub fn validate_conf(_conf: &serde_json::Value) -> HaliaResult<()> {
+    if !mqtt_server::is_running() {
+        return Err(HaliaError::Common(
+            "内置mqtt服务器未启用，请在配置文件中配置mqtt_server！".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn process_source_conf(
+    app_id: &String,
+    source_id: &String,
+    conf: &serde_json::Value,
+) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::process_conf(app_id, source_id, &conf).await
+}
+
+pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SinkConf = serde_json::from_value(conf.clone())?;
+    Sink::validate_conf(&conf)
+}
+
+#[async_trait]
+impl App for MqttServer {
+    async fn read_app_err(&self) -> Option<Arc<String>> {
+        self.read_err().await
+    }
+
+    async fn update(
+        &mut self,
+        _old_conf: serde_json::Value,
+        _new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        for mut sink in self.sinks.iter_mut() {
+            sink.stop().await;
+        }
+        self.stop_signal_tx.send(()).unwrap();
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.await;
+        }
+    }
+
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        let source = Source::new(conf).await?;
+        self.sources.insert(source_id, source);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: String,
+        old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let old_conf: SourceConf = serde_json::from_value(old_conf)?;
+        let new_conf: SourceConf = serde_json::from_value(new_conf)?;
+
+        let mut source = self
+            .sources
+            .get_mut(&source_id)
+            .ok_or(HaliaError::NotFound(source_id.to_owned()))?;
+
+        if old_conf.decode_type != new_conf.decode_type || old_conf.schema_id != new_conf.schema_id
+        {
+            source.decoder = schema::new_decoder(&new_conf.decode_type, &new_conf.schema_id).await?;
+        }
+        source.source_conf = new_conf;
+
+        Ok(())
+    }
+
+    async fn delete_source(&mut self, source_id: String) -> HaliaResult<()> {
+        match self.sources.remove(&source_id) {
+            Some(_) => Ok(()),
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
+        let conf: SinkConf = serde_json::from_value(conf)?;
+        let sink = Sink::new(conf).await?;
+        self.sinks.insert(sink_id, sink);
+        Ok(())
+    }
+
+    async fn update_sink(
+        &mut self,
+        sink_id: String,
+        old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let old_conf: SinkConf = serde_json::from_value(old_conf)?;
+        let new_conf: SinkConf = serde_json::from_value(new_conf)?;
+
+        match self.sinks.get_mut(&sink_id) {
+            Some(mut sink) => sink.update_conf(old_conf, new_conf).await,
+            None => Err(HaliaError::NotFound(sink_id)),
+        }
+    }
+
+    async fn delete_sink(&mut self, sink_id: String) -> HaliaResult<()> {
+        match self.sinks.remove(&sink_id) {
+            Some((_, mut sink)) => {
+                sink.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(sink_id)),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt)),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn get_sink_txs(
+        &self,
+        sink_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedSender<RuleMessageBatch>>> {
+        match self.sinks.get(sink_id) {
+            Some(sink) => Ok(sink.get_txs(cnt)),
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use common::error::{HaliaError, HaliaResult};
+use halia_derive::{ResourceStop, SinkTxs};
+use message::RuleMessageBatch;
+use rumqttc::valid_topic;
+use schema::Encoder;
+use tokio::{
+    select,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::warn;
+use types::apps::mqtt_server::SinkConf;
+
+#[derive(ResourceStop, SinkTxs)]
+pub struct Sink {
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_tx: UnboundedSender<RuleMessageBatch>,
+}
+
+pub struct TaskLoop {
+    sink_conf: SinkConf,
+    encoder: Box<dyn Encoder>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_rx: UnboundedReceiver<RuleMessageBatch>,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    Some(mb) = self.mb_rx.recv() => {
+                        self.handle_data(mb);
+                    }
+                }
+            }
+        })
+    }
+
+    fn handle_data(&mut self, rmb: RuleMessageBatch) {
+        let mb = rmb.take_mb();
+        if mb.get_messages().is_empty() {
+            return;
+        }
+
+        let payload = match self.encoder.encode(mb) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("{:?}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = mqtt_server::publish(self.sink_conf.topic.clone(), payload) {
+            warn!("mqtt server publish err:{}", e);
+        }
+    }
+}
+
+impl Sink {
+    pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
+        if !valid_topic(&conf.topic) {
+            return Err(HaliaError::Common("topic不合法！".to_owned()));
+        }
+
+        Ok(())
+    }
+
+    pub async fn new(sink_conf: SinkConf) -> HaliaResult<Self> {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (mb_tx, mb_rx) = unbounded_channel();
+
+        let encoder = schema::new_encoder(&sink_conf.encode_type, &sink_conf.schema_id).await?;
+        let task_loop = TaskLoop {
+            sink_conf,
+            encoder,
+            stop_signal_rx,
+            mb_rx,
+        };
+        let join_handle = task_loop.start();
+
+        Ok(Self {
+            mb_tx,
+            stop_signal_tx,
+            join_handle: Some(join_handle),
+        })
+    }
+
+    pub async fn update_conf(&mut self, old_conf: SinkConf, new_conf: SinkConf) -> HaliaResult<()> {
+        let mut task_loop = self.stop().await;
+        if old_conf.encode_type != new_conf.encode_type || old_conf.schema_id != new_conf.schema_id
+        {
+            match schema::new_encoder(&new_conf.encode_type, &new_conf.schema_id).await {
+                Ok(encoder) => task_loop.encoder = encoder,
+                Err(e) => {
+                    self.join_handle = Some(task_loop.start());
+                    return Err(e);
+                }
+            }
+        }
+        task_loop.sink_conf = new_conf;
+        self.join_handle = Some(task_loop.start());
+        Ok(())
+    }
+}
@@ -25,7 +25,13 @@ use tracing::{debug, error, warn};
 use types::apps::mqtt_client_v311::{Conf, Qos, SinkConf, SourceConf};
 use utils::ErrorManager;
 
-use crate::{mqtt_client_ssl::get_ssl_config, App};
+use crate::{
+    mqtt_client_ssl::get_ssl_config,
+    mqtt_client_topic::{
+        check_shared_subscription_conflict, insert_metadatas, match_topic, publish_metadatas,
+    },
+    App,
+};
 
 mod sink;
 mod source;
@@ -147,29 +153,36 @@ impl TaskLoop {
                 match event {
                     Event::Incoming(Incoming::Publish(p)) => {
                         for mut source in self.sources.iter_mut() {
-                            if matches(&p.topic, &source.source_conf.topic) {
-                                let mb = match source.decoder.decode(p.payload.clone()) {
-                                    Ok(mb) => mb,
-                                    Err(e) => {
-                                        warn!("decode err :{}", e);
-                                        break;
-                                    }
+                            let topic_levels =
+                                match match_topic(&p.topic, &source.source_conf.topic) {
+                                    Some(topic_levels) => topic_levels,
+                                    None => continue,
                                 };
 
-                                match source.mb_txs.len() {
-                                    0 => {}
-                                    1 => {
-                                        let mb = RuleMessageBatch::Owned(mb);
-                                        if let Err(e) = source.mb_txs[0].send(mb) {
-                                            warn!("send err :{}", e);
-                                            source.mb_txs.remove(0);
-                                        }
-                                    }
-                                    _ => {
-                                        let mb = RuleMessageBatch::Arc(Arc::new(mb));
-                                        source.mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+                            let mut mb = match source.decoder.decode(p.payload.clone()) {
+                                Ok(mb) => mb,
+                                Err(e) => {
+                                    warn!("decode err :{}", e);
+                                    continue;
+                                }
+                            };
+                            let metadatas =
+                                publish_metadatas(&p.topic, p.qos as u8, p.retain, topic_levels);
+                            insert_metadatas(&mut mb, &metadatas);
+
+                            match source.mb_txs.len() {
+                                0 => {}
+                                1 => {
+                                    let mb = RuleMessageBatch::Owned(mb);
+                                    if let Err(e) = source.mb_txs[0].send(mb) {
+                                        warn!("send err :{}", e);
+                                        source.mb_txs.remove(0);
                                     }
                                 }
+                                _ => {
+                                    let mb = RuleMessageBatch::Arc(Arc::new(mb));
+                                    source.mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+                                }
                             }
                         }
                     }
@@ -256,9 +269,29 @@ pub async fn process_source_conf(
     conf: &serde_json::Value,
 ) -> HaliaResult<()> {
     let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    check_source_topic(app_id, source_id, &conf).await?;
     Source::process_conf(app_id, source_id, &conf).await
 }
 
+// 与应用下已保存的其他源比较，更新源时排除自身
+pub async fn check_source_topic(
+    app_id: &String,
+    source_id: &String,
+    conf: &SourceConf,
+) -> HaliaResult<()> {
+    let mut topics = vec![];
+    let db_sources = storage::app::source_sink::read_all_sources_by_app_id(app_id).await?;
+    for db_source in db_sources {
+        if db_source.id == *source_id {
+            continue;
+        }
+        let db_conf: SourceConf = serde_json::from_value(db_source.conf)?;
+        topics.push(db_conf.topic);
+    }
+
+    check_shared_subscription_conflict(&conf.topic, topics.iter().map(String::as_str))
+}
+
 pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     let conf: SinkConf = serde_json::from_value(conf.clone())?;
     Sink::validate_conf(&conf)?;
@@ -267,41 +300,6 @@ pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
 
 impl MqttClient {}
 
-pub fn matches(topic: &str, filter: &str) -> bool {
-    if !topic.is_empty() && topic[..1].contains('$') {
-        return false;
-    }
-
-    let mut topics = topic.split('/');
-    let mut filters = filter.split('/');
-
-    for f in filters.by_ref() {
-        // "#" being the last element is validated by the broker with 'valid_filter'
-        if f == "#" {
-            return true;
-        }
-
-        // filter still has remaining elements
-        // filter = a/b/c/# should match topci = a/b/c
-        // filter = a/b/c/d should not match topic = a/b/c
-        let top = topics.next();
-        match top {
-            Some("#") => return false,
-            Some(_) if f == "+" => continue,
-            Some(t) if f != t => return false,
-            Some(_) => continue,
-            None => return false,
-        }
-    }
-
-    // topic has remaining elements and filter's last element isn't "#"
-    if topics.next().is_some() {
-        return false;
-    }
-
-    true
-}
-
 fn transfer_qos(qos: &Qos) -> mqttbytes::QoS {
     match qos {
         Qos::AtMostOnce => QoS::AtMostOnce,
@@ -426,11 +424,12 @@ impl App for MqttClient {
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let sink_conf: SinkConf = serde_json::from_value(conf)?;
         let sink = Sink::new(
+            &sink_id,
             sink_conf,
             self.mqtt_client.clone(),
             self.mqtt_status.clone(),
         )
-        .await;
+        .await?;
         self.sinks.insert(sink_id, sink);
         Ok(())
     }
@@ -445,7 +444,7 @@ impl App for MqttClient {
         let new_conf: SinkConf = serde_json::from_value(new_conf)?;
 
         match self.sinks.get_mut(&sink_id) {
-            Some(mut sink) => Ok(sink.update_conf(old_conf, new_conf).await),
+            Some(mut sink) => sink.update_conf(&sink_id, old_conf, new_conf).await,
             None => Err(HaliaError::NotFound(sink_id)),
         }
     }
@@ -1,6 +1,6 @@
-use std::{
-    collections::HashSet,
-    sync::{atomic::AtomicBool, Arc},
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
 };
 
 use common::{
@@ -8,9 +8,8 @@ use common::{
     sink_message_retain::{self, SinkMessageRetain},
 };
 use halia_derive::{ResourceStop, SinkTxs};
-use message::{Message, RuleMessageBatch};
-use regex::Regex;
-use rumqttc::{valid_topic, AsyncClient};
+use message::{MessageBatch, RuleMessageBatch};
+use rumqttc::{valid_topic, AsyncClient, ClientError};
 use schema::Encoder;
 use tokio::{
     select,
@@ -19,10 +18,13 @@ use tokio::{
         watch,
     },
     task::JoinHandle,
+    time,
 };
 use tracing::warn;
 use types::apps::mqtt_client_v311::SinkConf;
 
+use crate::mqtt_client_template::{group_messages, Template};
+
 use super::transfer_qos;
 
 #[derive(ResourceStop, SinkTxs)]
@@ -33,7 +35,7 @@ pub struct Sink {
 }
 
 pub struct TaskLoop {
-    topic: Topic,
+    topic: Template,
     sink_conf: SinkConf,
     qos: rumqttc::QoS,
     pub encoder: Box<dyn Encoder>,
@@ -46,19 +48,20 @@ pub struct TaskLoop {
 
 impl TaskLoop {
     pub async fn new(
+        sink_id: &str,
         sink_conf: SinkConf,
         stop_signal_rx: watch::Receiver<()>,
         mb_rx: UnboundedReceiver<RuleMessageBatch>,
         mqtt_client: Arc<AsyncClient>,
         mqtt_status: Arc<AtomicBool>,
-    ) -> Self {
+    ) -> HaliaResult<Self> {
         let qos = transfer_qos(&sink_conf.qos);
         let encoder = schema::new_encoder(&sink_conf.encode_type, &sink_conf.schema_id)
             .await
             .unwrap();
-        let message_retainer = sink_message_retain::new(&sink_conf.message_retain);
-        let topic = Topic::new(&sink_conf.topic);
-        Self {
+        let message_retainer = sink_message_retain::new(&sink_conf.message_retain, sink_id)?;
+        let topic = Template::new(&sink_conf.topic);
+        Ok(Self {
             topic,
             sink_conf,
             qos,
@@ -68,10 +71,11 @@ impl TaskLoop {
             mb_rx,
             mqtt_client,
             mqtt_status,
-        }
+        })
     }
 
     pub fn start(mut self) -> JoinHandle<Self> {
+        let mut replay_interval = time::interval(sink_message_retain::REPLAY_INTERVAL);
         tokio::spawn(async move {
             loop {
                 select! {
@@ -82,6 +86,10 @@ impl TaskLoop {
                     Some(mb) = self.mb_rx.recv() => {
                         self.handle_data(mb).await;
                     }
+
+                    _ = replay_interval.tick() => {
+                        self.replay_retained_mbs().await;
+                    }
                 }
             }
         })
@@ -89,24 +97,73 @@ impl TaskLoop {
 
     async fn handle_data(&mut self, rmb: RuleMessageBatch) {
         let mb = rmb.take_mb();
-        if !self.mqtt_status.load(std::sync::atomic::Ordering::Relaxed) {
+        // 连接断开或仍有待重发的消息时先保留，保证消息的发送顺序
+        if !self.mqtt_status.load(Ordering::Relaxed) || !self.message_retainer.is_empty() {
             self.message_retainer.push(mb);
+            self.replay_retained_mbs().await;
             return;
         }
-        let topic = {
-            let messages = mb.get_messages();
-            if messages.len() == 0 {
-                return;
+        if let Err(e) = self.publish(mb).await {
+            warn!("{:?}", e);
+        }
+    }
+
+    // 连接恢复后按顺序重发保留的消息
+    async fn replay_retained_mbs(&mut self) {
+        while self.mqtt_status.load(Ordering::Relaxed) {
+            let mb = match self.message_retainer.peek() {
+                Some(mb) => mb,
+                None => break,
+            };
+            // 发送成功后再移除，失败时保留在队首等待下次重发
+            if let Err(e) = self.publish(mb).await {
+                warn!("{:?}", e);
+                break;
             }
-            self.topic.get_topic(&messages[0])
-        };
+            self.message_retainer.pop();
+        }
+    }
+
+    async fn publish(&mut self, mb: MessageBatch) -> Result<(), ClientError> {
+        if mb.len() == 0 {
+            return Ok(());
+        }
+
+        if let Template::Const(topic) = &self.topic {
+            let topic = topic.clone();
+            return self.publish_to_topic(topic, mb).await;
+        }
 
+        // 主题中包含${field}时，按每条消息渲染出的主题分组发送
+        let groups = group_messages(mb, |msg| match self.topic.render(msg) {
+            Some(topic) if valid_topic(&topic) => Some(topic),
+            Some(topic) => {
+                warn!("topic不合法：{}，消息已丢弃", topic);
+                None
+            }
+            None => {
+                warn!("消息中缺少topic模板引用的字段，消息已丢弃");
+                None
+            }
+        });
+        for (topic, mb) in groups {
+            self.publish_to_topic(topic, mb).await?;
+        }
+        Ok(())
+    }
+
+    // 编码失败的消息直接丢弃，只返回发送的错误
+    async fn publish_to_topic(
+        &mut self,
+        topic: String,
+        mb: MessageBatch,
+    ) -> Result<(), ClientError> {
         let payload = {
             match self.encoder.encode(mb) {
                 Ok(data) => data,
                 Err(e) => {
                     warn!("{:?}", e);
-                    return;
+                    return Ok(());
                 }
             }
         };
@@ -114,13 +171,13 @@ impl TaskLoop {
         self.mqtt_client
             .publish_bytes(topic, self.qos, self.sink_conf.retain, payload)
             .await
-            .unwrap();
     }
 }
 
 impl Sink {
     pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
-        if !valid_topic(&conf.topic) {
+        // 模板主题在发布时按渲染结果校验
+        if Template::new(&conf.topic).is_const() && !valid_topic(&conf.topic) {
             return Err(HaliaError::Common("topic不合法！".to_owned()));
         }
 
@@ -128,29 +185,51 @@ impl Sink {
     }
 
     pub async fn new(
+        sink_id: &str,
         sink_conf: SinkConf,
         mqtt_client: Arc<AsyncClient>,
         mqtt_status: Arc<AtomicBool>,
-    ) -> Self {
+    ) -> HaliaResult<Self> {
         let (stop_signal_tx, stop_signal_rx) = watch::channel(());
         let (mb_tx, mb_rx) = unbounded_channel();
 
-        let task_loop =
-            TaskLoop::new(sink_conf, stop_signal_rx, mb_rx, mqtt_client, mqtt_status).await;
+        let task_loop = TaskLoop::new(
+            sink_id,
+            sink_conf,
+            stop_signal_rx,
+            mb_rx,
+            mqtt_client,
+            mqtt_status,
+        )
+        .await?;
         let join_handle = task_loop.start();
 
-        Self {
+        Ok(Self {
             mb_tx,
             stop_signal_tx,
             join_handle: Some(join_handle),
-        }
+        })
     }
 
-    pub async fn update_conf(&mut self, _old_conf: SinkConf, new_conf: SinkConf) {
+    pub async fn update_conf(
+        &mut self,
+        sink_id: &str,
+        old_conf: SinkConf,
+        new_conf: SinkConf,
+    ) -> HaliaResult<()> {
         let mut task_loop = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut task_loop.message_retainer,
+            &old_conf.message_retain,
+            &new_conf.message_retain,
+            sink_id,
+        );
+        task_loop.topic = Template::new(&new_conf.topic);
+        task_loop.qos = transfer_qos(&new_conf.qos);
         task_loop.sink_conf = new_conf;
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
+        res
     }
 
     pub async fn update_mqtt_client(&mut self, mqtt_client: Arc<AsyncClient>) {
@@ -160,93 +239,3 @@ impl Sink {
         self.join_handle = Some(join_handle);
     }
 }
-
-enum Topic {
-    Const(ConstTopic),
-    Dynamic(DynamicTopic),
-}
-
-struct ConstTopic(String);
-
-struct DynamicTopic {
-    template: String,
-    fields: Vec<String>,
-}
-
-impl DynamicTopic {}
-
-impl Topic {
-    fn new(topic: &str) -> Self {
-        let re = Regex::new(r"\$\{(.*?)\}").unwrap();
-        let mut fields = HashSet::new();
-        for cap in re.captures_iter(topic) {
-            fields.insert(cap[0][2..cap[0].len() - 1].to_owned());
-        }
-
-        if fields.is_empty() {
-            Self::Const(ConstTopic(topic.to_owned()))
-        } else {
-            Self::Dynamic(DynamicTopic {
-                template: topic.to_owned(),
-                fields: fields.into_iter().collect(),
-            })
-        }
-    }
-
-    fn get_topic(&self, msg: &Message) -> String {
-        match self {
-            Self::Const(ConstTopic(topic)) => topic.clone(),
-            Self::Dynamic(DynamicTopic { template, fields }) => {
-                let mut topic = template.clone();
-                for field in fields {
-                    match msg.get(field) {
-                        Some(value) => {
-                            topic = topic.replace(&format!("${{{}}}", field), &value.to_string());
-                        }
-                        None => panic!("todo"),
-                    };
-                }
-                topic
-            }
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_topic() {
-        let mut msg = Message::default();
-        msg.add(
-            "a".to_owned(),
-            message::MessageValue::String("hello".to_owned()),
-        );
-        msg.add(
-            "b".to_owned(),
-            message::MessageValue::String("world".to_owned()),
-        );
-
-        let topic = "hello/world";
-        let topic = Topic::new(topic);
-        match &topic {
-            Topic::Const(ConstTopic(topic)) => assert_eq!(topic, "hello/world"),
-            _ => panic!("todo"),
-        }
-        assert_eq!(topic.get_topic(&msg), "hello/world");
-
-        let topic = "${a}/${b}";
-        let topic = Topic::new(topic);
-        match &topic {
-            Topic::Dynamic(dynamic_topic) => {
-                assert_eq!(dynamic_topic.template, "${a}/${b}");
-                assert_eq!(dynamic_topic.fields.len(), 2);
-                assert!(dynamic_topic.fields.contains(&"a".to_owned()));
-                assert!(dynamic_topic.fields.contains(&"b".to_owned()));
-            }
-            _ => panic!("not right"),
-        }
-        assert_eq!(topic.get_topic(&msg), "hello/world");
-    }
-}
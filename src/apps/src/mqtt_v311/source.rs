@@ -1,10 +1,11 @@
 use common::error::{HaliaError, HaliaResult};
 use message::RuleMessageBatch;
-use rumqttc::valid_filter;
 use schema::Decoder;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use types::apps::mqtt_client_v311::SourceConf;
 
+use crate::mqtt_client_topic::validate_filter;
+
 pub struct Source {
     pub source_conf: SourceConf,
     pub mb_txs: Vec<UnboundedSender<RuleMessageBatch>>,
@@ -29,9 +30,7 @@ impl Source {
         source_id: &String,
         conf: &SourceConf,
     ) -> HaliaResult<()> {
-        if !valid_filter(&conf.topic) {
-            return Err(HaliaError::Common("topic错误！".to_owned()));
-        }
+        validate_filter(&conf.topic)?;
 
         match conf.decode_type {
             types::schema::DecodeType::Protobuf => match &conf.schema_id {
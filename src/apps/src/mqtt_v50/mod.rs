@@ -1,4 +1,8 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use base64::{prelude::BASE64_STANDARD, Engine as _};
@@ -6,14 +10,14 @@ use common::error::{HaliaError, HaliaResult};
 use dashmap::DashMap;
 use futures::lock::BiLock;
 use halia_derive::ResourceErr;
-use message::{MessageBatch, RuleMessageBatch};
+use message::{MessageValue, RuleMessageBatch};
 use rumqttc::v5::{
     self,
-    mqttbytes::v5::{ConnectProperties, LastWill},
-    AsyncClient,
+    mqttbytes::v5::{ConnectProperties, LastWill, PublishProperties},
+    AsyncClient, ClientError,
 };
 use sink::Sink;
-use source::Source;
+use source::{configured_subscription_id, Source};
 use tokio::{
     select,
     sync::{
@@ -27,7 +31,11 @@ use tracing::debug;
 use tracing::{error, warn};
 use types::apps::mqtt_client_v50::{MqttClientConf, Qos, SinkConf, SourceConf};
 
-use crate::{mqtt_client_ssl::get_ssl_config, App};
+use crate::{
+    mqtt_client_ssl::get_ssl_config,
+    mqtt_client_topic::{insert_metadatas, match_topic, publish_metadatas},
+    App,
+};
 
 mod sink;
 mod source;
@@ -52,6 +60,7 @@ struct JoinHandleData {
     app_err_tx: broadcast::Sender<bool>,
     sources: Arc<DashMap<String, Source>>,
     app_err: BiLock<Option<Arc<String>>>,
+    topic_aliases: HashMap<u16, String>,
 }
 
 pub fn new(id: String, conf: serde_json::Value) -> Box<dyn App> {
@@ -68,6 +77,7 @@ pub fn new(id: String, conf: serde_json::Value) -> Box<dyn App> {
         app_err_tx: app_err_tx.clone(),
         sources: sources.clone(),
         app_err: app_err1,
+        topic_aliases: HashMap::new(),
     };
 
     let (join_handle, mqtt_client) = MqttClient::event_loop(join_handle_data);
@@ -95,10 +105,13 @@ pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     Ok(())
 }
 
-pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+pub async fn process_source_conf(
+    app_id: &String,
+    source_id: &String,
+    conf: &serde_json::Value,
+) -> HaliaResult<()> {
     let conf: SourceConf = serde_json::from_value(conf.clone())?;
-    Source::validate_conf(&conf)?;
-    Ok(())
+    Source::process_conf(app_id, source_id, &conf).await
 }
 
 pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
@@ -108,6 +121,41 @@ pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
 }
 
 impl MqttClient {
+    // 始终携带订阅标识符，重叠的共享订阅与普通订阅各自投递消息时据此区分
+    async fn subscribe(
+        &self,
+        conf: &SourceConf,
+        subscription_id: usize,
+    ) -> Result<(), ClientError> {
+        let user_properties = match (conf.subscribe_properties_enable, &conf.subscribe_properties) {
+            (true, Some(subscribe_properties)) => subscribe_properties.user_properties.clone(),
+            _ => vec![],
+        };
+        self.mqtt_client
+            .subscribe_with_properties(
+                &conf.topic,
+                transfer_qos(&conf.qos),
+                v5::mqttbytes::v5::SubscribeProperties {
+                    id: Some(subscription_id),
+                    user_properties,
+                },
+            )
+            .await
+    }
+
+    // 配置了订阅标识符时使用配置的值，否则沿用源当前的标识符或分配未使用的最小值
+    fn subscription_id(&self, conf: &SourceConf, current: Option<usize>) -> usize {
+        if let Some(id) = configured_subscription_id(conf).or(current) {
+            return id;
+        }
+        let used: HashSet<usize> = self
+            .sources
+            .iter()
+            .map(|source| source.subscription_id)
+            .collect();
+        (1..).find(|id| !used.contains(id)).unwrap()
+    }
+
     fn event_loop(
         mut join_handle_data: JoinHandleData,
     ) -> (JoinHandle<JoinHandleData>, Arc<AsyncClient>) {
@@ -212,7 +260,13 @@ impl MqttClient {
                     }
 
                     event = event_loop.poll() => {
-                        Self::handle_event(event, &join_handle_data.sources).await;
+                        Self::handle_event(
+                            event,
+                            &join_handle_data.sources,
+                            &join_handle_data.app_err_tx,
+                            &mut join_handle_data.topic_aliases,
+                        )
+                        .await;
                     }
                 }
             }
@@ -224,132 +278,170 @@ impl MqttClient {
     async fn handle_event(
         event: Result<v5::Event, rumqttc::v5::ConnectionError>,
         sources: &Arc<DashMap<String, Source>>,
+        app_err_tx: &broadcast::Sender<bool>,
+        topic_aliases: &mut HashMap<u16, String>,
     ) {
         match event {
+            Ok(v5::Event::Incoming(v5::Incoming::ConnAck(_))) => {
+                // 主题别名仅在单个连接内有效
+                topic_aliases.clear();
+                let _ = app_err_tx.send(false);
+            }
             Ok(v5::Event::Incoming(v5::Incoming::Publish(p))) => {
                 debug!("Received: {:?}", p);
-                match MessageBatch::from_json(p.payload) {
-                    Ok(msg) => {
-                        if p.topic.len() > 0 {
-                            match String::from_utf8(p.topic.into()) {
-                                Ok(topic) => {
-                                    for mut source in sources.iter_mut() {
-                                        if matches(&source.conf.topic, &topic) {
-                                            match source.mb_txs.len() {
-                                                0 => {}
-                                                1 => {
-                                                    let mb = RuleMessageBatch::Owned(msg.clone());
-                                                    if let Err(e) = source.mb_txs[0].send(mb) {
-                                                        warn!("{}", e);
-                                                        source.mb_txs.remove(0);
-                                                    }
-                                                }
-                                                _ => {
-                                                    let rmb = RuleMessageBatch::Arc(Arc::new(
-                                                        msg.clone(),
-                                                    ));
-                                                    source
-                                                        .mb_txs
-                                                        .retain(|tx| tx.send(rmb.clone()).is_ok());
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => warn!("{}", e),
-                            }
-                        } else {
-                            match p.properties {
-                                Some(properties) => match properties.topic_alias {
-                                    Some(msg_topic_alias) => {
-                                        for mut source in sources.iter_mut() {
-                                            match source.conf.topic_alias {
-                                                Some(source_topic_alias) => {
-                                                    if source_topic_alias == msg_topic_alias {
-                                                        match source.mb_txs.len() {
-                                                            0 => {}
-                                                            1 => {
-                                                                let mb = RuleMessageBatch::Owned(
-                                                                    msg.clone(),
-                                                                );
-                                                                if let Err(e) =
-                                                                    source.mb_txs[0].send(mb)
-                                                                {
-                                                                    warn!("{}", e);
-                                                                    source.mb_txs.remove(0);
-                                                                }
-                                                            }
-                                                            _ => {
-                                                                let rmb = RuleMessageBatch::Arc(
-                                                                    Arc::new(msg.clone()),
-                                                                );
-                                                                source.mb_txs.retain(|tx| {
-                                                                    tx.send(rmb.clone()).is_ok()
-                                                                });
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                                None => {}
-                                            }
-                                        }
-                                    }
-                                    None => {}
-                                },
-                                None => {}
+                let topic = match String::from_utf8(p.topic.to_vec()) {
+                    Ok(topic) => topic,
+                    Err(e) => {
+                        warn!("{}", e);
+                        return;
+                    }
+                };
+                let topic_alias = p.properties.as_ref().and_then(|p| p.topic_alias);
+                let topic = match (topic.is_empty(), topic_alias) {
+                    (false, Some(topic_alias)) => {
+                        topic_aliases.insert(topic_alias, topic.clone());
+                        topic
+                    }
+                    (false, None) => topic,
+                    (true, Some(topic_alias)) => match topic_aliases.get(&topic_alias) {
+                        Some(topic) => topic.clone(),
+                        None => {
+                            warn!("unknown topic alias: {}", topic_alias);
+                            return;
+                        }
+                    },
+                    (true, None) => return,
+                };
+                let property_metadatas = properties_metadatas(&p.properties);
+                let subscription_ids = p
+                    .properties
+                    .as_ref()
+                    .map(|properties| properties.subscription_identifiers.clone())
+                    .unwrap_or_default();
+
+                for mut source in sources.iter_mut() {
+                    let topic_levels = match match_source(
+                        &topic,
+                        &subscription_ids,
+                        source.subscription_id,
+                        &source.conf.topic,
+                    ) {
+                        Some(topic_levels) => topic_levels,
+                        None => continue,
+                    };
+
+                    let mut mb = match source.decoder.decode(p.payload.clone()) {
+                        Ok(mb) => mb,
+                        Err(e) => {
+                            warn!("decode err :{}", e);
+                            continue;
+                        }
+                    };
+                    let mut metadatas =
+                        publish_metadatas(&topic, p.qos as u8, p.retain, topic_levels);
+                    metadatas.extend(property_metadatas.iter().cloned());
+                    insert_metadatas(&mut mb, &metadatas);
+
+                    match source.mb_txs.len() {
+                        0 => {}
+                        1 => {
+                            let mb = RuleMessageBatch::Owned(mb);
+                            if let Err(e) = source.mb_txs[0].send(mb) {
+                                warn!("{}", e);
+                                source.mb_txs.remove(0);
                             }
                         }
+                        _ => {
+                            let rmb = RuleMessageBatch::Arc(Arc::new(mb));
+                            source.mb_txs.retain(|tx| tx.send(rmb.clone()).is_ok());
+                        }
                     }
-                    Err(e) => error!("Failed to decode msg:{}", e),
                 }
             }
             Ok(_) => (),
-            Err(e) => match e {
-                v5::ConnectionError::MqttState(_) => todo!(),
-                v5::ConnectionError::Timeout(_) => todo!(),
-                v5::ConnectionError::Tls(_) => todo!(),
-                v5::ConnectionError::Io(_) => todo!(),
-                v5::ConnectionError::ConnectionRefused(_) => todo!(),
-                v5::ConnectionError::NotConnAck(_) => todo!(),
-                v5::ConnectionError::RequestsDone => todo!(),
-            },
+            Err(e) => {
+                warn!("{}", e);
+                let _ = app_err_tx.send(true);
+            }
         }
     }
 }
 
-pub fn matches(topic: &str, filter: &str) -> bool {
-    if !topic.is_empty() && topic[..1].contains('$') {
-        return false;
+// broker支持订阅标识符时只投递给订阅标识符匹配的源，否则按主题匹配
+fn match_source(
+    topic: &str,
+    subscription_ids: &[usize],
+    subscription_id: usize,
+    filter: &str,
+) -> Option<Vec<String>> {
+    if !subscription_ids.is_empty() && !subscription_ids.contains(&subscription_id) {
+        return None;
     }
+    match_topic(topic, filter)
+}
 
-    let mut topics = topic.split('/');
-    let mut filters = filter.split('/');
-
-    for f in filters.by_ref() {
-        // "#" being the last element is validated by the broker with 'valid_filter'
-        if f == "#" {
-            return true;
-        }
+// v5发布属性写入消息元数据
+fn properties_metadatas(properties: &Option<PublishProperties>) -> Vec<(String, MessageValue)> {
+    let mut metadatas = vec![];
+    let properties = match properties {
+        Some(properties) => properties,
+        None => return metadatas,
+    };
 
-        // filter still has remaining elements
-        // filter = a/b/c/# should match topci = a/b/c
-        // filter = a/b/c/d should not match topic = a/b/c
-        let top = topics.next();
-        match top {
-            Some("#") => return false,
-            Some(_) if f == "+" => continue,
-            Some(t) if f != t => return false,
-            Some(_) => continue,
-            None => return false,
+    if !properties.user_properties.is_empty() {
+        let mut user_properties = HashMap::new();
+        for (k, v) in &properties.user_properties {
+            user_properties.insert(k.clone(), MessageValue::String(v.clone()));
         }
+        metadatas.push((
+            "user_properties".to_owned(),
+            MessageValue::Object(user_properties),
+        ));
     }
-
-    // topic has remaining elements and filter's last element isn't "#"
-    if topics.next().is_some() {
-        return false;
+    if let Some(content_type) = &properties.content_type {
+        metadatas.push((
+            "content_type".to_owned(),
+            MessageValue::String(content_type.clone()),
+        ));
+    }
+    if let Some(response_topic) = &properties.response_topic {
+        metadatas.push((
+            "response_topic".to_owned(),
+            MessageValue::String(response_topic.clone()),
+        ));
+    }
+    if let Some(correlation_data) = &properties.correlation_data {
+        metadatas.push((
+            "correlation_data".to_owned(),
+            MessageValue::Bytes(correlation_data.to_vec()),
+        ));
+    }
+    if let Some(payload_format_indicator) = properties.payload_format_indicator {
+        metadatas.push((
+            "payload_format_indicator".to_owned(),
+            MessageValue::Int64(payload_format_indicator as i64),
+        ));
+    }
+    if let Some(message_expiry_interval) = properties.message_expiry_interval {
+        metadatas.push((
+            "message_expiry_interval".to_owned(),
+            MessageValue::Int64(message_expiry_interval as i64),
+        ));
+    }
+    if !properties.subscription_identifiers.is_empty() {
+        metadatas.push((
+            "subscription_identifiers".to_owned(),
+            MessageValue::Array(
+                properties
+                    .subscription_identifiers
+                    .iter()
+                    .map(|id| MessageValue::Int64(*id as i64))
+                    .collect(),
+            ),
+        ));
     }
 
-    true
+    metadatas
 }
 
 fn transfer_qos(qos: &Qos) -> v5::mqttbytes::QoS {
@@ -405,13 +497,10 @@ impl App for MqttClient {
     ) -> HaliaResult<()> {
         let conf: SourceConf = serde_json::from_value(conf)?;
 
-        let source = Source::new(conf);
+        let subscription_id = self.subscription_id(&conf, None);
+        let source = Source::new(conf, subscription_id).await?;
 
-        if let Err(e) = self
-            .mqtt_client
-            .subscribe(&source.conf.topic, transfer_qos(&source.conf.qos))
-            .await
-        {
+        if let Err(e) = self.subscribe(&source.conf, subscription_id).await {
             error!("client subscribe err:{e}");
         }
         self.sources.insert(source_id, source);
@@ -428,20 +517,36 @@ impl App for MqttClient {
         let old_conf: SourceConf = serde_json::from_value(old_conf)?;
         let new_conf: SourceConf = serde_json::from_value(new_conf)?;
 
+        let current_id = self
+            .sources
+            .get(&source_id)
+            .map(|source| source.subscription_id)
+            .ok_or(HaliaError::NotFound(source_id.to_owned()))?;
+        let subscription_id = self.subscription_id(&new_conf, Some(current_id));
+
         let mut source = self
             .sources
             .get_mut(&source_id)
             .ok_or(HaliaError::NotFound(source_id.to_owned()))?;
 
-        if let Err(e) = self.mqtt_client.unsubscribe(old_conf.topic).await {
-            error!("unsubscribe err:{e}");
+        if old_conf.topic != new_conf.topic
+            || old_conf.qos != new_conf.qos
+            || old_conf.subscribe_properties_enable != new_conf.subscribe_properties_enable
+            || old_conf.subscribe_properties != new_conf.subscribe_properties
+        {
+            if let Err(e) = self.mqtt_client.unsubscribe(old_conf.topic).await {
+                error!("unsubscribe err:{e}");
+            }
+            if let Err(e) = self.subscribe(&new_conf, subscription_id).await {
+                error!("subscribe err:{e}");
+            }
         }
-        if let Err(e) = self
-            .mqtt_client
-            .subscribe(&new_conf.topic, transfer_qos(&new_conf.qos))
-            .await
+        source.subscription_id = subscription_id;
+
+        if old_conf.decode_type != new_conf.decode_type || old_conf.schema_id != new_conf.schema_id
         {
-            error!("subscribe err:{e}");
+            source.decoder =
+                schema::new_decoder(&new_conf.decode_type, &new_conf.schema_id).await?;
         }
 
         source.conf = new_conf;
@@ -464,7 +569,13 @@ impl App for MqttClient {
 
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let conf: SinkConf = serde_json::from_value(conf)?;
-        let sink = Sink::new(conf, self.mqtt_client.clone(), self.app_err_tx.subscribe()).await;
+        let sink = Sink::new(
+            &sink_id,
+            conf,
+            self.mqtt_client.clone(),
+            self.app_err_tx.subscribe(),
+        )
+        .await?;
         self.sinks.insert(sink_id, sink);
         Ok(())
     }
@@ -479,7 +590,7 @@ impl App for MqttClient {
         let new_conf: SinkConf = serde_json::from_value(new_conf)?;
 
         match self.sinks.get_mut(&sink_id) {
-            Some(mut sink) => sink.update(old_conf, new_conf).await,
+            Some(mut sink) => sink.update(&sink_id, old_conf, new_conf).await,
             None => Err(HaliaError::NotFound(sink_id)),
         }
     }
@@ -516,3 +627,29 @@ impl App for MqttClient {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_source() {
+        // 共享订阅与普通订阅重叠时，broker投递的每份消息只携带对应订阅的标识符
+        assert_eq!(
+            match_source("a/b", &[1], 1, "a/+"),
+            Some(vec!["b".to_owned()])
+        );
+        assert_eq!(match_source("a/b", &[1], 2, "$share/g/a/#"), None);
+        assert_eq!(
+            match_source("a/b", &[1, 2], 2, "$share/g/a/#"),
+            Some(vec!["b".to_owned()])
+        );
+
+        // broker不支持订阅标识符时按主题匹配
+        assert_eq!(
+            match_source("a/b", &[], 2, "a/+"),
+            Some(vec!["b".to_owned()])
+        );
+        assert_eq!(match_source("a/b", &[], 2, "c/+"), None);
+    }
+}
@@ -2,13 +2,14 @@ use std::sync::Arc;
 
 use common::{
     error::{HaliaError, HaliaResult},
+    get_dynamic_value_from_json,
     sink_message_retain::{self, SinkMessageRetain},
+    DynamicValue,
 };
-use tracing::warn;
-use message::RuleMessageBatch;
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
 use rumqttc::v5::{
     mqttbytes::{self, v5},
-    AsyncClient,
+    AsyncClient, ClientError,
 };
 use tokio::{
     select,
@@ -18,7 +19,13 @@ use tokio::{
     },
     task::JoinHandle,
 };
-use types::apps::mqtt_client_v50::{PublishProperties, SinkConf};
+use tracing::warn;
+use types::{
+    apps::mqtt_client_v50::{PublishProperties, SinkConf},
+    PlainOrBase64ValueType,
+};
+
+use crate::mqtt_client_template::{get_message_value, group_messages, Template};
 
 use super::transfer_qos;
 
@@ -36,20 +43,23 @@ pub struct JoinHandleData {
     pub stop_signal_rx: mpsc::Receiver<()>,
     pub mb_rx: UnboundedReceiver<RuleMessageBatch>,
     pub app_err_rx: broadcast::Receiver<bool>,
-    pub publish_properties: Option<v5::PublishProperties>,
+    topic: Template,
+    properties: PropertiesTemplate,
 }
 
 impl Sink {
     pub async fn new(
+        sink_id: &str,
         conf: SinkConf,
         mqtt_client: Arc<AsyncClient>,
         app_err_rx: broadcast::Receiver<bool>,
-    ) -> Self {
-        let publish_properties = get_publish_properties(&conf.properties);
+    ) -> HaliaResult<Self> {
+        let topic = Template::new(&conf.topic);
+        let properties = PropertiesTemplate::new(&conf.properties);
         let (mb_tx, mb_rx) = unbounded_channel();
         let (stop_signal_tx, stop_signal_rx) = mpsc::channel(1);
 
-        let message_retainer = sink_message_retain::new(&conf.message_retain);
+        let message_retainer = sink_message_retain::new(&conf.message_retain, sink_id)?;
         let join_handle_data = JoinHandleData {
             mqtt_client,
             conf,
@@ -57,16 +67,17 @@ impl Sink {
             stop_signal_rx,
             mb_rx,
             app_err_rx,
-            publish_properties,
+            topic,
+            properties,
         };
 
         let join_handle = Self::event_loop(join_handle_data);
 
-        Self {
+        Ok(Self {
             mb_tx,
             stop_signal_tx,
             join_handle: Some(join_handle),
-        }
+        })
     }
 
     fn event_loop(mut join_handle_data: JoinHandleData) -> JoinHandle<JoinHandleData> {
@@ -80,23 +91,29 @@ impl Sink {
                     }
 
                     Some(mb) = join_handle_data.mb_rx.recv() => {
+                        let mb = mb.take_mb();
+                        // 连接断开或仍有待重发的消息时先保留，保证消息的发送顺序
+                        if err || !join_handle_data.message_retainer.is_empty() {
+                            join_handle_data.message_retainer.push(mb);
+                        } else if let Err(e) = Self::publish(&join_handle_data, qos, &mb).await {
+                            warn!("{:?}", e);
+                            err = true;
+                            join_handle_data.message_retainer.push(mb);
+                        }
+                    }
+
+                    Ok(app_err) = join_handle_data.app_err_rx.recv() => {
+                        err = app_err;
+                        // 连接恢复后发送保留的消息
                         if !err {
-                            match &join_handle_data.publish_properties {
-                                Some(pp) => {
-                                    if let Err(e) = join_handle_data.mqtt_client.publish_with_properties(&join_handle_data.conf.topic, qos, join_handle_data.conf.retain, mb.take_mb().to_json(), pp.clone()).await {
-                                        warn!("{:?}", e);
-                                        err = true;
-                                    }
-                                }
-                                None => {
-                                    if let Err(e) = join_handle_data.mqtt_client.publish(&join_handle_data.conf.topic, qos, join_handle_data.conf.retain, mb.take_mb().to_json()).await {
-                                        warn!("{:?}", e);
-                                        err = true;
-                                    }
+                            while let Some(mb) = join_handle_data.message_retainer.peek() {
+                                if let Err(e) = Self::publish(&join_handle_data, qos, &mb).await {
+                                    warn!("{:?}", e);
+                                    err = true;
+                                    break;
                                 }
+                                join_handle_data.message_retainer.pop();
                             }
-                        } else {
-                            join_handle_data.message_retainer.push(mb.take_mb());
                         }
                     }
                 }
@@ -104,20 +121,126 @@ impl Sink {
         })
     }
 
+    async fn publish(
+        join_handle_data: &JoinHandleData,
+        qos: mqttbytes::QoS,
+        mb: &MessageBatch,
+    ) -> Result<(), ClientError> {
+        if join_handle_data.is_const() {
+            if let Template::Const(topic) = &join_handle_data.topic {
+                let properties = join_handle_data.properties.base.clone();
+                return Self::publish_to_topic(join_handle_data, qos, topic, properties, mb).await;
+            }
+        }
+
+        // 主题或发布属性中包含${field}时，按每条消息渲染的结果分组发送
+        // 部分分组发送失败时整批消息会被保留重发
+        let groups = group_messages(mb.clone(), |msg| {
+            let topic = match join_handle_data.topic.render(msg) {
+                Some(topic) if mqttbytes::valid_topic(&topic) => topic,
+                Some(topic) => {
+                    warn!("topic不合法：{}，消息已丢弃", topic);
+                    return None;
+                }
+                None => {
+                    warn!("消息中缺少topic模板引用的字段，消息已丢弃");
+                    return None;
+                }
+            };
+            let properties = match join_handle_data.properties.render(msg) {
+                Some(properties) => properties,
+                None => {
+                    warn!("消息中缺少发布属性引用的字段或字段类型错误，消息已丢弃");
+                    return None;
+                }
+            };
+            Some((topic, properties))
+        });
+        for ((topic, properties), mb) in groups {
+            Self::publish_to_topic(join_handle_data, qos, &topic, properties, &mb).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn publish_to_topic(
+        join_handle_data: &JoinHandleData,
+        qos: mqttbytes::QoS,
+        topic: &str,
+        properties: Option<v5::PublishProperties>,
+        mb: &MessageBatch,
+    ) -> Result<(), ClientError> {
+        match properties {
+            Some(pp) => {
+                join_handle_data
+                    .mqtt_client
+                    .publish_with_properties(
+                        topic,
+                        qos,
+                        join_handle_data.conf.retain,
+                        mb.to_json(),
+                        pp,
+                    )
+                    .await
+            }
+            None => {
+                join_handle_data
+                    .mqtt_client
+                    .publish(topic, qos, join_handle_data.conf.retain, mb.to_json())
+                    .await
+            }
+        }
+    }
+
     pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
-        if !mqttbytes::valid_topic(&conf.topic) {
+        let topic = Template::new(&conf.topic);
+        // 模板主题在发布时按渲染结果校验
+        if topic.is_const() && !mqttbytes::valid_topic(&conf.topic) {
             return Err(HaliaError::Common("topic不合法！".to_owned()));
         }
 
+        if let Some(properties) = &conf.properties {
+            if !topic.is_const() && properties.topic_alias.is_some() {
+                return Err(HaliaError::Common(
+                    "动态topic不支持设置topic_alias！".to_owned(),
+                ));
+            }
+            if let Some(message_expiry_interval) = &properties.message_expiry_interval {
+                match get_dynamic_value_from_json(message_expiry_interval) {
+                    DynamicValue::Const(value) => {
+                        if value.as_u64().and_then(|v| u32::try_from(v).ok()).is_none() {
+                            return Err(HaliaError::Common(
+                                "message_expiry_interval必须为非负整数或${field}！".to_owned(),
+                            ));
+                        }
+                    }
+                    DynamicValue::Field(_) => {}
+                }
+            }
+        }
+
         Ok(())
     }
 
-    pub async fn update(&mut self, _old_conf: SinkConf, new_conf: SinkConf) -> HaliaResult<()> {
+    pub async fn update(
+        &mut self,
+        sink_id: &str,
+        old_conf: SinkConf,
+        new_conf: SinkConf,
+    ) -> HaliaResult<()> {
         let mut join_handle_data = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut join_handle_data.message_retainer,
+            &old_conf.message_retain,
+            &new_conf.message_retain,
+            sink_id,
+        );
+        join_handle_data.topic = Template::new(&new_conf.topic);
+        join_handle_data.properties = PropertiesTemplate::new(&new_conf.properties);
         join_handle_data.conf = new_conf;
-        Self::event_loop(join_handle_data);
+        self.join_handle = Some(Self::event_loop(join_handle_data));
 
-        Ok(())
+        res
     }
 
     pub async fn stop(&mut self) -> JoinHandleData {
@@ -128,7 +251,7 @@ impl Sink {
     pub async fn update_mqtt_client(&mut self, mqtt_client: Arc<AsyncClient>) {
         let mut join_handle_data = self.stop().await;
         join_handle_data.mqtt_client = mqtt_client;
-        Self::event_loop(join_handle_data);
+        self.join_handle = Some(Self::event_loop(join_handle_data));
     }
 
     pub fn get_txs(&self, cnt: usize) -> Vec<UnboundedSender<RuleMessageBatch>> {
@@ -140,55 +263,150 @@ impl Sink {
     }
 }
 
-fn get_publish_properties(conf: &Option<PublishProperties>) -> Option<v5::PublishProperties> {
-    let conf = match conf {
-        Some(conf) => conf,
-        None => return None,
-    };
-    let mut some = false;
-    let mut pp = v5::PublishProperties {
-        payload_format_indicator: None,
-        message_expiry_interval: None,
-        topic_alias: None,
-        response_topic: None,
-        correlation_data: None,
-        user_properties: vec![],
-        subscription_identifiers: vec![],
-        content_type: None,
-    };
-
-    if let Some(pfi) = conf.payload_format_indicator {
-        some = true;
-        pp.payload_format_indicator = Some(pfi);
-    }
-    if let Some(mpi) = conf.message_expiry_interval {
-        some = true;
-        pp.message_expiry_interval = Some(mpi);
-    }
-    if let Some(ta) = conf.topic_alias {
-        some = true;
-        pp.topic_alias = Some(ta);
-    }
-    if let Some(cd) = &conf.correlation_data {
-        some = true;
-        pp.correlation_data = Some(cd.clone().into());
-    }
-    if let Some(up) = &conf.user_properties {
-        some = true;
-        pp.user_properties = up.clone();
+impl JoinHandleData {
+    fn is_const(&self) -> bool {
+        self.topic.is_const() && self.properties.is_const()
     }
-    if let Some(si) = &conf.subscription_identifiers {
-        some = true;
-        pp.subscription_identifiers = si.clone();
+}
+
+// content_type、response_topic、明文的correlation_data和user_properties的值支持${field}，
+// message_expiry_interval支持"${field}"，发布时从每条消息中取值
+struct PropertiesTemplate {
+    base: Option<v5::PublishProperties>,
+    content_type: Option<Template>,
+    response_topic: Option<Template>,
+    correlation_data: Option<Template>,
+    user_properties: Vec<(String, Template)>,
+    message_expiry_interval: Option<String>,
+}
+
+impl PropertiesTemplate {
+    fn new(conf: &Option<PublishProperties>) -> Self {
+        let mut some = false;
+        let mut base = v5::PublishProperties::default();
+        let mut properties = Self {
+            base: None,
+            content_type: None,
+            response_topic: None,
+            correlation_data: None,
+            user_properties: vec![],
+            message_expiry_interval: None,
+        };
+        let conf = match conf {
+            Some(conf) => conf,
+            None => return properties,
+        };
+
+        if let Some(pfi) = conf.payload_format_indicator {
+            some = true;
+            base.payload_format_indicator = Some(pfi);
+        }
+        if let Some(mei) = &conf.message_expiry_interval {
+            some = true;
+            match get_dynamic_value_from_json(mei) {
+                DynamicValue::Const(value) => {
+                    base.message_expiry_interval =
+                        value.as_u64().and_then(|v| u32::try_from(v).ok());
+                }
+                DynamicValue::Field(field) => properties.message_expiry_interval = Some(field),
+            }
+        }
+        if let Some(ta) = conf.topic_alias {
+            some = true;
+            base.topic_alias = Some(ta);
+        }
+        if let Some(cd) = &conf.correlation_data {
+            some = true;
+            match cd.typ {
+                PlainOrBase64ValueType::Plain => {
+                    let template = Template::new(&cd.value);
+                    if template.is_const() {
+                        base.correlation_data = Some(cd.clone().into());
+                    } else {
+                        properties.correlation_data = Some(template);
+                    }
+                }
+                PlainOrBase64ValueType::Base64 => {
+                    base.correlation_data = Some(cd.clone().into());
+                }
+            }
+        }
+        if let Some(up) = &conf.user_properties {
+            some = true;
+            for (key, value) in up {
+                let template = Template::new(value);
+                if template.is_const() {
+                    base.user_properties.push((key.clone(), value.clone()));
+                } else {
+                    properties.user_properties.push((key.clone(), template));
+                }
+            }
+        }
+        if let Some(si) = &conf.subscription_identifiers {
+            some = true;
+            base.subscription_identifiers = si.clone();
+        }
+        if let Some(ct) = &conf.content_type {
+            some = true;
+            let template = Template::new(ct);
+            if template.is_const() {
+                base.content_type = Some(ct.clone());
+            } else {
+                properties.content_type = Some(template);
+            }
+        }
+        if let Some(rt) = &conf.response_topic {
+            some = true;
+            let template = Template::new(rt);
+            if template.is_const() {
+                base.response_topic = Some(rt.clone());
+            } else {
+                properties.response_topic = Some(template);
+            }
+        }
+
+        if some {
+            properties.base = Some(base);
+        }
+        properties
     }
-    if let Some(ct) = &conf.content_type {
-        some = true;
-        pp.content_type = Some(ct.clone());
+
+    fn is_const(&self) -> bool {
+        self.content_type.is_none()
+            && self.response_topic.is_none()
+            && self.correlation_data.is_none()
+            && self.user_properties.is_empty()
+            && self.message_expiry_interval.is_none()
     }
 
-    if some {
-        Some(pp)
-    } else {
-        None
+    // 外层为None表示消息中缺少引用的字段
+    fn render(&self, msg: &Message) -> Option<Option<v5::PublishProperties>> {
+        let mut pp = match &self.base {
+            Some(base) => base.clone(),
+            None => return Some(None),
+        };
+
+        if let Some(content_type) = &self.content_type {
+            pp.content_type = Some(content_type.render(msg)?);
+        }
+        if let Some(response_topic) = &self.response_topic {
+            pp.response_topic = Some(response_topic.render(msg)?);
+        }
+        if let Some(correlation_data) = &self.correlation_data {
+            pp.correlation_data = Some(correlation_data.render(msg)?.into());
+        }
+        for (key, value) in &self.user_properties {
+            pp.user_properties.push((key.clone(), value.render(msg)?));
+        }
+        if let Some(field) = &self.message_expiry_interval {
+            match get_message_value(msg, field)? {
+                MessageValue::Int64(v) => {
+                    pp.message_expiry_interval = Some(u32::try_from(*v).ok()?)
+                }
+                _ => return None,
+            }
+        }
+
+        Some(Some(pp))
     }
 }
@@ -1,25 +1,64 @@
 use common::error::{HaliaError, HaliaResult};
 use message::RuleMessageBatch;
-use rumqttc::valid_filter;
+use schema::Decoder;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use types::apps::mqtt_client_v50::SourceConf;
 
+use crate::mqtt_client_topic::validate_filter;
+
+// 订阅标识符的最大值，mqtt v5规范3.8.2.1.2
+const MAX_SUBSCRIPTION_ID: usize = 268_435_455;
+
 pub struct Source {
     pub conf: SourceConf,
+    // 订阅时携带的订阅标识符，用于将broker投递的消息路由到对应的源
+    pub subscription_id: usize,
     pub mb_txs: Vec<UnboundedSender<RuleMessageBatch>>,
+    pub decoder: Box<dyn Decoder>,
 }
 
 impl Source {
-    pub fn new(conf: SourceConf) -> Self {
-        Source {
+    pub async fn new(conf: SourceConf, subscription_id: usize) -> HaliaResult<Self> {
+        let decoder = schema::new_decoder(&conf.decode_type, &conf.schema_id).await?;
+        Ok(Source {
             conf,
+            subscription_id,
             mb_txs: vec![],
-        }
+            decoder,
+        })
     }
 
-    pub fn validate_conf(conf: &SourceConf) -> HaliaResult<()> {
-        if !valid_filter(&conf.topic) {
-            return Err(HaliaError::Common("topic错误！".to_owned()));
+    pub async fn process_conf(
+        app_id: &String,
+        source_id: &String,
+        conf: &SourceConf,
+    ) -> HaliaResult<()> {
+        validate_filter(&conf.topic)?;
+        if let Some(id) = configured_subscription_id(conf) {
+            if id == 0 || id > MAX_SUBSCRIPTION_ID {
+                return Err(HaliaError::Common(format!(
+                    "订阅标识符必须在1到{}之间！",
+                    MAX_SUBSCRIPTION_ID
+                )));
+            }
+        }
+
+        match conf.decode_type {
+            types::schema::DecodeType::Protobuf => match &conf.schema_id {
+                Some(schema_id) => {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+                None => return Err(HaliaError::Common("请填写schema_id".to_owned())),
+            },
+            types::schema::DecodeType::Csv | types::schema::DecodeType::Avro => {
+                if let Some(schema_id) = &conf.schema_id {
+                    schema::reference_app_source(schema_id, app_id, source_id).await?
+                }
+            }
+            types::schema::DecodeType::Raw
+            | types::schema::DecodeType::Yaml
+            | types::schema::DecodeType::Json
+            | types::schema::DecodeType::Toml => {}
         }
 
         Ok(())
@@ -35,3 +74,10 @@ impl Source {
         rxs
     }
 }
+
+pub fn configured_subscription_id(conf: &SourceConf) -> Option<usize> {
+    match (conf.subscribe_properties_enable, &conf.subscribe_properties) {
+        (true, Some(subscribe_properties)) => subscribe_properties.id,
+        _ => None,
+    }
+}
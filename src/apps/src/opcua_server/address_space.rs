@@ -0,0 +1,761 @@
+use std::collections::HashMap;
+
+use message::MessageValue;
+use opcua_protocol::types::{
+    Array, AttributeId, BrowseDescription, BrowseDirection, BrowsePath, BrowsePathResult,
+    BrowsePathTarget, BrowseResult, BuildInfo, ByteString, DataTypeId, DataValue, DateTime,
+    ExpandedNodeId, ExtensionObject, Identifier, LocalizedText, NodeClass, NodeId, ObjectId,
+    ObjectTypeId, QualifiedName, ReadValueId, ReferenceDescription, ReferenceTypeId, ServerState,
+    ServerStatusDataType, StatusCode, UAString, VariableId, VariableTypeId, Variant, VariantTypeId,
+    WriteValue,
+};
+
+pub(super) const NAMESPACE_URI: &str = "urn:halia:opcua-server";
+pub(super) const NAMESPACE_INDEX: u16 = 1;
+pub(super) const APPLICATION_URI: &str = "urn:halia:opcua-server";
+pub(super) const PRODUCT_URI: &str = "https://halia.com";
+pub(super) const DATABOARD_FOLDER: &str = "Databoard";
+
+// AccessLevel属性的位定义
+const ACCESS_LEVEL_CURRENT_READ: u8 = 0x01;
+const ACCESS_LEVEL_CURRENT_WRITE: u8 = 0x02;
+
+// 值为任意维度
+const VALUE_RANK_ANY: i32 = -2;
+
+struct Reference {
+    reference_type: ReferenceTypeId,
+    target: NodeId,
+    is_forward: bool,
+}
+
+struct Node {
+    node_class: NodeClass,
+    browse_name: QualifiedName,
+    display_name: LocalizedText,
+    type_definition: Option<NodeId>,
+    references: Vec<Reference>,
+    variable: Option<Variable>,
+}
+
+struct Variable {
+    value: DataValue,
+    // 允许写入时为所属目录及消息字段
+    write_back: Option<(String, String)>,
+}
+
+// 客户端写入变量后需要输出的内容
+pub(super) struct WriteBack {
+    pub folder: String,
+    pub field: String,
+    pub value: MessageValue,
+}
+
+pub(super) struct AddressSpace {
+    nodes: HashMap<NodeId, Node>,
+    start_time: DateTime,
+}
+
+impl AddressSpace {
+    pub fn new() -> Self {
+        let mut address_space = Self {
+            nodes: HashMap::new(),
+            start_time: DateTime::now(),
+        };
+        address_space.add_standard_nodes();
+        address_space
+    }
+
+    fn add_standard_nodes(&mut self) {
+        let folder_type: NodeId = ObjectTypeId::FolderType.into();
+        self.add_node(
+            ObjectId::RootFolder.into(),
+            None,
+            NodeClass::Object,
+            "Root",
+            Some(folder_type.clone()),
+            None,
+        );
+        for (node_id, name) in [
+            (ObjectId::ObjectsFolder, "Objects"),
+            (ObjectId::TypesFolder, "Types"),
+            (ObjectId::ViewsFolder, "Views"),
+        ] {
+            self.add_node(
+                node_id.into(),
+                Some((ObjectId::RootFolder.into(), ReferenceTypeId::Organizes)),
+                NodeClass::Object,
+                name,
+                Some(folder_type.clone()),
+                None,
+            );
+        }
+
+        let server: NodeId = ObjectId::Server.into();
+        self.add_node(
+            server.clone(),
+            Some((ObjectId::ObjectsFolder.into(), ReferenceTypeId::Organizes)),
+            NodeClass::Object,
+            "Server",
+            Some(ObjectTypeId::ServerType.into()),
+            None,
+        );
+        for (node_id, name) in [
+            (VariableId::Server_ServerArray, "ServerArray"),
+            (VariableId::Server_NamespaceArray, "NamespaceArray"),
+        ] {
+            self.add_node(
+                node_id.into(),
+                Some((server.clone(), ReferenceTypeId::HasProperty)),
+                NodeClass::Variable,
+                name,
+                Some(VariableTypeId::PropertyType.into()),
+                Some(Variable {
+                    value: DataValue::null(),
+                    write_back: None,
+                }),
+            );
+        }
+
+        let server_status: NodeId = VariableId::Server_ServerStatus.into();
+        self.add_node(
+            server_status.clone(),
+            Some((server, ReferenceTypeId::HasComponent)),
+            NodeClass::Variable,
+            "ServerStatus",
+            Some(VariableTypeId::ServerStatusType.into()),
+            Some(Variable {
+                value: DataValue::null(),
+                write_back: None,
+            }),
+        );
+        for (node_id, name) in [
+            (VariableId::Server_ServerStatus_StartTime, "StartTime"),
+            (VariableId::Server_ServerStatus_CurrentTime, "CurrentTime"),
+            (VariableId::Server_ServerStatus_State, "State"),
+        ] {
+            self.add_node(
+                node_id.into(),
+                Some((server_status.clone(), ReferenceTypeId::HasComponent)),
+                NodeClass::Variable,
+                name,
+                Some(VariableTypeId::BaseDataVariableType.into()),
+                Some(Variable {
+                    value: DataValue::null(),
+                    write_back: None,
+                }),
+            );
+        }
+    }
+
+    fn add_node(
+        &mut self,
+        node_id: NodeId,
+        parent: Option<(NodeId, ReferenceTypeId)>,
+        node_class: NodeClass,
+        name: &str,
+        type_definition: Option<NodeId>,
+        variable: Option<Variable>,
+    ) {
+        let namespace = node_id.namespace;
+        let mut references = vec![];
+        if let Some((parent, reference_type)) = parent {
+            if let Some(parent_node) = self.nodes.get_mut(&parent) {
+                parent_node.references.push(Reference {
+                    reference_type,
+                    target: node_id.clone(),
+                    is_forward: true,
+                });
+            }
+            references.push(Reference {
+                reference_type,
+                target: parent,
+                is_forward: false,
+            });
+        }
+        if let Some(type_definition) = &type_definition {
+            references.push(Reference {
+                reference_type: ReferenceTypeId::HasTypeDefinition,
+                target: type_definition.clone(),
+                is_forward: true,
+            });
+        }
+
+        self.nodes.insert(
+            node_id,
+            Node {
+                node_class,
+                browse_name: QualifiedName::new(namespace, name),
+                display_name: LocalizedText::new("", name),
+                type_definition,
+                references,
+                variable,
+            },
+        );
+    }
+
+    fn remove_node(&mut self, node_id: &NodeId) {
+        if let Some(node) = self.nodes.remove(node_id) {
+            for reference in node.references {
+                if let Some(target) = self.nodes.get_mut(&reference.target) {
+                    target.references.retain(|r| &r.target != node_id);
+                }
+            }
+        }
+    }
+
+    pub fn contains_folder(&self, folder: &str) -> bool {
+        self.nodes.contains_key(&get_folder_node_id(folder))
+    }
+
+    pub fn add_folder(&mut self, folder: &str) {
+        self.add_node(
+            get_folder_node_id(folder),
+            Some((ObjectId::ObjectsFolder.into(), ReferenceTypeId::Organizes)),
+            NodeClass::Object,
+            folder,
+            Some(ObjectTypeId::FolderType.into()),
+            None,
+        );
+    }
+
+    // 同时删除目录下的变量
+    pub fn remove_folder(&mut self, folder: &str) {
+        let folder_node_id = get_folder_node_id(folder);
+        let children: Vec<NodeId> = match self.nodes.get(&folder_node_id) {
+            Some(node) => node
+                .references
+                .iter()
+                .filter(|r| r.is_forward && r.reference_type == ReferenceTypeId::Organizes)
+                .map(|r| r.target.clone())
+                .collect(),
+            None => return,
+        };
+        for child in children.iter() {
+            self.remove_node(child);
+        }
+        self.remove_node(&folder_node_id);
+    }
+
+    pub fn add_variable(&mut self, folder: &str, name: &str, write_back: Option<String>) {
+        self.add_node(
+            get_variable_node_id(folder, name),
+            Some((get_folder_node_id(folder), ReferenceTypeId::Organizes)),
+            NodeClass::Variable,
+            name,
+            Some(VariableTypeId::BaseDataVariableType.into()),
+            Some(Variable {
+                value: DataValue::null(),
+                write_back: write_back.map(|field| (folder.to_owned(), field)),
+            }),
+        );
+    }
+
+    pub fn set_value(&mut self, folder: &str, name: &str, value: Variant) {
+        let node_id = get_variable_node_id(folder, name);
+        if let Some(Node {
+            variable: Some(variable),
+            ..
+        }) = self.nodes.get_mut(&node_id)
+        {
+            variable.value = DataValue::new_now(value);
+        }
+    }
+
+    pub fn browse(&self, browse_description: &BrowseDescription) -> BrowseResult {
+        let node = match self.nodes.get(&browse_description.node_id) {
+            Some(node) => node,
+            None => return browse_result_err(StatusCode::BadNodeIdUnknown),
+        };
+
+        let reference_type_filter = if browse_description.reference_type_id.is_null() {
+            None
+        } else {
+            match browse_description.reference_type_id.as_reference_type_id() {
+                Ok(reference_type) => Some(reference_type),
+                Err(_) => return browse_result_err(StatusCode::BadReferenceTypeIdInvalid),
+            }
+        };
+
+        let mut references = vec![];
+        for reference in node.references.iter() {
+            match browse_description.browse_direction {
+                BrowseDirection::Forward if !reference.is_forward => continue,
+                BrowseDirection::Inverse if reference.is_forward => continue,
+                BrowseDirection::Invalid => {
+                    return browse_result_err(StatusCode::BadBrowseDirectionInvalid)
+                }
+                _ => {}
+            }
+
+            if let Some(filter) = reference_type_filter {
+                if !is_reference_type_match(
+                    reference.reference_type,
+                    filter,
+                    browse_description.include_subtypes,
+                ) {
+                    continue;
+                }
+            }
+
+            // 标准类型节点不在地址空间中，以ObjectType或VariableType返回
+            let (node_class, browse_name, display_name, type_definition) =
+                match self.nodes.get(&reference.target) {
+                    Some(target) => (
+                        target.node_class,
+                        target.browse_name.clone(),
+                        target.display_name.clone(),
+                        target.type_definition.clone(),
+                    ),
+                    None => (
+                        get_type_node_class(&reference.target),
+                        QualifiedName::null(),
+                        LocalizedText::null(),
+                        None,
+                    ),
+                };
+
+            let node_class_mask = browse_description.node_class_mask;
+            if node_class_mask != 0 && node_class_mask & node_class as u32 == 0 {
+                continue;
+            }
+
+            references.push(ReferenceDescription {
+                reference_type_id: reference.reference_type.into(),
+                is_forward: reference.is_forward,
+                node_id: ExpandedNodeId::new(reference.target.clone()),
+                browse_name,
+                display_name,
+                node_class,
+                type_definition: match type_definition {
+                    Some(type_definition) => ExpandedNodeId::new(type_definition),
+                    None => ExpandedNodeId::null(),
+                },
+            });
+        }
+
+        BrowseResult {
+            status_code: StatusCode::Good,
+            continuation_point: ByteString::null(),
+            references: Some(references),
+        }
+    }
+
+    pub fn translate_browse_path(&self, browse_path: &BrowsePath) -> BrowsePathResult {
+        let elements = match &browse_path.relative_path.elements {
+            Some(elements) if !elements.is_empty() => elements,
+            _ => {
+                return BrowsePathResult {
+                    status_code: StatusCode::BadNothingToDo,
+                    targets: None,
+                }
+            }
+        };
+        if !self.nodes.contains_key(&browse_path.starting_node) {
+            return BrowsePathResult {
+                status_code: StatusCode::BadNodeIdUnknown,
+                targets: None,
+            };
+        }
+
+        let mut current = browse_path.starting_node.clone();
+        for element in elements.iter() {
+            let node = match self.nodes.get(&current) {
+                Some(node) => node,
+                None => break,
+            };
+            let reference_type_filter = element.reference_type_id.as_reference_type_id().ok();
+            let next = node.references.iter().find(|reference| {
+                if reference.is_forward == element.is_inverse {
+                    return false;
+                }
+                if let Some(filter) = reference_type_filter {
+                    if !is_reference_type_match(
+                        reference.reference_type,
+                        filter,
+                        element.include_subtypes,
+                    ) {
+                        return false;
+                    }
+                }
+                match self.nodes.get(&reference.target) {
+                    Some(target) => target.browse_name == element.target_name,
+                    None => false,
+                }
+            });
+            match next {
+                Some(reference) => current = reference.target.clone(),
+                None => {
+                    return BrowsePathResult {
+                        status_code: StatusCode::BadNoMatch,
+                        targets: None,
+                    }
+                }
+            }
+        }
+
+        BrowsePathResult {
+            status_code: StatusCode::Good,
+            targets: Some(vec![BrowsePathTarget {
+                target_id: ExpandedNodeId::new(current),
+                remaining_path_index: u32::MAX,
+            }]),
+        }
+    }
+
+    pub fn read(&self, read_value_id: &ReadValueId) -> DataValue {
+        let node = match self.nodes.get(&read_value_id.node_id) {
+            Some(node) => node,
+            None => return data_value_err(StatusCode::BadNodeIdUnknown),
+        };
+
+        let attribute_id = match AttributeId::from_u32(read_value_id.attribute_id) {
+            Ok(attribute_id) => attribute_id,
+            Err(_) => return data_value_err(StatusCode::BadAttributeIdInvalid),
+        };
+
+        if attribute_id == AttributeId::Value {
+            return match &node.variable {
+                Some(variable) => self.read_value(&read_value_id.node_id, variable),
+                None => data_value_err(StatusCode::BadAttributeIdInvalid),
+            };
+        }
+
+        let value = match attribute_id {
+            AttributeId::NodeId => Variant::from(read_value_id.node_id.clone()),
+            AttributeId::NodeClass => Variant::Int32(node.node_class as i32),
+            AttributeId::BrowseName => Variant::from(node.browse_name.clone()),
+            AttributeId::DisplayName => Variant::from(node.display_name.clone()),
+            AttributeId::Description => Variant::from(LocalizedText::null()),
+            AttributeId::WriteMask | AttributeId::UserWriteMask => Variant::UInt32(0),
+            AttributeId::EventNotifier if node.node_class == NodeClass::Object => Variant::Byte(0),
+            _ => match &node.variable {
+                Some(variable) => match self.read_variable_attribute(
+                    &read_value_id.node_id,
+                    variable,
+                    attribute_id,
+                ) {
+                    Some(value) => value,
+                    None => return data_value_err(StatusCode::BadAttributeIdInvalid),
+                },
+                None => return data_value_err(StatusCode::BadAttributeIdInvalid),
+            },
+        };
+
+        DataValue::value_only(value)
+    }
+
+    fn read_variable_attribute(
+        &self,
+        node_id: &NodeId,
+        variable: &Variable,
+        attribute_id: AttributeId,
+    ) -> Option<Variant> {
+        let value = match attribute_id {
+            AttributeId::DataType => {
+                let data_type = match &self.read_value(node_id, variable).value {
+                    Some(value @ Variant::Array(_)) => value.array_data_type(),
+                    Some(value) => value.scalar_data_type(),
+                    None => None,
+                };
+                Variant::from(data_type.unwrap_or(DataTypeId::BaseDataType.into()))
+            }
+            AttributeId::ValueRank => Variant::Int32(VALUE_RANK_ANY),
+            AttributeId::AccessLevel | AttributeId::UserAccessLevel => {
+                let mut access_level = ACCESS_LEVEL_CURRENT_READ;
+                if variable.write_back.is_some() {
+                    access_level |= ACCESS_LEVEL_CURRENT_WRITE;
+                }
+                Variant::Byte(access_level)
+            }
+            AttributeId::MinimumSamplingInterval => Variant::Double(0.0),
+            AttributeId::Historizing => Variant::Boolean(false),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // 服务端状态类变量在读取时生成
+    fn read_value(&self, node_id: &NodeId, variable: &Variable) -> DataValue {
+        let variable_id = match get_standard_id(node_id) {
+            Some(id) => VariableId::try_from(id),
+            None => return variable.value.clone(),
+        };
+        let value = match variable_id {
+            Ok(VariableId::Server_ServerArray) => Variant::from(vec![APPLICATION_URI.to_owned()]),
+            Ok(VariableId::Server_NamespaceArray) => Variant::from(vec![
+                "http://opcfoundation.org/UA/".to_owned(),
+                NAMESPACE_URI.to_owned(),
+            ]),
+            Ok(VariableId::Server_ServerStatus) => {
+                let server_status = ServerStatusDataType {
+                    start_time: self.start_time,
+                    current_time: DateTime::now(),
+                    state: ServerState::Running,
+                    build_info: BuildInfo {
+                        product_uri: UAString::from(PRODUCT_URI),
+                        manufacturer_name: UAString::from("Halia"),
+                        product_name: UAString::from("Halia"),
+                        software_version: UAString::from(env!("CARGO_PKG_VERSION")),
+                        build_number: UAString::null(),
+                        build_date: DateTime::null(),
+                    },
+                    seconds_till_shutdown: 0,
+                    shutdown_reason: LocalizedText::null(),
+                };
+                Variant::from(ExtensionObject::from_encodable(
+                    ObjectId::ServerStatusDataType_Encoding_DefaultBinary,
+                    &server_status,
+                ))
+            }
+            Ok(VariableId::Server_ServerStatus_StartTime) => Variant::from(self.start_time),
+            Ok(VariableId::Server_ServerStatus_CurrentTime) => Variant::from(DateTime::now()),
+            Ok(VariableId::Server_ServerStatus_State) => {
+                Variant::Int32(ServerState::Running as i32)
+            }
+            _ => return variable.value.clone(),
+        };
+        DataValue::new_now(value)
+    }
+
+    pub fn write(&mut self, write_value: &WriteValue) -> (StatusCode, Option<WriteBack>) {
+        let node = match self.nodes.get_mut(&write_value.node_id) {
+            Some(node) => node,
+            None => return (StatusCode::BadNodeIdUnknown, None),
+        };
+        if write_value.attribute_id != AttributeId::Value as u32 {
+            return (StatusCode::BadNotWritable, None);
+        }
+        if !write_value.index_range.is_null() {
+            return (StatusCode::BadIndexRangeInvalid, None);
+        }
+        let variable = match &mut node.variable {
+            Some(variable) => variable,
+            None => return (StatusCode::BadNotWritable, None),
+        };
+        let (folder, field) = match &variable.write_back {
+            Some(write_back) => write_back.clone(),
+            None => return (StatusCode::BadNotWritable, None),
+        };
+        let value = match &write_value.value.value {
+            Some(value) => value.clone(),
+            None => return (StatusCode::BadTypeMismatch, None),
+        };
+
+        variable.value = DataValue::new_now(value.clone());
+        (
+            StatusCode::Good,
+            Some(WriteBack {
+                folder,
+                field,
+                value: variant_to_message_value(value),
+            }),
+        )
+    }
+}
+
+pub(super) fn get_folder_node_id(folder: &str) -> NodeId {
+    NodeId::new(NAMESPACE_INDEX, folder.to_owned())
+}
+
+pub(super) fn get_variable_node_id(folder: &str, name: &str) -> NodeId {
+    NodeId::new(NAMESPACE_INDEX, format!("{}.{}", folder, name))
+}
+
+// 标准节点的数字id
+fn get_standard_id(node_id: &NodeId) -> Option<u32> {
+    match node_id.identifier {
+        Identifier::Numeric(id) if node_id.namespace == 0 => Some(id),
+        _ => None,
+    }
+}
+
+fn get_type_node_class(node_id: &NodeId) -> NodeClass {
+    match get_standard_id(node_id) {
+        Some(id) if ObjectTypeId::try_from(id).is_ok() => NodeClass::ObjectType,
+        Some(id) if VariableTypeId::try_from(id).is_ok() => NodeClass::VariableType,
+        _ => NodeClass::Unspecified,
+    }
+}
+
+fn browse_result_err(status_code: StatusCode) -> BrowseResult {
+    BrowseResult {
+        status_code,
+        continuation_point: ByteString::null(),
+        references: None,
+    }
+}
+
+fn data_value_err(status_code: StatusCode) -> DataValue {
+    DataValue {
+        status: Some(status_code),
+        ..Default::default()
+    }
+}
+
+fn get_parent_reference_type(reference_type: ReferenceTypeId) -> Option<ReferenceTypeId> {
+    match reference_type {
+        ReferenceTypeId::HasComponent | ReferenceTypeId::HasProperty => {
+            Some(ReferenceTypeId::Aggregates)
+        }
+        ReferenceTypeId::Aggregates | ReferenceTypeId::HasSubtype => {
+            Some(ReferenceTypeId::HasChild)
+        }
+        ReferenceTypeId::HasChild | ReferenceTypeId::Organizes => {
+            Some(ReferenceTypeId::HierarchicalReferences)
+        }
+        ReferenceTypeId::HasTypeDefinition => Some(ReferenceTypeId::NonHierarchicalReferences),
+        ReferenceTypeId::HierarchicalReferences | ReferenceTypeId::NonHierarchicalReferences => {
+            Some(ReferenceTypeId::References)
+        }
+        _ => None,
+    }
+}
+
+fn is_reference_type_match(
+    reference_type: ReferenceTypeId,
+    filter: ReferenceTypeId,
+    include_subtypes: bool,
+) -> bool {
+    if reference_type == filter {
+        return true;
+    }
+    if !include_subtypes {
+        return false;
+    }
+    let mut current = reference_type;
+    while let Some(parent) = get_parent_reference_type(current) {
+        if parent == filter {
+            return true;
+        }
+        current = parent;
+    }
+    false
+}
+
+pub(super) fn message_value_to_variant(value: &MessageValue) -> Variant {
+    match value {
+        MessageValue::Null => Variant::Empty,
+        MessageValue::Boolean(b) => Variant::Boolean(*b),
+        MessageValue::Int64(i) => Variant::Int64(*i),
+        MessageValue::Float64(f) => Variant::Double(*f),
+        MessageValue::String(s) => Variant::from(s.clone()),
+        MessageValue::Bytes(bytes) => Variant::from(ByteString::from(bytes.clone())),
+        MessageValue::Array(values) => {
+            let values: Vec<Variant> = values.iter().map(message_value_to_variant).collect();
+            // 数组元素类型需一致，否则转换为json字符串
+            let value_type = match values.first() {
+                Some(first) => first.type_id(),
+                None => VariantTypeId::Empty,
+            };
+            match Array::new(value_type, values) {
+                Ok(array) => Variant::from(array),
+                Err(_) => message_value_to_json_variant(value),
+            }
+        }
+        MessageValue::Object(_) => message_value_to_json_variant(value),
+    }
+}
+
+fn message_value_to_json_variant(value: &MessageValue) -> Variant {
+    let value: serde_json::Value = value.clone().into();
+    Variant::from(value.to_string())
+}
+
+pub(super) fn variant_to_message_value(variant: Variant) -> MessageValue {
+    match variant {
+        Variant::Empty => MessageValue::Null,
+        Variant::Boolean(b) => MessageValue::Boolean(b),
+        Variant::SByte(i) => MessageValue::Int64(i as i64),
+        Variant::Byte(u) => MessageValue::Int64(u as i64),
+        Variant::Int16(i) => MessageValue::Int64(i as i64),
+        Variant::UInt16(u) => MessageValue::Int64(u as i64),
+        Variant::Int32(i) => MessageValue::Int64(i as i64),
+        Variant::UInt32(u) => MessageValue::Int64(u as i64),
+        Variant::Int64(i) => MessageValue::Int64(i),
+        Variant::UInt64(u) => MessageValue::Int64(u as i64),
+        Variant::Float(f) => MessageValue::Float64(f as f64),
+        Variant::Double(f) => MessageValue::Float64(f),
+        Variant::String(s) => MessageValue::String(s.to_string()),
+        // 毫秒时间戳
+        Variant::DateTime(dt) => MessageValue::Int64(dt.as_chrono().timestamp_millis()),
+        Variant::ByteString(bs) => MessageValue::Bytes(bs.value.unwrap_or_default()),
+        Variant::Array(array) => MessageValue::Array(
+            array
+                .values
+                .into_iter()
+                .map(variant_to_message_value)
+                .collect(),
+        ),
+        Variant::Variant(variant) => variant_to_message_value(*variant),
+        other => MessageValue::String(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opcua_protocol::types::{
+        AttributeId, BrowseDescription, BrowseDirection, BrowseResultMask, DataValue, NodeId,
+        ObjectId, QualifiedName, ReadValueId, ReferenceTypeId, UAString, Variant, WriteValue,
+    };
+
+    use super::{get_folder_node_id, get_variable_node_id, AddressSpace};
+
+    #[test]
+    fn test_address_space() {
+        let mut address_space = AddressSpace::new();
+        address_space.add_folder("line1");
+        address_space.add_variable("line1", "temperature", None);
+        address_space.add_variable("line1", "setpoint", Some("sp".to_owned()));
+        address_space.set_value("line1", "temperature", Variant::Double(21.5));
+
+        let result = address_space.browse(&BrowseDescription {
+            node_id: ObjectId::ObjectsFolder.into(),
+            browse_direction: BrowseDirection::Forward,
+            reference_type_id: ReferenceTypeId::HierarchicalReferences.into(),
+            include_subtypes: true,
+            node_class_mask: 0,
+            result_mask: BrowseResultMask::All as u32,
+        });
+        let references = result.references.unwrap();
+        assert!(references
+            .iter()
+            .any(|r| r.node_id.node_id == get_folder_node_id("line1")));
+
+        let data_value = address_space.read(&ReadValueId {
+            node_id: get_variable_node_id("line1", "temperature"),
+            attribute_id: AttributeId::Value as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        });
+        assert_eq!(data_value.value, Some(Variant::Double(21.5)));
+
+        let (status_code, write_back) = address_space.write(&WriteValue {
+            node_id: get_variable_node_id("line1", "temperature"),
+            attribute_id: AttributeId::Value as u32,
+            index_range: UAString::null(),
+            value: DataValue::value_only(Variant::Double(1.0)),
+        });
+        assert!(status_code.is_bad());
+        assert!(write_back.is_none());
+
+        let (status_code, write_back) = address_space.write(&WriteValue {
+            node_id: get_variable_node_id("line1", "setpoint"),
+            attribute_id: AttributeId::Value as u32,
+            index_range: UAString::null(),
+            value: DataValue::value_only(Variant::Int32(3)),
+        });
+        assert!(status_code.is_good());
+        assert_eq!(write_back.unwrap().field, "sp");
+
+        address_space.remove_folder("line1");
+        assert!(!address_space.contains_folder("line1"));
+        let data_value = address_space.read(&ReadValueId {
+            node_id: NodeId::new(1, "line1.setpoint"),
+            attribute_id: AttributeId::Value as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        });
+        assert!(data_value.status.unwrap().is_bad());
+    }
+}
@@ -0,0 +1,1313 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use futures::StreamExt;
+use message::Message;
+use opcua_protocol::{
+    core::{
+        comms::{
+            chunker::Chunker,
+            message_chunk::{MessageChunk, MessageIsFinalType},
+            secure_channel::{Role, SecureChannel},
+            security_header::SecurityHeader,
+            tcp_codec::{Message as TcpMessage, TcpCodec},
+            tcp_types::{
+                AcknowledgeMessage, ErrorMessage, HelloMessage, MessageHeader, MessageType,
+            },
+        },
+        supported_message::SupportedMessage,
+    },
+    crypto::{certificate_store::CertificateStore, SecurityPolicy},
+    sync::RwLock as OpcuaRwLock,
+    types::{
+        profiles, ActivateSessionRequest, ActivateSessionResponse, AnonymousIdentityToken,
+        ApplicationDescription, ApplicationType, BinaryEncoder, BrowseNextRequest,
+        BrowseNextResponse, BrowseRequest, BrowseResponse, BrowseResult, ByteString,
+        ChannelSecurityToken, CloseSessionResponse, CreateMonitoredItemsRequest,
+        CreateMonitoredItemsResponse, CreateSessionRequest, CreateSessionResponse,
+        CreateSubscriptionRequest, CreateSubscriptionResponse, DataChangeNotification, DataValue,
+        DateTime, DecodingOptions, DeleteMonitoredItemsRequest, DeleteMonitoredItemsResponse,
+        DeleteSubscriptionsRequest, DeleteSubscriptionsResponse, EndpointDescription,
+        ExtensionObject, FindServersRequest, FindServersResponse, GetEndpointsRequest,
+        GetEndpointsResponse, Guid, LocalizedText, MessageSecurityMode,
+        ModifyMonitoredItemsRequest, ModifyMonitoredItemsResponse, ModifySubscriptionRequest,
+        ModifySubscriptionResponse, MonitoredItemCreateResult, MonitoredItemModifyResult,
+        MonitoredItemNotification, MonitoringMode, MonitoringParameters, NodeId,
+        NotificationMessage, ObjectId, OpenSecureChannelRequest, OpenSecureChannelResponse,
+        PublishRequest, PublishResponse, ReadRequest, ReadResponse, ReadValueId,
+        RegisterNodesRequest, RegisterNodesResponse, RequestHeader, ResponseHeader,
+        SecurityTokenRequestType, ServiceFault, SetMonitoringModeRequest,
+        SetMonitoringModeResponse, SetPublishingModeRequest, SetPublishingModeResponse,
+        SignatureData, StatusCode, TranslateBrowsePathsToNodeIdsRequest,
+        TranslateBrowsePathsToNodeIdsResponse, UAString, UnregisterNodesResponse, UserTokenPolicy,
+        UserTokenType, WriteRequest, WriteResponse,
+    },
+};
+use tokio::{io::AsyncWriteExt, net::TcpStream, select, sync::broadcast, sync::watch, time};
+use tokio_util::codec::FramedRead;
+use tracing::{debug, warn};
+use types::apps::opcua_server::{AuthMethod, OpcuaServerConf};
+
+use super::{
+    address_space::{AddressSpace, APPLICATION_URI, PRODUCT_URI},
+    WriteEvent,
+};
+
+const PROTOCOL_VERSION: u32 = 0;
+// 收发缓冲区大小
+const BUFFER_SIZE: u32 = 65535;
+const MAX_MESSAGE_SIZE: u32 = 65535 * 64;
+const MAX_CHUNK_COUNT: u32 = 64;
+// 通道令牌有效期，单位毫秒
+const MIN_TOKEN_LIFETIME: u32 = 60_000;
+const MAX_TOKEN_LIFETIME: u32 = 3_600_000;
+const SESSION_TIMEOUT: f64 = 60_000.0;
+
+// 订阅检查周期，也是最小采样及发布间隔，单位毫秒
+const TICK_INTERVAL: u64 = 100;
+const DEFAULT_PUBLISHING_INTERVAL: f64 = 1000.0;
+const DEFAULT_MAX_KEEP_ALIVE_COUNT: u32 = 10;
+const MAX_KEEP_ALIVE_COUNT: u32 = 1000;
+const MAX_SUBSCRIPTIONS: usize = 64;
+const MAX_PUBLISH_REQUESTS: usize = 64;
+const MAX_QUEUE_SIZE: u32 = 100;
+
+const ANONYMOUS_POLICY_ID: &str = "anonymous";
+
+pub(super) fn start(
+    stream: TcpStream,
+    conf: Arc<OpcuaServerConf>,
+    address_space: Arc<RwLock<AddressSpace>>,
+    write_tx: broadcast::Sender<Arc<WriteEvent>>,
+    stop_signal_rx: watch::Receiver<()>,
+) {
+    tokio::spawn(async move {
+        let mut connection = Connection::new(conf, address_space, write_tx);
+        if let Err(e) = connection.run(stream, stop_signal_rx).await {
+            debug!("opcua connection closed: {}", e);
+        }
+    });
+}
+
+struct Session {
+    authentication_token: NodeId,
+    activated: bool,
+}
+
+struct Subscription {
+    publishing_interval: f64,
+    max_keep_alive_count: u32,
+    max_notifications_per_publish: u32,
+    publishing_enabled: bool,
+    monitored_items: HashMap<u32, MonitoredItem>,
+    // 下一条通知的序号
+    sequence_number: u32,
+    keep_alive_counter: u32,
+    last_publish: Instant,
+    notifications: VecDeque<MonitoredItemNotification>,
+}
+
+struct MonitoredItem {
+    item_to_monitor: ReadValueId,
+    monitoring_mode: MonitoringMode,
+    client_handle: u32,
+    sampling_interval: f64,
+    queue_size: u32,
+    last_sample: Option<Instant>,
+    last_value: Option<DataValue>,
+}
+
+struct Connection {
+    conf: Arc<OpcuaServerConf>,
+    address_space: Arc<RwLock<AddressSpace>>,
+    write_tx: broadcast::Sender<Arc<WriteEvent>>,
+    decoding_options: DecodingOptions,
+    secure_channel: SecureChannel,
+    // 对端的接收缓冲区大小，即发送时单个分片的最大长度
+    send_buffer_size: usize,
+    last_sent_sequence_number: u32,
+    pending_chunks: Vec<MessageChunk>,
+    endpoint_url: String,
+    session: Option<Session>,
+    subscriptions: HashMap<u32, Subscription>,
+    // 等待响应的发布请求
+    publish_requests: VecDeque<(u32, RequestHeader)>,
+    next_id: u32,
+    out: Vec<u8>,
+}
+
+impl Connection {
+    fn new(
+        conf: Arc<OpcuaServerConf>,
+        address_space: Arc<RwLock<AddressSpace>>,
+        write_tx: broadcast::Sender<Arc<WriteEvent>>,
+    ) -> Self {
+        let decoding_options = DecodingOptions::default();
+        // 仅支持None安全策略，证书库不会被使用
+        let certificate_store = Arc::new(OpcuaRwLock::new(CertificateStore::new(Path::new(
+            "./pki/opcua_server",
+        ))));
+        let secure_channel =
+            SecureChannel::new(certificate_store, Role::Server, decoding_options.clone());
+        let endpoint_url = format!("opc.tcp://{}:{}{}", conf.host, conf.port, conf.path);
+
+        Self {
+            conf,
+            address_space,
+            write_tx,
+            decoding_options,
+            secure_channel,
+            send_buffer_size: BUFFER_SIZE as usize,
+            last_sent_sequence_number: 0,
+            pending_chunks: vec![],
+            endpoint_url,
+            session: None,
+            subscriptions: HashMap::new(),
+            publish_requests: VecDeque::new(),
+            next_id: 1,
+            out: vec![],
+        }
+    }
+
+    async fn run(
+        &mut self,
+        stream: TcpStream,
+        mut stop_signal_rx: watch::Receiver<()>,
+    ) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = FramedRead::new(reader, TcpCodec::new(self.decoding_options.clone()));
+        let mut interval = time::interval(Duration::from_millis(TICK_INTERVAL));
+
+        let mut hello_received = false;
+        loop {
+            let mut closed = false;
+            select! {
+                _ = stop_signal_rx.changed() => {
+                    return Ok(());
+                }
+
+                msg = reader.next() => {
+                    let msg = match msg {
+                        Some(msg) => msg?,
+                        None => return Ok(()),
+                    };
+                    match msg {
+                        TcpMessage::Hello(hello) if !hello_received => {
+                            hello_received = true;
+                            if let Err(status_code) = self.handle_hello(hello) {
+                                self.out.extend(ErrorMessage::from_status_code(status_code).encode_to_vec());
+                                closed = true;
+                            }
+                        }
+                        TcpMessage::Chunk(chunk) if hello_received => {
+                            if let Err(status_code) = self.handle_chunk(chunk, &mut closed) {
+                                self.out.extend(ErrorMessage::from_status_code(status_code).encode_to_vec());
+                                closed = true;
+                            }
+                        }
+                        _ => {
+                            self.out.extend(
+                                ErrorMessage::from_status_code(StatusCode::BadCommunicationError).encode_to_vec(),
+                            );
+                            closed = true;
+                        }
+                    }
+                }
+
+                _ = interval.tick() => {
+                    self.tick();
+                }
+            }
+
+            if !self.out.is_empty() {
+                writer.write_all(&self.out).await?;
+                self.out.clear();
+            }
+            if closed {
+                return Ok(());
+            }
+        }
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn handle_hello(&mut self, hello: HelloMessage) -> Result<(), StatusCode> {
+        if !hello.is_valid_buffer_sizes() {
+            return Err(StatusCode::BadCommunicationError);
+        }
+
+        self.send_buffer_size = hello.receive_buffer_size.min(BUFFER_SIZE) as usize;
+        let mut ack = AcknowledgeMessage {
+            message_header: MessageHeader::new(MessageType::Acknowledge),
+            protocol_version: PROTOCOL_VERSION,
+            receive_buffer_size: hello.send_buffer_size.min(BUFFER_SIZE),
+            send_buffer_size: self.send_buffer_size as u32,
+            max_message_size: MAX_MESSAGE_SIZE,
+            max_chunk_count: MAX_CHUNK_COUNT,
+        };
+        ack.message_header.message_size = ack.byte_len() as u32;
+        self.out.extend(ack.encode_to_vec());
+        Ok(())
+    }
+
+    fn handle_chunk(&mut self, chunk: MessageChunk, closed: &mut bool) -> Result<(), StatusCode> {
+        // 拒绝None以外的安全策略
+        if let SecurityHeader::Asymmetric(security_header) =
+            chunk.security_header(&self.decoding_options)?
+        {
+            if SecurityPolicy::from_uri(security_header.security_policy_uri.as_ref())
+                != SecurityPolicy::None
+            {
+                return Err(StatusCode::BadSecurityPolicyRejected);
+            }
+        }
+
+        let chunk = self
+            .secure_channel
+            .verify_and_remove_security(&chunk.data)?;
+        let chunk_info = chunk.chunk_info(&self.secure_channel)?;
+        match chunk_info.message_header.is_final {
+            MessageIsFinalType::Intermediate => {
+                if self.pending_chunks.len() >= MAX_CHUNK_COUNT as usize {
+                    return Err(StatusCode::BadEncodingLimitsExceeded);
+                }
+                self.pending_chunks.push(chunk);
+                return Ok(());
+            }
+            MessageIsFinalType::FinalError => {
+                self.pending_chunks.clear();
+                return Ok(());
+            }
+            MessageIsFinalType::Final => self.pending_chunks.push(chunk),
+        }
+
+        let chunks = std::mem::take(&mut self.pending_chunks);
+        Chunker::validate_chunks(0, &self.secure_channel, &chunks)?;
+        let request = Chunker::decode(&chunks, &self.secure_channel, None)?;
+        let request_id = chunk_info.sequence_header.request_id;
+
+        match request {
+            SupportedMessage::OpenSecureChannelRequest(request) => {
+                let response = self.open_secure_channel(*request);
+                self.send(request_id, response)?;
+            }
+            SupportedMessage::CloseSecureChannelRequest(_) => {
+                *closed = true;
+            }
+            SupportedMessage::PublishRequest(request) => {
+                self.handle_publish(request_id, *request)?;
+            }
+            request => {
+                let response = self.handle_request(request);
+                self.send(request_id, response)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 按对端缓冲区大小分片后写入发送缓冲
+    fn send(&mut self, request_id: u32, message: SupportedMessage) -> Result<(), StatusCode> {
+        let chunks = Chunker::encode(
+            self.last_sent_sequence_number + 1,
+            request_id,
+            MAX_MESSAGE_SIZE as usize,
+            self.send_buffer_size,
+            &self.secure_channel,
+            &message,
+        )?;
+        self.last_sent_sequence_number += chunks.len() as u32;
+
+        let mut data = vec![0u8; self.send_buffer_size + 1024];
+        for chunk in chunks {
+            let size = self.secure_channel.apply_security(&chunk, &mut data)?;
+            self.out.extend_from_slice(&data[..size]);
+        }
+        Ok(())
+    }
+
+    fn open_secure_channel(&mut self, request: OpenSecureChannelRequest) -> SupportedMessage {
+        let request_header = &request.request_header;
+        if request.security_mode != MessageSecurityMode::None {
+            return ServiceFault::new(request_header, StatusCode::BadSecurityModeRejected).into();
+        }
+
+        match request.request_type {
+            SecurityTokenRequestType::Issue => {
+                if self.secure_channel.secure_channel_id() == 0 {
+                    let secure_channel_id = self.next_id();
+                    self.secure_channel.set_secure_channel_id(secure_channel_id);
+                }
+            }
+            SecurityTokenRequestType::Renew => {
+                if self.secure_channel.secure_channel_id() == 0 {
+                    return ServiceFault::new(
+                        request_header,
+                        StatusCode::BadSecureChannelIdInvalid,
+                    )
+                    .into();
+                }
+            }
+        }
+
+        let token_id = self.next_id();
+        let security_token = ChannelSecurityToken {
+            channel_id: self.secure_channel.secure_channel_id(),
+            token_id,
+            created_at: DateTime::now(),
+            revised_lifetime: request
+                .requested_lifetime
+                .clamp(MIN_TOKEN_LIFETIME, MAX_TOKEN_LIFETIME),
+        };
+        self.secure_channel.set_token_id(token_id);
+        self.secure_channel
+            .set_security_token(security_token.clone());
+
+        OpenSecureChannelResponse {
+            response_header: ResponseHeader::new_good(request_header),
+            server_protocol_version: PROTOCOL_VERSION,
+            security_token,
+            server_nonce: ByteString::null(),
+        }
+        .into()
+    }
+
+    fn handle_request(&mut self, request: SupportedMessage) -> SupportedMessage {
+        match request {
+            SupportedMessage::GetEndpointsRequest(request) => self.get_endpoints(*request),
+            SupportedMessage::FindServersRequest(request) => self.find_servers(*request),
+            SupportedMessage::CreateSessionRequest(request) => self.create_session(*request),
+            SupportedMessage::ActivateSessionRequest(request) => self.activate_session(*request),
+            request => {
+                let request_header = request.request_header().clone();
+                if let Err(status_code) = self.check_session(&request_header) {
+                    return ServiceFault::new(&request_header, status_code).into();
+                }
+                match request {
+                    SupportedMessage::CloseSessionRequest(_) => {
+                        self.session = None;
+                        self.subscriptions.clear();
+                        self.publish_requests.clear();
+                        CloseSessionResponse {
+                            response_header: ResponseHeader::new_good(&request_header),
+                        }
+                        .into()
+                    }
+                    SupportedMessage::BrowseRequest(request) => self.browse(*request),
+                    SupportedMessage::BrowseNextRequest(request) => self.browse_next(*request),
+                    SupportedMessage::ReadRequest(request) => self.read(*request),
+                    SupportedMessage::WriteRequest(request) => self.write(*request),
+                    SupportedMessage::TranslateBrowsePathsToNodeIdsRequest(request) => {
+                        self.translate_browse_paths(*request)
+                    }
+                    SupportedMessage::RegisterNodesRequest(request) => {
+                        self.register_nodes(*request)
+                    }
+                    SupportedMessage::UnregisterNodesRequest(_) => UnregisterNodesResponse {
+                        response_header: ResponseHeader::new_good(&request_header),
+                    }
+                    .into(),
+                    SupportedMessage::CreateSubscriptionRequest(request) => {
+                        self.create_subscription(*request)
+                    }
+                    SupportedMessage::ModifySubscriptionRequest(request) => {
+                        self.modify_subscription(*request)
+                    }
+                    SupportedMessage::DeleteSubscriptionsRequest(request) => {
+                        self.delete_subscriptions(*request)
+                    }
+                    SupportedMessage::SetPublishingModeRequest(request) => {
+                        self.set_publishing_mode(*request)
+                    }
+                    SupportedMessage::CreateMonitoredItemsRequest(request) => {
+                        self.create_monitored_items(*request)
+                    }
+                    SupportedMessage::ModifyMonitoredItemsRequest(request) => {
+                        self.modify_monitored_items(*request)
+                    }
+                    SupportedMessage::DeleteMonitoredItemsRequest(request) => {
+                        self.delete_monitored_items(*request)
+                    }
+                    SupportedMessage::SetMonitoringModeRequest(request) => {
+                        self.set_monitoring_mode(*request)
+                    }
+                    // 不保留已发送的通知
+                    SupportedMessage::RepublishRequest(_) => {
+                        ServiceFault::new(&request_header, StatusCode::BadMessageNotAvailable)
+                            .into()
+                    }
+                    _ => {
+                        ServiceFault::new(&request_header, StatusCode::BadServiceUnsupported).into()
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_session(&self, request_header: &RequestHeader) -> Result<(), StatusCode> {
+        match &self.session {
+            Some(session)
+                if session.authentication_token == request_header.authentication_token =>
+            {
+                if session.activated {
+                    Ok(())
+                } else {
+                    Err(StatusCode::BadSessionNotActivated)
+                }
+            }
+            _ => Err(StatusCode::BadSessionIdInvalid),
+        }
+    }
+
+    fn application_description(&self) -> ApplicationDescription {
+        ApplicationDescription {
+            application_uri: UAString::from(APPLICATION_URI),
+            product_uri: UAString::from(PRODUCT_URI),
+            application_name: LocalizedText::new("", "Halia OPC UA Server"),
+            application_type: ApplicationType::Server,
+            gateway_server_uri: UAString::null(),
+            discovery_profile_uri: UAString::null(),
+            discovery_urls: Some(vec![UAString::from(self.endpoint_url.as_str())]),
+        }
+    }
+
+    fn endpoints(&self) -> Vec<EndpointDescription> {
+        let user_token_policy = match self.conf.auth_method {
+            AuthMethod::Anonymous => UserTokenPolicy {
+                policy_id: UAString::from(ANONYMOUS_POLICY_ID),
+                token_type: UserTokenType::Anonymous,
+                issued_token_type: UAString::null(),
+                issuer_endpoint_url: UAString::null(),
+                security_policy_uri: UAString::null(),
+            },
+        };
+
+        vec![EndpointDescription {
+            endpoint_url: UAString::from(self.endpoint_url.as_str()),
+            server: self.application_description(),
+            server_certificate: ByteString::null(),
+            security_mode: MessageSecurityMode::None,
+            security_policy_uri: UAString::from(SecurityPolicy::None.to_uri()),
+            user_identity_tokens: Some(vec![user_token_policy]),
+            transport_profile_uri: UAString::from(profiles::TRANSPORT_PROFILE_URI_BINARY),
+            security_level: 0,
+        }]
+    }
+
+    // 监听0.0.0.0时使用客户端请求的地址作为端点地址
+    fn update_endpoint_url(&mut self, endpoint_url: &UAString) {
+        if !endpoint_url.is_empty() {
+            self.endpoint_url = endpoint_url.to_string();
+        }
+    }
+
+    fn get_endpoints(&mut self, request: GetEndpointsRequest) -> SupportedMessage {
+        self.update_endpoint_url(&request.endpoint_url);
+        GetEndpointsResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            endpoints: Some(self.endpoints()),
+        }
+        .into()
+    }
+
+    fn find_servers(&mut self, request: FindServersRequest) -> SupportedMessage {
+        self.update_endpoint_url(&request.endpoint_url);
+        FindServersResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            servers: Some(vec![self.application_description()]),
+        }
+        .into()
+    }
+
+    fn create_session(&mut self, request: CreateSessionRequest) -> SupportedMessage {
+        self.update_endpoint_url(&request.endpoint_url);
+
+        // 每个连接仅保留一个会话
+        let session_id = NodeId::new(1, self.next_id());
+        // 会话令牌使用随机值，避免被其他连接猜测
+        let authentication_token = NodeId::new(1, Guid::new());
+        self.session = Some(Session {
+            authentication_token: authentication_token.clone(),
+            activated: false,
+        });
+        self.subscriptions.clear();
+        self.publish_requests.clear();
+
+        let revised_session_timeout = if request.requested_session_timeout > 0.0 {
+            request.requested_session_timeout.min(SESSION_TIMEOUT)
+        } else {
+            SESSION_TIMEOUT
+        };
+
+        CreateSessionResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            session_id,
+            authentication_token,
+            revised_session_timeout,
+            server_nonce: ByteString::null(),
+            server_certificate: ByteString::null(),
+            server_endpoints: Some(self.endpoints()),
+            server_software_certificates: None,
+            server_signature: SignatureData {
+                algorithm: UAString::null(),
+                signature: ByteString::null(),
+            },
+            max_request_message_size: MAX_MESSAGE_SIZE,
+        }
+        .into()
+    }
+
+    fn activate_session(&mut self, request: ActivateSessionRequest) -> SupportedMessage {
+        let request_header = &request.request_header;
+        match &self.session {
+            Some(session)
+                if session.authentication_token == request_header.authentication_token => {}
+            _ => return ServiceFault::new(request_header, StatusCode::BadSessionIdInvalid).into(),
+        }
+
+        if let Err(status_code) = self.authenticate(&request.user_identity_token) {
+            return ServiceFault::new(request_header, status_code).into();
+        }
+
+        if let Some(session) = &mut self.session {
+            session.activated = true;
+        }
+        ActivateSessionResponse {
+            response_header: ResponseHeader::new_good(request_header),
+            server_nonce: ByteString::null(),
+            results: None,
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn authenticate(&self, user_identity_token: &ExtensionObject) -> Result<(), StatusCode> {
+        // 空令牌视为匿名
+        let object_id = match user_identity_token.is_null() {
+            true => ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary,
+            false => user_identity_token
+                .object_id()
+                .map_err(|_| StatusCode::BadIdentityTokenInvalid)?,
+        };
+
+        match (&self.conf.auth_method, object_id) {
+            (AuthMethod::Anonymous, ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary) => {
+                if !user_identity_token.is_null() {
+                    user_identity_token
+                        .decode_inner::<AnonymousIdentityToken>(&self.decoding_options)
+                        .map_err(|_| StatusCode::BadIdentityTokenInvalid)?;
+                }
+                Ok(())
+            }
+            _ => Err(StatusCode::BadIdentityTokenRejected),
+        }
+    }
+
+    fn browse(&self, request: BrowseRequest) -> SupportedMessage {
+        let nodes_to_browse = match request.nodes_to_browse {
+            Some(nodes_to_browse) if !nodes_to_browse.is_empty() => nodes_to_browse,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+
+        let address_space = self.address_space.read().unwrap();
+        let results = nodes_to_browse
+            .iter()
+            .map(|browse_description| address_space.browse(browse_description))
+            .collect();
+        BrowseResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    // 浏览结果一次性返回，不会产生继续点
+    fn browse_next(&self, request: BrowseNextRequest) -> SupportedMessage {
+        let continuation_points = match request.continuation_points {
+            Some(continuation_points) if !continuation_points.is_empty() => continuation_points,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+
+        let results = continuation_points
+            .iter()
+            .map(|_| BrowseResult {
+                status_code: StatusCode::BadContinuationPointInvalid,
+                continuation_point: ByteString::null(),
+                references: None,
+            })
+            .collect();
+        BrowseNextResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn read(&self, request: ReadRequest) -> SupportedMessage {
+        let nodes_to_read = match request.nodes_to_read {
+            Some(nodes_to_read) if !nodes_to_read.is_empty() => nodes_to_read,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+
+        let address_space = self.address_space.read().unwrap();
+        let results = nodes_to_read
+            .iter()
+            .map(|read_value_id| address_space.read(read_value_id))
+            .collect();
+        ReadResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn write(&self, request: WriteRequest) -> SupportedMessage {
+        let nodes_to_write = match request.nodes_to_write {
+            Some(nodes_to_write) if !nodes_to_write.is_empty() => nodes_to_write,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+
+        let mut results = Vec::with_capacity(nodes_to_write.len());
+        // 同一请求中写入同一目录的变量合并为一条消息
+        let mut messages: Vec<(String, Message)> = vec![];
+        {
+            let mut address_space = self.address_space.write().unwrap();
+            for write_value in nodes_to_write.iter() {
+                let (status_code, write_back) = address_space.write(write_value);
+                results.push(status_code);
+                if let Some(write_back) = write_back {
+                    match messages
+                        .iter_mut()
+                        .find(|(folder, _)| *folder == write_back.folder)
+                    {
+                        Some((_, message)) => message.add(write_back.field, write_back.value),
+                        None => {
+                            let mut message = Message::default();
+                            message.add(write_back.field, write_back.value);
+                            messages.push((write_back.folder, message));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (folder, message) in messages {
+            // 没有源订阅时忽略
+            let _ = self.write_tx.send(Arc::new(WriteEvent { folder, message }));
+        }
+
+        WriteResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn translate_browse_paths(
+        &self,
+        request: TranslateBrowsePathsToNodeIdsRequest,
+    ) -> SupportedMessage {
+        let browse_paths = match request.browse_paths {
+            Some(browse_paths) if !browse_paths.is_empty() => browse_paths,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+
+        let address_space = self.address_space.read().unwrap();
+        let results = browse_paths
+            .iter()
+            .map(|browse_path| address_space.translate_browse_path(browse_path))
+            .collect();
+        TranslateBrowsePathsToNodeIdsResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    // 节点id直接返回，不做额外处理
+    fn register_nodes(&self, request: RegisterNodesRequest) -> SupportedMessage {
+        match request.nodes_to_register {
+            Some(nodes_to_register) if !nodes_to_register.is_empty() => RegisterNodesResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+                registered_node_ids: Some(nodes_to_register),
+            }
+            .into(),
+            _ => ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo).into(),
+        }
+    }
+
+    fn create_subscription(&mut self, request: CreateSubscriptionRequest) -> SupportedMessage {
+        if self.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return ServiceFault::new(&request.request_header, StatusCode::BadTooManySubscriptions)
+                .into();
+        }
+
+        let publishing_interval = revise_publishing_interval(request.requested_publishing_interval);
+        let max_keep_alive_count =
+            revise_max_keep_alive_count(request.requested_max_keep_alive_count);
+        let lifetime_count =
+            revise_lifetime_count(request.requested_lifetime_count, max_keep_alive_count);
+
+        let subscription_id = self.next_id();
+        self.subscriptions.insert(
+            subscription_id,
+            Subscription {
+                publishing_interval,
+                max_keep_alive_count,
+                max_notifications_per_publish: request.max_notifications_per_publish,
+                publishing_enabled: request.publishing_enabled,
+                monitored_items: HashMap::new(),
+                sequence_number: 1,
+                keep_alive_counter: 0,
+                last_publish: Instant::now(),
+                notifications: VecDeque::new(),
+            },
+        );
+
+        CreateSubscriptionResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            subscription_id,
+            revised_publishing_interval: publishing_interval,
+            revised_lifetime_count: lifetime_count,
+            revised_max_keep_alive_count: max_keep_alive_count,
+        }
+        .into()
+    }
+
+    fn modify_subscription(&mut self, request: ModifySubscriptionRequest) -> SupportedMessage {
+        let subscription = match self.subscriptions.get_mut(&request.subscription_id) {
+            Some(subscription) => subscription,
+            None => {
+                return ServiceFault::new(
+                    &request.request_header,
+                    StatusCode::BadSubscriptionIdInvalid,
+                )
+                .into()
+            }
+        };
+
+        subscription.publishing_interval =
+            revise_publishing_interval(request.requested_publishing_interval);
+        subscription.max_keep_alive_count =
+            revise_max_keep_alive_count(request.requested_max_keep_alive_count);
+        subscription.max_notifications_per_publish = request.max_notifications_per_publish;
+        let lifetime_count = revise_lifetime_count(
+            request.requested_lifetime_count,
+            subscription.max_keep_alive_count,
+        );
+
+        ModifySubscriptionResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            revised_publishing_interval: subscription.publishing_interval,
+            revised_lifetime_count: lifetime_count,
+            revised_max_keep_alive_count: subscription.max_keep_alive_count,
+        }
+        .into()
+    }
+
+    fn delete_subscriptions(&mut self, request: DeleteSubscriptionsRequest) -> SupportedMessage {
+        let subscription_ids = match request.subscription_ids {
+            Some(subscription_ids) if !subscription_ids.is_empty() => subscription_ids,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+
+        let results = subscription_ids
+            .iter()
+            .map(|id| match self.subscriptions.remove(id) {
+                Some(_) => StatusCode::Good,
+                None => StatusCode::BadSubscriptionIdInvalid,
+            })
+            .collect();
+        DeleteSubscriptionsResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn set_publishing_mode(&mut self, request: SetPublishingModeRequest) -> SupportedMessage {
+        let subscription_ids = match request.subscription_ids {
+            Some(subscription_ids) if !subscription_ids.is_empty() => subscription_ids,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+
+        let results = subscription_ids
+            .iter()
+            .map(|id| match self.subscriptions.get_mut(id) {
+                Some(subscription) => {
+                    subscription.publishing_enabled = request.publishing_enabled;
+                    StatusCode::Good
+                }
+                None => StatusCode::BadSubscriptionIdInvalid,
+            })
+            .collect();
+        SetPublishingModeResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn create_monitored_items(&mut self, request: CreateMonitoredItemsRequest) -> SupportedMessage {
+        let items_to_create = match request.items_to_create {
+            Some(items_to_create) if !items_to_create.is_empty() => items_to_create,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+        if !self.subscriptions.contains_key(&request.subscription_id) {
+            return ServiceFault::new(
+                &request.request_header,
+                StatusCode::BadSubscriptionIdInvalid,
+            )
+            .into();
+        }
+
+        let mut results = Vec::with_capacity(items_to_create.len());
+        for item in items_to_create {
+            // 仅支持数据变化，不支持过滤器
+            if !item.requested_parameters.filter.is_null() {
+                results.push(monitored_item_create_err(StatusCode::BadFilterNotAllowed));
+                continue;
+            }
+            let data_value = self
+                .address_space
+                .read()
+                .unwrap()
+                .read(&item.item_to_monitor);
+            if let Some(status_code) = data_value.status {
+                if status_code.is_bad() {
+                    results.push(monitored_item_create_err(status_code));
+                    continue;
+                }
+            }
+
+            let monitored_item_id = self.next_id();
+            let (sampling_interval, queue_size) =
+                revise_monitoring_parameters(&item.requested_parameters);
+            results.push(MonitoredItemCreateResult {
+                status_code: StatusCode::Good,
+                monitored_item_id,
+                revised_sampling_interval: sampling_interval,
+                revised_queue_size: queue_size,
+                filter_result: ExtensionObject::null(),
+            });
+
+            let subscription = self
+                .subscriptions
+                .get_mut(&request.subscription_id)
+                .unwrap();
+            subscription.monitored_items.insert(
+                monitored_item_id,
+                MonitoredItem {
+                    item_to_monitor: item.item_to_monitor,
+                    monitoring_mode: item.monitoring_mode,
+                    client_handle: item.requested_parameters.client_handle,
+                    sampling_interval,
+                    queue_size,
+                    last_sample: None,
+                    last_value: None,
+                },
+            );
+        }
+
+        CreateMonitoredItemsResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn modify_monitored_items(&mut self, request: ModifyMonitoredItemsRequest) -> SupportedMessage {
+        let items_to_modify = match request.items_to_modify {
+            Some(items_to_modify) if !items_to_modify.is_empty() => items_to_modify,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+        let subscription = match self.subscriptions.get_mut(&request.subscription_id) {
+            Some(subscription) => subscription,
+            None => {
+                return ServiceFault::new(
+                    &request.request_header,
+                    StatusCode::BadSubscriptionIdInvalid,
+                )
+                .into()
+            }
+        };
+
+        let results = items_to_modify
+            .iter()
+            .map(|item| {
+                match subscription
+                    .monitored_items
+                    .get_mut(&item.monitored_item_id)
+                {
+                    Some(monitored_item) => {
+                        if !item.requested_parameters.filter.is_null() {
+                            return monitored_item_modify_err(StatusCode::BadFilterNotAllowed);
+                        }
+                        let (sampling_interval, queue_size) =
+                            revise_monitoring_parameters(&item.requested_parameters);
+                        monitored_item.client_handle = item.requested_parameters.client_handle;
+                        monitored_item.sampling_interval = sampling_interval;
+                        monitored_item.queue_size = queue_size;
+                        MonitoredItemModifyResult {
+                            status_code: StatusCode::Good,
+                            revised_sampling_interval: sampling_interval,
+                            revised_queue_size: queue_size,
+                            filter_result: ExtensionObject::null(),
+                        }
+                    }
+                    None => monitored_item_modify_err(StatusCode::BadMonitoredItemIdInvalid),
+                }
+            })
+            .collect();
+        ModifyMonitoredItemsResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn delete_monitored_items(&mut self, request: DeleteMonitoredItemsRequest) -> SupportedMessage {
+        let monitored_item_ids = match request.monitored_item_ids {
+            Some(monitored_item_ids) if !monitored_item_ids.is_empty() => monitored_item_ids,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+        let subscription = match self.subscriptions.get_mut(&request.subscription_id) {
+            Some(subscription) => subscription,
+            None => {
+                return ServiceFault::new(
+                    &request.request_header,
+                    StatusCode::BadSubscriptionIdInvalid,
+                )
+                .into()
+            }
+        };
+
+        let results = monitored_item_ids
+            .iter()
+            .map(|id| match subscription.monitored_items.remove(id) {
+                Some(_) => StatusCode::Good,
+                None => StatusCode::BadMonitoredItemIdInvalid,
+            })
+            .collect();
+        DeleteMonitoredItemsResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn set_monitoring_mode(&mut self, request: SetMonitoringModeRequest) -> SupportedMessage {
+        let monitored_item_ids = match request.monitored_item_ids {
+            Some(monitored_item_ids) if !monitored_item_ids.is_empty() => monitored_item_ids,
+            _ => {
+                return ServiceFault::new(&request.request_header, StatusCode::BadNothingToDo)
+                    .into()
+            }
+        };
+        let subscription = match self.subscriptions.get_mut(&request.subscription_id) {
+            Some(subscription) => subscription,
+            None => {
+                return ServiceFault::new(
+                    &request.request_header,
+                    StatusCode::BadSubscriptionIdInvalid,
+                )
+                .into()
+            }
+        };
+
+        let results = monitored_item_ids
+            .iter()
+            .map(|id| match subscription.monitored_items.get_mut(id) {
+                Some(monitored_item) => {
+                    monitored_item.monitoring_mode = request.monitoring_mode;
+                    // 重新启用时重新上报当前值
+                    if request.monitoring_mode == MonitoringMode::Disabled {
+                        monitored_item.last_value = None;
+                    }
+                    StatusCode::Good
+                }
+                None => StatusCode::BadMonitoredItemIdInvalid,
+            })
+            .collect();
+        SetMonitoringModeResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            results: Some(results),
+            diagnostic_infos: None,
+        }
+        .into()
+    }
+
+    fn handle_publish(
+        &mut self,
+        request_id: u32,
+        request: PublishRequest,
+    ) -> Result<(), StatusCode> {
+        let request_header = request.request_header;
+        if let Err(status_code) = self.check_session(&request_header) {
+            return self.send(
+                request_id,
+                ServiceFault::new(&request_header, status_code).into(),
+            );
+        }
+        // 通知发送后即丢弃，确认无需处理
+        if self.subscriptions.is_empty() {
+            return self.send(
+                request_id,
+                ServiceFault::new(&request_header, StatusCode::BadNoSubscription).into(),
+            );
+        }
+        if self.publish_requests.len() >= MAX_PUBLISH_REQUESTS {
+            return self.send(
+                request_id,
+                ServiceFault::new(&request_header, StatusCode::BadTooManyPublishRequests).into(),
+            );
+        }
+        self.publish_requests
+            .push_back((request_id, request_header));
+        Ok(())
+    }
+
+    // 采样监控项并在到达发布周期时发送通知或保活消息
+    fn tick(&mut self) {
+        if self.subscriptions.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        {
+            let address_space = self.address_space.read().unwrap();
+            for subscription in self.subscriptions.values_mut() {
+                subscription.sample(&address_space, now);
+            }
+        }
+
+        let mut responses = vec![];
+        for (subscription_id, subscription) in self.subscriptions.iter_mut() {
+            if now.duration_since(subscription.last_publish).as_millis()
+                < subscription.publishing_interval as u128
+            {
+                continue;
+            }
+            subscription.last_publish = now;
+
+            let notifications = match subscription.publishing_enabled {
+                true => subscription.take_notifications(),
+                false => vec![],
+            };
+            if notifications.is_empty() {
+                subscription.keep_alive_counter += 1;
+                if subscription.keep_alive_counter < subscription.max_keep_alive_count {
+                    continue;
+                }
+            }
+
+            let (request_id, request_header) = match self.publish_requests.pop_front() {
+                Some(publish_request) => publish_request,
+                None => {
+                    // 没有可用的发布请求时保留通知到下个周期
+                    for notification in notifications.into_iter().rev() {
+                        subscription.notifications.push_front(notification);
+                    }
+                    continue;
+                }
+            };
+            subscription.keep_alive_counter = 0;
+
+            // 保活消息的序号为下一条通知的序号，且不递增
+            let sequence_number = subscription.sequence_number;
+            let notification_data = if notifications.is_empty() {
+                None
+            } else {
+                subscription.sequence_number = subscription.sequence_number.wrapping_add(1).max(1);
+                let data_change_notification = DataChangeNotification {
+                    monitored_items: Some(notifications),
+                    diagnostic_infos: None,
+                };
+                Some(vec![ExtensionObject::from_encodable(
+                    ObjectId::DataChangeNotification_Encoding_DefaultBinary,
+                    &data_change_notification,
+                )])
+            };
+
+            let response = PublishResponse {
+                response_header: ResponseHeader::new_good(&request_header),
+                subscription_id: *subscription_id,
+                available_sequence_numbers: None,
+                more_notifications: !subscription.notifications.is_empty(),
+                notification_message: NotificationMessage {
+                    sequence_number,
+                    publish_time: DateTime::now(),
+                    notification_data,
+                },
+                results: None,
+                diagnostic_infos: None,
+            };
+            responses.push((request_id, response));
+        }
+
+        for (request_id, response) in responses {
+            if let Err(e) = self.send(request_id, response.into()) {
+                warn!("opcua server send publish response err:{}", e);
+            }
+        }
+    }
+}
+
+impl Subscription {
+    fn sample(&mut self, address_space: &AddressSpace, now: Instant) {
+        for monitored_item in self.monitored_items.values_mut() {
+            if monitored_item.monitoring_mode == MonitoringMode::Disabled {
+                continue;
+            }
+            if let Some(last_sample) = monitored_item.last_sample {
+                if now.duration_since(last_sample).as_millis()
+                    < monitored_item.sampling_interval as u128
+                {
+                    continue;
+                }
+            }
+            monitored_item.last_sample = Some(now);
+
+            let data_value = address_space.read(&monitored_item.item_to_monitor);
+            let changed = match &monitored_item.last_value {
+                Some(last_value) => {
+                    last_value.value != data_value.value || last_value.status != data_value.status
+                }
+                None => true,
+            };
+            if !changed {
+                continue;
+            }
+            monitored_item.last_value = Some(data_value.clone());
+
+            if monitored_item.monitoring_mode == MonitoringMode::Reporting {
+                // 超出队列长度时丢弃该监控项最旧的通知
+                let client_handle = monitored_item.client_handle;
+                let queued = self
+                    .notifications
+                    .iter()
+                    .filter(|n| n.client_handle == client_handle)
+                    .count();
+                if queued >= monitored_item.queue_size as usize {
+                    if let Some(index) = self
+                        .notifications
+                        .iter()
+                        .position(|n| n.client_handle == client_handle)
+                    {
+                        self.notifications.remove(index);
+                    }
+                }
+                self.notifications.push_back(MonitoredItemNotification {
+                    client_handle,
+                    value: data_value,
+                });
+            }
+        }
+    }
+
+    fn take_notifications(&mut self) -> Vec<MonitoredItemNotification> {
+        let len = match self.max_notifications_per_publish {
+            0 => self.notifications.len(),
+            max => self.notifications.len().min(max as usize),
+        };
+        self.notifications.drain(..len).collect()
+    }
+}
+
+fn revise_publishing_interval(publishing_interval: f64) -> f64 {
+    if publishing_interval.is_nan() || publishing_interval <= 0.0 {
+        DEFAULT_PUBLISHING_INTERVAL
+    } else {
+        publishing_interval.max(TICK_INTERVAL as f64)
+    }
+}
+
+fn revise_max_keep_alive_count(max_keep_alive_count: u32) -> u32 {
+    match max_keep_alive_count {
+        0 => DEFAULT_MAX_KEEP_ALIVE_COUNT,
+        count => count.min(MAX_KEEP_ALIVE_COUNT),
+    }
+}
+
+// 生命周期计数至少为保活计数的3倍
+fn revise_lifetime_count(lifetime_count: u32, max_keep_alive_count: u32) -> u32 {
+    lifetime_count.max(max_keep_alive_count * 3)
+}
+
+fn revise_monitoring_parameters(parameters: &MonitoringParameters) -> (f64, u32) {
+    let sampling_interval =
+        if parameters.sampling_interval.is_nan() || parameters.sampling_interval < 0.0 {
+            TICK_INTERVAL as f64
+        } else {
+            parameters.sampling_interval.max(TICK_INTERVAL as f64)
+        };
+    let queue_size = parameters.queue_size.clamp(1, MAX_QUEUE_SIZE);
+    (sampling_interval, queue_size)
+}
+
+fn monitored_item_create_err(status_code: StatusCode) -> MonitoredItemCreateResult {
+    MonitoredItemCreateResult {
+        status_code,
+        monitored_item_id: 0,
+        revised_sampling_interval: 0.0,
+        revised_queue_size: 0,
+        filter_result: ExtensionObject::null(),
+    }
+}
+
+fn monitored_item_modify_err(status_code: StatusCode) -> MonitoredItemModifyResult {
+    MonitoredItemModifyResult {
+        status_code,
+        revised_sampling_interval: 0.0,
+        revised_queue_size: 0,
+        filter_result: ExtensionObject::null(),
+    }
+}
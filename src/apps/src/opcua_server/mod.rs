@@ -0,0 +1,388 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use address_space::{message_value_to_variant, AddressSpace, DATABOARD_FOLDER};
+use async_trait::async_trait;
+use common::error::{HaliaError, HaliaResult};
+use dashmap::DashMap;
+use futures::lock::BiLock;
+use halia_derive::ResourceErr;
+use message::{Message, RuleMessageBatch};
+use sink::Sink;
+use source::Source;
+use tokio::{
+    net::TcpListener,
+    select,
+    sync::{broadcast, mpsc, watch},
+    task::JoinHandle,
+    time,
+};
+use tracing::{debug, warn};
+use types::apps::opcua_server::{OpcuaServerConf, SinkConf, SourceConf};
+use utils::ErrorManager;
+
+use crate::App;
+
+mod address_space;
+mod connection;
+mod sink;
+mod source;
+
+// 客户端写入事件的缓存数量
+const WRITE_EVENT_CHANNEL_SIZE: usize = 256;
+
+#[derive(ResourceErr)]
+pub struct OpcuaServer {
+    conf: Arc<OpcuaServerConf>,
+    err: BiLock<Option<Arc<String>>>,
+    address_space: Arc<RwLock<AddressSpace>>,
+    write_tx: broadcast::Sender<Arc<WriteEvent>>,
+    sources: DashMap<String, Source>,
+    sinks: DashMap<String, Sink>,
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+}
+
+// 客户端对某个目录下变量的一次写入
+pub(crate) struct WriteEvent {
+    folder: String,
+    message: Message,
+}
+
+pub fn new(id: String, conf: serde_json::Value) -> Box<dyn App> {
+    let conf: OpcuaServerConf = serde_json::from_value(conf).unwrap();
+    let conf = Arc::new(conf);
+
+    let (err1, err2) = BiLock::new(None);
+    let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let (write_tx, _) = broadcast::channel(WRITE_EVENT_CHANNEL_SIZE);
+
+    let mut address_space = AddressSpace::new();
+    add_databoard_variables(&mut address_space, &conf);
+    let address_space = Arc::new(RwLock::new(address_space));
+
+    let task_loop = TaskLoop::new(
+        id,
+        conf.clone(),
+        err1,
+        address_space.clone(),
+        write_tx.clone(),
+        stop_signal_rx,
+    );
+    let join_handle = task_loop.start();
+
+    Box::new(OpcuaServer {
+        conf,
+        err: err2,
+        address_space,
+        write_tx,
+        sources: DashMap::new(),
+        sinks: DashMap::new(),
+        stop_signal_tx,
+        join_handle: Some(join_handle),
+    })
+}
+
+fn add_databoard_variables(address_space: &mut AddressSpace, conf: &OpcuaServerConf) {
+    address_space.remove_folder(DATABOARD_FOLDER);
+    if conf.databoard_datas.is_empty() {
+        return;
+    }
+    address_space.add_folder(DATABOARD_FOLDER);
+    for data in conf.databoard_datas.iter() {
+        address_space.add_variable(DATABOARD_FOLDER, &data.name, None);
+    }
+}
+
+struct TaskLoop {
+    conf: Arc<OpcuaServerConf>,
+    address_space: Arc<RwLock<AddressSpace>>,
+    write_tx: broadcast::Sender<Arc<WriteEvent>>,
+    stop_signal_rx: watch::Receiver<()>,
+    error_manager: ErrorManager,
+}
+
+impl TaskLoop {
+    fn new(
+        id: String,
+        conf: Arc<OpcuaServerConf>,
+        err: BiLock<Option<Arc<String>>>,
+        address_space: Arc<RwLock<AddressSpace>>,
+        write_tx: broadcast::Sender<Arc<WriteEvent>>,
+        stop_signal_rx: watch::Receiver<()>,
+    ) -> Self {
+        let error_manager = ErrorManager::new(utils::error_manager::ResourceType::App, id, err);
+        Self {
+            conf,
+            address_space,
+            write_tx,
+            stop_signal_rx,
+            error_manager,
+        }
+    }
+
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            let mut databoard_interval = time::interval(Duration::from_secs(1));
+            loop {
+                let addr = format!("{}:{}", self.conf.host, self.conf.port);
+                let listener = match TcpListener::bind(&addr).await {
+                    Ok(listener) => {
+                        self.error_manager.set_ok().await;
+                        listener
+                    }
+                    Err(e) => {
+                        warn!("opcua server bind {} err:{}", addr, e);
+                        self.error_manager.set_err(Arc::new(e.to_string())).await;
+                        let sleep = time::sleep(Duration::from_secs(10));
+                        tokio::pin!(sleep);
+                        select! {
+                            _ = self.stop_signal_rx.changed() => {
+                                return self;
+                            }
+
+                            _ = &mut sleep => continue,
+                        }
+                    }
+                };
+
+                loop {
+                    select! {
+                        _ = self.stop_signal_rx.changed() => {
+                            return self;
+                        }
+
+                        res = listener.accept() => {
+                            match res {
+                                Ok((stream, peer_addr)) => {
+                                    debug!("opcua client connected: {}", peer_addr);
+                                    connection::start(
+                                        stream,
+                                        self.conf.clone(),
+                                        self.address_space.clone(),
+                                        self.write_tx.clone(),
+                                        self.stop_signal_rx.clone(),
+                                    );
+                                }
+                                Err(e) => warn!("opcua server accept err:{}", e),
+                            }
+                        }
+
+                        _ = databoard_interval.tick() => {
+                            self.refresh_databoard_variables().await;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    async fn refresh_databoard_variables(&self) {
+        let mut values = Vec::with_capacity(self.conf.databoard_datas.len());
+        for data in self.conf.databoard_datas.iter() {
+            match databoard::read_data_runtime(&data.databoard_id, &data.data_id).await {
+                Ok(resp) => {
+                    let value: message::MessageValue = resp.value.into();
+                    values.push((&data.name, message_value_to_variant(&value)));
+                }
+                Err(e) => debug!("read databoard data err:{}", e),
+            }
+        }
+
+        let mut address_space = self.address_space.write().unwrap();
+        for (name, value) in values {
+            address_space.set_value(DATABOARD_FOLDER, name, value);
+        }
+    }
+}
+
+pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: OpcuaServerConf = serde_json::from_value(conf.clone())?;
+    if !conf.path.is_empty() && !conf.path.starts_with('/') {
+        return Err(HaliaError::Common("端点路径必须以/开头！".to_owned()));
+    }
+    for (i, data) in conf.databoard_datas.iter().enumerate() {
+        if conf.databoard_datas[..i]
+            .iter()
+            .any(|d| d.name == data.name)
+        {
+            return Err(HaliaError::Common(format!("变量{}重复！", data.name)));
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::validate_conf(&conf)?;
+    Ok(())
+}
+
+pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SinkConf = serde_json::from_value(conf.clone())?;
+    Sink::validate_conf(&conf)?;
+    Ok(())
+}
+
+impl OpcuaServer {
+    fn check_folder_exists(&self, folder: &str) -> HaliaResult<()> {
+        if self.address_space.read().unwrap().contains_folder(folder) {
+            return Err(HaliaError::Common(format!("目录{}已存在！", folder)));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl App for OpcuaServer {
+    async fn read_app_err(&self) -> Option<Arc<String>> {
+        self.read_err().await
+    }
+
+    async fn read_source_err(&self, source_id: &String) -> HaliaResult<Option<Arc<String>>> {
+        match self.sources.get(source_id) {
+            Some(_) => Ok(None),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn read_sink_err(&self, sink_id: &String) -> HaliaResult<Option<Arc<String>>> {
+        match self.sinks.get(sink_id) {
+            Some(_) => Ok(None),
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+
+    async fn update(
+        &mut self,
+        _old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let new_conf: OpcuaServerConf = serde_json::from_value(new_conf)?;
+
+        // 重新监听，已建立的连接会被关闭
+        self.stop_signal_tx.send(()).unwrap();
+        let mut task_loop = self.join_handle.take().unwrap().await.unwrap();
+
+        self.conf = Arc::new(new_conf);
+        add_databoard_variables(&mut self.address_space.write().unwrap(), &self.conf);
+        task_loop.conf = self.conf.clone();
+        self.join_handle = Some(task_loop.start());
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        for mut source in self.sources.iter_mut() {
+            source.stop().await;
+        }
+        for mut sink in self.sinks.iter_mut() {
+            sink.stop().await;
+        }
+
+        self.stop_signal_tx.send(()).unwrap();
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.await;
+        }
+    }
+
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        let source = Source::new(conf, self.write_tx.subscribe());
+        self.sources.insert(source_id, source);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: String,
+        old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        match self.sources.get_mut(&source_id) {
+            Some(mut source) => {
+                let old_conf: SourceConf = serde_json::from_value(old_conf)?;
+                let new_conf: SourceConf = serde_json::from_value(new_conf)?;
+                source.update_conf(old_conf, new_conf).await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn delete_source(&mut self, source_id: String) -> HaliaResult<()> {
+        match self.sources.remove(&source_id) {
+            Some((_, mut source)) => {
+                source.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
+        let conf: SinkConf = serde_json::from_value(conf)?;
+        self.check_folder_exists(&conf.folder)?;
+        let sink = Sink::new(conf, self.address_space.clone());
+        self.sinks.insert(sink_id, sink);
+        Ok(())
+    }
+
+    async fn update_sink(
+        &mut self,
+        sink_id: String,
+        old_conf: serde_json::Value,
+        new_conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let old_conf: SinkConf = serde_json::from_value(old_conf)?;
+        let new_conf: SinkConf = serde_json::from_value(new_conf)?;
+        if old_conf.folder != new_conf.folder {
+            self.check_folder_exists(&new_conf.folder)?;
+        }
+        match self.sinks.get_mut(&sink_id) {
+            Some(mut sink) => {
+                sink.update_conf(old_conf, new_conf).await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(sink_id)),
+        }
+    }
+
+    async fn delete_sink(&mut self, sink_id: String) -> HaliaResult<()> {
+        match self.sinks.remove(&sink_id) {
+            Some((_, mut sink)) => {
+                sink.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(sink_id)),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<mpsc::UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt).await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn get_sink_txs(
+        &self,
+        sink_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<mpsc::UnboundedSender<RuleMessageBatch>>> {
+        match self.sinks.get(sink_id) {
+            Some(sink) => Ok(sink.get_txs(cnt)),
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::{Arc, RwLock};
+
+use common::error::{HaliaError, HaliaResult};
+use halia_derive::{ResourceStop, SinkTxs};
+use message::RuleMessageBatch;
+use tokio::{
+    select,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
+use types::apps::opcua_server::SinkConf;
+
+use super::address_space::{message_value_to_variant, AddressSpace, DATABOARD_FOLDER};
+
+#[derive(ResourceStop, SinkTxs)]
+pub struct Sink {
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_tx: UnboundedSender<RuleMessageBatch>,
+}
+
+pub struct TaskLoop {
+    sink_conf: SinkConf,
+    stop_signal_rx: watch::Receiver<()>,
+    address_space: Arc<RwLock<AddressSpace>>,
+    mb_rx: UnboundedReceiver<RuleMessageBatch>,
+}
+
+impl Sink {
+    pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
+        if conf.folder.is_empty() {
+            return Err(HaliaError::Common("目录不能为空！".to_owned()));
+        }
+        if conf.folder == DATABOARD_FOLDER {
+            return Err(HaliaError::Common(format!(
+                "目录{}为数据看板保留目录！",
+                DATABOARD_FOLDER
+            )));
+        }
+        for (i, variable) in conf.variables.iter().enumerate() {
+            if variable.name.is_empty() {
+                return Err(HaliaError::Common("变量名称不能为空！".to_owned()));
+            }
+            if conf.variables[..i].iter().any(|v| v.name == variable.name) {
+                return Err(HaliaError::Common(format!("变量{}重复！", variable.name)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn new(sink_conf: SinkConf, address_space: Arc<RwLock<AddressSpace>>) -> Self {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (mb_tx, mb_rx) = unbounded_channel();
+
+        let task_loop = TaskLoop {
+            sink_conf,
+            stop_signal_rx,
+            address_space,
+            mb_rx,
+        };
+        let join_handle = task_loop.start();
+
+        Self {
+            stop_signal_tx,
+            join_handle: Some(join_handle),
+            mb_tx,
+        }
+    }
+
+    pub async fn update_conf(&mut self, _old_conf: SinkConf, new_conf: SinkConf) {
+        let mut task_loop = self.stop().await;
+        task_loop.sink_conf = new_conf;
+        self.join_handle = Some(task_loop.start());
+    }
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        self.add_variables();
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        // 停止后从地址空间中移除
+                        self.address_space
+                            .write()
+                            .unwrap()
+                            .remove_folder(&self.sink_conf.folder);
+                        return self;
+                    }
+
+                    Some(rmb) = self.mb_rx.recv() => {
+                        self.update_variables(rmb);
+                    }
+                }
+            }
+        })
+    }
+
+    fn add_variables(&self) {
+        let mut address_space = self.address_space.write().unwrap();
+        address_space.add_folder(&self.sink_conf.folder);
+        for variable in self.sink_conf.variables.iter() {
+            let write_back = match variable.writable {
+                true => Some(variable.field.clone()),
+                false => None,
+            };
+            address_space.add_variable(&self.sink_conf.folder, &variable.name, write_back);
+        }
+    }
+
+    fn update_variables(&self, rmb: RuleMessageBatch) {
+        let mb = rmb.take_mb();
+        let mut address_space = self.address_space.write().unwrap();
+        // 批次中的消息依次写入，变量保留最后一个值
+        for message in mb.get_messages() {
+            for variable in self.sink_conf.variables.iter() {
+                if let Some(value) = message.get(&variable.field) {
+                    address_space.set_value(
+                        &self.sink_conf.folder,
+                        &variable.name,
+                        message_value_to_variant(value),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use common::error::{HaliaError, HaliaResult};
+use futures::lock::BiLock;
+use halia_derive::{ResourceStop, SourceRxs};
+use message::{MessageBatch, RuleMessageBatch};
+use tokio::{
+    select,
+    sync::{broadcast, mpsc::UnboundedSender, watch},
+    task::JoinHandle,
+};
+use tracing::warn;
+use types::apps::opcua_server::SourceConf;
+
+use super::WriteEvent;
+
+#[derive(ResourceStop, SourceRxs)]
+pub struct Source {
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+}
+
+pub struct TaskLoop {
+    source_conf: SourceConf,
+    stop_signal_rx: watch::Receiver<()>,
+    write_rx: broadcast::Receiver<Arc<WriteEvent>>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+}
+
+impl Source {
+    pub fn validate_conf(conf: &SourceConf) -> HaliaResult<()> {
+        if conf.folder.is_empty() {
+            return Err(HaliaError::Common("目录不能为空！".to_owned()));
+        }
+        Ok(())
+    }
+
+    pub fn new(source_conf: SourceConf, write_rx: broadcast::Receiver<Arc<WriteEvent>>) -> Self {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (mb_txs1, mb_txs2) = BiLock::new(vec![]);
+
+        let task_loop = TaskLoop {
+            source_conf,
+            stop_signal_rx,
+            write_rx,
+            mb_txs: mb_txs1,
+        };
+        let join_handle = task_loop.start();
+
+        Self {
+            stop_signal_tx,
+            join_handle: Some(join_handle),
+            mb_txs: mb_txs2,
+        }
+    }
+
+    pub async fn update_conf(&mut self, _old_conf: SourceConf, new_conf: SourceConf) {
+        let mut task_loop = self.stop().await;
+        task_loop.source_conf = new_conf;
+        self.join_handle = Some(task_loop.start());
+    }
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    event = self.write_rx.recv() => {
+                        match event {
+                            Ok(event) => self.handle_write_event(event).await,
+                            Err(broadcast::error::RecvError::Lagged(n)) => {
+                                warn!("opcua server source lagged {} write events", n);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => return self,
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    async fn handle_write_event(&mut self, event: Arc<WriteEvent>) {
+        if event.folder != self.source_conf.folder {
+            return;
+        }
+
+        let mut mb_txs = self.mb_txs.lock().await;
+        if mb_txs.is_empty() {
+            return;
+        }
+
+        let mut mb = MessageBatch::default();
+        mb.push_message(event.message.clone());
+        if mb_txs.len() == 1 {
+            let rmb = RuleMessageBatch::Owned(mb);
+            if mb_txs[0].send(rmb).is_err() {
+                mb_txs.remove(0);
+            }
+        } else {
+            let rmb = RuleMessageBatch::Arc(Arc::new(mb));
+            mb_txs.retain(|tx| tx.send(rmb.clone()).is_ok());
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{fmt::Display, future::Future, sync::Arc, time::Duration};
+
+use message::{MessageBatch, RuleMessageBatch};
+use tokio::{
+    select,
+    sync::{mpsc::UnboundedSender, watch},
+    time,
+};
+use tracing::warn;
+use utils::ErrorManager;
+
+// 服务绑定失败或异常退出后等待重启的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+// 内置服务器(coap、http)任务的公共部分，负责绑定地址、记录错误及等待重启
+pub(crate) struct ServerTask {
+    name: &'static str,
+    pub stop_signal_rx: watch::Receiver<()>,
+    error_manager: ErrorManager,
+}
+
+impl ServerTask {
+    pub fn new(
+        name: &'static str,
+        stop_signal_rx: watch::Receiver<()>,
+        error_manager: ErrorManager,
+    ) -> Self {
+        Self {
+            name,
+            stop_signal_rx,
+            error_manager,
+        }
+    }
+
+    // 绑定失败时等待重试，收到停止信号时返回None
+    pub async fn bind<T, E, F>(&mut self, addr: &str, bind: impl Fn(String) -> F) -> Option<T>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        loop {
+            match bind(addr.to_owned()).await {
+                Ok(server) => {
+                    self.error_manager.set_ok().await;
+                    return Some(server);
+                }
+                Err(e) => {
+                    warn!("{} server bind {} err:{}", self.name, addr, e);
+                    self.error_manager.set_err(Arc::new(e.to_string())).await;
+                    if self.wait_retry().await {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn set_err(&mut self, e: impl Display) {
+        warn!("{} server err:{}", self.name, e);
+        self.error_manager.set_err(Arc::new(e.to_string())).await;
+    }
+
+    // 返回true表示收到停止信号
+    pub async fn wait_retry(&mut self) -> bool {
+        let sleep = time::sleep(RETRY_INTERVAL);
+        tokio::pin!(sleep);
+        select! {
+            _ = self.stop_signal_rx.changed() => true,
+            _ = &mut sleep => false,
+        }
+    }
+}
+
+// 请求路径不区分首尾的/
+pub(crate) fn normalize_path(path: &str) -> String {
+    path.trim_matches('/').to_owned()
+}
+
+// 将推送至源的数据发送给引用该源的规则，发送失败的规则被移除
+pub(crate) fn send_mb(mb_txs: &mut Vec<UnboundedSender<RuleMessageBatch>>, mb: MessageBatch) {
+    match mb_txs.len() {
+        0 => {}
+        1 => {
+            let mb = RuleMessageBatch::Owned(mb);
+            if let Err(e) = mb_txs[0].send(mb) {
+                warn!("send err :{}", e);
+                mb_txs.remove(0);
+            }
+        }
+        _ => {
+            let mb = RuleMessageBatch::Arc(Arc::new(mb));
+            mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+        }
+    }
+}
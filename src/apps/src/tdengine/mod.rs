@@ -7,10 +7,17 @@ use futures::lock::BiLock;
 use halia_derive::ResourceErr;
 use message::RuleMessageBatch;
 use sink::Sink;
-use taos::{AsyncQueryable, AsyncTBuilder, Taos, TaosBuilder};
-use tokio::sync::mpsc;
-use tracing::warn;
+use taos::{AsyncTBuilder, Taos, TaosBuilder};
+use tokio::{
+    select,
+    sync::{
+        mpsc::{self, unbounded_channel, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
 use types::apps::tdengine::{SinkConf, TDengineConf};
+use utils::ErrorManager;
 
 use crate::App;
 
@@ -18,10 +25,12 @@ mod sink;
 
 #[derive(ResourceErr)]
 pub struct TDengine {
-    _id: String,
+    err: BiLock<Option<Arc<String>>>,
     conf: Arc<TDengineConf>,
     sinks: DashMap<String, Sink>,
-    err: BiLock<Option<Arc<String>>>,
+    app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
 }
 
 pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
@@ -37,35 +46,98 @@ pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     Ok(())
 }
 
-pub fn validate_sink_conf(_conf: &serde_json::Value) -> HaliaResult<()> {
+pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SinkConf = serde_json::from_value(conf.clone())?;
+    Sink::validate_conf(&conf)?;
     Ok(())
 }
 
 pub fn new(id: String, conf: serde_json::Value) -> Box<dyn App> {
     let conf: TDengineConf = serde_json::from_value(conf).unwrap();
 
-    let (app_err1, _app_err2) = BiLock::new(None);
+    let (app_err1, app_err2) = BiLock::new(None);
+    let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let (app_err_tx, app_err_rx) = unbounded_channel();
+    let task_loop = TaskLoop::new(id, app_err1, app_err_rx, stop_signal_rx);
+    let join_handle = task_loop.start();
 
     Box::new(TDengine {
-        _id: id,
+        err: app_err2,
         conf: Arc::new(conf),
         sinks: DashMap::new(),
-        err: app_err1,
+        app_err_tx,
+        stop_signal_tx,
+        join_handle: Some(join_handle),
     })
 }
 
+struct TaskLoop {
+    error_manager: ErrorManager,
+    app_err_rx: mpsc::UnboundedReceiver<Option<Arc<String>>>,
+    stop_signal_rx: watch::Receiver<()>,
+}
+
+impl TaskLoop {
+    fn new(
+        id: String,
+        app_err: BiLock<Option<Arc<String>>>,
+        app_err_rx: mpsc::UnboundedReceiver<Option<Arc<String>>>,
+        stop_signal_rx: watch::Receiver<()>,
+    ) -> Self {
+        let error_manager = ErrorManager::new(utils::error_manager::ResourceType::App, id, app_err);
+        Self {
+            error_manager,
+            app_err_rx,
+            stop_signal_rx,
+        }
+    }
+
+    fn start(mut self) -> JoinHandle<TaskLoop> {
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+                    Some(err) = self.app_err_rx.recv() => {
+                        self.handle_err(err).await;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn handle_err(&mut self, err: Option<Arc<String>>) {
+        match err {
+            Some(err) => {
+                self.error_manager.set_err(err).await;
+            }
+            None => {
+                self.error_manager.set_ok().await;
+            }
+        }
+    }
+}
+
 #[async_trait]
 impl App for TDengine {
     async fn read_app_err(&self) -> Option<Arc<String>> {
         self.read_err().await
     }
 
+    async fn read_sink_err(&self, sink_id: &String) -> HaliaResult<Option<Arc<String>>> {
+        match self.sinks.get(sink_id) {
+            Some(sink) => Ok(sink.read_err().await),
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+
     async fn update(
         &mut self,
         _old_conf: serde_json::Value,
         new_conf: serde_json::Value,
     ) -> HaliaResult<()> {
-        let new_conf: Arc<TDengineConf> = Arc::new(serde_json::from_value(new_conf.clone())?);
+        let new_conf: Arc<TDengineConf> = Arc::new(serde_json::from_value(new_conf)?);
         for mut sink in self.sinks.iter_mut() {
             sink.update_tdengine_conf(new_conf.clone()).await;
         }
@@ -77,11 +149,20 @@ impl App for TDengine {
         for mut sink in self.sinks.iter_mut() {
             sink.stop().await;
         }
+        let _ = self.stop_signal_tx.send(());
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.await;
+        }
     }
 
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
-        let conf: SinkConf = serde_json::from_value(conf.clone())?;
-        let sink = Sink::new(conf, self.conf.clone()).await;
+        let conf: SinkConf = serde_json::from_value(conf)?;
+        let sink = Sink::new(
+            sink_id.clone(),
+            conf,
+            self.conf.clone(),
+            self.app_err_tx.clone(),
+        )?;
         self.sinks.insert(sink_id, sink);
         Ok(())
     }
@@ -89,16 +170,12 @@ impl App for TDengine {
     async fn update_sink(
         &mut self,
         sink_id: String,
-        old_conf: serde_json::Value,
+        _old_conf: serde_json::Value,
         new_conf: serde_json::Value,
     ) -> HaliaResult<()> {
+        let new_conf: SinkConf = serde_json::from_value(new_conf)?;
         match self.sinks.get_mut(&sink_id) {
-            Some(mut sink) => {
-                let old_conf: SinkConf = serde_json::from_value(old_conf)?;
-                let new_conf: SinkConf = serde_json::from_value(new_conf)?;
-                sink.update_conf(old_conf, new_conf).await;
-                Ok(())
-            }
+            Some(mut sink) => sink.update_conf(&sink_id, new_conf).await,
             None => Err(HaliaError::NotFound(sink_id)),
         }
     }
@@ -125,26 +202,22 @@ impl App for TDengine {
     }
 }
 
-async fn new_tdengine_client(td_engine_conf: &Arc<TDengineConf>, sink_conf: &SinkConf) -> Taos {
-    let dsn = match td_engine_conf.auth_method {
+async fn new_tdengine_client(tdengine_conf: &TDengineConf, db: &str) -> taos::RawResult<Taos> {
+    let dsn = match tdengine_conf.auth_method {
         types::apps::tdengine::TDengineAuthMethod::None => {
-            format!("ws://{}:{}", td_engine_conf.host, td_engine_conf.port)
+            format!("ws://{}:{}/{}", tdengine_conf.host, tdengine_conf.port, db)
         }
         types::apps::tdengine::TDengineAuthMethod::Password => {
-            let auth_password = td_engine_conf.auth_password.as_ref().unwrap();
+            let auth_password = tdengine_conf.auth_password.as_ref().unwrap();
             format!(
-                "ws://{}:{}@{}:{}",
+                "ws://{}:{}@{}:{}/{}",
                 auth_password.username,
                 auth_password.password,
-                td_engine_conf.host,
-                td_engine_conf.port
+                tdengine_conf.host,
+                tdengine_conf.port,
+                db
             )
         }
     };
-    let taos = TaosBuilder::from_dsn(dsn).unwrap().build().await.unwrap();
-    if let Err(e) = taos.exec(format!("USE `{}`", sink_conf.db)).await {
-        warn!("{}", e);
-    }
-
-    taos
+    TaosBuilder::from_dsn(dsn)?.build().await
 }
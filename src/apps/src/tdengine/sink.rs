@@ -1,10 +1,17 @@
 use std::sync::Arc;
 
-use chrono::Utc;
-use common::get_dynamic_value_from_json;
-use tracing::warn;
-use message::RuleMessageBatch;
-use taos::{AsyncQueryable, Taos};
+use common::{
+    error::{HaliaError, HaliaResult},
+    get_dynamic_value_from_json,
+    sink_message_retain::{self, SinkMessageRetain},
+};
+use futures::lock::BiLock;
+use halia_derive::{ResourceErr, ResourceStop, SinkTxs};
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
+use taos::{
+    taos_query::common::raw::{SchemalessPrecision, SchemalessProtocol, SmlDataBuilder},
+    AsyncBindable, AsyncQueryable, ColumnView, Stmt, Taos, Ty, Value,
+};
 use tokio::{
     select,
     sync::{
@@ -12,133 +19,717 @@ use tokio::{
         watch,
     },
     task::JoinHandle,
+    time,
 };
-use types::apps::tdengine::{SinkConf, TDengineConf};
+use tracing::warn;
+use types::apps::tdengine::{DataType, IngestMode, SinkConf, TDengineConf};
+use utils::ErrorManager;
+
+use crate::mqtt_client_template::{group_messages, Template};
 
 use super::new_tdengine_client;
 
+// 时间戳列名
+const TS_COLUMN: &str = "ts";
+
+#[derive(ResourceErr, ResourceStop, SinkTxs)]
 pub struct Sink {
     stop_signal_tx: watch::Sender<()>,
-    join_handle: Option<JoinHandle<JoinHandleData>>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    err: BiLock<Option<Arc<String>>>,
     pub mb_tx: UnboundedSender<RuleMessageBatch>,
 }
 
-pub struct JoinHandleData {
-    conf: SinkConf,
-    taos: Taos,
-    stop_signal_rx: watch::Receiver<()>,
-    mb_rx: UnboundedReceiver<RuleMessageBatch>,
-}
-
 impl Sink {
-    pub async fn new(conf: SinkConf, tdengine_conf: Arc<TDengineConf>) -> Self {
+    pub fn validate_conf(conf: &SinkConf) -> HaliaResult<()> {
+        if conf.db.is_empty() {
+            return Err(HaliaError::Common("数据库不能为空！".to_owned()));
+        }
+        if conf.stable.is_empty() {
+            return Err(HaliaError::Common("超级表不能为空！".to_owned()));
+        }
+        if conf.columns.is_empty() {
+            return Err(HaliaError::Common("columns不能为空！".to_owned()));
+        }
+        if conf.mode == IngestMode::Stmt {
+            if conf.table.is_empty() {
+                return Err(HaliaError::Common("子表名不能为空！".to_owned()));
+            }
+            // 超级表至少需要一个标签
+            if conf.tags.is_empty() {
+                return Err(HaliaError::Common("tags不能为空！".to_owned()));
+            }
+        }
+        for column in conf.columns.iter().chain(conf.tags.iter()) {
+            if column.name.is_empty() || column.name == TS_COLUMN {
+                return Err(HaliaError::Common(format!("列名{}不合法！", column.name)));
+            }
+            if let DataType::Varchar(0) | DataType::Nchar(0) = column.typ {
+                return Err(HaliaError::Common(format!(
+                    "列{}的长度必须大于0！",
+                    column.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn new(
+        sink_id: String,
+        sink_conf: SinkConf,
+        tdengine_conf: Arc<TDengineConf>,
+        app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    ) -> HaliaResult<Self> {
         let (stop_signal_tx, stop_signal_rx) = watch::channel(());
         let (mb_tx, mb_rx) = unbounded_channel();
-        let taos = new_tdengine_client(&tdengine_conf, &conf).await;
-        let join_handle_data = JoinHandleData {
-            conf,
-            taos,
+        let (err1, err2) = BiLock::new(None);
+
+        let task_loop = TaskLoop::new(
+            sink_id,
+            sink_conf,
+            err1,
+            tdengine_conf,
+            app_err_tx,
             stop_signal_rx,
             mb_rx,
-        };
-
-        let join_handle = Self::event_loop(join_handle_data);
+        )?;
+        let join_handle = task_loop.start();
 
-        Self {
+        Ok(Self {
             stop_signal_tx,
             join_handle: Some(join_handle),
+            err: err2,
             mb_tx,
-        }
+        })
     }
 
-    fn event_loop(mut join_handle_data: JoinHandleData) -> JoinHandle<JoinHandleData> {
+    pub async fn update_conf(&mut self, sink_id: &str, sink_conf: SinkConf) -> HaliaResult<()> {
+        let mut task_loop = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut task_loop.message_retainer,
+            &task_loop.sink_conf.message_retain,
+            &sink_conf.message_retain,
+            sink_id,
+        );
+        task_loop.table = Template::new(&sink_conf.table);
+        task_loop.sink_conf = sink_conf;
+        task_loop.close();
+        self.join_handle = Some(task_loop.start());
+        res
+    }
+
+    pub async fn update_tdengine_conf(&mut self, tdengine_conf: Arc<TDengineConf>) {
+        let mut task_loop = self.stop().await;
+        task_loop.tdengine_conf = tdengine_conf;
+        task_loop.close();
+        self.join_handle = Some(task_loop.start());
+    }
+}
+
+pub struct TaskLoop {
+    sink_conf: SinkConf,
+    tdengine_conf: Arc<TDengineConf>,
+    table: Template,
+    // 连接在首次写入时建立，连接断开后置空，下次写入时重连
+    taos: Option<Taos>,
+    stmt: Option<Stmt>,
+    app_err_tx: UnboundedSender<Option<Arc<String>>>,
+    message_retainer: Box<dyn SinkMessageRetain>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_rx: UnboundedReceiver<RuleMessageBatch>,
+    error_manager: ErrorManager,
+}
+
+impl TaskLoop {
+    fn new(
+        sink_id: String,
+        sink_conf: SinkConf,
+        sink_err: BiLock<Option<Arc<String>>>,
+        tdengine_conf: Arc<TDengineConf>,
+        app_err_tx: UnboundedSender<Option<Arc<String>>>,
+        stop_signal_rx: watch::Receiver<()>,
+        mb_rx: UnboundedReceiver<RuleMessageBatch>,
+    ) -> HaliaResult<Self> {
+        let message_retainer = sink_message_retain::new(&sink_conf.message_retain, &sink_id)?;
+        let error_manager = ErrorManager::new(
+            utils::error_manager::ResourceType::AppSink,
+            sink_id,
+            sink_err,
+        );
+        Ok(Self {
+            table: Template::new(&sink_conf.table),
+            sink_conf,
+            tdengine_conf,
+            taos: None,
+            stmt: None,
+            app_err_tx,
+            message_retainer,
+            stop_signal_rx,
+            mb_rx,
+            error_manager,
+        })
+    }
+
+    fn start(mut self) -> JoinHandle<Self> {
+        let mut replay_interval = time::interval(sink_message_retain::REPLAY_INTERVAL);
+
         tokio::spawn(async move {
             loop {
                 select! {
-                    _ = join_handle_data.stop_signal_rx.changed() => {
-                        return join_handle_data;
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    Some(rmb) = self.mb_rx.recv() => {
+                        self.handle_data(rmb.take_mb()).await;
                     }
-                    Some(mb) = join_handle_data.mb_rx.recv() => {
-                        Self::handle_message_batch(&join_handle_data.conf, &join_handle_data.taos, mb).await;
+
+                    _ = replay_interval.tick() => {
+                        self.replay_retained_mbs().await;
                     }
                 }
             }
         })
     }
 
-    // INSERT INTO d1001 VALUES (1538548685000, 10.3, 219, 0.31);
-    async fn handle_message_batch(conf: &SinkConf, taos: &Taos, rmb: RuleMessageBatch) {
-        let mut mb = rmb.take_mb();
-        let mut values = vec![];
-        let msg = mb.take_one_message().unwrap();
-        for value in conf.values.iter() {
-            match get_dynamic_value_from_json(value) {
-                common::DynamicValue::Const(value) => {
-                    match value {
-                        serde_json::Value::Bool(b) => values.push(b.to_string()),
-                        serde_json::Value::Number(number) => {
-                            values.push(number.to_string());
-                        }
-                        serde_json::Value::String(s) => values.push(s),
-                        _ => {}
-                    }
-                    // values.push(value),
+    fn close(&mut self) {
+        self.stmt = None;
+        self.taos = None;
+    }
+
+    async fn handle_data(&mut self, mb: MessageBatch) {
+        // 仍有待重发的消息时先保留，保证消息的写入顺序
+        if !self.message_retainer.is_empty() {
+            self.message_retainer.push(mb);
+            self.replay_retained_mbs().await;
+            return;
+        }
+
+        if !self.send_mb(&mb).await {
+            self.message_retainer.push(mb);
+        }
+    }
+
+    async fn replay_retained_mbs(&mut self) {
+        while let Some(mb) = self.message_retainer.peek() {
+            if !self.send_mb(&mb).await {
+                break;
+            }
+            self.message_retainer.pop();
+        }
+    }
+
+    // 无法连接时返回false，由调用方保留消息等待重发
+    async fn send_mb(&mut self, mb: &MessageBatch) -> bool {
+        if let Err(e) = self.connect().await {
+            self.close();
+            self.set_conn_err(e.to_string()).await;
+            return false;
+        }
+
+        let res = match self.sink_conf.mode {
+            IngestMode::Stmt => self.write_stmt(mb).await,
+            IngestMode::Schemaless => self.write_schemaless(mb).await,
+        };
+        match res {
+            Ok(_) => {
+                let status_changed = self.error_manager.set_ok().await;
+                if status_changed {
+                    let _ = self.app_err_tx.send(None);
                 }
-                common::DynamicValue::Field(field) => match msg.get(&field) {
-                    Some(v) => match v {
-                        message::MessageValue::Boolean(b) => values.push(b.to_string()),
-                        message::MessageValue::Int64(i) => values.push(i.to_string()),
-                        message::MessageValue::Float64(f) => values.push(f.to_string()),
-                        message::MessageValue::String(s) => {
-                            values.push(format!("'${}'", s.clone()))
-                        }
-                        _ => {
-                            warn!("field {} type not supported", field);
-                        }
-                    },
-                    None => {
-                        warn!("field {} not found in message", field);
-                        continue;
-                    }
-                },
+                true
             }
+            Err(e) if is_conn_err(&e) => {
+                self.close();
+                self.set_conn_err(e.to_string()).await;
+                false
+            }
+            // 已连接但写入失败，重发无意义，直接丢弃，stmt需要重新准备
+            Err(e) => {
+                warn!("Failed to write to tdengine: {}", e);
+                self.close();
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+                true
+            }
+        }
+    }
+
+    async fn set_conn_err(&mut self, err: String) {
+        let err = Arc::new(err);
+        let status_changed = self.error_manager.set_err(err.clone()).await;
+        if status_changed {
+            let _ = self.app_err_tx.send(Some(err));
+        }
+    }
+
+    async fn connect(&mut self) -> taos::RawResult<()> {
+        if self.taos.is_some() {
+            return Ok(());
         }
-        let values = values
+
+        let taos = new_tdengine_client(&self.tdengine_conf, &self.sink_conf.db).await?;
+        if self.sink_conf.mode == IngestMode::Stmt {
+            taos.exec(create_stable_sql(&self.sink_conf)).await?;
+            let mut stmt = Stmt::init(&taos).await?;
+            stmt.prepare(&insert_sql(&self.sink_conf)).await?;
+            self.stmt = Some(stmt);
+        }
+        self.taos = Some(taos);
+        Ok(())
+    }
+
+    // 按子表分组，每个子表绑定一次标签，超级表下不存在的子表自动创建
+    async fn write_stmt(&mut self, mb: &MessageBatch) -> taos::RawResult<()> {
+        let groups = group_messages(mb.clone(), |msg| self.table.render(msg));
+        if groups.is_empty() {
+            return Ok(());
+        }
+
+        let stmt = self.stmt.as_mut().unwrap();
+        for (table, group_mb) in groups {
+            let msgs = group_mb.get_messages();
+            let tags: Vec<Value> = self
+                .sink_conf
+                .tags
+                .iter()
+                .map(|tag| to_taos_value(&tag.typ, get_value(&msgs[0], &tag.value)))
+                .collect();
+            stmt.set_tbname_tags(&table, &tags).await?;
+
+            let mut params = Vec::with_capacity(self.sink_conf.columns.len() + 1);
+            params.push(ColumnView::from_millis_timestamp(
+                msgs.iter()
+                    .map(|msg| get_timestamp(msg, &self.sink_conf.timestamp_field))
+                    .collect::<Vec<_>>(),
+            ));
+            for column in &self.sink_conf.columns {
+                let values = msgs
+                    .iter()
+                    .map(|msg| to_taos_value(&column.typ, get_value(msg, &column.value)))
+                    .collect();
+                params.push(to_column_view(&column.typ, values));
+            }
+            stmt.bind(&params).await?;
+            stmt.add_batch().await?;
+        }
+        stmt.execute().await?;
+        Ok(())
+    }
+
+    async fn write_schemaless(&mut self, mb: &MessageBatch) -> taos::RawResult<()> {
+        let lines: Vec<String> = mb
+            .get_messages()
             .iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
-        let ts = Utc::now().timestamp_millis();
-        let sql = format!("INSERT INTO {} VALUES ({}, {});", conf.table, ts, values);
-        if let Err(e) = taos.exec(&sql).await {
-            warn!("{}", e);
+            .filter_map(|msg| build_line(&self.sink_conf, msg))
+            .collect();
+        if lines.is_empty() {
+            return Ok(());
         }
+
+        let data = SmlDataBuilder::default()
+            .protocol(SchemalessProtocol::Line)
+            .precision(SchemalessPrecision::Millisecond)
+            .data(lines)
+            .build()?;
+        self.taos.as_ref().unwrap().put(&data).await
     }
+}
 
-    pub async fn stop(&mut self) -> JoinHandleData {
-        self.stop_signal_tx.send(()).unwrap();
-        self.join_handle.take().unwrap().await.unwrap()
+// websocket连接相关的错误码
+fn is_conn_err(e: &taos::Error) -> bool {
+    (0xE000..=0xE0FF).contains(&i32::from(e.code()))
+}
+
+fn create_stable_sql(conf: &SinkConf) -> String {
+    let columns: Vec<String> = conf
+        .columns
+        .iter()
+        .map(|column| format!("`{}` {}", column.name, sql_type(&column.typ)))
+        .collect();
+    let tags: Vec<String> = conf
+        .tags
+        .iter()
+        .map(|tag| format!("`{}` {}", tag.name, sql_type(&tag.typ)))
+        .collect();
+    format!(
+        "CREATE STABLE IF NOT EXISTS `{}` (`{}` TIMESTAMP, {}) TAGS ({})",
+        conf.stable,
+        TS_COLUMN,
+        columns.join(", "),
+        tags.join(", ")
+    )
+}
+
+fn insert_sql(conf: &SinkConf) -> String {
+    let columns: Vec<String> = conf
+        .columns
+        .iter()
+        .map(|column| format!("`{}`", column.name))
+        .collect();
+    format!(
+        "INSERT INTO ? USING `{}` TAGS ({}) (`{}`, {}) VALUES (?, {})",
+        conf.stable,
+        vec!["?"; conf.tags.len()].join(", "),
+        TS_COLUMN,
+        columns.join(", "),
+        vec!["?"; conf.columns.len()].join(", ")
+    )
+}
+
+fn sql_type(typ: &DataType) -> String {
+    match typ {
+        DataType::Bool => "BOOL".to_owned(),
+        DataType::TinyInt => "TINYINT".to_owned(),
+        DataType::SmallInt => "SMALLINT".to_owned(),
+        DataType::Int => "INT".to_owned(),
+        DataType::BigInt => "BIGINT".to_owned(),
+        DataType::UtinyInt => "TINYINT UNSIGNED".to_owned(),
+        DataType::UsmallInt => "SMALLINT UNSIGNED".to_owned(),
+        DataType::Uint => "INT UNSIGNED".to_owned(),
+        DataType::UbigInt => "BIGINT UNSIGNED".to_owned(),
+        DataType::Float => "FLOAT".to_owned(),
+        DataType::Double => "DOUBLE".to_owned(),
+        DataType::Varchar(len) => format!("VARCHAR({})", len),
+        DataType::Nchar(len) => format!("NCHAR({})", len),
     }
+}
 
-    pub async fn update_conf(&mut self, _old_conf: SinkConf, new_conf: SinkConf) {
-        let mut join_handle_data = self.stop().await;
-        join_handle_data.conf = new_conf;
-        let join_handle = Self::event_loop(join_handle_data);
-        self.join_handle = Some(join_handle);
+fn taos_ty(typ: &DataType) -> Ty {
+    match typ {
+        DataType::Bool => Ty::Bool,
+        DataType::TinyInt => Ty::TinyInt,
+        DataType::SmallInt => Ty::SmallInt,
+        DataType::Int => Ty::Int,
+        DataType::BigInt => Ty::BigInt,
+        DataType::UtinyInt => Ty::UTinyInt,
+        DataType::UsmallInt => Ty::USmallInt,
+        DataType::Uint => Ty::UInt,
+        DataType::UbigInt => Ty::UBigInt,
+        DataType::Float => Ty::Float,
+        DataType::Double => Ty::Double,
+        DataType::Varchar(_) => Ty::VarChar,
+        DataType::Nchar(_) => Ty::NChar,
     }
+}
 
-    pub async fn update_tdengine_conf(&mut self, tdengine_conf: Arc<TDengineConf>) {
-        let mut join_handle_data = self.stop().await;
-        join_handle_data.taos = new_tdengine_client(&tdengine_conf, &join_handle_data.conf).await;
-        let join_handle = Self::event_loop(join_handle_data);
-        self.join_handle = Some(join_handle);
+// 值缺失或与列类型不匹配时为NULL
+fn to_taos_value(typ: &DataType, value: serde_json::Value) -> Value {
+    let taos_value = match typ {
+        DataType::Bool => value.as_bool().map(Value::Bool),
+        DataType::TinyInt => value
+            .as_i64()
+            .and_then(|i| i.try_into().ok())
+            .map(Value::TinyInt),
+        DataType::SmallInt => value
+            .as_i64()
+            .and_then(|i| i.try_into().ok())
+            .map(Value::SmallInt),
+        DataType::Int => value
+            .as_i64()
+            .and_then(|i| i.try_into().ok())
+            .map(Value::Int),
+        DataType::BigInt => value.as_i64().map(Value::BigInt),
+        DataType::UtinyInt => value
+            .as_u64()
+            .and_then(|u| u.try_into().ok())
+            .map(Value::UTinyInt),
+        DataType::UsmallInt => value
+            .as_u64()
+            .and_then(|u| u.try_into().ok())
+            .map(Value::USmallInt),
+        DataType::Uint => value
+            .as_u64()
+            .and_then(|u| u.try_into().ok())
+            .map(Value::UInt),
+        DataType::UbigInt => value.as_u64().map(Value::UBigInt),
+        DataType::Float => value.as_f64().map(|f| Value::Float(f as f32)),
+        DataType::Double => value.as_f64().map(Value::Double),
+        DataType::Varchar(_) => to_string(value).map(Value::VarChar),
+        DataType::Nchar(_) => to_string(value).map(Value::NChar),
+    };
+    taos_value.unwrap_or(Value::Null(taos_ty(typ)))
+}
+
+macro_rules! column_view {
+    ($values:expr, $from:ident, $variant:ident, $ty:ty) => {
+        ColumnView::$from(
+            $values
+                .into_iter()
+                .map(|value| match value {
+                    Value::$variant(v) => Some(v),
+                    _ => None,
+                })
+                .collect::<Vec<Option<$ty>>>(),
+        )
+    };
+}
+
+fn to_column_view(typ: &DataType, values: Vec<Value>) -> ColumnView {
+    match typ {
+        DataType::Bool => column_view!(values, from_bools, Bool, bool),
+        DataType::TinyInt => column_view!(values, from_tiny_ints, TinyInt, i8),
+        DataType::SmallInt => column_view!(values, from_small_ints, SmallInt, i16),
+        DataType::Int => column_view!(values, from_ints, Int, i32),
+        DataType::BigInt => column_view!(values, from_big_ints, BigInt, i64),
+        DataType::UtinyInt => column_view!(values, from_unsigned_tiny_ints, UTinyInt, u8),
+        DataType::UsmallInt => column_view!(values, from_unsigned_small_ints, USmallInt, u16),
+        DataType::Uint => column_view!(values, from_unsigned_ints, UInt, u32),
+        DataType::UbigInt => column_view!(values, from_unsigned_big_ints, UBigInt, u64),
+        DataType::Float => column_view!(values, from_floats, Float, f32),
+        DataType::Double => column_view!(values, from_doubles, Double, f64),
+        DataType::Varchar(_) => ColumnView::from_varchar::<String, _, _, _>(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::VarChar(s) => Some(s),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        DataType::Nchar(_) => ColumnView::from_nchar::<String, _, _, _>(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::NChar(s) => Some(s),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
+
+// 行协议: stable,tag1=v1 col1=1i32,col2="s" 1626006833639
+// schemaless模式下标签均按NCHAR写入，没有可写入列的消息被丢弃
+fn build_line(conf: &SinkConf, msg: &Message) -> Option<String> {
+    let fields: Vec<String> = conf
+        .columns
+        .iter()
+        .filter_map(|column| {
+            let value = to_taos_value(&column.typ, get_value(msg, &column.value));
+            format_line_value(&value).map(|value| format!("{}={}", escape_key(&column.name), value))
+        })
+        .collect();
+    if fields.is_empty() {
+        warn!("消息中没有可写入的列，已丢弃");
+        return None;
+    }
+
+    let mut line = conf.stable.replace(',', "\\,").replace(' ', "\\ ");
+    for tag in &conf.tags {
+        if let Some(value) = to_string(get_value(msg, &tag.value)) {
+            line.push_str(&format!(
+                ",{}={}",
+                escape_key(&tag.name),
+                escape_key(&value)
+            ));
+        }
+    }
+    line.push_str(&format!(
+        " {} {}",
+        fields.join(","),
+        get_timestamp(msg, &conf.timestamp_field)
+    ));
+    Some(line)
+}
+
+fn format_line_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Bool(b) => Some(b.to_string()),
+        Value::TinyInt(v) => Some(format!("{}i8", v)),
+        Value::SmallInt(v) => Some(format!("{}i16", v)),
+        Value::Int(v) => Some(format!("{}i32", v)),
+        Value::BigInt(v) => Some(format!("{}i64", v)),
+        Value::UTinyInt(v) => Some(format!("{}u8", v)),
+        Value::USmallInt(v) => Some(format!("{}u16", v)),
+        Value::UInt(v) => Some(format!("{}u32", v)),
+        Value::UBigInt(v) => Some(format!("{}u64", v)),
+        Value::Float(v) => Some(format!("{}f32", v)),
+        Value::Double(v) => Some(format!("{}f64", v)),
+        Value::VarChar(s) => Some(format!("\"{}\"", escape_string(s))),
+        Value::NChar(s) => Some(format!("L\"{}\"", escape_string(s))),
+        _ => None,
+    }
+}
+
+fn escape_key(s: &str) -> String {
+    s.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn escape_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn to_string(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s),
+        value => Some(value.to_string()),
+    }
+}
+
+// 依次使用时间戳字段、消息元数据中的timestamp及当前时间，单位毫秒
+fn get_timestamp(msg: &Message, timestamp_field: &Option<String>) -> i64 {
+    timestamp_field
+        .as_ref()
+        .and_then(|field| msg.get(field))
+        .and_then(get_timestamp_ms)
+        .or_else(|| msg.get_metadata("timestamp").and_then(get_timestamp_ms))
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis())
+}
+
+fn get_timestamp_ms(value: &MessageValue) -> Option<i64> {
+    match value {
+        MessageValue::Int64(ms) => Some(*ms),
+        MessageValue::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|ts| ts.timestamp_millis()),
+        _ => None,
     }
+}
+
+fn get_value(msg: &Message, value: &serde_json::Value) -> serde_json::Value {
+    match get_dynamic_value_from_json(value) {
+        common::DynamicValue::Const(value) => value,
+        common::DynamicValue::Field(s) => match msg.get(&s) {
+            Some(value) => value.clone().into(),
+            None => serde_json::Value::Null,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use types::{
+        apps::tdengine::{Column, TDengineAuthMethod},
+        MessageRetain, MessageRetainType,
+    };
 
-    pub fn get_txs(&self, cnt: usize) -> Vec<UnboundedSender<RuleMessageBatch>> {
-        let mut txs = vec![];
-        for _ in 0..cnt {
-            txs.push(self.mb_tx.clone());
+    use super::*;
+
+    fn new_conf(mode: IngestMode) -> SinkConf {
+        SinkConf {
+            db: "power".to_owned(),
+            mode,
+            stable: "meters".to_owned(),
+            table: "d_${device}".to_owned(),
+            tags: vec![Column {
+                name: "location".to_owned(),
+                typ: DataType::Nchar(64),
+                value: serde_json::json!("${location}"),
+            }],
+            columns: vec![
+                Column {
+                    name: "current".to_owned(),
+                    typ: DataType::Float,
+                    value: serde_json::json!("${current}"),
+                },
+                Column {
+                    name: "voltage".to_owned(),
+                    typ: DataType::Int,
+                    value: serde_json::json!("${voltage}"),
+                },
+                Column {
+                    name: "note".to_owned(),
+                    typ: DataType::Varchar(32),
+                    value: serde_json::json!("${note}"),
+                },
+            ],
+            timestamp_field: Some("ts".to_owned()),
+            message_retain: MessageRetain {
+                typ: MessageRetainType::None,
+                count: None,
+                time: None,
+                size: None,
+            },
         }
-        txs
+    }
+
+    #[test]
+    fn test_sql() {
+        let conf = new_conf(IngestMode::Stmt);
+        assert!(Sink::validate_conf(&conf).is_ok());
+        assert_eq!(
+            create_stable_sql(&conf),
+            "CREATE STABLE IF NOT EXISTS `meters` (`ts` TIMESTAMP, `current` FLOAT, `voltage` INT, `note` VARCHAR(32)) TAGS (`location` NCHAR(64))"
+        );
+        assert_eq!(
+            insert_sql(&conf),
+            "INSERT INTO ? USING `meters` TAGS (?) (`ts`, `current`, `voltage`, `note`) VALUES (?, ?, ?, ?)"
+        );
+    }
+
+    #[test]
+    fn test_build_line() {
+        let conf = new_conf(IngestMode::Schemaless);
+        let mut msg = Message::default();
+        msg.add("ts".to_owned(), MessageValue::Int64(1626006833639));
+        msg.add(
+            "location".to_owned(),
+            MessageValue::String("California, LA".to_owned()),
+        );
+        msg.add("current".to_owned(), MessageValue::Float64(10.5));
+        msg.add("voltage".to_owned(), MessageValue::Int64(219));
+        msg.add(
+            "note".to_owned(),
+            MessageValue::String("a \"b\"".to_owned()),
+        );
+        assert_eq!(
+            build_line(&conf, &msg).unwrap(),
+            r#"meters,location=California\,\ LA current=10.5f32,voltage=219i32,note="a \"b\"" 1626006833639"#
+        );
+
+        // 缺失及类型不匹配的列不写入
+        let mut msg = Message::default();
+        msg.add("ts".to_owned(), MessageValue::Int64(1626006833639));
+        msg.add("voltage".to_owned(), MessageValue::String("x".to_owned()));
+        assert!(build_line(&conf, &msg).is_none());
+    }
+
+    // 模拟的REST端点拒绝websocket握手，应按连接错误处理
+    #[tokio::test]
+    async fn test_connect_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 0\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let (err, _) = BiLock::new(None);
+        let (app_err_tx, _app_err_rx) = unbounded_channel();
+        let (_stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (_mb_tx, mb_rx) = unbounded_channel();
+        let mut task_loop = TaskLoop::new(
+            "sink".to_owned(),
+            new_conf(IngestMode::Stmt),
+            err,
+            Arc::new(TDengineConf {
+                host: "127.0.0.1".to_owned(),
+                port,
+                auth_method: TDengineAuthMethod::None,
+                auth_password: None,
+            }),
+            app_err_tx,
+            stop_signal_rx,
+            mb_rx,
+        )
+        .unwrap();
+
+        let e = task_loop.connect().await.unwrap_err();
+        assert!(is_conn_err(&e), "unexpected error: {}", e);
+        assert!(task_loop.taos.is_none());
+        assert!(task_loop.stmt.is_none());
     }
 }
@@ -56,6 +56,16 @@ pub trait ClientTransport: Send + Sync {
     async fn send(&self, buf: &[u8]) -> std::io::Result<usize>;
 }
 
+#[async_trait]
+impl ClientTransport for Box<dyn ClientTransport> {
+    async fn recv(&self, buf: &mut [u8]) -> std::io::Result<(usize, Option<SocketAddr>)> {
+        self.as_ref().recv(buf).await
+    }
+    async fn send(&self, buf: &[u8]) -> std::io::Result<usize> {
+        self.as_ref().send(buf).await
+    }
+}
+
 trait TransportExt {
     async fn receive_packet(&self) -> IoResult<Option<Packet>>;
 }
@@ -328,6 +338,9 @@ impl ClientTransport for UdpTransport {
 /// A CoAP client over UDP. This client can send multicast and broadcasts
 pub type UdpCoAPClient = CoAPClient<UdpTransport>;
 
+/// A CoAP client whose transport is chosen at runtime, e.g. plain UDP or DTLS
+pub type DynCoAPClient = CoAPClient<Box<dyn ClientTransport>>;
+
 pub struct CoAPClient<T: ClientTransport> {
     transport: CoapClientTransport<T>,
     block1_size: usize,
@@ -1,6 +1,9 @@
 use super::client::ClientTransport;
-use super::server::{Responder, TransportRequestSender};
+use super::server::{Listener, Responder, TransportRequestSender};
 use async_trait::async_trait;
+use rustls::pki_types::CertificateDer;
+use rustls::RootCertStore;
+use std::io::{BufReader, Cursor};
 use std::net::SocketAddr;
 use std::time::Duration;
 use std::{
@@ -8,10 +11,23 @@ use std::{
     sync::Arc,
 };
 use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
+use webrtc_dtls::cipher_suite::CipherSuiteId;
+use webrtc_dtls::config::{ClientAuthType, ExtendedMasterSecretType};
 use webrtc_dtls::conn::DTLSConn;
+use webrtc_dtls::crypto::CryptoPrivateKey;
 use webrtc_dtls::state::State;
-use webrtc_util::conn::Conn;
+use webrtc_util::conn::{Conn, Listener as ConnListener};
+
+pub use webrtc_dtls::{config::Config, crypto::Certificate};
+
+/// Cipher suites used in PSK mode. TLS_PSK_WITH_AES_128_CCM_8 is mandatory for CoAP (RFC 7252 9.1.3.1)
+const PSK_CIPHER_SUITES: [CipherSuiteId; 3] = [
+    CipherSuiteId::Tls_Psk_With_Aes_128_Ccm_8,
+    CipherSuiteId::Tls_Psk_With_Aes_128_Ccm,
+    CipherSuiteId::Tls_Psk_With_Aes_128_Gcm_Sha256,
+];
 
 pub struct DtlsResponse {
     pub conn: Arc<dyn Conn + Send + Sync>,
@@ -100,6 +116,7 @@ impl DtlsConnection {
         state: Option<State>,
         on_drop: Option<Box<dyn DtlsDropHook>>,
     ) -> IoResult<Self> {
+        install_crypto_provider();
         let dtls_conn = timeout(
             handshake_timeout,
             DTLSConn::new(connection, dtls_config, true, state),
@@ -150,4 +167,233 @@ impl Drop for DtlsConnection {
             });
         }
     }
-}
\ No newline at end of file
+}
+/// rustls cannot pick a default crypto provider when several are enabled in the dependency
+/// graph, so the one used by webrtc-dtls is installed explicitly
+fn install_crypto_provider() {
+    // fails only when a provider has already been installed
+    let _ = rustls::crypto::ring::default_provider().install_default();
+}
+
+fn to_io_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::new(ErrorKind::InvalidInput, e.to_string())
+}
+
+/// Creates a client config authenticating with a pre-shared key. The identity is sent to the
+/// server, which uses it to look up the key
+pub fn client_psk_config(identity: Vec<u8>, key: Vec<u8>) -> Config {
+    Config {
+        psk: Some(Arc::new(move |_hint: &[u8]| Ok(key.clone()))),
+        psk_identity_hint: Some(identity),
+        cipher_suites: PSK_CIPHER_SUITES.to_vec(),
+        extended_master_secret: ExtendedMasterSecretType::Request,
+        ..Default::default()
+    }
+}
+
+/// Creates a client config authenticating with X.509 certificates.
+///
+/// `ca_cert` is a PEM bundle used to verify the server, `client_cert` is an optional PEM
+/// certificate chain and private key pair presented when the server asks for one. When `verify`
+/// is false the server certificate is accepted without any check
+pub fn client_cert_config(
+    verify: bool,
+    server_name: String,
+    ca_cert: Option<&str>,
+    client_cert: Option<(&str, &str)>,
+) -> IoResult<Config> {
+    let mut roots_cas = RootCertStore::empty();
+    if let Some(ca_cert) = ca_cert {
+        for cert in parse_certs(ca_cert)? {
+            roots_cas.add(cert).map_err(to_io_error)?;
+        }
+    }
+
+    let certificates = match client_cert {
+        Some((cert, key)) => vec![parse_certificate(cert, key)?],
+        None => vec![],
+    };
+
+    Ok(Config {
+        certificates,
+        roots_cas,
+        server_name,
+        insecure_skip_verify: !verify,
+        extended_master_secret: ExtendedMasterSecretType::Request,
+        ..Default::default()
+    })
+}
+
+/// Creates a server config which accepts clients knowing the pre-shared key
+pub fn server_psk_config(identity_hint: Vec<u8>, key: Vec<u8>) -> Config {
+    Config {
+        psk: Some(Arc::new(move |_identity: &[u8]| Ok(key.clone()))),
+        psk_identity_hint: Some(identity_hint),
+        cipher_suites: PSK_CIPHER_SUITES.to_vec(),
+        extended_master_secret: ExtendedMasterSecretType::Request,
+        ..Default::default()
+    }
+}
+
+/// Creates a server config presenting the given certificate. When `client_ca_cert` is set,
+/// clients must present a certificate signed by it
+pub fn server_cert_config(
+    certificate: Certificate,
+    client_ca_cert: Option<&str>,
+) -> IoResult<Config> {
+    let (client_auth, client_cas) = match client_ca_cert {
+        Some(client_ca_cert) => {
+            let mut client_cas = RootCertStore::empty();
+            for cert in parse_certs(client_ca_cert)? {
+                client_cas.add(cert).map_err(to_io_error)?;
+            }
+            (ClientAuthType::RequireAndVerifyClientCert, client_cas)
+        }
+        None => (ClientAuthType::NoClientCert, RootCertStore::empty()),
+    };
+
+    Ok(Config {
+        certificates: vec![certificate],
+        client_auth,
+        client_cas,
+        extended_master_secret: ExtendedMasterSecretType::Request,
+        ..Default::default()
+    })
+}
+
+/// Parses a PEM certificate chain and its PEM private key. Only ECDSA P-256 and Ed25519 keys are
+/// supported by the DTLS implementation
+pub fn parse_certificate(cert: &str, key: &str) -> IoResult<Certificate> {
+    let certificate = parse_certs(cert)?;
+    if certificate.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "no certificate found"));
+    }
+    let key_pair = rcgen::KeyPair::from_pem(key).map_err(to_io_error)?;
+    let private_key = CryptoPrivateKey::from_key_pair(&key_pair).map_err(to_io_error)?;
+    Ok(Certificate {
+        certificate,
+        private_key,
+    })
+}
+
+fn parse_certs(pem: &str) -> IoResult<Vec<CertificateDer<'static>>> {
+    rustls_pemfile::certs(&mut BufReader::new(Cursor::new(pem))).collect()
+}
+
+/// A listener accepting DTLS connections on a UDP socket
+pub struct DtlsListener {
+    listener: Arc<dyn ConnListener + Send + Sync>,
+}
+
+impl DtlsListener {
+    pub async fn new(addr: SocketAddr, config: Config) -> IoResult<Self> {
+        install_crypto_provider();
+        let listener = webrtc_dtls::listener::listen(addr, config)
+            .await
+            .map_err(|e| Error::new(ErrorKind::Other, e))?;
+        Ok(Self {
+            listener: Arc::new(listener),
+        })
+    }
+
+    pub async fn local_addr(&self) -> IoResult<SocketAddr> {
+        self.listener
+            .addr()
+            .await
+            .map_err(|e| Error::new(ErrorKind::Other, e))
+    }
+}
+
+#[async_trait]
+impl Listener for DtlsListener {
+    async fn listen(
+        self: Box<Self>,
+        sender: TransportRequestSender,
+    ) -> IoResult<JoinHandle<IoResult<()>>> {
+        Ok(tokio::spawn(async move {
+            loop {
+                // a failed handshake only affects that peer, keep accepting others
+                let (conn, remote_addr) = match self.listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(
+                        webrtc_util::Error::ErrClosedListener
+                        | webrtc_util::Error::ErrClosedListenerAcceptCh,
+                    ) => return Ok(()),
+                    Err(_) => continue,
+                };
+                tokio::spawn(spawn_webrtc_conn(conn, remote_addr, sender.clone()));
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::CoAPClient;
+    use crate::request::{Method, RequestBuilder};
+    use crate::server::Server;
+    use coap_lite::CoapRequest;
+
+    async fn request_handler(
+        mut req: Box<CoapRequest<SocketAddr>>,
+    ) -> Box<CoapRequest<SocketAddr>> {
+        let payload = req.message.payload.clone();
+        if let Some(ref mut response) = req.response {
+            response.message.payload = payload;
+        }
+        req
+    }
+
+    async fn spawn_dtls_server(config: Config) -> SocketAddr {
+        let listener = DtlsListener::new("127.0.0.1:0".parse().unwrap(), config)
+            .await
+            .unwrap();
+        let addr = listener.local_addr().await.unwrap();
+        let server = Server::from_listeners(vec![Box::new(listener)]);
+        tokio::spawn(server.run(request_handler));
+        addr
+    }
+
+    async fn echo(config: Config, dest_addr: SocketAddr) -> IoResult<Vec<u8>> {
+        let client = CoAPClient::from_udp_dtls_config(UdpDtlsConfig { config, dest_addr }).await?;
+        let request = RequestBuilder::new("echo", Method::Put)
+            .data(Some(b"hello".to_vec()))
+            .build();
+        Ok(client.send(request).await?.message.payload)
+    }
+
+    #[tokio::test]
+    async fn test_psk() {
+        let addr =
+            spawn_dtls_server(server_psk_config(b"halia".to_vec(), b"secret".to_vec())).await;
+
+        let payload = echo(
+            client_psk_config(b"client".to_vec(), b"secret".to_vec()),
+            addr,
+        )
+        .await
+        .unwrap();
+        assert_eq!(payload, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_cert() {
+        let rcgen::CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let cert_pem = cert.pem();
+        let certificate = parse_certificate(&cert_pem, &key_pair.serialize_pem()).unwrap();
+        let addr = spawn_dtls_server(server_cert_config(certificate, None).unwrap()).await;
+
+        let config =
+            client_cert_config(true, "localhost".to_owned(), Some(&cert_pem), None).unwrap();
+        assert_eq!(echo(config, addr).await.unwrap(), b"hello");
+
+        // 服务端证书不在信任列表中
+        let other = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let config =
+            client_cert_config(true, "localhost".to_owned(), Some(&other.cert.pem()), None)
+                .unwrap();
+        assert!(echo(config, addr).await.is_err());
+    }
+}
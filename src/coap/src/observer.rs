@@ -183,13 +183,16 @@ impl Observer {
 
     async fn resource_changed(&mut self, request: &CoapRequest<SocketAddr>) {
         let resource_path = request.get_path();
-        let ref resource_payload = request.message.payload;
+        self.set_resource(&resource_path, &request.message.payload).await;
+    }
 
+    /// updates the resource at the path and notifies all the registers observing it
+    pub async fn set_resource(&mut self, resource_path: &String, resource_payload: &Vec<u8>) {
         debug!("resource_changed {} {:?}", resource_path, resource_payload);
 
         let register_resource_keys: Vec<String>;
         {
-            let resource = self.record_resource(&resource_path, &resource_payload);
+            let resource = self.record_resource(resource_path, resource_payload);
             register_resource_keys = resource
                 .register_resources
                 .iter()
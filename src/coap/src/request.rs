@@ -1,7 +1,7 @@
 use std::net::SocketAddr;
 
 pub use coap_lite::{
-    CoapOption, CoapRequest, MessageClass, MessageType, ObserveOption, Packet,
+    CoapOption, CoapRequest, CoapResponse, ContentFormat, MessageClass, MessageType, ObserveOption, Packet,
     RequestType as Method, ResponseType as Status,
 };
 
@@ -351,6 +351,36 @@ impl ServerCoapState {
     }
 }
 
+/// A handle to update observable resources of a running server from outside of the request
+/// handler, e.g. when the value behind a path changes
+#[derive(Clone)]
+pub struct ObserveHandle {
+    coap_state: Arc<Mutex<ServerCoapState>>,
+}
+
+impl ObserveHandle {
+    /// sets the payload of the resource and notifies its observers
+    pub async fn resource_changed(&self, path: &str, payload: Vec<u8>) {
+        self.coap_state
+            .lock()
+            .await
+            .observer
+            .set_resource(&path.to_string(), &payload)
+            .await;
+    }
+}
+
+/// aborts the listener tasks once the server stops running, so their sockets are released
+struct ListenerHandles(Vec<JoinHandle<std::io::Result<()>>>);
+
+impl Drop for ListenerHandles {
+    fn drop(&mut self) {
+        for handle in self.0.iter() {
+            handle.abort();
+        }
+    }
+}
+
 pub struct Server {
     listeners: Vec<Box<dyn Listener>>,
     coap_state: Arc<Mutex<ServerCoapState>>,
@@ -389,7 +419,9 @@ impl Server {
 
     /// run the server.
     pub async fn run<Handler: RequestHandler>(mut self, handler: Handler) -> Result<(), io::Error> {
-        let _handles = Self::spawn_handles(self.listeners, self.new_packet_sender.clone()).await?;
+        let _handles = ListenerHandles(
+            Self::spawn_handles(self.listeners, self.new_packet_sender.clone()).await?,
+        );
 
         let handler_arc = Arc::new(handler);
         // receive an input, sync our cache / states, then call custom handler
@@ -436,6 +468,13 @@ impl Server {
             responder.respond(b).await;
         }
     }
+    /// returns a handle to update observable resources while the server is running
+    pub fn observe_handle(&self) -> ObserveHandle {
+        ObserveHandle {
+            coap_state: self.coap_state.clone(),
+        }
+    }
+
     /// disable auto-observe handling in server
     pub async fn disable_observe_handling(&mut self, value: bool) {
         let mut coap_state = self.coap_state.lock().await;
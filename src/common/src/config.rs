@@ -2,6 +2,7 @@ use std::fs;
 
 use serde::Deserialize;
 use tracing::info;
+use types::mqtt_server::MqttServerConf;
 
 pub fn init(config_path: &str) -> Config {
     match fs::read_to_string(config_path) {
@@ -34,6 +35,7 @@ pub fn init(config_path: &str) -> Config {
                 log_level,
                 storage,
                 event_retain_days,
+                mqtt_server: config_raw.mqtt_server.take(),
             }
         }
         Err(_) => {
@@ -53,6 +55,8 @@ struct ConfigRaw {
     pub storage: Option<StorageConfig>,
     // 事件保留时间，默认为7天
     pub event_retain_days: Option<usize>,
+    // 内置mqtt服务器，未配置时不启动
+    pub mqtt_server: Option<MqttServerConf>,
 }
 
 #[derive(Deserialize)]
@@ -70,6 +74,7 @@ pub struct Config {
     pub log_level: LogLevel,
     pub storage: StorageConfig,
     pub event_retain_days: usize,
+    pub mqtt_server: Option<MqttServerConf>,
 }
 
 impl Default for Config {
@@ -82,6 +87,7 @@ impl Default for Config {
                 path: "./db".to_string(),
             }),
             event_retain_days: 7,
+            mqtt_server: None,
         }
     }
 }
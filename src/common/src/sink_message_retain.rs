@@ -1,19 +1,51 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
-    time::{SystemTime, UNIX_EPOCH},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use message::MessageBatch;
+use tracing::warn;
 use types::MessageRetain;
 
+use crate::error::{HaliaError, HaliaResult};
+
+// 连接恢复后重发保留消息的检查间隔
+pub const REPLAY_INTERVAL: Duration = Duration::from_secs(3);
+
+// 磁盘保留队列的根目录，每个动作一个子目录
+const DISK_RETAIN_DIR: &str = "./retain";
+// 未配置大小时磁盘队列的默认上限，MB
+const DEFAULT_DISK_SIZE: u64 = 1024;
+// 已发送部分超过该大小且超过文件一半时压缩数据文件
+const COMPACT_THRESHOLD: u64 = 4 * 1024 * 1024;
+// 记录头：写入时间(8字节) + 数据长度(4字节)
+const RECORD_HEADER_LEN: u64 = 12;
+
+static DISK_RETAIN_DEPTHS: LazyLock<Mutex<HashMap<String, Arc<AtomicUsize>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 pub trait SinkMessageRetain: Debug + Sync + Send {
     fn push(&mut self, mb: MessageBatch);
     fn pop(&mut self) -> Option<MessageBatch>;
+    // 查看队首消息但不移除，发送成功后再调用pop，保证失败时消息顺序不变
+    fn peek(&mut self) -> Option<MessageBatch>;
+    // 按时间保留的队列会先移除已过期的消息，因此需要可变引用
+    fn len(&mut self) -> usize;
+    fn is_empty(&mut self) -> bool {
+        self.len() == 0
+    }
 }
 
-pub fn new(mr: &MessageRetain) -> Box<dyn SinkMessageRetain> {
-    match mr.typ {
+pub fn new(mr: &MessageRetain, sink_id: &str) -> HaliaResult<Box<dyn SinkMessageRetain>> {
+    let retainer: Box<dyn SinkMessageRetain> = match mr.typ {
         types::MessageRetainType::All => Box::new(SinkMessageRetainAll {
             mbs: VecDeque::new(),
         }),
@@ -26,6 +58,66 @@ pub fn new(mr: &MessageRetain) -> Box<dyn SinkMessageRetain> {
             duration: *mr.time.as_ref().unwrap(),
             mbs: VecDeque::new(),
         }),
+        types::MessageRetainType::Disk => {
+            let max_size = mr.size.unwrap_or(DEFAULT_DISK_SIZE) * 1024 * 1024;
+            let max_age = mr.time.map(|time| time * 1000);
+            let dir = Path::new(DISK_RETAIN_DIR).join(sink_id);
+            // 打开失败时直接返回错误，不退化为无上限的内存保留
+            match SinkMessageRetainDisk::open(sink_id.to_owned(), dir, max_size, max_age) {
+                Ok(retainer) => Box::new(retainer),
+                Err(e) => return Err(HaliaError::Common(format!("打开磁盘保留队列失败：{}", e))),
+            }
+        }
+    };
+    Ok(retainer)
+}
+
+// 保留策略变更时重建保留队列，尚未重发的消息按顺序迁移到新队列
+pub fn update(
+    retainer: &mut Box<dyn SinkMessageRetain>,
+    old_mr: &MessageRetain,
+    new_mr: &MessageRetain,
+    sink_id: &str,
+) -> HaliaResult<()> {
+    if old_mr == new_mr {
+        return Ok(());
+    }
+
+    let old_disk = old_mr.typ == types::MessageRetainType::Disk;
+    if old_disk && new_mr.typ == types::MessageRetainType::Disk {
+        // 使用同一目录，先关闭旧队列，再以新的限制重新打开
+        *retainer = Box::new(SinkMessageRetainNone {});
+        *retainer = new(new_mr, sink_id)?;
+        return Ok(());
+    }
+
+    let mut new_retainer = new(new_mr, sink_id)?;
+    while let Some(mb) = retainer.pop() {
+        new_retainer.push(mb);
+    }
+    *retainer = new_retainer;
+    if old_disk {
+        remove_disk_data(sink_id);
+    }
+    Ok(())
+}
+
+// 读取磁盘队列中等待重发的消息数量，非磁盘保留的动作返回None
+pub fn get_depth(sink_id: &str) -> Option<usize> {
+    DISK_RETAIN_DEPTHS
+        .lock()
+        .unwrap()
+        .get(sink_id)
+        .map(|depth| depth.load(Ordering::Relaxed))
+}
+
+// 删除动作时清理磁盘队列文件
+pub fn remove_disk_data(sink_id: &str) {
+    let dir = Path::new(DISK_RETAIN_DIR).join(sink_id);
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("remove disk retain queue of sink {} err:{}", sink_id, e);
+        }
     }
 }
 
@@ -42,6 +134,14 @@ impl SinkMessageRetain for SinkMessageRetainAll {
     fn pop(&mut self) -> Option<MessageBatch> {
         self.mbs.pop_front()
     }
+
+    fn peek(&mut self) -> Option<MessageBatch> {
+        self.mbs.front().cloned()
+    }
+
+    fn len(&mut self) -> usize {
+        self.mbs.len()
+    }
 }
 
 #[derive(Debug)]
@@ -53,6 +153,14 @@ impl SinkMessageRetain for SinkMessageRetainNone {
     fn pop(&mut self) -> Option<MessageBatch> {
         None
     }
+
+    fn peek(&mut self) -> Option<MessageBatch> {
+        None
+    }
+
+    fn len(&mut self) -> usize {
+        0
+    }
 }
 
 #[derive(Debug)]
@@ -72,6 +180,14 @@ impl SinkMessageRetain for SinkMessageRetainCount {
     fn pop(&mut self) -> Option<MessageBatch> {
         self.mbs.pop_front()
     }
+
+    fn peek(&mut self) -> Option<MessageBatch> {
+        self.mbs.front().cloned()
+    }
+
+    fn len(&mut self) -> usize {
+        self.mbs.len()
+    }
 }
 
 #[derive(Debug)]
@@ -83,11 +199,9 @@ struct SinkMessageRetainTime {
 
 impl SinkMessageRetainTime {
     fn remove_expire_mbs(&mut self) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        self.mbs.retain(|mb| mb.get_ts() + self.duration < now);
+        let now = now_ms();
+        self.mbs
+            .retain(|mb| mb.get_ts() + self.duration * 1000 >= now);
     }
 }
 
@@ -101,4 +215,469 @@ impl SinkMessageRetain for SinkMessageRetainTime {
         self.remove_expire_mbs();
         self.mbs.pop_front()
     }
+
+    fn peek(&mut self) -> Option<MessageBatch> {
+        self.remove_expire_mbs();
+        self.mbs.front().cloned()
+    }
+
+    fn len(&mut self) -> usize {
+        self.remove_expire_mbs();
+        self.mbs.len()
+    }
+}
+
+// 磁盘队列，消息以记录的形式追加写入数据文件，head文件记录队首记录在数据文件中的偏移，
+// 重启后从head处继续重发
+#[derive(Debug)]
+struct SinkMessageRetainDisk {
+    sink_id: String,
+    dir: PathBuf,
+    data_file: File,
+    head_file: File,
+    // 队首记录偏移
+    head: u64,
+    // 数据文件长度
+    tail: u64,
+    // 字节
+    max_size: u64,
+    // 毫秒
+    max_age: Option<u64>,
+    depth: Arc<AtomicUsize>,
+    // peek读出的队首消息及下一条记录的偏移，避免pop时重复读取
+    peeked: Option<(u64, MessageBatch)>,
+}
+
+impl SinkMessageRetainDisk {
+    fn open(
+        sink_id: String,
+        dir: PathBuf,
+        max_size: u64,
+        max_age: Option<u64>,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let data_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join("data"))?;
+        let mut head_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join("head"))?;
+
+        let mut buf = [0u8; 8];
+        let mut head = match head_file.read_exact(&mut buf) {
+            Ok(_) => u64::from_le_bytes(buf),
+            Err(_) => 0,
+        };
+        let tail = data_file.metadata()?.len();
+        if head > tail {
+            head = 0;
+        }
+
+        let mut retainer = Self {
+            sink_id,
+            dir,
+            data_file,
+            head_file,
+            head,
+            tail,
+            max_size,
+            max_age,
+            depth: Arc::new(AtomicUsize::new(0)),
+            peeked: None,
+        };
+        retainer.recover()?;
+
+        DISK_RETAIN_DEPTHS
+            .lock()
+            .unwrap()
+            .insert(retainer.sink_id.clone(), retainer.depth.clone());
+
+        Ok(retainer)
+    }
+
+    // 统计队列深度，并截断进程异常退出时写入不完整的记录
+    fn recover(&mut self) -> io::Result<()> {
+        let mut offset = self.head;
+        let mut depth = 0;
+        while offset + RECORD_HEADER_LEN <= self.tail {
+            let (_, len) = self.read_header(offset)?;
+            let next = offset + RECORD_HEADER_LEN + len as u64;
+            if next > self.tail {
+                break;
+            }
+            offset = next;
+            depth += 1;
+        }
+
+        if offset != self.tail {
+            warn!(
+                "disk retain queue of sink {} truncated {} bytes",
+                self.sink_id,
+                self.tail - offset
+            );
+            self.data_file.set_len(offset)?;
+            self.tail = offset;
+        }
+        self.depth.store(depth, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn read_header(&mut self, offset: u64) -> io::Result<(u64, u32)> {
+        let mut buf = [0u8; RECORD_HEADER_LEN as usize];
+        self.data_file.seek(SeekFrom::Start(offset))?;
+        self.data_file.read_exact(&mut buf)?;
+        let ts = u64::from_le_bytes(buf[..8].try_into().unwrap());
+        let len = u32::from_le_bytes(buf[8..].try_into().unwrap());
+        Ok((ts, len))
+    }
+
+    fn append(&mut self, mb: &MessageBatch) -> io::Result<()> {
+        let payload = serde_json::to_vec(mb)?;
+        let record_len = RECORD_HEADER_LEN + payload.len() as u64;
+        if record_len > self.max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "消息大小超过磁盘队列上限",
+            ));
+        }
+
+        // 数据文件将超出大小限制时，丢弃最早的消息直至腾出四分之一的空间，再压缩数据文件，
+        // 保证文件大小不超过上限，同时避免每次写入都压缩
+        if self.tail + record_len > self.max_size {
+            let limit = self.max_size - self.max_size / 4;
+            let mut dropped = false;
+            while self.head < self.tail && self.tail - self.head + record_len > limit {
+                self.drop_head()?;
+                dropped = true;
+            }
+            if dropped {
+                warn!(
+                    "disk retain queue of sink {} is full, drop oldest messages",
+                    self.sink_id
+                );
+            }
+            if self.head >= self.tail {
+                self.clear()?;
+            } else if self.head > 0 {
+                self.compact()?;
+            }
+        }
+
+        let mut record = Vec::with_capacity(record_len as usize);
+        record.extend_from_slice(&now_ms().to_le_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&payload);
+        self.data_file.seek(SeekFrom::Start(self.tail))?;
+        self.data_file.write_all(&record)?;
+        self.tail += record_len;
+        self.depth.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    // 移除队首已过期的消息
+    fn expire(&mut self) -> io::Result<()> {
+        let max_age = match self.max_age {
+            Some(max_age) => max_age,
+            None => return Ok(()),
+        };
+
+        let now = now_ms();
+        let mut dropped = false;
+        while self.head < self.tail {
+            let (ts, _) = self.read_header(self.head)?;
+            if ts + max_age >= now {
+                break;
+            }
+            self.drop_head()?;
+            dropped = true;
+        }
+        if dropped {
+            self.reclaim()?;
+        }
+        Ok(())
+    }
+
+    fn drop_head(&mut self) -> io::Result<()> {
+        let (_, len) = self.read_header(self.head)?;
+        self.head += RECORD_HEADER_LEN + len as u64;
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        self.peeked = None;
+        Ok(())
+    }
+
+    // 读取队首未过期的消息，返回下一条记录的偏移
+    fn read_head(&mut self) -> io::Result<Option<(u64, MessageBatch)>> {
+        let now = now_ms();
+        let mut dropped = false;
+        let res = loop {
+            if self.head >= self.tail {
+                break None;
+            }
+
+            let (ts, len) = self.read_header(self.head)?;
+            if let Some(max_age) = self.max_age {
+                if ts + max_age < now {
+                    self.drop_head()?;
+                    dropped = true;
+                    continue;
+                }
+            }
+
+            let mut payload = vec![0u8; len as usize];
+            self.data_file.read_exact(&mut payload)?;
+            match serde_json::from_slice(&payload) {
+                Ok(mb) => break Some((self.head + RECORD_HEADER_LEN + len as u64, mb)),
+                Err(e) => {
+                    warn!(
+                        "disk retain queue of sink {} decode err:{}",
+                        self.sink_id, e
+                    );
+                    self.drop_head()?;
+                    dropped = true;
+                }
+            }
+        };
+
+        if dropped {
+            self.persist_head()?;
+        }
+        Ok(res)
+    }
+
+    fn advance(&mut self, next: u64) -> io::Result<()> {
+        self.head = next;
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        self.reclaim()
+    }
+
+    // 队首移动后持久化head，并按需清空或压缩数据文件
+    fn reclaim(&mut self) -> io::Result<()> {
+        if self.head >= self.tail {
+            self.clear()
+        } else if self.head >= COMPACT_THRESHOLD && self.head * 2 >= self.tail {
+            self.compact()
+        } else {
+            self.persist_head()
+        }
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.head = 0;
+        self.tail = 0;
+        self.peeked = None;
+        self.persist_head()?;
+        self.data_file.set_len(0)
+    }
+
+    // 将未发送的记录拷贝到新文件，回收已发送部分占用的空间
+    fn compact(&mut self) -> io::Result<()> {
+        let tmp_path = self.dir.join("data.tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        self.data_file.seek(SeekFrom::Start(self.head))?;
+        io::copy(
+            &mut (&self.data_file).take(self.tail - self.head),
+            &mut tmp_file,
+        )?;
+        tmp_file.sync_all()?;
+
+        // 先持久化head再替换文件，中途退出最多导致重复发送，不会读到错位的记录
+        let len = self.tail - self.head;
+        if let Some((next, _)) = &mut self.peeked {
+            *next -= self.head;
+        }
+        self.head = 0;
+        self.persist_head()?;
+        fs::rename(&tmp_path, self.dir.join("data"))?;
+
+        self.data_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.dir.join("data"))?;
+        self.tail = len;
+        Ok(())
+    }
+
+    fn persist_head(&mut self) -> io::Result<()> {
+        self.head_file.seek(SeekFrom::Start(0))?;
+        self.head_file.write_all(&self.head.to_le_bytes())
+    }
+}
+
+impl SinkMessageRetain for SinkMessageRetainDisk {
+    fn push(&mut self, mb: MessageBatch) {
+        if let Err(e) = self.append(&mb) {
+            warn!("disk retain queue of sink {} push err:{}", self.sink_id, e);
+        }
+    }
+
+    fn pop(&mut self) -> Option<MessageBatch> {
+        let (next, mb) = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => match self.read_head() {
+                Ok(Some(head)) => head,
+                Ok(None) => return None,
+                Err(e) => {
+                    warn!("disk retain queue of sink {} read err:{}", self.sink_id, e);
+                    return None;
+                }
+            },
+        };
+
+        if let Err(e) = self.advance(next) {
+            warn!("disk retain queue of sink {} pop err:{}", self.sink_id, e);
+        }
+        Some(mb)
+    }
+
+    fn peek(&mut self) -> Option<MessageBatch> {
+        if self.peeked.is_none() {
+            match self.read_head() {
+                Ok(head) => self.peeked = head,
+                Err(e) => {
+                    warn!("disk retain queue of sink {} read err:{}", self.sink_id, e);
+                    return None;
+                }
+            }
+        }
+        self.peeked.as_ref().map(|(_, mb)| mb.clone())
+    }
+
+    fn len(&mut self) -> usize {
+        if let Err(e) = self.expire() {
+            warn!("disk retain queue of sink {} read err:{}", self.sink_id, e);
+        }
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SinkMessageRetainDisk {
+    fn drop(&mut self) {
+        let mut depths = DISK_RETAIN_DEPTHS.lock().unwrap();
+        if let Some(depth) = depths.get(&self.sink_id) {
+            if Arc::ptr_eq(depth, &self.depth) {
+                depths.remove(&self.sink_id);
+            }
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use message::{Message, MessageValue};
+
+    use super::*;
+
+    fn new_mb(i: i64) -> MessageBatch {
+        let mut message = Message::default();
+        message.add("i".to_owned(), MessageValue::Int64(i));
+        let mut mb = MessageBatch::default();
+        mb.push_message(message);
+        mb
+    }
+
+    fn get_i(mb: &MessageBatch) -> i64 {
+        *mb.get_messages()[0].get_int("i").unwrap()
+    }
+
+    #[test]
+    fn test_disk_retain() {
+        let dir = std::env::temp_dir().join(format!("halia-retain-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut retainer =
+            SinkMessageRetainDisk::open("test".to_owned(), dir.clone(), 1024 * 1024, None).unwrap();
+        for i in 0..10 {
+            retainer.push(new_mb(i));
+        }
+        assert_eq!(get_depth("test"), Some(10));
+        assert_eq!(get_i(&retainer.peek().unwrap()), 0);
+        assert_eq!(get_i(&retainer.pop().unwrap()), 0);
+        assert_eq!(get_i(&retainer.pop().unwrap()), 1);
+        drop(retainer);
+        assert_eq!(get_depth("test"), None);
+
+        // 重启后从上次的位置继续
+        let mut retainer =
+            SinkMessageRetainDisk::open("test".to_owned(), dir.clone(), 1024 * 1024, None).unwrap();
+        assert_eq!(retainer.len(), 8);
+        for i in 2..10 {
+            assert_eq!(get_i(&retainer.pop().unwrap()), i);
+        }
+        assert!(retainer.pop().is_none());
+        assert_eq!(retainer.tail, 0);
+        drop(retainer);
+
+        // 超出大小时丢弃最早的消息
+        let record_len = RECORD_HEADER_LEN + serde_json::to_vec(&new_mb(0)).unwrap().len() as u64;
+        let mut retainer =
+            SinkMessageRetainDisk::open("test".to_owned(), dir.clone(), record_len * 3, None)
+                .unwrap();
+        for i in 0..5 {
+            retainer.push(new_mb(i));
+        }
+        assert_eq!(retainer.len(), 3);
+        assert_eq!(get_i(&retainer.pop().unwrap()), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_disk_retain_bounded() {
+        let dir = std::env::temp_dir().join(format!("halia-retain-bounded-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // 只写入不重发时数据文件大小也不能超过上限
+        let record_len = RECORD_HEADER_LEN + serde_json::to_vec(&new_mb(0)).unwrap().len() as u64;
+        let max_size = record_len * 10;
+        let mut retainer =
+            SinkMessageRetainDisk::open("bounded".to_owned(), dir.clone(), max_size, None).unwrap();
+        for i in 0..100 {
+            retainer.push(new_mb(i));
+            assert!(fs::metadata(dir.join("data")).unwrap().len() <= max_size);
+        }
+
+        let len = retainer.len();
+        assert!(len > 0 && len <= 10);
+        for i in (100 - len as i64)..100 {
+            assert_eq!(get_i(&retainer.pop().unwrap()), i);
+        }
+        assert!(retainer.pop().is_none());
+        drop(retainer);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_disk_retain_expire() {
+        let dir = std::env::temp_dir().join(format!("halia-retain-expire-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut retainer =
+            SinkMessageRetainDisk::open("expire".to_owned(), dir.clone(), 1024 * 1024, Some(1))
+                .unwrap();
+        for i in 0..3 {
+            retainer.push(new_mb(i));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(retainer.len(), 0);
+        assert_eq!(get_depth("expire"), Some(0));
+        assert_eq!(retainer.tail, 0);
+        drop(retainer);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
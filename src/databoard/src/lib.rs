@@ -9,7 +9,7 @@ use types::{
     databoard::{
         CreateUpdateDataReq, CreateUpdateDataboardReq, ListDataboardsItem, ListDataboardsResp,
         ListDatasItemResp, ListDatasResp, QueryDatasParams, QueryParams, QueryRuleInfo,
-        RuleInfoData, RuleInfoDataboard, RuleInfoResp,
+        RuleInfoData, RuleInfoDataboard, RuleInfoResp, SearchDatasRuntimeResp,
     },
     Pagination, Summary,
 };
@@ -237,3 +237,13 @@ pub async fn get_data_txs(
         }
     }
 }
+
+pub async fn read_data_runtime(
+    databoard_id: &String,
+    databoard_data_id: &String,
+) -> HaliaResult<SearchDatasRuntimeResp> {
+    match GLOBAL_DATABOARD_MANAGER.get(databoard_id) {
+        Some(databoard) => databoard.read_data_runtime(databoard_data_id).await,
+        None => Err(HaliaError::NotFound(databoard_id.to_string())),
+    }
+}
@@ -1,9 +1,13 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use base64::{prelude::BASE64_STANDARD, Engine as _};
-use coap_protocol::{client::UdpCoAPClient, request::CoapOption};
+use coap_protocol::{
+    client::{ClientTransport, DynCoAPClient, UdpTransport},
+    dtls::{self, DtlsConnection, UdpDtlsConfig},
+    request::CoapOption,
+};
 use common::error::{HaliaError, HaliaResult};
 use dashmap::DashMap;
 use futures::lock::BiLock;
@@ -12,27 +16,95 @@ use message::RuleMessageBatch;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use sink::Sink;
 use source::Source;
-use tokio::sync::{mpsc, Mutex};
-use types::devices::device::coap::{DeviceConf, SinkConf, SourceConf};
+use tokio::{
+    net::{lookup_host, UdpSocket},
+    select,
+    sync::{mpsc, watch, Mutex},
+    task::JoinHandle,
+    time,
+};
+use tracing::warn;
+use types::devices::device::coap::{DeviceConf, DtlsConf, DtlsMode, SinkConf, SourceConf};
+use utils::ErrorManager;
 
 use crate::{Device, UpdateConfMode};
 
 mod sink;
 mod source;
 
+// 创建客户端失败后的重试间隔，每次失败后翻倍
+const MIN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(ResourceErr)]
 struct Coap {
-    sources: DashMap<String, Source>,
-    sinks: DashMap<String, Sink>,
+    sources: Arc<DashMap<String, Source>>,
+    sinks: Arc<DashMap<String, Sink>>,
 
-    coap_client: Arc<UdpCoAPClient>,
+    // 客户端创建成功前为空
+    coap_client: Arc<Mutex<Option<Arc<DynCoAPClient>>>>,
     err: BiLock<Option<Arc<String>>>,
     token_manager: Arc<Mutex<TokenManager>>,
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
 }
 
 struct TaskLoop {
-    device_err: BiLock<Option<Arc<String>>>,
-    id: String,
+    device_conf: DeviceConf,
+    sources: Arc<DashMap<String, Source>>,
+    sinks: Arc<DashMap<String, Sink>>,
+    coap_client: Arc<Mutex<Option<Arc<DynCoAPClient>>>>,
+    stop_signal_rx: watch::Receiver<()>,
+    error_manager: ErrorManager,
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            let mut retry_interval = MIN_RETRY_INTERVAL;
+            loop {
+                match new_coap_client(&self.device_conf).await {
+                    Ok(coap_client) => {
+                        self.error_manager.set_ok().await;
+                        self.set_coap_client(Some(coap_client)).await;
+                        _ = self.stop_signal_rx.changed().await;
+                        return self;
+                    }
+                    Err(e) => {
+                        warn!("create coap client err:{}", e);
+                        self.error_manager.set_err(Arc::new(e.to_string())).await;
+                    }
+                }
+
+                if self.wait_retry(retry_interval).await {
+                    return self;
+                }
+                retry_interval = (retry_interval * 2).min(MAX_RETRY_INTERVAL);
+            }
+        })
+    }
+
+    // 返回true表示收到停止信号
+    async fn wait_retry(&mut self, retry_interval: Duration) -> bool {
+        let sleep = time::sleep(retry_interval);
+        tokio::pin!(sleep);
+        select! {
+            _ = self.stop_signal_rx.changed() => true,
+            _ = &mut sleep => false,
+        }
+    }
+
+    // 持有锁期间更新源和动作，避免与同时创建的源和动作交错
+    async fn set_coap_client(&mut self, coap_client: Option<Arc<DynCoAPClient>>) {
+        let mut current = self.coap_client.lock().await;
+        for mut source in self.sources.iter_mut() {
+            source.update_coap_client(coap_client.clone()).await;
+        }
+        for mut sink in self.sinks.iter_mut() {
+            sink.update_coap_client(coap_client.clone()).await;
+        }
+        *current = coap_client;
+    }
 }
 
 pub(crate) async fn new_by_customize(
@@ -40,28 +112,131 @@ pub(crate) async fn new_by_customize(
     conf: serde_json::Value,
 ) -> HaliaResult<Box<dyn Device>> {
     let device_conf: DeviceConf = serde_json::from_value(conf)?;
-    let coap_client =
-        Arc::new(UdpCoAPClient::new_udp((device_conf.host.clone(), device_conf.port)).await?);
 
     let (err1, err2) = BiLock::new(None);
+    let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let sources = Arc::new(DashMap::new());
+    let sinks = Arc::new(DashMap::new());
+    let coap_client = Arc::new(Mutex::new(None));
+
+    // 在任务中创建客户端，失败时按间隔重试
+    let task_loop = TaskLoop {
+        device_conf,
+        sources: sources.clone(),
+        sinks: sinks.clone(),
+        coap_client: coap_client.clone(),
+        stop_signal_rx,
+        error_manager: ErrorManager::new(utils::error_manager::ResourceType::Device, id, err1),
+    };
+    let join_handle = task_loop.start();
 
     Ok(Box::new(Coap {
-        sources: DashMap::new(),
-        sinks: DashMap::new(),
+        sources,
+        sinks,
         coap_client,
         err: err2,
         token_manager: Arc::new(Mutex::new(TokenManager::new())),
+        stop_signal_tx,
+        join_handle: Some(join_handle),
     }))
 }
 
+impl Coap {
+    async fn stop_task_loop(&mut self) -> TaskLoop {
+        self.stop_signal_tx.send(()).unwrap();
+        self.join_handle.take().unwrap().await.unwrap()
+    }
+}
+
 pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
-    let _conf: DeviceConf = serde_json::from_value(conf.clone())?;
+    let conf: DeviceConf = serde_json::from_value(conf.clone())?;
+    if let Some(dtls_conf) = &conf.dtls {
+        get_dtls_config(&conf.host, dtls_conf)?;
+    }
     Ok(())
 }
 
+async fn new_coap_client(device_conf: &DeviceConf) -> HaliaResult<Arc<DynCoAPClient>> {
+    let peer_addr = lookup_host((device_conf.host.as_str(), device_conf.port))
+        .await?
+        .next()
+        .ok_or(HaliaError::Common(format!(
+            "无法解析地址：{}",
+            device_conf.host
+        )))?;
+
+    let transport: Box<dyn ClientTransport> = match &device_conf.dtls {
+        Some(dtls_conf) => {
+            let config = get_dtls_config(&device_conf.host, dtls_conf)?;
+            Box::new(
+                DtlsConnection::try_new(UdpDtlsConfig {
+                    config,
+                    dest_addr: peer_addr,
+                })
+                .await?,
+            )
+        }
+        None => {
+            let bind_addr = match peer_addr {
+                std::net::SocketAddr::V4(_) => "0.0.0.0:0",
+                std::net::SocketAddr::V6(_) => "[::]:0",
+            };
+            let socket = UdpSocket::bind(bind_addr).await?;
+            Box::new(UdpTransport { socket, peer_addr })
+        }
+    };
+
+    Ok(Arc::new(DynCoAPClient::from_transport(transport)))
+}
+
+fn get_dtls_config(host: &str, dtls_conf: &DtlsConf) -> HaliaResult<coap_protocol::dtls::Config> {
+    match dtls_conf.mode {
+        DtlsMode::Psk => {
+            let psk_conf = dtls_conf
+                .psk
+                .as_ref()
+                .ok_or(HaliaError::Common("psk配置为空！".to_owned()))?;
+            if let types::PlainOrBase64ValueType::Base64 = psk_conf.key.typ {
+                BASE64_STANDARD
+                    .decode(&psk_conf.key.value)
+                    .map_err(|e| HaliaError::Common(format!("psk密钥错误：{}", e)))?;
+            }
+            Ok(dtls::client_psk_config(
+                psk_conf.identity.clone().into_bytes(),
+                psk_conf.key.clone().into(),
+            ))
+        }
+        DtlsMode::Cert => {
+            let cert_conf = dtls_conf
+                .cert
+                .as_ref()
+                .ok_or(HaliaError::Common("证书配置为空！".to_owned()))?;
+            let client_cert = match (&cert_conf.client_cert, &cert_conf.client_key) {
+                (Some(client_cert), Some(client_key)) => {
+                    Some((client_cert.as_str(), client_key.as_str()))
+                }
+                (None, None) => None,
+                _ => {
+                    return Err(HaliaError::Common(
+                        "客户端证书与私钥需同时配置！".to_owned(),
+                    ))
+                }
+            };
+            let server_name = cert_conf.server_name.clone().unwrap_or(host.to_owned());
+            dtls::client_cert_config(
+                cert_conf.verify,
+                server_name,
+                cert_conf.ca_cert.as_deref(),
+                client_cert,
+            )
+            .map_err(|e| HaliaError::Common(format!("证书错误：{}", e)))
+        }
+    }
+}
+
 pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     let conf: SourceConf = serde_json::from_value(conf.clone())?;
-    Source::validate_conf(conf)
+    Source::validate_conf(&conf)
 }
 
 pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
@@ -75,8 +250,11 @@ impl Device for Coap {
         self.read_err().await
     }
 
-    async fn read_source_err(&self, _source_id: &String) -> Option<String> {
-        None
+    async fn read_source_err(&self, source_id: &String) -> Option<String> {
+        match self.sources.get(source_id) {
+            Some(source) => source.read_err().await.map(|err| (*err).clone()),
+            None => None,
+        }
     }
 
     async fn read_sink_err(&self, _sink_id: &String) -> Option<String> {
@@ -88,21 +266,23 @@ impl Device for Coap {
         mode: UpdateConfMode,
         conf: serde_json::Value,
     ) -> HaliaResult<()> {
-        // let _old_conf: CoapConf = serde_json::from_value(old_conf)?;
-        // let new_conf: CoapConf = serde_json::from_value(new_conf)?;
-        // let coap_client =
-        //     Arc::new(UdpCoAPClient::new_udp((new_conf.host.clone(), new_conf.port)).await?);
-        // for mut source in self.sources.iter_mut() {
-        //     _ = source.update_coap_client(coap_client.clone());
-        // }
-        // for mut sink in self.sinks.iter_mut() {
-        //     _ = sink.update_coap_client(coap_client.clone());
-        // }
-        // self.coap_client = coap_client;
+        let device_conf: DeviceConf = match mode {
+            UpdateConfMode::CustomizeMode => serde_json::from_value(conf)?,
+            UpdateConfMode::TemplateModeCustomize | UpdateConfMode::TemplateModeTemplate => {
+                return Err(HaliaError::NotSupportResource)
+            }
+        };
+        let mut task_loop = self.stop_task_loop().await;
+        // 新客户端创建前，源和动作不再使用旧的客户端
+        task_loop.set_coap_client(None).await;
+        task_loop.device_conf = device_conf;
+        self.join_handle = Some(task_loop.start());
         Ok(())
     }
 
     async fn stop(&mut self) {
+        // 先停止任务，避免其更新已停止的源和动作
+        self.stop_task_loop().await;
         for mut source in self.sources.iter_mut() {
             source.stop().await;
         }
@@ -117,7 +297,14 @@ impl Device for Coap {
         conf: serde_json::Value,
     ) -> HaliaResult<()> {
         let conf: SourceConf = serde_json::from_value(conf)?;
-        let source = Source::new(conf, self.coap_client.clone(), self.token_manager.clone()).await;
+        let coap_client = self.coap_client.lock().await;
+        let source = Source::new(
+            source_id.clone(),
+            conf,
+            coap_client.clone(),
+            self.token_manager.clone(),
+        )
+        .await?;
         self.sources.insert(source_id, source);
         Ok(())
     }
@@ -127,7 +314,11 @@ impl Device for Coap {
         source_id: &String,
         conf: serde_json::Value,
     ) -> HaliaResult<()> {
-        todo!()
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => source.update_conf(conf).await,
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
     }
 
     async fn delete_source(&mut self, source_id: &String) -> HaliaResult<()> {
@@ -142,7 +333,8 @@ impl Device for Coap {
 
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let conf: SinkConf = serde_json::from_value(conf)?;
-        let sink = Sink::new(self.coap_client.clone(), conf, self.token_manager.clone()).await;
+        let coap_client = self.coap_client.lock().await;
+        let sink = Sink::new(coap_client.clone(), conf, self.token_manager.clone()).await;
         self.sinks.insert(sink_id, sink);
         Ok(())
     }
@@ -166,11 +358,10 @@ impl Device for Coap {
         source_id: &String,
         cnt: usize,
     ) -> HaliaResult<Vec<mpsc::UnboundedReceiver<RuleMessageBatch>>> {
-        todo!()
-        // match self.sources.get(source_id) {
-        //     Some(source) => Ok(source.mb_tx.subscribe()),
-        //     None => Err(HaliaError::NotFound(source_id.to_owned())),
-        // }
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt).await),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
     }
 
     async fn get_sink_txs(
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use coap_protocol::{
-    client::UdpCoAPClient,
+    client::DynCoAPClient,
     request::{Method, RequestBuilder},
 };
 use common::error::HaliaResult;
@@ -20,7 +20,7 @@ pub struct Sink {
 
     join_handle: Option<
         JoinHandle<(
-            Arc<UdpCoAPClient>,
+            Option<Arc<DynCoAPClient>>,
             mpsc::Receiver<MessageBatch>,
             mpsc::Receiver<()>,
             SinkConf,
@@ -37,7 +37,7 @@ impl Sink {
     }
 
     pub async fn new(
-        coap_client: Arc<UdpCoAPClient>,
+        coap_client: Option<Arc<DynCoAPClient>>,
         conf: SinkConf,
         token_manager: Arc<Mutex<TokenManager>>,
     ) -> Self {
@@ -60,19 +60,19 @@ impl Sink {
         self.join_handle = Some(join_handle);
     }
 
-    pub async fn update_coap_client(&mut self, coap_client: Arc<UdpCoAPClient>) {
+    pub async fn update_coap_client(&mut self, coap_client: Option<Arc<DynCoAPClient>>) {
         let (_, mb_rx, stop_signal_rx, conf) = self.stop().await;
         let join_handle = Self::event_loop(coap_client, conf, stop_signal_rx, mb_rx).await;
         self.join_handle = Some(join_handle);
     }
 
     async fn event_loop(
-        coap_client: Arc<UdpCoAPClient>,
+        coap_client: Option<Arc<DynCoAPClient>>,
         conf: SinkConf,
         mut stop_signal_rx: mpsc::Receiver<()>,
         mut mb_rx: mpsc::Receiver<MessageBatch>,
     ) -> JoinHandle<(
-        Arc<UdpCoAPClient>,
+        Option<Arc<DynCoAPClient>>,
         mpsc::Receiver<MessageBatch>,
         mpsc::Receiver<()>,
         SinkConf,
@@ -98,7 +98,8 @@ impl Sink {
                     }
 
                     mb = mb_rx.recv() => {
-                        if let Some(_mb) = mb {
+                        // 客户端创建前丢弃消息
+                        if let (Some(_mb), Some(coap_client)) = (mb, &coap_client) {
                             match coap_client.send(request.clone()).await {
                                 Ok(_) => {}
                                 Err(_) => {}
@@ -113,7 +114,7 @@ impl Sink {
     pub async fn stop(
         &mut self,
     ) -> (
-        Arc<UdpCoAPClient>,
+        Option<Arc<DynCoAPClient>>,
         mpsc::Receiver<MessageBatch>,
         mpsc::Receiver<()>,
         SinkConf,
@@ -1,92 +1,268 @@
-use std::io::Result as IoResult;
 use std::{sync::Arc, time::Duration};
 
+use bytes::Bytes;
 use coap_protocol::{
-    client::{ObserveMessage, UdpCoAPClient},
-    request::{Method, RequestBuilder},
+    client::{DynCoAPClient, ObserveMessage},
+    request::{
+        CoapOption, CoapRequest, ContentFormat, MessageClass, Method, Packet, RequestBuilder,
+        Status,
+    },
 };
 use common::error::{HaliaError, HaliaResult};
 use futures::lock::BiLock;
-use halia_derive::{ResourceErr, SourceRxs};
+use halia_derive::{ResourceErr, ResourceStop, SourceRxs};
 use message::{MessageBatch, RuleMessageBatch};
-use tokio::sync::mpsc::UnboundedSender;
-use tokio::sync::watch;
+use schema::Decoder;
+use std::net::SocketAddr;
 use tokio::{
     select,
-    sync::{broadcast, mpsc, oneshot, Mutex},
+    sync::{
+        mpsc::{self, UnboundedSender},
+        oneshot, watch, Mutex,
+    },
     task::JoinHandle,
-    time,
+    time::{self, Instant},
+};
+use tracing::warn;
+use types::{
+    devices::device::coap::{SourceConf, SourceMethod},
+    schema::DecodeType,
 };
-use tracing::{debug, warn};
-use types::devices::device::coap::{SourceConf, SourceMethod};
 use url::form_urlencoded;
+use utils::ErrorManager;
 
 use super::{transform_options, TokenManager};
 
-#[derive(ResourceErr, SourceRxs)]
-pub struct Source {
-    join_handle: Option<JoinHandle<TaskLoop>>,
-
-    get_stop_signal_tx: Option<mpsc::Sender<()>>,
-    observe_stop_signal_tx: Option<oneshot::Sender<ObserveMessage>>,
+// 通知未携带Max-Age选项时的默认值，秒，RFC 7252 5.10.5
+const DEFAULT_MAX_AGE: u64 = 60;
 
+#[derive(ResourceErr, ResourceStop, SourceRxs)]
+pub struct Source {
+    pub source_conf: SourceConf,
+    stop_signal_tx: watch::Sender<()>,
     err: BiLock<Option<Arc<String>>>,
-
+    join_handle: Option<JoinHandle<TaskLoop>>,
     mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
 }
 
 pub struct TaskLoop {
     source_conf: SourceConf,
-    coap_client: Arc<UdpCoAPClient>,
-    stop_signal_rx: watch::Receiver<()>,
+    // 设备的客户端创建成功前为空
+    coap_client: Option<Arc<DynCoAPClient>>,
     token_manager: Arc<Mutex<TokenManager>>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+    error_manager: ErrorManager,
+    decoders: Decoders,
 }
 
-impl TaskLoop {
-    fn new(
-        source_conf: SourceConf,
-        coap_client: Arc<UdpCoAPClient>,
-        err: BiLock<Option<Arc<String>>>,
-        mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
-        token_manager: Arc<Mutex<TokenManager>>,
-    ) -> Self {
-        todo!()
+// 响应的Content-Format与配置的解码方式冲突时改用JSON解码，其余情况使用配置的解码器
+struct Decoders {
+    decode_type: DecodeType,
+    decoder: Box<dyn Decoder>,
+    json_decoder: Box<dyn Decoder>,
+}
+
+impl Decoders {
+    async fn new(source_conf: &SourceConf) -> HaliaResult<Self> {
+        Ok(Self {
+            decode_type: source_conf.decode_type.clone(),
+            decoder: schema::new_decoder(&source_conf.decode_type, &source_conf.schema_id).await?,
+            json_decoder: schema::new_decoder(&DecodeType::Json, &None).await?,
+        })
     }
 
-    fn start(
-        mut self,
-    ) -> (
-        JoinHandle<Self>,
-        Option<mpsc::Sender<()>>,
-        Option<oneshot::Sender<ObserveMessage>>,
-    ) {
-        match self.source_conf.method {
-            SourceMethod::Get => todo!(),
-            SourceMethod::Observe => todo!(),
+    fn decode(&self, packet: Packet) -> anyhow::Result<MessageBatch> {
+        let is_json = packet
+            .get_content_format()
+            .map(is_json_content_format)
+            .unwrap_or(false);
+        let payload = Bytes::from(packet.payload);
+        // text/plain和application/octet-stream为通用格式，可能承载protobuf等二进制数据，
+        // 不作为切换解码器的依据
+        let conflicting = matches!(
+            self.decode_type,
+            DecodeType::Csv | DecodeType::Avro | DecodeType::Toml | DecodeType::Protobuf
+        );
+        if is_json && conflicting {
+            self.json_decoder.decode(payload)
+        } else {
+            self.decoder.decode(payload)
         }
-        todo!()
     }
+}
 
-    fn start_get(mut self) -> JoinHandle<Self> {
+fn is_json_content_format(content_format: ContentFormat) -> bool {
+    matches!(
+        content_format,
+        ContentFormat::ApplicationJSON
+            | ContentFormat::ApplicationSenmlJSON
+            | ContentFormat::ApplicationSensmlJSON
+            | ContentFormat::ApplicationCoapGroupJson
+    )
+}
+
+// 2.05 Content和2.03 Valid以外的响应码表示请求失败，负载为错误信息而非数据
+fn is_success(packet: &Packet) -> bool {
+    matches!(
+        packet.header.code,
+        MessageClass::Response(Status::Content | Status::Valid)
+    )
+}
+
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            // 客户端创建后由设备重新启动任务
+            let coap_client = match self.coap_client.clone() {
+                Some(coap_client) => coap_client,
+                None => {
+                    _ = self.stop_signal_rx.changed().await;
+                    return self;
+                }
+            };
+            match self.source_conf.method {
+                SourceMethod::Get => self.run_get(&coap_client).await,
+                SourceMethod::Observe => self.run_observe(&coap_client).await,
+            }
+            self
+        })
+    }
+
+    async fn run_get(&mut self, coap_client: &DynCoAPClient) {
         let mut interval = time::interval(Duration::from_millis(
             self.source_conf.get.as_ref().unwrap().interval,
         ));
-        tokio::spawn(async move {
-            loop {
+        loop {
+            select! {
+                _ = self.stop_signal_rx.changed() => {
+                    return;
+                }
+
+                _ = interval.tick() => {
+                    self.coap_get(coap_client).await;
+                }
+            }
+        }
+    }
+
+    async fn coap_get(&mut self, coap_client: &DynCoAPClient) {
+        if self.mb_txs.lock().await.is_empty() {
+            return;
+        }
+
+        let token = self.token_manager.lock().await.acquire();
+        let request = self.build_request(token.clone());
+        let res = coap_client.send(request).await;
+        self.token_manager.lock().await.release(token);
+
+        match res {
+            Ok(response) => match response.get_status() {
+                Status::Content | Status::Valid => {
+                    self.error_manager.set_ok().await;
+                    self.handle_packet(response.message).await;
+                }
+                status => {
+                    self.error_manager
+                        .set_err(Arc::new(format!("响应错误：{:?}", status)))
+                        .await;
+                }
+            },
+            Err(e) => {
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+            }
+        }
+    }
+
+    async fn run_observe(&mut self, coap_client: &DynCoAPClient) {
+        // 通知在coap客户端的任务中回调，通过通道转交给当前任务
+        let (packet_tx, mut packet_rx) = mpsc::unbounded_channel();
+        let retry_interval =
+            Duration::from_secs(self.source_conf.observe.as_ref().unwrap().retry_interval);
+
+        loop {
+            // 收到错误响应后服务端已移除观察关系，等待重新注册
+            let mut rejected = false;
+            let token = self.token_manager.lock().await.acquire();
+            let observe_tx = self
+                .register_observe(coap_client, &packet_tx, token.clone())
+                .await;
+            // 注册成功后超过Max-Age未收到通知，认为服务端已丢失观察关系，重新注册
+            let deadline = match &observe_tx {
+                Some(_) => Duration::from_secs(DEFAULT_MAX_AGE) + retry_interval,
+                None => retry_interval,
+            };
+            let sleep = time::sleep(deadline);
+            tokio::pin!(sleep);
+
+            let stopped = loop {
                 select! {
                     _ = self.stop_signal_rx.changed() => {
-                        return self;
+                        break true;
+                    }
+
+                    Some(packet) = packet_rx.recv(), if !rejected => {
+                        if !is_success(&packet) {
+                            self.error_manager
+                                .set_err(Arc::new(format!("observe通知错误：{}", packet.header.code)))
+                                .await;
+                            rejected = true;
+                            sleep.as_mut().reset(Instant::now() + retry_interval);
+                            continue;
+                        }
+
+                        let max_age = get_max_age(&packet);
+                        sleep.as_mut().reset(Instant::now() + Duration::from_secs(max_age) + retry_interval);
+                        self.error_manager.set_ok().await;
+                        self.handle_packet(packet).await;
                     }
 
-                    _ = interval.tick() => {
-                        self.coap_get().await;
+                    _ = &mut sleep => {
+                        if observe_tx.is_some() && !rejected {
+                            self.error_manager
+                                .set_err(Arc::new("observe通知超时！".to_owned()))
+                                .await;
+                        }
+                        break false;
                     }
                 }
+            };
+
+            if let Some(observe_tx) = observe_tx {
+                let _ = observe_tx.send(ObserveMessage::Terminate);
             }
-        })
+            self.token_manager.lock().await.release(token);
+
+            if stopped {
+                return;
+            }
+        }
+    }
+
+    async fn register_observe(
+        &mut self,
+        coap_client: &DynCoAPClient,
+        packet_tx: &UnboundedSender<Packet>,
+        token: Vec<u8>,
+    ) -> Option<oneshot::Sender<ObserveMessage>> {
+        let request = self.build_request(token);
+        let packet_tx = packet_tx.clone();
+        match coap_client
+            .observe_with(request, move |packet| {
+                let _ = packet_tx.send(packet);
+            })
+            .await
+        {
+            Ok(observe_tx) => Some(observe_tx),
+            Err(e) => {
+                warn!("coap observe err:{}", e);
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+                None
+            }
+        }
     }
 
-    async fn coap_get(&self) {
+    fn build_request(&self, token: Vec<u8>) -> CoapRequest<SocketAddr> {
         let mut request_builder = RequestBuilder::new(&self.source_conf.path, Method::Get);
 
         if let Some(querys) = &self.source_conf.querys {
@@ -96,191 +272,203 @@ impl TaskLoop {
             request_builder = request_builder.queries(Some(encoded_params.into_bytes()));
         }
 
+        // 在validate_conf中已校验
         if let Some(options) = &self.source_conf.options {
             let options = transform_options(options).unwrap();
             request_builder = request_builder.options(options);
         }
 
-        let token = self.token_manager.lock().await.acquire();
-        let request = request_builder.token(Some(token.clone())).build();
-        match self.coap_client.send(request).await {
-            Ok(_) => debug!("success"),
-            Err(e) => warn!("{:?}", e),
-        }
-        self.token_manager.lock().await.release(token);
+        request_builder.token(Some(token)).build()
     }
 
-    async fn start_observe(
-        &self,
-        mb_tx: broadcast::Sender<MessageBatch>,
-        token: Vec<u8>,
-    ) -> IoResult<oneshot::Sender<ObserveMessage>> {
-        let request_builder =
-            RequestBuilder::new(&self.source_conf.path, Method::Get).token(Some(token));
-        let request = request_builder.build();
-
-        // 加入重试功能
-        self.coap_client
-            .observe_with(request, move |msg| {
-                debug!("{:?}", msg);
-                if mb_tx.receiver_count() > 0 {
-                    _ = mb_tx.send(MessageBatch::from_json(msg.payload.into()).unwrap());
+    async fn handle_packet(&mut self, packet: Packet) {
+        if packet.payload.is_empty() {
+            return;
+        }
+
+        let mb = match self.decoders.decode(packet) {
+            Ok(mb) => mb,
+            Err(e) => {
+                warn!("decode err :{}", e);
+                return;
+            }
+        };
+
+        let mut mb_txs = self.mb_txs.lock().await;
+        match mb_txs.len() {
+            0 => {}
+            1 => {
+                let mb = RuleMessageBatch::Owned(mb);
+                if mb_txs[0].send(mb).is_err() {
+                    mb_txs.remove(0);
                 }
-            })
-            .await
+            }
+            _ => {
+                let mb = RuleMessageBatch::Arc(Arc::new(mb));
+                mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+            }
+        }
+    }
+}
+
+// Max-Age为大端序的无符号整数
+fn get_max_age(packet: &Packet) -> u64 {
+    match packet
+        .get_option(CoapOption::MaxAge)
+        .and_then(|values| values.front())
+    {
+        Some(value) => value
+            .iter()
+            .fold(0u64, |acc, byte| (acc << 8) | *byte as u64),
+        None => DEFAULT_MAX_AGE,
     }
 }
 
 impl Source {
-    pub fn validate_conf(conf: SourceConf) -> HaliaResult<()> {
+    pub fn validate_conf(conf: &SourceConf) -> HaliaResult<()> {
         match conf.method {
-            SourceMethod::Get => {
-                if conf.get.is_none() {
-                    return Err(HaliaError::Common("get请求为空！".to_owned()));
+            SourceMethod::Get => match &conf.get {
+                Some(get) => {
+                    if get.interval == 0 {
+                        return Err(HaliaError::Common("请求间隔必须大于0！".to_owned()));
+                    }
                 }
-            }
-            SourceMethod::Observe => {
-                if conf.observe.is_none() {
-                    return Err(HaliaError::Common("observe配置为空！".to_owned()));
+                None => return Err(HaliaError::Common("get请求为空！".to_owned())),
+            },
+            SourceMethod::Observe => match &conf.observe {
+                Some(observe) => {
+                    if observe.retry_interval == 0 {
+                        return Err(HaliaError::Common("重新注册间隔必须大于0！".to_owned()));
+                    }
                 }
-            }
+                None => return Err(HaliaError::Common("observe配置为空！".to_owned())),
+            },
+        }
+
+        if let Some(options) = &conf.options {
+            transform_options(options)
+                .map_err(|e| HaliaError::Common(format!("options错误：{}", e)))?;
         }
 
         Ok(())
     }
 
     pub async fn new(
+        source_id: String,
         source_conf: SourceConf,
-        coap_client: Arc<UdpCoAPClient>,
+        coap_client: Option<Arc<DynCoAPClient>>,
         token_manager: Arc<Mutex<TokenManager>>,
-    ) -> Self {
+    ) -> HaliaResult<Self> {
+        let decoders = Decoders::new(&source_conf).await?;
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
         let (err1, err2) = BiLock::new(None);
         let (mb_txs1, mb_txs2) = BiLock::new(vec![]);
 
-        let task_loop = TaskLoop::new(source_conf, coap_client, err1, mb_txs1, token_manager);
-        let (join_handle, get_stop_signal_tx, observe_stop_signal_tx) = task_loop.start();
+        let error_manager = ErrorManager::new(
+            utils::error_manager::ResourceType::DeviceSource,
+            source_id,
+            err1,
+        );
+        let task_loop = TaskLoop {
+            source_conf: source_conf.clone(),
+            coap_client,
+            token_manager,
+            stop_signal_rx,
+            mb_txs: mb_txs1,
+            error_manager,
+            decoders,
+        };
+        let join_handle = task_loop.start();
 
-        Source {
+        Ok(Self {
+            source_conf,
+            stop_signal_tx,
+            err: err2,
             join_handle: Some(join_handle),
-            get_stop_signal_tx,
-            observe_stop_signal_tx,
             mb_txs: mb_txs2,
-            err: err2,
-        }
+        })
     }
 
-    pub async fn update_conf(&mut self, conf: SourceConf) -> HaliaResult<()> {
-        // match (&old_conf.method, &new_conf.method) {
-        //     (SourceMethod::Get, SourceMethod::Get) => {
-        //         let (coap_client, stop_signal_rx, token_manager, _) = self.stop_get().await;
-        //         let join_handle = Self::start_get(
-        //             new_conf.get.unwrap(),
-        //             coap_client,
-        //             stop_signal_rx,
-        //             token_manager,
-        //         )
-        //         .await;
-        //         self.join_handle = Some(join_handle);
-        //     }
-        //     (SourceMethod::Get, SourceMethod::Observe) => {
-        //         let (coap_client, _, token_manager, _) = self.stop_get().await;
-        //         self.stop_signal_tx = None;
-        //         self.join_handle = None;
-
-        //         let observe_conf = new_conf.observe.unwrap();
-        //         let token = token_manager.lock().await.acquire();
-        //         match Self::start_observe(
-        //             &coap_client,
-        //             &observe_conf,
-        //             self.mb_tx.clone(),
-        //             token.clone(),
-        //         )
-        //         .await
-        //         {
-        //             Ok(observe_tx) => self.observe_tx = Some(observe_tx),
-        //             Err(e) => {
-        //                 warn!("start observe err:{:?}", e);
-        //             }
-        //         }
-        //         self.oberserve_conf = Some(observe_conf);
-        //         self.coap_client = Some(coap_client);
-        //         self.token_manager = Some(token_manager);
-        //         self.token = Some(token);
-        //     }
-        //     (SourceMethod::Observe, SourceMethod::Get) => {
-        //         self.stop_obeserve().await;
-        //         self.observe_tx = None;
-        //         let token_manager = self.token_manager.take().unwrap();
-        //         token_manager
-        //             .lock()
-        //             .await
-        //             .release(self.token.take().unwrap());
-
-        //         let (stop_signal_tx, stop_signal_rx) = mpsc::channel(1);
-        //         self.stop_signal_tx = Some(stop_signal_tx);
-        //         let coap_client = self.coap_client.take().unwrap();
-        //         let join_handle = Self::start_get(
-        //             new_conf.get.unwrap(),
-        //             coap_client,
-        //             stop_signal_rx,
-        //             token_manager,
-        //         )
-        //         .await;
-        //         self.join_handle = Some(join_handle);
-        //     }
-        //     (SourceMethod::Observe, SourceMethod::Observe) => {
-        //         self.stop_obeserve().await;
-        //         let observe_conf = new_conf.observe.unwrap();
-        //         Self::start_observe(
-        //             self.coap_client.as_ref().unwrap(),
-        //             &observe_conf,
-        //             self.mb_tx.clone(),
-        //             self.token.as_ref().unwrap().clone(),
-        //         )
-        //         .await;
-        //         self.oberserve_conf = Some(observe_conf);
-        //     }
-        // }
-        todo!()
-
-        // Ok(())
+    pub async fn update_conf(&mut self, source_conf: SourceConf) -> HaliaResult<()> {
+        let decoders = Decoders::new(&source_conf).await?;
+        let mut task_loop = self.stop().await;
+        task_loop.source_conf = source_conf.clone();
+        task_loop.decoders = decoders;
+        self.join_handle = Some(task_loop.start());
+        self.source_conf = source_conf;
+        Ok(())
     }
 
-    pub async fn update_coap_client(&mut self, coap_client: Arc<UdpCoAPClient>) -> HaliaResult<()> {
+    pub async fn update_coap_client(&mut self, coap_client: Option<Arc<DynCoAPClient>>) {
         let mut task_loop = self.stop().await;
         task_loop.coap_client = coap_client;
-        let (join_handle, _, _) = task_loop.start();
-        self.join_handle = Some(join_handle);
+        self.join_handle = Some(task_loop.start());
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_max_age() {
+        let mut packet = Packet::new();
+        assert_eq!(get_max_age(&packet), DEFAULT_MAX_AGE);
+
+        // Max-Age为大端序的无符号整数
+        packet.add_option(CoapOption::MaxAge, vec![0x01, 0x2C]);
+        assert_eq!(get_max_age(&packet), 300);
+
+        let mut packet = Packet::new();
+        packet.add_option(CoapOption::MaxAge, vec![]);
+        assert_eq!(get_max_age(&packet), 0);
     }
 
-    pub async fn stop(&mut self) -> TaskLoop {
-        if let Some(stop_tx) = &self.get_stop_signal_tx {
-            stop_tx.send(()).await.unwrap();
-        } else {
-            let stop_tx = self.observe_stop_signal_tx.take().unwrap();
-            stop_tx.send(ObserveMessage::Terminate).unwrap();
-        }
+    fn new_packet(status: Status, content_format: ContentFormat, payload: &[u8]) -> Packet {
+        let mut packet = Packet::new();
+        packet.header.code = MessageClass::Response(status);
+        packet.set_content_format(content_format);
+        packet.payload = payload.to_vec();
+        packet
+    }
+
+    #[tokio::test]
+    async fn test_decode() {
+        // 以raw解码器代替需要schema的protobuf解码器
+        let decoders = Decoders {
+            decode_type: DecodeType::Protobuf,
+            decoder: schema::new_decoder(&DecodeType::Raw, &None).await.unwrap(),
+            json_decoder: schema::new_decoder(&DecodeType::Json, &None).await.unwrap(),
+        };
+        let decode = |content_format, payload: &[u8]| {
+            let mb = decoders
+                .decode(new_packet(Status::Content, content_format, payload))
+                .unwrap();
+            mb.get_messages()[0].clone()
+        };
+
+        // 通用格式仍使用配置的解码器
+        let msg = decode(ContentFormat::ApplicationOctetStream, &[0x08, 0x01]);
+        assert!(msg.get("raw_data").is_some());
+        let msg = decode(ContentFormat::TextPlain, b"abc");
+        assert!(msg.get("raw_data").is_some());
+
+        // 与配置冲突的JSON格式改用JSON解码
+        let msg = decode(ContentFormat::ApplicationJSON, br#"{"a":1}"#);
+        assert!(msg.get("a").is_some());
+    }
 
-        self.join_handle.take().unwrap().await.unwrap()
-        // match (&self.get_stop_signal_tx, &mut self.observe_stop_tx) {
-        //     (None, Some(stop_tx)) => {
-        //         if let Err(e) = stop_tx.send(ObserveMessage::Terminate) {
-        //             warn!("stop send msg err:{:?}", e);
-        //         }
-
-        //         // self.token_manager
-        //         //     .as_ref()
-        //         //     .unwrap()
-        //         //     .lock()
-        //         //     .await
-        //         //     .release(self.token.take().unwrap());
-        //     }
-        //     (Some(stop_tx), None) => {
-        //         stop_tx.send(()).await.unwrap();
-        //     }
-        //     _ => unreachable!(),
-        // }
+    #[test]
+    fn test_is_success() {
+        assert!(is_success(&new_packet(
+            Status::Content,
+            ContentFormat::TextPlain,
+            b""
+        )));
+        assert!(!is_success(&new_packet(
+            Status::NotFound,
+            ContentFormat::TextPlain,
+            b"not found"
+        )));
     }
 }
@@ -20,6 +20,7 @@ pub async fn create_device_template(req: CreateReq) -> HaliaResult<()> {
         DeviceType::Opcua => opcua::template::validate_device_template_conf(req.conf.clone())?,
         // DeviceType::Coap => coap::validate_conf(&req.conf.ext)?,
         DeviceType::Coap => todo!(),
+        DeviceType::S7 => return Err(HaliaError::NotSupportResource),
     }
 
     let id = common::get_id();
@@ -8,12 +8,18 @@ use tokio::sync::mpsc;
 use tracing::debug;
 use types::{
     devices::{
-        device::QueryParams, ConfType, DeviceSourceGroupCreateReq, DeviceSourceGroupListItem,
-        DeviceSourceGroupListResp, DeviceSourceGroupQueryParams, DeviceSourceGroupReadResp,
-        DeviceSourceGroupUpdateReq, DeviceType, ListDevicesResp, ListSourcesSinksItem,
-        ListSourcesSinksResp, Metadatas, QueryRuleInfoParams, ReadDeviceResp, ReadSourceSinkResp,
-        RuleInfoDevice, RuleInfoResp, RuleInfoSourceSink, SourceSinkCreateUpdateReq,
-        SourceSinkQueryParams,
+        device::{
+            opcua::{
+                BrowseNextReq, BrowseReq, BrowseResp, ClientCertificateResp,
+                TranslateBrowsePathReq, TranslateBrowsePathResp,
+            },
+            QueryParams,
+        },
+        ConfType, DeviceSourceGroupCreateReq, DeviceSourceGroupListItem, DeviceSourceGroupListResp,
+        DeviceSourceGroupQueryParams, DeviceSourceGroupReadResp, DeviceSourceGroupUpdateReq,
+        DeviceType, ListDevicesResp, ListSourcesSinksItem, ListSourcesSinksResp, Metadatas,
+        QueryRuleInfoParams, ReadDeviceResp, ReadSourceSinkResp, RuleInfoDevice, RuleInfoResp,
+        RuleInfoSourceSink, SourceSinkCreateUpdateReq, SourceSinkQueryParams,
     },
     Pagination, SourceSinkType, Status, Summary, Value,
 };
@@ -22,6 +28,7 @@ pub mod coap;
 pub mod device_template;
 pub mod modbus;
 pub mod opcua;
+pub mod s7;
 pub mod source_group;
 
 static GLOBAL_DEVICE_MANAGER: LazyLock<DashMap<String, Box<dyn Device>>> =
@@ -52,6 +59,22 @@ pub(crate) trait Device: Send + Sync {
     async fn write_source_value(&mut self, _source_id: String, _req: Value) -> HaliaResult<()> {
         return Err(HaliaError::Common("不支持写入数据。".into()));
     }
+    // 浏览地址空间，目前仅opcua支持
+    async fn browse(&self, _req: BrowseReq) -> HaliaResult<BrowseResp> {
+        Err(HaliaError::NotSupportResource)
+    }
+    async fn browse_next(&self, _req: BrowseNextReq) -> HaliaResult<BrowseResp> {
+        Err(HaliaError::NotSupportResource)
+    }
+    async fn translate_browse_path(
+        &self,
+        _req: TranslateBrowsePathReq,
+    ) -> HaliaResult<TranslateBrowsePathResp> {
+        Err(HaliaError::NotSupportResource)
+    }
+    async fn get_client_certificate(&self) -> HaliaResult<ClientCertificateResp> {
+        Err(HaliaError::NotSupportResource)
+    }
     async fn delete_source(&mut self, source_id: &String) -> HaliaResult<()>;
 
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()>;
@@ -140,8 +163,11 @@ pub async fn create_device(
                     DeviceType::Modbus => {
                         modbus::template::validate_device_customize_conf(req.conf.clone())?
                     }
-                    DeviceType::Opcua => todo!(),
-                    DeviceType::Coap => todo!(),
+                    DeviceType::Opcua => {
+                        opcua::template::validate_device_customize_conf(req.conf.clone())?
+                    }
+                    DeviceType::Coap => return Err(HaliaError::NotSupportResource),
+                    DeviceType::S7 => return Err(HaliaError::NotSupportResource),
                 }
                 let device_template_sources =
                     storage::device::template_source_sink::read_sources_by_device_template_id(
@@ -183,6 +209,7 @@ pub async fn create_device(
             DeviceType::Modbus => modbus::validate_conf(&req.conf)?,
             DeviceType::Opcua => opcua::validate_conf(&req.conf)?,
             DeviceType::Coap => coap::validate_conf(&req.conf)?,
+            DeviceType::S7 => s7::validate_conf(&req.conf)?,
         },
     }
 
@@ -274,8 +301,32 @@ pub async fn list_devices(
                     }
                 }
             },
-            DeviceType::Opcua => todo!(),
-            DeviceType::Coap => todo!(),
+            DeviceType::Opcua => match &db_device.conf_type {
+                ConfType::Template => {
+                    let customize_conf: types::devices::device_template::opcua::CustomizeConf =
+                        serde_json::from_value(db_device.conf)?;
+                    format!("opc.tcp://{}:{}", customize_conf.host, customize_conf.port)
+                }
+                ConfType::Customize => {
+                    let conf: types::devices::device::opcua::OpcuaConf =
+                        serde_json::from_value(db_device.conf)?;
+                    format!("opc.tcp://{}:{}{}", conf.host, conf.port, conf.path)
+                }
+            },
+            DeviceType::Coap => {
+                let conf: types::devices::device::coap::DeviceConf =
+                    serde_json::from_value(db_device.conf)?;
+                let scheme = match conf.dtls {
+                    Some(_) => "coaps",
+                    None => "coap",
+                };
+                format!("{}://{}:{}", scheme, conf.host, conf.port)
+            }
+            DeviceType::S7 => {
+                let conf: types::devices::device::s7::S7Conf =
+                    serde_json::from_value(db_device.conf)?;
+                format!("tcp://{}:{}", conf.host, conf.port)
+            }
         };
 
         let device = types::devices::ListDevicesItem {
@@ -364,16 +415,24 @@ pub async fn start_device(device_id: String) -> HaliaResult<()> {
                     DeviceType::Modbus => {
                         modbus::new_by_template(db_device.id.clone(), db_device.conf, template_conf)
                     }
-                    DeviceType::Opcua => todo!(),
-                    DeviceType::Coap => todo!(),
+                    DeviceType::Opcua => opcua::new_by_template_conf(
+                        db_device.id.clone(),
+                        db_device.conf,
+                        template_conf,
+                    ),
+                    DeviceType::Coap => return Err(HaliaError::NotSupportResource),
+                    DeviceType::S7 => return Err(HaliaError::NotSupportResource),
                 }
             }
             None => unreachable!(),
         },
         ConfType::Customize => match db_device.device_type {
             DeviceType::Modbus => modbus::new_by_customize(db_device.id.clone(), db_device.conf),
-            DeviceType::Opcua => todo!(),
-            DeviceType::Coap => todo!(),
+            DeviceType::Opcua => opcua::new_by_customize_conf(db_device.id.clone(), db_device.conf),
+            DeviceType::Coap => {
+                coap::new_by_customize(db_device.id.clone(), db_device.conf).await?
+            }
+            DeviceType::S7 => s7::new_by_customize(db_device.id.clone(), db_device.conf),
         },
     };
 
@@ -443,6 +502,12 @@ pub async fn device_create_source(
     if conf_type == ConfType::Template {
         return Err(HaliaError::Common("模板设备不能创建源".to_string()));
     }
+    match storage::device::device::read_device_type(&device_id).await? {
+        DeviceType::Modbus => modbus::validate_source_conf(&req.conf)?,
+        DeviceType::S7 => s7::validate_source_conf(&req.conf)?,
+        DeviceType::Coap => coap::validate_source_conf(&req.conf)?,
+        _ => {}
+    }
 
     let (source_id, status) = create_source(&device_id, req.conf.clone()).await?;
     storage::device::source_sink::device_insert_source(&source_id, status, &device_id, req).await?;
@@ -558,6 +623,7 @@ pub async fn read_source(device_id: String, source_id: String) -> HaliaResult<Re
         status: db_source.status,
         err,
         rule_ref_cnt,
+        retain_depth: None,
     })
 }
 
@@ -571,6 +637,10 @@ pub async fn device_update_source(
         return Err(HaliaError::Common("模板设备不能修改源。".to_string()));
     }
 
+    if storage::device::device::read_device_type(&device_id).await? == DeviceType::Modbus {
+        modbus::validate_source_conf(&req.conf)?;
+    }
+
     let db_source = storage::device::source_sink::read_one(&source_id).await?;
     if req.conf != db_source.conf {
         update_source_conf(device_id, source_id.clone(), req.conf.clone()).await?;
@@ -619,6 +689,41 @@ pub async fn write_source_value(
         .await
 }
 
+pub async fn browse(device_id: String, req: BrowseReq) -> HaliaResult<BrowseResp> {
+    GLOBAL_DEVICE_MANAGER
+        .get(&device_id)
+        .ok_or(HaliaError::NotFound(device_id))?
+        .browse(req)
+        .await
+}
+
+pub async fn browse_next(device_id: String, req: BrowseNextReq) -> HaliaResult<BrowseResp> {
+    GLOBAL_DEVICE_MANAGER
+        .get(&device_id)
+        .ok_or(HaliaError::NotFound(device_id))?
+        .browse_next(req)
+        .await
+}
+
+pub async fn translate_browse_path(
+    device_id: String,
+    req: TranslateBrowsePathReq,
+) -> HaliaResult<TranslateBrowsePathResp> {
+    GLOBAL_DEVICE_MANAGER
+        .get(&device_id)
+        .ok_or(HaliaError::NotFound(device_id))?
+        .translate_browse_path(req)
+        .await
+}
+
+pub async fn get_client_certificate(device_id: String) -> HaliaResult<ClientCertificateResp> {
+    GLOBAL_DEVICE_MANAGER
+        .get(&device_id)
+        .ok_or(HaliaError::NotFound(device_id))?
+        .get_client_certificate()
+        .await
+}
+
 pub async fn device_delete_source(device_id: String, source_id: String) -> HaliaResult<()> {
     if storage::device::device::read_conf_type(&device_id).await? == ConfType::Template {
         return Err(HaliaError::Common("模板设备不能删除源".to_string()));
@@ -760,6 +865,9 @@ pub async fn device_create_sink(
     if conf_type == ConfType::Template {
         return Err(HaliaError::Common("模板设备不能创建动作。".to_string()));
     }
+    if storage::device::device::read_device_type(&device_id).await? == DeviceType::S7 {
+        s7::validate_sink_conf(&req.conf)?;
+    }
 
     let (sink_id, status) = create_sink(&device_id, req.conf.clone()).await?;
     storage::device::source_sink::device_insert_sink(&sink_id, status, &device_id, req).await?;
@@ -850,6 +958,7 @@ pub async fn read_sink(device_id: String, sink_id: String) -> HaliaResult<ReadSo
         status: db_sink.status,
         err,
         rule_ref_cnt,
+        retain_depth: common::sink_message_retain::get_depth(&sink_id),
     })
 }
 
@@ -899,6 +1008,7 @@ pub(crate) async fn delete_sink(device_id: String, sink_id: String) -> HaliaResu
     if let Some(mut device) = GLOBAL_DEVICE_MANAGER.get_mut(&device_id) {
         device.delete_sink(&sink_id).await?;
     }
+    common::sink_message_retain::remove_disk_data(&sink_id);
 
     storage::device::source_sink::delete_by_id(&sink_id).await?;
 
@@ -961,7 +1071,8 @@ async fn get_source_conf(
                         let conf = serde_json::to_value(conf)?;
                         Ok(conf)
                     }
-                    DeviceType::Coap => todo!(),
+                    DeviceType::Coap => unreachable!(),
+                    DeviceType::S7 => unreachable!(),
                 }
             }
             None => unreachable!(),
@@ -1041,7 +1152,8 @@ async fn get_sink_conf(
                             let conf = serde_json::to_value(conf)?;
                             Ok(conf)
                         }
-                        DeviceType::Coap => todo!(),
+                        DeviceType::Coap => unreachable!(),
+                        DeviceType::S7 => unreachable!(),
                     }
                 }
                 None => unreachable!(),
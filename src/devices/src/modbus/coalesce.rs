@@ -0,0 +1,274 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::{
+    select,
+    sync::{broadcast, mpsc::UnboundedSender, watch},
+};
+use types::devices::device::modbus::{Area, Coalesce};
+
+use super::{
+    source::{aligned_interval, tick},
+    ReadEvent,
+};
+
+pub(crate) struct Point {
+    pub id: Arc<String>,
+    pub slave: u8,
+    pub area: Area,
+    pub address: u16,
+    pub quantity: u16,
+}
+
+// 一次合并后的连续读取
+pub(crate) struct Block {
+    pub slave: u8,
+    pub area: Area,
+    pub address: u16,
+    pub quantity: u16,
+    pub points: Vec<Point>,
+}
+
+impl Block {
+    fn new(point: Point) -> Self {
+        Self {
+            slave: point.slave,
+            area: point.area,
+            address: point.address,
+            quantity: point.quantity,
+            points: vec![point],
+        }
+    }
+
+    fn end(&self) -> u32 {
+        self.address as u32 + self.quantity as u32
+    }
+
+    // 从整块数据中取出点位对应的数据，线圈类区域按位重新打包
+    pub fn slice(&self, data: &[u8], point: &Point) -> Option<Vec<u8>> {
+        let offset = (point.address - self.address) as usize;
+        let quantity = point.quantity as usize;
+        match self.area {
+            Area::InputRegisters | Area::HoldingRegisters => data
+                .get(offset * 2..(offset + quantity) * 2)
+                .map(|data| data.to_vec()),
+            Area::InputDiscrete | Area::Coils => {
+                if data.len() * 8 < offset + quantity {
+                    return None;
+                }
+                let mut bits = vec![0; quantity.div_ceil(8)];
+                for i in 0..quantity {
+                    let pos = offset + i;
+                    if (data[pos / 8] >> (pos % 8)) & 1 == 1 {
+                        bits[i / 8] |= 1 << (i % 8);
+                    }
+                }
+                Some(bits)
+            }
+        }
+    }
+}
+
+fn area_index(area: &Area) -> u8 {
+    match area {
+        Area::InputDiscrete => 0,
+        Area::Coils => 1,
+        Area::InputRegisters => 2,
+        Area::HoldingRegisters => 3,
+    }
+}
+
+// 将同一从站、同一区域的点位按地址排序后合并为连续块，
+// 点位之间的间隔不超过max_gap，且整块数量不超过单次请求的上限
+pub(crate) fn plan(mut points: Vec<Point>, conf: &Coalesce) -> Vec<Block> {
+    points.sort_by_key(|point| (point.slave, area_index(&point.area), point.address));
+
+    let mut blocks: Vec<Block> = vec![];
+    for point in points {
+        let max_quantity = match point.area {
+            Area::InputDiscrete | Area::Coils => conf.max_bits(),
+            Area::InputRegisters | Area::HoldingRegisters => conf.max_registers(),
+        } as u32;
+
+        if let Some(block) = blocks.last_mut() {
+            let point_end = point.address as u32 + point.quantity as u32;
+            let new_end = block.end().max(point_end);
+            if block.slave == point.slave
+                && block.area == point.area
+                && point.address as u32 <= block.end() + conf.max_gap as u32
+                && new_end - block.address as u32 <= max_quantity
+            {
+                block.quantity = (new_end - block.address as u32) as u16;
+                block.points.push(point);
+                continue;
+            }
+        }
+
+        blocks.push(Block::new(point));
+    }
+
+    blocks
+}
+
+// 按点位频率分组后分别合并，频率为0的点位不读取
+pub(crate) fn plan_groups(points: Vec<(u64, Point)>, conf: &Coalesce) -> HashMap<u64, Vec<Block>> {
+    let mut groups: HashMap<u64, Vec<Point>> = HashMap::new();
+    for (interval, point) in points {
+        if interval > 0 {
+            groups.entry(interval).or_default().push(point);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(interval, points)| (interval, plan(points, conf)))
+        .collect()
+}
+
+// 相同频率的点位共用一个定时器，每次触发读取创建时已合并好的块
+// 点位变更时整组重建，Group被丢弃后定时任务随之退出
+pub(crate) struct Group {
+    _stop_signal_tx: watch::Sender<()>,
+}
+
+impl Group {
+    pub fn new(
+        interval: u64,
+        blocks: Vec<Block>,
+        read_tx: UnboundedSender<ReadEvent>,
+        mut device_err_rx: broadcast::Receiver<bool>,
+    ) -> Self {
+        let (stop_signal_tx, mut stop_signal_rx) = watch::channel(());
+        let blocks = Arc::new(blocks);
+        let mut interval = aligned_interval(interval);
+        let mut device_err = false;
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = stop_signal_rx.changed() => {
+                        return;
+                    }
+
+                    _ = tick(&mut interval) => {
+                        if !device_err {
+                            _ = read_tx.send(ReadEvent::Blocks(blocks.clone()));
+                        }
+                    }
+
+                    Ok(err) = device_err_rx.recv() => {
+                        device_err = err;
+                    }
+                }
+            }
+        });
+
+        Self {
+            _stop_signal_tx: stop_signal_tx,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use types::devices::device::modbus::{Area, Coalesce};
+
+    use super::{plan, plan_groups, Point};
+
+    fn point(id: &str, slave: u8, area: Area, address: u16, quantity: u16) -> Point {
+        Point {
+            id: Arc::new(id.to_owned()),
+            slave,
+            area,
+            address,
+            quantity,
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let conf = Coalesce {
+            max_gap: 2,
+            max_pdu_size: 22,
+        };
+        assert_eq!(conf.max_registers(), 10);
+
+        let points = vec![
+            point("a", 1, Area::HoldingRegisters, 0, 2),
+            point("b", 1, Area::HoldingRegisters, 4, 1),
+            // 间隔过大
+            point("c", 1, Area::HoldingRegisters, 10, 2),
+            // 超出单次读取上限
+            point("d", 1, Area::HoldingRegisters, 12, 4),
+            point("e", 1, Area::HoldingRegisters, 17, 4),
+            point("f", 2, Area::HoldingRegisters, 1, 1),
+            point("g", 1, Area::InputRegisters, 1, 1),
+        ];
+        let blocks = plan(points, &conf);
+        let blocks: Vec<(u8, u16, u16, usize)> = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.slave,
+                    block.address,
+                    block.quantity,
+                    block.points.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![
+                (1, 1, 1, 1),
+                (1, 0, 5, 2),
+                (1, 10, 6, 2),
+                (1, 17, 4, 1),
+                (2, 1, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slice_bits() {
+        let conf = Coalesce {
+            max_gap: 8,
+            max_pdu_size: 253,
+        };
+        let blocks = plan(
+            vec![
+                point("a", 1, Area::Coils, 0, 1),
+                point("b", 1, Area::Coils, 9, 1),
+            ],
+            &conf,
+        );
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks[0];
+        let data = [0b0000_0000, 0b0000_0010];
+        assert_eq!(block.slice(&data, &block.points[0]), Some(vec![0]));
+        assert_eq!(block.slice(&data, &block.points[1]), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_plan_groups() {
+        let conf = Coalesce {
+            max_gap: 8,
+            max_pdu_size: 253,
+        };
+        let groups = plan_groups(
+            vec![
+                (1000, point("a", 1, Area::HoldingRegisters, 0, 1)),
+                (1000, point("b", 1, Area::HoldingRegisters, 2, 1)),
+                // 不同频率的点位即使地址相邻也不合并
+                (500, point("c", 1, Area::HoldingRegisters, 1, 1)),
+                (0, point("d", 1, Area::HoldingRegisters, 3, 1)),
+            ],
+            &conf,
+        );
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[&1000].len(), 1);
+        assert_eq!(groups[&1000][0].quantity, 3);
+        assert_eq!(groups[&1000][0].points.len(), 2);
+        assert_eq!(groups[&500].len(), 1);
+        assert_eq!(groups[&500][0].points[0].id.as_str(), "c");
+    }
+}
@@ -1,4 +1,13 @@
-use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use common::error::{HaliaError, HaliaResult};
@@ -6,18 +15,22 @@ use dashmap::DashMap;
 use futures::lock::BiLock;
 use halia_derive::ResourceErr;
 use message::RuleMessageBatch;
-use modbus_protocol::{self, rtu, tcp, Context};
+use modbus_protocol::{
+    self, rtu,
+    server::{self, SharedStore, Store, WriteRecord},
+    tcp, Context,
+};
 use sink::Sink;
 use source::Source;
 use tokio::{
-    net::{lookup_host, TcpStream},
+    net::{lookup_host, TcpListener, TcpStream},
     select,
     sync::{
         broadcast,
         mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
         watch,
     },
-    task::JoinHandle,
+    task::{JoinHandle, JoinSet},
     time,
 };
 use tokio_serial::{DataBits, Parity, SerialPort, SerialStream, StopBits};
@@ -25,7 +38,8 @@ use tracing::{debug, trace, warn};
 use types::{
     devices::{
         device::modbus::{
-            Area, DataType, DeviceConf, Encode, Ethernet, Serial, SinkConf, SourceConf, Type,
+            Area, Coalesce, DataType, DeviceConf, Encode, Ethernet, Mode, Serial, SinkConf,
+            SourceConf, Type,
         },
         device_template::modbus::{CustomizeConf, TemplateConf},
     },
@@ -35,6 +49,7 @@ use utils::ErrorManager;
 
 use crate::{Device, UpdateConfMode};
 
+mod coalesce;
 mod sink;
 mod source;
 pub(crate) mod template;
@@ -49,7 +64,15 @@ struct Modbus {
     err: BiLock<Option<Arc<String>>>,
 
     write_tx: UnboundedSender<WritePointEvent>,
-    read_tx: UnboundedSender<Arc<String>>,
+    read_tx: UnboundedSender<ReadEvent>,
+
+    // 合并读取配置，开启时按点位频率分组读取
+    coalesce: Option<Coalesce>,
+    coalesced: Arc<AtomicBool>,
+    groups: Vec<coalesce::Group>,
+
+    // 从站模式下网关持有的数据，与任务共享
+    store: SharedStore,
 
     join_handle: Option<JoinHandle<TaskLoop>>,
 }
@@ -60,7 +83,10 @@ struct TaskLoop {
     sources: Arc<DashMap<String, Source>>,
     stop_signal_rx: watch::Receiver<()>,
     write_rx: UnboundedReceiver<WritePointEvent>,
-    read_rx: UnboundedReceiver<Arc<String>>,
+    read_rx: UnboundedReceiver<ReadEvent>,
+    device_err_tx: broadcast::Sender<bool>,
+    // 从站模式下网关持有的数据，重启后保留
+    store: SharedStore,
 }
 
 impl TaskLoop {
@@ -71,7 +97,9 @@ impl TaskLoop {
         stop_signal_rx: watch::Receiver<()>,
         sources: Arc<DashMap<String, Source>>,
         write_rx: UnboundedReceiver<WritePointEvent>,
-        read_rx: UnboundedReceiver<Arc<String>>,
+        read_rx: UnboundedReceiver<ReadEvent>,
+        device_err_tx: broadcast::Sender<bool>,
+        store: SharedStore,
     ) -> Self {
         let error_manager =
             ErrorManager::new(utils::error_manager::ResourceType::Device, device_id, err);
@@ -82,59 +110,271 @@ impl TaskLoop {
             stop_signal_rx,
             write_rx,
             read_rx,
+            device_err_tx,
+            store,
         }
     }
 
     pub fn start(mut self) -> JoinHandle<TaskLoop> {
         tokio::spawn(async move {
-            loop {
-                match self.connect().await {
-                    Ok(mut ctx) => {
-                        self.error_manager.set_ok().await;
-                        loop {
-                            select! {
-                                biased;
-                                _ = self.stop_signal_rx.changed() => {
-                                    return self;
-                                }
+            match self.get_mode() {
+                Mode::Client => self.run_client().await,
+                Mode::Server => self.run_server().await,
+            }
+            self
+        })
+    }
 
-                                wpe = self.write_rx.recv() => {
-                                    if let Some(wpe) = wpe {
-                                        if write_value(&mut ctx, wpe).await.is_err() {
-                                            break
-                                        }
-                                    }
-                                    if self.device_conf.interval > 0 {
-                                        time::sleep(Duration::from_millis(self.device_conf.interval)).await;
+    fn get_mode(&self) -> Mode {
+        match self.device_conf.link_type {
+            types::devices::device::modbus::LinkType::Ethernet => {
+                self.device_conf.ethernet.as_ref().unwrap().mode.clone()
+            }
+            types::devices::device::modbus::LinkType::Serial => {
+                self.device_conf.serial.as_ref().unwrap().mode.clone()
+            }
+        }
+    }
+
+    async fn run_client(&mut self) {
+        loop {
+            match self.connect().await {
+                Ok(mut ctx) => {
+                    self.error_manager.set_ok().await;
+                    _ = self.device_err_tx.send(false);
+                    loop {
+                        select! {
+                            biased;
+                            _ = self.stop_signal_rx.changed() => {
+                                return;
+                            }
+
+                            wpe = self.write_rx.recv() => {
+                                if let Some(wpe) = wpe {
+                                    if write_value(&mut ctx, wpe).await.is_err() {
+                                        break
                                     }
                                 }
+                                if self.device_conf.interval > 0 {
+                                    time::sleep(Duration::from_millis(self.device_conf.interval)).await;
+                                }
+                            }
 
-                                Some(point_id) = self.read_rx.recv() => {
-                                    if let Some(mut source) = self.sources.get_mut(point_id.as_ref()) {
-                                        if let Err(_) = source.read(&mut ctx, &self.device_conf).await {
-                                            break
-                                        }
-                                    }
+                            Some(event) = self.read_rx.recv() => {
+                                if self.read(&mut ctx, event).await.is_err() {
+                                    break
                                 }
                             }
                         }
                     }
-                    Err(e) => {
-                        let e = Arc::new(e.to_string());
-                        self.error_manager.set_err(e.clone()).await;
-                        let sleep = time::sleep(Duration::from_secs(self.device_conf.reconnect));
-                        tokio::pin!(sleep);
+                    _ = self.device_err_tx.send(true);
+                }
+                Err(e) => {
+                    let e = Arc::new(e.to_string());
+                    self.error_manager.set_err(e.clone()).await;
+                    _ = self.device_err_tx.send(true);
+                    let sleep = time::sleep(Duration::from_secs(self.device_conf.reconnect));
+                    tokio::pin!(sleep);
+                    select! {
+                        _ = self.stop_signal_rx.changed() => {
+                            return;
+                        }
+
+                        _ = &mut sleep => {}
+                    }
+                }
+            }
+        }
+    }
+
+    async fn read(&mut self, ctx: &mut Box<dyn Context>, event: ReadEvent) -> io::Result<()> {
+        match event {
+            ReadEvent::Point(point_id) => match self.sources.get_mut(point_id.as_ref()) {
+                Some(mut source) => source.read(ctx, &self.device_conf).await,
+                None => Ok(()),
+            },
+            ReadEvent::Blocks(blocks) => self.read_blocks(ctx, &blocks).await,
+        }
+    }
+
+    // 读取预先合并好的连续块，再拆分给各点位
+    async fn read_blocks(
+        &mut self,
+        ctx: &mut Box<dyn Context>,
+        blocks: &[coalesce::Block],
+    ) -> io::Result<()> {
+        for block in blocks {
+            let res = match block.area {
+                Area::InputDiscrete => {
+                    ctx.read_discrete_inputs(block.slave, block.address, block.quantity)
+                        .await
+                }
+                Area::Coils => {
+                    ctx.read_coils(block.slave, block.address, block.quantity)
+                        .await
+                }
+                Area::InputRegisters => {
+                    ctx.read_input_registers(block.slave, block.address, block.quantity)
+                        .await
+                }
+                Area::HoldingRegisters => {
+                    ctx.read_holding_registers(block.slave, block.address, block.quantity)
+                        .await
+                }
+            };
+
+            match res {
+                Ok(data) => {
+                    for point in block.points.iter() {
+                        if let Some(mut source) = self.sources.get_mut(point.id.as_ref()) {
+                            match block.slice(data, point) {
+                                Some(mut data) => source.send(&mut data, &self.device_conf),
+                                None => source.set_err_info("返回数据长度过小".to_owned()),
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    let err_info = match e {
+                        modbus_protocol::ModbusError::Transport(e) => return Err(e),
+                        modbus_protocol::ModbusError::Protocol(e) => e.to_string(),
+                        modbus_protocol::ModbusError::Exception(e) => e.to_string(),
+                    };
+                    warn!("{}", err_info);
+                    for point in block.points.iter() {
+                        if let Some(mut source) = self.sources.get_mut(point.id.as_ref()) {
+                            source.set_err_info(err_info.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_server(&mut self) {
+        let (record_tx, mut record_rx) = unbounded_channel();
+        loop {
+            match self.listen(record_tx.clone()).await {
+                Ok(mut listener) => {
+                    self.error_manager.set_ok().await;
+                    let mut ctx = server::new_context(self.store.clone());
+                    loop {
                         select! {
+                            biased;
                             _ = self.stop_signal_rx.changed() => {
-                                return self;
+                                listener.abort();
+                                return;
                             }
 
-                            _ = &mut sleep => {}
+                            Some(wpe) = self.write_rx.recv() => {
+                                _ = write_value(&mut ctx, wpe).await;
+                            }
+
+                            Some(event) = self.read_rx.recv() => {
+                                _ = self.read(&mut ctx, event).await;
+                            }
+
+                            Some(record) = record_rx.recv() => {
+                                self.on_master_write(&mut ctx, record).await;
+                            }
+
+                            res = &mut listener => {
+                                let e = match res {
+                                    Ok(Err(e)) => e.to_string(),
+                                    Ok(Ok(())) => "监听已关闭".to_owned(),
+                                    Err(e) => e.to_string(),
+                                };
+                                self.error_manager.set_err(Arc::new(e)).await;
+                                break
+                            }
                         }
                     }
                 }
+                Err(e) => {
+                    let e = Arc::new(e.to_string());
+                    self.error_manager.set_err(e.clone()).await;
+                }
             }
-        })
+
+            let sleep = time::sleep(Duration::from_secs(self.device_conf.reconnect));
+            tokio::pin!(sleep);
+            select! {
+                _ = self.stop_signal_rx.changed() => {
+                    return;
+                }
+
+                _ = &mut sleep => {}
+            }
+        }
+    }
+
+    // 主站写入后，触发地址范围有重叠的源
+    async fn on_master_write(&mut self, ctx: &mut Box<dyn Context>, record: WriteRecord) {
+        for mut source in self.sources.iter_mut() {
+            let area = match source.source_conf.area {
+                Area::Coils => server::Area::Coils,
+                Area::HoldingRegisters => server::Area::HoldingRegisters,
+                Area::InputDiscrete | Area::InputRegisters => continue,
+            };
+            if source.source_conf.slave != record.slave
+                || area != record.area
+                || !record.overlaps(
+                    source.source_conf.address,
+                    source.source_conf.data_type.get_quantity(),
+                )
+            {
+                continue;
+            }
+
+            _ = source.read(ctx, &self.device_conf).await;
+        }
+    }
+
+    async fn listen(
+        &self,
+        record_tx: UnboundedSender<WriteRecord>,
+    ) -> io::Result<JoinHandle<io::Result<()>>> {
+        let store = self.store.clone();
+        match self.device_conf.link_type {
+            types::devices::device::modbus::LinkType::Ethernet => {
+                let ethernet = self.device_conf.ethernet.as_ref().unwrap();
+                let listener =
+                    TcpListener::bind(format!("{}:{}", ethernet.host, ethernet.port)).await?;
+                let encode = ethernet.encode.clone();
+                Ok(tokio::spawn(async move {
+                    // 任务被终止时，JoinSet会一并终止所有主站连接
+                    let mut conns = JoinSet::new();
+                    loop {
+                        select! {
+                            res = listener.accept() => {
+                                let (stream, addr) = res?;
+                                debug!("modbus master {} connected", addr);
+                                let store = store.clone();
+                                let record_tx = record_tx.clone();
+                                let encode = encode.clone();
+                                conns.spawn(async move {
+                                    let res = match encode {
+                                        Encode::Tcp => tcp::serve(stream, store, record_tx).await,
+                                        Encode::RtuOverTcp => rtu::serve(stream, store, record_tx).await,
+                                    };
+                                    if let Err(e) = res {
+                                        debug!("modbus master {} disconnected: {}", addr, e);
+                                    }
+                                });
+                            }
+
+                            Some(_) = conns.join_next() => {}
+                        }
+                    }
+                }))
+            }
+            types::devices::device::modbus::LinkType::Serial => {
+                let port = open_serial(self.device_conf.serial.as_ref().unwrap())?;
+                Ok(tokio::spawn(rtu::serve(port, store, record_tx)))
+            }
+        }
     }
 
     pub async fn connect(&self) -> io::Result<Box<dyn Context>> {
@@ -155,37 +395,41 @@ impl TaskLoop {
                 }
             }
             types::devices::device::modbus::LinkType::Serial => {
-                let serial = &self.device_conf.serial.as_ref().unwrap();
-                let builder = tokio_serial::new(serial.path.clone(), serial.baud_rate);
-
-                let mut port = SerialStream::open(&builder).unwrap();
-                let stop_bits = match serial.stop_bits {
-                    types::devices::device::modbus::StopBits::One => StopBits::One,
-                    types::devices::device::modbus::StopBits::Two => StopBits::Two,
-                };
-                port.set_stop_bits(stop_bits).unwrap();
-
-                let data_bits = match serial.data_bits {
-                    types::devices::device::modbus::DataBits::Five => DataBits::Five,
-                    types::devices::device::modbus::DataBits::Six => DataBits::Six,
-                    types::devices::device::modbus::DataBits::Seven => DataBits::Seven,
-                    types::devices::device::modbus::DataBits::Eight => DataBits::Eight,
-                };
-                port.set_data_bits(data_bits).unwrap();
-
-                let partity = match serial.parity {
-                    types::devices::device::modbus::Parity::None => Parity::None,
-                    types::devices::device::modbus::Parity::Odd => Parity::Odd,
-                    types::devices::device::modbus::Parity::Even => Parity::Even,
-                };
-                port.set_parity(partity).unwrap();
-
+                let port = open_serial(self.device_conf.serial.as_ref().unwrap())?;
                 rtu::new(port)
             }
         }
     }
 }
 
+fn open_serial(serial: &Serial) -> io::Result<SerialStream> {
+    let builder = tokio_serial::new(serial.path.clone(), serial.baud_rate);
+
+    let mut port = SerialStream::open(&builder)?;
+    let stop_bits = match serial.stop_bits {
+        types::devices::device::modbus::StopBits::One => StopBits::One,
+        types::devices::device::modbus::StopBits::Two => StopBits::Two,
+    };
+    port.set_stop_bits(stop_bits)?;
+
+    let data_bits = match serial.data_bits {
+        types::devices::device::modbus::DataBits::Five => DataBits::Five,
+        types::devices::device::modbus::DataBits::Six => DataBits::Six,
+        types::devices::device::modbus::DataBits::Seven => DataBits::Seven,
+        types::devices::device::modbus::DataBits::Eight => DataBits::Eight,
+    };
+    port.set_data_bits(data_bits)?;
+
+    let partity = match serial.parity {
+        types::devices::device::modbus::Parity::None => Parity::None,
+        types::devices::device::modbus::Parity::Odd => Parity::Odd,
+        types::devices::device::modbus::Parity::Even => Parity::Even,
+    };
+    port.set_parity(partity)?;
+
+    Ok(port)
+}
+
 pub fn validate_conf(device_conf: &serde_json::Value) -> HaliaResult<()> {
     let device_conf: DeviceConf = serde_json::from_value(device_conf.clone())?;
 
@@ -227,6 +471,8 @@ fn new(device_id: String, device_conf: DeviceConf) -> Box<dyn Device> {
 
     let sources = Arc::new(DashMap::new());
     let (err1, err2) = BiLock::new(None);
+    let store = Store::new_shared();
+    let coalesce = device_conf.coalesce.clone();
 
     let task_loop = TaskLoop::new(
         device_id,
@@ -236,6 +482,8 @@ fn new(device_id: String, device_conf: DeviceConf) -> Box<dyn Device> {
         sources.clone(),
         write_rx,
         read_rx,
+        device_err_tx.clone(),
+        store.clone(),
     );
 
     let join_handle = task_loop.start();
@@ -248,13 +496,17 @@ fn new(device_id: String, device_conf: DeviceConf) -> Box<dyn Device> {
         device_err_tx,
         write_tx,
         read_tx,
+        coalesced: Arc::new(AtomicBool::new(coalesce.is_some())),
+        coalesce,
+        groups: vec![],
+        store,
         join_handle: Some(join_handle),
     })
 }
 
-pub fn validate_source_conf(_conf: &serde_json::Value) -> HaliaResult<()> {
-    // let conf: SourceConf = serde_json::from_value(conf.clone())?;
-    // Source::validate_conf(&conf)?;
+pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::validate_conf(&conf)?;
     Ok(())
 }
 
@@ -271,14 +523,80 @@ impl Modbus {
             conf,
             self.read_tx.clone(),
             self.device_err_tx.subscribe(),
+            self.coalesced.clone(),
         );
         self.sources.insert(source_id, source);
+        self.sync_slaves();
+        self.plan_groups();
     }
 
-    fn create_sink(&mut self, sink_id: String, conf: SinkConf) {
-        let sink = Sink::new(conf, self.write_tx.clone(), self.device_err_tx.subscribe());
+    // 点位或合并配置变更后重新分组合并，旧的分组随之停止
+    fn plan_groups(&mut self) {
+        self.groups.clear();
+        let coalesce = match &self.coalesce {
+            Some(coalesce) => coalesce,
+            None => {
+                self.coalesced.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+        self.coalesced.store(true, Ordering::Relaxed);
+
+        let points = self
+            .sources
+            .iter()
+            .map(|source| {
+                (
+                    source.source_conf.interval,
+                    coalesce::Point {
+                        id: Arc::new(source.key().clone()),
+                        slave: source.source_conf.slave,
+                        area: source.source_conf.area,
+                        address: source.source_conf.address,
+                        quantity: source.source_conf.data_type.get_quantity(),
+                    },
+                )
+            })
+            .collect();
+        for (interval, blocks) in coalesce::plan_groups(points, coalesce) {
+            self.groups.push(coalesce::Group::new(
+                interval,
+                blocks,
+                self.read_tx.clone(),
+                self.device_err_tx.subscribe(),
+            ));
+        }
+    }
+
+    fn create_sink(&mut self, sink_id: String, conf: SinkConf) -> HaliaResult<()> {
+        let sink = Sink::new(
+            &sink_id,
+            conf,
+            self.write_tx.clone(),
+            self.device_err_tx.subscribe(),
+        )?;
         self.sinks.insert(sink_id, sink);
+        self.sync_slaves();
+        Ok(())
     }
+
+    // 从站模式下只响应源、动作引用的从站，其余从站返回异常
+    fn sync_slaves(&self) {
+        let mut slaves = HashSet::new();
+        for source in self.sources.iter() {
+            slaves.insert(source.source_conf.slave);
+        }
+        for sink in self.sinks.iter() {
+            slaves.insert(sink.slave);
+        }
+        self.store.lock().unwrap().set_slaves(&slaves);
+    }
+}
+
+// 未开启合并读取时按点位读取，开启后按频率分组读取合并好的块
+pub(crate) enum ReadEvent {
+    Point(Arc<String>),
+    Blocks(Arc<Vec<coalesce::Block>>),
 }
 
 #[derive(Debug)]
@@ -413,8 +731,10 @@ impl Device for Modbus {
                 update_template_conf(&mut task_loop.device_conf, conf)?;
             }
         }
+        self.coalesce = task_loop.device_conf.coalesce.clone();
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
+        self.plan_groups();
         Ok(())
     }
 
@@ -427,6 +747,7 @@ impl Device for Modbus {
             sink.stop().await;
         }
 
+        self.groups.clear();
         self.stop_signal_tx.send(()).unwrap();
     }
 
@@ -446,12 +767,12 @@ impl Device for Modbus {
         conf: serde_json::Value,
     ) -> HaliaResult<()> {
         match self.sources.get_mut(source_id) {
-            Some(mut source) => {
-                source.update(conf).await?;
-                Ok(())
-            }
-            None => Err(HaliaError::NotFound(source_id.to_owned())),
+            Some(mut source) => source.update(conf).await?,
+            None => return Err(HaliaError::NotFound(source_id.to_owned())),
         }
+        self.sync_slaves();
+        self.plan_groups();
+        Ok(())
     }
 
     async fn write_source_value(&mut self, source_id: String, req: Value) -> HaliaResult<()> {
@@ -484,38 +805,35 @@ impl Device for Modbus {
 
     async fn delete_source(&mut self, source_id: &String) -> HaliaResult<()> {
         match self.sources.remove(source_id) {
-            Some((_, mut source)) => {
-                source.stop().await;
-                Ok(())
-            }
-            None => Err(HaliaError::NotFound(source_id.to_owned())),
-        }
+            Some((_, mut source)) => source.stop().await,
+            None => return Err(HaliaError::NotFound(source_id.to_owned())),
+        };
+        self.sync_slaves();
+        self.plan_groups();
+        Ok(())
     }
 
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let conf: SinkConf = serde_json::from_value(conf)?;
-        self.create_sink(sink_id, conf);
-        Ok(())
+        self.create_sink(sink_id, conf)
     }
 
     async fn update_sink(&mut self, sink_id: &String, conf: serde_json::Value) -> HaliaResult<()> {
         match self.sinks.get_mut(sink_id) {
-            Some(mut sink) => {
-                sink.update(conf).await?;
-                Ok(())
-            }
-            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+            Some(mut sink) => sink.update(sink_id, conf).await?,
+            None => return Err(HaliaError::NotFound(sink_id.to_owned())),
         }
+        self.sync_slaves();
+        Ok(())
     }
 
     async fn delete_sink(&mut self, sink_id: &String) -> HaliaResult<()> {
         match self.sinks.remove(sink_id) {
-            Some((_, mut sink)) => {
-                sink.stop().await;
-                Ok(())
-            }
-            None => Err(HaliaError::NotFound(sink_id.to_owned())),
-        }
+            Some((_, mut sink)) => sink.stop().await,
+            None => return Err(HaliaError::NotFound(sink_id.to_owned())),
+        };
+        self.sync_slaves();
+        Ok(())
     }
 
     async fn get_source_rxs(
@@ -562,6 +880,7 @@ fn get_conf(
                         port: ethernet_customize_conf.port,
                     }),
                     serial: None,
+                    coalesce: template_conf.coalesce,
                     metadatas: customize_conf.metadatas,
                 }),
                 _ => unreachable!(),
@@ -575,12 +894,14 @@ fn get_conf(
                     interval: template_conf.interval,
                     ethernet: None,
                     serial: Some(Serial {
+                        mode: serial_template_conf.mode,
                         path: serial_customize_conf.path,
                         stop_bits: serial_template_conf.stop_bits,
                         baud_rate: serial_template_conf.baud_rate,
                         data_bits: serial_template_conf.data_bits,
                         parity: serial_template_conf.parity,
                     }),
+                    coalesce: template_conf.coalesce,
                     metadatas: customize_conf.metadatas,
                 }),
                 _ => unreachable!(),
@@ -617,6 +938,7 @@ fn update_template_conf(
 
     device_conf.interval = template_conf.interval;
     device_conf.reconnect = template_conf.reconnect;
+    device_conf.coalesce = template_conf.coalesce;
     match device_conf.link_type {
         types::devices::device::modbus::LinkType::Ethernet => {
             let ethernet = template_conf.ethernet.unwrap();
@@ -625,6 +947,7 @@ fn update_template_conf(
         }
         types::devices::device::modbus::LinkType::Serial => {
             let serial = template_conf.serial.unwrap();
+            device_conf.serial.as_mut().unwrap().mode = serial.mode;
             device_conf.serial.as_mut().unwrap().stop_bits = serial.stop_bits;
             device_conf.serial.as_mut().unwrap().baud_rate = serial.baud_rate;
             device_conf.serial.as_mut().unwrap().data_bits = serial.data_bits;
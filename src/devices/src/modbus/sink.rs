@@ -22,6 +22,8 @@ pub struct Sink {
     stop_signal_tx: watch::Sender<()>,
     join_handle: Option<JoinHandle<TaskLoop>>,
     pub mb_tx: mpsc::UnboundedSender<RuleMessageBatch>,
+    // 写入的从站地址，从站模式下需在数据中注册
+    pub slave: u8,
 }
 
 pub struct TaskLoop {
@@ -35,21 +37,22 @@ pub struct TaskLoop {
 
 impl TaskLoop {
     fn new(
+        sink_id: &str,
         sink_conf: SinkConf,
         stop_signal_rx: watch::Receiver<()>,
         mb_rx: mpsc::UnboundedReceiver<RuleMessageBatch>,
         write_tx: UnboundedSender<WritePointEvent>,
         device_err_rx: broadcast::Receiver<bool>,
-    ) -> Self {
-        let message_retainer = sink_message_retain::new(&sink_conf.message_retain);
-        Self {
+    ) -> HaliaResult<Self> {
+        let message_retainer = sink_message_retain::new(&sink_conf.message_retain, sink_id)?;
+        Ok(Self {
             sink_conf,
             stop_signal_rx,
             mb_rx,
             write_tx,
             device_err_rx,
             message_retainer,
-        }
+        })
     }
 
     fn start(mut self) -> JoinHandle<Self> {
@@ -72,13 +75,11 @@ impl TaskLoop {
 
                     Ok(err) = self.device_err_rx.recv() => {
                         device_err = err;
-                        match err {
-                            true => {
-                                while let Some(mb) = self.message_retainer.pop() {
-                                    self.send_write_point_event(mb).await;
-                                }
+                        // 设备恢复后发送保留的消息
+                        if !err {
+                            while let Some(mb) = self.message_retainer.pop() {
+                                self.send_write_point_event(mb).await;
                             }
-                            false => {}
                         }
                     }
                 }
@@ -123,30 +124,47 @@ impl Sink {
     }
 
     pub fn new(
+        sink_id: &str,
         sink_conf: SinkConf,
         write_tx: UnboundedSender<WritePointEvent>,
         device_err_rx: broadcast::Receiver<bool>,
-    ) -> Self {
+    ) -> HaliaResult<Self> {
         let (stop_signal_tx, stop_signal_rx) = watch::channel(());
         let (mb_tx, mb_rx) = mpsc::unbounded_channel();
+        let slave = sink_conf.slave;
 
-        let task_loop = TaskLoop::new(sink_conf, stop_signal_rx, mb_rx, write_tx, device_err_rx);
+        let task_loop = TaskLoop::new(
+            sink_id,
+            sink_conf,
+            stop_signal_rx,
+            mb_rx,
+            write_tx,
+            device_err_rx,
+        )?;
         let join_handle = task_loop.start();
 
-        Self {
+        Ok(Self {
             stop_signal_tx,
             join_handle: Some(join_handle),
             mb_tx,
-        }
+            slave,
+        })
     }
 
-    pub async fn update(&mut self, sink_conf: serde_json::Value) -> HaliaResult<()> {
-        let mut task_loop = self.stop().await;
+    pub async fn update(&mut self, sink_id: &str, sink_conf: serde_json::Value) -> HaliaResult<()> {
         let sink_conf: SinkConf = serde_json::from_value(sink_conf)?;
+        let mut task_loop = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut task_loop.message_retainer,
+            &task_loop.sink_conf.message_retain,
+            &sink_conf.message_retain,
+            sink_id,
+        );
+        self.slave = sink_conf.slave;
         task_loop.sink_conf = sink_conf;
         let join_handle = task_loop.start();
         self.join_handle = Some(join_handle);
-        Ok(())
+        res
     }
 
     pub async fn stop(&mut self) -> TaskLoop {
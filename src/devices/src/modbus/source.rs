@@ -1,4 +1,11 @@
-use std::{io, sync::Arc, time::Duration};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use common::error::{HaliaError, HaliaResult};
 use message::{Message, MessageBatch, RuleMessageBatch};
@@ -11,11 +18,13 @@ use tokio::{
         watch,
     },
     task::JoinHandle,
-    time,
+    time::{self, Instant, Interval, MissedTickBehavior},
 };
 use tracing::warn;
 use types::devices::device::modbus::{Area, DeviceConf, SourceConf};
 
+use super::ReadEvent;
+
 pub struct Source {
     pub source_conf: SourceConf,
     quantity: u16,
@@ -30,27 +39,57 @@ pub struct Source {
 pub struct TaskLoop {
     id: Arc<String>,
     stop_signal_rx: watch::Receiver<()>,
-    read_tx: mpsc::UnboundedSender<Arc<String>>,
+    read_tx: mpsc::UnboundedSender<ReadEvent>,
     device_err_rx: broadcast::Receiver<bool>,
+    // 开启合并读取时由设备按频率分组读取，点位自身不再触发
+    coalesced: Arc<AtomicBool>,
+}
+
+// 按时间戳对齐首次触发，使相同或成倍频率的点位同时触发
+// 频率为0时返回None，旧版本存储的点位可能未经校验
+pub(crate) fn aligned_interval(interval: u64) -> Option<Interval> {
+    if interval == 0 {
+        return None;
+    }
+    let period = Duration::from_millis(interval);
+    let delay = interval - common::timestamp_millis() % interval;
+    let mut interval = time::interval_at(Instant::now() + Duration::from_millis(delay), period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    Some(interval)
+}
+
+// 未设置定时器时永不触发
+pub(crate) async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
 }
 
 impl TaskLoop {
     fn new(
         id: String,
         stop_signal_rx: watch::Receiver<()>,
-        read_tx: UnboundedSender<Arc<String>>,
+        read_tx: UnboundedSender<ReadEvent>,
         device_err_rx: broadcast::Receiver<bool>,
+        coalesced: Arc<AtomicBool>,
     ) -> Self {
         Self {
             id: Arc::new(id),
             stop_signal_rx,
             read_tx,
             device_err_rx,
+            coalesced,
         }
     }
 
     fn start(mut self, source_conf: &SourceConf) -> JoinHandle<Self> {
-        let mut interval = time::interval(Duration::from_millis(source_conf.interval));
+        let mut interval = aligned_interval(source_conf.interval);
+        if interval.is_none() {
+            warn!("source {} interval is 0, skip reading", self.id);
+        }
         let mut device_err = false;
         tokio::spawn(async move {
             loop {
@@ -59,9 +98,9 @@ impl TaskLoop {
                         return self;
                     }
 
-                    _ = interval.tick() => {
-                        if !device_err {
-                            _ = self.read_tx.send(self.id.clone());
+                    _ = tick(&mut interval) => {
+                        if !device_err && !self.coalesced.load(Ordering::Relaxed) {
+                            _ = self.read_tx.send(ReadEvent::Point(self.id.clone()));
                         }
                     }
 
@@ -78,12 +117,13 @@ impl Source {
     pub fn new(
         id: String,
         source_conf: SourceConf,
-        read_tx: UnboundedSender<Arc<String>>,
+        read_tx: UnboundedSender<ReadEvent>,
         device_err_rx: broadcast::Receiver<bool>,
+        coalesced: Arc<AtomicBool>,
     ) -> Self {
         let (stop_signal_tx, stop_signal_rx) = watch::channel(());
 
-        let task_loop = TaskLoop::new(id, stop_signal_rx, read_tx, device_err_rx);
+        let task_loop = TaskLoop::new(id, stop_signal_rx, read_tx, device_err_rx, coalesced);
 
         let join_handle = task_loop.start(&source_conf);
 
@@ -237,38 +277,8 @@ impl Source {
         };
 
         match res {
-            Ok(mut data) => {
-                let value = self.source_conf.data_type.decode(&mut data);
-                let mut message = Message::default();
-                // todo 考虑field的共享，避免clone
-                message.add(self.source_conf.field.clone(), value);
-
-                if let Some(metadatas) = &device_conf.metadatas {
-                    message.insert_raw_metadatas(metadatas.clone());
-                }
-                if let Some(metadatas) = &self.source_conf.metadatas {
-                    message.insert_raw_metadatas(metadatas.clone());
-                }
-
-                let mut message_batch = MessageBatch::default();
-                message_batch.push_message(message);
-
-                // todo 没有receiver时不请求
-                // 删除关闭的channel
-                match self.mb_txs.len() {
-                    0 => {}
-                    1 => {
-                        let mb = RuleMessageBatch::Owned(message_batch);
-                        if let Err(_) = self.mb_txs[0].send(mb) {
-                            self.mb_txs.remove(0);
-                        }
-                    }
-                    _ => {
-                        let mb = RuleMessageBatch::Arc(Arc::new(message_batch));
-                        self.mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
-                    }
-                }
-
+            Ok(data) => {
+                self.send(data, device_conf);
                 Ok(())
             }
             Err(e) => match e {
@@ -287,6 +297,46 @@ impl Source {
         }
     }
 
+    pub fn set_err_info(&mut self, err_info: String) {
+        self.err_info = Some(err_info);
+    }
+
+    // 解码读取到的数据并发送至规则
+    pub fn send(&mut self, data: &mut [u8], device_conf: &DeviceConf) {
+        // 读取成功，清除之前的错误
+        self.err_info = None;
+        let value = self.source_conf.data_type.decode(data);
+        let mut message = Message::default();
+        // todo 考虑field的共享，避免clone
+        message.add(self.source_conf.field.clone(), value);
+
+        if let Some(metadatas) = &device_conf.metadatas {
+            message.insert_raw_metadatas(metadatas.clone());
+        }
+        if let Some(metadatas) = &self.source_conf.metadatas {
+            message.insert_raw_metadatas(metadatas.clone());
+        }
+
+        let mut message_batch = MessageBatch::default();
+        message_batch.push_message(message);
+
+        // todo 没有receiver时不请求
+        // 删除关闭的channel
+        match self.mb_txs.len() {
+            0 => {}
+            1 => {
+                let mb = RuleMessageBatch::Owned(message_batch);
+                if let Err(_) = self.mb_txs[0].send(mb) {
+                    self.mb_txs.remove(0);
+                }
+            }
+            _ => {
+                let mb = RuleMessageBatch::Arc(Arc::new(message_batch));
+                self.mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+            }
+        }
+    }
+
     pub fn get_rxs(&mut self, cnt: usize) -> Vec<mpsc::UnboundedReceiver<RuleMessageBatch>> {
         let mut rxs = vec![];
         for _ in 0..cnt {
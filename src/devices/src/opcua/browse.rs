@@ -0,0 +1,260 @@
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use common::error::{HaliaError, HaliaResult};
+use opcua_protocol::{
+    client::Session,
+    types::{
+        AttributeId, BrowseDescription, BrowseDirection, BrowsePath, BrowseResult,
+        BrowseResultMask, ByteString, DataTypeId, Identifier, NodeClass, NodeId, ObjectId,
+        QualifiedName, ReadValueId, ReferenceTypeId, RelativePath, RelativePathElement,
+        TimestampsToReturn, UAString, Variant,
+    },
+};
+use types::devices::device::opcua::{
+    AccessLevel, BrowseNextReq, BrowseReference, BrowseReq, BrowseResp, TranslateBrowsePathReq,
+    TranslateBrowsePathResp,
+};
+
+use super::transfer_node_id;
+
+// AccessLevel属性的位定义
+const ACCESS_LEVEL_CURRENT_READ: u8 = 0x01;
+const ACCESS_LEVEL_CURRENT_WRITE: u8 = 0x02;
+
+pub(super) async fn browse(session: &Session, req: BrowseReq) -> HaliaResult<BrowseResp> {
+    let node_id = match &req.node_id {
+        Some(node_id) => transfer_node_id(node_id),
+        None => NodeId::from(&ObjectId::ObjectsFolder),
+    };
+
+    let browse_description = BrowseDescription {
+        node_id,
+        browse_direction: BrowseDirection::Forward,
+        reference_type_id: NodeId::from(&ReferenceTypeId::HierarchicalReferences),
+        include_subtypes: true,
+        node_class_mask: 0,
+        result_mask: BrowseResultMask::All as u32,
+    };
+    let results = session
+        .browse(&[browse_description])
+        .await
+        .map_err(|e| HaliaError::Common(e.to_string()))?;
+    transfer_browse_result(session, results).await
+}
+
+pub(super) async fn browse_next(session: &Session, req: BrowseNextReq) -> HaliaResult<BrowseResp> {
+    let continuation_point = BASE64_STANDARD
+        .decode(req.continuation_point)
+        .map_err(|e| HaliaError::Base64DecodeErr(e.to_string()))?;
+    let results = session
+        .browse_next(false, &[ByteString::from(continuation_point)])
+        .await
+        .map_err(|e| HaliaError::Common(e.to_string()))?;
+    transfer_browse_result(session, results).await
+}
+
+pub(super) async fn translate_browse_path(
+    session: &Session,
+    req: TranslateBrowsePathReq,
+) -> HaliaResult<TranslateBrowsePathResp> {
+    let starting_node = match &req.start_node_id {
+        Some(node_id) => transfer_node_id(node_id),
+        None => NodeId::from(&ObjectId::ObjectsFolder),
+    };
+
+    let elements = req
+        .browse_path
+        .iter()
+        .map(|name| RelativePathElement {
+            reference_type_id: NodeId::from(&ReferenceTypeId::HierarchicalReferences),
+            is_inverse: false,
+            include_subtypes: true,
+            target_name: parse_qualified_name(name),
+        })
+        .collect();
+    let browse_path = BrowsePath {
+        starting_node,
+        relative_path: RelativePath {
+            elements: Some(elements),
+        },
+    };
+
+    let results = session
+        .translate_browse_paths_to_node_ids(&[browse_path])
+        .await
+        .map_err(|e| HaliaError::Common(e.to_string()))?;
+    let result = match results.into_iter().next() {
+        Some(result) => result,
+        None => return Err(HaliaError::Common("服务端未返回结果！".to_owned())),
+    };
+    if !result.status_code.is_good() {
+        return Err(HaliaError::Common(result.status_code.to_string()));
+    }
+
+    let node_ids = result
+        .targets
+        .unwrap_or_default()
+        .into_iter()
+        .map(|target| transfer_conf_node_id(&target.target_id.node_id))
+        .collect();
+    Ok(TranslateBrowsePathResp { node_ids })
+}
+
+async fn transfer_browse_result(
+    session: &Session,
+    results: Option<Vec<BrowseResult>>,
+) -> HaliaResult<BrowseResp> {
+    let result = match results.and_then(|results| results.into_iter().next()) {
+        Some(result) => result,
+        None => return Err(HaliaError::Common("服务端未返回结果！".to_owned())),
+    };
+    if !result.status_code.is_good() {
+        return Err(HaliaError::Common(result.status_code.to_string()));
+    }
+
+    let continuation_point = match result.continuation_point.value {
+        Some(value) if !value.is_empty() => Some(BASE64_STANDARD.encode(value)),
+        _ => None,
+    };
+
+    let mut references = vec![];
+    let mut variable_node_ids = vec![];
+    for reference in result.references.unwrap_or_default() {
+        if reference.node_class == NodeClass::Variable {
+            variable_node_ids.push((references.len(), reference.node_id.node_id.clone()));
+        }
+        references.push(BrowseReference {
+            node_id: transfer_conf_node_id(&reference.node_id.node_id),
+            browse_name: format!(
+                "{}:{}",
+                reference.browse_name.namespace_index, reference.browse_name.name
+            ),
+            display_name: reference.display_name.text.to_string(),
+            node_class: transfer_node_class(reference.node_class),
+            data_type: None,
+            access_level: None,
+        });
+    }
+
+    // 变量节点需额外读取数据类型及访问权限
+    if !variable_node_ids.is_empty() {
+        let mut nodes_to_read = Vec::with_capacity(variable_node_ids.len() * 2);
+        for (_, node_id) in variable_node_ids.iter() {
+            nodes_to_read.push(get_read_value_id(node_id, AttributeId::DataType));
+            nodes_to_read.push(get_read_value_id(node_id, AttributeId::AccessLevel));
+        }
+        let data_values = session
+            .read(&nodes_to_read, TimestampsToReturn::Neither, 0.0)
+            .await
+            .map_err(|e| HaliaError::Common(e.to_string()))?;
+        for ((index, _), data_values) in variable_node_ids.iter().zip(data_values.chunks(2)) {
+            if let Some(Variant::NodeId(data_type)) = &data_values[0].value {
+                references[*index].data_type = Some(get_data_type_name(data_type));
+            }
+            if let Some(Variant::Byte(access_level)) =
+                data_values.get(1).and_then(|dv| dv.value.as_ref())
+            {
+                references[*index].access_level = Some(AccessLevel {
+                    readable: access_level & ACCESS_LEVEL_CURRENT_READ != 0,
+                    writable: access_level & ACCESS_LEVEL_CURRENT_WRITE != 0,
+                });
+            }
+        }
+    }
+
+    Ok(BrowseResp {
+        references,
+        continuation_point,
+    })
+}
+
+fn get_read_value_id(node_id: &NodeId, attribute_id: AttributeId) -> ReadValueId {
+    ReadValueId {
+        node_id: node_id.clone(),
+        attribute_id: attribute_id as u32,
+        index_range: UAString::null(),
+        data_encoding: QualifiedName::null(),
+    }
+}
+
+// 内置类型返回类型名称，自定义类型返回节点id
+fn get_data_type_name(data_type: &NodeId) -> String {
+    if data_type.namespace == 0 {
+        if let Identifier::Numeric(id) = data_type.identifier {
+            if let Ok(data_type_id) = DataTypeId::try_from(id) {
+                return format!("{:?}", data_type_id);
+            }
+        }
+    }
+    data_type.to_string()
+}
+
+// 格式为"命名空间:名称"，无命名空间时默认为0
+fn parse_qualified_name(name: &str) -> QualifiedName {
+    if let Some((namespace, name)) = name.split_once(':') {
+        if let Ok(namespace) = namespace.parse::<u16>() {
+            return QualifiedName::new(namespace, name);
+        }
+    }
+    QualifiedName::new(0, name)
+}
+
+fn transfer_node_class(node_class: NodeClass) -> types::devices::device::opcua::NodeClass {
+    match node_class {
+        NodeClass::Unspecified => types::devices::device::opcua::NodeClass::Unspecified,
+        NodeClass::Object => types::devices::device::opcua::NodeClass::Object,
+        NodeClass::Variable => types::devices::device::opcua::NodeClass::Variable,
+        NodeClass::Method => types::devices::device::opcua::NodeClass::Method,
+        NodeClass::ObjectType => types::devices::device::opcua::NodeClass::ObjectType,
+        NodeClass::VariableType => types::devices::device::opcua::NodeClass::VariableType,
+        NodeClass::ReferenceType => types::devices::device::opcua::NodeClass::ReferenceType,
+        NodeClass::DataType => types::devices::device::opcua::NodeClass::DataType,
+        NodeClass::View => types::devices::device::opcua::NodeClass::View,
+    }
+}
+
+// 与transfer_node_id相反，转换为配置中使用的节点id
+fn transfer_conf_node_id(node_id: &NodeId) -> types::devices::device::opcua::NodeId {
+    let (typ, value) = match &node_id.identifier {
+        Identifier::Numeric(num) => (
+            types::devices::device::opcua::IdentifierType::Numeric,
+            serde_json::Value::from(*num),
+        ),
+        Identifier::String(s) => (
+            types::devices::device::opcua::IdentifierType::String,
+            serde_json::to_value(s).unwrap_or_default(),
+        ),
+        Identifier::Guid(guid) => (
+            types::devices::device::opcua::IdentifierType::Guid,
+            serde_json::to_value(guid).unwrap_or_default(),
+        ),
+        Identifier::ByteString(bs) => (
+            types::devices::device::opcua::IdentifierType::Opaque,
+            serde_json::to_value(bs).unwrap_or_default(),
+        ),
+    };
+
+    types::devices::device::opcua::NodeId {
+        namespace: node_id.namespace,
+        identifier: types::devices::device::opcua::Identifer { typ, value },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_qualified_name;
+
+    #[test]
+    fn test_parse_qualified_name() {
+        let name = parse_qualified_name("2:Temperature");
+        assert_eq!(name.namespace_index, 2);
+        assert_eq!(name.name.as_ref(), "Temperature");
+
+        let name = parse_qualified_name("Objects");
+        assert_eq!(name.namespace_index, 0);
+        assert_eq!(name.name.as_ref(), "Objects");
+
+        let name = parse_qualified_name("a:b");
+        assert_eq!(name.namespace_index, 0);
+        assert_eq!(name.name.as_ref(), "a:b");
+    }
+}
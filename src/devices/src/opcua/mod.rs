@@ -1,48 +1,56 @@
-use std::{
-    sync::{atomic::AtomicU16, Arc},
-    time::Duration,
-};
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::{bail, Result};
 use async_trait::async_trait;
 use common::error::{HaliaError, HaliaResult};
 use dashmap::DashMap;
-use futures::lock::BiLock;
+use futures::{lock::BiLock, TryStreamExt};
 use halia_derive::ResourceErr;
 use message::RuleMessageBatch;
 use opcua_protocol::{
-    client::{ClientBuilder, IdentityToken, Session},
-    types::{EndpointDescription, Identifier, MonitoringMode, NodeId, StatusCode},
+    client::{ClientBuilder, IdentityToken, Session, SessionEventLoop, SessionPollResult},
+    types::{
+        AttributeId, EndpointDescription, Identifier, MonitoringMode, NodeId, QualifiedName,
+        ReadValueId, TimestampsToReturn, UAString, Variant, VariantTypeId, WriteValue,
+    },
 };
 use sink::Sink;
 use source::Source;
 use tokio::{
     select,
-    sync::{mpsc, watch, RwLock},
+    sync::{broadcast, mpsc, watch, RwLock},
     task::JoinHandle,
     time,
 };
 use tracing::{debug, warn};
 use types::{
     devices::{
-        device::opcua::{OpcuaConf, SinkConf, SourceConf},
+        device::opcua::{
+            AuthCertificate, AuthMethod, BrowseNextReq, BrowseReq, BrowseResp,
+            ClientCertificateResp, OpcuaConf, SinkConf, SourceConf, TranslateBrowsePathReq,
+            TranslateBrowsePathResp,
+        },
         device_template::opcua::{CustomizeConf, TemplateConf},
     },
     Value,
 };
+use utils::ErrorManager;
 
 use crate::{Device, UpdateConfMode};
 
+mod browse;
+mod security;
 mod sink;
 mod source;
 pub(crate) mod template;
 
 #[derive(ResourceErr)]
 struct Opcua {
+    id: String,
     err: BiLock<Option<Arc<String>>>,
-    rtt: Arc<AtomicU16>,
     stop_signal_tx: watch::Sender<()>,
     opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
+    device_err_tx: broadcast::Sender<bool>,
 
     sources: DashMap<String, Source>,
     sinks: DashMap<String, Sink>,
@@ -54,15 +62,81 @@ struct JoinHandleData {
     pub opcua_conf: OpcuaConf,
     pub opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
     pub stop_signal_rx: watch::Receiver<()>,
-    pub err: Arc<RwLock<Option<String>>>,
+    pub error_manager: ErrorManager,
+    pub device_err_tx: broadcast::Sender<bool>,
 }
 
-pub fn new_by_customize_conf(id: String, conf: serde_json::Value) -> Box<dyn Device> {
+impl JoinHandleData {
+    async fn set_err(&mut self, err: String) {
+        self.opcua_client.write().await.take();
+        self.error_manager.set_err(Arc::new(err)).await;
+        _ = self.device_err_tx.send(true);
+    }
+
+    // 驱动会话事件循环，返回true时表示收到了停止信号
+    async fn run_session(&mut self, session: Arc<Session>, event_loop: SessionEventLoop) -> bool {
+        let stream = event_loop.enter();
+        tokio::pin!(stream);
+        loop {
+            select! {
+                biased;
+                _ = self.stop_signal_rx.changed() => {
+                    self.opcua_client.write().await.take();
+                    // 断开过程中需要继续驱动事件循环，否则请求无法发出
+                    let disconnect = session.disconnect();
+                    tokio::pin!(disconnect);
+                    loop {
+                        select! {
+                            res = &mut disconnect => {
+                                if let Err(e) = res {
+                                    debug!("session disconnect err: {}", e);
+                                }
+                                break;
+                            }
+                            res = stream.try_next() => {
+                                if !matches!(res, Ok(Some(_))) {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    return true;
+                }
+
+                res = stream.try_next() => match res {
+                    // 首次连接及断线重连成功后都会返回该事件
+                    Ok(Some(SessionPollResult::Reconnected(_))) => {
+                        debug!("opcua connect success");
+                        self.opcua_client.write().await.replace(session.clone());
+                        self.error_manager.set_ok().await;
+                        _ = self.device_err_tx.send(false);
+                    }
+                    Ok(Some(SessionPollResult::ConnectionLost(code))) => {
+                        warn!("opcua connection lost: {}", code);
+                        self.set_err(code.to_string()).await;
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => {
+                        self.set_err("session closed.".to_owned()).await;
+                        return false;
+                    }
+                    Err(code) => {
+                        warn!("opcua session err: {}", code);
+                        self.set_err(code.to_string()).await;
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn new_by_customize_conf(id: String, conf: serde_json::Value) -> Box<dyn Device> {
     let opcua_conf: OpcuaConf = serde_json::from_value(conf).unwrap();
     new(id, opcua_conf)
 }
 
-pub fn new_by_template_conf(
+pub(crate) fn new_by_template_conf(
     id: String,
     customize_conf: serde_json::Value,
     template_conf: serde_json::Value,
@@ -73,131 +147,151 @@ pub fn new_by_template_conf(
 
 fn new(id: String, opcua_conf: OpcuaConf) -> Box<dyn Device> {
     let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let (device_err_tx, _) = broadcast::channel(16);
 
     let opcua_client: Arc<RwLock<Option<Arc<Session>>>> = Arc::new(RwLock::new(None));
 
     let (err1, err2) = BiLock::new(None);
+    let error_manager =
+        ErrorManager::new(utils::error_manager::ResourceType::Device, id.clone(), err1);
     let join_handle_data = JoinHandleData {
-        id,
+        id: id.clone(),
         opcua_conf,
         opcua_client: opcua_client.clone(),
         stop_signal_rx,
-        err: todo!(),
+        error_manager,
+        device_err_tx: device_err_tx.clone(),
     };
 
     let join_handle = Opcua::event_loop(join_handle_data);
 
     Box::new(Opcua {
+        id,
         err: err2,
         opcua_client,
         stop_signal_tx,
+        device_err_tx,
         sources: DashMap::new(),
         sinks: DashMap::new(),
         join_handle: Some(join_handle),
-        rtt: Arc::new(AtomicU16::new(0)),
     })
 }
 
-pub fn validate_conf(_conf: &serde_json::Value) -> HaliaResult<()> {
+pub fn validate_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: OpcuaConf = serde_json::from_value(conf.clone())?;
+    validate_auth(&conf)?;
+    security::validate_security(
+        &conf.security_policy,
+        &conf.security_mode,
+        &conf.client_certificate,
+        &conf.trusted_server_certs,
+    )
+}
+
+fn validate_auth(conf: &OpcuaConf) -> HaliaResult<()> {
+    match conf.auth_method {
+        AuthMethod::Anonymous => {}
+        AuthMethod::Username => {
+            if conf.auth_username.is_none() {
+                return Err(HaliaError::Common("用户名认证配置为空！".to_owned()));
+            }
+        }
+        AuthMethod::X509 => match &conf.auth_certificate {
+            Some(certificate) => {
+                if certificate.cert.is_empty() || certificate.key.is_empty() {
+                    return Err(HaliaError::Common("证书或私钥内容为空！".to_owned()));
+                }
+            }
+            None => return Err(HaliaError::Common("证书认证配置为空！".to_owned())),
+        },
+    }
     Ok(())
 }
 
 pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     let conf: SourceConf = serde_json::from_value(conf.clone())?;
-    Source::validate_conf(conf)?;
+    Source::validate_conf(&conf)?;
     Ok(())
 }
 
 pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
     let conf: SinkConf = serde_json::from_value(conf.clone())?;
-    Sink::validate_conf(conf)?;
+    Sink::validate_conf(&conf)?;
     Ok(())
 }
 
 impl Opcua {
-    async fn connect(conf: &OpcuaConf) -> Result<(Arc<Session>, JoinHandle<StatusCode>)> {
-        let mut client = ClientBuilder::new()
+    async fn connect(id: &String, conf: &OpcuaConf) -> Result<(Arc<Session>, SessionEventLoop)> {
+        let pki_dir = security::prepare_pki(id, conf)?;
+        let mut builder = ClientBuilder::new()
             .application_name("Halia")
             .application_uri("https://halia.com")
             .product_uri("https://halia.com")
-            .trust_server_certs(true)
-            .create_sample_keypair(true)
-            .client()
-            .unwrap();
+            .pki_dir(pki_dir)
+            .trust_server_certs(conf.trust_unknown_server_certs)
+            // 未配置客户端证书时自动生成
+            .create_sample_keypair(conf.client_certificate.is_none());
+        if conf.client_certificate.is_some() {
+            builder = builder
+                .certificate_path(security::CLIENT_CERT_PATH)
+                .private_key_path(security::CLIENT_KEY_PATH);
+        }
+        let mut client = match builder.client() {
+            Some(client) => client,
+            None => bail!("opcua客户端配置错误！"),
+        };
 
         let url = format!("opc.tcp://{}:{}{}", conf.host, conf.port, conf.path);
-        let endpoint: EndpointDescription = EndpointDescription::from(url.as_ref());
+        let endpoint: EndpointDescription = EndpointDescription::from((
+            url.as_ref(),
+            security::transfer_security_policy(&conf.security_policy).to_uri(),
+            security::transfer_security_mode(&conf.security_mode),
+        ));
 
         let user_identity_token = match conf.auth_method {
-            types::devices::device::opcua::AuthMethod::Anonymous => IdentityToken::Anonymous,
-            types::devices::device::opcua::AuthMethod::Username => {
+            AuthMethod::Anonymous => IdentityToken::Anonymous,
+            AuthMethod::Username => {
                 let username = conf.auth_username.as_ref().unwrap();
                 IdentityToken::UserName(username.username.clone(), username.password.clone())
             }
-            types::devices::device::opcua::AuthMethod::X509 => {
+            AuthMethod::X509 => {
                 let certificate = conf.auth_certificate.as_ref().unwrap();
-                // 将内容写入内存
-                IdentityToken::X509(todo!(), todo!())
+                let (cert_path, key_path) = write_certificate(id, certificate)?;
+                IdentityToken::X509(cert_path, key_path)
             }
         };
-        let (session, event_loop) = match client
+        match client
             .new_session_from_endpoint(endpoint, user_identity_token)
             .await
         {
-            Ok((session, event_loop)) => (session, event_loop),
+            Ok((session, event_loop)) => Ok((session, event_loop)),
             Err(e) => bail!(e.to_string()),
-        };
-
-        let handle = event_loop.spawn();
-        // session.wait_for_connection().await;
-        debug!("opcua connect success");
-        Ok((session, handle))
+        }
     }
 
     fn event_loop(mut join_handle_data: JoinHandleData) -> JoinHandle<JoinHandleData> {
         tokio::spawn(async move {
-            let mut task_err: Option<String> = Some("not connectd.".to_owned());
             loop {
-                match Opcua::connect(&join_handle_data.opcua_conf).await {
-                    Ok((session, join_handle)) => {
-                        debug!("opcua connect success");
-                        task_err = None;
-                        events::insert_connect_succeed(
-                            types::events::ResourceType::Device,
-                            &join_handle_data.id,
-                        )
-                        .await;
-                        join_handle_data.opcua_client.write().await.replace(session);
-                        match join_handle.await {
-                            Ok(s) => {
-                                debug!("{}", s);
-                            }
-                            Err(e) => debug!("{}", e),
+                match Opcua::connect(&join_handle_data.id, &join_handle_data.opcua_conf).await {
+                    Ok((session, event_loop)) => {
+                        if join_handle_data.run_session(session, event_loop).await {
+                            return join_handle_data;
                         }
                     }
                     Err(e) => {
-                        if task_err.is_none() {}
-                        debug!("{}", e);
-
-                        events::insert_connect_failed(
-                            types::events::ResourceType::Device,
-                            &join_handle_data.id,
-                            e.to_string(),
-                        )
-                        .await;
                         warn!("connect error: {}", e);
-                        let sleep =
-                            time::sleep(Duration::from_secs(join_handle_data.opcua_conf.reconnect));
-                        tokio::pin!(sleep);
-                        select! {
-                            _ = join_handle_data.stop_signal_rx.changed() => {
-                                return join_handle_data;
-                            }
+                        join_handle_data.set_err(e.to_string()).await;
+                    }
+                }
 
-                            _ = &mut sleep => {}
-                        }
-                        debug!("{e}");
+                let sleep = time::sleep(Duration::from_secs(join_handle_data.opcua_conf.reconnect));
+                tokio::pin!(sleep);
+                select! {
+                    _ = join_handle_data.stop_signal_rx.changed() => {
+                        return join_handle_data;
                     }
+
+                    _ = &mut sleep => {}
                 }
             }
         })
@@ -218,23 +312,59 @@ impl Opcua {
             auth_method: template_conf.auth_method,
             auth_username: template_conf.auth_username,
             auth_certificate: template_conf.auth_certificate,
+            security_policy: template_conf.security_policy,
+            security_mode: template_conf.security_mode,
+            client_certificate: template_conf.client_certificate,
+            trusted_server_certs: template_conf.trusted_server_certs,
+            trust_unknown_server_certs: template_conf.trust_unknown_server_certs,
         })
     }
 
-    async fn update_conf(&mut self, opcua_conf: OpcuaConf) {
+    async fn update_conf(
+        &mut self,
+        mode: UpdateConfMode,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
         self.stop_signal_tx.send(()).unwrap();
         let mut join_handle_data = self.join_handle.take().unwrap().await.unwrap();
-        join_handle_data.opcua_conf = opcua_conf;
+        remove_certificate(&self.id);
+        match mode {
+            UpdateConfMode::CustomizeMode => {
+                join_handle_data.opcua_conf = serde_json::from_value(conf)?;
+            }
+            UpdateConfMode::TemplateModeCustomize => {
+                let customize_conf: CustomizeConf = serde_json::from_value(conf)?;
+                join_handle_data.opcua_conf.host = customize_conf.host;
+                join_handle_data.opcua_conf.port = customize_conf.port;
+            }
+            UpdateConfMode::TemplateModeTemplate => {
+                let template_conf: TemplateConf = serde_json::from_value(conf)?;
+                join_handle_data.opcua_conf.path = template_conf.path;
+                join_handle_data.opcua_conf.reconnect = template_conf.reconnect;
+                join_handle_data.opcua_conf.auth_method = template_conf.auth_method;
+                join_handle_data.opcua_conf.auth_username = template_conf.auth_username;
+                join_handle_data.opcua_conf.auth_certificate = template_conf.auth_certificate;
+                join_handle_data.opcua_conf.security_policy = template_conf.security_policy;
+                join_handle_data.opcua_conf.security_mode = template_conf.security_mode;
+                join_handle_data.opcua_conf.client_certificate = template_conf.client_certificate;
+                join_handle_data.opcua_conf.trusted_server_certs =
+                    template_conf.trusted_server_certs;
+                join_handle_data.opcua_conf.trust_unknown_server_certs =
+                    template_conf.trust_unknown_server_certs;
+            }
+        }
         let join_handle = Self::event_loop(join_handle_data);
         self.join_handle = Some(join_handle);
+        Ok(())
     }
 
+    #[allow(dead_code)]
     fn get_source_conf(
         customize_conf: serde_json::Value,
         template_conf: serde_json::Value,
     ) -> HaliaResult<SourceConf> {
-        let customize_conf: SourceConf = serde_json::from_value(customize_conf).unwrap();
-        let template_conf: SourceConf = serde_json::from_value(template_conf).unwrap();
+        let customize_conf: SourceConf = serde_json::from_value(customize_conf)?;
+        let template_conf: SourceConf = serde_json::from_value(template_conf)?;
         Ok(SourceConf {
             typ: customize_conf.typ,
             group: customize_conf.group.or(template_conf.group),
@@ -242,20 +372,33 @@ impl Opcua {
             monitored_item: customize_conf
                 .monitored_item
                 .or(template_conf.monitored_item),
-            metadatas: todo!(),
+            metadatas: customize_conf.metadatas.or(template_conf.metadatas),
         })
     }
+
+    async fn get_session(&self) -> HaliaResult<Arc<Session>> {
+        if let Some(err) = self.err.lock().await.as_ref() {
+            return Err(HaliaError::Common(err.to_string()));
+        }
+
+        match self.opcua_client.read().await.as_ref() {
+            Some(session) => Ok(session.clone()),
+            None => Err(HaliaError::Common("设备未连接！".to_owned())),
+        }
+    }
 }
 
 #[async_trait]
 impl Device for Opcua {
     async fn read_device_err(&self) -> Option<Arc<String>> {
-        // self.err.read().await.clone()
-        todo!()
+        self.read_err().await
     }
 
-    async fn read_source_err(&self, _source_id: &String) -> Option<String> {
-        None
+    async fn read_source_err(&self, source_id: &String) -> Option<String> {
+        match self.sources.get(source_id) {
+            Some(source) => source.read_err().await.map(|err| (*err).clone()),
+            None => None,
+        }
     }
 
     async fn read_sink_err(&self, _sink_id: &String) -> Option<String> {
@@ -267,9 +410,7 @@ impl Device for Opcua {
         mode: UpdateConfMode,
         conf: serde_json::Value,
     ) -> HaliaResult<()> {
-        let opcua_conf: OpcuaConf = serde_json::from_value(conf)?;
-        Self::update_conf(&mut self, opcua_conf).await;
-        Ok(())
+        Self::update_conf(self, mode, conf).await
     }
 
     async fn create_source(
@@ -278,7 +419,13 @@ impl Device for Opcua {
         conf: serde_json::Value,
     ) -> HaliaResult<()> {
         let conf: SourceConf = serde_json::from_value(conf)?;
-        let source = Source::new(self.opcua_client.clone(), conf).await;
+        Source::validate_conf(&conf)?;
+        let source = Source::new(
+            source_id.clone(),
+            conf,
+            self.opcua_client.clone(),
+            self.device_err_tx.subscribe(),
+        );
         self.sources.insert(source_id, source);
         Ok(())
     }
@@ -288,18 +435,49 @@ impl Device for Opcua {
         source_id: &String,
         conf: serde_json::Value,
     ) -> HaliaResult<()> {
-        let conf: SourceConf = serde_json::from_value(conf)?;
         match self.sources.get_mut(source_id) {
-            Some(mut source) => {
-                source.update_conf(conf).await;
-                Ok(())
-            }
+            Some(mut source) => source.update(conf).await,
             None => Err(HaliaError::NotFound(source_id.to_string())),
         }
     }
 
-    async fn write_source_value(&mut self, source_id: String, value: Value) -> HaliaResult<()> {
-        todo!()
+    // 监控项类型直接写入值，组及订阅类型的值需为以字段名为键的对象
+    async fn write_source_value(&mut self, source_id: String, req: Value) -> HaliaResult<()> {
+        let variables = match self.sources.get(&source_id) {
+            Some(source) => source.get_write_variables(req.value)?,
+            None => return Err(HaliaError::NotFound(source_id)),
+        };
+
+        let session = self.get_session().await?;
+        for (node_id, value) in variables {
+            let typ = read_variant_type(&session, &node_id).await?;
+            let variant = json_to_variant(value, typ)?;
+            write_variant(&session, node_id, variant).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn browse(&self, req: BrowseReq) -> HaliaResult<BrowseResp> {
+        let session = self.get_session().await?;
+        browse::browse(&session, req).await
+    }
+
+    async fn browse_next(&self, req: BrowseNextReq) -> HaliaResult<BrowseResp> {
+        let session = self.get_session().await?;
+        browse::browse_next(&session, req).await
+    }
+
+    async fn translate_browse_path(
+        &self,
+        req: TranslateBrowsePathReq,
+    ) -> HaliaResult<TranslateBrowsePathResp> {
+        let session = self.get_session().await?;
+        browse::translate_browse_path(&session, req).await
+    }
+
+    async fn get_client_certificate(&self) -> HaliaResult<ClientCertificateResp> {
+        security::read_client_certificate(&self.id)
     }
 
     async fn delete_source(&mut self, source_id: &String) -> HaliaResult<()> {
@@ -314,17 +492,22 @@ impl Device for Opcua {
 
     async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
         let conf: SinkConf = serde_json::from_value(conf)?;
-        let sink = Sink::new(self.opcua_client.clone(), conf);
+        Sink::validate_conf(&conf)?;
+        let sink = Sink::new(
+            &sink_id,
+            conf,
+            self.opcua_client.clone(),
+            self.device_err_tx.subscribe(),
+        )?;
         self.sinks.insert(sink_id, sink);
         Ok(())
     }
 
-    async fn update_sink(
-        &mut self,
-        source_id: &String,
-        conf: serde_json::Value,
-    ) -> HaliaResult<()> {
-        todo!()
+    async fn update_sink(&mut self, sink_id: &String, conf: serde_json::Value) -> HaliaResult<()> {
+        match self.sinks.get_mut(sink_id) {
+            Some(mut sink) => sink.update(sink_id, conf).await,
+            None => Err(HaliaError::NotFound(sink_id.to_string())),
+        }
     }
 
     async fn delete_sink(&mut self, sink_id: &String) -> HaliaResult<()> {
@@ -342,11 +525,10 @@ impl Device for Opcua {
         source_id: &String,
         cnt: usize,
     ) -> HaliaResult<Vec<mpsc::UnboundedReceiver<RuleMessageBatch>>> {
-        // match self.sources.get(source_id) {
-        //     Some(source) => Ok(source.mb_tx.subscribe()),
-        //     None => Err(HaliaError::NotFound(source_id.to_string())),
-        // }
-        todo!()
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt).await),
+            None => Err(HaliaError::NotFound(source_id.to_string())),
+        }
     }
 
     async fn get_sink_txs(
@@ -354,11 +536,10 @@ impl Device for Opcua {
         sink_id: &String,
         cnt: usize,
     ) -> HaliaResult<Vec<mpsc::UnboundedSender<RuleMessageBatch>>> {
-        todo!()
-        // match self.sinks.get(sink_id) {
-        //     Some(sink) => Ok(sink.mb_tx.clone()),
-        //     None => Err(HaliaError::NotFound(sink_id.to_string())),
-        // }
+        match self.sinks.get(sink_id) {
+            Some(sink) => Ok(sink.get_txs(cnt)),
+            None => Err(HaliaError::NotFound(sink_id.to_string())),
+        }
     }
 
     async fn stop(&mut self) {
@@ -370,17 +551,37 @@ impl Device for Opcua {
             sink.stop().await;
         }
 
+        // 由事件循环断开会话
         self.stop_signal_tx.send(()).unwrap();
-        match self.opcua_client.read().await.as_ref() {
-            Some(session) => match session.disconnect().await {
-                Ok(_) => {
-                    debug!("session disconnect success");
-                }
-                Err(e) => {
-                    debug!("err code is :{}", e);
-                }
-            },
-            None => {}
+        _ = self.join_handle.take().unwrap().await;
+        remove_certificate(&self.id);
+    }
+}
+
+const USER_CERT_FILE: &str = "user_cert.pem";
+const USER_KEY_FILE: &str = "user_key.pem";
+
+// 证书认证时将PEM内容写入文件，供opcua客户端加载，私钥文件仅允许当前用户读写
+fn write_certificate(id: &String, certificate: &AuthCertificate) -> Result<(PathBuf, PathBuf)> {
+    let dir = security::get_pki_dir(id);
+    fs::create_dir_all(&dir)?;
+    let cert_path = dir.join(USER_CERT_FILE);
+    fs::write(&cert_path, &certificate.cert)?;
+
+    let key_path = dir.join(USER_KEY_FILE);
+    security::write_private_key(&key_path, certificate.key.as_bytes())?;
+    Ok((cert_path, key_path))
+}
+
+// 会话结束后删除证书认证使用的文件
+fn remove_certificate(id: &String) {
+    let dir = security::get_pki_dir(id);
+    for file in [USER_CERT_FILE, USER_KEY_FILE] {
+        let path = dir.join(file);
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("删除证书文件{:?}失败：{}", path, e);
+            }
         }
     }
 }
@@ -421,3 +622,77 @@ fn transfer_monitoring_node(
         types::devices::device::opcua::MonitoringMode::Reporting => MonitoringMode::Reporting,
     }
 }
+
+// 读取节点的数据类型，写入前需将值转换为该类型
+async fn read_variant_type(session: &Session, node_id: &NodeId) -> HaliaResult<VariantTypeId> {
+    let read_value_id = ReadValueId {
+        node_id: node_id.clone(),
+        attribute_id: AttributeId::DataType as u32,
+        index_range: UAString::null(),
+        data_encoding: QualifiedName::null(),
+    };
+    let data_values = session
+        .read(&[read_value_id], TimestampsToReturn::Neither, 0.0)
+        .await
+        .map_err(|e| HaliaError::Common(e.to_string()))?;
+    match data_values.into_iter().next().and_then(|dv| dv.value) {
+        Some(Variant::NodeId(data_type)) => match VariantTypeId::try_from(data_type.as_ref()) {
+            Ok(typ) => Ok(typ),
+            Err(_) => Err(HaliaError::Common(format!(
+                "不支持的数据类型：{}",
+                data_type
+            ))),
+        },
+        _ => Err(HaliaError::Common("读取节点数据类型失败！".to_owned())),
+    }
+}
+
+fn json_to_variant(value: serde_json::Value, typ: VariantTypeId) -> HaliaResult<Variant> {
+    let variant = match value {
+        serde_json::Value::Bool(b) => Variant::Boolean(b),
+        serde_json::Value::Number(number) => {
+            if let Some(i) = number.as_i64() {
+                Variant::Int64(i)
+            } else if let Some(u) = number.as_u64() {
+                Variant::UInt64(u)
+            } else {
+                Variant::Double(number.as_f64().unwrap())
+            }
+        }
+        serde_json::Value::String(s) => Variant::String(s.into()),
+        serde_json::Value::Array(values) => {
+            let mut variants = Vec::with_capacity(values.len());
+            for value in values {
+                variants.push(json_to_variant(value, typ)?);
+            }
+            return Ok(Variant::from((typ, variants)));
+        }
+        serde_json::Value::Null | serde_json::Value::Object(_) => {
+            return Err(HaliaError::Common("不支持的值类型！".to_owned()))
+        }
+    };
+
+    match variant.cast(typ) {
+        Variant::Empty => Err(HaliaError::Common(format!("值无法转换为{:?}类型！", typ))),
+        variant => Ok(variant),
+    }
+}
+
+async fn write_variant(session: &Session, node_id: NodeId, variant: Variant) -> HaliaResult<()> {
+    let nodes_to_write = [WriteValue {
+        node_id,
+        attribute_id: AttributeId::Value as u32,
+        index_range: UAString::null(),
+        value: variant.into(),
+    }];
+    let results = session
+        .write(&nodes_to_write)
+        .await
+        .map_err(|e| HaliaError::Common(e.to_string()))?;
+    match results.first() {
+        Some(status_code) if !status_code.is_good() => {
+            Err(HaliaError::Common(status_code.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
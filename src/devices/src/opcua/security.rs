@@ -0,0 +1,238 @@
+use std::{fs, io::Write, path::PathBuf};
+
+use anyhow::{bail, Result};
+use common::error::{HaliaError, HaliaResult};
+use opcua_protocol::{
+    crypto::{certificate_store::CertificateStore, SecurityPolicy, X509},
+    types::MessageSecurityMode,
+};
+use types::devices::device::opcua::{
+    AuthCertificate, ClientCertificateResp, OpcuaConf, SecurityMode,
+};
+
+// 相对于pki目录
+pub(super) const CLIENT_CERT_PATH: &str = "own/cert.pem";
+pub(super) const CLIENT_KEY_PATH: &str = "private/private.pem";
+// 自动生成的客户端证书路径
+const SAMPLE_CLIENT_CERT_PATH: &str = "own/cert.der";
+const TRUSTED_CERTS_DIR: &str = "trusted";
+const REJECTED_CERTS_DIR: &str = "rejected";
+
+// 每个设备使用独立的pki目录
+pub(super) fn get_pki_dir(id: &String) -> PathBuf {
+    PathBuf::from("./pki/opcua").join(id)
+}
+
+pub(super) fn validate_security(
+    security_policy: &types::devices::device::opcua::SecurityPolicy,
+    security_mode: &SecurityMode,
+    client_certificate: &Option<AuthCertificate>,
+    trusted_server_certs: &[String],
+) -> HaliaResult<()> {
+    match (security_policy, security_mode) {
+        (types::devices::device::opcua::SecurityPolicy::None, SecurityMode::None) => {}
+        (types::devices::device::opcua::SecurityPolicy::None, _) => {
+            return Err(HaliaError::Common(
+                "安全策略为None时安全模式必须为None！".to_owned(),
+            ))
+        }
+        (_, SecurityMode::None) => {
+            return Err(HaliaError::Common(
+                "安全模式为None时安全策略必须为None！".to_owned(),
+            ))
+        }
+        _ => {}
+    }
+
+    if let Some(certificate) = client_certificate {
+        if certificate.cert.is_empty() || certificate.key.is_empty() {
+            return Err(HaliaError::Common("客户端证书或私钥内容为空！".to_owned()));
+        }
+        if X509::from_pem(certificate.cert.as_bytes()).is_err() {
+            return Err(HaliaError::Common("客户端证书格式错误！".to_owned()));
+        }
+    }
+
+    for cert in trusted_server_certs.iter() {
+        if X509::from_pem(cert.as_bytes()).is_err() {
+            return Err(HaliaError::Common("服务端证书格式错误！".to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) fn transfer_security_policy(
+    security_policy: &types::devices::device::opcua::SecurityPolicy,
+) -> SecurityPolicy {
+    match security_policy {
+        types::devices::device::opcua::SecurityPolicy::None => SecurityPolicy::None,
+        types::devices::device::opcua::SecurityPolicy::Basic256Sha256 => {
+            SecurityPolicy::Basic256Sha256
+        }
+        types::devices::device::opcua::SecurityPolicy::Aes128Sha256RsaOaep => {
+            SecurityPolicy::Aes128Sha256RsaOaep
+        }
+        types::devices::device::opcua::SecurityPolicy::Aes256Sha256RsaPss => {
+            SecurityPolicy::Aes256Sha256RsaPss
+        }
+    }
+}
+
+pub(super) fn transfer_security_mode(security_mode: &SecurityMode) -> MessageSecurityMode {
+    match security_mode {
+        SecurityMode::None => MessageSecurityMode::None,
+        SecurityMode::Sign => MessageSecurityMode::Sign,
+        SecurityMode::SignAndEncrypt => MessageSecurityMode::SignAndEncrypt,
+    }
+}
+
+// 连接前根据配置准备pki目录，客户端证书及信任列表均以配置为准
+pub(super) fn prepare_pki(id: &String, conf: &OpcuaConf) -> Result<PathBuf> {
+    let pki_dir = get_pki_dir(id);
+
+    let cert_path = pki_dir.join(CLIENT_CERT_PATH);
+    let key_path = pki_dir.join(CLIENT_KEY_PATH);
+    match &conf.client_certificate {
+        Some(certificate) => {
+            write_file(&cert_path, certificate.cert.as_bytes())?;
+            write_private_key(&key_path, certificate.key.as_bytes())?;
+        }
+        None => {
+            // 删除之前配置的证书，使用自动生成的证书
+            if cert_path.exists() {
+                fs::remove_file(&cert_path)?;
+            }
+            if key_path.exists() {
+                fs::remove_file(&key_path)?;
+            }
+        }
+    }
+
+    // 自动信任时保留已信任的证书，否则重建信任列表
+    let trusted_dir = pki_dir.join(TRUSTED_CERTS_DIR);
+    if !conf.trust_unknown_server_certs && trusted_dir.exists() {
+        fs::remove_dir_all(&trusted_dir)?;
+    }
+    fs::create_dir_all(&trusted_dir)?;
+    let rejected_dir = pki_dir.join(REJECTED_CERTS_DIR);
+    for cert in conf.trusted_server_certs.iter() {
+        let cert = match X509::from_pem(cert.as_bytes()) {
+            Ok(cert) => cert,
+            Err(_) => bail!("服务端证书格式错误！"),
+        };
+        let der = match cert.to_der() {
+            Ok(der) => der,
+            Err(_) => bail!("服务端证书格式错误！"),
+        };
+        // 证书库以证书名称判断是否信任，被拒绝过的证书需从拒绝目录中移除
+        let file_name = CertificateStore::cert_file_name(&cert);
+        let rejected_path = rejected_dir.join(&file_name);
+        if rejected_path.exists() {
+            fs::remove_file(&rejected_path)?;
+        }
+        fs::write(trusted_dir.join(file_name), der)?;
+    }
+
+    Ok(pki_dir)
+}
+
+// 返回客户端证书，用于添加至服务端的信任列表中
+pub(super) fn read_client_certificate(id: &String) -> HaliaResult<ClientCertificateResp> {
+    let pki_dir = get_pki_dir(id);
+
+    let cert_path = pki_dir.join(CLIENT_CERT_PATH);
+    if cert_path.exists() {
+        let cert = fs::read_to_string(&cert_path)?;
+        return Ok(ClientCertificateResp { cert });
+    }
+
+    let cert_path = pki_dir.join(SAMPLE_CLIENT_CERT_PATH);
+    if !cert_path.exists() {
+        return Err(HaliaError::Common("客户端证书尚未生成！".to_owned()));
+    }
+    let cert = CertificateStore::read_cert(&cert_path).map_err(HaliaError::Common)?;
+    match cert.to_pem() {
+        Ok(pem) => Ok(ClientCertificateResp {
+            cert: String::from_utf8_lossy(&pem).to_string(),
+        }),
+        Err(_) => Err(HaliaError::Common("客户端证书格式错误！".to_owned())),
+    }
+}
+
+fn write_file(path: &PathBuf, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+// 私钥文件仅允许当前用户读写，权限仅在创建文件时生效，先删除之前的文件
+pub(super) fn write_private_key(path: &PathBuf, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use types::devices::device::opcua::{SecurityMode, SecurityPolicy};
+
+    use super::{validate_security, write_private_key};
+
+    #[test]
+    fn test_validate_security() {
+        assert!(validate_security(&SecurityPolicy::None, &SecurityMode::None, &None, &[]).is_ok());
+        assert!(validate_security(
+            &SecurityPolicy::Basic256Sha256,
+            &SecurityMode::SignAndEncrypt,
+            &None,
+            &[]
+        )
+        .is_ok());
+        assert!(validate_security(&SecurityPolicy::None, &SecurityMode::Sign, &None, &[]).is_err());
+        assert!(validate_security(
+            &SecurityPolicy::Aes256Sha256RsaPss,
+            &SecurityMode::None,
+            &None,
+            &[]
+        )
+        .is_err());
+        assert!(validate_security(
+            &SecurityPolicy::None,
+            &SecurityMode::None,
+            &None,
+            &["invalid".to_owned()]
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private_key() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let path = std::env::temp_dir()
+            .join("halia_opcua_security_test")
+            .join("private/private.pem");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private_key(&path, b"key").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "key");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+}
@@ -1,113 +1,187 @@
 use std::sync::Arc;
 
-use anyhow::Result;
+use common::{
+    error::HaliaResult,
+    sink_message_retain::{self, SinkMessageRetain},
+};
+use halia_derive::{ResourceStop, SinkTxs};
 use message::{MessageBatch, RuleMessageBatch};
 use opcua_protocol::{
     client::Session,
-    types::{DataValue, UAString, WriteValue},
+    types::{NodeId, VariantTypeId},
 };
 use tokio::{
     select,
     sync::{
-        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        broadcast,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
         watch, RwLock,
     },
+    task::JoinHandle,
 };
 use tracing::warn;
 use types::devices::device::opcua::SinkConf;
 
-use super::transfer_node_id;
+use super::{json_to_variant, read_variant_type, transfer_node_id, write_variant};
 
+#[derive(ResourceStop, SinkTxs)]
 pub struct Sink {
     stop_signal_tx: watch::Sender<()>,
-    pub mb_tx: UnboundedSender<RuleMessageBatch>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_tx: UnboundedSender<RuleMessageBatch>,
 }
 
-impl Sink {
-    pub fn validate_conf(_conf: SinkConf) -> Result<()> {
-        Ok(())
-    }
+pub struct TaskLoop {
+    sink_conf: SinkConf,
+    node_id: NodeId,
+    // 节点的数据类型，首次写入时读取
+    variant_type: Option<VariantTypeId>,
+    opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
+    stop_signal_rx: watch::Receiver<()>,
+    mb_rx: UnboundedReceiver<RuleMessageBatch>,
+    device_err_rx: broadcast::Receiver<bool>,
+    message_retainer: Box<dyn SinkMessageRetain>,
+}
 
-    pub fn new(opcua_client: Arc<RwLock<Option<Arc<Session>>>>, conf: SinkConf) -> Self {
-        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
-        let (mb_tx, mb_rx) = unbounded_channel();
-        Self::event_loop(conf, stop_signal_rx, mb_rx);
-        Self {
-            stop_signal_tx,
-            mb_tx,
-        }
+impl TaskLoop {
+    fn new(
+        sink_id: &str,
+        sink_conf: SinkConf,
+        opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
+        stop_signal_rx: watch::Receiver<()>,
+        mb_rx: UnboundedReceiver<RuleMessageBatch>,
+        device_err_rx: broadcast::Receiver<bool>,
+    ) -> HaliaResult<Self> {
+        let message_retainer = sink_message_retain::new(&sink_conf.message_retain, sink_id)?;
+        let node_id = transfer_node_id(&sink_conf.node_id);
+        Ok(Self {
+            sink_conf,
+            node_id,
+            variant_type: None,
+            opcua_client,
+            stop_signal_rx,
+            mb_rx,
+            device_err_rx,
+            message_retainer,
+        })
     }
 
-    fn event_loop(
-        conf: SinkConf,
-        mut stop_signal_rx: watch::Receiver<()>,
-        mut mb_rx: UnboundedReceiver<RuleMessageBatch>,
-    ) {
+    fn start(mut self) -> JoinHandle<Self> {
         tokio::spawn(async move {
             loop {
                 select! {
-                    _ = stop_signal_rx.changed() => {
-                        break;
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
                     }
-                    Some(mb) = mb_rx.recv() => {
-                        // Self::handle_mb(&conf, &opcua_client, mb).await;
+
+                    Some(mb) = self.mb_rx.recv() => {
+                        let mb = mb.take_mb();
+                        let session = self.opcua_client.read().await.clone();
+                        match session {
+                            Some(session) => self.write(&session, mb).await,
+                            None => self.message_retainer.push(mb),
+                        }
+                    }
+
+                    Ok(err) = self.device_err_rx.recv() => {
+                        // 设备恢复后发送保留的消息
+                        if !err {
+                            let session = self.opcua_client.read().await.clone();
+                            if let Some(session) = session {
+                                while let Some(mb) = self.message_retainer.pop() {
+                                    self.write(&session, mb).await;
+                                }
+                            }
+                        }
                     }
                 }
             }
-        });
+        })
     }
 
-    async fn handle_mb(
-        conf: &SinkConf,
-        opcua_client: &Arc<RwLock<Option<Arc<Session>>>>,
-        mut mb: MessageBatch,
-    ) {
-        let msg = match mb.take_one_message() {
-            Some(msg) => msg,
+    async fn write(&mut self, session: &Session, mut mb: MessageBatch) {
+        let message = match mb.take_one_message() {
+            Some(message) => message,
             None => return,
         };
 
-        let value = match msg.get(&conf.field) {
-            Some(value) => value,
+        let value: serde_json::Value = match message.get(&self.sink_conf.field) {
+            Some(value) => value.clone().into(),
             None => return,
         };
 
-        let data_value = match value {
-            message::MessageValue::Null => todo!(),
-            message::MessageValue::Boolean(b) => DataValue::from(*b),
-            message::MessageValue::Int64(i64) => DataValue::from(*i64),
-            message::MessageValue::Float64(_) => todo!(),
-            message::MessageValue::String(_) => todo!(),
-            message::MessageValue::Bytes(vec) => todo!(),
-            message::MessageValue::Array(vec) => todo!(),
-            message::MessageValue::Object(hash_map) => todo!(),
+        let variant_type = match self.variant_type {
+            Some(variant_type) => variant_type,
+            None => match read_variant_type(session, &self.node_id).await {
+                Ok(variant_type) => {
+                    self.variant_type = Some(variant_type);
+                    variant_type
+                }
+                Err(e) => {
+                    warn!("read opcua data type err: {}", e);
+                    return;
+                }
+            },
         };
 
-        let node_id = transfer_node_id(&conf.node_id);
-        let nodes_to_write = vec![WriteValue {
-            node_id,
-            attribute_id: 13,
-            index_range: UAString::null(),
-            value: data_value,
-        }];
-
-        match opcua_client.read().await.as_ref() {
-            Some(client) => {
-                if let Err(e) = client.write(nodes_to_write.as_slice()).await {
-                    warn!("Failed to write to opcua: {:?}", e);
-                }
+        let variant = match json_to_variant(value, variant_type) {
+            Ok(variant) => variant,
+            Err(e) => {
+                warn!("value is err: {}", e);
+                return;
             }
-            None => return,
         };
+
+        if let Err(e) = write_variant(session, self.node_id.clone(), variant).await {
+            warn!("Failed to write to opcua: {}", e);
+        }
+    }
+}
+
+impl Sink {
+    pub fn validate_conf(_conf: &SinkConf) -> HaliaResult<()> {
+        Ok(())
     }
 
-    pub async fn update_conf(&mut self, old_conf: SinkConf, new_conf: SinkConf) {
-        todo!()
+    pub fn new(
+        sink_id: &str,
+        sink_conf: SinkConf,
+        opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
+        device_err_rx: broadcast::Receiver<bool>,
+    ) -> HaliaResult<Self> {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (mb_tx, mb_rx) = mpsc::unbounded_channel();
+        let task_loop = TaskLoop::new(
+            sink_id,
+            sink_conf,
+            opcua_client,
+            stop_signal_rx,
+            mb_rx,
+            device_err_rx,
+        )?;
+        let join_handle = task_loop.start();
+        Ok(Self {
+            stop_signal_tx,
+            join_handle: Some(join_handle),
+            mb_tx,
+        })
     }
 
-    pub async fn stop(&mut self) {
-        if let Err(e) = self.stop_signal_tx.send(()) {
-            warn!("Failed to send stop signal: {:?}", e);
-        }
+    pub async fn update(&mut self, sink_id: &str, sink_conf: serde_json::Value) -> HaliaResult<()> {
+        let sink_conf: SinkConf = serde_json::from_value(sink_conf)?;
+        Self::validate_conf(&sink_conf)?;
+        let mut task_loop = self.stop().await;
+        let res = sink_message_retain::update(
+            &mut task_loop.message_retainer,
+            &task_loop.sink_conf.message_retain,
+            &sink_conf.message_retain,
+            sink_id,
+        );
+        task_loop.node_id = transfer_node_id(&sink_conf.node_id);
+        task_loop.variant_type = None;
+        task_loop.sink_conf = sink_conf;
+        let join_handle = task_loop.start();
+        self.join_handle = Some(join_handle);
+        res
     }
 }
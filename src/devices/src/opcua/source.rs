@@ -1,319 +1,541 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
-use anyhow::{bail, Result};
-use message::{Message, MessageBatch, MessageValue};
+use common::error::{HaliaError, HaliaResult};
+use futures::lock::BiLock;
+use halia_derive::{ResourceErr, ResourceStop, SourceRxs};
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
 use opcua_protocol::{
-    client::{DataChangeCallback, MonitoredItem, Session},
+    client::{DataChangeCallback, Session},
     types::{
-        DataValue, ExtensionObject, MonitoredItemCreateRequest, MonitoringParameters,
-        QualifiedName, ReadValueId, TimestampsToReturn, UAString,
+        DataValue, ExtensionObject, MonitoredItemCreateRequest, MonitoringParameters, NodeId,
+        QualifiedName, ReadValueId, TimestampsToReturn, UAString, Variant,
     },
 };
 use tokio::{
     select,
-    sync::{broadcast, watch, RwLock},
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedSender},
+        watch, RwLock,
+    },
     task::JoinHandle,
     time,
 };
-use tracing::warn;
-use types::devices::device::opcua::{GroupConf, SourceConf, Subscriptionconf, VariableConf};
+use tracing::{debug, warn};
+use types::devices::device::opcua::{
+    MonitoredItemconf, SourceConf, SourceType, Subscriptionconf, VariableConf,
+};
+use utils::ErrorManager;
 
 use super::{transfer_monitoring_node, transfer_node_id};
 
+#[derive(ResourceErr, ResourceStop, SourceRxs)]
 pub struct Source {
-    // variables: Option<Arc<RwLock<(Vec<Variable>, Vec<ReadValueId>)>>>,
-    group_stop_signal_tx: Option<watch::Sender<()>>,
-    group_join_handle: Option<JoinHandle<GroupJoinHandleData>>,
-    err: Option<String>,
-
-    pub mb_tx: broadcast::Sender<MessageBatch>,
+    pub source_conf: SourceConf,
+    stop_signal_tx: watch::Sender<()>,
+    err: BiLock<Option<Arc<String>>>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
 }
 
-pub struct GroupJoinHandleData {
-    pub opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
-    pub conf: GroupConf,
-    pub stop_signal_rx: watch::Receiver<()>,
-    pub mb_tx: broadcast::Sender<MessageBatch>,
+pub struct TaskLoop {
+    source_conf: SourceConf,
+    opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
+    stop_signal_rx: watch::Receiver<()>,
+    device_err_rx: broadcast::Receiver<bool>,
+    mb_txs: BiLock<Vec<UnboundedSender<RuleMessageBatch>>>,
+    error_manager: ErrorManager,
+    // 订阅所在的会话及订阅id，断线期间保留，重连后由opcua客户端转移或重建
+    subscription: Option<(Arc<Session>, u32)>,
 }
 
-impl Source {
-    pub async fn new(opcua_client: Arc<RwLock<Option<Arc<Session>>>>, conf: SourceConf) -> Self {
-        let (mb_tx, _) = broadcast::channel(16);
-
-        let mut source = Self {
-            group_stop_signal_tx: None,
-            mb_tx: mb_tx.clone(),
-            group_join_handle: None,
-            err: None,
-        };
-
-        match conf.typ {
-            types::devices::device::opcua::SourceType::Group => {
-                let (group_stop_signal_tx, group_stop_signal_rx) = watch::channel(());
-                let join_handle_data = GroupJoinHandleData {
-                    opcua_client: opcua_client.clone(),
-                    conf: conf.group.unwrap(),
-                    stop_signal_rx: group_stop_signal_rx,
-                    mb_tx,
-                };
-                let join_handle = Self::start_group(join_handle_data);
-                source.group_stop_signal_tx = Some(group_stop_signal_tx);
-                source.group_join_handle = Some(join_handle);
+impl TaskLoop {
+    fn start(mut self) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            match self.source_conf.typ {
+                SourceType::Group => self.run_group().await,
+                SourceType::Subscription | SourceType::MonitoredItem => {
+                    self.run_subscription().await
+                }
             }
-            types::devices::device::opcua::SourceType::Subscription => {}
-            types::devices::device::opcua::SourceType::MonitoredItem => todo!(),
+            self
+        })
+    }
+
+    async fn run_group(&mut self) {
+        let group_conf = self.source_conf.group.as_ref().unwrap();
+        let max_age = group_conf.max_age;
+        let mut fields = vec![];
+        let mut read_value_ids = vec![];
+        for variable_conf in group_conf.variables.iter() {
+            fields.push(variable_conf.field.clone());
+            read_value_ids.push(get_read_value_id(variable_conf));
         }
 
-        source
-    }
+        let mut interval = time::interval(Duration::from_millis(group_conf.interval));
+        loop {
+            select! {
+                _ = self.stop_signal_rx.changed() => {
+                    return;
+                }
 
-    pub fn validate_conf(conf: SourceConf) -> Result<()> {
-        match conf.typ {
-            types::devices::device::opcua::SourceType::Group => match conf.group {
-                Some(_group) => Ok(()),
-                None => bail!("类型为组类型，配置为空"),
-            },
-            types::devices::device::opcua::SourceType::Subscription => match conf.subscription {
-                Some(subscription) => {
-                    if subscription.publishing_interval == 0 {
-                        bail!("发布间隔必须大于0");
-                    }
-                    Ok(())
+                _ = interval.tick() => {
+                    self.read_group(&fields, &read_value_ids, max_age).await;
                 }
-                None => bail!("类型为订阅类型，配置为空"),
-            },
-            types::devices::device::opcua::SourceType::MonitoredItem => match conf.monitored_item {
-                Some(_monitored_item) => Ok(()),
-                None => bail!("类型为监控类型，配置为空"),
-            },
+            }
         }
     }
 
-    fn start_group(mut join_handle_data: GroupJoinHandleData) -> JoinHandle<GroupJoinHandleData> {
-        let mut need_read_variable_fields = vec![];
-        let mut need_read_variable_ids = vec![];
-        for variable_conf in join_handle_data.conf.variables.iter() {
-            need_read_variable_fields.push(variable_conf.field.clone());
-            need_read_variable_ids.push(Self::group_get_read_value_id(variable_conf));
+    async fn read_group(
+        &mut self,
+        fields: &[String],
+        read_value_ids: &[ReadValueId],
+        max_age: f64,
+    ) {
+        if self.mb_txs.lock().await.is_empty() {
+            return;
         }
 
-        tokio::spawn(async move {
-            let mut interval =
-                time::interval(Duration::from_millis(join_handle_data.conf.interval));
+        let session = match self.opcua_client.read().await.as_ref() {
+            Some(session) => session.clone(),
+            None => return,
+        };
 
-            loop {
-                select! {
-                    _ = join_handle_data.stop_signal_rx.changed() => {
-                        return join_handle_data;
+        match session
+            .read(read_value_ids, TimestampsToReturn::Neither, max_age)
+            .await
+        {
+            Ok(data_values) => {
+                self.error_manager.set_ok().await;
+                let mut message = Message::default();
+                for (field, data_value) in fields.iter().zip(data_values) {
+                    message.add(field.clone(), data_value_to_message_value(data_value));
+                }
+                self.send(message).await;
+            }
+            Err(e) => {
+                warn!("opcua read err code :{:?}", e);
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+            }
+        }
+    }
+
+    async fn run_subscription(&mut self) {
+        // 回调运行在opcua的事件循环中，通过通道将数据转交给当前任务
+        let (data_tx, mut data_rx) = mpsc::unbounded_channel::<(u32, DataValue)>();
+        let fields: HashMap<u32, String> = self
+            .get_monitored_items()
+            .iter()
+            .map(|item| (item.client_handle, item.variable.field.clone()))
+            .collect();
+
+        self.create_subscription(&data_tx).await;
+        loop {
+            select! {
+                _ = self.stop_signal_rx.changed() => {
+                    self.delete_subscription().await;
+                    return;
+                }
+
+                Ok(err) = self.device_err_rx.recv() => {
+                    if !err {
+                        self.create_subscription(&data_tx).await;
                     }
-                    _ = interval.tick() => {
-                        Self::group_read_variables_from_remote(&join_handle_data.opcua_client, &need_read_variable_fields, &need_read_variable_ids, join_handle_data.conf.max_age, &join_handle_data.mb_tx).await;
+                }
+
+                Some((client_handle, data_value)) = data_rx.recv() => {
+                    if let Some(field) = fields.get(&client_handle) {
+                        let mut message = Message::default();
+                        message.add(field.clone(), data_value_to_message_value(data_value));
+                        self.send(message).await;
                     }
                 }
             }
-        })
+        }
     }
 
-    async fn start_subscription(
-        conf: Subscriptionconf,
-        opcua_client: Arc<Session>,
-        mb_tx: broadcast::Sender<MessageBatch>,
-    ) {
-        let opcua_subscription_id = opcua_client
+    fn get_monitored_items(&self) -> Vec<&MonitoredItemconf> {
+        match self.source_conf.typ {
+            SourceType::Subscription => self
+                .source_conf
+                .subscription
+                .as_ref()
+                .unwrap()
+                .monitored_items
+                .iter()
+                .collect(),
+            SourceType::MonitoredItem => vec![self.source_conf.monitored_item.as_ref().unwrap()],
+            SourceType::Group => unreachable!(),
+        }
+    }
+
+    async fn create_subscription(&mut self, data_tx: &UnboundedSender<(u32, DataValue)>) {
+        let session = match self.opcua_client.read().await.as_ref() {
+            Some(session) => session.clone(),
+            None => return,
+        };
+        // 同一会话内的断线重连由opcua客户端转移订阅，转移失败时客户端以新的id重建，
+        // 只有会话已更换或客户端重建也失败时才需要重新创建
+        if let Some((subscription_session, subscription_id)) = self.subscription.take() {
+            if Arc::ptr_eq(&subscription_session, &session) {
+                if let Some(subscription_id) = session.current_subscription_id(subscription_id) {
+                    self.subscription = Some((subscription_session, subscription_id));
+                    return;
+                }
+            }
+        }
+
+        let subscription_conf = match self.source_conf.typ {
+            SourceType::Subscription => self.source_conf.subscription.clone().unwrap(),
+            _ => get_monitored_item_subscription_conf(
+                self.source_conf.monitored_item.as_ref().unwrap(),
+            ),
+        };
+        let items_to_create: Vec<MonitoredItemCreateRequest> = self
+            .get_monitored_items()
+            .into_iter()
+            .map(get_monitored_item_create_request)
+            .collect();
+
+        let data_tx = data_tx.clone();
+        let subscription_id = match session
             .create_subscription(
-                Duration::from_secs(conf.publishing_interval),
-                conf.lifetime_count,
-                conf.max_keep_alive_count,
-                conf.max_notifications_per_publish,
-                conf.priority,
-                conf.publishing_enabled,
-                DataChangeCallback::new(|dv, item| {
-                    println!("Data change from server:");
-                    Self::print_value(&dv, item);
+                Duration::from_secs(subscription_conf.publishing_interval),
+                subscription_conf.lifetime_count,
+                subscription_conf.max_keep_alive_count,
+                subscription_conf.max_notifications_per_publish,
+                subscription_conf.priority,
+                subscription_conf.publishing_enabled,
+                DataChangeCallback::new(move |data_value, item| {
+                    _ = data_tx.send((item.client_handle(), data_value));
                 }),
             )
             .await
-            .unwrap();
-
-        // TODO
-        let ns = 2;
-        let items_to_create: Vec<MonitoredItemCreateRequest> = conf
-            .monitored_items
-            .iter()
-            .map(|v| MonitoredItemCreateRequest {
-                item_to_monitor: ReadValueId {
-                    node_id: transfer_node_id(&v.variable.node_id),
-                    attribute_id: 13,
-                    index_range: UAString::null(),
-                    data_encoding: QualifiedName::null(),
-                },
-                monitoring_mode: transfer_monitoring_node(&v.monitoring_mode),
-                requested_parameters: MonitoringParameters {
-                    client_handle: v.client_handle,
-                    sampling_interval: v.sampling_interval,
-                    // TODO
-                    filter: ExtensionObject::null(),
-                    queue_size: v.queue_size,
-                    discard_oldest: v.discard_oldest,
-                },
-            })
-            .collect();
+        {
+            Ok(subscription_id) => subscription_id,
+            Err(e) => {
+                warn!("create subscription err code :{:?}", e);
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+                return;
+            }
+        };
+        self.subscription = Some((session.clone(), subscription_id));
 
-        let _ = opcua_client
-            .create_monitored_items(
-                opcua_subscription_id,
-                TimestampsToReturn::Both,
-                items_to_create,
-            )
+        match session
+            .create_monitored_items(subscription_id, TimestampsToReturn::Both, items_to_create)
             .await
-            .unwrap();
+        {
+            Ok(results) => {
+                let errs: Vec<String> = results
+                    .iter()
+                    .filter(|result| !result.status_code.is_good())
+                    .map(|result| result.status_code.to_string())
+                    .collect();
+                if errs.is_empty() {
+                    self.error_manager.set_ok().await;
+                } else {
+                    self.error_manager.set_err(Arc::new(errs.join(","))).await;
+                }
+            }
+            Err(e) => {
+                warn!("create monitored items err code :{:?}", e);
+                self.error_manager.set_err(Arc::new(e.to_string())).await;
+            }
+        }
     }
 
-    fn print_value(data_value: &DataValue, item: &MonitoredItem) {
-        let node_id = &item.item_to_monitor().node_id;
-        if let Some(ref value) = data_value.value {
-            println!("Item \"{}\", Value = {:?}", node_id, value);
-        } else {
-            println!(
-                "Item \"{}\", Value not found, error: {}",
-                node_id,
-                data_value.status.as_ref().unwrap()
-            );
+    async fn delete_subscription(&mut self) {
+        if let Some((session, subscription_id)) = self.subscription.take() {
+            if let Err(e) = session.delete_subscription(subscription_id).await {
+                debug!("delete subscription err code :{:?}", e);
+            }
         }
     }
 
-    async fn start_monitored_item(&mut self) {}
+    async fn send(&mut self, mut message: Message) {
+        if let Some(metadatas) = &self.source_conf.metadatas {
+            message.insert_raw_metadatas(metadatas.clone());
+        }
+
+        let mut message_batch = MessageBatch::default();
+        message_batch.push_message(message);
 
-    pub async fn stop(&mut self) {
-        match &self.group_stop_signal_tx {
-            Some(stop_signal_tx) => stop_signal_tx.send(()).unwrap(),
-            None => {}
+        let mut mb_txs = self.mb_txs.lock().await;
+        match mb_txs.len() {
+            0 => {}
+            1 => {
+                let mb = RuleMessageBatch::Owned(message_batch);
+                if let Err(_) = mb_txs[0].send(mb) {
+                    mb_txs.remove(0);
+                }
+            }
+            _ => {
+                let mb = RuleMessageBatch::Arc(Arc::new(message_batch));
+                mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+            }
         }
     }
+}
 
-    pub async fn update_conf(&mut self, _conf: SourceConf) {
-        // if self.stop_signal_tx.is_some() && restart {
-        //     self.stop_signal_tx
-        //         .as_ref()
-        //         .unwrap()
-        //         .send(())
-        //         .await
-        //         .unwrap();
-
-        //     let (stop_signal_rx, read_tx, device_err) =
-        //         self.join_handle.take().unwrap().await.unwrap();
-        //     // self.event_loop(self.ext_conf.interval, stop_signal_rx, read_tx, device_err)
-        //     //     .await;
-        // }
-    }
+impl Source {
+    pub fn new(
+        id: String,
+        source_conf: SourceConf,
+        opcua_client: Arc<RwLock<Option<Arc<Session>>>>,
+        device_err_rx: broadcast::Receiver<bool>,
+    ) -> Self {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+        let (err1, err2) = BiLock::new(None);
+        let (mb_txs1, mb_txs2) = BiLock::new(vec![]);
 
-    pub fn group_get_read_value_id(variable: &VariableConf) -> ReadValueId {
-        let node_id = transfer_node_id(&variable.node_id);
+        let error_manager =
+            ErrorManager::new(utils::error_manager::ResourceType::DeviceSource, id, err1);
+        let task_loop = TaskLoop {
+            source_conf: source_conf.clone(),
+            opcua_client,
+            stop_signal_rx,
+            device_err_rx,
+            mb_txs: mb_txs1,
+            error_manager,
+            subscription: None,
+        };
+        let join_handle = task_loop.start();
 
-        ReadValueId {
-            node_id,
-            attribute_id: 13,
-            index_range: UAString::null(),
-            data_encoding: QualifiedName::null(),
+        Self {
+            source_conf,
+            stop_signal_tx,
+            err: err2,
+            join_handle: Some(join_handle),
+            mb_txs: mb_txs2,
         }
     }
 
-    async fn group_read_variables_from_remote(
-        opcua_client: &Arc<RwLock<Option<Arc<Session>>>>,
-        need_read_variable_names: &Vec<String>,
-        need_read_variable_ids: &Vec<ReadValueId>,
-        max_age: f64,
-        mb_tx: &broadcast::Sender<MessageBatch>,
-    ) {
-        if mb_tx.receiver_count() == 0 {
-            return;
-        }
-        match opcua_client.read().await.as_ref() {
-            Some(client) => match client
-                .read(
-                    &need_read_variable_ids,
-                    TimestampsToReturn::Neither,
-                    max_age,
-                )
-                .await
-            {
-                Ok(data_values) => {
-                    let mut message = Message::default();
-                    for (index, data_value) in data_values.into_iter().enumerate() {
-                        let name = unsafe { need_read_variable_names.get_unchecked(index) };
-                        let value = match data_value.value {
-                            Some(variant) => match variant {
-                                opcua_protocol::types::Variant::Empty => MessageValue::Null,
-                                opcua_protocol::types::Variant::Boolean(bool) => {
-                                    MessageValue::Boolean(bool)
-                                }
-                                opcua_protocol::types::Variant::SByte(i) => {
-                                    MessageValue::Int64(i as i64)
-                                }
-                                opcua_protocol::types::Variant::Byte(u) => {
-                                    MessageValue::Int64(u as i64)
-                                }
-                                opcua_protocol::types::Variant::Int16(i) => {
-                                    MessageValue::Int64(i as i64)
-                                }
-                                opcua_protocol::types::Variant::UInt16(u) => {
-                                    MessageValue::Int64(u as i64)
-                                }
-                                opcua_protocol::types::Variant::Int32(i) => {
-                                    MessageValue::Int64(i as i64)
-                                }
-                                opcua_protocol::types::Variant::UInt32(u) => {
-                                    MessageValue::Int64(u as i64)
-                                }
-                                opcua_protocol::types::Variant::Int64(i) => {
-                                    MessageValue::Int64(i as i64)
-                                }
-                                opcua_protocol::types::Variant::UInt64(u) => {
-                                    MessageValue::Int64(u as i64)
-                                }
-                                opcua_protocol::types::Variant::Float(f) => {
-                                    MessageValue::Float64(f as f64)
-                                }
-                                opcua_protocol::types::Variant::Double(f) => {
-                                    MessageValue::Float64(f)
-                                }
-                                opcua_protocol::types::Variant::String(s) => {
-                                    MessageValue::String(s.to_string())
-                                }
-                                opcua_protocol::types::Variant::DateTime(_) => todo!(),
-                                opcua_protocol::types::Variant::Guid(guid) => {
-                                    MessageValue::String(guid.to_string())
-                                }
-                                opcua_protocol::types::Variant::StatusCode(s) => {
-                                    MessageValue::String(s.name().to_owned())
-                                }
-                                opcua_protocol::types::Variant::ByteString(_) => todo!(),
-                                opcua_protocol::types::Variant::XmlElement(_) => todo!(),
-                                opcua_protocol::types::Variant::QualifiedName(_) => todo!(),
-                                opcua_protocol::types::Variant::LocalizedText(_) => todo!(),
-                                opcua_protocol::types::Variant::NodeId(_) => todo!(),
-                                opcua_protocol::types::Variant::ExpandedNodeId(_) => todo!(),
-                                opcua_protocol::types::Variant::ExtensionObject(_) => todo!(),
-                                opcua_protocol::types::Variant::Variant(_) => todo!(),
-                                opcua_protocol::types::Variant::DataValue(_) => todo!(),
-                                opcua_protocol::types::Variant::DiagnosticInfo(_) => todo!(),
-                                opcua_protocol::types::Variant::Array(array) => todo!(),
-                            },
-                            None => MessageValue::Null,
-                        };
-
-                        message.add(name.clone(), value);
+    pub fn validate_conf(conf: &SourceConf) -> HaliaResult<()> {
+        match conf.typ {
+            SourceType::Group => match &conf.group {
+                Some(group) => {
+                    if group.interval == 0 {
+                        return Err(HaliaError::Common("读取间隔必须大于0".to_owned()));
+                    }
+                    if group.variables.is_empty() {
+                        return Err(HaliaError::Common("变量不能为空".to_owned()));
                     }
-                    let mut mb = MessageBatch::default();
-                    mb.push_message(message);
-                    mb_tx.send(mb).unwrap();
                 }
-                Err(e) => {
-                    warn!("err code :{:?}", e);
-                    return;
+                None => return Err(HaliaError::Common("类型为组类型，配置为空".to_owned())),
+            },
+            SourceType::Subscription => match &conf.subscription {
+                Some(subscription) => {
+                    if subscription.publishing_interval == 0 {
+                        return Err(HaliaError::Common("发布间隔必须大于0".to_owned()));
+                    }
+                    if subscription.monitored_items.is_empty() {
+                        return Err(HaliaError::Common("监控项不能为空".to_owned()));
+                    }
+                    let mut client_handles = HashSet::new();
+                    for monitored_item in subscription.monitored_items.iter() {
+                        if !client_handles.insert(monitored_item.client_handle) {
+                            return Err(HaliaError::Common(format!(
+                                "client_handle {} 重复",
+                                monitored_item.client_handle
+                            )));
+                        }
+                    }
                 }
+                None => return Err(HaliaError::Common("类型为订阅类型，配置为空".to_owned())),
             },
-            None => return,
+            SourceType::MonitoredItem => {
+                if conf.monitored_item.is_none() {
+                    return Err(HaliaError::Common("类型为监控类型，配置为空".to_owned()));
+                }
+            }
         }
+
+        Ok(())
+    }
+
+    pub async fn update(&mut self, conf: serde_json::Value) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        Self::validate_conf(&conf)?;
+        let mut task_loop = self.stop().await;
+        task_loop.source_conf = conf.clone();
+        self.source_conf = conf;
+        let join_handle = task_loop.start();
+        self.join_handle = Some(join_handle);
+        Ok(())
+    }
+
+    // 返回需要写入的节点及值
+    pub fn get_write_variables(
+        &self,
+        value: serde_json::Value,
+    ) -> HaliaResult<Vec<(NodeId, serde_json::Value)>> {
+        let variables: Vec<&VariableConf> = match self.source_conf.typ {
+            SourceType::Group => self
+                .source_conf
+                .group
+                .as_ref()
+                .unwrap()
+                .variables
+                .iter()
+                .collect(),
+            SourceType::Subscription => self
+                .source_conf
+                .subscription
+                .as_ref()
+                .unwrap()
+                .monitored_items
+                .iter()
+                .map(|item| &item.variable)
+                .collect(),
+            SourceType::MonitoredItem => {
+                let variable = &self.source_conf.monitored_item.as_ref().unwrap().variable;
+                return Ok(vec![(transfer_node_id(&variable.node_id), value)]);
+            }
+        };
+
+        let mut values = match value {
+            serde_json::Value::Object(values) => values,
+            _ => return Err(HaliaError::Common("值必须为对象！".to_owned())),
+        };
+        let mut write_variables = vec![];
+        for variable in variables {
+            if let Some(value) = values.remove(&variable.field) {
+                write_variables.push((transfer_node_id(&variable.node_id), value));
+            }
+        }
+        if write_variables.is_empty() {
+            return Err(HaliaError::Common("没有匹配的字段！".to_owned()));
+        }
+        Ok(write_variables)
+    }
+}
+
+fn get_read_value_id(variable: &VariableConf) -> ReadValueId {
+    ReadValueId {
+        node_id: transfer_node_id(&variable.node_id),
+        attribute_id: 13,
+        index_range: UAString::null(),
+        data_encoding: QualifiedName::null(),
+    }
+}
+
+fn get_monitored_item_create_request(conf: &MonitoredItemconf) -> MonitoredItemCreateRequest {
+    let index_range = match conf.index_range.is_empty() {
+        true => UAString::null(),
+        false => UAString::from(conf.index_range.as_str()),
+    };
+    let data_encoding = match conf.data_encoding.is_empty() {
+        true => QualifiedName::null(),
+        false => QualifiedName::new(0, conf.data_encoding.as_str()),
+    };
+
+    MonitoredItemCreateRequest {
+        item_to_monitor: ReadValueId {
+            node_id: transfer_node_id(&conf.variable.node_id),
+            attribute_id: conf.attribute_id,
+            index_range,
+            data_encoding,
+        },
+        monitoring_mode: transfer_monitoring_node(&conf.monitoring_mode),
+        requested_parameters: MonitoringParameters {
+            client_handle: conf.client_handle,
+            sampling_interval: conf.sampling_interval,
+            filter: ExtensionObject::null(),
+            queue_size: conf.queue_size,
+            discard_oldest: conf.discard_oldest,
+        },
+    }
+}
+
+// 单个监控项使用独立的订阅，发布间隔取不小于采样间隔的整秒数
+fn get_monitored_item_subscription_conf(conf: &MonitoredItemconf) -> Subscriptionconf {
+    let publishing_interval = ((conf.sampling_interval / 1000.0).ceil() as u64).max(1);
+    Subscriptionconf {
+        publishing_interval,
+        lifetime_count: 60,
+        max_keep_alive_count: 20,
+        max_notifications_per_publish: 0,
+        priority: 0,
+        publishing_enabled: true,
+        monitored_items: vec![],
+    }
+}
+
+fn data_value_to_message_value(data_value: DataValue) -> MessageValue {
+    match data_value.value {
+        Some(variant) => variant_to_message_value(variant),
+        None => MessageValue::Null,
+    }
+}
+
+fn variant_to_message_value(variant: Variant) -> MessageValue {
+    match variant {
+        Variant::Empty => MessageValue::Null,
+        Variant::Boolean(b) => MessageValue::Boolean(b),
+        Variant::SByte(i) => MessageValue::Int64(i as i64),
+        Variant::Byte(u) => MessageValue::Int64(u as i64),
+        Variant::Int16(i) => MessageValue::Int64(i as i64),
+        Variant::UInt16(u) => MessageValue::Int64(u as i64),
+        Variant::Int32(i) => MessageValue::Int64(i as i64),
+        Variant::UInt32(u) => MessageValue::Int64(u as i64),
+        Variant::Int64(i) => MessageValue::Int64(i),
+        Variant::UInt64(u) => MessageValue::Int64(u as i64),
+        Variant::Float(f) => MessageValue::Float64(f as f64),
+        Variant::Double(f) => MessageValue::Float64(f),
+        Variant::String(s) => MessageValue::String(s.to_string()),
+        // 毫秒时间戳
+        Variant::DateTime(dt) => MessageValue::Int64(dt.as_chrono().timestamp_millis()),
+        Variant::Guid(guid) => MessageValue::String(guid.to_string()),
+        Variant::StatusCode(s) => MessageValue::String(s.name().to_owned()),
+        Variant::ByteString(bs) => MessageValue::Bytes(bs.value.unwrap_or_default()),
+        Variant::XmlElement(xml) => MessageValue::String(xml.to_string()),
+        Variant::QualifiedName(name) => MessageValue::String(name.name.to_string()),
+        Variant::LocalizedText(text) => MessageValue::String(text.text.to_string()),
+        Variant::NodeId(node_id) => MessageValue::String(node_id.to_string()),
+        Variant::ExpandedNodeId(node_id) => MessageValue::String(node_id.to_string()),
+        Variant::ExtensionObject(_) | Variant::DiagnosticInfo(_) => MessageValue::Null,
+        Variant::Variant(variant) => variant_to_message_value(*variant),
+        Variant::DataValue(data_value) => data_value_to_message_value(*data_value),
+        Variant::Array(array) => MessageValue::Array(
+            array
+                .values
+                .into_iter()
+                .map(variant_to_message_value)
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::MessageValue;
+    use opcua_protocol::types::{Variant, VariantTypeId};
+
+    use super::variant_to_message_value;
+
+    #[test]
+    fn test_variant_to_message_value() {
+        assert_eq!(
+            variant_to_message_value(Variant::UInt16(7)),
+            MessageValue::Int64(7)
+        );
+        assert_eq!(
+            variant_to_message_value(Variant::from((
+                VariantTypeId::Boolean,
+                vec![Variant::Boolean(true), Variant::Boolean(false)]
+            ))),
+            MessageValue::Array(vec![
+                MessageValue::Boolean(true),
+                MessageValue::Boolean(false)
+            ])
+        );
+        assert_eq!(
+            variant_to_message_value(Variant::Variant(Box::new(Variant::Double(1.5)))),
+            MessageValue::Float64(1.5)
+        );
     }
 }
@@ -1,7 +1,17 @@
 use common::error::HaliaResult;
-use types::devices::device_template::opcua::TemplateConf;
+use types::devices::device_template::opcua::{CustomizeConf, TemplateConf};
 
 pub fn validate_device_template_conf(conf: serde_json::Value) -> HaliaResult<()> {
-    let _: TemplateConf = serde_json::from_value(conf)?;
+    let conf: TemplateConf = serde_json::from_value(conf)?;
+    super::security::validate_security(
+        &conf.security_policy,
+        &conf.security_mode,
+        &conf.client_certificate,
+        &conf.trusted_server_certs,
+    )
+}
+
+pub fn validate_device_customize_conf(conf: serde_json::Value) -> HaliaResult<()> {
+    let _: CustomizeConf = serde_json::from_value(conf)?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,506 @@
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use common::error::{HaliaError, HaliaResult};
+use dashmap::DashMap;
+use futures::lock::BiLock;
+use halia_derive::ResourceErr;
+use message::RuleMessageBatch;
+use s7_protocol::{Client, S7Error};
+use sink::Sink;
+use source::Source;
+use tokio::{
+    net::{lookup_host, TcpStream},
+    select,
+    sync::{
+        broadcast,
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+    time,
+};
+use tracing::{trace, warn};
+use types::{
+    devices::device::s7::{Area, CpuType, DataType, S7Conf, SinkConf, SourceConf, Type},
+    Value,
+};
+use utils::ErrorManager;
+
+use crate::{Device, UpdateConfMode};
+
+mod sink;
+mod source;
+
+#[derive(ResourceErr)]
+struct S7 {
+    sources: Arc<DashMap<String, Source>>,
+    sinks: DashMap<String, Sink>,
+
+    stop_signal_tx: watch::Sender<()>,
+    device_err_tx: broadcast::Sender<bool>,
+    err: BiLock<Option<Arc<String>>>,
+
+    write_tx: UnboundedSender<WritePointEvent>,
+    read_tx: UnboundedSender<Arc<String>>,
+
+    join_handle: Option<JoinHandle<TaskLoop>>,
+}
+
+struct TaskLoop {
+    pub device_conf: S7Conf,
+    error_manager: ErrorManager,
+    sources: Arc<DashMap<String, Source>>,
+    stop_signal_rx: watch::Receiver<()>,
+    write_rx: UnboundedReceiver<WritePointEvent>,
+    read_rx: UnboundedReceiver<Arc<String>>,
+    device_err_tx: broadcast::Sender<bool>,
+}
+
+impl TaskLoop {
+    pub fn new(
+        device_id: String,
+        device_conf: S7Conf,
+        err: BiLock<Option<Arc<String>>>,
+        stop_signal_rx: watch::Receiver<()>,
+        sources: Arc<DashMap<String, Source>>,
+        write_rx: UnboundedReceiver<WritePointEvent>,
+        read_rx: UnboundedReceiver<Arc<String>>,
+        device_err_tx: broadcast::Sender<bool>,
+    ) -> Self {
+        let error_manager =
+            ErrorManager::new(utils::error_manager::ResourceType::Device, device_id, err);
+        Self {
+            device_conf,
+            error_manager,
+            sources,
+            stop_signal_rx,
+            write_rx,
+            read_rx,
+            device_err_tx,
+        }
+    }
+
+    pub fn start(mut self) -> JoinHandle<TaskLoop> {
+        tokio::spawn(async move {
+            self.run().await;
+            self
+        })
+    }
+
+    async fn run(&mut self) {
+        loop {
+            match self.connect().await {
+                Ok(mut client) => {
+                    self.error_manager.set_ok().await;
+                    _ = self.device_err_tx.send(false);
+                    loop {
+                        select! {
+                            biased;
+                            _ = self.stop_signal_rx.changed() => {
+                                return;
+                            }
+
+                            wpe = self.write_rx.recv() => {
+                                if let Some(wpe) = wpe {
+                                    if write_value(&mut client, wpe).await.is_err() {
+                                        break
+                                    }
+                                }
+                                if self.device_conf.interval > 0 {
+                                    time::sleep(Duration::from_millis(self.device_conf.interval)).await;
+                                }
+                            }
+
+                            Some(point_id) = self.read_rx.recv() => {
+                                if let Some(mut source) = self.sources.get_mut(point_id.as_ref()) {
+                                    if source.read(&mut client, &self.device_conf).await.is_err() {
+                                        break
+                                    }
+                                }
+                                if self.device_conf.interval > 0 {
+                                    time::sleep(Duration::from_millis(self.device_conf.interval)).await;
+                                }
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    let e = Arc::new(e.to_string());
+                    self.error_manager.set_err(e.clone()).await;
+                    _ = self.device_err_tx.send(true);
+                    let sleep = time::sleep(Duration::from_secs(self.device_conf.reconnect));
+                    tokio::pin!(sleep);
+                    select! {
+                        _ = self.stop_signal_rx.changed() => {
+                            return;
+                        }
+
+                        _ = &mut sleep => {}
+                    }
+                }
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Client<TcpStream>, S7Error> {
+        let socket_addrs = lookup_host(format!(
+            "{}:{}",
+            self.device_conf.host, self.device_conf.port
+        ))
+        .await?
+        .collect::<Vec<SocketAddr>>();
+        if socket_addrs.is_empty() {
+            return Err(io::Error::other("no address found").into());
+        }
+        let stream = TcpStream::connect(socket_addrs[0]).await?;
+        let (local_tsap, remote_tsap) = get_tsap(&self.device_conf);
+        Client::connect(stream, local_tsap, remote_tsap).await
+    }
+}
+
+// 返回本地TSAP与远端TSAP
+fn get_tsap(device_conf: &S7Conf) -> (u16, u16) {
+    match device_conf.cpu_type {
+        // S7-200通过CP243以太网模块通讯
+        CpuType::S200 => (0x4D57, 0x4D57),
+        CpuType::S300 | CpuType::S400 | CpuType::S1200 | CpuType::S1500 => (
+            0x0100,
+            s7_protocol::remote_tsap(0x03, device_conf.rack as u8, device_conf.slot as u8),
+        ),
+    }
+}
+
+fn get_area(area: &Area) -> s7_protocol::Area {
+    match area {
+        Area::Input => s7_protocol::Area::Input,
+        Area::Output => s7_protocol::Area::Output,
+        Area::Merker => s7_protocol::Area::Merker,
+        Area::DataBlock => s7_protocol::Area::DataBlock,
+    }
+}
+
+fn validate_address(area: &Area, db_number: &Option<u16>, data_type: &DataType) -> HaliaResult<()> {
+    if *area == Area::DataBlock && db_number.is_none() {
+        return Err(HaliaError::Common("DB区必须填写DB块号！".to_owned()));
+    }
+    if let Err(e) = data_type.validate() {
+        return Err(HaliaError::Common(e.to_string()));
+    }
+    Ok(())
+}
+
+pub fn validate_conf(device_conf: &serde_json::Value) -> HaliaResult<()> {
+    let device_conf: S7Conf = serde_json::from_value(device_conf.clone())?;
+    if device_conf.rack > 7 {
+        return Err(HaliaError::Common("机架号必须为0-7！".to_owned()));
+    }
+    if device_conf.slot > 31 {
+        return Err(HaliaError::Common("槽号必须为0-31！".to_owned()));
+    }
+    Ok(())
+}
+
+pub(crate) fn new_by_customize(id: String, device_conf: serde_json::Value) -> Box<dyn Device> {
+    let device_conf: S7Conf = serde_json::from_value(device_conf).unwrap();
+    new(id, device_conf)
+}
+
+fn new(device_id: String, device_conf: S7Conf) -> Box<dyn Device> {
+    let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+    let (read_tx, read_rx) = unbounded_channel();
+    let (write_tx, write_rx) = unbounded_channel();
+    let (device_err_tx, _) = broadcast::channel(16);
+
+    let sources = Arc::new(DashMap::new());
+    let (err1, err2) = BiLock::new(None);
+
+    let task_loop = TaskLoop::new(
+        device_id,
+        device_conf,
+        err1,
+        stop_signal_rx,
+        sources.clone(),
+        write_rx,
+        read_rx,
+        device_err_tx.clone(),
+    );
+
+    let join_handle = task_loop.start();
+
+    Box::new(S7 {
+        err: err2,
+        sources,
+        sinks: DashMap::new(),
+        stop_signal_tx,
+        device_err_tx,
+        write_tx,
+        read_tx,
+        join_handle: Some(join_handle),
+    })
+}
+
+pub fn validate_source_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SourceConf = serde_json::from_value(conf.clone())?;
+    Source::validate_conf(&conf)
+}
+
+pub fn validate_sink_conf(conf: &serde_json::Value) -> HaliaResult<()> {
+    let conf: SinkConf = serde_json::from_value(conf.clone())?;
+    Sink::validate_conf(&conf)
+}
+
+impl S7 {
+    fn create_source(&mut self, source_id: String, conf: SourceConf) {
+        let source = Source::new(
+            source_id.clone(),
+            conf,
+            self.read_tx.clone(),
+            self.device_err_tx.subscribe(),
+        );
+        self.sources.insert(source_id, source);
+    }
+
+    fn create_sink(&mut self, sink_id: String, conf: SinkConf) -> HaliaResult<()> {
+        let sink = Sink::new(
+            &sink_id,
+            conf,
+            self.write_tx.clone(),
+            self.device_err_tx.subscribe(),
+        )?;
+        self.sinks.insert(sink_id, sink);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct WritePointEvent {
+    pub area: Area,
+    pub db_number: u16,
+    pub address: u32,
+    pub data_type: DataType,
+    pub data: Vec<u8>,
+}
+
+impl WritePointEvent {
+    pub fn new(
+        area: Area,
+        db_number: Option<u16>,
+        address: u32,
+        data_type: DataType,
+        value: serde_json::Value,
+    ) -> HaliaResult<Self> {
+        if area == Area::Input {
+            return Err(HaliaError::Common("区域不支持写入操作!".to_owned()));
+        }
+
+        let data = match data_type.encode(value) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(HaliaError::Common(format!("数据解析错误：{:?}", e)));
+            }
+        };
+
+        Ok(WritePointEvent {
+            area,
+            db_number: db_number.unwrap_or(0),
+            address,
+            data_type,
+            data,
+        })
+    }
+}
+
+// 仅网络错误及协议错误时返回错误，由调用方重连
+async fn write_value(client: &mut Client<TcpStream>, wpe: WritePointEvent) -> HaliaResult<()> {
+    let area = get_area(&wpe.area);
+    let resp = match wpe.data_type.typ {
+        Type::Bool => {
+            client
+                .write_bit(
+                    area,
+                    wpe.db_number,
+                    wpe.address,
+                    wpe.data_type.bit.unwrap_or(0),
+                    wpe.data[0] == 1,
+                )
+                .await
+        }
+        _ => {
+            client
+                .write_area(area, wpe.db_number, wpe.address, &wpe.data)
+                .await
+        }
+    };
+
+    match resp {
+        Ok(_) => Ok(()),
+        Err(e) => match e {
+            S7Error::Transport(e) => Err(HaliaError::Io(e)),
+            S7Error::Protocol(e) => {
+                warn!("s7 protocol err :{:?}", e);
+                Err(HaliaError::Common(e.to_string()))
+            }
+            S7Error::Item(e) => {
+                warn!("s7 item err :{:?}", e);
+                Ok(())
+            }
+        },
+    }
+}
+
+#[async_trait]
+impl Device for S7 {
+    async fn read_device_err(&self) -> Option<Arc<String>> {
+        self.read_err().await
+    }
+
+    async fn read_source_err(&self, source_id: &String) -> Option<String> {
+        match self.sources.get(source_id) {
+            Some(source) => source.err_info.clone(),
+            None => None,
+        }
+    }
+
+    async fn read_sink_err(&self, _sink_id: &String) -> Option<String> {
+        None
+    }
+
+    async fn update_conf(
+        &mut self,
+        mode: UpdateConfMode,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let device_conf: S7Conf = match mode {
+            UpdateConfMode::CustomizeMode => serde_json::from_value(conf)?,
+            UpdateConfMode::TemplateModeCustomize | UpdateConfMode::TemplateModeTemplate => {
+                return Err(HaliaError::NotSupportResource)
+            }
+        };
+        self.stop_signal_tx.send(()).unwrap();
+        let mut task_loop = self.join_handle.take().unwrap().await.unwrap();
+        task_loop.device_conf = device_conf;
+        let join_handle = task_loop.start();
+        self.join_handle = Some(join_handle);
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        for mut source in self.sources.iter_mut() {
+            source.stop().await;
+        }
+
+        for mut sink in self.sinks.iter_mut() {
+            sink.stop().await;
+        }
+
+        self.stop_signal_tx.send(()).unwrap();
+    }
+
+    async fn create_source(
+        &mut self,
+        source_id: String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        Source::validate_conf(&conf)?;
+        self.create_source(source_id, conf);
+        Ok(())
+    }
+
+    async fn update_source(
+        &mut self,
+        source_id: &String,
+        conf: serde_json::Value,
+    ) -> HaliaResult<()> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => {
+                source.update(conf).await?;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn write_source_value(&mut self, source_id: String, req: Value) -> HaliaResult<()> {
+        if let Some(err) = self.err.lock().await.as_ref() {
+            return Err(HaliaError::Common(err.to_string()));
+        }
+
+        match self.sources.get(&source_id) {
+            Some(source) => {
+                let wpe = WritePointEvent::new(
+                    source.source_conf.area,
+                    source.source_conf.db_number,
+                    source.source_conf.address,
+                    source.source_conf.data_type,
+                    req.value,
+                )?;
+                match self.write_tx.send(wpe) {
+                    Ok(_) => trace!("send write value success"),
+                    Err(e) => warn!("send write value err:{:?}", e),
+                }
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id)),
+        }
+    }
+
+    async fn delete_source(&mut self, source_id: &String) -> HaliaResult<()> {
+        match self.sources.remove(source_id) {
+            Some((_, mut source)) => {
+                source.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn create_sink(&mut self, sink_id: String, conf: serde_json::Value) -> HaliaResult<()> {
+        let conf: SinkConf = serde_json::from_value(conf)?;
+        Sink::validate_conf(&conf)?;
+        self.create_sink(sink_id, conf)
+    }
+
+    async fn update_sink(&mut self, sink_id: &String, conf: serde_json::Value) -> HaliaResult<()> {
+        match self.sinks.get_mut(sink_id) {
+            Some(mut sink) => {
+                sink.update(sink_id, conf).await?;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+
+    async fn delete_sink(&mut self, sink_id: &String) -> HaliaResult<()> {
+        match self.sinks.remove(sink_id) {
+            Some((_, mut sink)) => {
+                sink.stop().await;
+                Ok(())
+            }
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+
+    async fn get_source_rxs(
+        &self,
+        source_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedReceiver<RuleMessageBatch>>> {
+        match self.sources.get_mut(source_id) {
+            Some(mut source) => Ok(source.get_rxs(cnt)),
+            None => Err(HaliaError::NotFound(source_id.to_owned())),
+        }
+    }
+
+    async fn get_sink_txs(
+        &self,
+        sink_id: &String,
+        cnt: usize,
+    ) -> HaliaResult<Vec<UnboundedSender<RuleMessageBatch>>> {
+        match self.sinks.get(sink_id) {
+            Some(sink) => Ok(sink.get_txs(cnt)),
+            None => Err(HaliaError::NotFound(sink_id.to_owned())),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::{io, sync::Arc, time::Duration};
+
+use common::error::{HaliaError, HaliaResult};
+use message::{Message, MessageBatch, RuleMessageBatch};
+use s7_protocol::{Client, S7Error};
+use tokio::{
+    net::TcpStream,
+    select,
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+    time,
+};
+use tracing::warn;
+use types::devices::device::s7::{S7Conf, SourceConf, Type};
+
+use super::{get_area, validate_address};
+
+pub struct Source {
+    pub source_conf: SourceConf,
+
+    stop_signal_tx: watch::Sender<()>,
+    join_handle: Option<JoinHandle<TaskLoop>>,
+    pub err_info: Option<String>,
+
+    pub mb_txs: Vec<mpsc::UnboundedSender<RuleMessageBatch>>,
+}
+
+pub struct TaskLoop {
+    id: Arc<String>,
+    stop_signal_rx: watch::Receiver<()>,
+    read_tx: mpsc::UnboundedSender<Arc<String>>,
+    device_err_rx: broadcast::Receiver<bool>,
+}
+
+impl TaskLoop {
+    fn new(
+        id: String,
+        stop_signal_rx: watch::Receiver<()>,
+        read_tx: UnboundedSender<Arc<String>>,
+        device_err_rx: broadcast::Receiver<bool>,
+    ) -> Self {
+        Self {
+            id: Arc::new(id),
+            stop_signal_rx,
+            read_tx,
+            device_err_rx,
+        }
+    }
+
+    fn start(mut self, source_conf: &SourceConf) -> JoinHandle<Self> {
+        let mut interval = time::interval(Duration::from_millis(source_conf.interval));
+        let mut device_err = false;
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = self.stop_signal_rx.changed() => {
+                        return self;
+                    }
+
+                    _ = interval.tick() => {
+                        if !device_err {
+                            _ = self.read_tx.send(self.id.clone());
+                        }
+                    }
+
+                    Ok(err) = self.device_err_rx.recv() => {
+                        device_err = err;
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Source {
+    pub fn new(
+        id: String,
+        source_conf: SourceConf,
+        read_tx: UnboundedSender<Arc<String>>,
+        device_err_rx: broadcast::Receiver<bool>,
+    ) -> Self {
+        let (stop_signal_tx, stop_signal_rx) = watch::channel(());
+
+        let task_loop = TaskLoop::new(id, stop_signal_rx, read_tx, device_err_rx);
+
+        let join_handle = task_loop.start(&source_conf);
+
+        Self {
+            source_conf,
+            stop_signal_tx,
+            join_handle: Some(join_handle),
+            err_info: None,
+            mb_txs: vec![],
+        }
+    }
+
+    pub fn validate_conf(conf: &SourceConf) -> HaliaResult<()> {
+        if conf.interval == 0 {
+            return Err(HaliaError::Common("点位频率必须大于0".to_owned()));
+        }
+
+        validate_address(&conf.area, &conf.db_number, &conf.data_type)
+    }
+
+    pub async fn stop(&mut self) -> TaskLoop {
+        self.stop_signal_tx.send(()).unwrap();
+        self.join_handle.take().unwrap().await.unwrap()
+    }
+
+    pub async fn update(&mut self, conf: serde_json::Value) -> HaliaResult<()> {
+        let conf: SourceConf = serde_json::from_value(conf)?;
+        Self::validate_conf(&conf)?;
+        let task_loop = self.stop().await;
+        self.source_conf = conf;
+        let join_handle = task_loop.start(&self.source_conf);
+        self.join_handle = Some(join_handle);
+
+        Ok(())
+    }
+
+    // 仅网络错误及协议错误时返回错误，由设备重连
+    pub async fn read(
+        &mut self,
+        client: &mut Client<TcpStream>,
+        device_conf: &S7Conf,
+    ) -> io::Result<()> {
+        let area = get_area(&self.source_conf.area);
+        let db_number = self.source_conf.db_number.unwrap_or(0);
+        let res = match self.source_conf.data_type.typ {
+            Type::Bool => client
+                .read_bit(
+                    area,
+                    db_number,
+                    self.source_conf.address,
+                    self.source_conf.data_type.bit.unwrap_or(0),
+                )
+                .await
+                .map(|value| vec![value as u8]),
+            _ => {
+                client
+                    .read_area(
+                        area,
+                        db_number,
+                        self.source_conf.address,
+                        self.source_conf.data_type.get_size(),
+                    )
+                    .await
+            }
+        };
+
+        match res {
+            Ok(data) => {
+                self.err_info = None;
+                self.send(&data, device_conf);
+                Ok(())
+            }
+            Err(e) => match e {
+                S7Error::Transport(e) => Err(e),
+                S7Error::Protocol(e) => {
+                    warn!("{}", e);
+                    Err(io::Error::new(io::ErrorKind::InvalidData, e))
+                }
+                S7Error::Item(e) => {
+                    warn!("{}", e);
+                    self.err_info = Some(e.to_string());
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn send(&mut self, data: &[u8], device_conf: &S7Conf) {
+        let value = self.source_conf.data_type.decode(data);
+        let mut message = Message::default();
+        message.add(self.source_conf.field.clone(), value);
+
+        if let Some(metadatas) = &device_conf.metadatas {
+            message.insert_raw_metadatas(metadatas.clone());
+        }
+        if let Some(metadatas) = &self.source_conf.metadatas {
+            message.insert_raw_metadatas(metadatas.clone());
+        }
+
+        let mut message_batch = MessageBatch::default();
+        message_batch.push_message(message);
+
+        match self.mb_txs.len() {
+            0 => {}
+            1 => {
+                let mb = RuleMessageBatch::Owned(message_batch);
+                if self.mb_txs[0].send(mb).is_err() {
+                    self.mb_txs.remove(0);
+                }
+            }
+            _ => {
+                let mb = RuleMessageBatch::Arc(Arc::new(message_batch));
+                self.mb_txs.retain(|tx| tx.send(mb.clone()).is_ok());
+            }
+        }
+    }
+
+    pub fn get_rxs(&mut self, cnt: usize) -> Vec<mpsc::UnboundedReceiver<RuleMessageBatch>> {
+        let mut rxs = vec![];
+        for _ in 0..cnt {
+            let (tx, rx) = mpsc::unbounded_channel();
+            self.mb_txs.push(tx);
+            rxs.push(rx);
+        }
+        rxs
+    }
+}
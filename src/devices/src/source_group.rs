@@ -74,7 +74,9 @@ pub async fn create_source(source_group_id: String, req: CreateUpdateSourceReq)
         types::devices::DeviceType::Modbus => {
             modbus::template::validate_source_template_conf(req.conf.clone())?;
         }
-        types::devices::DeviceType::Opcua | types::devices::DeviceType::Coap => {
+        types::devices::DeviceType::Opcua
+        | types::devices::DeviceType::Coap
+        | types::devices::DeviceType::S7 => {
             return Err(HaliaError::NotSupportResource);
         }
     }
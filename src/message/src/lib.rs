@@ -7,6 +7,7 @@ use std::{
 };
 
 use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 
 mod avro;
 mod csv;
@@ -15,7 +16,7 @@ mod ptorobuf;
 mod toml;
 mod yaml;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MessageBatch {
     ts: u64,
     name: String,
@@ -123,7 +124,7 @@ impl Default for MessageBatch {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Message {
     metadatas: HashMap<String, MessageValue>,
     value: MessageValue,
@@ -299,7 +300,7 @@ impl Default for Message {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum MessageValue {
     Null,
     Boolean(bool),
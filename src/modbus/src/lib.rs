@@ -5,6 +5,7 @@ use thiserror::Error;
 
 pub(crate) mod pdu;
 pub mod rtu;
+pub mod server;
 pub mod tcp;
 
 // 协议使用大端编码方式
@@ -159,4 +160,4 @@ pub(crate) fn encode_u16(data: u16) -> (u8, u8) {
 
 pub(crate) fn decode_u16(data0: u8, data1: u8) -> u16 {
     (data0 as u16) << 8 | (data1 as u16)
-}
\ No newline at end of file
+}
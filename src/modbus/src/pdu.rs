@@ -1,4 +1,4 @@
-use super::{encode_u16, Exception, ModbusError, ProtocolError};
+use super::{decode_u16, encode_u16, Exception, ModbusError, ProtocolError};
 
 // function code: 1 Byte 0x01
 // starting address: 2 Bytes 0x0000 to 0xFFFF
@@ -168,7 +168,7 @@ pub fn decode_read_input_registers(buffer: &mut [u8]) -> Result<&mut [u8], Modbu
         return Err(ProtocolError::FunctionCodeMismatch.into());
     }
 
-    let len = buffer[2] as usize;
+    let len = buffer[1] as usize;
     Ok(&mut buffer[2..2 + len])
 }
 
@@ -316,3 +316,179 @@ pub fn decode_mask_write_register(buffer: &[u8]) -> Result<(), ModbusError> {
 
     Ok(())
 }
+
+// 从站模式下主站发来的请求
+pub enum Request<'a> {
+    ReadCoils {
+        addr: u16,
+        quantity: u16,
+    },
+    ReadDiscreteInputs {
+        addr: u16,
+        quantity: u16,
+    },
+    ReadHoldingRegisters {
+        addr: u16,
+        quantity: u16,
+    },
+    ReadInputRegisters {
+        addr: u16,
+        quantity: u16,
+    },
+    WriteSingleCoil {
+        addr: u16,
+        value: bool,
+    },
+    WriteSingleRegister {
+        addr: u16,
+        value: u16,
+    },
+    WriteMultipleCoils {
+        addr: u16,
+        quantity: u16,
+        data: &'a [u8],
+    },
+    WriteMultipleRegisters {
+        addr: u16,
+        quantity: u16,
+        data: &'a [u8],
+    },
+    MaskWriteRegister {
+        addr: u16,
+        and_mask: u16,
+        or_mask: u16,
+    },
+}
+
+// 根据已读取的功能码及前置字节计算请求PDU的总长度，数据不足时返回None
+// 0x0F和0x10需要读取到第6个字节(byte count)后才能确定长度
+pub fn request_len(buffer: &[u8]) -> Option<Result<usize, Exception>> {
+    match buffer.first()? {
+        0x01..=0x06 => Some(Ok(5)),
+        0x0F | 0x10 => {
+            let byte_count = *buffer.get(5)? as usize;
+            Some(Ok(6 + byte_count))
+        }
+        0x16 => Some(Ok(7)),
+        _ => Some(Err(Exception::IllegalFunction)),
+    }
+}
+
+pub fn decode_request(buffer: &[u8]) -> Result<Request<'_>, Exception> {
+    let len = match request_len(buffer) {
+        Some(len) => len?,
+        None => return Err(Exception::IllegalDataValue),
+    };
+    if buffer.len() < len {
+        return Err(Exception::IllegalDataValue);
+    }
+
+    let addr = decode_u16(buffer[1], buffer[2]);
+    let value = decode_u16(buffer[3], buffer[4]);
+    let req = match buffer[0] {
+        0x01 => {
+            if value == 0 || value > 0x07D0 {
+                return Err(Exception::IllegalDataValue);
+            }
+            Request::ReadCoils {
+                addr,
+                quantity: value,
+            }
+        }
+        0x02 => {
+            if value == 0 || value > 0x07D0 {
+                return Err(Exception::IllegalDataValue);
+            }
+            Request::ReadDiscreteInputs {
+                addr,
+                quantity: value,
+            }
+        }
+        0x03 => {
+            if value == 0 || value > 0x7D {
+                return Err(Exception::IllegalDataValue);
+            }
+            Request::ReadHoldingRegisters {
+                addr,
+                quantity: value,
+            }
+        }
+        0x04 => {
+            if value == 0 || value > 0x7D {
+                return Err(Exception::IllegalDataValue);
+            }
+            Request::ReadInputRegisters {
+                addr,
+                quantity: value,
+            }
+        }
+        0x05 => match value {
+            0xFF00 => Request::WriteSingleCoil { addr, value: true },
+            0x0000 => Request::WriteSingleCoil { addr, value: false },
+            _ => return Err(Exception::IllegalDataValue),
+        },
+        0x06 => Request::WriteSingleRegister { addr, value },
+        0x0F => {
+            let byte_count = buffer[5] as usize;
+            if value == 0 || value > 0x07B0 || byte_count != (value as usize).div_ceil(8) {
+                return Err(Exception::IllegalDataValue);
+            }
+            Request::WriteMultipleCoils {
+                addr,
+                quantity: value,
+                data: &buffer[6..6 + byte_count],
+            }
+        }
+        0x10 => {
+            let byte_count = buffer[5] as usize;
+            if value == 0 || value > 0x7B || byte_count != value as usize * 2 {
+                return Err(Exception::IllegalDataValue);
+            }
+            Request::WriteMultipleRegisters {
+                addr,
+                quantity: value,
+                data: &buffer[6..6 + byte_count],
+            }
+        }
+        0x16 => Request::MaskWriteRegister {
+            addr,
+            and_mask: value,
+            or_mask: decode_u16(buffer[5], buffer[6]),
+        },
+        _ => return Err(Exception::IllegalFunction),
+    };
+
+    Ok(req)
+}
+
+// function code: 1 Byte Function code + 0x80
+// exception code: 1 Byte
+pub fn encode_exception(buffer: &mut [u8], function_code: u8, exception: Exception) -> u16 {
+    buffer[0] = function_code | 0x80;
+    buffer[1] = exception as u8;
+    2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_read_holding_registers, decode_read_input_registers};
+    use crate::{Exception, ModbusError};
+
+    // 字节数位于功能码之后，与0x03的响应格式一致
+    #[test]
+    fn test_decode_read_input_registers() {
+        let mut buffer = [0x04, 0x04, 0x00, 0x0A, 0x01, 0x02, 0xFF];
+        let data = decode_read_input_registers(&mut buffer).unwrap();
+        assert_eq!(data, &[0x00, 0x0A, 0x01, 0x02]);
+
+        let mut buffer = [0x03, 0x02, 0x12, 0x34, 0xFF];
+        let data = decode_read_holding_registers(&mut buffer).unwrap();
+        assert_eq!(data, &[0x12, 0x34]);
+
+        let mut buffer = [0x84, 0x02];
+        assert!(matches!(
+            decode_read_input_registers(&mut buffer),
+            Err(ModbusError::Exception(Exception::IllegalDataAddress))
+        ));
+    }
+}
@@ -1,7 +1,11 @@
-use std::{fmt::Debug, io};
+use std::{fmt::Debug, io, time::Duration};
 
 use async_trait::async_trait;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::UnboundedSender,
+    time,
+};
 
 use super::{
     pdu::{
@@ -11,10 +15,17 @@ use super::{
         encode_mask_write_register, encode_read_coils, encode_read_discrete_inputs,
         encode_read_holding_registers, encode_read_input_registers,
         encode_write_multiple_registers, encode_write_single_coil, encode_write_single_register,
+        request_len,
     },
+    server::{SharedStore, WriteRecord},
     Context, ModbusError, ProtocolError,
 };
 
+// 帧内字节间的最大静默时间，大于1200波特率下3.5个字符的时间
+const FRAME_SILENCE: Duration = Duration::from_millis(50);
+
+const BROADCAST_SLAVE: u8 = 0;
+
 // PDU最大为256 Bytes，包含1位的服务器地址和2位crc校验码
 struct RtuContext<T> {
     slave: u8,
@@ -81,16 +92,127 @@ where
     }
 
     fn crc16(&self) -> (u8, u8) {
-        let mut crc_hi = 0xFF;
-        let mut crc_lo = 0xFF;
-        for n in 0..(self.buffer_len as usize) {
-            let index = (crc_hi ^ self.buffer[n]) as usize;
-            crc_hi = crc_lo ^ TABLE_CRC_HI[index];
-            crc_lo = TABLE_CRC_LO[index];
+        crc16(&self.buffer[..self.buffer_len])
+    }
+}
+
+// 从站模式，处理主站的请求直至连接断开
+// 帧长度错误或crc校验失败时丢弃数据直至总线静默，随后的第一个字节视为新帧的开始
+pub async fn serve<T>(
+    mut transport: T,
+    store: SharedStore,
+    record_tx: UnboundedSender<WriteRecord>,
+) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let mut req = [0; 256];
+    let mut resp = [0; 256];
+    loop {
+        // slave
+        transport.read_exact(&mut req[..1]).await?;
+        // function code，帧内静默表示上一帧已中断，直接开始下一帧
+        if !read_frame_part(&mut transport, &mut req[1..2]).await? {
+            continue;
+        }
+        let mut read = 2;
+        // 0x0F和0x10需读取到byte count才能确定长度
+        if req[1] == 0x0F || req[1] == 0x10 {
+            if !read_frame_part(&mut transport, &mut req[2..7]).await? {
+                continue;
+            }
+            read = 7;
+        }
+
+        let pdu_len = match request_len(&req[1..read]) {
+            Some(Ok(pdu_len)) => pdu_len,
+            // 无法确定帧长度
+            _ => {
+                resync(&mut transport).await?;
+                continue;
+            }
+        };
+        // slave + pdu + crc
+        let frame_len = 1 + pdu_len + 2;
+        if frame_len > req.len() {
+            resync(&mut transport).await?;
+            continue;
+        }
+        if !read_frame_part(&mut transport, &mut req[read..frame_len]).await? {
+            continue;
         }
 
-        (crc_hi, crc_lo)
+        if crc16(&req[..1 + pdu_len]) != (req[1 + pdu_len], req[2 + pdu_len]) {
+            resync(&mut transport).await?;
+            continue;
+        }
+
+        let slave = req[0];
+        // 广播地址不响应
+        if slave == BROADCAST_SLAVE {
+            continue;
+        }
+        // 未配置的从站返回0x0B异常
+        let (len, record) =
+            store
+                .lock()
+                .unwrap()
+                .handle(slave, &req[1..1 + pdu_len], &mut resp[1..]);
+
+        resp[0] = slave;
+        let len = 1 + len as usize;
+        (resp[len], resp[len + 1]) = crc16(&resp[..len]);
+        transport.write_all(&resp[..len + 2]).await?;
+
+        if let Some(record) = record {
+            _ = record_tx.send(record);
+        }
+    }
+}
+
+// 读满buf，字节间静默超过FRAME_SILENCE时返回false
+async fn read_frame_part<T>(transport: &mut T, buf: &mut [u8]) -> io::Result<bool>
+where
+    T: AsyncRead + Unpin,
+{
+    let mut n = 0;
+    while n < buf.len() {
+        match time::timeout(FRAME_SILENCE, transport.read(&mut buf[n..])).await {
+            Ok(Ok(0)) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(Ok(len)) => n += len,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Ok(false),
+        }
     }
+    Ok(true)
+}
+
+// 丢弃数据直至总线静默
+async fn resync<T>(transport: &mut T) -> io::Result<()>
+where
+    T: AsyncRead + Unpin,
+{
+    let mut buf = [0; 256];
+    loop {
+        match time::timeout(FRAME_SILENCE, transport.read(&mut buf)).await {
+            Ok(Ok(0)) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Ok(()),
+        }
+    }
+}
+
+fn crc16(data: &[u8]) -> (u8, u8) {
+    let mut crc_hi = 0xFF;
+    let mut crc_lo = 0xFF;
+    for byte in data {
+        let index = (crc_hi ^ byte) as usize;
+        crc_hi = crc_lo ^ TABLE_CRC_HI[index];
+        crc_lo = TABLE_CRC_LO[index];
+    }
+
+    (crc_hi, crc_lo)
 }
 
 #[async_trait]
@@ -240,3 +362,62 @@ const TABLE_CRC_LO: [u8; 256] = [
     0x88, 0x48, 0x49, 0x89, 0x4B, 0x8B, 0x8A, 0x4A, 0x4E, 0x8E, 0x8F, 0x4F, 0x8D, 0x4D, 0x4C, 0x8C,
     0x44, 0x84, 0x85, 0x45, 0x87, 0x47, 0x46, 0x86, 0x82, 0x42, 0x43, 0x83, 0x41, 0x81, 0x80, 0x40,
 ];
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::{duplex, AsyncReadExt, AsyncWriteExt},
+        sync::mpsc::unbounded_channel,
+        time,
+    };
+
+    use super::{crc16, serve};
+    use crate::server::Store;
+
+    fn frame(pdu: &[u8], slave: u8) -> Vec<u8> {
+        let mut frame = vec![slave];
+        frame.extend_from_slice(pdu);
+        let (hi, lo) = crc16(&frame);
+        frame.push(hi);
+        frame.push(lo);
+        frame
+    }
+
+    #[tokio::test]
+    async fn test_serve_resync_and_unknown_slave() {
+        let store = Store::new_shared();
+        store.lock().unwrap().add_slave(1);
+        let (record_tx, _record_rx) = unbounded_channel();
+        let (mut master, slave) = duplex(1024);
+        tokio::spawn(serve(slave, store, record_tx));
+
+        // crc错误的帧，其后紧跟的数据在总线静默前均被丢弃
+        let mut bad = frame(&[0x03, 0x00, 0x00, 0x00, 0x01], 1);
+        bad[6] ^= 0xFF;
+        master.write_all(&bad).await.unwrap();
+        master.write_all(&[0x01, 0x03]).await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+
+        master
+            .write_all(&frame(&[0x03, 0x00, 0x00, 0x00, 0x01], 1))
+            .await
+            .unwrap();
+        let mut resp = [0; 7];
+        master.read_exact(&mut resp).await.unwrap();
+        assert_eq!(resp.to_vec(), frame(&[0x03, 0x02, 0x00, 0x00], 1));
+
+        // 中断的帧在静默后被丢弃
+        master.write_all(&[0x01, 0x03, 0x00]).await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+
+        master
+            .write_all(&frame(&[0x03, 0x00, 0x00, 0x00, 0x01], 2))
+            .await
+            .unwrap();
+        let mut resp = [0; 5];
+        master.read_exact(&mut resp).await.unwrap();
+        assert_eq!(resp.to_vec(), frame(&[0x83, 0x0B], 2));
+    }
+}
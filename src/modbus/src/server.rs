@@ -0,0 +1,460 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use super::{
+    encode_u16,
+    pdu::{
+        decode_mask_write_register, decode_read_coils, decode_read_discrete_inputs,
+        decode_read_holding_registers, decode_read_input_registers, decode_request,
+        decode_write_multiple_registers, decode_write_single_coil, decode_write_single_register,
+        encode_exception, encode_mask_write_register, encode_read_coils,
+        encode_read_discrete_inputs, encode_read_holding_registers, encode_read_input_registers,
+        encode_write_multiple_registers, encode_write_single_coil, encode_write_single_register,
+        Request,
+    },
+    Context, Exception, ModbusError,
+};
+
+// 每个区域可以有65536个数据
+const AREA_SIZE: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    Coils,
+    DiscreteInputs,
+    InputRegisters,
+    HoldingRegisters,
+}
+
+// 主站写入后的通知，用于触发对应的源
+#[derive(Debug)]
+pub struct WriteRecord {
+    pub slave: u8,
+    pub area: Area,
+    pub address: u16,
+    pub quantity: u16,
+}
+
+struct Memory {
+    coils: Vec<bool>,
+    discrete_inputs: Vec<bool>,
+    input_registers: Vec<u16>,
+    holding_registers: Vec<u16>,
+}
+
+impl Memory {
+    fn new() -> Self {
+        Self {
+            coils: vec![false; AREA_SIZE],
+            discrete_inputs: vec![false; AREA_SIZE],
+            input_registers: vec![0; AREA_SIZE],
+            holding_registers: vec![0; AREA_SIZE],
+        }
+    }
+}
+
+// 从站模式下网关持有的数据，按从站地址划分
+#[derive(Default)]
+pub struct Store {
+    slaves: HashMap<u8, Memory>,
+}
+
+pub type SharedStore = Arc<Mutex<Store>>;
+
+impl Store {
+    pub fn new_shared() -> SharedStore {
+        Arc::new(Mutex::new(Store::default()))
+    }
+
+    pub fn add_slave(&mut self, slave: u8) {
+        self.slaves.entry(slave).or_insert_with(Memory::new);
+    }
+
+    // 保持与源、动作引用的从站一致，不再引用的从站连同数据一并移除
+    pub fn set_slaves(&mut self, slaves: &HashSet<u8>) {
+        self.slaves.retain(|slave, _| slaves.contains(slave));
+        for slave in slaves {
+            self.add_slave(*slave);
+        }
+    }
+
+    pub fn contains_slave(&self, slave: u8) -> bool {
+        self.slaves.contains_key(&slave)
+    }
+
+    // 处理一帧请求PDU，将响应PDU写入resp，返回响应长度以及主站的写入记录
+    pub fn handle(&mut self, slave: u8, req: &[u8], resp: &mut [u8]) -> (u16, Option<WriteRecord>) {
+        let function_code = match req.first() {
+            Some(function_code) => *function_code,
+            None => return (0, None),
+        };
+
+        let res = match decode_request(req) {
+            Ok(request) => self.process(slave, request, resp),
+            Err(e) => Err(e),
+        };
+
+        match res {
+            Ok((len, record)) => (len, record),
+            Err(e) => (encode_exception(resp, function_code, e), None),
+        }
+    }
+
+    fn process(
+        &mut self,
+        slave: u8,
+        request: Request,
+        resp: &mut [u8],
+    ) -> Result<(u16, Option<WriteRecord>), Exception> {
+        let memory = match self.slaves.get_mut(&slave) {
+            Some(memory) => memory,
+            None => return Err(Exception::GatewayTargetDevice),
+        };
+        match request {
+            Request::ReadCoils { addr, quantity } => {
+                let bits = get_range(&memory.coils, addr, quantity)?;
+                resp[0] = 0x01;
+                Ok((encode_bits(resp, bits), None))
+            }
+            Request::ReadDiscreteInputs { addr, quantity } => {
+                let bits = get_range(&memory.discrete_inputs, addr, quantity)?;
+                resp[0] = 0x02;
+                Ok((encode_bits(resp, bits), None))
+            }
+            Request::ReadHoldingRegisters { addr, quantity } => {
+                let registers = get_range(&memory.holding_registers, addr, quantity)?;
+                resp[0] = 0x03;
+                Ok((encode_registers(resp, registers), None))
+            }
+            Request::ReadInputRegisters { addr, quantity } => {
+                let registers = get_range(&memory.input_registers, addr, quantity)?;
+                resp[0] = 0x04;
+                Ok((encode_registers(resp, registers), None))
+            }
+            Request::WriteSingleCoil { addr, value } => {
+                memory.coils[addr as usize] = value;
+                resp[0] = 0x05;
+                (resp[1], resp[2]) = encode_u16(addr);
+                (resp[3], resp[4]) = match value {
+                    true => (0xFF, 0x00),
+                    false => (0x00, 0x00),
+                };
+                Ok((5, Some(WriteRecord::new(slave, Area::Coils, addr, 1))))
+            }
+            Request::WriteSingleRegister { addr, value } => {
+                memory.holding_registers[addr as usize] = value;
+                resp[0] = 0x06;
+                (resp[1], resp[2]) = encode_u16(addr);
+                (resp[3], resp[4]) = encode_u16(value);
+                Ok((
+                    5,
+                    Some(WriteRecord::new(slave, Area::HoldingRegisters, addr, 1)),
+                ))
+            }
+            Request::WriteMultipleCoils {
+                addr,
+                quantity,
+                data,
+            } => {
+                let coils = get_range_mut(&mut memory.coils, addr, quantity)?;
+                for (i, coil) in coils.iter_mut().enumerate() {
+                    *coil = (data[i / 8] >> (i % 8)) & 1 == 1;
+                }
+                resp[0] = 0x0F;
+                (resp[1], resp[2]) = encode_u16(addr);
+                (resp[3], resp[4]) = encode_u16(quantity);
+                Ok((
+                    5,
+                    Some(WriteRecord::new(slave, Area::Coils, addr, quantity)),
+                ))
+            }
+            Request::WriteMultipleRegisters {
+                addr,
+                quantity,
+                data,
+            } => {
+                let registers = get_range_mut(&mut memory.holding_registers, addr, quantity)?;
+                for (i, register) in registers.iter_mut().enumerate() {
+                    *register = super::decode_u16(data[i * 2], data[i * 2 + 1]);
+                }
+                resp[0] = 0x10;
+                (resp[1], resp[2]) = encode_u16(addr);
+                (resp[3], resp[4]) = encode_u16(quantity);
+                Ok((
+                    5,
+                    Some(WriteRecord::new(
+                        slave,
+                        Area::HoldingRegisters,
+                        addr,
+                        quantity,
+                    )),
+                ))
+            }
+            Request::MaskWriteRegister {
+                addr,
+                and_mask,
+                or_mask,
+            } => {
+                let register = &mut memory.holding_registers[addr as usize];
+                *register = (*register & and_mask) | (or_mask & !and_mask);
+                resp[0] = 0x16;
+                (resp[1], resp[2]) = encode_u16(addr);
+                (resp[3], resp[4]) = encode_u16(and_mask);
+                (resp[5], resp[6]) = encode_u16(or_mask);
+                Ok((
+                    7,
+                    Some(WriteRecord::new(slave, Area::HoldingRegisters, addr, 1)),
+                ))
+            }
+        }
+    }
+}
+
+impl WriteRecord {
+    fn new(slave: u8, area: Area, address: u16, quantity: u16) -> Self {
+        Self {
+            slave,
+            area,
+            address,
+            quantity,
+        }
+    }
+
+    // 判断写入范围是否与[address, address + quantity)有交集
+    pub fn overlaps(&self, address: u16, quantity: u16) -> bool {
+        let start = self.address as usize;
+        let end = start + self.quantity as usize;
+        let other_start = address as usize;
+        let other_end = other_start + quantity as usize;
+        start < other_end && other_start < end
+    }
+}
+
+fn get_range<T>(data: &[T], addr: u16, quantity: u16) -> Result<&[T], Exception> {
+    let start = addr as usize;
+    let end = start + quantity as usize;
+    if end > AREA_SIZE {
+        return Err(Exception::IllegalDataAddress);
+    }
+    Ok(&data[start..end])
+}
+
+fn get_range_mut<T>(data: &mut [T], addr: u16, quantity: u16) -> Result<&mut [T], Exception> {
+    let start = addr as usize;
+    let end = start + quantity as usize;
+    if end > AREA_SIZE {
+        return Err(Exception::IllegalDataAddress);
+    }
+    Ok(&mut data[start..end])
+}
+
+// function code: 1 Byte
+// byte count: 1 Byte N
+// status: N Bytes，低位在前
+fn encode_bits(resp: &mut [u8], bits: &[bool]) -> u16 {
+    let byte_count = bits.len().div_ceil(8);
+    resp[1] = byte_count as u8;
+    resp[2..2 + byte_count].fill(0);
+    for (i, bit) in bits.iter().enumerate() {
+        if *bit {
+            resp[2 + i / 8] |= 1 << (i % 8);
+        }
+    }
+    2 + byte_count as u16
+}
+
+// function code: 1 Byte
+// byte count: 1 Byte 2 * N
+// register value: N * 2 Bytes
+fn encode_registers(resp: &mut [u8], registers: &[u16]) -> u16 {
+    resp[1] = (registers.len() * 2) as u8;
+    for (i, register) in registers.iter().enumerate() {
+        (resp[2 + i * 2], resp[3 + i * 2]) = encode_u16(*register);
+    }
+    2 + (registers.len() * 2) as u16
+}
+
+// 网关本地读写从站数据使用的Context，与主站请求共用同一份数据
+struct StoreContext {
+    store: SharedStore,
+    req: [u8; 256],
+    buffer: [u8; 256],
+}
+
+pub fn new_context(store: SharedStore) -> Box<dyn Context> {
+    Box::new(StoreContext {
+        store,
+        req: [0; 256],
+        buffer: [0; 256],
+    })
+}
+
+impl StoreContext {
+    // 本地写入不通知源，避免规则中的源、动作形成回环
+    fn call(&mut self, slave: u8, len: u16) {
+        let mut store = self.store.lock().unwrap();
+        store.handle(slave, &self.req[..len as usize], &mut self.buffer);
+    }
+}
+
+#[async_trait]
+impl Context for StoreContext {
+    async fn read_coils(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<&mut [u8], ModbusError> {
+        let len = encode_read_coils(&mut self.req, addr, quantity);
+        self.call(slave, len);
+        decode_read_coils(&mut self.buffer)
+    }
+
+    async fn read_discrete_inputs(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<&mut [u8], ModbusError> {
+        let len = encode_read_discrete_inputs(&mut self.req, addr, quantity);
+        self.call(slave, len);
+        decode_read_discrete_inputs(&mut self.buffer)
+    }
+
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<&mut [u8], ModbusError> {
+        let len = encode_read_holding_registers(&mut self.req, addr, quantity);
+        self.call(slave, len);
+        decode_read_holding_registers(&mut self.buffer)
+    }
+
+    async fn read_input_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<&mut [u8], ModbusError> {
+        let len = encode_read_input_registers(&mut self.req, addr, quantity);
+        self.call(slave, len);
+        decode_read_input_registers(&mut self.buffer)
+    }
+
+    async fn write_single_coil(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        value: Vec<u8>,
+    ) -> Result<(), ModbusError> {
+        let len = encode_write_single_coil(&mut self.req, addr, value);
+        self.call(slave, len);
+        decode_write_single_coil(&self.buffer)
+    }
+
+    async fn write_single_register(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        value: Vec<u8>,
+    ) -> Result<(), ModbusError> {
+        let len = encode_write_single_register(&mut self.req, addr, value);
+        self.call(slave, len);
+        decode_write_single_register(&self.buffer)
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        value: Vec<u8>,
+    ) -> Result<(), ModbusError> {
+        let len = encode_write_multiple_registers(&mut self.req, addr, value);
+        self.call(slave, len);
+        decode_write_multiple_registers(&self.buffer)
+    }
+
+    async fn mask_write_register(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        value: Vec<u8>,
+    ) -> Result<(), ModbusError> {
+        let len = encode_mask_write_register(&mut self.req, addr, value);
+        self.call(slave, len);
+        decode_mask_write_register(&self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{Area, Store};
+
+    #[test]
+    fn test_write_then_read_holding_registers() {
+        let mut store = Store::default();
+        store.add_slave(1);
+        let mut resp = [0; 256];
+
+        // write multiple registers: addr 10, quantity 2, values 0x0102 0x0304
+        let req = [0x10, 0x00, 0x0A, 0x00, 0x02, 0x04, 0x01, 0x02, 0x03, 0x04];
+        let (len, record) = store.handle(1, &req, &mut resp);
+        assert_eq!(&resp[..len as usize], &[0x10, 0x00, 0x0A, 0x00, 0x02]);
+        let record = record.unwrap();
+        assert_eq!(record.area, Area::HoldingRegisters);
+        assert!(record.overlaps(11, 1));
+        assert!(!record.overlaps(12, 2));
+
+        let req = [0x03, 0x00, 0x0A, 0x00, 0x02];
+        let (len, record) = store.handle(1, &req, &mut resp);
+        assert!(record.is_none());
+        assert_eq!(&resp[..len as usize], &[0x03, 0x04, 0x01, 0x02, 0x03, 0x04]);
+    }
+
+    #[test]
+    fn test_coils_and_exceptions() {
+        let mut store = Store::default();
+        store.add_slave(1);
+        let mut resp = [0; 256];
+
+        let req = [0x05, 0x00, 0x03, 0xFF, 0x00];
+        store.handle(1, &req, &mut resp);
+
+        let req = [0x01, 0x00, 0x00, 0x00, 0x08];
+        let (len, _) = store.handle(1, &req, &mut resp);
+        assert_eq!(&resp[..len as usize], &[0x01, 0x01, 0b0000_1000]);
+
+        // 地址越界
+        let req = [0x03, 0xFF, 0xFF, 0x00, 0x02];
+        let (len, _) = store.handle(1, &req, &mut resp);
+        assert_eq!(&resp[..len as usize], &[0x83, 0x02]);
+
+        // 不支持的功能码
+        let req = [0x2B, 0x00, 0x00, 0x00, 0x00];
+        let (len, _) = store.handle(1, &req, &mut resp);
+        assert_eq!(&resp[..len as usize], &[0xAB, 0x01]);
+
+        // 未配置的从站
+        let req = [0x03, 0x00, 0x00, 0x00, 0x01];
+        let (len, _) = store.handle(2, &req, &mut resp);
+        assert_eq!(&resp[..len as usize], &[0x83, 0x0B]);
+    }
+
+    #[test]
+    fn test_set_slaves() {
+        let mut store = Store::default();
+        store.add_slave(1);
+        store.set_slaves(&HashSet::from([2, 3]));
+        assert!(!store.contains_slave(1));
+        assert!(store.contains_slave(2));
+        assert!(store.contains_slave(3));
+    }
+}
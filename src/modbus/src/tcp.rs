@@ -1,7 +1,10 @@
 use std::{fmt::Debug, io};
 
 use async_trait::async_trait;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc::UnboundedSender,
+};
 
 use crate::pdu::decode_read_coils;
 
@@ -14,6 +17,7 @@ use super::{
         encode_read_discrete_inputs, encode_read_holding_registers, encode_read_input_registers,
         encode_write_multiple_registers, encode_write_single_coil, encode_write_single_register,
     },
+    server::{SharedStore, WriteRecord},
     Context, ModbusError, ProtocolError,
 };
 
@@ -219,3 +223,50 @@ where
         decode_mask_write_register(&self.buffer[7..])
     }
 }
+
+// 从站模式，处理主站的请求直至连接断开
+pub async fn serve<T>(
+    mut transport: T,
+    store: SharedStore,
+    record_tx: UnboundedSender<WriteRecord>,
+) -> io::Result<()>
+where
+    T: AsyncReadExt + AsyncWriteExt + Unpin + Send,
+{
+    let mut req = [0; 260];
+    let mut resp = [0; 260];
+    loop {
+        transport.read_exact(&mut req[..7]).await?;
+        // 随后的长度，包含unit id
+        let len = decode_u16(req[4], req[5]) as usize;
+        if !(2..=254).contains(&len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "MBAP长度错误"));
+        }
+        transport.read_exact(&mut req[7..6 + len]).await?;
+
+        // tcp 协议 固定为0
+        if req[2] != 0 || req[3] != 0 {
+            continue;
+        }
+
+        let slave = req[6];
+        // 未配置的从站返回0x0B异常
+        let (pdu_len, record) =
+            store
+                .lock()
+                .unwrap()
+                .handle(slave, &req[7..6 + len], &mut resp[7..]);
+
+        resp[0] = req[0];
+        resp[1] = req[1];
+        resp[2] = 0;
+        resp[3] = 0;
+        (resp[4], resp[5]) = encode_u16(pdu_len + 1);
+        resp[6] = slave;
+        transport.write_all(&resp[..7 + pdu_len as usize]).await?;
+
+        if let Some(record) = record {
+            _ = record_tx.send(record);
+        }
+    }
+}
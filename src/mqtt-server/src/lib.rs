@@ -1,55 +1,216 @@
-use rumqttd::{Broker, Config, Notification};
-use types::mqtt_server::MqttServerConf;
-
-pub struct MqttServer {
-    conf: MqttServerConf,
-}
-
-impl MqttServer {
-    pub fn start(&self) {
-        let config: Config = Config {
-            id: todo!(),
-            router: todo!(),
-            v4: todo!(),
-            v5: todo!(),
-            ws: todo!(),
-            cluster: todo!(),
-            console: todo!(),
-            bridge: todo!(),
-            prometheus: todo!(),
-            metrics: todo!(),
-        };
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    thread,
+};
 
-        let mut broker = Broker::new(config);
-        let alerts = broker.alerts().unwrap();
-        let (mut link_tx, mut link_rx) = broker.link("consumer").unwrap();
-        tokio::spawn(async move {
-            broker.start().unwrap();
-        });
-        println!("here");
-
-        link_tx.subscribe("#").unwrap();
-        tokio::spawn(async move {
-            let mut count = 0;
-            loop {
-                let notification = match link_rx.recv().unwrap() {
-                    Some(v) => v,
-                    None => continue,
-                };
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use rumqttd::{
+    local::{LinkRx, LinkTx},
+    Broker, Config, ConnectionSettings, Notification, RouterConfig, TlsConfig,
+};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+use types::mqtt_server::{MqttServerConf, ServerSettings};
 
-                match notification {
-                    Notification::Forward(forward) => {
-                        count += 1;
-                        println!(
-                            "Topic = {:?}, Count = {}, Payload = {:?} bytes",
-                            forward.publish.topic, count, forward.publish.payload,
-                        );
-                    }
-                    v => {
-                        println!("{v:?}");
+pub use rumqttd::protocol::{valid_filter, valid_topic};
+
+// 网关内部连接到broker使用的客户端id
+const LOCAL_CLIENT_ID: &str = "halia";
+
+// 本地订阅者接收消息的缓存数量
+const PUBLISH_CHANNEL_SIZE: usize = 1024;
+
+static GLOBAL_MQTT_SERVER: OnceLock<MqttServer> = OnceLock::new();
+
+struct MqttServer {
+    link_tx: Mutex<LinkTx>,
+    publish_tx: broadcast::Sender<Publish>,
+}
+
+// broker上的一条消息，包括外部客户端及网关内部发布的消息
+#[derive(Clone, Debug)]
+pub struct Publish {
+    pub topic: String,
+    pub payload: Bytes,
+}
+
+pub fn start(conf: &MqttServerConf) -> Result<()> {
+    if GLOBAL_MQTT_SERVER.get().is_some() {
+        bail!("mqtt服务器已启动！");
+    }
+
+    let config = build_config(conf)?;
+    let mut broker = Broker::new(config);
+    let (mut link_tx, link_rx) = broker.link(LOCAL_CLIENT_ID)?;
+    link_tx.subscribe("#")?;
+
+    thread::Builder::new()
+        .name("mqtt-server".to_owned())
+        .spawn(move || {
+            if let Err(e) = broker.start() {
+                error!("mqtt server err:{}", e);
+            }
+        })?;
+
+    let (publish_tx, _) = broadcast::channel(PUBLISH_CHANNEL_SIZE);
+    let _ = GLOBAL_MQTT_SERVER.set(MqttServer {
+        link_tx: Mutex::new(link_tx),
+        publish_tx: publish_tx.clone(),
+    });
+
+    tokio::spawn(forward_loop(link_rx, publish_tx));
+
+    for (protocol, settings) in [("v4", &conf.v4), ("v5", &conf.v5), ("ws", &conf.ws)] {
+        if let Some(settings) = settings {
+            info!("mqtt server({}) listening on {}", protocol, settings.listen);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_running() -> bool {
+    GLOBAL_MQTT_SERVER.get().is_some()
+}
+
+pub fn publish(topic: String, payload: Bytes) -> Result<()> {
+    match GLOBAL_MQTT_SERVER.get() {
+        Some(server) => {
+            server.link_tx.lock().unwrap().publish(topic, payload)?;
+            Ok(())
+        }
+        None => bail!("mqtt服务器未启动！"),
+    }
+}
+
+// 接收broker上所有非$开头主题的消息，由订阅方自行过滤主题
+pub fn subscribe() -> Result<broadcast::Receiver<Publish>> {
+    match GLOBAL_MQTT_SERVER.get() {
+        Some(server) => Ok(server.publish_tx.subscribe()),
+        None => bail!("mqtt服务器未启动！"),
+    }
+}
+
+async fn forward_loop(mut link_rx: LinkRx, publish_tx: broadcast::Sender<Publish>) {
+    loop {
+        let notification = match link_rx.next().await {
+            Ok(Some(notification)) => notification,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("mqtt server link err:{}", e);
+                return;
+            }
+        };
+
+        match notification {
+            Notification::Forward(forward) => {
+                let topic = match String::from_utf8(forward.publish.topic.to_vec()) {
+                    Ok(topic) => topic,
+                    Err(e) => {
+                        warn!("mqtt server topic err:{}", e);
+                        continue;
                     }
-                }
+                };
+                // 没有订阅者时发送失败，直接忽略
+                let _ = publish_tx.send(Publish {
+                    topic,
+                    payload: forward.publish.payload,
+                });
             }
-        });
+            v => debug!("{:?}", v),
+        }
+    }
+}
+
+fn build_config(conf: &MqttServerConf) -> Result<Config> {
+    if conf.v4.is_none() && conf.v5.is_none() && conf.ws.is_none() {
+        bail!("mqtt服务器至少需要配置v4、v5、ws中的一个监听！");
+    }
+
+    let router = RouterConfig {
+        max_connections: conf.router.max_connections,
+        max_outgoing_packet_count: conf.router.max_outgoing_packet_count,
+        max_segment_size: conf.router.max_segment_size,
+        max_segment_count: conf.router.max_segment_count,
+        ..Default::default()
+    };
+
+    Ok(Config {
+        id: 0,
+        router,
+        v4: conf.v4.as_ref().map(|s| transfer_server_settings("v4", s)),
+        v5: conf.v5.as_ref().map(|s| transfer_server_settings("v5", s)),
+        ws: conf.ws.as_ref().map(|s| transfer_server_settings("ws", s)),
+        ..Default::default()
+    })
+}
+
+fn transfer_server_settings(
+    name: &str,
+    settings: &ServerSettings,
+) -> HashMap<String, rumqttd::ServerSettings> {
+    let tls = settings.tls.as_ref().map(|tls| TlsConfig::Rustls {
+        capath: None,
+        certpath: tls.cert_path.clone(),
+        keypath: tls.key_path.clone(),
+    });
+
+    let server_settings = rumqttd::ServerSettings {
+        name: format!("mqtt-server-{}", name),
+        listen: settings.listen,
+        tls,
+        next_connection_delay_ms: settings.next_connection_delay_ms,
+        connections: ConnectionSettings {
+            connection_timeout_ms: settings.connection_timeout_ms,
+            max_payload_size: settings.max_payload_size,
+            max_inflight_count: settings.max_inflight_count,
+            auth: settings.auth.clone(),
+            external_auth: None,
+            dynamic_filters: true,
+        },
+    };
+
+    HashMap::from([(name.to_owned(), server_settings)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_config() {
+        let conf: MqttServerConf = toml::from_str(
+            r#"
+            [v4]
+            listen = "0.0.0.0:1883"
+
+            [v4.auth]
+            admin = "123456"
+
+            [ws]
+            listen = "0.0.0.0:8083"
+            "#,
+        )
+        .unwrap();
+
+        let config = build_config(&conf).unwrap();
+        let v4 = &config.v4.unwrap()["v4"];
+        assert_eq!(v4.listen.port(), 1883);
+        assert_eq!(v4.connections.max_payload_size, 20480);
+        assert_eq!(
+            v4.connections.auth.as_ref().unwrap().get("admin").unwrap(),
+            "123456"
+        );
+        assert!(config.v5.is_none());
+        assert!(config.ws.is_some());
+        assert_eq!(config.router.max_connections, 10010);
+    }
+
+    #[test]
+    fn test_build_config_without_listener() {
+        let conf: MqttServerConf = toml::from_str("").unwrap();
+        assert!(build_config(&conf).is_err());
     }
 }
@@ -148,6 +148,14 @@ impl Session {
         subscription_state.subscription_exists(subscription_id)
     }
 
+    /// Get the current ID of a subscription created on this session. After a reconnect the
+    /// subscription is either transferred with the same ID or recreated with a new one.
+    /// Returns `None` if it could not be transferred or recreated.
+    pub fn current_subscription_id(&self, subscription_id: u32) -> Option<u32> {
+        let subscription_state = trace_lock!(self.subscription_state);
+        subscription_state.current_subscription_id(subscription_id)
+    }
+
     /// Modifies a subscription by sending a [`ModifySubscriptionRequest`] to the server.
     ///
     /// See OPC UA Part 4 - Services 5.13.3 for complete description of the service and error responses.
@@ -918,6 +926,7 @@ impl Session {
                 continue;
             };
 
+            let old_subscription_id = subscription_id;
             let Ok(subscription_id) = self
                 .create_subscription_inner(
                     subscription.publishing_interval,
@@ -938,6 +947,11 @@ impl Session {
                 continue;
             };
 
+            {
+                let mut subscription_state = trace_lock!(self.subscription_state);
+                subscription_state.add_recreated(old_subscription_id, subscription_id);
+            }
+
             let items_to_create = subscription
                 .monitored_items
                 .iter()
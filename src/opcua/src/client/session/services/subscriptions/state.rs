@@ -16,6 +16,8 @@ pub struct SubscriptionState {
     acknowledgements: Vec<SubscriptionAcknowledgement>,
     keep_alive_timeout: Option<Duration>,
     min_publish_interval: Duration,
+    /// Subscriptions recreated after a failed transfer, mapping the old ID to the new one.
+    recreated: HashMap<u32, u32>,
 }
 
 impl SubscriptionState {
@@ -32,6 +34,7 @@ impl SubscriptionState {
             acknowledgements: Vec::new(),
             keep_alive_timeout: None,
             min_publish_interval,
+            recreated: HashMap::new(),
         }
     }
 
@@ -85,6 +88,23 @@ impl SubscriptionState {
         self.subscriptions.contains_key(&subscription_id)
     }
 
+    /// Follow the subscription ID through any recreations and return the current ID,
+    /// or `None` if the subscription no longer exists.
+    pub fn current_subscription_id(&self, mut subscription_id: u32) -> Option<u32> {
+        // Server assigned IDs may be reused, so bound the walk by the number of entries.
+        for _ in 0..=self.recreated.len() {
+            if self.subscriptions.contains_key(&subscription_id) {
+                return Some(subscription_id);
+            }
+            subscription_id = *self.recreated.get(&subscription_id)?;
+        }
+        None
+    }
+
+    pub(crate) fn add_recreated(&mut self, old_subscription_id: u32, subscription_id: u32) {
+        self.recreated.insert(old_subscription_id, subscription_id);
+    }
+
     /// Get a reference to a subscription by ID.
     pub fn get(&self, subscription_id: u32) -> Option<&Subscription> {
         self.subscriptions.get(&subscription_id)
@@ -206,3 +226,44 @@ impl SubscriptionState {
             .min()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SubscriptionState;
+    use crate::client::{DataChangeCallback, Subscription};
+
+    fn subscription(subscription_id: u32) -> Subscription {
+        Subscription::new(
+            subscription_id,
+            Duration::from_secs(1),
+            10,
+            3,
+            0,
+            0,
+            true,
+            Box::new(DataChangeCallback::new(|_, _| {})),
+        )
+    }
+
+    #[test]
+    fn current_subscription_id_follows_recreated() {
+        let mut state = SubscriptionState::new(Duration::from_millis(100));
+        state.add_subscription(subscription(1));
+        assert_eq!(state.current_subscription_id(1), Some(1));
+
+        // transfer failed, recreated as 2 then as 3
+        state.delete_subscription(1);
+        state.add_subscription(subscription(2));
+        state.add_recreated(1, 2);
+        state.delete_subscription(2);
+        state.add_subscription(subscription(3));
+        state.add_recreated(2, 3);
+        assert_eq!(state.current_subscription_id(1), Some(3));
+
+        // recreate failed
+        state.delete_subscription(3);
+        assert_eq!(state.current_subscription_id(1), None);
+    }
+}
@@ -216,6 +216,13 @@ impl X509 {
         })
     }
 
+    pub fn from_pem(pem: &[u8]) -> Result<Self, X509Error> {
+        x509::X509::from_pem(pem).map(X509::from).map_err(|_| {
+            error!("Cannot produce an x509 cert from the data supplied");
+            X509Error
+        })
+    }
+
     /// Creates a self-signed X509v3 certificate and public/private key from the supplied creation args.
     /// The certificate identifies an instance of the application running on a host as well
     /// as the public key. The PKey holds the corresponding public/private key. Note that if
@@ -582,6 +589,13 @@ impl X509 {
         })
     }
 
+    pub fn to_pem(&self) -> Result<Vec<u8>, X509Error> {
+        self.value.to_pem().map_err(|e| {
+            error!("Cannot turn X509 cert to PEM, err = {:?}", e);
+            X509Error
+        })
+    }
+
     fn parse_asn1_date(date: &str) -> Result<DateTime<Utc>, X509Error> {
         const SUFFIX: &str = " GMT";
         // Parse ASN1 time format
@@ -206,7 +206,8 @@ impl Graph {
                                         | types::rules::NodeType::Databoard
                                         | types::rules::NodeType::BlackHole
                                         | types::rules::NodeType::DeviceSink
-                                        | types::rules::NodeType::AppSink => break,
+                                        | types::rules::NodeType::AppSink
+                                        | types::rules::NodeType::MqttServerSink => break,
                                         _ => {}
                                     }
                                     ids.push(current_id);
@@ -5,9 +5,11 @@ use dashmap::DashMap;
 use rule::Rule;
 use types::{
     rules::{
-        AppSinkNode, AppSourceNode, Conf, CreateUpdateRuleReq, DataboardNode, DeviceSinkNode,
-        DeviceSourceNode, ListRulesItem, ListRulesResp, Node, QueryParams, ReadRuleResp,
+        AppSinkNode, AppSourceNode, Conf, CreateUpdateRuleReq, CreateUpdateRuleSqlReq,
+        DataboardNode, DeviceSinkNode, DeviceSourceNode, ListRulesItem, ListRulesResp,
+        MqttServerSinkNode, MqttServerSourceNode, Node, QueryParams, ReadRuleResp,
     },
+    schema::DecodeType,
     Pagination, Summary,
 };
 
@@ -15,6 +17,7 @@ mod graph;
 pub mod rule;
 mod segment;
 mod nodes;
+pub mod sql;
 
 static GLOBAL_RULE_MANAGER: LazyLock<DashMap<String, Rule>> = LazyLock::new(|| DashMap::new());
 
@@ -37,6 +40,7 @@ pub async fn load_from_storage() -> HaliaResult<()> {
 }
 
 pub async fn create(req: CreateUpdateRuleReq) -> HaliaResult<()> {
+    validate_nodes(&req.conf.nodes)?;
     let id = common::get_id();
     create_rule_refs(&id, &req.conf.nodes).await?;
 
@@ -45,6 +49,15 @@ pub async fn create(req: CreateUpdateRuleReq) -> HaliaResult<()> {
     Ok(())
 }
 
+pub async fn create_by_sql(req: CreateUpdateRuleSqlReq) -> HaliaResult<()> {
+    let conf = sql::compile(&req.sql).await?;
+    create(CreateUpdateRuleReq {
+        name: req.name,
+        conf,
+    })
+    .await
+}
+
 pub async fn list(pagination: Pagination, query: QueryParams) -> HaliaResult<ListRulesResp> {
     let (count, db_rules) = storage::rule::search(pagination, query).await?;
     let mut list = vec![];
@@ -101,7 +114,9 @@ pub async fn stop(id: String) -> HaliaResult<()> {
 }
 
 pub async fn update(id: String, req: CreateUpdateRuleReq) -> HaliaResult<()> {
+    validate_nodes(&req.conf.nodes)?;
     storage::rule::reference::delete_many_by_rule_id(&id).await?;
+    schema::unreference_rule(&id).await?;
     create_rule_refs(&id, &req.conf.nodes).await?;
 
     if let Some(mut rule) = GLOBAL_RULE_MANAGER.get_mut(&id) {
@@ -118,6 +133,18 @@ pub async fn update(id: String, req: CreateUpdateRuleReq) -> HaliaResult<()> {
     Ok(())
 }
 
+pub async fn update_by_sql(id: String, req: CreateUpdateRuleSqlReq) -> HaliaResult<()> {
+    let conf = sql::compile(&req.sql).await?;
+    update(
+        id,
+        CreateUpdateRuleReq {
+            name: req.name,
+            conf,
+        },
+    )
+    .await
+}
+
 pub async fn delete(id: String) -> HaliaResult<()> {
     if GLOBAL_RULE_MANAGER.contains_key(&id) {
         return Err(HaliaError::DeleteRunning);
@@ -126,14 +153,47 @@ pub async fn delete(id: String) -> HaliaResult<()> {
     events::insert_delete(types::events::ResourceType::Rule, &id).await;
     storage::rule::delete_by_id(&id).await?;
     storage::rule::reference::delete_many_by_rule_id(&id).await?;
+    schema::unreference_rule(&id).await?;
+
+    Ok(())
+}
+
+fn validate_nodes(nodes: &Vec<Node>) -> HaliaResult<()> {
+    for node in nodes {
+        match node.node_type {
+            types::rules::NodeType::MqttServerSource => {
+                let source_node: MqttServerSourceNode = serde_json::from_value(node.conf.clone())?;
+                crate::nodes::mqtt_server::validate_source_conf(&source_node)?;
+            }
+            types::rules::NodeType::MqttServerSink => {
+                let sink_node: MqttServerSinkNode = serde_json::from_value(node.conf.clone())?;
+                crate::nodes::mqtt_server::validate_sink_conf(&sink_node)?;
+            }
+            _ => {}
+        }
+    }
 
     Ok(())
 }
 
 async fn create_rule_refs(id: &String, nodes: &Vec<Node>) -> HaliaResult<()> {
     let mut err = None;
+    let mut schema_ids = vec![];
     for node in nodes {
         match node.node_type {
+            types::rules::NodeType::MqttServerSource => {
+                let source_node: MqttServerSourceNode = serde_json::from_value(node.conf.clone())?;
+                if let Some(schema_id) = source_node.schema_id {
+                    let use_schema = matches!(
+                        source_node.decode_type,
+                        DecodeType::Protobuf | DecodeType::Csv | DecodeType::Avro
+                    );
+                    if use_schema && !schema_ids.contains(&schema_id) {
+                        schema::reference_rule_node(&schema_id, id).await?;
+                        schema_ids.push(schema_id);
+                    }
+                }
+            }
             types::rules::NodeType::DeviceSource => {
                 let source_node: DeviceSourceNode = serde_json::from_value(node.conf.clone())?;
                 if !storage::device::source_sink::check_exists(&source_node.source_id).await? {
@@ -194,6 +254,7 @@ async fn create_rule_refs(id: &String, nodes: &Vec<Node>) -> HaliaResult<()> {
     match err {
         Some(e) => {
             storage::rule::reference::delete_many_by_rule_id(&id).await?;
+            schema::unreference_rule(id).await?;
             return Err(HaliaError::NotFound(e));
         }
         None => Ok(()),
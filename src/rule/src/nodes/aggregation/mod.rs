@@ -1,9 +1,10 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use message::{Message, MessageBatch, MessageValue};
+use tracing::warn;
 use types::rules::functions::aggregation::{self, Conf};
 
-use crate::nodes::Function;
+use crate::nodes::{group_by::Grouper, Function};
 mod avg;
 mod collect;
 mod count;
@@ -19,6 +20,7 @@ pub trait Aggregater: Sync + Send {
 
 pub struct Aggregate {
     aggregaters: Vec<Box<dyn Aggregater>>,
+    grouper: Grouper,
 }
 
 pub fn new(conf: Conf) -> Result<Box<dyn Function>> {
@@ -37,20 +39,56 @@ pub fn new(conf: Conf) -> Result<Box<dyn Function>> {
 
         aggregaters.push(aggregater);
     }
-    Ok(Box::new(Aggregate { aggregaters }))
+    Ok(Box::new(Aggregate {
+        aggregaters,
+        grouper: Grouper::new(conf.group_by, conf.max_groups),
+    }))
 }
 
 #[async_trait]
 impl Function for Aggregate {
     async fn call(&mut self, message_batch: &mut MessageBatch) -> bool {
+        // 事件时间窗口转发的迟到消息原样输出，不参与聚合
+        if let Some(MessageValue::Boolean(true)) = message_batch.get_metadata("late") {
+            return true;
+        }
+
+        if self.grouper.is_empty() {
+            let message = self.aggregate(message_batch);
+            message_batch.clear();
+            message_batch.push_message(message);
+            return true;
+        }
+
+        // 每个分组输出一条消息
+        let ts = message_batch.get_ts();
+        let groups = self.grouper.split(std::mem::take(message_batch));
+        message_batch.set_ts(ts);
+        if groups.len() > self.grouper.max_groups {
+            warn!(
+                "聚合分组数 {} 超出上限 {}，丢弃多余的分组",
+                groups.len(),
+                self.grouper.max_groups
+            );
+        }
+        for group in groups.into_iter().take(self.grouper.max_groups) {
+            let mut message = Message::default();
+            self.grouper.fill(&mut message, group.values);
+            message.merge(self.aggregate(&group.mb));
+            message_batch.push_message(message);
+        }
+        true
+    }
+}
+
+impl Aggregate {
+    fn aggregate(&self, mb: &MessageBatch) -> Message {
         let mut message = Message::default();
         for aggregater in self.aggregaters.iter() {
-            let (new_field, value) = aggregater.aggregate(&message_batch);
+            let (new_field, value) = aggregater.aggregate(mb);
             message.add(new_field, value);
         }
-        message_batch.clear();
-        message_batch.push_message(message);
-        true
+        message
     }
 }
 
@@ -63,3 +101,32 @@ macro_rules! aggregate_return {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_skip_late() {
+        let conf: Conf = serde_json::from_value(serde_json::json!({
+            "conf": [{"type": "count", "field": "t", "target_field": "cnt", "args": null}],
+            "max_groups": null,
+        }))
+        .unwrap();
+        let mut aggregate = new(conf).unwrap();
+
+        let mut mb = MessageBatch::default();
+        for t in 0..3 {
+            let mut message = Message::default();
+            message.add("t".to_owned(), MessageValue::Int64(t));
+            mb.push_message(message);
+        }
+        let mut late_mb = mb.clone();
+        late_mb.add_metadata("late".to_owned(), MessageValue::Boolean(true));
+
+        assert!(aggregate.call(&mut mb).await);
+        assert_eq!(mb.len(), 1);
+        assert!(aggregate.call(&mut late_mb).await);
+        assert_eq!(late_mb.len(), 3);
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use anyhow::{bail, Result};
 
 use common::get_dynamic_value_from_json;
+use serde::de::DeserializeOwned;
 
 pub(crate) const FIELD_KEY: &str = "field";
 pub(crate) const TARGET_FIELD_KEY: &str = "target_field";
@@ -86,6 +87,17 @@ impl Args {
         })
     }
 
+    // 按类型反序列化参数，参数不存在或为null时返回None
+    pub fn take_option<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>> {
+        match self.0.remove(key) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(arg) => match serde_json::from_value(arg) {
+                Ok(arg) => Ok(Some(arg)),
+                Err(e) => bail!("{} 格式错误: {}", key, e),
+            },
+        }
+    }
+
     pub fn take_array_string_field(&mut self, key: &str) -> Result<Vec<StringFieldArg>> {
         self.0
             .remove(key)
@@ -0,0 +1,35 @@
+use anyhow::Result;
+use message::Message;
+
+use crate::nodes::{
+    args::{Args, TARGET_FIELD_KEY},
+    computes::Computer,
+    expr::Expression,
+};
+
+const EXPR_KEY: &str = "expr";
+
+/// 表达式，结果写入目标字段
+struct Expr {
+    expr: Expression,
+    target_field: String,
+}
+
+pub fn validate_conf(mut args: Args) -> Result<()> {
+    Expression::new(&args.take_string(EXPR_KEY)?)?;
+    args.take_string(TARGET_FIELD_KEY)?;
+    Ok(())
+}
+
+pub fn new(mut args: Args) -> Result<Box<dyn Computer>> {
+    let expr = Expression::new(&args.take_string(EXPR_KEY)?)?;
+    let target_field = args.take_string(TARGET_FIELD_KEY)?;
+    Ok(Box::new(Expr { expr, target_field }))
+}
+
+impl Computer for Expr {
+    fn compute(&mut self, message: &mut Message) {
+        let value = self.expr.eval(message);
+        message.add(self.target_field.clone(), value);
+    }
+}
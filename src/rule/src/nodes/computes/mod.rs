@@ -8,8 +8,10 @@ use super::{args::Args, Function};
 mod array;
 mod compress;
 mod date;
+mod expr;
 mod hash;
 mod number;
+mod stateful;
 mod string;
 mod trigonometric;
 mod type_conversion;
@@ -149,6 +151,18 @@ pub fn validate_conf(conf: Conf) -> Result<()> {
             types::rules::functions::Type::TypeConversionInt => todo!(),
             types::rules::functions::Type::TypeConversionStr => todo!(),
             types::rules::functions::Type::NumberSgn => todo!(),
+            types::rules::functions::Type::Expr => expr::validate_conf(Args::new(item_conf.args))?,
+
+            // 有状态计算
+            types::rules::functions::Type::Delta => {
+                stateful::delta::validate_conf(Args::new(item_conf.args))?
+            }
+            types::rules::functions::Type::Derivative => {
+                stateful::derivative::validate_conf(Args::new(item_conf.args))?
+            }
+            types::rules::functions::Type::Integral => {
+                stateful::integral::validate_conf(Args::new(item_conf.args))?
+            }
         }
     }
 
@@ -439,6 +453,18 @@ pub fn new(conf: Conf) -> Result<Box<dyn Function>> {
             types::rules::functions::Type::TypeConversionStr => {
                 type_conversion::str::new(Args::new(item_conf.args))?
             }
+            types::rules::functions::Type::Expr => expr::new(Args::new(item_conf.args))?,
+
+            // 有状态计算
+            types::rules::functions::Type::Delta => {
+                stateful::delta::new(Args::new(item_conf.args))?
+            }
+            types::rules::functions::Type::Derivative => {
+                stateful::derivative::new(Args::new(item_conf.args))?
+            }
+            types::rules::functions::Type::Integral => {
+                stateful::integral::new(Args::new(item_conf.args))?
+            }
         };
         computers.push(computer);
     }
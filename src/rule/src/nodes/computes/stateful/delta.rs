@@ -0,0 +1,60 @@
+use anyhow::Result;
+use message::{Message, MessageValue};
+
+use crate::nodes::{args::Args, computes::Computer};
+
+use super::States;
+
+/// 与同一分组上一条消息的差值，第一条消息不输出
+struct Delta {
+    field: String,
+    target_field: String,
+    states: States<MessageValue>,
+}
+
+pub fn validate_conf(mut args: Args) -> Result<()> {
+    args.take_field_and_target_field()?;
+    States::<MessageValue>::new(&mut args)?;
+    Ok(())
+}
+
+pub fn new(mut args: Args) -> Result<Box<dyn Computer>> {
+    let (field, target_field) = args.take_field_and_target_field()?;
+    let states = States::new(&mut args)?;
+    Ok(Box::new(Delta {
+        field,
+        target_field,
+        states,
+    }))
+}
+
+impl Computer for Delta {
+    fn compute(&mut self, message: &mut Message) {
+        let value = match message.get(&self.field) {
+            Some(value @ (MessageValue::Int64(_) | MessageValue::Float64(_))) => value.clone(),
+            _ => return,
+        };
+
+        let key = self.states.key(message);
+        let result = match (self.states.get(&key), &value) {
+            (Some(MessageValue::Int64(last)), MessageValue::Int64(current)) => {
+                Some(MessageValue::Int64(current.wrapping_sub(*last)))
+            }
+            (Some(MessageValue::Int64(last)), MessageValue::Float64(current)) => {
+                Some(MessageValue::Float64(current - *last as f64))
+            }
+            (Some(MessageValue::Float64(last)), MessageValue::Int64(current)) => {
+                Some(MessageValue::Float64(*current as f64 - last))
+            }
+            (Some(MessageValue::Float64(last)), MessageValue::Float64(current)) => {
+                Some(MessageValue::Float64(current - last))
+            }
+            _ => None,
+        };
+
+        self.states.insert(key, value);
+        if let Some(result) = result {
+            message.add(self.target_field.clone(), result);
+        }
+    }
+}
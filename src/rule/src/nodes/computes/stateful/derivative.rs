@@ -0,0 +1,54 @@
+use anyhow::Result;
+use message::{Message, MessageValue};
+
+use crate::nodes::{args::Args, computes::Computer};
+
+use super::{take_unit, Sample, States};
+
+/// 变化速率，即相邻两条消息的差值除以时间间隔，第一条消息不输出
+struct Derivative {
+    field: String,
+    target_field: String,
+    // 时间单位对应的毫秒数
+    unit: f64,
+    states: States<Sample>,
+}
+
+pub fn validate_conf(mut args: Args) -> Result<()> {
+    args.take_field_and_target_field()?;
+    take_unit(&mut args)?;
+    States::<Sample>::new(&mut args)?;
+    Ok(())
+}
+
+pub fn new(mut args: Args) -> Result<Box<dyn Computer>> {
+    let (field, target_field) = args.take_field_and_target_field()?;
+    let unit = take_unit(&mut args)?;
+    let states = States::new(&mut args)?;
+    Ok(Box::new(Derivative {
+        field,
+        target_field,
+        unit,
+        states,
+    }))
+}
+
+impl Computer for Derivative {
+    fn compute(&mut self, message: &mut Message) {
+        let sample = match Sample::new(message, &self.field) {
+            Some(sample) => sample,
+            None => return,
+        };
+
+        let key = self.states.key(message);
+        if let Some(last) = self.states.get(&key) {
+            // 时间未前进的消息不参与计算
+            if sample.ts <= last.ts {
+                return;
+            }
+            let rate = (sample.value - last.value) / ((sample.ts - last.ts) as f64 / self.unit);
+            message.add(self.target_field.clone(), MessageValue::Float64(rate));
+        }
+        self.states.insert(key, sample);
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use message::{Message, MessageValue};
+
+use crate::nodes::{args::Args, computes::Computer};
+
+use super::{take_unit, Sample, States};
+
+/// 按梯形法累计的积分，第一条消息输出0
+struct Integral {
+    field: String,
+    target_field: String,
+    // 时间单位对应的毫秒数
+    unit: f64,
+    // 上一次采样与累计值
+    states: States<(Sample, f64)>,
+}
+
+pub fn validate_conf(mut args: Args) -> Result<()> {
+    args.take_field_and_target_field()?;
+    take_unit(&mut args)?;
+    States::<(Sample, f64)>::new(&mut args)?;
+    Ok(())
+}
+
+pub fn new(mut args: Args) -> Result<Box<dyn Computer>> {
+    let (field, target_field) = args.take_field_and_target_field()?;
+    let unit = take_unit(&mut args)?;
+    let states = States::new(&mut args)?;
+    Ok(Box::new(Integral {
+        field,
+        target_field,
+        unit,
+        states,
+    }))
+}
+
+impl Computer for Integral {
+    fn compute(&mut self, message: &mut Message) {
+        let sample = match Sample::new(message, &self.field) {
+            Some(sample) => sample,
+            None => return,
+        };
+
+        let key = self.states.key(message);
+        let total = match self.states.get(&key) {
+            // 时间未前进的消息不参与计算
+            Some((last, _)) if sample.ts <= last.ts => return,
+            Some((last, total)) => {
+                let elapsed = (sample.ts - last.ts) as f64 / self.unit;
+                total + (last.value + sample.value) / 2.0 * elapsed
+            }
+            None => 0.0,
+        };
+
+        message.add(self.target_field.clone(), MessageValue::Float64(total));
+        self.states.insert(key, (sample, total));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_integral() {
+        let mut args = HashMap::new();
+        args.insert("field".to_owned(), serde_json::json!("power"));
+        args.insert("target_field".to_owned(), serde_json::json!("energy"));
+        let mut integral = new(Args::new(args)).unwrap();
+
+        let mut totals = vec![];
+        for (ts, power) in [(0, 10), (1000, 20), (3000, 20), (3000, 50)] {
+            let mut message = Message::default();
+            message.insert_metadata("timestamp".to_owned(), MessageValue::Int64(ts));
+            message.add("power".to_owned(), MessageValue::Int64(power));
+            integral.compute(&mut message);
+            totals.push(message.get_float("energy").copied());
+        }
+        // 时间戳相同的消息不参与计算
+        assert_eq!(totals, vec![Some(0.0), Some(15.0), Some(55.0), None]);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use message::{Message, MessageValue};
+use tracing::warn;
+
+use crate::nodes::{args::Args, group_by::Grouper};
+
+pub mod delta;
+pub mod derivative;
+pub mod integral;
+
+// 按分组分别保存状态，未配置时所有消息共用一个状态
+const GROUP_BY_KEY: &str = "group_by";
+// 状态数上限，超出后新分组的消息不参与计算
+const MAX_GROUPS_KEY: &str = "max_groups";
+// 计算速率与积分时的时间单位
+const UNIT_KEY: &str = "unit";
+
+// 一次采样，ts为消息的 timestamp 元数据，单位ms
+#[derive(Clone, Copy)]
+pub(crate) struct Sample {
+    pub ts: i64,
+    pub value: f64,
+}
+
+impl Sample {
+    fn new(message: &Message, field: &str) -> Option<Self> {
+        let ts = match message.get_metadata("timestamp") {
+            Some(MessageValue::Int64(ts)) => *ts,
+            _ => return None,
+        };
+        let value = match message.get(field) {
+            Some(MessageValue::Int64(i)) => *i as f64,
+            Some(MessageValue::Float64(f)) => *f,
+            _ => return None,
+        };
+        Some(Self { ts, value })
+    }
+}
+
+pub(crate) struct States<T> {
+    grouper: Grouper,
+    states: HashMap<String, T>,
+}
+
+impl<T> States<T> {
+    fn new(args: &mut Args) -> Result<Self> {
+        let group_by = args.take_option(GROUP_BY_KEY)?.unwrap_or_default();
+        let max_groups = args.take_option(MAX_GROUPS_KEY)?;
+        Ok(Self {
+            grouper: Grouper::new(group_by, max_groups),
+            states: HashMap::new(),
+        })
+    }
+
+    fn key(&self, message: &Message) -> String {
+        self.grouper.key(message)
+    }
+
+    fn get(&self, key: &str) -> Option<&T> {
+        self.states.get(key)
+    }
+
+    fn insert(&mut self, key: String, state: T) {
+        if !self.states.contains_key(&key) && self.states.len() >= self.grouper.max_groups {
+            warn!("计算状态数超出上限 {}", self.grouper.max_groups);
+            return;
+        }
+        self.states.insert(key, state);
+    }
+}
+
+// 返回一个时间单位对应的毫秒数，默认为秒
+fn take_unit(args: &mut Args) -> Result<f64> {
+    let unit = match args.take_option_string(UNIT_KEY)?.as_deref() {
+        Some("ms") => 1.0,
+        None | Some("s") => 1000.0,
+        Some("mi") => 60.0 * 1000.0,
+        Some("hh") => 60.0 * 60.0 * 1000.0,
+        Some(unit) => bail!("不支持的时间单位 {}", unit),
+    };
+    Ok(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_group_by() {
+        let args = Args::new(HashMap::from([
+            ("field".to_owned(), json!("v")),
+            ("target_field".to_owned(), json!("d")),
+            (
+                "group_by".to_owned(),
+                json!([{"type": "metadata", "name": "device_id"}]),
+            ),
+        ]));
+        let mut delta = delta::new(args).unwrap();
+        for (device_id, v, d) in [("a", 1, None), ("b", 10, None), ("a", 3, Some(2))] {
+            let mut message = Message::default();
+            message.insert_metadata(
+                "device_id".to_owned(),
+                MessageValue::String(device_id.to_owned()),
+            );
+            message.add("v".to_owned(), MessageValue::Int64(v));
+            delta.compute(&mut message);
+            assert_eq!(message.get("d"), d.map(MessageValue::Int64).as_ref());
+        }
+    }
+}
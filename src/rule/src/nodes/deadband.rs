@@ -0,0 +1,176 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use message::{Message, MessageBatch, MessageValue};
+use tracing::warn;
+use types::rules::functions::deadband::{Conf, ItemConf, Type};
+
+use super::{group_by::Grouper, Function};
+
+// 按例外上报：仅在值的变化超出死区，或距上次转发超过心跳间隔时转发消息
+pub struct Node {
+    items: Vec<ItemConf>,
+    heartbeat: Option<Duration>,
+    grouper: Grouper,
+    states: HashMap<String, State>,
+}
+
+struct State {
+    // 上次转发时各字段的值
+    values: Vec<Option<MessageValue>>,
+    forwarded_at: Instant,
+}
+
+pub fn new(conf: Conf) -> Result<Box<dyn Function>> {
+    if conf.items.is_empty() && conf.heartbeat.is_none() {
+        bail!("至少需要配置一个字段或心跳间隔");
+    }
+    for item in conf.items.iter() {
+        match (item.typ, item.value) {
+            (Type::Change, _) => {}
+            (_, Some(value)) if value >= 0.0 => {}
+            _ => bail!("字段 {} 需要配置非负的死区", item.field),
+        }
+    }
+
+    Ok(Box::new(Node {
+        items: conf.items,
+        heartbeat: conf.heartbeat.map(Duration::from_millis),
+        grouper: Grouper::new(conf.group_by, conf.max_groups),
+        states: HashMap::new(),
+    }))
+}
+
+impl Node {
+    fn forward(&mut self, message: &Message, now: Instant) -> bool {
+        let values: Vec<_> = self
+            .items
+            .iter()
+            .map(|item| message.get(&item.field).cloned())
+            .collect();
+
+        let key = self.grouper.key(message);
+        let state = match self.states.get_mut(&key) {
+            Some(state) => state,
+            None => {
+                // 超出上限的分组不做过滤
+                if self.states.len() >= self.grouper.max_groups {
+                    warn!("死区分组数超出上限 {}", self.grouper.max_groups);
+                    return true;
+                }
+                self.states.insert(
+                    key,
+                    State {
+                        values,
+                        forwarded_at: now,
+                    },
+                );
+                return true;
+            }
+        };
+
+        let changed = self
+            .items
+            .iter()
+            .zip(values.iter())
+            .zip(state.values.iter())
+            .any(|((item, new), old)| changed(item, old.as_ref(), new.as_ref()));
+        let heartbeat = self
+            .heartbeat
+            .is_some_and(|heartbeat| now.duration_since(state.forwarded_at) >= heartbeat);
+        if !changed && !heartbeat {
+            return false;
+        }
+
+        // 只在转发时更新，缓慢的变化累计超出死区后同样会被转发
+        for (old, new) in state.values.iter_mut().zip(values) {
+            if new.is_some() {
+                *old = new;
+            }
+        }
+        state.forwarded_at = now;
+        true
+    }
+}
+
+fn changed(item: &ItemConf, old: Option<&MessageValue>, new: Option<&MessageValue>) -> bool {
+    let (old, new) = match (old, new) {
+        (_, None) => return false,
+        (None, Some(_)) => return true,
+        (Some(old), Some(new)) => (old, new),
+    };
+
+    let deadband = item.value.unwrap_or(0.0);
+    match (item.typ, as_f64(old), as_f64(new)) {
+        // MessageValue 的浮点数比较带有误差，数值按 f64 比较
+        (Type::Change, Some(old), Some(new)) => old != new,
+        (Type::Absolute, Some(old), Some(new)) => (new - old).abs() > deadband,
+        (Type::Percent, Some(old), Some(new)) => {
+            if old == 0.0 {
+                new != 0.0
+            } else {
+                (new - old).abs() / old.abs() * 100.0 > deadband
+            }
+        }
+        // 非数值时按是否变化判断
+        _ => old != new,
+    }
+}
+
+fn as_f64(value: &MessageValue) -> Option<f64> {
+    match value {
+        MessageValue::Int64(i) => Some(*i as f64),
+        MessageValue::Float64(f) => Some(*f),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl Function for Node {
+    async fn call(&mut self, message_batch: &mut MessageBatch) -> bool {
+        let now = Instant::now();
+        message_batch
+            .get_messages_mut()
+            .retain(|message| self.forward(message, now));
+        message_batch.len() != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(temp: f64) -> Message {
+        let mut message = Message::default();
+        message.add("temp".to_owned(), MessageValue::Float64(temp));
+        message
+    }
+
+    #[test]
+    fn test_forward() {
+        let mut node = Node {
+            items: vec![ItemConf {
+                typ: Type::Absolute,
+                field: "temp".to_owned(),
+                value: Some(1.0),
+            }],
+            heartbeat: Some(Duration::from_secs(60)),
+            grouper: Grouper::new(vec![], None),
+            states: HashMap::new(),
+        };
+
+        let now = Instant::now();
+        let forwarded: Vec<_> = [20.0, 20.5, 20.9, 21.2, 20.5]
+            .into_iter()
+            .map(|temp| node.forward(&message(temp), now))
+            .collect();
+        // 与上次转发的 20.0 比较，21.2 超出死区
+        assert_eq!(forwarded, vec![true, false, false, true, false]);
+
+        assert!(node.forward(&message(21.0), now + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,500 @@
+//! 表达式，解析一次后对每条消息求值，供过滤与计算节点使用
+//!
+//! - 字段：`temp`、`a.b.0`，名称含特殊字符时使用 `${a b}`
+//! - 元数据：`@timestamp`，名称含特殊字符时使用 `@{a b}`
+//! - 常量：`1`、`1.5`、`'str'`、`"str"`、`true`、`false`、`null`
+//! - 运算符(优先级从低到高)：`||`/`or`，`&&`/`and`，`!`/`not`，
+//!   `==` `!=` `>` `>=` `<` `<=`，`+` `-`，`*` `/` `%`，负号
+//! - 函数：见 [`Func`]
+//!
+//! 字段不存在、类型不匹配、除零等情况求值结果为null，逻辑运算中非true的值均视为false。
+
+use std::cmp::Ordering;
+
+use anyhow::Result;
+use message::{Message, MessageValue};
+use regex::Regex;
+
+mod parser;
+
+pub struct Expression(Expr);
+
+impl Expression {
+    pub fn new(expr: &str) -> Result<Self> {
+        Ok(Self(parser::parse(expr)?))
+    }
+
+    pub fn eval(&self, message: &Message) -> MessageValue {
+        self.0.eval(message)
+    }
+
+    pub fn eval_bool(&self, message: &Message) -> bool {
+        is_true(&self.0.eval(message))
+    }
+}
+
+enum Expr {
+    Const(MessageValue),
+    Field(String),
+    Metadata(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+    Matches(Box<Expr>, Regex),
+}
+
+#[derive(Clone, Copy)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Neq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+/// 表达式中可调用的函数
+#[derive(Clone, Copy)]
+enum Func {
+    Abs,
+    Ceil,
+    Floor,
+    Round,
+    Sqrt,
+    Pow,
+    Min,
+    Max,
+    Len,
+    Lower,
+    Upper,
+    Trim,
+    Contains,
+    StartsWith,
+    EndsWith,
+    // matches(value, '正则')
+    Matches,
+    IsNull,
+    Int,
+    Float,
+    Str,
+    // if(条件, 条件为true时的值, 否则的值)
+    If,
+    // 返回第一个非null的参数
+    Coalesce,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Self> {
+        let func = match name {
+            "abs" => Func::Abs,
+            "ceil" => Func::Ceil,
+            "floor" => Func::Floor,
+            "round" => Func::Round,
+            "sqrt" => Func::Sqrt,
+            "pow" => Func::Pow,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "len" => Func::Len,
+            "lower" => Func::Lower,
+            "upper" => Func::Upper,
+            "trim" => Func::Trim,
+            "contains" => Func::Contains,
+            "starts_with" => Func::StartsWith,
+            "ends_with" => Func::EndsWith,
+            "matches" => Func::Matches,
+            "is_null" => Func::IsNull,
+            "int" => Func::Int,
+            "float" => Func::Float,
+            "str" => Func::Str,
+            "if" => Func::If,
+            "coalesce" => Func::Coalesce,
+            _ => return None,
+        };
+        Some(func)
+    }
+
+    // 参数个数的范围
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            Func::Abs
+            | Func::Ceil
+            | Func::Floor
+            | Func::Round
+            | Func::Sqrt
+            | Func::Len
+            | Func::Lower
+            | Func::Upper
+            | Func::Trim
+            | Func::IsNull
+            | Func::Int
+            | Func::Float
+            | Func::Str => (1, 1),
+            Func::Pow | Func::Contains | Func::StartsWith | Func::EndsWith | Func::Matches => {
+                (2, 2)
+            }
+            Func::Min | Func::Max => (2, usize::MAX),
+            Func::If => (3, 3),
+            Func::Coalesce => (1, usize::MAX),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, message: &Message) -> MessageValue {
+        match self {
+            Expr::Const(value) => value.clone(),
+            Expr::Field(field) => message.get(field).cloned().unwrap_or(MessageValue::Null),
+            Expr::Metadata(key) => message
+                .get_metadata(key)
+                .cloned()
+                .unwrap_or(MessageValue::Null),
+            Expr::Neg(expr) => match expr.eval(message) {
+                MessageValue::Int64(n) => n
+                    .checked_neg()
+                    .map(MessageValue::Int64)
+                    .unwrap_or(MessageValue::Null),
+                MessageValue::Float64(f) => MessageValue::Float64(-f),
+                _ => MessageValue::Null,
+            },
+            Expr::Not(expr) => MessageValue::Boolean(!is_true(&expr.eval(message))),
+            Expr::And(left, right) => {
+                MessageValue::Boolean(is_true(&left.eval(message)) && is_true(&right.eval(message)))
+            }
+            Expr::Or(left, right) => {
+                MessageValue::Boolean(is_true(&left.eval(message)) || is_true(&right.eval(message)))
+            }
+            Expr::Binary(op, left, right) => binary(*op, left.eval(message), right.eval(message)),
+            Expr::Call(func, args) => call(*func, args, message),
+            Expr::Matches(expr, regex) => match expr.eval(message) {
+                MessageValue::String(s) => MessageValue::Boolean(regex.is_match(&s)),
+                _ => MessageValue::Boolean(false),
+            },
+        }
+    }
+}
+
+fn is_true(value: &MessageValue) -> bool {
+    matches!(value, MessageValue::Boolean(true))
+}
+
+fn as_f64(value: &MessageValue) -> Option<f64> {
+    match value {
+        MessageValue::Int64(n) => Some(*n as f64),
+        MessageValue::Float64(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn float_or_null(f: f64) -> MessageValue {
+    if f.is_finite() {
+        MessageValue::Float64(f)
+    } else {
+        MessageValue::Null
+    }
+}
+
+fn compare(left: &MessageValue, right: &MessageValue) -> Option<Ordering> {
+    match (left, right) {
+        (MessageValue::Int64(l), MessageValue::Int64(r)) => Some(l.cmp(r)),
+        (MessageValue::String(l), MessageValue::String(r)) => Some(l.cmp(r)),
+        (MessageValue::Boolean(l), MessageValue::Boolean(r)) => Some(l.cmp(r)),
+        _ => as_f64(left)?.partial_cmp(&as_f64(right)?),
+    }
+}
+
+fn equals(left: &MessageValue, right: &MessageValue) -> bool {
+    match compare(left, right) {
+        Some(ordering) => ordering == Ordering::Equal,
+        None => match (left, right) {
+            (MessageValue::Null, MessageValue::Null) => true,
+            (MessageValue::Bytes(l), MessageValue::Bytes(r)) => l == r,
+            (MessageValue::Array(_), MessageValue::Array(_))
+            | (MessageValue::Object(_), MessageValue::Object(_)) => left == right,
+            _ => false,
+        },
+    }
+}
+
+fn binary(op: BinaryOp, left: MessageValue, right: MessageValue) -> MessageValue {
+    let ordering = || compare(&left, &right);
+    match op {
+        BinaryOp::Eq => MessageValue::Boolean(equals(&left, &right)),
+        BinaryOp::Neq => MessageValue::Boolean(!equals(&left, &right)),
+        BinaryOp::Gt => MessageValue::Boolean(ordering() == Some(Ordering::Greater)),
+        BinaryOp::Gte => MessageValue::Boolean(matches!(
+            ordering(),
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        BinaryOp::Lt => MessageValue::Boolean(ordering() == Some(Ordering::Less)),
+        BinaryOp::Lte => {
+            MessageValue::Boolean(matches!(ordering(), Some(Ordering::Less | Ordering::Equal)))
+        }
+        BinaryOp::Add => match (&left, &right) {
+            (MessageValue::String(l), MessageValue::String(r)) => {
+                MessageValue::String(format!("{}{}", l, r))
+            }
+            _ => arithmetic(&left, &right, i64::checked_add, |l, r| l + r),
+        },
+        BinaryOp::Sub => arithmetic(&left, &right, i64::checked_sub, |l, r| l - r),
+        BinaryOp::Mul => arithmetic(&left, &right, i64::checked_mul, |l, r| l * r),
+        // 除法结果总是浮点数
+        BinaryOp::Div => match (as_f64(&left), as_f64(&right)) {
+            (Some(l), Some(r)) if r != 0.0 => float_or_null(l / r),
+            _ => MessageValue::Null,
+        },
+        BinaryOp::Rem => arithmetic(&left, &right, i64::checked_rem, |l, r| l % r),
+    }
+}
+
+// 两侧均为整数时按整数计算，溢出时为null，否则按浮点数计算
+fn arithmetic(
+    left: &MessageValue,
+    right: &MessageValue,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> MessageValue {
+    match (left, right) {
+        (MessageValue::Int64(l), MessageValue::Int64(r)) => int_op(*l, *r)
+            .map(MessageValue::Int64)
+            .unwrap_or(MessageValue::Null),
+        _ => match (as_f64(left), as_f64(right)) {
+            (Some(l), Some(r)) => float_or_null(float_op(l, r)),
+            _ => MessageValue::Null,
+        },
+    }
+}
+
+fn call(func: Func, args: &[Expr], message: &Message) -> MessageValue {
+    // 惰性求值的函数
+    match func {
+        Func::If => {
+            return match is_true(&args[0].eval(message)) {
+                true => args[1].eval(message),
+                false => args[2].eval(message),
+            }
+        }
+        Func::Coalesce => {
+            return args
+                .iter()
+                .map(|arg| arg.eval(message))
+                .find(|value| !matches!(value, MessageValue::Null))
+                .unwrap_or(MessageValue::Null)
+        }
+        _ => {}
+    }
+
+    let values: Vec<MessageValue> = args.iter().map(|arg| arg.eval(message)).collect();
+    let value = &values[0];
+    match func {
+        Func::Abs => match value {
+            MessageValue::Int64(n) => n
+                .checked_abs()
+                .map(MessageValue::Int64)
+                .unwrap_or(MessageValue::Null),
+            MessageValue::Float64(f) => MessageValue::Float64(f.abs()),
+            _ => MessageValue::Null,
+        },
+        Func::Ceil => round_with(value, f64::ceil),
+        Func::Floor => round_with(value, f64::floor),
+        Func::Round => round_with(value, f64::round),
+        Func::Sqrt => match as_f64(value) {
+            Some(f) if f >= 0.0 => MessageValue::Float64(f.sqrt()),
+            _ => MessageValue::Null,
+        },
+        Func::Pow => match (as_f64(value), as_f64(&values[1])) {
+            (Some(base), Some(exp)) => float_or_null(base.powf(exp)),
+            _ => MessageValue::Null,
+        },
+        Func::Min | Func::Max => {
+            let wanted = match func {
+                Func::Min => Ordering::Less,
+                _ => Ordering::Greater,
+            };
+            let mut result = value.clone();
+            for value in &values[1..] {
+                match compare(value, &result) {
+                    Some(ordering) if ordering == wanted => result = value.clone(),
+                    Some(_) => {}
+                    None => return MessageValue::Null,
+                }
+            }
+            result
+        }
+        Func::Len => match value {
+            MessageValue::String(s) => MessageValue::Int64(s.chars().count() as i64),
+            MessageValue::Bytes(b) => MessageValue::Int64(b.len() as i64),
+            MessageValue::Array(a) => MessageValue::Int64(a.len() as i64),
+            MessageValue::Object(o) => MessageValue::Int64(o.len() as i64),
+            _ => MessageValue::Null,
+        },
+        Func::Lower => map_str(value, |s| s.to_lowercase()),
+        Func::Upper => map_str(value, |s| s.to_uppercase()),
+        Func::Trim => map_str(value, |s| s.trim().to_owned()),
+        Func::Contains => match (value, &values[1]) {
+            (MessageValue::String(s), MessageValue::String(sub)) => {
+                MessageValue::Boolean(s.contains(sub.as_str()))
+            }
+            (MessageValue::Array(arr), item) => {
+                MessageValue::Boolean(arr.iter().any(|v| equals(v, item)))
+            }
+            _ => MessageValue::Boolean(false),
+        },
+        Func::StartsWith => match (value, &values[1]) {
+            (MessageValue::String(s), MessageValue::String(prefix)) => {
+                MessageValue::Boolean(s.starts_with(prefix.as_str()))
+            }
+            _ => MessageValue::Boolean(false),
+        },
+        Func::EndsWith => match (value, &values[1]) {
+            (MessageValue::String(s), MessageValue::String(suffix)) => {
+                MessageValue::Boolean(s.ends_with(suffix.as_str()))
+            }
+            _ => MessageValue::Boolean(false),
+        },
+        Func::IsNull => MessageValue::Boolean(matches!(value, MessageValue::Null)),
+        Func::Int => match value {
+            MessageValue::Int64(n) => MessageValue::Int64(*n),
+            MessageValue::Float64(f) if f.is_finite() => MessageValue::Int64(*f as i64),
+            MessageValue::Boolean(b) => MessageValue::Int64(*b as i64),
+            MessageValue::String(s) => s
+                .trim()
+                .parse()
+                .map(MessageValue::Int64)
+                .unwrap_or(MessageValue::Null),
+            _ => MessageValue::Null,
+        },
+        Func::Float => match value {
+            MessageValue::Int64(n) => MessageValue::Float64(*n as f64),
+            MessageValue::Float64(f) => MessageValue::Float64(*f),
+            MessageValue::String(s) => s
+                .trim()
+                .parse()
+                .map(float_or_null)
+                .unwrap_or(MessageValue::Null),
+            _ => MessageValue::Null,
+        },
+        Func::Str => match value {
+            MessageValue::Null => MessageValue::Null,
+            MessageValue::String(s) => MessageValue::String(s.clone()),
+            _ => MessageValue::String(value.to_string()),
+        },
+        Func::Matches | Func::If | Func::Coalesce => unreachable!(),
+    }
+}
+
+fn round_with(value: &MessageValue, f: fn(f64) -> f64) -> MessageValue {
+    match value {
+        MessageValue::Int64(n) => MessageValue::Int64(*n),
+        MessageValue::Float64(v) => MessageValue::Float64(f(*v)),
+        _ => MessageValue::Null,
+    }
+}
+
+fn map_str(value: &MessageValue, f: impl Fn(&str) -> String) -> MessageValue {
+    match value {
+        MessageValue::String(s) => MessageValue::String(f(s)),
+        _ => MessageValue::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> Message {
+        let mut message = Message::default();
+        message.add("temp".to_owned(), MessageValue::Int64(60));
+        message.add("humi".to_owned(), MessageValue::Float64(35.5));
+        message.add("status".to_owned(), MessageValue::String("run".to_owned()));
+        message.add(
+            "tags".to_owned(),
+            MessageValue::Array(vec![MessageValue::String("a".to_owned())]),
+        );
+        message.add("a b".to_owned(), MessageValue::Int64(1));
+        message.insert_metadata("topic".to_owned(), MessageValue::String("t/1".to_owned()));
+        message
+    }
+
+    fn eval(expr: &str) -> MessageValue {
+        Expression::new(expr).unwrap().eval(&message())
+    }
+
+    #[test]
+    fn test_logical() {
+        let message = message();
+        for (expr, expected) in [
+            (r#"temp > 50 && status == "run""#, true),
+            ("temp > 50 and not (status == 'run' or humi < 10)", false),
+            ("!(temp <= 50) || missing", true),
+            ("missing == null && ${a b} == 1", true),
+            ("@topic == 't/1' && starts_with(@{topic}, 't/')", true),
+            ("contains(tags, 'a') && matches(status, '^r.n$')", true),
+            ("temp + humi * 2 > 130 && temp % 7 == 4", true),
+            ("temp == 60.0 && status > 'abc'", true),
+        ] {
+            assert_eq!(
+                Expression::new(expr).unwrap().eval_bool(&message),
+                expected,
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("temp * 2 - 1"), MessageValue::Int64(119));
+        assert_eq!(eval("temp / 8"), MessageValue::Float64(7.5));
+        assert_eq!(eval("temp / 0"), MessageValue::Null);
+        assert_eq!(
+            eval("-abs(-3) + max(1, 2.5, 2)"),
+            MessageValue::Float64(-0.5)
+        );
+        assert_eq!(
+            eval("upper(status) + '!'"),
+            MessageValue::String("RUN!".to_owned())
+        );
+        assert_eq!(
+            eval("if(temp > 50, 'hot', 'cold')"),
+            MessageValue::String("hot".to_owned())
+        );
+        assert_eq!(
+            eval("coalesce(missing, int('42'))"),
+            MessageValue::Int64(42)
+        );
+        assert_eq!(eval("round(humi) + len(tags)"), MessageValue::Float64(37.0));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        for expr in [
+            "temp >",
+            "(temp > 1",
+            "foo(1)",
+            "abs(1, 2)",
+            "temp > 1 1",
+            "'abc",
+        ] {
+            assert!(Expression::new(expr).is_err(), "{}", expr);
+        }
+
+        // 嵌套过深时返回错误而非栈溢出
+        for expr in [
+            format!("{}1{}", "(".repeat(10000), ")".repeat(10000)),
+            format!("{}1", "!".repeat(10000)),
+            format!("{}1", "-".repeat(10000)),
+            format!("{}1{}", "abs(".repeat(10000), ")".repeat(10000)),
+        ] {
+            assert!(Expression::new(&expr).is_err());
+        }
+        assert!(Expression::new(&format!("{}1{}", "(".repeat(100), ")".repeat(100))).is_ok());
+    }
+}
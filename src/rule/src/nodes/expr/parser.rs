@@ -0,0 +1,406 @@
+use anyhow::{bail, Result};
+use message::MessageValue;
+use regex::Regex;
+
+use super::{BinaryOp, Expr, Func};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Float(f64),
+    String(String),
+    // 字段路径，a.b.0
+    Ident(String),
+    // ${...}
+    Field(String),
+    // @name 或 @{...}
+    Metadata(String),
+    LParen,
+    RParen,
+    Comma,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Not,
+    And,
+    Or,
+    Eq,
+    Neq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Eof,
+}
+
+// 括号、函数调用及一元运算的嵌套层数上限，避免解析时栈溢出
+const MAX_DEPTH: usize = 128;
+
+pub(super) fn parse(input: &str) -> Result<Expr> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        (Token::Eof, _) => Ok(expr),
+        (_, pos) => bail!("表达式第{}个字符处存在多余内容", pos + 1),
+    }
+}
+
+// 返回token及其起始字符位置
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let next = chars.get(i + 1).copied();
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '!' if next == Some('=') => {
+                i += 1;
+                Token::Neq
+            }
+            '!' => Token::Not,
+            '=' if next == Some('=') => {
+                i += 1;
+                Token::Eq
+            }
+            '=' => Token::Eq,
+            '>' if next == Some('=') => {
+                i += 1;
+                Token::Gte
+            }
+            '>' => Token::Gt,
+            '<' if next == Some('=') => {
+                i += 1;
+                Token::Lte
+            }
+            '<' => Token::Lt,
+            '&' if next == Some('&') => {
+                i += 1;
+                Token::And
+            }
+            '|' if next == Some('|') => {
+                i += 1;
+                Token::Or
+            }
+            '\'' | '"' => {
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\\') => {
+                            match chars.get(i + 1) {
+                                Some('n') => s.push('\n'),
+                                Some('t') => s.push('\t'),
+                                Some(c) => s.push(*c),
+                                None => bail!("表达式第{}个字符处字符串未结束", start + 1),
+                            }
+                            i += 2;
+                        }
+                        Some(q) if *q == c => break,
+                        Some(ch) => {
+                            s.push(*ch);
+                            i += 1;
+                        }
+                        None => bail!("表达式第{}个字符处字符串未结束", start + 1),
+                    }
+                }
+                Token::String(s)
+            }
+            '$' if next == Some('{') => Token::Field(braced(&chars, &mut i, start)?),
+            '@' if next == Some('{') => Token::Metadata(braced(&chars, &mut i, start)?),
+            '@' => {
+                i += 1;
+                let name = take_while(&chars, &mut i, is_ident_char);
+                if name.is_empty() {
+                    bail!("表达式第{}个字符处缺少元数据名称", start + 1);
+                }
+                i -= 1;
+                Token::Metadata(name)
+            }
+            c if c.is_ascii_digit() => {
+                let mut literal = take_while(&chars, &mut i, |c| c.is_ascii_digit());
+                let is_float = chars.get(i) == Some(&'.')
+                    && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+                if is_float {
+                    i += 1;
+                    literal.push('.');
+                    literal.push_str(&take_while(&chars, &mut i, |c| c.is_ascii_digit()));
+                }
+                i -= 1;
+                if is_float {
+                    Token::Float(literal.parse()?)
+                } else {
+                    match literal.parse() {
+                        Ok(n) => Token::Int(n),
+                        Err(_) => bail!("表达式第{}个字符处整数超出范围", start + 1),
+                    }
+                }
+            }
+            c if is_ident_char(c) => {
+                // 字段路径中以.分隔，允许数组下标
+                let mut path = take_while(&chars, &mut i, is_ident_char);
+                while chars.get(i) == Some(&'.')
+                    && chars.get(i + 1).is_some_and(|c| is_ident_char(*c))
+                {
+                    i += 1;
+                    path.push('.');
+                    path.push_str(&take_while(&chars, &mut i, is_ident_char));
+                }
+                i -= 1;
+                match path.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(path),
+                }
+            }
+            c => bail!("表达式第{}个字符处无法识别的字符 {}", start + 1, c),
+        };
+        tokens.push((token, start));
+        i += 1;
+    }
+
+    tokens.push((Token::Eof, chars.len()));
+    Ok(tokens)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+fn take_while(chars: &[char], i: &mut usize, f: impl Fn(char) -> bool) -> String {
+    let mut s = String::new();
+    while let Some(c) = chars.get(*i) {
+        if !f(*c) {
+            break;
+        }
+        s.push(*c);
+        *i += 1;
+    }
+    s
+}
+
+// 读取 {...} 中的内容，结束时i指向}
+fn braced(chars: &[char], i: &mut usize, start: usize) -> Result<String> {
+    *i += 2;
+    let s = take_while(chars, i, |c| c != '}');
+    if chars.get(*i) != Some(&'}') {
+        bail!("表达式第{}个字符处缺少 }}", start + 1);
+    }
+    Ok(s)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    // 当前的嵌套层数
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> (&Token, usize) {
+        let (token, pos) = &self.tokens[self.pos];
+        (token, *pos)
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Token::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek().0 == token {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Result<()> {
+        let (next, pos) = self.next();
+        if next != token {
+            bail!("表达式第{}个字符处应为 {}", pos + 1, expected);
+        }
+        Ok(())
+    }
+
+    fn nested(&mut self, f: impl FnOnce(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth >= MAX_DEPTH {
+            bail!("表达式嵌套超过{}层", MAX_DEPTH);
+        }
+        self.depth += 1;
+        let expr = f(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.eat(&Token::Or) {
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut left = self.parse_not()?;
+        while self.eat(&Token::And) {
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.nested(Self::parse_not)?)));
+        }
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr> {
+        let left = self.parse_additive()?;
+        let op = match self.peek().0 {
+            Token::Eq => BinaryOp::Eq,
+            Token::Neq => BinaryOp::Neq,
+            Token::Gt => BinaryOp::Gt,
+            Token::Gte => BinaryOp::Gte,
+            Token::Lt => BinaryOp::Lt,
+            Token::Lte => BinaryOp::Lte,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.parse_additive()?;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek().0 {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_multiplicative()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.peek().0 {
+                Token::Star => BinaryOp::Mul,
+                Token::Slash => BinaryOp::Div,
+                Token::Percent => BinaryOp::Rem,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Minus) {
+            return Ok(Expr::Neg(Box::new(self.nested(Self::parse_unary)?)));
+        }
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.nested(Self::parse_unary)?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let (token, pos) = self.next();
+        let expr = match token {
+            Token::Int(n) => Expr::Const(MessageValue::Int64(n)),
+            Token::Float(f) => Expr::Const(MessageValue::Float64(f)),
+            Token::String(s) => Expr::Const(MessageValue::String(s)),
+            Token::Field(field) => Expr::Field(field),
+            Token::Metadata(key) => Expr::Metadata(key),
+            Token::LParen => {
+                let expr = self.nested(Self::parse_or)?;
+                self.expect(Token::RParen, ")")?;
+                expr
+            }
+            Token::Ident(name) => {
+                if self.eat(&Token::LParen) {
+                    return self.parse_call(name, pos);
+                }
+                match name.to_ascii_lowercase().as_str() {
+                    "true" => Expr::Const(MessageValue::Boolean(true)),
+                    "false" => Expr::Const(MessageValue::Boolean(false)),
+                    "null" => Expr::Const(MessageValue::Null),
+                    _ => Expr::Field(name),
+                }
+            }
+            Token::Eof => bail!("表达式不完整"),
+            _ => bail!("表达式第{}个字符处应为值、字段或函数", pos + 1),
+        };
+        Ok(expr)
+    }
+
+    fn parse_call(&mut self, name: String, pos: usize) -> Result<Expr> {
+        let mut args = vec![];
+        if !self.eat(&Token::RParen) {
+            loop {
+                args.push(self.nested(Self::parse_or)?);
+                if self.eat(&Token::RParen) {
+                    break;
+                }
+                self.expect(Token::Comma, ", 或 )")?;
+            }
+        }
+
+        let func = match Func::from_name(&name.to_ascii_lowercase()) {
+            Some(func) => func,
+            None => bail!("表达式第{}个字符处不支持的函数 {}", pos + 1, name),
+        };
+        let (min, max) = func.arity();
+        if args.len() < min || args.len() > max {
+            bail!("表达式第{}个字符处函数 {} 的参数个数不正确", pos + 1, name);
+        }
+
+        // 正则在解析时编译，避免逐条消息重复编译
+        if let Func::Matches = func {
+            let pattern = match args.pop() {
+                Some(Expr::Const(MessageValue::String(pattern))) => pattern,
+                _ => bail!("表达式第{}个字符处 matches 的正则必须为字符串常量", pos + 1),
+            };
+            let regex = Regex::new(&pattern)?;
+            return Ok(Expr::Matches(Box::new(args.pop().unwrap()), regex));
+        }
+
+        Ok(Expr::Call(func, args))
+    }
+}
@@ -1,10 +1,10 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
 use common::log::LoggerItem;
 use message::Message;
 use types::rules::functions::filter::Conf;
 
-use super::Function;
+use super::{expr::Expression, Function};
 
 mod ct;
 mod eq;
@@ -22,9 +22,20 @@ pub(crate) trait Filter: Sync + Send {
 
 pub struct Node {
     filters: Vec<Box<dyn Filter>>,
+    expr: Option<Expression>,
 }
 
 pub fn new(conf: Conf, logger: LoggerItem) -> Result<Box<dyn Function>> {
+    if let Some(expr) = conf.expr {
+        if !conf.items.is_empty() {
+            bail!("表达式与过滤条件不能同时配置");
+        }
+        return Ok(Box::new(Node {
+            filters: vec![],
+            expr: Some(Expression::new(&expr)?),
+        }));
+    }
+
     let mut filters: Vec<Box<dyn Filter>> = Vec::with_capacity(conf.items.len());
     for item_conf in conf.items {
         let filter = match item_conf.typ {
@@ -40,7 +51,10 @@ pub fn new(conf: Conf, logger: LoggerItem) -> Result<Box<dyn Function>> {
         };
         filters.push(filter);
     }
-    Ok(Box::new(Node { filters }))
+    Ok(Box::new(Node {
+        filters,
+        expr: None,
+    }))
 }
 
 #[async_trait]
@@ -48,13 +62,19 @@ impl Function for Node {
     async fn call(&mut self, message_batch: &mut message::MessageBatch) -> bool {
         let messages = message_batch.get_messages_mut();
         for message in messages.iter_mut() {
-            let mut passed = false;
-            for filter in &self.filters {
-                if filter.filter(message).await {
-                    passed = true;
-                    break;
+            let passed = match &self.expr {
+                Some(expr) => expr.eval_bool(message),
+                None => {
+                    let mut passed = false;
+                    for filter in &self.filters {
+                        if filter.filter(message).await {
+                            passed = true;
+                            break;
+                        }
+                    }
+                    passed
                 }
-            }
+            };
             if !passed {
                 message.insert_metadata("keep".to_owned(), message::MessageValue::Boolean(false));
             }
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use message::{Message, MessageBatch, MessageValue};
+use types::rules::functions::{GroupBy, GroupByType};
+
+// 未配置上限时的分组数上限
+const DEFAULT_MAX_GROUPS: usize = 1024;
+
+pub(crate) struct Grouper {
+    group_by: Vec<GroupBy>,
+    pub max_groups: usize,
+}
+
+pub(crate) struct Group {
+    // 分组值序列化后的结果，用于区分分组
+    pub key: String,
+    pub values: Vec<MessageValue>,
+    pub mb: MessageBatch,
+}
+
+impl Grouper {
+    pub fn new(group_by: Vec<GroupBy>, max_groups: Option<usize>) -> Self {
+        Self {
+            group_by,
+            max_groups: max_groups.unwrap_or(DEFAULT_MAX_GROUPS),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.group_by.is_empty()
+    }
+
+    fn values(&self, message: &Message) -> Vec<MessageValue> {
+        self.group_by
+            .iter()
+            .map(|group_by| {
+                let value = match group_by.typ {
+                    GroupByType::Field => message.get(&group_by.name),
+                    GroupByType::Metadata => message.get_metadata(&group_by.name),
+                };
+                value.cloned().unwrap_or(MessageValue::Null)
+            })
+            .collect()
+    }
+
+    // 分组值序列化后的结果，序列化结果区分了值的类型
+    pub fn key(&self, message: &Message) -> String {
+        serde_json::to_string(&self.values(message)).unwrap()
+    }
+
+    // 按分组拆分消息批次，分组顺序与其首条消息出现的顺序一致
+    pub fn split(&self, mb: MessageBatch) -> Vec<Group> {
+        if self.group_by.is_empty() {
+            return vec![Group {
+                key: String::new(),
+                values: vec![],
+                mb,
+            }];
+        }
+
+        let mut groups: Vec<Group> = vec![];
+        let mut indexes: HashMap<String, usize> = HashMap::new();
+        let ts = mb.get_ts();
+        let mut mb = mb;
+        for message in mb.get_messages_mut().drain(..) {
+            let values = self.values(&message);
+            let key = serde_json::to_string(&values).unwrap();
+            match indexes.get(&key) {
+                Some(index) => groups[*index].mb.push_message(message),
+                None => {
+                    let mut group_mb = MessageBatch::default();
+                    group_mb.set_ts(ts);
+                    group_mb.push_message(message);
+                    indexes.insert(key.clone(), groups.len());
+                    groups.push(Group {
+                        key,
+                        values,
+                        mb: group_mb,
+                    });
+                }
+            }
+        }
+        groups
+    }
+
+    // 将分组的值写入聚合结果
+    pub fn fill(&self, message: &mut Message, values: Vec<MessageValue>) {
+        for (group_by, value) in self.group_by.iter().zip(values) {
+            match group_by.typ {
+                GroupByType::Field => message.add(group_by.name.clone(), value),
+                GroupByType::Metadata => message.insert_metadata(group_by.name.clone(), value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use types::rules::functions::{GroupBy, GroupByType};
+
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let grouper = Grouper::new(
+            vec![
+                GroupBy {
+                    typ: GroupByType::Metadata,
+                    name: "device_id".to_owned(),
+                },
+                GroupBy {
+                    typ: GroupByType::Field,
+                    name: "line".to_owned(),
+                },
+            ],
+            None,
+        );
+
+        let mut mb = MessageBatch::default();
+        for (device_id, line) in [("a", 1), ("b", 1), ("a", 1), ("a", 2)] {
+            let mut message = Message::default();
+            message.insert_metadata(
+                "device_id".to_owned(),
+                MessageValue::String(device_id.to_owned()),
+            );
+            message.add("line".to_owned(), MessageValue::Int64(line));
+            mb.push_message(message);
+        }
+
+        let groups = grouper.split(mb);
+        assert_eq!(
+            groups
+                .iter()
+                .map(|group| group.mb.len())
+                .collect::<Vec<_>>(),
+            vec![2, 1, 1]
+        );
+
+        let mut message = Message::default();
+        grouper.fill(&mut message, groups[2].values.clone());
+        assert_eq!(
+            message.get_metadata("device_id"),
+            Some(&MessageValue::String("a".to_owned()))
+        );
+        assert_eq!(message.get_int("line"), Some(&2));
+    }
+}
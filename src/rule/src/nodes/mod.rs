@@ -3,9 +3,13 @@ use message::MessageBatch;
 
 pub mod aggregation;
 pub mod computes;
+pub mod deadband;
+pub mod expr;
 pub mod field;
 pub mod filter;
+pub(crate) mod group_by;
 pub mod merge;
+pub mod mqtt_server;
 pub mod window;
 
 pub mod args;
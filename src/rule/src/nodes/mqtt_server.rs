@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use common::error::{HaliaError, HaliaResult};
+use futures::StreamExt;
+use message::RuleMessageBatch;
+use schema::{Decoder, Encoder};
+use tokio::{
+    select,
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::warn;
+use types::{
+    rules::{MqttServerSinkNode, MqttServerSourceNode},
+    schema::DecodeType,
+};
+
+pub fn validate_source_conf(conf: &MqttServerSourceNode) -> HaliaResult<()> {
+    if !mqtt_server::valid_filter(&conf.topic) {
+        return Err(HaliaError::Common(format!("topic {} 错误！", conf.topic)));
+    }
+    if conf.decode_type == DecodeType::Protobuf && conf.schema_id.is_none() {
+        return Err(HaliaError::Common("请填写schema_id".to_owned()));
+    }
+    Ok(())
+}
+
+pub fn validate_sink_conf(conf: &MqttServerSinkNode) -> HaliaResult<()> {
+    if !mqtt_server::valid_topic(&conf.topic) {
+        return Err(HaliaError::Common(format!("topic {} 不合法！", conf.topic)));
+    }
+    Ok(())
+}
+
+pub async fn get_source_rxs(
+    conf: MqttServerSourceNode,
+    cnt: usize,
+    stop_signal_rx: broadcast::Receiver<()>,
+) -> HaliaResult<Vec<mpsc::UnboundedReceiver<RuleMessageBatch>>> {
+    validate_source_conf(&conf)?;
+    let publish_rx = mqtt_server::subscribe()?;
+    let decoder = schema::new_decoder(&conf.decode_type, &conf.schema_id).await?;
+
+    let mut txs = Vec::with_capacity(cnt);
+    let mut rxs = Vec::with_capacity(cnt);
+    for _ in 0..cnt {
+        let (tx, rx) = mpsc::unbounded_channel();
+        txs.push(tx);
+        rxs.push(rx);
+    }
+
+    run_source(conf.topic, decoder, publish_rx, txs, stop_signal_rx);
+    Ok(rxs)
+}
+
+fn run_source(
+    filter: String,
+    decoder: Box<dyn Decoder>,
+    mut publish_rx: broadcast::Receiver<mqtt_server::Publish>,
+    txs: Vec<mpsc::UnboundedSender<RuleMessageBatch>>,
+    mut stop_signal_rx: broadcast::Receiver<()>,
+) {
+    tokio::spawn(async move {
+        loop {
+            select! {
+                res = publish_rx.recv() => {
+                    match res {
+                        Ok(publish) => {
+                            if apps::match_topic(&publish.topic, &filter).is_none() {
+                                continue;
+                            }
+
+                            let mb = match decoder.decode(publish.payload) {
+                                Ok(mb) => mb,
+                                Err(e) => {
+                                    warn!("decode err :{}", e);
+                                    continue;
+                                }
+                            };
+
+                            match txs.len() {
+                                1 => {
+                                    let _ = txs[0].send(RuleMessageBatch::Owned(mb));
+                                }
+                                _ => {
+                                    let mb = Arc::new(mb);
+                                    for tx in txs.iter() {
+                                        let _ = tx.send(RuleMessageBatch::Arc(mb.clone()));
+                                    }
+                                }
+                            }
+                        }
+                        Err(RecvError::Lagged(n)) => warn!("mqtt server source lagged {} messages", n),
+                        Err(RecvError::Closed) => return,
+                    }
+                }
+
+                _ = stop_signal_rx.recv() => {
+                    return
+                }
+            }
+        }
+    });
+}
+
+pub async fn get_sink_txs(
+    conf: MqttServerSinkNode,
+    cnt: usize,
+    stop_signal_rx: broadcast::Receiver<()>,
+) -> HaliaResult<Vec<mpsc::UnboundedSender<RuleMessageBatch>>> {
+    validate_sink_conf(&conf)?;
+    if !mqtt_server::is_running() {
+        return Err(HaliaError::Common("mqtt服务器未启动！".to_owned()));
+    }
+    let encoder = schema::new_encoder(&conf.encode_type, &conf.schema_id).await?;
+
+    let mut txs = Vec::with_capacity(cnt);
+    let mut rxs = Vec::with_capacity(cnt);
+    for _ in 0..cnt {
+        let (tx, rx) = mpsc::unbounded_channel();
+        txs.push(tx);
+        rxs.push(rx);
+    }
+
+    run_sink(conf.topic, encoder, rxs, stop_signal_rx);
+    Ok(txs)
+}
+
+fn run_sink(
+    topic: String,
+    encoder: Box<dyn Encoder>,
+    rxs: Vec<mpsc::UnboundedReceiver<RuleMessageBatch>>,
+    mut stop_signal_rx: broadcast::Receiver<()>,
+) {
+    let streams: Vec<_> = rxs.into_iter().map(UnboundedReceiverStream::new).collect();
+    let mut stream = futures::stream::select_all(streams);
+
+    tokio::spawn(async move {
+        loop {
+            select! {
+                Some(rmb) = stream.next() => {
+                    let mb = rmb.take_mb();
+                    if mb.get_messages().is_empty() {
+                        continue;
+                    }
+
+                    let payload = match encoder.encode(mb) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            warn!("encode err :{}", e);
+                            continue;
+                        }
+                    };
+
+                    if let Err(e) = mqtt_server::publish(topic.clone(), payload) {
+                        warn!("mqtt server publish err:{}", e);
+                    }
+                }
+
+                _ = stop_signal_rx.recv() => {
+                    return
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use types::schema::EncodeType;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_conf() {
+        let source = |topic: &str| MqttServerSourceNode {
+            topic: topic.to_owned(),
+            decode_type: DecodeType::Json,
+            schema_id: None,
+        };
+        assert!(validate_source_conf(&source("a/+/c")).is_ok());
+        assert!(validate_source_conf(&source("a/#")).is_ok());
+        assert!(validate_source_conf(&source("a/#/c")).is_err());
+        assert!(validate_source_conf(&MqttServerSourceNode {
+            topic: "a/b".to_owned(),
+            decode_type: DecodeType::Protobuf,
+            schema_id: None,
+        })
+        .is_err());
+
+        let sink = |topic: &str| MqttServerSinkNode {
+            topic: topic.to_owned(),
+            encode_type: EncodeType::Json,
+            schema_id: None,
+        };
+        assert!(validate_sink_conf(&sink("a/b/c")).is_ok());
+        assert!(validate_sink_conf(&sink("a/+/c")).is_err());
+    }
+}
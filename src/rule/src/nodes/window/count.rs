@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::collections::HashMap;
 
 use anyhow::Result;
 use message::{MessageBatch, RuleMessageBatch};
@@ -9,56 +9,45 @@ use tokio::{
         mpsc::{UnboundedReceiver, UnboundedSender},
     },
 };
-use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
+use tokio_stream::StreamExt;
 use types::rules::functions::window::Count;
 
+use crate::nodes::group_by::Grouper;
+
+// 每个分组收到count个消息批次后输出
 pub fn run(
     conf: Count,
-    mut rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
+    grouper: Grouper,
+    rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
     txs: Vec<UnboundedSender<RuleMessageBatch>>,
     mut stop_signal_rx: broadcast::Receiver<()>,
 ) -> Result<()> {
-    tokio::spawn(async move {
-        let mut mbs = vec![];
-        let mut cnt: u64 = 0;
+    let mut stream = super::select_all(rxs);
 
-        if rxs.len() == 0 {
-            loop {
-                select! {
-                    Some(rmb) = rxs[0].recv() => {
-                        mbs.push(rmb.take_mb());
-                        cnt += 1;
-                        if cnt == conf.count {
-                            cnt = 0;
-                            send_rule_message(&txs, &mut mbs);
+    tokio::spawn(async move {
+        let mut groups: HashMap<String, (u64, MessageBatch)> = HashMap::new();
+        loop {
+            select! {
+                Some(rmb) = stream.next() => {
+                    for group in grouper.split(rmb.take_mb()) {
+                        let full = match super::group_state(&mut groups, group.key.clone(), grouper.max_groups) {
+                            Some((cnt, mb)) => {
+                                *cnt += 1;
+                                mb.extend(group.mb);
+                                *cnt >= conf.count
+                            }
+                            None => false,
+                        };
+                        if full {
+                            if let Some((_, mb)) = groups.remove(&group.key) {
+                                super::send_rule_message(&txs, mb);
+                            }
                         }
                     }
-
-                    _ = stop_signal_rx.recv() => {
-                        return
-                    }
                 }
-            }
-        } else {
-            let streams: Vec<_> = rxs
-                .into_iter()
-                .map(|rx| UnboundedReceiverStream::new(rx))
-                .collect();
-            let mut stream = futures::stream::select_all(streams);
-            loop {
-                select! {
-                    Some(rmb) = stream.next() => {
-                        mbs.push(rmb.take_mb());
-                        cnt += 1;
-                        if cnt == conf.count {
-                            cnt = 0;
-                            send_rule_message(&txs, &mut mbs);
-                        }
-                    }
 
-                    _ = stop_signal_rx.recv() => {
-                        return
-                    }
+                _ = stop_signal_rx.recv() => {
+                    return
                 }
             }
         }
@@ -66,22 +55,3 @@ pub fn run(
 
     Ok(())
 }
-
-fn send_rule_message(txs: &Vec<UnboundedSender<RuleMessageBatch>>, mbs: &mut Vec<MessageBatch>) {
-    let mut send_mb = MessageBatch::default();
-    for mb in mbs.drain(..) {
-        send_mb.extend(mb);
-    }
-
-    match txs.len() {
-        0 => unreachable!(),
-        1 => {
-            let rmb = RuleMessageBatch::Owned(send_mb);
-            txs[0].send(rmb).unwrap();
-        }
-        _ => {
-            let rmb = RuleMessageBatch::Arc(Arc::new(send_mb));
-            txs.iter().for_each(|tx| tx.send(rmb.clone()).unwrap());
-        }
-    }
-}
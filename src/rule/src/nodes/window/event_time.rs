@@ -0,0 +1,427 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{bail, Result};
+use message::{Message, MessageBatch, MessageValue, RuleMessageBatch};
+use tokio::{
+    select,
+    sync::{
+        broadcast::Receiver,
+        mpsc::{UnboundedReceiver, UnboundedSender},
+    },
+    time::{self, Instant},
+};
+use tokio_stream::StreamExt;
+use tracing::{debug, warn};
+use types::rules::functions::{
+    window::{EventTime, LateStrategy, TimestampUnit},
+    GroupByType,
+};
+
+use crate::nodes::group_by::Grouper;
+
+// 事件时间下的窗口划分方式，时长均为ms
+#[derive(Clone, Copy)]
+pub(super) enum Assigner {
+    Tumbling { size: i64 },
+    Hopping { size: i64, hop: i64 },
+    Session { timeout: i64, max: i64 },
+}
+
+impl Assigner {
+    pub(super) fn validate(&self) -> Result<()> {
+        match *self {
+            Assigner::Tumbling { size } if size <= 0 => bail!("窗口大小必须大于0"),
+            Assigner::Hopping { size, .. } if size <= 0 => bail!("窗口大小必须大于0"),
+            Assigner::Hopping { hop, .. } if hop <= 0 => bail!("滑动步长必须大于0"),
+            Assigner::Hopping { size, hop } if hop > size => bail!("滑动步长不能大于窗口大小"),
+            Assigner::Session { timeout, .. } if timeout <= 0 => bail!("会话超时时间必须大于0"),
+            Assigner::Session { max, .. } if max <= 0 => bail!("会话最大时长必须大于0"),
+            _ => Ok(()),
+        }
+    }
+
+    fn end(&self, window: &Window) -> i64 {
+        match *self {
+            Assigner::Tumbling { size } | Assigner::Hopping { size, .. } => {
+                window.start.saturating_add(size)
+            }
+            Assigner::Session { timeout, max } => window
+                .last
+                .saturating_add(timeout)
+                .min(window.start.saturating_add(max)),
+        }
+    }
+
+    // 返回消息所属的固定窗口的起始时间，超出i64范围时返回None
+    fn starts(&self, ts: i64) -> Option<Vec<i64>> {
+        match *self {
+            Assigner::Tumbling { size } => Some(vec![ts.div_euclid(size).checked_mul(size)?]),
+            Assigner::Hopping { size, hop } => {
+                let mut starts = vec![];
+                let mut start = ts.div_euclid(hop).checked_mul(hop)?;
+                while start.saturating_add(size) > ts {
+                    starts.push(start);
+                    start = start.checked_sub(hop)?;
+                }
+                Some(starts)
+            }
+            Assigner::Session { .. } => Some(vec![ts]),
+        }
+    }
+}
+
+struct Window {
+    start: i64,
+    // 窗口内最大的事件时间
+    last: i64,
+    mb: MessageBatch,
+}
+
+impl Window {
+    fn new(start: i64, ts: i64) -> Self {
+        Self {
+            start,
+            last: ts,
+            mb: MessageBatch::default(),
+        }
+    }
+}
+
+// 每个分组单独维护水位线，避免某个分组的事件时间超前导致其他分组的消息被判定为迟到
+#[derive(Default)]
+struct Group {
+    // 该分组已收到的最大事件时间
+    max_ts: Option<i64>,
+    windows: Vec<Window>,
+}
+
+impl Group {
+    // 水位线之前结束的窗口不再接收消息
+    fn watermark(&self, allowed_lateness: i64) -> i64 {
+        match self.max_ts {
+            Some(ts) => ts.saturating_sub(allowed_lateness),
+            None => i64::MIN,
+        }
+    }
+
+    fn update_max_ts(&mut self, ts: i64) {
+        self.max_ts = Some(self.max_ts.map_or(ts, |max_ts| max_ts.max(ts)));
+    }
+}
+
+struct Windows {
+    assigner: Assigner,
+    allowed_lateness: i64,
+    max_groups: usize,
+    groups: HashMap<String, Group>,
+}
+
+impl Windows {
+    fn new(assigner: Assigner, allowed_lateness: i64, max_groups: usize) -> Self {
+        Self {
+            assigner,
+            allowed_lateness,
+            max_groups,
+            groups: HashMap::new(),
+        }
+    }
+
+    // 将消息放入所属的窗口，消息迟到时将其返回
+    fn push(&mut self, key: String, ts: i64, message: Message) -> Option<Message> {
+        if !self.groups.contains_key(&key) && self.groups.len() >= self.max_groups {
+            self.evict();
+        }
+        let assigner = self.assigner;
+        let group = super::group_state(&mut self.groups, key, self.max_groups)?;
+        let watermark = group.watermark(self.allowed_lateness);
+
+        // 能并入已有会话时直接放入
+        if let Assigner::Session { timeout, max } = assigner {
+            if let Some(window) = group.windows.iter_mut().find(|window| {
+                ts >= window.start.saturating_sub(timeout)
+                    && ts <= window.last.saturating_add(timeout)
+                    && window
+                        .last
+                        .max(ts)
+                        .checked_sub(window.start.min(ts))
+                        .is_some_and(|duration| duration < max)
+            }) {
+                window.start = window.start.min(ts);
+                window.last = window.last.max(ts);
+                window.mb.push_message(message);
+                group.update_max_ts(ts);
+                return None;
+            }
+        }
+
+        let starts = match assigner.starts(ts) {
+            Some(starts) => starts,
+            None => {
+                warn!("事件时间 {} 所属的窗口超出范围，已丢弃", ts);
+                return None;
+            }
+        };
+        let starts: Vec<_> = starts
+            .into_iter()
+            .filter(|start| assigner.end(&Window::new(*start, ts)) > watermark)
+            .collect();
+        if starts.is_empty() {
+            return Some(message);
+        }
+
+        group.update_max_ts(ts);
+        for start in starts {
+            match group
+                .windows
+                .iter_mut()
+                .find(|window| window.start == start)
+            {
+                Some(window) => window.mb.push_message(message.clone()),
+                None => {
+                    let mut window = Window::new(start, ts);
+                    window.mb.push_message(message.clone());
+                    group.windows.push(window);
+                }
+            }
+        }
+        None
+    }
+
+    // 分组数达到上限时移除没有未结束窗口且事件时间最早的分组
+    fn evict(&mut self) {
+        let key = self
+            .groups
+            .iter()
+            .filter(|(_, group)| group.windows.is_empty())
+            .min_by_key(|(_, group)| group.max_ts)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = key {
+            self.groups.remove(&key);
+        }
+    }
+
+    // 取出各分组水位线之前已结束的窗口，按结束时间排列
+    fn advance(&mut self) -> Vec<MessageBatch> {
+        let allowed_lateness = self.allowed_lateness;
+        self.take(|group, end| end <= group.watermark(allowed_lateness))
+    }
+
+    fn flush(&mut self) -> Vec<MessageBatch> {
+        self.take(|_, _| true)
+    }
+
+    fn take(&mut self, f: impl Fn(&Group, i64) -> bool) -> Vec<MessageBatch> {
+        let mut closed = vec![];
+        for group in self.groups.values_mut() {
+            let windows = std::mem::take(&mut group.windows);
+            for window in windows {
+                let end = self.assigner.end(&window);
+                if f(group, end) {
+                    closed.push((end, window.mb));
+                } else {
+                    group.windows.push(window);
+                }
+            }
+        }
+        closed.sort_by_key(|(end, _)| *end);
+        closed.into_iter().map(|(_, mb)| mb).collect()
+    }
+}
+
+pub(super) fn run(
+    conf: EventTime,
+    assigner: Assigner,
+    grouper: Grouper,
+    rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
+    txs: Vec<UnboundedSender<RuleMessageBatch>>,
+    mut stop_signal_rx: Receiver<()>,
+) -> Result<()> {
+    let mut stream = super::select_all(rxs);
+    let mut windows = Windows::new(assigner, conf.allowed_lateness as i64, grouper.max_groups);
+
+    tokio::spawn(async move {
+        let idle_timeout = Duration::from_millis(conf.idle_timeout.unwrap_or(0));
+        let idle = time::sleep(idle_timeout);
+        tokio::pin!(idle);
+        loop {
+            select! {
+                Some(rmb) = stream.next() => {
+                    let mut late_mb = MessageBatch::default();
+                    for mut group in grouper.split(rmb.take_mb()) {
+                        for message in group.mb.get_messages_mut().drain(..) {
+                            let ts = match get_timestamp(&conf, &message) {
+                                Some(ts) => ts,
+                                None => {
+                                    warn!("消息缺少事件时间 {}，已丢弃", conf.name);
+                                    continue;
+                                }
+                            };
+                            if let Some(mut message) = windows.push(group.key.clone(), ts, message) {
+                                match conf.late_strategy {
+                                    LateStrategy::Drop => debug!("丢弃迟到的消息，事件时间 {}", ts),
+                                    LateStrategy::Forward => {
+                                        message.insert_metadata("late".to_owned(), MessageValue::Boolean(true));
+                                        late_mb.push_message(message);
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // 迟到的消息单独成批并带上 late 元数据，后续的聚合节点不对其聚合
+                    late_mb.add_metadata("late".to_owned(), MessageValue::Boolean(true));
+                    super::send_rule_message(&txs, late_mb);
+                    for mb in windows.advance() {
+                        super::send_rule_message(&txs, mb);
+                    }
+                    idle.as_mut().reset(Instant::now() + idle_timeout);
+                }
+
+                _ = &mut idle, if conf.idle_timeout.is_some() => {
+                    for mb in windows.flush() {
+                        super::send_rule_message(&txs, mb);
+                    }
+                    idle.as_mut().reset(Instant::now() + idle_timeout);
+                }
+
+                _ = stop_signal_rx.recv() => {
+                    return
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// 返回毫秒级的事件时间
+fn get_timestamp(conf: &EventTime, message: &Message) -> Option<i64> {
+    let value = match conf.typ {
+        GroupByType::Field => message.get(&conf.name),
+        GroupByType::Metadata => message.get_metadata(&conf.name),
+    }?;
+
+    let ts = match value {
+        MessageValue::Int64(ts) => *ts,
+        MessageValue::Float64(ts) => *ts as i64,
+        MessageValue::String(s) => {
+            return chrono::DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|dt| dt.timestamp_millis())
+        }
+        _ => return None,
+    };
+
+    match conf.unit {
+        TimestampUnit::S => ts.checked_mul(1000),
+        TimestampUnit::Ms => Some(ts),
+        TimestampUnit::Us => Some(ts / 1000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(ts: i64) -> Message {
+        let mut message = Message::default();
+        message.add("ts".to_owned(), MessageValue::Int64(ts));
+        message
+    }
+
+    fn lens(mbs: Vec<MessageBatch>) -> Vec<usize> {
+        mbs.iter().map(|mb| mb.len()).collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Assigner::Tumbling { size: 0 }.validate().is_err());
+        assert!(Assigner::Hopping { size: 10, hop: 0 }.validate().is_err());
+        assert!(Assigner::Hopping { size: 10, hop: 20 }.validate().is_err());
+        assert!(Assigner::Hopping { size: 10, hop: 5 }.validate().is_ok());
+        let session = |timeout, max| Assigner::Session { timeout, max };
+        assert!(session(0, 10).validate().is_err());
+        assert!(session(5, 0).validate().is_err());
+        assert!(session(5, 10).validate().is_ok());
+    }
+
+    #[test]
+    fn test_tumbling() {
+        let mut windows = Windows::new(Assigner::Tumbling { size: 10 }, 5, 16);
+        for ts in [1, 12, 3] {
+            assert!(windows.push("".to_owned(), ts, message(ts)).is_none());
+        }
+        // 水位线为 12 - 5，第一个窗口尚未结束
+        assert!(windows.advance().is_empty());
+
+        assert!(windows.push("".to_owned(), 15, message(15)).is_none());
+        assert_eq!(lens(windows.advance()), vec![2]);
+
+        // 所属窗口已输出，消息迟到
+        assert!(windows.push("".to_owned(), 9, message(9)).is_some());
+        assert_eq!(lens(windows.flush()), vec![2]);
+    }
+
+    #[test]
+    fn test_hopping_and_session() {
+        let mut windows = Windows::new(Assigner::Hopping { size: 10, hop: 5 }, 0, 16);
+        windows.push("".to_owned(), 7, message(7));
+        windows.push("".to_owned(), 21, message(21));
+        // [0, 10) 与 [5, 15) 均已结束
+        assert_eq!(lens(windows.advance()), vec![1, 1]);
+
+        let mut windows = Windows::new(
+            Assigner::Session {
+                timeout: 5,
+                max: 100,
+            },
+            0,
+            16,
+        );
+        for ts in [1, 4, 8, 20] {
+            windows.push("".to_owned(), ts, message(ts));
+        }
+        assert_eq!(lens(windows.advance()), vec![3]);
+        assert_eq!(lens(windows.flush()), vec![1]);
+    }
+
+    #[test]
+    fn test_group_watermark() {
+        let mut windows = Windows::new(Assigner::Tumbling { size: 10 }, 0, 16);
+        assert!(windows.push("a".to_owned(), 100, message(100)).is_none());
+        // 其他分组的事件时间超前不影响本分组
+        assert!(windows.push("b".to_owned(), 5, message(5)).is_none());
+        assert!(windows.advance().is_empty());
+
+        assert!(windows.push("b".to_owned(), 15, message(15)).is_none());
+        assert_eq!(lens(windows.advance()), vec![1]);
+        assert!(windows.push("a".to_owned(), 3, message(3)).is_some());
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        for assigner in [
+            Assigner::Tumbling { size: 10 },
+            Assigner::Hopping { size: 10, hop: 5 },
+        ] {
+            let mut windows = Windows::new(assigner, 0, 16);
+            let ts = i64::MIN + 1;
+            // 所属窗口的起始时间超出范围，丢弃而非视为迟到
+            assert!(windows.push("".to_owned(), ts, message(ts)).is_none());
+            assert!(windows.flush().is_empty());
+        }
+
+        let mut windows = Windows::new(
+            Assigner::Session {
+                timeout: 5,
+                max: 100,
+            },
+            0,
+            16,
+        );
+        for ts in [i64::MIN, i64::MAX] {
+            assert!(windows.push("".to_owned(), ts, message(ts)).is_none());
+        }
+        assert_eq!(lens(windows.flush()), vec![1, 1]);
+    }
+}
@@ -1,12 +1,20 @@
+use std::collections::HashMap;
+
 use anyhow::{bail, Result};
-use message::RuleMessageBatch;
+use futures::stream::SelectAll;
+use message::{MessageBatch, RuleMessageBatch};
 use tokio::sync::{
     broadcast,
     mpsc::{UnboundedReceiver, UnboundedSender},
 };
-use types::rules::functions::window::Conf;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{error, warn};
+use types::rules::functions::window::{Conf, Type};
+
+use super::group_by::Grouper;
 
 mod count;
+mod event_time;
 mod time_hopping;
 mod time_session;
 mod time_sliding;
@@ -18,22 +26,96 @@ pub fn run(
     txs: Vec<UnboundedSender<RuleMessageBatch>>,
     stop_signal_rx: broadcast::Receiver<()>,
 ) -> Result<()> {
+    let grouper = Grouper::new(conf.group_by, conf.max_groups);
+    if let Some(event_time) = conf.event_time {
+        let assigner = match (
+            conf.typ,
+            conf.time_thmbling,
+            conf.time_hopping,
+            conf.time_session,
+        ) {
+            (Type::TimeThmbling, Some(time_thmbling), _, _) => event_time::Assigner::Tumbling {
+                size: time_thmbling.interval as i64,
+            },
+            (Type::TimeHopping, _, Some(time_hopping), _) => event_time::Assigner::Hopping {
+                size: time_hopping.interval as i64,
+                hop: time_hopping.hopping as i64,
+            },
+            (Type::TimeSession, _, _, Some(time_session)) => event_time::Assigner::Session {
+                timeout: time_session.timeout as i64,
+                max: time_session.max as i64,
+            },
+            (Type::Count, _, _, _) => bail!("计数窗口不支持事件时间"),
+            (typ, _, _, _) => bail!("{:?} is required", typ),
+        };
+        assigner.validate()?;
+        return event_time::run(event_time, assigner, grouper, rxs, txs, stop_signal_rx);
+    }
+
     match conf.typ {
         types::rules::functions::window::Type::TimeThmbling => match conf.time_thmbling {
-            Some(time_thmbling) => time_thmbling::run(time_thmbling, rxs, txs, stop_signal_rx),
+            Some(time_thmbling) => {
+                time_thmbling::run(time_thmbling, grouper, rxs, txs, stop_signal_rx)
+            }
             None => bail!("time_thmbling is required"),
         },
         types::rules::functions::window::Type::TimeHopping => match conf.time_hopping {
-            Some(time_hopping) => time_hopping::run(time_hopping, rxs, txs, stop_signal_rx),
+            Some(time_hopping) => {
+                time_hopping::run(time_hopping, grouper, rxs, txs, stop_signal_rx)
+            }
             None => bail!("time_hopping is required"),
         },
         types::rules::functions::window::Type::TimeSession => match conf.time_session {
-            Some(time_session) => time_session::run(time_session, rxs, txs, stop_signal_rx),
+            Some(time_session) => {
+                time_session::run(time_session, grouper, rxs, txs, stop_signal_rx)
+            }
             None => bail!("time_session is required"),
         },
         types::rules::functions::window::Type::Count => match conf.count {
-            Some(count) => count::run(count, rxs, txs, stop_signal_rx),
+            Some(count) => count::run(count, grouper, rxs, txs, stop_signal_rx),
             None => bail!("count is required"),
         },
     }
 }
+
+fn select_all(
+    rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
+) -> SelectAll<UnboundedReceiverStream<RuleMessageBatch>> {
+    futures::stream::select_all(rxs.into_iter().map(UnboundedReceiverStream::new))
+}
+
+// 获取分组的窗口状态，分组数达到上限时新分组的消息被丢弃
+fn group_state<T: Default>(
+    states: &mut HashMap<String, T>,
+    key: String,
+    max_groups: usize,
+) -> Option<&mut T> {
+    if !states.contains_key(&key) && states.len() >= max_groups {
+        warn!("窗口分组数超出上限 {}，丢弃新分组的消息", max_groups);
+        return None;
+    }
+    Some(states.entry(key).or_default())
+}
+
+fn send_rule_message(txs: &[UnboundedSender<RuleMessageBatch>], mb: MessageBatch) {
+    if mb.len() == 0 {
+        return;
+    }
+
+    match txs.len() {
+        0 => unreachable!(),
+        1 => {
+            if let Err(e) = txs[0].send(RuleMessageBatch::Owned(mb)) {
+                error!("send rule message error: {}", e);
+            }
+        }
+        _ => {
+            let rmb = RuleMessageBatch::new_by_len(txs.len(), mb);
+            txs.iter().for_each(|tx| {
+                if let Err(e) = tx.send(rmb.clone()) {
+                    error!("send rule message error: {}", e);
+                }
+            });
+        }
+    }
+}
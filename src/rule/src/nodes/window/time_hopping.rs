@@ -1,4 +1,7 @@
-use std::{collections::VecDeque, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
 
 use anyhow::Result;
 use common::timestamp_millis;
@@ -11,102 +14,76 @@ use tokio::{
     },
     time::{self, Instant},
 };
-use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
-use tracing::error;
+use tokio_stream::StreamExt;
 use types::rules::functions::window::TimeHopping;
 
+use crate::nodes::group_by::Grouper;
+
+// 每隔hopping输出一次各分组最近interval内收到的消息
 pub fn run(
     conf: TimeHopping,
-    mut rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
+    grouper: Grouper,
+    rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
     txs: Vec<UnboundedSender<RuleMessageBatch>>,
     mut stop_signal_rx: Receiver<()>,
 ) -> Result<()> {
-    let mut mbs = VecDeque::new();
-    let start = Instant::now()
-        .checked_add(Duration::from_micros(conf.interval))
-        .unwrap();
-    let mut interval = time::interval_at(start, Duration::from_micros(conf.interval));
-
-    if rxs.len() == 1 {
-        tokio::spawn(async move {
-            loop {
-                select! {
-                    Some(rmb) = rxs[0].recv() => {
-                        mbs.push_back((timestamp_millis(), rmb.take_mb()));
-                    }
-
-                    _ = interval.tick() => {
-                        send_rule_message(conf.hopping, &txs, &mut mbs);
-                    }
+    let mut stream = super::select_all(rxs);
 
-                    _ = stop_signal_rx.recv() => {
-                        return
+    tokio::spawn(async move {
+        // 每个分组按接收时间排列的消息
+        let mut groups: HashMap<String, VecDeque<(u64, MessageBatch)>> = HashMap::new();
+        let start = Instant::now()
+            .checked_add(Duration::from_millis(conf.hopping))
+            .unwrap();
+        let mut interval = time::interval_at(start, Duration::from_millis(conf.hopping));
+        loop {
+            select! {
+                Some(rmb) = stream.next() => {
+                    let now = timestamp_millis();
+                    for group in grouper.split(rmb.take_mb()) {
+                        if let Some(mbs) = super::group_state(&mut groups, group.key, grouper.max_groups) {
+                            mbs.push_back((now, group.mb));
+                        }
                     }
                 }
-            }
-        });
-    } else {
-        let streams: Vec<_> = rxs
-            .into_iter()
-            .map(|rx| UnboundedReceiverStream::new(rx))
-            .collect();
-        let mut stream = futures::stream::select_all(streams);
 
-        tokio::spawn(async move {
-            loop {
-                select! {
-                    Some(rmb) = stream.next() => {
-                        mbs.push_back((timestamp_millis(), rmb.take_mb()));
-                    }
-
-                    _ = interval.tick() => {
-                        send_rule_message(conf.hopping, &txs, &mut mbs);
-                    }
+                _ = interval.tick() => {
+                    send_rule_message(conf.interval, &txs, &mut groups);
+                }
 
-                    _ = stop_signal_rx.recv() => {
-                        return
-                    }
+                _ = stop_signal_rx.recv() => {
+                    return
                 }
             }
-        });
-    }
+        }
+    });
 
     Ok(())
 }
 
 fn send_rule_message(
-    hopping: u64,
-    txs: &Vec<UnboundedSender<RuleMessageBatch>>,
-    mbs: &mut VecDeque<(u64, MessageBatch)>,
+    interval: u64,
+    txs: &[UnboundedSender<RuleMessageBatch>],
+    groups: &mut HashMap<String, VecDeque<(u64, MessageBatch)>>,
 ) {
-    let mut send_mb = MessageBatch::default();
-    loop {
-        if let Some((ts, mb)) = mbs.front() {
-            if timestamp_millis() - ts > hopping {
-                send_mb.extend(mbs.pop_front().unwrap().1);
+    let now = timestamp_millis();
+    for mbs in groups.values_mut() {
+        // 移除已滑出窗口的消息
+        while let Some((ts, _)) = mbs.front() {
+            if now.saturating_sub(*ts) >= interval {
+                mbs.pop_front();
             } else {
-                send_mb.extend(mb.clone());
+                break;
             }
-        } else {
-            break;
         }
-    }
 
-    match txs.len() {
-        0 => unreachable!(),
-        1 => {
-            let mb = RuleMessageBatch::Owned(send_mb);
-            if let Err(e) = txs[0].send(mb) {
-                error!("send rule message error: {}", e);
-            }
-        }
-        _ => {
-            let mb = RuleMessageBatch::Arc(Arc::new(send_mb));
-            txs.iter().for_each(|tx| {
-                if let Err(e) = tx.send(mb.clone()) {
-                    error!("send rule message error: {}", e);
-                }
-            });
+        let mut send_mb = MessageBatch::default();
+        for (_, mb) in mbs.iter() {
+            send_mb.extend(mb.clone());
         }
+        super::send_rule_message(txs, send_mb);
     }
+
+    // 没有消息的分组不再占用分组数
+    groups.retain(|_, mbs| !mbs.is_empty());
 }
@@ -1,4 +1,4 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, time::Duration};
 
 use anyhow::Result;
 use message::{MessageBatch, RuleMessageBatch};
@@ -10,49 +10,83 @@ use tokio::{
     },
     time::{self, Instant},
 };
-use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
-use tracing::error;
+use tokio_stream::StreamExt;
 use types::rules::functions::window::TimeSession;
 
+use crate::nodes::group_by::Grouper;
+
+struct Session {
+    start: Instant,
+    last: Instant,
+    mb: MessageBatch,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            last: now,
+            mb: MessageBatch::default(),
+        }
+    }
+}
+
+impl Session {
+    // 超过timeout未收到消息，或会话时长达到max时结束
+    fn deadline(&self, timeout: Duration, max: Duration) -> Instant {
+        (self.last + timeout).min(self.start + max)
+    }
+}
+
 pub fn run(
     conf: TimeSession,
+    grouper: Grouper,
     rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
     txs: Vec<UnboundedSender<RuleMessageBatch>>,
     mut stop_signal_rx: Receiver<()>,
 ) -> Result<()> {
-    let streams: Vec<_> = rxs
-        .into_iter()
-        .map(|rx| UnboundedReceiverStream::new(rx))
-        .collect();
-
-    let mut stream = futures::stream::select_all(streams);
+    let mut stream = super::select_all(rxs);
+    let timeout = Duration::from_millis(conf.timeout);
+    let max = Duration::from_millis(conf.max);
 
     tokio::spawn(async move {
-        let mut mbs = vec![];
+        // 每个分组各自维护会话
+        let mut sessions: HashMap<String, Session> = HashMap::new();
         loop {
-            let timeout = time::sleep(Duration::from_micros(conf.timeout));
-            let mut empty = true;
-            tokio::pin!(timeout);
-            let max = time::sleep(Duration::from_micros(conf.max));
-            tokio::pin!(max);
+            let deadline = sessions
+                .values()
+                .map(|session| session.deadline(timeout, max))
+                .min();
+            let sleep = async {
+                match deadline {
+                    Some(deadline) => time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
             select! {
                 Some(rmb) = stream.next() => {
-                    if empty {
-                        empty = false;
-                        max.as_mut().reset(Instant::now() + Duration::from_micros(conf.max));
+                    for group in grouper.split(rmb.take_mb()) {
+                        if let Some(session) = super::group_state(&mut sessions, group.key, grouper.max_groups) {
+                            session.last = Instant::now();
+                            session.mb.extend(group.mb);
+                        }
                     }
-                    mbs.push(rmb.take_mb());
-                    timeout.as_mut().reset(Instant::now() + Duration::from_micros(conf.timeout));
                 }
 
-                _ = &mut timeout =>  {
-                    send_rule_message(conf.timeout, &txs, &mut mbs);
-                    empty = true;
-                }
-
-                _ = &mut max => {
-                    send_rule_message(conf.max, &txs, &mut mbs);
-                    empty = true;
+                _ = sleep => {
+                    let now = Instant::now();
+                    let keys: Vec<_> = sessions
+                        .iter()
+                        .filter(|(_, session)| session.deadline(timeout, max) <= now)
+                        .map(|(key, _)| key.clone())
+                        .collect();
+                    for key in keys {
+                        if let Some(session) = sessions.remove(&key) {
+                            super::send_rule_message(&txs, session.mb);
+                        }
+                    }
                 }
 
                 _ = stop_signal_rx.recv() => {
@@ -64,32 +98,3 @@ pub fn run(
 
     Ok(())
 }
-
-fn send_rule_message(
-    hopping: u64,
-    txs: &Vec<UnboundedSender<RuleMessageBatch>>,
-    mbs: &mut Vec<MessageBatch>,
-) {
-    let mut send_mb = MessageBatch::default();
-    for mb in mbs.iter() {
-        send_mb.extend(mb.clone());
-    }
-
-    match txs.len() {
-        0 => unreachable!(),
-        1 => {
-            let mb = RuleMessageBatch::Owned(send_mb);
-            if let Err(e) = txs[0].send(mb) {
-                error!("send rule message error: {}", e);
-            }
-        }
-        _ => {
-            let mb = RuleMessageBatch::Arc(Arc::new(send_mb));
-            txs.iter().for_each(|tx| {
-                if let Err(e) = tx.send(mb.clone()) {
-                    error!("send rule message error: {}", e);
-                }
-            });
-        }
-    }
-}
@@ -1,4 +1,4 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, time::Duration};
 
 use anyhow::Result;
 use message::{MessageBatch, RuleMessageBatch};
@@ -10,38 +10,41 @@ use tokio::{
     },
     time::{self, Instant},
 };
-use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
-use tracing::error;
+use tokio_stream::StreamExt;
 use types::rules::functions::window::TimeThmbling;
 
+use crate::nodes::group_by::Grouper;
+
 pub fn run(
     conf: TimeThmbling,
+    grouper: Grouper,
     rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
     txs: Vec<UnboundedSender<RuleMessageBatch>>,
     mut stop_signal_rx: Receiver<()>,
 ) -> Result<()> {
-    let streams: Vec<_> = rxs
-        .into_iter()
-        .map(|rx| UnboundedReceiverStream::new(rx))
-        .collect();
-
-    let mut stream = futures::stream::select_all(streams);
+    let mut stream = super::select_all(rxs);
 
     tokio::spawn(async move {
-        let mut mb = MessageBatch::default();
+        // 每个分组在窗口内收到的消息
+        let mut groups: HashMap<String, MessageBatch> = HashMap::new();
         let start = Instant::now()
-            .checked_add(Duration::from_micros(conf.interval))
+            .checked_add(Duration::from_millis(conf.interval))
             .unwrap();
-        let mut interval = time::interval_at(start, Duration::from_micros(conf.interval));
+        let mut interval = time::interval_at(start, Duration::from_millis(conf.interval));
         loop {
             select! {
                 Some(rmb) = stream.next() => {
-                    mb.extend(rmb.take_mb());
+                    for group in grouper.split(rmb.take_mb()) {
+                        if let Some(mb) = super::group_state(&mut groups, group.key, grouper.max_groups) {
+                            mb.extend(group.mb);
+                        }
+                    }
                 }
 
                 _ = interval.tick() => {
-                    send_rule_message(&txs, mb);
-                    mb = MessageBatch::default();
+                    for (_, mb) in groups.drain() {
+                        super::send_rule_message(&txs, mb);
+                    }
                 }
 
                 _ = stop_signal_rx.recv() => {
@@ -53,23 +56,3 @@ pub fn run(
 
     Ok(())
 }
-
-fn send_rule_message(txs: &Vec<UnboundedSender<RuleMessageBatch>>, mb: MessageBatch) {
-    match txs.len() {
-        0 => unreachable!(),
-        1 => {
-            let mb = RuleMessageBatch::Owned(mb);
-            if let Err(e) = txs[0].send(mb) {
-                error!("send rule message error: {}", e);
-            }
-        }
-        _ => {
-            let mb = RuleMessageBatch::Arc(Arc::new(mb));
-            txs.iter().for_each(|tx| {
-                if let Err(e) = tx.send(mb.clone()) {
-                    error!("send rule message error: {}", e);
-                }
-            });
-        }
-    }
-}
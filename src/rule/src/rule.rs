@@ -4,23 +4,23 @@ use common::{
     error::{HaliaError, HaliaResult},
     log::Logger,
 };
-use message::RuleMessageBatch;
+use message::{MessageValue, RuleMessageBatch};
 use tokio::{
     select,
     sync::{
         broadcast,
-        mpsc::{UnboundedReceiver, UnboundedSender},
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
     },
 };
 use tracing::{debug, error};
 use types::rules::{
-    AppSinkNode, AppSourceNode, Conf, DataboardNode, DeviceSinkNode, DeviceSourceNode, NodeType,
-    ReadRuleNodeResp, ReadRuleResp,
+    AppSinkNode, AppSourceNode, Conf, DataboardNode, DeviceSinkNode, DeviceSourceNode,
+    MqttServerSinkNode, MqttServerSourceNode, NodeType, ReadRuleNodeResp, ReadRuleResp,
 };
 
 use crate::{
     graph::Graph,
-    nodes::{aggregation, computes, filter, merge::merge, window},
+    nodes::{aggregation, computes, deadband, filter, merge::merge, mqtt_server, window},
     segment::{start_segment, BlackHole},
 };
 
@@ -63,12 +63,52 @@ impl Rule {
             let rxs = match node.node_type {
                 NodeType::DeviceSource => {
                     let source_node: DeviceSourceNode = serde_json::from_value(node.conf.clone())?;
-                    devices::get_source_rxs(&source_node.device_id, &source_node.source_id, cnt)
-                        .await?
+                    let rxs = devices::get_source_rxs(
+                        &source_node.device_id,
+                        &source_node.source_id,
+                        cnt,
+                    )
+                    .await?;
+                    insert_source_metadatas(
+                        rxs,
+                        vec![
+                            (
+                                "device_id".to_owned(),
+                                MessageValue::String(source_node.device_id),
+                            ),
+                            (
+                                "source_id".to_owned(),
+                                MessageValue::String(source_node.source_id),
+                            ),
+                        ],
+                        &self.stop_signal_tx,
+                    )
                 }
                 NodeType::AppSource => {
                     let source_node: AppSourceNode = serde_json::from_value(node.conf.clone())?;
-                    apps::get_source_rxs(&source_node.app_id, &source_node.source_id, cnt).await?
+                    let rxs =
+                        apps::get_source_rxs(&source_node.app_id, &source_node.source_id, cnt)
+                            .await?;
+                    insert_source_metadatas(
+                        rxs,
+                        vec![
+                            (
+                                "app_id".to_owned(),
+                                MessageValue::String(source_node.app_id),
+                            ),
+                            (
+                                "source_id".to_owned(),
+                                MessageValue::String(source_node.source_id),
+                            ),
+                        ],
+                        &self.stop_signal_tx,
+                    )
+                }
+                NodeType::MqttServerSource => {
+                    let source_node: MqttServerSourceNode =
+                        serde_json::from_value(node.conf.clone())?;
+                    mqtt_server::get_source_rxs(source_node, cnt, self.stop_signal_tx.subscribe())
+                        .await?
                 }
                 _ => return Err(HaliaError::Common(format!("{:?} 不是源节点", node))),
             };
@@ -104,6 +144,11 @@ impl Rule {
                     black_hole.run(self.stop_signal_tx.subscribe());
                     txs
                 }
+                NodeType::MqttServerSink => {
+                    let sink_node: MqttServerSinkNode = serde_json::from_value(node.conf.clone())?;
+                    mqtt_server::get_sink_txs(sink_node, cnt, self.stop_signal_tx.subscribe())
+                        .await?
+                }
 
                 _ => unreachable!(),
             };
@@ -149,6 +194,12 @@ impl Rule {
                         functions.push(aggregation::new(conf)?);
                         indexes.push(index);
                     }
+                    NodeType::Deadband => {
+                        let conf: types::rules::functions::deadband::Conf =
+                            serde_json::from_value(node.conf.clone())?;
+                        functions.push(deadband::new(conf)?);
+                        indexes.push(index);
+                    }
                     _ => {
                         debug!("{:?}", node);
                     }
@@ -300,6 +351,21 @@ impl Rule {
                     node_type: NodeType::Aggregation,
                     data: Some(node.conf),
                 }),
+                NodeType::Deadband => nodes.push(ReadRuleNodeResp {
+                    index: node.index,
+                    node_type: NodeType::Deadband,
+                    data: Some(node.conf),
+                }),
+                NodeType::MqttServerSource => nodes.push(ReadRuleNodeResp {
+                    index: node.index,
+                    node_type: NodeType::MqttServerSource,
+                    data: Some(node.conf),
+                }),
+                NodeType::MqttServerSink => nodes.push(ReadRuleNodeResp {
+                    index: node.index,
+                    node_type: NodeType::MqttServerSink,
+                    data: Some(node.conf),
+                }),
             }
         }
 
@@ -354,3 +420,36 @@ fn run_direct_link(
         }
     });
 }
+
+// 为源输出的消息写入来源的元数据，供窗口与聚合按来源分组
+fn insert_source_metadatas(
+    rxs: Vec<UnboundedReceiver<RuleMessageBatch>>,
+    metadatas: Vec<(String, MessageValue)>,
+    stop_signal_tx: &broadcast::Sender<()>,
+) -> Vec<UnboundedReceiver<RuleMessageBatch>> {
+    rxs.into_iter()
+        .map(|mut rx| {
+            let (tx, new_rx) = mpsc::unbounded_channel();
+            let metadatas = metadatas.clone();
+            let mut stop_signal_rx = stop_signal_tx.subscribe();
+            tokio::spawn(async move {
+                loop {
+                    select! {
+                        Some(rmb) = rx.recv() => {
+                            let mut mb = rmb.take_mb();
+                            for message in mb.get_messages_mut() {
+                                message.insert_metadatas(metadatas.clone());
+                            }
+                            _ = tx.send(RuleMessageBatch::Owned(mb));
+                        }
+
+                        _ = stop_signal_rx.recv() => {
+                            return
+                        }
+                    }
+                }
+            });
+            new_rx
+        })
+        .collect()
+}
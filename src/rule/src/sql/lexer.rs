@@ -0,0 +1,203 @@
+use super::{Span, SqlError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenKind {
+    // 未加引号的标识符同时用作关键字，quoted为true时不会被识别为关键字
+    Ident { value: String, quoted: bool },
+    Number(String),
+    String(String),
+    Comma,
+    Dot,
+    LParen,
+    RParen,
+    Star,
+    Minus,
+    Eq,
+    Neq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    // 不区分大小写匹配关键字
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        match &self.kind {
+            TokenKind::Ident {
+                value,
+                quoted: false,
+            } => value.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+}
+
+pub(crate) fn tokenize(sql: &str) -> Result<Vec<Token>, SqlError> {
+    let mut tokens = vec![];
+    let mut chars = sql.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        // 行注释
+        if c == '-' && sql[start..].starts_with("--") {
+            while let Some(&(_, c)) = chars.peek() {
+                if c == '\n' {
+                    break;
+                }
+                chars.next();
+            }
+            continue;
+        }
+
+        let kind = match c {
+            ',' => single(&mut chars, TokenKind::Comma),
+            '.' => single(&mut chars, TokenKind::Dot),
+            '(' => single(&mut chars, TokenKind::LParen),
+            ')' => single(&mut chars, TokenKind::RParen),
+            '*' => single(&mut chars, TokenKind::Star),
+            '-' => single(&mut chars, TokenKind::Minus),
+            '=' => {
+                chars.next();
+                // 兼容 ==
+                let _ = chars.next_if(|&(_, c)| c == '=');
+                TokenKind::Eq
+            }
+            '!' => {
+                chars.next();
+                match chars.next_if(|&(_, c)| c == '=') {
+                    Some(_) => TokenKind::Neq,
+                    None => {
+                        return Err(SqlError::new(
+                            Span::new(start, start + 1),
+                            "无法识别的字符 !，是否应为 !=",
+                        ))
+                    }
+                }
+            }
+            '>' => {
+                chars.next();
+                match chars.next_if(|&(_, c)| c == '=') {
+                    Some(_) => TokenKind::Gte,
+                    None => TokenKind::Gt,
+                }
+            }
+            '<' => {
+                chars.next();
+                match chars.next_if(|&(_, c)| c == '=' || c == '>') {
+                    Some((_, '=')) => TokenKind::Lte,
+                    Some(_) => TokenKind::Neq,
+                    None => TokenKind::Lt,
+                }
+            }
+            '\'' => TokenKind::String(quoted(sql, &mut chars, '\'')?),
+            '"' | '`' => TokenKind::Ident {
+                value: quoted(sql, &mut chars, c)?,
+                quoted: true,
+            },
+            c if c.is_ascii_digit() => {
+                let mut end = start;
+                let mut seen_dot = false;
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_ascii_digit() {
+                        chars.next();
+                    } else if c == '.'
+                        && !seen_dot
+                        && sql[i + 1..].starts_with(|c: char| c.is_ascii_digit())
+                    {
+                        seen_dot = true;
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                }
+                TokenKind::Number(sql[start..end].to_owned())
+            }
+            c if is_ident_start(c) => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !is_ident_part(c) {
+                        break;
+                    }
+                    chars.next();
+                    end = i + c.len_utf8();
+                }
+                TokenKind::Ident {
+                    value: sql[start..end].to_owned(),
+                    quoted: false,
+                }
+            }
+            c => {
+                return Err(SqlError::new(
+                    Span::new(start, start + c.len_utf8()),
+                    format!("无法识别的字符 {}", c),
+                ))
+            }
+        };
+
+        let end = chars.peek().map(|&(i, _)| i).unwrap_or(sql.len());
+        tokens.push(Token {
+            kind,
+            span: Span::new(start, end),
+        });
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        span: Span::new(sql.len(), sql.len()),
+    });
+    Ok(tokens)
+}
+
+fn single(chars: &mut std::iter::Peekable<std::str::CharIndices>, kind: TokenKind) -> TokenKind {
+    chars.next();
+    kind
+}
+
+// 引号内连续两个引号表示引号本身
+fn quoted(
+    sql: &str,
+    chars: &mut std::iter::Peekable<std::str::CharIndices>,
+    quote: char,
+) -> Result<String, SqlError> {
+    let (start, _) = chars.next().unwrap();
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some((_, c)) if c == quote => {
+                if chars.next_if(|&(_, c)| c == quote).is_some() {
+                    value.push(quote);
+                } else {
+                    return Ok(value);
+                }
+            }
+            Some((_, c)) => value.push(c),
+            None => {
+                return Err(SqlError::new(
+                    Span::new(start, sql.len()),
+                    format!("缺少结束的 {}", quote),
+                ))
+            }
+        }
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
+
+fn is_ident_part(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
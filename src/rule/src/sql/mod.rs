@@ -0,0 +1,582 @@
+//! 流式SQL规则，编译为规则的节点与边
+//!
+//! ```sql
+//! SELECT line, avg(temp) AS t, max(humi) FROM device.source
+//! WHERE temp > 0 AND (status = 'on' OR force = true)
+//! GROUP BY line, META(device_id), TUMBLINGWINDOW(ss, 10)
+//! INTO app.sink
+//! ```
+//!
+//! GROUP BY 中除窗口外的字段为分组依据，META(name) 按元数据分组，窗口与聚合按分组各自输出。
+//!
+//! 编译结果为一条链：源 -> 合并(多个源时) -> 过滤 -> 计算 -> 窗口 -> 聚合 -> 动作。
+//! 过滤节点内各条件为或的关系，WHERE会被展开为合取范式，每个子句对应一个过滤节点。
+//! SELECT中的普通字段不做裁剪，仅函数会生成计算或聚合节点。
+
+use common::error::{HaliaError, HaliaResult};
+use parser::{CompareOp, Expr, ObjectName, SelectItem, Statement};
+use serde_json::{json, Value};
+use types::rules::{
+    functions::{self, aggregation, filter, GroupByType},
+    AppSinkNode, AppSourceNode, Conf, DeviceSinkNode, DeviceSourceNode, Edge, Node, NodeType,
+};
+
+mod lexer;
+mod parser;
+
+// WHERE展开为合取范式后的子句上限，即过滤节点数量上限
+const MAX_FILTER_CLAUSES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Span {
+    // 字节偏移
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SqlError {
+    pub span: Span,
+    pub msg: String,
+}
+
+impl SqlError {
+    pub fn new(span: Span, msg: impl Into<String>) -> Self {
+        Self {
+            span,
+            msg: msg.into(),
+        }
+    }
+
+    // 输出错误所在的行列，并在该行下方用^标出出错的片段
+    fn render(&self, sql: &str) -> String {
+        let start = self.span.start.min(sql.len());
+        let line_start = sql[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = sql[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or(sql.len());
+        let line_no = sql[..start].matches('\n').count() + 1;
+        let column = sql[line_start..start].chars().count() + 1;
+        let width = sql[start..self.span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        format!(
+            "SQL第{}行第{}列：{}\n{}\n{}{}",
+            line_no,
+            column,
+            self.msg,
+            &sql[line_start..line_end],
+            " ".repeat(column - 1),
+            "^".repeat(width)
+        )
+    }
+
+    fn into_halia_error(self, sql: &str) -> HaliaError {
+        HaliaError::Common(self.render(sql))
+    }
+}
+
+/// 解析SQL，并按名称查找设备或应用下的源与动作，生成规则配置
+pub async fn compile(sql: &str) -> HaliaResult<Conf> {
+    let stmt = lexer::tokenize(sql)
+        .and_then(parser::parse)
+        .map_err(|e| e.into_halia_error(sql))?;
+
+    let mut sources = Vec::with_capacity(stmt.sources.len());
+    for name in &stmt.sources {
+        sources.push(resolve(sql, name, true).await?);
+    }
+    let mut sinks = Vec::with_capacity(stmt.sinks.len());
+    for name in &stmt.sinks {
+        sinks.push(resolve(sql, name, false).await?);
+    }
+
+    build(stmt, sources, sinks).map_err(|e| e.into_halia_error(sql))
+}
+
+// 先按设备查找，设备不存在或其下没有同名源/动作时再按应用查找
+async fn resolve(sql: &str, name: &ObjectName, is_source: bool) -> HaliaResult<(NodeType, Value)> {
+    let parent = &name.parent.value;
+    let child = &name.name.value;
+
+    let device_id = storage::device::device::read_id_by_name(parent).await?;
+    if let Some(device_id) = &device_id {
+        if is_source {
+            if let Some(source_id) =
+                storage::device::source_sink::read_source_id_by_name(device_id, child).await?
+            {
+                let node = DeviceSourceNode {
+                    device_id: device_id.clone(),
+                    source_id,
+                };
+                return Ok((NodeType::DeviceSource, serde_json::to_value(node)?));
+            }
+        } else if let Some(sink_id) =
+            storage::device::source_sink::read_sink_id_by_name(device_id, child).await?
+        {
+            let node = DeviceSinkNode {
+                device_id: device_id.clone(),
+                sink_id,
+            };
+            return Ok((NodeType::DeviceSink, serde_json::to_value(node)?));
+        }
+    }
+
+    let app_id = storage::app::read_id_by_name(parent).await?;
+    if let Some(app_id) = &app_id {
+        if is_source {
+            if let Some(source_id) =
+                storage::app::source_sink::read_source_id_by_name(app_id, child).await?
+            {
+                let node = AppSourceNode {
+                    app_id: app_id.clone(),
+                    source_id,
+                };
+                return Ok((NodeType::AppSource, serde_json::to_value(node)?));
+            }
+        } else if let Some(sink_id) =
+            storage::app::source_sink::read_sink_id_by_name(app_id, child).await?
+        {
+            let node = AppSinkNode {
+                app_id: app_id.clone(),
+                sink_id,
+            };
+            return Ok((NodeType::AppSink, serde_json::to_value(node)?));
+        }
+    }
+
+    let kind = if is_source { "源" } else { "动作" };
+    let err = match (device_id, app_id) {
+        (None, None) => SqlError::new(name.parent.span, format!("设备或应用 {} 不存在", parent)),
+        _ => SqlError::new(
+            name.name.span,
+            format!("{} 下不存在名为 {} 的{}", parent, child, kind),
+        ),
+    };
+    Err(err.into_halia_error(sql))
+}
+
+#[derive(Default)]
+struct GraphBuilder {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    // 当前链路末端的节点
+    tails: Vec<usize>,
+}
+
+impl GraphBuilder {
+    fn add(&mut self, node_type: NodeType, conf: Value) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            index,
+            node_type,
+            conf,
+        });
+        for source in &self.tails {
+            self.edges.push(Edge {
+                source: *source,
+                target: index,
+            });
+        }
+        index
+    }
+
+    fn chain(&mut self, node_type: NodeType, conf: Value) {
+        let index = self.add(node_type, conf);
+        self.tails = vec![index];
+    }
+}
+
+fn build(
+    stmt: Statement,
+    sources: Vec<(NodeType, Value)>,
+    sinks: Vec<(NodeType, Value)>,
+) -> Result<Conf, SqlError> {
+    let (computer_items, aggregation_items) = compile_select(&stmt)?;
+
+    let mut builder = GraphBuilder::default();
+    let mut source_indexes = vec![];
+    for (node_type, conf) in sources {
+        source_indexes.push(builder.add(node_type, conf));
+    }
+    builder.tails = source_indexes;
+    if builder.tails.len() > 1 {
+        builder.chain(NodeType::Merge, json!({}));
+    }
+
+    if let Some(condition) = &stmt.condition {
+        for clause in to_cnf(condition, false)? {
+            let items = clause.into_iter().map(to_filter_item).collect();
+            builder.chain(
+                NodeType::Filter,
+                to_value(filter::Conf { items, expr: None }),
+            );
+        }
+    }
+
+    if !computer_items.is_empty() {
+        builder.chain(
+            NodeType::Computer,
+            to_value(functions::Conf {
+                items: computer_items,
+            }),
+        );
+    }
+
+    let mut group_by = vec![];
+    if let Some(window) = stmt.window {
+        group_by = window.group_by.clone();
+        builder.chain(NodeType::Window, to_value(window));
+    }
+
+    if !aggregation_items.is_empty() {
+        builder.chain(
+            NodeType::Aggregation,
+            to_value(aggregation::Conf {
+                items: aggregation_items,
+                group_by,
+                max_groups: None,
+            }),
+        );
+    }
+
+    for (node_type, conf) in sinks {
+        builder.add(node_type, conf);
+    }
+
+    Ok(Conf {
+        nodes: builder.nodes,
+        edges: builder.edges,
+    })
+}
+
+fn to_value<T: serde::Serialize>(conf: T) -> Value {
+    // 配置结构体均可序列化
+    serde_json::to_value(conf).unwrap()
+}
+
+fn compile_select(
+    stmt: &Statement,
+) -> Result<(Vec<functions::ItemConf>, Vec<aggregation::ItemConf>), SqlError> {
+    let mut computer_items = vec![];
+    let mut aggregation_items = vec![];
+    // 聚合查询中，非聚合且不在 GROUP BY 中的字段没有意义
+    let mut plain_spans = vec![];
+    let group_fields: Vec<&String> = match &stmt.window {
+        Some(window) => window
+            .group_by
+            .iter()
+            .filter(|group_by| group_by.typ == GroupByType::Field)
+            .map(|group_by| &group_by.name)
+            .collect(),
+        None => vec![],
+    };
+
+    for item in &stmt.items {
+        match item {
+            SelectItem::Wildcard(span) => plain_spans.push(*span),
+            SelectItem::Field { field, alias } => {
+                if let Some(alias) = alias {
+                    return Err(SqlError::new(alias.span, "仅函数的结果支持 AS 别名"));
+                }
+                if !group_fields.contains(&&field.value) {
+                    plain_spans.push(field.span);
+                }
+            }
+            SelectItem::Function {
+                func,
+                field,
+                alias,
+                span,
+            } => {
+                let name = func.value.to_ascii_lowercase();
+                let target_field = alias.as_ref().map(|alias| alias.value.clone());
+                if let Some(typ) = aggregation_type(&name) {
+                    if stmt.window.is_none() {
+                        return Err(SqlError::new(*span, "聚合函数需要配合 GROUP BY 窗口使用"));
+                    }
+                    aggregation_items.push(aggregation::ItemConf {
+                        typ,
+                        field: field.value.clone(),
+                        target_field,
+                        args: None,
+                    });
+                } else if let Some(typ) = computer_type(&name) {
+                    let mut args = std::collections::HashMap::new();
+                    args.insert("field".to_owned(), Value::String(field.value.clone()));
+                    if let Some(target_field) = target_field {
+                        args.insert("target_field".to_owned(), Value::String(target_field));
+                    }
+                    computer_items.push((functions::ItemConf { typ, args }, *span));
+                } else {
+                    return Err(SqlError::new(
+                        func.span,
+                        format!("不支持的函数 {}", func.value),
+                    ));
+                }
+            }
+        }
+    }
+
+    if !aggregation_items.is_empty() {
+        if let Some(span) = plain_spans.first() {
+            return Err(SqlError::new(
+                *span,
+                "聚合查询中的字段必须在聚合函数或 GROUP BY 中使用",
+            ));
+        }
+        if let Some((_, span)) = computer_items.first() {
+            return Err(SqlError::new(*span, "聚合查询中不支持与非聚合函数混用"));
+        }
+    }
+
+    Ok((
+        computer_items.into_iter().map(|(item, _)| item).collect(),
+        aggregation_items,
+    ))
+}
+
+fn aggregation_type(name: &str) -> Option<aggregation::Type> {
+    let typ = match name {
+        "sum" => aggregation::Type::Sum,
+        "avg" => aggregation::Type::Avg,
+        "max" => aggregation::Type::Max,
+        "min" => aggregation::Type::Min,
+        "count" => aggregation::Type::Count,
+        "collect" => aggregation::Type::Collect,
+        "deduplicate" => aggregation::Type::Deduplicate,
+        _ => return None,
+    };
+    Some(typ)
+}
+
+// 仅支持参数为单个字段的计算函数
+fn computer_type(name: &str) -> Option<functions::Type> {
+    let typ = match name {
+        "abs" => functions::Type::NumberAbs,
+        "cbrt" => functions::Type::NumberCbrt,
+        "ceil" => functions::Type::NumberCeil,
+        "exp" => functions::Type::NumberExp,
+        "floor" => functions::Type::NumberFloor,
+        "ln" => functions::Type::NumberLn,
+        "round" => functions::Type::NumberRound,
+        "sign" => functions::Type::NumberSgn,
+        "length" => functions::Type::StringLength,
+        "lower" => functions::Type::StringLower,
+        "upper" => functions::Type::StringUpper,
+        "trim" => functions::Type::StringTrim,
+        "to_bool" => functions::Type::TypeConversionBool,
+        "to_float" => functions::Type::TypeConversionFloat,
+        "to_int" => functions::Type::TypeConversionInt,
+        "to_string" => functions::Type::TypeConversionStr,
+        _ => return None,
+    };
+    Some(typ)
+}
+
+// 展开为合取范式，外层为与，内层为或，negate表示对表达式取反
+// 解析时已限制条件的嵌套层数不超过 parser::MAX_DEPTH，递归深度有界
+fn to_cnf(expr: &Expr, negate: bool) -> Result<Vec<Vec<parser::Compare>>, SqlError> {
+    match expr {
+        Expr::Compare(compare) => {
+            let mut compare = compare.clone();
+            if negate {
+                compare.op = match compare.op {
+                    CompareOp::Eq => CompareOp::Neq,
+                    CompareOp::Neq => CompareOp::Eq,
+                    CompareOp::Gt => CompareOp::Lte,
+                    CompareOp::Gte => CompareOp::Lt,
+                    CompareOp::Lt => CompareOp::Gte,
+                    CompareOp::Lte => CompareOp::Gt,
+                    CompareOp::Contains | CompareOp::Regexp => {
+                        return Err(SqlError::new(compare.span, "CONTAINS/REGEXP 不支持取反"))
+                    }
+                };
+            }
+            Ok(vec![vec![compare]])
+        }
+        Expr::Not(expr) => to_cnf(expr, !negate),
+        Expr::And(left, right, span) => combine(left, right, *span, negate, !negate),
+        Expr::Or(left, right, span) => combine(left, right, *span, negate, negate),
+    }
+}
+
+// conjunction为true时两侧子句直接合并，否则需要两两做或
+fn combine(
+    left: &Expr,
+    right: &Expr,
+    span: Span,
+    negate: bool,
+    conjunction: bool,
+) -> Result<Vec<Vec<parser::Compare>>, SqlError> {
+    let mut left = to_cnf(left, negate)?;
+    let right = to_cnf(right, negate)?;
+    let clauses = if conjunction {
+        left.extend(right);
+        left
+    } else {
+        let mut clauses = Vec::with_capacity(left.len() * right.len());
+        for l in &left {
+            for r in &right {
+                clauses.push(l.iter().chain(r.iter()).cloned().collect());
+            }
+        }
+        clauses
+    };
+
+    if clauses.len() > MAX_FILTER_CLAUSES {
+        return Err(SqlError::new(
+            span,
+            format!(
+                "WHERE 条件展开后超过 {} 个过滤节点，请简化条件",
+                MAX_FILTER_CLAUSES
+            ),
+        ));
+    }
+    Ok(clauses)
+}
+
+fn to_filter_item(compare: parser::Compare) -> filter::ItemConf {
+    let typ = match compare.op {
+        CompareOp::Eq => filter::Type::Eq,
+        CompareOp::Neq => filter::Type::Neq,
+        CompareOp::Gt => filter::Type::Gt,
+        CompareOp::Gte => filter::Type::Gte,
+        CompareOp::Lt => filter::Type::Lt,
+        CompareOp::Lte => filter::Type::Lte,
+        CompareOp::Contains => filter::Type::Ct,
+        CompareOp::Regexp => filter::Type::Reg,
+    };
+    filter::ItemConf {
+        typ,
+        field: compare.field,
+        value: compare.value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_sql(sql: &str) -> Result<Conf, String> {
+        let stmt = lexer::tokenize(sql)
+            .and_then(parser::parse)
+            .map_err(|e| e.render(sql))?;
+        let sources = stmt
+            .sources
+            .iter()
+            .map(|_| {
+                (
+                    NodeType::DeviceSource,
+                    json!({"device_id": "d", "source_id": "s"}),
+                )
+            })
+            .collect();
+        let sinks = stmt
+            .sinks
+            .iter()
+            .map(|_| (NodeType::AppSink, json!({"app_id": "a", "sink_id": "s"})))
+            .collect();
+        build(stmt, sources, sinks).map_err(|e| e.render(sql))
+    }
+
+    #[test]
+    fn test_build() {
+        let conf = build_sql(
+            "SELECT line, avg(temp) AS t, max(humi) FROM d.s, d.s2 \
+             GROUP BY line, META(device_id), TUMBLINGWINDOW(ss, 10) \
+             WHERE temp > 0 AND NOT (status = 'off' AND 10 <= humi) \
+             INTO a.s",
+        )
+        .unwrap();
+
+        let node_types: Vec<_> = conf
+            .nodes
+            .iter()
+            .map(|node| node.node_type.clone())
+            .collect();
+        assert_eq!(
+            node_types,
+            vec![
+                NodeType::DeviceSource,
+                NodeType::DeviceSource,
+                NodeType::Merge,
+                NodeType::Filter,
+                NodeType::Filter,
+                NodeType::Window,
+                NodeType::Aggregation,
+                NodeType::AppSink,
+            ]
+        );
+        assert_eq!(conf.edges.len(), 7);
+
+        assert_eq!(
+            conf.nodes[4].conf,
+            json!({"conf": [
+                {"type": "neq", "field": "status", "value": "off"},
+                {"type": "lt", "field": "humi", "value": 10},
+            ], "expr": null})
+        );
+        let group_by = json!([
+            {"type": "field", "name": "line"},
+            {"type": "metadata", "name": "device_id"},
+        ]);
+        assert_eq!(
+            conf.nodes[5].conf,
+            json!({"type": "time_thmbling", "time_thmbling": {"interval": 10000},
+                "time_hopping": null, "time_session": null, "count": null,
+                "group_by": group_by, "max_groups": null, "event_time": null})
+        );
+        assert_eq!(conf.nodes[6].conf["group_by"], group_by);
+        assert_eq!(
+            conf.nodes[6].conf["conf"][0],
+            json!({"type": "avg", "field": "temp", "target_field": "t", "args": null})
+        );
+    }
+
+    #[test]
+    fn test_error_span() {
+        let err = build_sql("SELECT avg(temp)\nFROM d.s\nINTO a.s").unwrap_err();
+        assert_eq!(
+            err,
+            "SQL第1行第8列：聚合函数需要配合 GROUP BY 窗口使用\nSELECT avg(temp)\n       ^^^^^^^^^"
+        );
+
+        let err = build_sql("SELECT * FROM d.s\nWHERE t >> 1 INTO a.s").unwrap_err();
+        assert!(err.starts_with("SQL第2行第10列"), "{}", err);
+
+        let err =
+            build_sql("SELECT line, avg(temp) FROM d.s GROUP BY META(line) INTO a.s").unwrap_err();
+        assert!(err.contains("GROUP BY 缺少窗口"), "{}", err);
+    }
+
+    #[test]
+    fn test_nested_condition() {
+        let sql = format!(
+            "SELECT * FROM d.s WHERE {}t > 1{} INTO a.s",
+            "(".repeat(100),
+            ")".repeat(100)
+        );
+        build_sql(&sql).unwrap();
+
+        for condition in [
+            format!("{}t > 1{}", "(".repeat(100_000), ")".repeat(100_000)),
+            format!("{}t > 1", "NOT ".repeat(100_000)),
+            vec!["t > 1"; 100_000].join(" OR "),
+            format!("({})", vec!["t > 1"; 100_000].join(" AND ")),
+        ] {
+            let sql = format!("SELECT * FROM d.s WHERE {} INTO a.s", condition);
+            let err = build_sql(&sql).unwrap_err();
+            assert!(err.contains("WHERE 条件嵌套超过 128 层"), "{}", err);
+        }
+    }
+}
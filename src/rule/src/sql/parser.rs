@@ -0,0 +1,585 @@
+use types::rules::functions::{window, GroupBy, GroupByType};
+
+use super::{
+    lexer::{Token, TokenKind},
+    Span, SqlError,
+};
+
+pub(crate) struct Statement {
+    pub items: Vec<SelectItem>,
+    pub sources: Vec<ObjectName>,
+    pub condition: Option<Expr>,
+    pub window: Option<window::Conf>,
+    pub sinks: Vec<ObjectName>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Ident {
+    pub value: String,
+    pub span: Span,
+}
+
+pub(crate) enum SelectItem {
+    Wildcard(Span),
+    Field {
+        field: Ident,
+        alias: Option<Ident>,
+    },
+    Function {
+        func: Ident,
+        field: Ident,
+        alias: Option<Ident>,
+        span: Span,
+    },
+}
+
+// 形如 parent.name，parent为设备或应用名称，name为源或动作名称
+pub(crate) struct ObjectName {
+    pub parent: Ident,
+    pub name: Ident,
+}
+
+#[derive(Debug)]
+pub(crate) enum Expr {
+    And(Box<Expr>, Box<Expr>, Span),
+    Or(Box<Expr>, Box<Expr>, Span),
+    Not(Box<Expr>),
+    Compare(Compare),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Compare {
+    pub field: String,
+    pub op: CompareOp,
+    // 常量或 ${field} 形式的字段引用
+    pub value: serde_json::Value,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CompareOp {
+    Eq,
+    Neq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Contains,
+    Regexp,
+}
+
+enum Operand {
+    Field(String),
+    Const(serde_json::Value),
+}
+
+// WHERE 条件的最大嵌套层数，避免递归解析及展开时栈溢出
+pub(crate) const MAX_DEPTH: usize = 128;
+
+pub(crate) fn parse(tokens: Vec<Token>) -> Result<Statement, SqlError> {
+    Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    }
+    .parse_statement()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // 当前条件的嵌套层数
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if &self.peek().kind == kind {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<Span, SqlError> {
+        let token = self.next();
+        if token.is_keyword(keyword) {
+            Ok(token.span)
+        } else {
+            Err(unexpected(&token, keyword))
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Result<Span, SqlError> {
+        let token = self.next();
+        if token.kind == kind {
+            Ok(token.span)
+        } else {
+            Err(unexpected(&token, expected))
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<Ident, SqlError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Ident { value, quoted } => {
+                if !quoted && is_reserved(&value) {
+                    return Err(SqlError::new(
+                        token.span,
+                        format!("{} 是关键字，作为名称使用时需要加双引号", value),
+                    ));
+                }
+                Ok(Ident {
+                    value,
+                    span: token.span,
+                })
+            }
+            _ => Err(unexpected(&token, "名称")),
+        }
+    }
+
+    fn parse_statement(mut self) -> Result<Statement, SqlError> {
+        self.expect_keyword("SELECT")?;
+        let mut items = vec![self.parse_select_item()?];
+        while self.eat(&TokenKind::Comma) {
+            items.push(self.parse_select_item()?);
+        }
+
+        self.expect_keyword("FROM")?;
+        let sources = self.parse_object_names()?;
+
+        // WHERE 与 GROUP BY 顺序不限
+        let mut condition = None;
+        let mut window = None;
+        loop {
+            let token = self.peek().clone();
+            if token.is_keyword("WHERE") {
+                if condition.is_some() {
+                    return Err(SqlError::new(token.span, "WHERE 重复出现"));
+                }
+                self.pos += 1;
+                condition = Some(self.parse_or()?);
+            } else if token.is_keyword("GROUP") {
+                if window.is_some() {
+                    return Err(SqlError::new(token.span, "GROUP BY 重复出现"));
+                }
+                self.pos += 1;
+                self.expect_keyword("BY")?;
+                window = Some(self.parse_group_by(token.span)?);
+            } else {
+                break;
+            }
+        }
+
+        self.expect_keyword("INTO")?;
+        let sinks = self.parse_object_names()?;
+
+        let token = self.next();
+        if token.kind != TokenKind::Eof {
+            return Err(unexpected(&token, "语句结束"));
+        }
+
+        Ok(Statement {
+            items,
+            sources,
+            condition,
+            window,
+            sinks,
+        })
+    }
+
+    fn parse_select_item(&mut self) -> Result<SelectItem, SqlError> {
+        if let TokenKind::Star = self.peek().kind {
+            return Ok(SelectItem::Wildcard(self.next().span));
+        }
+
+        let ident = self.parse_ident()?;
+        if self.eat(&TokenKind::LParen) {
+            let field = self.parse_ident()?;
+            let end = self.expect(TokenKind::RParen, ")")?.end;
+            let alias = self.parse_alias()?;
+            let span = Span::new(ident.span.start, end);
+            Ok(SelectItem::Function {
+                func: ident,
+                field,
+                alias,
+                span,
+            })
+        } else {
+            let alias = self.parse_alias()?;
+            Ok(SelectItem::Field {
+                field: ident,
+                alias,
+            })
+        }
+    }
+
+    fn parse_alias(&mut self) -> Result<Option<Ident>, SqlError> {
+        if self.eat_keyword("AS") {
+            Ok(Some(self.parse_ident()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_object_names(&mut self) -> Result<Vec<ObjectName>, SqlError> {
+        let mut names = vec![];
+        loop {
+            let parent = self.parse_ident()?;
+            self.expect(TokenKind::Dot, ".")?;
+            let name = self.parse_ident()?;
+            names.push(ObjectName { parent, name });
+            if !self.eat(&TokenKind::Comma) {
+                return Ok(names);
+            }
+        }
+    }
+
+    fn enter(&mut self, span: Span) -> Result<(), SqlError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(SqlError::new(
+                span,
+                format!("WHERE 条件嵌套超过 {} 层", MAX_DEPTH),
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn nested(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<Expr, SqlError>,
+    ) -> Result<Expr, SqlError> {
+        self.enter(self.peek().span)?;
+        let expr = f(self);
+        self.depth -= 1;
+        expr
+    }
+
+    // 连续的 AND/OR 构成左深的表达式树，每个运算符同样计一层
+    fn parse_or(&mut self) -> Result<Expr, SqlError> {
+        let depth = self.depth;
+        let mut left = self.parse_and()?;
+        loop {
+            let span = self.peek().span;
+            if !self.eat_keyword("OR") {
+                self.depth = depth;
+                return Ok(left);
+            }
+            self.enter(span)?;
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right), span);
+        }
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, SqlError> {
+        let depth = self.depth;
+        let mut left = self.parse_not()?;
+        loop {
+            let span = self.peek().span;
+            if !self.eat_keyword("AND") {
+                self.depth = depth;
+                return Ok(left);
+            }
+            self.enter(span)?;
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right), span);
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, SqlError> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.nested(Self::parse_not)?)));
+        }
+
+        if self.eat(&TokenKind::LParen) {
+            let expr = self.nested(Self::parse_or)?;
+            self.expect(TokenKind::RParen, ")")?;
+            return Ok(expr);
+        }
+
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr, SqlError> {
+        let start = self.peek().span.start;
+        let left = self.parse_operand()?;
+        let op_token = self.next();
+        let op = match op_token.kind {
+            TokenKind::Eq => CompareOp::Eq,
+            TokenKind::Neq => CompareOp::Neq,
+            TokenKind::Gt => CompareOp::Gt,
+            TokenKind::Gte => CompareOp::Gte,
+            TokenKind::Lt => CompareOp::Lt,
+            TokenKind::Lte => CompareOp::Lte,
+            _ if op_token.is_keyword("CONTAINS") => CompareOp::Contains,
+            _ if op_token.is_keyword("REGEXP") => CompareOp::Regexp,
+            _ => return Err(unexpected(&op_token, "比较运算符")),
+        };
+        let right = self.parse_operand()?;
+        let span = Span::new(start, self.tokens[self.pos - 1].span.end);
+
+        let (field, op, value) = match (left, right) {
+            (Operand::Field(field), Operand::Field(target)) => (
+                field,
+                op,
+                serde_json::Value::String(format!("${{{}}}", target)),
+            ),
+            (Operand::Field(field), Operand::Const(value)) => (field, op, value),
+            // 常量在左侧时交换两侧
+            (Operand::Const(value), Operand::Field(field)) => {
+                let op = match op {
+                    CompareOp::Gt => CompareOp::Lt,
+                    CompareOp::Gte => CompareOp::Lte,
+                    CompareOp::Lt => CompareOp::Gt,
+                    CompareOp::Lte => CompareOp::Gte,
+                    CompareOp::Eq | CompareOp::Neq => op,
+                    CompareOp::Contains | CompareOp::Regexp => {
+                        return Err(SqlError::new(span, "CONTAINS/REGEXP 左侧必须为字段"))
+                    }
+                };
+                (field, op, value)
+            }
+            (Operand::Const(_), Operand::Const(_)) => {
+                return Err(SqlError::new(span, "比较的两侧至少需要一个字段"))
+            }
+        };
+
+        if matches!(op, CompareOp::Contains | CompareOp::Regexp) && !value.is_string() {
+            return Err(SqlError::new(span, "CONTAINS/REGEXP 右侧必须为字符串"));
+        }
+
+        Ok(Expr::Compare(Compare {
+            field,
+            op,
+            value,
+            span,
+        }))
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, SqlError> {
+        let token = self.peek().clone();
+        match &token.kind {
+            TokenKind::Number(_) | TokenKind::Minus => Ok(Operand::Const(
+                serde_json::Value::Number(self.parse_number()?.0),
+            )),
+            TokenKind::String(s) => {
+                self.pos += 1;
+                Ok(Operand::Const(serde_json::Value::String(s.clone())))
+            }
+            _ if token.is_keyword("TRUE") => {
+                self.pos += 1;
+                Ok(Operand::Const(serde_json::Value::Bool(true)))
+            }
+            _ if token.is_keyword("FALSE") => {
+                self.pos += 1;
+                Ok(Operand::Const(serde_json::Value::Bool(false)))
+            }
+            _ => Ok(Operand::Field(self.parse_ident()?.value)),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<(serde_json::Number, Span), SqlError> {
+        let start = self.peek().span.start;
+        let negative = self.eat(&TokenKind::Minus);
+        let token = self.next();
+        let span = Span::new(start, token.span.end);
+        let literal = match &token.kind {
+            TokenKind::Number(n) => n,
+            _ => return Err(unexpected(&token, "数字")),
+        };
+        let literal = if negative {
+            format!("-{}", literal)
+        } else {
+            literal.clone()
+        };
+
+        let number = if literal.contains('.') {
+            literal
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+        } else {
+            literal.parse::<i64>().ok().map(serde_json::Number::from)
+        };
+        match number {
+            Some(number) => Ok((number, span)),
+            None => Err(SqlError::new(span, format!("数字 {} 不合法", literal))),
+        }
+    }
+
+    fn parse_positive_integer(&mut self) -> Result<(u64, Span), SqlError> {
+        let (number, span) = self.parse_number()?;
+        match number.as_u64() {
+            Some(n) if n > 0 => Ok((n, span)),
+            _ => Err(SqlError::new(span, "必须为正整数")),
+        }
+    }
+
+    // 时长参数，返回毫秒数
+    fn parse_duration(&mut self, unit: u64) -> Result<u64, SqlError> {
+        let (n, span) = self.parse_positive_integer()?;
+        n.checked_mul(unit)
+            .ok_or_else(|| SqlError::new(span, "时长过大"))
+    }
+
+    fn parse_time_unit(&mut self) -> Result<u64, SqlError> {
+        let token = self.next();
+        let unit = match &token.kind {
+            TokenKind::Ident {
+                value,
+                quoted: false,
+            } => match value.to_ascii_lowercase().as_str() {
+                "ms" => Some(1),
+                "ss" => Some(1000),
+                "mi" => Some(60 * 1000),
+                "hh" => Some(60 * 60 * 1000),
+                "dd" => Some(24 * 60 * 60 * 1000),
+                _ => None,
+            },
+            _ => None,
+        };
+        unit.ok_or_else(|| SqlError::new(token.span, "时间单位必须为 ms、ss、mi、hh、dd 之一"))
+    }
+
+    // GROUP BY 中有且仅有一个窗口，其余为分组字段，META(name) 表示按元数据分组
+    fn parse_group_by(&mut self, group_span: Span) -> Result<window::Conf, SqlError> {
+        let mut window: Option<window::Conf> = None;
+        let mut group_by = vec![];
+        loop {
+            let start = self.pos;
+            let ident = self.parse_ident()?;
+            if self.peek().kind != TokenKind::LParen {
+                group_by.push(GroupBy {
+                    typ: GroupByType::Field,
+                    name: ident.value,
+                });
+            } else if self.tokens[start].is_keyword("META") {
+                self.pos += 1;
+                let name = self.parse_ident()?;
+                self.expect(TokenKind::RParen, ")")?;
+                group_by.push(GroupBy {
+                    typ: GroupByType::Metadata,
+                    name: name.value,
+                });
+            } else {
+                if window.is_some() {
+                    return Err(SqlError::new(ident.span, "GROUP BY 中只能有一个窗口"));
+                }
+                self.pos = start;
+                window = Some(self.parse_window()?);
+            }
+
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+
+        match window {
+            Some(mut window) => {
+                window.group_by = group_by;
+                Ok(window)
+            }
+            None => Err(SqlError::new(group_span, "GROUP BY 缺少窗口")),
+        }
+    }
+
+    fn parse_window(&mut self) -> Result<window::Conf, SqlError> {
+        let name = self.next();
+        let mut conf = window::Conf {
+            typ: window::Type::Count,
+            time_thmbling: None,
+            time_hopping: None,
+            time_session: None,
+            count: None,
+            group_by: vec![],
+            max_groups: None,
+            event_time: None,
+        };
+
+        if name.is_keyword("TUMBLINGWINDOW") {
+            self.expect(TokenKind::LParen, "(")?;
+            let unit = self.parse_time_unit()?;
+            self.expect(TokenKind::Comma, ",")?;
+            conf.typ = window::Type::TimeThmbling;
+            conf.time_thmbling = Some(window::TimeThmbling {
+                interval: self.parse_duration(unit)?,
+            });
+        } else if name.is_keyword("HOPPINGWINDOW") {
+            self.expect(TokenKind::LParen, "(")?;
+            let unit = self.parse_time_unit()?;
+            self.expect(TokenKind::Comma, ",")?;
+            let interval = self.parse_duration(unit)?;
+            self.expect(TokenKind::Comma, ",")?;
+            conf.typ = window::Type::TimeHopping;
+            conf.time_hopping = Some(window::TimeHopping {
+                interval,
+                hopping: self.parse_duration(unit)?,
+            });
+        } else if name.is_keyword("SESSIONWINDOW") {
+            self.expect(TokenKind::LParen, "(")?;
+            let unit = self.parse_time_unit()?;
+            self.expect(TokenKind::Comma, ",")?;
+            let timeout_span = self.peek().span;
+            let timeout = self.parse_duration(unit)?;
+            self.expect(TokenKind::Comma, ",")?;
+            let max = self.parse_duration(unit)?;
+            if timeout > max {
+                return Err(SqlError::new(timeout_span, "会话超时时间不能大于最大时长"));
+            }
+            conf.typ = window::Type::TimeSession;
+            conf.time_session = Some(window::TimeSession { timeout, max });
+        } else if name.is_keyword("COUNTWINDOW") {
+            self.expect(TokenKind::LParen, "(")?;
+            conf.count = Some(window::Count {
+                count: self.parse_positive_integer()?.0,
+            });
+        } else {
+            return Err(SqlError::new(
+                name.span,
+                "GROUP BY 仅支持 TUMBLINGWINDOW、HOPPINGWINDOW、SESSIONWINDOW、COUNTWINDOW",
+            ));
+        }
+
+        self.expect(TokenKind::RParen, ")")?;
+        Ok(conf)
+    }
+}
+
+const RESERVED: [&str; 15] = [
+    "SELECT", "FROM", "WHERE", "GROUP", "BY", "INTO", "AS", "AND", "OR", "NOT", "CONTAINS",
+    "REGEXP", "TRUE", "FALSE", "NULL",
+];
+
+fn is_reserved(value: &str) -> bool {
+    RESERVED.iter().any(|k| value.eq_ignore_ascii_case(k))
+}
+
+fn unexpected(token: &Token, expected: &str) -> SqlError {
+    match token.kind {
+        TokenKind::Eof => SqlError::new(token.span, format!("语句不完整，缺少 {}", expected)),
+        _ => SqlError::new(token.span, format!("此处应为 {}", expected)),
+    }
+}
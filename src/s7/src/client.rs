@@ -0,0 +1,273 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    decode_u16, encode_u16,
+    pdu::{
+        decode_connection_confirm, decode_read_var, decode_setup_communication, decode_write_var,
+        encode_connection_request, encode_read_var, encode_setup_communication, encode_write_var,
+        Item, READ_OVERHEAD, WRITE_OVERHEAD,
+    },
+    Area, ProtocolError, S7Error,
+};
+
+// 请求的PDU长度，最终以PLC协商结果为准
+const REQUEST_PDU_LEN: u16 = 480;
+
+pub struct Client<T> {
+    transport: T,
+    pdu_ref: u16,
+    pdu_len: u16,
+}
+
+impl<T> Client<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    // 建立COTP连接并协商PDU长度
+    pub async fn connect(
+        mut transport: T,
+        local_tsap: u16,
+        remote_tsap: u16,
+    ) -> Result<Self, S7Error> {
+        transport
+            .write_all(&encode_connection_request(local_tsap, remote_tsap))
+            .await?;
+        let frame = read_tpkt(&mut transport).await?;
+        decode_connection_confirm(&frame)?;
+
+        let mut client = Self {
+            transport,
+            pdu_ref: 0,
+            pdu_len: REQUEST_PDU_LEN,
+        };
+        let pdu_ref = client.next_pdu_ref();
+        let pdu = client
+            .request(encode_setup_communication(pdu_ref, REQUEST_PDU_LEN))
+            .await?;
+        let pdu_len = decode_setup_communication(&pdu, pdu_ref)?;
+        if (pdu_len as usize) <= WRITE_OVERHEAD {
+            return Err(ProtocolError::InvalidResp.into());
+        }
+        client.pdu_len = pdu_len;
+
+        Ok(client)
+    }
+
+    pub fn pdu_len(&self) -> u16 {
+        self.pdu_len
+    }
+
+    // 按字节读取，超出单个PDU时拆分为多次请求
+    pub async fn read_area(
+        &mut self,
+        area: Area,
+        db_number: u16,
+        start: u32,
+        len: u16,
+    ) -> Result<Vec<u8>, S7Error> {
+        let max_len = self.pdu_len - READ_OVERHEAD as u16;
+        let mut data = Vec::with_capacity(len as usize);
+        let mut offset = 0;
+        while offset < len {
+            let chunk_len = (len - offset).min(max_len);
+            let item = Item {
+                area,
+                db_number,
+                start: start + offset as u32,
+                bit: None,
+                len: chunk_len,
+            };
+            let chunk = self.read_item(&item).await?;
+            if chunk.len() < chunk_len as usize {
+                return Err(ProtocolError::DataTooSmall.into());
+            }
+            data.extend_from_slice(&chunk[..chunk_len as usize]);
+            offset += chunk_len;
+        }
+        Ok(data)
+    }
+
+    pub async fn read_bit(
+        &mut self,
+        area: Area,
+        db_number: u16,
+        start: u32,
+        bit: u8,
+    ) -> Result<bool, S7Error> {
+        let item = Item {
+            area,
+            db_number,
+            start,
+            bit: Some(bit),
+            len: 1,
+        };
+        let data = self.read_item(&item).await?;
+        match data.first() {
+            Some(value) => Ok(*value & 0x01 == 0x01),
+            None => Err(ProtocolError::DataTooSmall.into()),
+        }
+    }
+
+    // 按字节写入，超出单个PDU时拆分为多次请求
+    pub async fn write_area(
+        &mut self,
+        area: Area,
+        db_number: u16,
+        start: u32,
+        data: &[u8],
+    ) -> Result<(), S7Error> {
+        let max_len = self.pdu_len as usize - WRITE_OVERHEAD;
+        for (i, chunk) in data.chunks(max_len).enumerate() {
+            let item = Item {
+                area,
+                db_number,
+                start: start + (i * max_len) as u32,
+                bit: None,
+                len: chunk.len() as u16,
+            };
+            self.write_item(&item, chunk).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn write_bit(
+        &mut self,
+        area: Area,
+        db_number: u16,
+        start: u32,
+        bit: u8,
+        value: bool,
+    ) -> Result<(), S7Error> {
+        let item = Item {
+            area,
+            db_number,
+            start,
+            bit: Some(bit),
+            len: 1,
+        };
+        self.write_item(&item, &[value as u8]).await
+    }
+
+    async fn read_item(&mut self, item: &Item) -> Result<Vec<u8>, S7Error> {
+        let pdu_ref = self.next_pdu_ref();
+        let pdu = self.request(encode_read_var(pdu_ref, item)?).await?;
+        Ok(decode_read_var(&pdu, pdu_ref)??)
+    }
+
+    async fn write_item(&mut self, item: &Item, value: &[u8]) -> Result<(), S7Error> {
+        let pdu_ref = self.next_pdu_ref();
+        let pdu = self
+            .request(encode_write_var(pdu_ref, item, value)?)
+            .await?;
+        Ok(decode_write_var(&pdu, pdu_ref)??)
+    }
+
+    fn next_pdu_ref(&mut self) -> u16 {
+        self.pdu_ref = self.pdu_ref.wrapping_add(1);
+        self.pdu_ref
+    }
+
+    // 发送S7 PDU并读取响应，响应可能由多个COTP数据帧组成
+    async fn request(&mut self, pdu: Vec<u8>) -> Result<Vec<u8>, S7Error> {
+        let mut buffer = Vec::with_capacity(7 + pdu.len());
+        let (hi, lo) = encode_u16((7 + pdu.len()) as u16);
+        buffer.extend_from_slice(&[0x03, 0x00, hi, lo]);
+        // COTP DT，最后一个数据单元
+        buffer.extend_from_slice(&[0x02, 0xF0, 0x80]);
+        buffer.extend_from_slice(&pdu);
+        self.transport.write_all(&buffer).await?;
+
+        let mut resp = vec![];
+        loop {
+            let frame = read_tpkt(&mut self.transport).await?;
+            if frame.len() < 3 || frame[1] != 0xF0 {
+                return Err(ProtocolError::InvalidResp.into());
+            }
+            let header_len = frame[0] as usize + 1;
+            if frame.len() < header_len {
+                return Err(ProtocolError::DataTooSmall.into());
+            }
+            resp.extend_from_slice(&frame[header_len..]);
+            if frame[2] & 0x80 == 0x80 {
+                return Ok(resp);
+            }
+        }
+    }
+}
+
+// 读取一个TPKT帧，返回去掉TPKT头的数据
+async fn read_tpkt<T>(transport: &mut T) -> Result<Vec<u8>, S7Error>
+where
+    T: AsyncRead + Unpin,
+{
+    let mut header = [0; 4];
+    transport.read_exact(&mut header).await?;
+    if header[0] != 0x03 {
+        return Err(ProtocolError::TpktVersion.into());
+    }
+    let len = decode_u16(header[2], header[3]) as usize;
+    if len < 4 {
+        return Err(ProtocolError::DataTooSmall.into());
+    }
+    let mut frame = vec![0; len - 4];
+    transport.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    use crate::{remote_tsap, Area};
+
+    use super::Client;
+
+    // 模拟PLC，依次返回连接确认、通信设置响应及读取响应
+    #[tokio::test]
+    async fn test_client() {
+        let (client_io, mut server_io) = duplex(1024);
+        let server = tokio::spawn(async move {
+            let mut buffer = [0; 256];
+            let n = server_io.read(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[17..19], &[0x03, 0x01]);
+            assert_eq!(n, 22);
+            server_io
+                .write_all(&[
+                    0x03, 0x00, 0x00, 0x16, 0x11, 0xD0, 0x00, 0x01, 0x00, 0x01, 0x00, 0xC0, 0x01,
+                    0x0A, 0xC1, 0x02, 0x01, 0x00, 0xC2, 0x02, 0x03, 0x01,
+                ])
+                .await
+                .unwrap();
+
+            let n = server_io.read(&mut buffer).await.unwrap();
+            assert_eq!(n, 25);
+            server_io
+                .write_all(&[
+                    0x03, 0x00, 0x00, 0x1B, 0x02, 0xF0, 0x80, 0x32, 0x03, 0x00, 0x00, 0x00, 0x01,
+                    0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0xF0, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
+                    0xF0,
+                ])
+                .await
+                .unwrap();
+
+            let n = server_io.read(&mut buffer).await.unwrap();
+            assert_eq!(n, 31);
+            server_io
+                .write_all(&[
+                    0x03, 0x00, 0x00, 0x1B, 0x02, 0xF0, 0x80, 0x32, 0x03, 0x00, 0x00, 0x00, 0x02,
+                    0x00, 0x02, 0x00, 0x06, 0x00, 0x00, 0x04, 0x01, 0xFF, 0x04, 0x00, 0x10, 0x12,
+                    0x34,
+                ])
+                .await
+                .unwrap();
+        });
+
+        let mut client = Client::connect(client_io, 0x0100, remote_tsap(0x03, 0, 1))
+            .await
+            .unwrap();
+        assert_eq!(client.pdu_len(), 240);
+        let data = client.read_area(Area::DataBlock, 1, 0, 2).await.unwrap();
+        assert_eq!(data, vec![0x12, 0x34]);
+        server.await.unwrap();
+    }
+}
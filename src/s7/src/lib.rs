@@ -0,0 +1,99 @@
+use std::io;
+
+use thiserror::Error;
+
+pub mod client;
+pub(crate) mod pdu;
+
+pub use client::Client;
+
+// 协议使用大端编码方式
+// 报文结构：TPKT(RFC1006) + COTP(ISO 8073) + S7comm
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    // I
+    Input = 0x81,
+    // Q
+    Output = 0x82,
+    // M
+    Merker = 0x83,
+    // DB
+    DataBlock = 0x84,
+}
+
+#[derive(Error, Debug)]
+pub enum S7Error {
+    #[error("网络错误：{0}")]
+    Transport(#[from] io::Error),
+    #[error("协议错误：{0}")]
+    Protocol(#[from] ProtocolError),
+    #[error("数据项错误：{0}")]
+    Item(#[from] ItemError),
+}
+
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("TPKT版本错误")]
+    TpktVersion,
+    #[error("连接被拒绝")]
+    ConnectRefused,
+    #[error("返回数据长度过小")]
+    DataTooSmall,
+    #[error("返回数据格式错误")]
+    InvalidResp,
+    #[error("PDU引用不匹配")]
+    PduRefMismatch,
+    #[error("功能码不匹配")]
+    FunctionMismatch,
+    #[error("PLC返回错误，错误类：{0:#04x}，错误码：{1:#04x}")]
+    ErrorResp(u8, u8),
+    #[error("地址超出范围")]
+    AddressOutOfRange,
+}
+
+// 数据项返回码
+#[derive(Error, Debug)]
+pub enum ItemError {
+    #[error("硬件错误")]
+    HardwareFault,
+    #[error("无访问权限")]
+    AccessDenied,
+    #[error("地址无效")]
+    AddressOutOfRange,
+    #[error("不支持的数据类型")]
+    DataTypeNotSupported,
+    #[error("数据类型不一致")]
+    DataTypeInconsistent,
+    #[error("对象不存在")]
+    ObjectNotExist,
+    #[error("未知错误：{0:#04x}")]
+    Unknown(u8),
+}
+
+impl From<u8> for ItemError {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => ItemError::HardwareFault,
+            0x03 => ItemError::AccessDenied,
+            0x05 => ItemError::AddressOutOfRange,
+            0x06 => ItemError::DataTypeNotSupported,
+            0x07 => ItemError::DataTypeInconsistent,
+            0x0A => ItemError::ObjectNotExist,
+            code => ItemError::Unknown(code),
+        }
+    }
+}
+
+// 远端TSAP，高字节为连接类型，低字节为机架号和槽号
+pub fn remote_tsap(connection_type: u8, rack: u8, slot: u8) -> u16 {
+    (connection_type as u16) << 8 | ((rack as u16) * 0x20 + slot as u16)
+}
+
+pub(crate) fn encode_u16(data: u16) -> (u8, u8) {
+    ((data >> 8) as u8, (data & 0x00FF) as u8)
+}
+
+pub(crate) fn decode_u16(data0: u8, data1: u8) -> u16 {
+    (data0 as u16) << 8 | (data1 as u16)
+}
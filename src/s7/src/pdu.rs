@@ -0,0 +1,293 @@
+use crate::{decode_u16, encode_u16, Area, ItemError, ProtocolError};
+
+const PROTOCOL_ID: u8 = 0x32;
+
+const ROSCTR_JOB: u8 = 0x01;
+const ROSCTR_ACK: u8 = 0x02;
+const ROSCTR_ACK_DATA: u8 = 0x03;
+
+const FUNCTION_READ_VAR: u8 = 0x04;
+const FUNCTION_WRITE_VAR: u8 = 0x05;
+const FUNCTION_SETUP_COMMUNICATION: u8 = 0xF0;
+
+const TRANSPORT_SIZE_BIT: u8 = 0x01;
+const TRANSPORT_SIZE_BYTE: u8 = 0x02;
+
+// 数据部分的传输类型
+const DATA_TRANSPORT_SIZE_BIT: u8 = 0x03;
+const DATA_TRANSPORT_SIZE_BYTE: u8 = 0x04;
+const DATA_TRANSPORT_SIZE_INTEGER: u8 = 0x05;
+
+const RETURN_CODE_SUCCESS: u8 = 0xFF;
+
+// 请求头长度
+pub(crate) const JOB_HEADER_LEN: usize = 10;
+// 响应头长度，比请求头多出错误类和错误码
+pub(crate) const ACK_DATA_HEADER_LEN: usize = 12;
+
+// 单次读取时，响应中除数据外占用的长度：响应头 + 功能码及数量 + 数据项头
+pub(crate) const READ_OVERHEAD: usize = ACK_DATA_HEADER_LEN + 2 + 4;
+// 单次写入时，请求中除数据外占用的长度：请求头 + 功能码及数量 + 地址项 + 数据项头
+pub(crate) const WRITE_OVERHEAD: usize = JOB_HEADER_LEN + 2 + 12 + 4;
+
+// 24位地址中以位为单位，最大字节地址为2^21-1
+const MAX_BYTE_ADDRESS: u32 = (1 << 21) - 1;
+
+pub(crate) struct Item {
+    pub area: Area,
+    pub db_number: u16,
+    pub start: u32,
+    // 为Some时按位访问
+    pub bit: Option<u8>,
+    pub len: u16,
+}
+
+impl Item {
+    fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), ProtocolError> {
+        if self.start > MAX_BYTE_ADDRESS {
+            return Err(ProtocolError::AddressOutOfRange);
+        }
+
+        // 变量规范，地址项长度，寻址方式为S7ANY
+        buffer.extend_from_slice(&[0x12, 0x0A, 0x10]);
+        let (transport_size, address) = match self.bit {
+            Some(bit) => (TRANSPORT_SIZE_BIT, self.start * 8 + (bit & 0x07) as u32),
+            None => (TRANSPORT_SIZE_BYTE, self.start * 8),
+        };
+        buffer.push(transport_size);
+        let (hi, lo) = encode_u16(self.len);
+        buffer.extend_from_slice(&[hi, lo]);
+        let (hi, lo) = match self.area {
+            Area::DataBlock => encode_u16(self.db_number),
+            _ => (0, 0),
+        };
+        buffer.extend_from_slice(&[hi, lo]);
+        buffer.push(self.area as u8);
+        buffer.extend_from_slice(&[(address >> 16) as u8, (address >> 8) as u8, address as u8]);
+        Ok(())
+    }
+}
+
+fn encode_header(buffer: &mut Vec<u8>, pdu_ref: u16, param_len: u16, data_len: u16) {
+    buffer.push(PROTOCOL_ID);
+    buffer.push(ROSCTR_JOB);
+    buffer.extend_from_slice(&[0x00, 0x00]);
+    let (hi, lo) = encode_u16(pdu_ref);
+    buffer.extend_from_slice(&[hi, lo]);
+    let (hi, lo) = encode_u16(param_len);
+    buffer.extend_from_slice(&[hi, lo]);
+    let (hi, lo) = encode_u16(data_len);
+    buffer.extend_from_slice(&[hi, lo]);
+}
+
+// 校验响应头，返回参数部分与数据部分
+fn decode_header(pdu: &[u8], pdu_ref: u16, function: u8) -> Result<(&[u8], &[u8]), ProtocolError> {
+    if pdu.len() < ACK_DATA_HEADER_LEN {
+        return Err(ProtocolError::DataTooSmall);
+    }
+    if pdu[0] != PROTOCOL_ID {
+        return Err(ProtocolError::InvalidResp);
+    }
+    match pdu[1] {
+        ROSCTR_ACK | ROSCTR_ACK_DATA => {}
+        _ => return Err(ProtocolError::InvalidResp),
+    }
+    if decode_u16(pdu[4], pdu[5]) != pdu_ref {
+        return Err(ProtocolError::PduRefMismatch);
+    }
+    if pdu[10] != 0 || pdu[11] != 0 {
+        return Err(ProtocolError::ErrorResp(pdu[10], pdu[11]));
+    }
+
+    let param_len = decode_u16(pdu[6], pdu[7]) as usize;
+    let data_len = decode_u16(pdu[8], pdu[9]) as usize;
+    if pdu.len() < ACK_DATA_HEADER_LEN + param_len + data_len {
+        return Err(ProtocolError::DataTooSmall);
+    }
+    let param = &pdu[ACK_DATA_HEADER_LEN..ACK_DATA_HEADER_LEN + param_len];
+    let data = &pdu[ACK_DATA_HEADER_LEN + param_len..ACK_DATA_HEADER_LEN + param_len + data_len];
+
+    match param.first() {
+        Some(f) if *f == function => Ok((param, data)),
+        _ => Err(ProtocolError::FunctionMismatch),
+    }
+}
+
+// COTP连接请求，包含TPKT头
+pub(crate) fn encode_connection_request(local_tsap: u16, remote_tsap: u16) -> Vec<u8> {
+    let (local_hi, local_lo) = encode_u16(local_tsap);
+    let (remote_hi, remote_lo) = encode_u16(remote_tsap);
+    let mut buffer = Vec::with_capacity(22);
+    // TPKT
+    buffer.extend_from_slice(&[0x03, 0x00, 0x00, 0x16]);
+    // COTP CR，目的引用，源引用，类别
+    buffer.extend_from_slice(&[0x11, 0xE0, 0x00, 0x00, 0x00, 0x01, 0x00]);
+    // 源TSAP
+    buffer.extend_from_slice(&[0xC1, 0x02, local_hi, local_lo]);
+    // 目的TSAP
+    buffer.extend_from_slice(&[0xC2, 0x02, remote_hi, remote_lo]);
+    // TPDU大小，1024
+    buffer.extend_from_slice(&[0xC0, 0x01, 0x0A]);
+    buffer
+}
+
+// 校验COTP连接确认，frame不含TPKT头
+pub(crate) fn decode_connection_confirm(frame: &[u8]) -> Result<(), ProtocolError> {
+    if frame.len() < 2 {
+        return Err(ProtocolError::DataTooSmall);
+    }
+    match frame[1] {
+        0xD0 => Ok(()),
+        _ => Err(ProtocolError::ConnectRefused),
+    }
+}
+
+pub(crate) fn encode_setup_communication(pdu_ref: u16, pdu_len: u16) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(JOB_HEADER_LEN + 8);
+    encode_header(&mut buffer, pdu_ref, 8, 0);
+    buffer.extend_from_slice(&[FUNCTION_SETUP_COMMUNICATION, 0x00]);
+    // 最大并发调用数量
+    buffer.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+    let (hi, lo) = encode_u16(pdu_len);
+    buffer.extend_from_slice(&[hi, lo]);
+    buffer
+}
+
+// 返回协商后的PDU长度
+pub(crate) fn decode_setup_communication(pdu: &[u8], pdu_ref: u16) -> Result<u16, ProtocolError> {
+    let (param, _) = decode_header(pdu, pdu_ref, FUNCTION_SETUP_COMMUNICATION)?;
+    if param.len() < 8 {
+        return Err(ProtocolError::DataTooSmall);
+    }
+    Ok(decode_u16(param[6], param[7]))
+}
+
+pub(crate) fn encode_read_var(pdu_ref: u16, item: &Item) -> Result<Vec<u8>, ProtocolError> {
+    let mut buffer = Vec::with_capacity(JOB_HEADER_LEN + 14);
+    encode_header(&mut buffer, pdu_ref, 14, 0);
+    buffer.extend_from_slice(&[FUNCTION_READ_VAR, 0x01]);
+    item.encode(&mut buffer)?;
+    Ok(buffer)
+}
+
+pub(crate) fn decode_read_var(
+    pdu: &[u8],
+    pdu_ref: u16,
+) -> Result<Result<Vec<u8>, ItemError>, ProtocolError> {
+    let (_, data) = decode_header(pdu, pdu_ref, FUNCTION_READ_VAR)?;
+    if data.is_empty() {
+        return Err(ProtocolError::DataTooSmall);
+    }
+    if data[0] != RETURN_CODE_SUCCESS {
+        return Ok(Err(ItemError::from(data[0])));
+    }
+    if data.len() < 4 {
+        return Err(ProtocolError::DataTooSmall);
+    }
+
+    let len = decode_u16(data[2], data[3]) as usize;
+    // 位、字节、整数类型的长度单位为位，其余为字节
+    let len = match data[1] {
+        DATA_TRANSPORT_SIZE_BIT | DATA_TRANSPORT_SIZE_BYTE | DATA_TRANSPORT_SIZE_INTEGER => {
+            len.div_ceil(8)
+        }
+        _ => len,
+    };
+    match data.get(4..4 + len) {
+        Some(value) => Ok(Ok(value.to_vec())),
+        None => Err(ProtocolError::DataTooSmall),
+    }
+}
+
+pub(crate) fn encode_write_var(
+    pdu_ref: u16,
+    item: &Item,
+    value: &[u8],
+) -> Result<Vec<u8>, ProtocolError> {
+    let mut buffer = Vec::with_capacity(JOB_HEADER_LEN + 18 + value.len());
+    encode_header(&mut buffer, pdu_ref, 14, 4 + value.len() as u16);
+    buffer.extend_from_slice(&[FUNCTION_WRITE_VAR, 0x01]);
+    item.encode(&mut buffer)?;
+
+    let (transport_size, len) = match item.bit {
+        Some(_) => (DATA_TRANSPORT_SIZE_BIT, value.len() as u16),
+        None => (DATA_TRANSPORT_SIZE_BYTE, value.len() as u16 * 8),
+    };
+    buffer.extend_from_slice(&[0x00, transport_size]);
+    let (hi, lo) = encode_u16(len);
+    buffer.extend_from_slice(&[hi, lo]);
+    buffer.extend_from_slice(value);
+    Ok(buffer)
+}
+
+pub(crate) fn decode_write_var(
+    pdu: &[u8],
+    pdu_ref: u16,
+) -> Result<Result<(), ItemError>, ProtocolError> {
+    let (_, data) = decode_header(pdu, pdu_ref, FUNCTION_WRITE_VAR)?;
+    match data.first() {
+        Some(&RETURN_CODE_SUCCESS) => Ok(Ok(())),
+        Some(code) => Ok(Err(ItemError::from(*code))),
+        None => Err(ProtocolError::DataTooSmall),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Area;
+
+    use super::{decode_read_var, encode_read_var, encode_write_var, Item};
+
+    #[test]
+    fn test_encode_read_var() {
+        let item = Item {
+            area: Area::DataBlock,
+            db_number: 1,
+            start: 10,
+            bit: None,
+            len: 4,
+        };
+        let pdu = encode_read_var(3, &item).unwrap();
+        assert_eq!(
+            pdu,
+            vec![
+                0x32, 0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x0E, 0x00, 0x00, 0x04, 0x01, 0x12, 0x0A,
+                0x10, 0x02, 0x00, 0x04, 0x00, 0x01, 0x84, 0x00, 0x00, 0x50,
+            ]
+        );
+
+        let item = Item {
+            area: Area::Merker,
+            db_number: 1,
+            start: 2,
+            bit: Some(3),
+            len: 1,
+        };
+        let pdu = encode_write_var(4, &item, &[0x01]).unwrap();
+        assert_eq!(
+            &pdu[12..],
+            &[
+                0x12, 0x0A, 0x10, 0x01, 0x00, 0x01, 0x00, 0x00, 0x83, 0x00, 0x00, 0x13, 0x00, 0x03,
+                0x00, 0x01, 0x01,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_read_var() {
+        let pdu = [
+            0x32, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x08, 0x00, 0x00, 0x04, 0x01,
+            0xFF, 0x04, 0x00, 0x20, 0x01, 0x02, 0x03, 0x04,
+        ];
+        let data = decode_read_var(&pdu, 3).unwrap().unwrap();
+        assert_eq!(data, vec![0x01, 0x02, 0x03, 0x04]);
+
+        assert!(decode_read_var(&pdu, 4).is_err());
+
+        let pdu = [
+            0x32, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x04, 0x00, 0x00, 0x04, 0x01,
+            0x0A, 0x00, 0x00, 0x00,
+        ];
+        assert!(decode_read_var(&pdu, 3).unwrap().is_err());
+    }
+}
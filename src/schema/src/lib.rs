@@ -99,6 +99,10 @@ pub async fn list_references(
                 let source = storage::device::source_sink::read_one(&reference.resource_id).await?;
                 (device.name, source.name)
             }
+            types::schema::ParentType::Rule => {
+                let rule = storage::rule::read_one(&reference.parent_id).await?;
+                (rule.name, "MQTT服务器源".to_owned())
+            }
         };
         list.push(ListReferencesItem {
             parent_type: reference.parent_type,
@@ -194,3 +198,11 @@ pub async fn reference_app_source(
 ) -> HaliaResult<()> {
     storage::schema::reference::insert_app_source(schema_id, app_id, app_source_id).await
 }
+
+pub async fn reference_rule_node(schema_id: &String, rule_id: &String) -> HaliaResult<()> {
+    storage::schema::reference::insert_rule_node(schema_id, rule_id).await
+}
+
+pub async fn unreference_rule(rule_id: &String) -> HaliaResult<()> {
+    storage::schema::reference::delete_many_by_parent_id(rule_id).await
+}
@@ -59,6 +59,10 @@ async fn main() -> Result<()> {
 
     storage::init(&config.storage).await?;
 
+    if let Some(mqtt_server_conf) = &config.mqtt_server {
+        mqtt_server::start(mqtt_server_conf)?;
+    }
+
     devices::load_from_storage().await.unwrap();
     apps::load_from_storage().await.unwrap();
     databoard::load_from_storage().await.unwrap();
@@ -13,6 +13,7 @@ use types::{
 
 use super::POOL;
 
+pub mod source_offset;
 pub mod source_sink;
 
 static TABLE_NAME: &str = "apps";
@@ -103,6 +104,16 @@ pub async fn read_name(id: &String) -> Result<String> {
     Ok(name)
 }
 
+pub async fn read_id_by_name(name: &String) -> Result<Option<String>> {
+    let id: Option<String> =
+        sqlx::query_scalar(format!("SELECT id FROM {} WHERE name = ?", TABLE_NAME).as_str())
+            .bind(name)
+            .fetch_optional(POOL.get().unwrap())
+            .await?;
+
+    Ok(id)
+}
+
 pub async fn read_conf(id: &String) -> Result<serde_json::Value> {
     let conf: Vec<u8> =
         sqlx::query_scalar(format!("SELECT conf FROM {} WHERE id = ?", TABLE_NAME).as_str())
@@ -0,0 +1,136 @@
+use anyhow::Result;
+use common::error::HaliaResult;
+use sqlx::FromRow;
+
+use super::POOL;
+
+static TABLE_NAME: &str = "app_source_offsets";
+
+#[derive(FromRow)]
+struct DbSourceOffset {
+    pub topic: String,
+    pub topic_partition: i32,
+    pub next_offset: i64,
+}
+
+pub(crate) fn create_table() -> String {
+    format!(
+        r#"
+CREATE TABLE IF NOT EXISTS {} (
+    id CHAR(32) PRIMARY KEY,
+    topic VARCHAR(255) NOT NULL,
+    topic_partition INTEGER NOT NULL,
+    next_offset BIGINT NOT NULL,
+    ts BIGINT UNSIGNED NOT NULL
+);
+"#,
+        TABLE_NAME
+    )
+}
+
+// 读取源在指定主题分区上已消费到的偏移量，主题或分区不一致时返回空
+pub async fn read(id: &String, topic: &String, partition: i32) -> Result<Option<i64>> {
+    let db_source_offset = sqlx::query_as::<_, DbSourceOffset>(
+        format!(
+            "SELECT topic, topic_partition, next_offset FROM {} WHERE id = ?",
+            TABLE_NAME
+        )
+        .as_str(),
+    )
+    .bind(id)
+    .fetch_optional(POOL.get().unwrap())
+    .await?;
+
+    match db_source_offset {
+        Some(db_source_offset)
+            if db_source_offset.topic == *topic
+                && db_source_offset.topic_partition == partition =>
+        {
+            Ok(Some(db_source_offset.next_offset))
+        }
+        _ => Ok(None),
+    }
+}
+
+pub async fn upsert(id: &String, topic: &String, partition: i32, offset: i64) -> Result<()> {
+    let ts = common::timestamp_millis() as i64;
+    let res = sqlx::query(
+        format!(
+            "UPDATE {} SET topic = ?, topic_partition = ?, next_offset = ?, ts = ? WHERE id = ?",
+            TABLE_NAME
+        )
+        .as_str(),
+    )
+    .bind(topic)
+    .bind(partition)
+    .bind(offset)
+    .bind(ts)
+    .bind(id)
+    .execute(POOL.get().unwrap())
+    .await?;
+    if res.rows_affected() > 0 {
+        return Ok(());
+    }
+
+    sqlx::query(
+        format!(
+            "INSERT INTO {} (id, topic, topic_partition, next_offset, ts) VALUES (?, ?, ?, ?, ?)",
+            TABLE_NAME
+        )
+        .as_str(),
+    )
+    .bind(id)
+    .bind(topic)
+    .bind(partition)
+    .bind(offset)
+    .bind(ts)
+    .execute(POOL.get().unwrap())
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_by_id(id: &String) -> HaliaResult<()> {
+    crate::delete_by_id(id, TABLE_NAME).await
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::any::AnyPoolOptions;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_source_offset() {
+        POOL.get_or_init(|| async {
+            sqlx::any::install_default_drivers();
+            // 内存数据库的每个连接相互独立，只使用一个连接
+            AnyPoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap()
+        })
+        .await;
+        sqlx::query(&create_table())
+            .execute(POOL.get().unwrap())
+            .await
+            .unwrap();
+
+        let id = "source".to_owned();
+        let topic = "topic".to_owned();
+        assert_eq!(read(&id, &topic, 0).await.unwrap(), None);
+
+        upsert(&id, &topic, 0, 10).await.unwrap();
+        assert_eq!(read(&id, &topic, 0).await.unwrap(), Some(10));
+        upsert(&id, &topic, 0, 20).await.unwrap();
+        assert_eq!(read(&id, &topic, 0).await.unwrap(), Some(20));
+
+        // 主题或分区变化后不能沿用之前的偏移量
+        assert_eq!(read(&id, &topic, 1).await.unwrap(), None);
+        assert_eq!(read(&id, &"other".to_owned(), 0).await.unwrap(), None);
+
+        delete_by_id(&id).await.unwrap();
+        assert_eq!(read(&id, &topic, 0).await.unwrap(), None);
+    }
+}
@@ -134,6 +134,35 @@ async fn read_all_by_app_id(
     Ok(sources_sinks)
 }
 
+pub async fn read_source_id_by_name(app_id: &String, name: &String) -> Result<Option<String>> {
+    read_id_by_name(SourceSinkType::Source, app_id, name).await
+}
+
+pub async fn read_sink_id_by_name(app_id: &String, name: &String) -> Result<Option<String>> {
+    read_id_by_name(SourceSinkType::Sink, app_id, name).await
+}
+
+async fn read_id_by_name(
+    source_sink_type: SourceSinkType,
+    app_id: &String,
+    name: &String,
+) -> Result<Option<String>> {
+    let id: Option<String> = sqlx::query_scalar(
+        format!(
+            "SELECT id FROM {} WHERE app_id = ? AND source_sink_type = ? AND name = ?",
+            TABLE_NAME
+        )
+        .as_str(),
+    )
+    .bind(app_id)
+    .bind(Into::<i32>::into(source_sink_type))
+    .bind(name)
+    .fetch_optional(POOL.get().unwrap())
+    .await?;
+
+    Ok(id)
+}
+
 pub async fn read_one(id: &String) -> Result<SourceSink> {
     let db_source_sink = sqlx::query_as::<_, DbSourceSink>(
         format!("SELECT * FROM {} WHERE id = ?", TABLE_NAME).as_str(),
@@ -279,6 +279,16 @@ pub async fn read_name(id: &String) -> Result<String> {
     Ok(name)
 }
 
+pub async fn read_id_by_name(name: &String) -> Result<Option<String>> {
+    let id: Option<String> =
+        sqlx::query_scalar(format!("SELECT id FROM {} WHERE name = ?", TABLE_NAME).as_str())
+            .bind(name)
+            .fetch_optional(POOL.get().unwrap())
+            .await?;
+
+    Ok(id)
+}
+
 pub async fn read_device_type(id: &String) -> Result<DeviceType> {
     let device_type: i32 =
         sqlx::query_scalar(format!("SELECT device_type FROM {} WHERE id = ?", TABLE_NAME).as_str())
@@ -279,6 +279,34 @@ pub async fn read_sinks_by_device_id(device_id: &String) -> Result<Vec<SourceSin
     read_by_device_id(SourceSinkType::Sink, device_id).await
 }
 
+pub async fn read_source_id_by_name(device_id: &String, name: &String) -> Result<Option<String>> {
+    read_id_by_name(SourceSinkType::Source, device_id, name).await
+}
+
+pub async fn read_sink_id_by_name(device_id: &String, name: &String) -> Result<Option<String>> {
+    read_id_by_name(SourceSinkType::Sink, device_id, name).await
+}
+
+async fn read_id_by_name(
+    source_sink_type: SourceSinkType,
+    device_id: &String,
+    name: &String,
+) -> Result<Option<String>> {
+    let id: Option<String> = sqlx::query_scalar(
+        format!(
+            "SELECT id FROM {} WHERE source_sink_type = ? AND device_id = ? AND name = ?",
+            TABLE_NAME
+        )
+        .as_str(),
+    )
+    .bind(Into::<i32>::into(source_sink_type))
+    .bind(device_id)
+    .bind(name)
+    .fetch_optional(POOL.get().unwrap())
+    .await?;
+    Ok(id)
+}
+
 pub async fn update_status(id: &String, status: Status) -> Result<()> {
     sqlx::query(format!("UPDATE {} SET status = ? WHERE id = ?", TABLE_NAME).as_str())
         .bind(Into::<i32>::into(status))
@@ -89,6 +89,10 @@ pub async fn init(config: &StorageConfig) -> Result<()> {
         .execute(POOL.get().unwrap())
         .await
         .unwrap();
+    sqlx::query(&app::source_offset::create_table())
+        .execute(POOL.get().unwrap())
+        .await
+        .unwrap();
     sqlx::query(&rule::create_table())
         .execute(POOL.get().unwrap())
         .await
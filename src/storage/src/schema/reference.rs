@@ -115,6 +115,18 @@ pub async fn insert_device_sink(
     .await
 }
 
+// 规则节点没有独立的id，以规则id作为资源id，同一规则对同一schema只记录一次
+pub async fn insert_rule_node(schema_id: &String, rule_id: &String) -> HaliaResult<()> {
+    insert(
+        schema_id,
+        ParentType::Rule,
+        rule_id,
+        ResourceType::Node,
+        rule_id,
+    )
+    .await
+}
+
 async fn insert(
     schema_id: &String,
     parent_type: ParentType,
@@ -140,6 +152,15 @@ async fn insert(
     Ok(())
 }
 
+pub async fn delete_many_by_parent_id(parent_id: &String) -> HaliaResult<()> {
+    sqlx::query(format!("DELETE FROM {} WHERE parent_id = ?", TABLE_NAME).as_str())
+        .bind(parent_id)
+        .execute(POOL.get().unwrap())
+        .await?;
+
+    Ok(())
+}
+
 pub async fn count_by_schema_id(schema_id: &String) -> HaliaResult<usize> {
     let count: i64 = sqlx::query_scalar(
         format!("SELECT COUNT(*) FROM {} WHERE schema_id = ?", TABLE_NAME).as_str(),
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{DecodeType, EncodeType};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CoapServerConf {
+    // 监听地址
+    pub host: String,
+    pub port: u16,
+}
+
+// 接收客户端对该路径的POST及PUT请求
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct SourceConf {
+    pub path: String,
+    pub decode_type: DecodeType,
+    pub schema_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListSourceConf {
+    pub path: String,
+    pub decode_type: DecodeType,
+}
+
+// 规则输出的最新消息，客户端可通过GET读取或Observe订阅该路径
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct SinkConf {
+    pub path: String,
+    pub encode_type: EncodeType,
+    pub schema_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListSinkConf {
+    pub path: String,
+    pub encode_type: EncodeType,
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::schema::DecodeType;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct HttpServerConf {
+    // 监听地址
+    pub host: String,
+    pub port: u16,
+}
+
+// 接收外部系统对该路径的POST及PUT请求
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct SourceConf {
+    pub path: String,
+    pub auth_method: AuthMethod,
+    // Authorization: Bearer <token>
+    pub auth_token: Option<String>,
+    pub auth_basic: Option<BasicAuth>,
+    pub decode_type: DecodeType,
+    pub schema_id: Option<String>,
+    // 数据接收成功后返回给调用方的响应
+    pub response: ResponseConf,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    None,
+    Token,
+    Basic,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct ResponseConf {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Serialize)]
+pub struct ListSourceConf {
+    pub path: String,
+    pub auth_method: AuthMethod,
+    pub decode_type: DecodeType,
+}
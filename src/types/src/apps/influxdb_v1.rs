@@ -35,6 +35,27 @@ pub struct AuthApiToken {
     pub api_token: String,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
+pub struct SourceConf {
+    pub database: String,
+    // InfluxQL查询语句，$start和$end会被替换为本次查询时间范围的纳秒时间戳，
+    // 如：SELECT mean(v) FROM m WHERE time >= $start AND time < $end GROUP BY time(1m)
+    pub query: String,
+    // 查询间隔(ms)
+    pub interval: u64,
+    // 首次查询时向前回溯的时长(s)
+    pub initial_lookback: u64,
+    // 查询范围的结束时间相对当前时间的延迟(s)，用于等待延迟写入的数据
+    pub delay: u64,
+}
+
+#[derive(Serialize)]
+pub struct ListSourceConf {
+    pub database: String,
+    pub query: String,
+    pub interval: u64,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
 pub struct SinkConf {
     pub database: String,
@@ -43,9 +64,20 @@ pub struct SinkConf {
     pub fields: Vec<(String, serde_json::Value)>,
     pub tags: Option<Vec<(String, serde_json::Value)>>,
     pub precision: Precision,
+    // 时间戳字段，值为毫秒时间戳或RFC3339字符串，为空或消息中不存在时使用消息元数据中的timestamp
+    pub timestamp_field: Option<String>,
+    // 累计的点数达到batch_size或等待时间超过batch_timeout(ms)时批量写入，默认逐条写入
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default)]
+    pub batch_timeout: u64,
     pub message_retain: MessageRetain,
 }
 
+fn default_batch_size() -> usize {
+    1
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum Precision {
@@ -1,16 +1,55 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{MessageRetain, PlainOrBase64Value};
+use crate::{schema::DecodeType, MessageRetain, PlainOrBase64Value, SslConf};
 
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
 pub struct Conf {
     pub bootstrap_brokers: Vec<(String, u16)>,
 
-    // pub ssl: bool,
-    // pub certifacte_verfication: bool,
+    // 重连间隔，单位为s
     pub reconnect: u64,
-    // 超时时间，单位为s
-    // pub timeout: usize,
+
+    pub ssl_enable: bool,
+    pub ssl_conf: Option<SslConf>,
+
+    pub sasl_enable: bool,
+    pub sasl_conf: Option<SaslConf>,
+}
+
+// 目前仅支持PLAIN机制
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
+pub struct SaslConf {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
+pub struct SourceConf {
+    pub topic: String,
+    pub partition: i32,
+    pub unknown_topic_handling: UnknownTopicHandling,
+    // 首次消费或偏移量失效时开始消费的位置
+    pub offset_reset: OffsetReset,
+    // 单次拉取的最大字节数
+    pub max_bytes: i32,
+    // 单次拉取的最长等待时间，单位为ms
+    pub max_wait_ms: i32,
+    pub decode_type: DecodeType,
+    pub schema_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListSourceConf {
+    pub topic: String,
+    pub partition: i32,
+    pub decode_type: DecodeType,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum OffsetReset {
+    Earliest,
+    Latest,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
@@ -27,6 +66,13 @@ pub struct SinkConf {
     pub message_retain: MessageRetain,
 }
 
+#[derive(Serialize)]
+pub struct ListSinkConf {
+    pub topic: String,
+    pub partition: i32,
+    pub compression: Compression,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum UnknownTopicHandling {
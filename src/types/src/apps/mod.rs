@@ -5,12 +5,16 @@ use serde::{Deserialize, Serialize};
 
 use crate::{RuleRefCnt, Status};
 
+pub mod coap_server;
 pub mod http_client;
+pub mod http_server;
 pub mod influxdb_v1;
 pub mod influxdb_v2;
 pub mod kafka;
 pub mod mqtt_client_v311;
 pub mod mqtt_client_v50;
+pub mod mqtt_server;
+pub mod opcua_server;
 pub mod tdengine;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
@@ -36,6 +40,10 @@ pub enum AppType {
     InfluxdbV1,
     InfluxdbV2,
     Tdengine,
+    OpcuaServer,
+    MqttServer,
+    CoapServer,
+    HttpServer,
 }
 
 impl Into<i32> for AppType {
@@ -48,6 +56,10 @@ impl Into<i32> for AppType {
             AppType::InfluxdbV1 => 40,
             AppType::InfluxdbV2 => 41,
             AppType::Tdengine => 5,
+            AppType::OpcuaServer => 6,
+            AppType::MqttServer => 7,
+            AppType::CoapServer => 8,
+            AppType::HttpServer => 9,
         }
     }
 }
@@ -64,6 +76,10 @@ impl TryFrom<i32> for AppType {
             40 => Ok(AppType::InfluxdbV1),
             41 => Ok(AppType::InfluxdbV2),
             5 => Ok(AppType::Tdengine),
+            6 => Ok(AppType::OpcuaServer),
+            7 => Ok(AppType::MqttServer),
+            8 => Ok(AppType::CoapServer),
+            9 => Ok(AppType::HttpServer),
             _ => bail!("未知应用类型: {}", value),
         }
     }
@@ -78,6 +94,10 @@ impl fmt::Display for AppType {
             AppType::InfluxdbV1 | AppType::InfluxdbV2 => write!(f, "influxdb"),
             AppType::Tdengine => write!(f, "tdengine"),
             AppType::MqttV50 => write!(f, "mqtt_v50"),
+            AppType::OpcuaServer => write!(f, "opcua_server"),
+            AppType::MqttServer => write!(f, "mqtt_server"),
+            AppType::CoapServer => write!(f, "coap_server"),
+            AppType::HttpServer => write!(f, "http_server"),
         }
     }
 }
@@ -94,6 +114,10 @@ impl TryFrom<String> for AppType {
             "influxdb_v1" => Ok(AppType::InfluxdbV1),
             "influxdb_v2" => Ok(AppType::InfluxdbV2),
             "tdengine" => Ok(AppType::Tdengine),
+            "opcua_server" => Ok(AppType::OpcuaServer),
+            "mqtt_server" => Ok(AppType::MqttServer),
+            "coap_server" => Ok(AppType::CoapServer),
+            "http_server" => Ok(AppType::HttpServer),
             _ => bail!("未知应用类型: {}", value),
         }
     }
@@ -214,4 +238,7 @@ pub struct ReadSourceSinkResp {
     pub err: Option<Arc<String>>,
     #[serde(flatten)]
     pub rule_ref_cnt: RuleRefCnt,
+    // 动作保留在磁盘队列中等待重发的消息数量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retain_depth: Option<usize>,
 }
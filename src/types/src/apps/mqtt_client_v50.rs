@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
-use crate::{MessageRetain, PlainOrBase64Value, SslConf};
+use crate::{schema::DecodeType, MessageRetain, PlainOrBase64Value, SslConf};
 
 #[derive(Deserialize, Serialize, PartialEq)]
 pub struct MqttClientConf {
@@ -91,6 +91,18 @@ pub struct SourceConf {
 
     pub subscribe_properties_enable: bool,
     pub subscribe_properties: Option<SubscribeProperties>,
+
+    // 兼容未保存解码方式的旧配置
+    #[serde(default)]
+    pub decode_type: DecodeType,
+    pub schema_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListSourceConf {
+    pub topic: String,
+    pub qos: Qos,
+    pub decode_type: DecodeType,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
@@ -127,8 +139,9 @@ pub struct PublishProperties {
     pub payload_format_indicator: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content_type: Option<String>,
+    // 数字或"${field}"，为字段时从消息中取值
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub message_expiry_interval: Option<u32>,
+    pub message_expiry_interval: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub topic_alias: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{DecodeType, EncodeType};
+
+// 订阅内置mqtt服务器上的主题，支持通配符
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct SourceConf {
+    pub topic: String,
+    pub decode_type: DecodeType,
+    pub schema_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListSourceConf {
+    pub topic: String,
+    pub decode_type: DecodeType,
+}
+
+// 发布到内置mqtt服务器上的主题
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct SinkConf {
+    pub topic: String,
+    pub encode_type: EncodeType,
+    pub schema_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListSinkConf {
+    pub topic: String,
+    pub encode_type: EncodeType,
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct OpcuaServerConf {
+    // 监听地址
+    pub host: String,
+    pub port: u16,
+    // 端点路径，如/halia
+    pub path: String,
+
+    pub auth_method: AuthMethod,
+
+    // 挂载在Databoard目录下的数据看板数据
+    #[serde(default)]
+    pub databoard_datas: Vec<DataboardDataConf>,
+}
+
+// 仅支持SecurityPolicy None，用户名密码会以明文传输，因此只提供匿名认证
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    Anonymous,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DataboardDataConf {
+    // 变量名称
+    pub name: String,
+    pub databoard_id: String,
+    pub data_id: String,
+}
+
+// 每个动作对应地址空间中的一个目录，目录下的变量值取自规则中的消息
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SinkConf {
+    pub folder: String,
+    pub variables: Vec<VariableConf>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct VariableConf {
+    // 变量名称
+    pub name: String,
+    // 消息中的字段
+    pub field: String,
+    // 是否允许客户端写入，写入的值由对应目录的源输出
+    #[serde(default)]
+    pub writable: bool,
+}
+
+// 输出客户端对指定目录下变量的写入
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SourceConf {
+    pub folder: String,
+}
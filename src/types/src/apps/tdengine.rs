@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::MessageRetain;
+
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
 pub struct TDengineConf {
     pub host: String,
@@ -25,6 +27,59 @@ pub struct TDengineAuthPassword {
 #[derive(Deserialize, Serialize, PartialEq, Clone)]
 pub struct SinkConf {
     pub db: String,
+    pub mode: IngestMode,
+    // 超级表，不存在时自动创建
+    pub stable: String,
+    // 子表名，支持${field}引用消息字段，不存在时使用超级表和tags自动创建
+    // schemaless模式下子表名由TDengine根据tags生成，该配置不生效
     pub table: String,
-    pub values: Vec<serde_json::Value>,
+    pub tags: Vec<Column>,
+    pub columns: Vec<Column>,
+    // 时间戳字段，值为毫秒时间戳或RFC3339字符串，为空或消息中不存在时使用消息元数据中的timestamp
+    pub timestamp_field: Option<String>,
+    pub message_retain: MessageRetain,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestMode {
+    // 参数绑定写入
+    Stmt,
+    // 无模式写入，使用行协议
+    Schemaless,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: DataType,
+    // 常量或${field}引用消息字段
+    pub value: serde_json::Value,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum DataType {
+    Bool,
+    TinyInt,
+    SmallInt,
+    Int,
+    BigInt,
+    UtinyInt,
+    UsmallInt,
+    Uint,
+    UbigInt,
+    Float,
+    Double,
+    // 长度为字节数
+    Varchar(u16),
+    Nchar(u16),
+}
+
+#[derive(Serialize)]
+pub struct ListSinkConf {
+    pub db: String,
+    pub mode: IngestMode,
+    pub stable: String,
 }
@@ -1,9 +1,45 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{schema::DecodeType, PlainOrBase64Value};
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct DeviceConf {
     pub host: String,
     pub port: u16,
+    // 为空时使用coap，否则使用coaps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dtls: Option<DtlsConf>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DtlsConf {
+    pub mode: DtlsMode,
+    pub psk: Option<DtlsPskConf>,
+    pub cert: Option<DtlsCertConf>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DtlsMode {
+    Psk,
+    Cert,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DtlsPskConf {
+    pub identity: String,
+    pub key: PlainOrBase64Value,
+}
+
+// 证书均为PEM格式，私钥仅支持ECDSA P-256及Ed25519
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DtlsCertConf {
+    pub verify: bool,
+    // 校验服务端证书时使用的域名，为空时使用host
+    pub server_name: Option<String>,
+    pub ca_cert: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
@@ -44,6 +80,12 @@ pub struct SourceConf {
     pub get: Option<SourceGetConf>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub observe: Option<SourceObserveConf>,
+
+    // 响应未携带Content-Format或无法识别时使用的解码方式
+    #[serde(default)]
+    pub decode_type: DecodeType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_id: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
@@ -55,11 +97,15 @@ pub enum SourceMethod {
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct SourceGetConf {
+    // 毫秒
     pub interval: u64,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
-pub struct SourceObserveConf {}
+pub struct SourceObserveConf {
+    // 秒，注册失败或超过Max-Age未收到通知时重新注册的间隔
+    pub retry_interval: u64,
+}
 
 // #[derive(Serialize)]
 // pub struct SearchObservesResp {
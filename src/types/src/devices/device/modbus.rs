@@ -21,9 +21,38 @@ pub struct DeviceConf {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub serial: Option<Serial>,
 
+    // 合并读取，为空时每个点位单独读取
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coalesce: Option<Coalesce>,
+
     pub metadatas: Option<Vec<(String, serde_json::Value)>>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Coalesce {
+    // 相邻点位之间允许的最大间隔，单位为寄存器或线圈数量
+    pub max_gap: u16,
+    // 响应PDU的最大字节数，协议上限为253
+    #[serde(default = "default_max_pdu_size")]
+    pub max_pdu_size: u16,
+}
+
+fn default_max_pdu_size() -> u16 {
+    253
+}
+
+impl Coalesce {
+    // 单次读取寄存器的最大数量
+    pub fn max_registers(&self) -> u16 {
+        ((self.max_pdu_size.min(253).saturating_sub(2)) / 2).clamp(1, 125)
+    }
+
+    // 单次读取线圈、离散输入的最大数量
+    pub fn max_bits(&self) -> u16 {
+        ((self.max_pdu_size.min(253).saturating_sub(2)) * 8).clamp(1, 2000)
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Ethernet {
     pub mode: Mode,
@@ -34,6 +63,8 @@ pub struct Ethernet {
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Serial {
+    #[serde(default)]
+    pub mode: Mode,
     pub path: String,
     pub stop_bits: StopBits,
     pub baud_rate: u32,
@@ -125,6 +156,12 @@ pub enum Mode {
     Server,
 }
 
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Client
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum Encode {
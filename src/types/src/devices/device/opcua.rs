@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::MessageRetain;
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct OpcuaConf {
     pub host: String,
@@ -9,7 +11,36 @@ pub struct OpcuaConf {
     pub auth_method: AuthMethod,
     pub auth_username: Option<AuthUsername>,
     pub auth_certificate: Option<AuthCertificate>,
-    // pub use_security: bool,
+
+    #[serde(default)]
+    pub security_policy: SecurityPolicy,
+    #[serde(default)]
+    pub security_mode: SecurityMode,
+    // 客户端证书，为空时自动生成自签名证书
+    pub client_certificate: Option<AuthCertificate>,
+    // 信任的服务端证书列表，pem格式
+    #[serde(default)]
+    pub trusted_server_certs: Vec<String>,
+    // 是否自动信任未知的服务端证书
+    #[serde(default)]
+    pub trust_unknown_server_certs: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SecurityPolicy {
+    #[default]
+    None,
+    Basic256Sha256,
+    Aes128Sha256RsaOaep,
+    Aes256Sha256RsaPss,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SecurityMode {
+    #[default]
+    None,
+    Sign,
+    SignAndEncrypt,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
@@ -123,12 +154,16 @@ pub struct Subscriptionconf {
 pub struct MonitoredItemconf {
     #[serde(flatten)]
     pub variable: VariableConf,
+    // 13为Value属性
     pub attribute_id: u32,
+    // 为空时不生效
     pub index_range: String,
     pub data_encoding: String,
     pub monitoring_mode: MonitoringMode,
 
+    // 同一订阅内不可重复
     pub client_handle: u32,
+    // 毫秒
     pub sampling_interval: f64,
     // pub filter:
     pub queue_size: u32,
@@ -143,8 +178,77 @@ pub enum MonitoringMode {
     Reporting,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct SinkConf {
     pub field: String,
     pub node_id: NodeId,
+    pub message_retain: MessageRetain,
+}
+
+#[derive(Deserialize)]
+pub struct BrowseReq {
+    // 为空时从Objects目录开始浏览
+    pub node_id: Option<NodeId>,
+}
+
+#[derive(Deserialize)]
+pub struct BrowseNextReq {
+    pub continuation_point: String,
+}
+
+#[derive(Serialize)]
+pub struct BrowseResp {
+    pub references: Vec<BrowseReference>,
+    // 不为空时需通过browse_next继续获取
+    pub continuation_point: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BrowseReference {
+    pub node_id: NodeId,
+    pub browse_name: String,
+    pub display_name: String,
+    pub node_class: NodeClass,
+    // 仅变量节点有值
+    pub data_type: Option<String>,
+    pub access_level: Option<AccessLevel>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeClass {
+    Unspecified,
+    Object,
+    Variable,
+    Method,
+    ObjectType,
+    VariableType,
+    ReferenceType,
+    DataType,
+    View,
+}
+
+#[derive(Serialize)]
+pub struct AccessLevel {
+    pub readable: bool,
+    pub writable: bool,
+}
+
+#[derive(Deserialize)]
+pub struct TranslateBrowsePathReq {
+    // 为空时从Objects目录开始
+    pub start_node_id: Option<NodeId>,
+    // 浏览名称路径，格式为"命名空间:名称"，如["2:Device1", "2:Temperature"]
+    pub browse_path: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct TranslateBrowsePathResp {
+    pub node_ids: Vec<NodeId>,
+}
+
+#[derive(Serialize)]
+pub struct ClientCertificateResp {
+    // pem格式
+    pub cert: String,
 }
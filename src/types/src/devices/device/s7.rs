@@ -1,17 +1,310 @@
+use anyhow::{bail, Result};
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use message::MessageValue;
+use serde::{Deserialize, Serialize};
+
+use crate::MessageRetain;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct S7Conf {
     pub cpu_type: CpuType,
     pub host: String,
     // default: 102
+    #[serde(default = "default_port")]
     pub port: u16,
 
     pub rack: u16,
-    pub slot: u16
+    pub slot: u16,
+
+    // ms，两次请求之间的间隔
+    pub interval: u64,
+    // s
+    pub reconnect: u64,
+
+    pub metadatas: Option<Vec<(String, serde_json::Value)>>,
 }
 
+fn default_port() -> u16 {
+    102
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub enum CpuType {
     S200,
     S300,
     S400,
     S1200,
     S1500,
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SourceConf {
+    // 字段名称
+    pub field: String,
+
+    pub area: Area,
+    // 仅DB区需要
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub db_number: Option<u16>,
+    // 字节地址
+    pub address: u32,
+    #[serde(flatten)]
+    pub data_type: DataType,
+    pub interval: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadatas: Option<Vec<(String, serde_json::Value)>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SinkConf {
+    pub area: Area,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub db_number: Option<u16>,
+    pub address: u32,
+    #[serde(flatten)]
+    pub data_type: DataType,
+    pub value: serde_json::Value,
+    pub message_retain: MessageRetain,
+}
+
+// S7-200的V区对应DB1
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Area {
+    // I
+    Input,
+    // Q
+    Output,
+    // M
+    Merker,
+    // DB
+    DataBlock,
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+pub struct DataType {
+    #[serde(rename = "type")]
+    pub typ: Type,
+
+    // bool的位地址，0-7
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bit: Option<u8>,
+    // string为最大字符数，bytes为字节数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub len: Option<u16>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    Bool,
+    Byte,
+    Word,
+    Dword,
+    Lword,
+    Sint,
+    Usint,
+    Int,
+    Uint,
+    Dint,
+    Udint,
+    Lint,
+    Ulint,
+    Real,
+    Lreal,
+    Char,
+    // 前两个字节为最大长度和实际长度
+    String,
+    Bytes,
+}
+
+impl DataType {
+    pub fn validate(&self) -> Result<()> {
+        match self.typ {
+            Type::Bool => match self.bit {
+                Some(bit) if bit <= 7 => {}
+                Some(_) => bail!("位地址必须为0-7！"),
+                None => bail!("必须填写位地址！"),
+            },
+            Type::String => match self.len {
+                Some(len) if len > 0 && len <= 254 => {}
+                Some(_) => bail!("字符串长度必须为1-254！"),
+                None => bail!("必须填写长度配置！"),
+            },
+            Type::Bytes => match self.len {
+                Some(len) if len > 0 => {}
+                _ => bail!("必须填写长度配置！"),
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // 读取的字节数量
+    pub fn get_size(&self) -> u16 {
+        match self.typ {
+            Type::Bool | Type::Byte | Type::Sint | Type::Usint | Type::Char => 1,
+            Type::Word | Type::Int | Type::Uint => 2,
+            Type::Dword | Type::Dint | Type::Udint | Type::Real => 4,
+            Type::Lword | Type::Lint | Type::Ulint | Type::Lreal => 8,
+            Type::String => *self.len.as_ref().unwrap() + 2,
+            Type::Bytes => *self.len.as_ref().unwrap(),
+        }
+    }
+
+    pub fn decode(&self, data: &[u8]) -> MessageValue {
+        match self.typ {
+            Type::Bool => match data.first() {
+                Some(v) => MessageValue::Boolean(*v & 0x01 == 0x01),
+                None => MessageValue::Null,
+            },
+            Type::Byte | Type::Usint => match data {
+                [a] => MessageValue::Int64(*a as i64),
+                _ => MessageValue::Null,
+            },
+            Type::Sint => match data {
+                [a] => MessageValue::Int64(*a as i8 as i64),
+                _ => MessageValue::Null,
+            },
+            Type::Char => match data {
+                [a] => MessageValue::String((*a as char).to_string()),
+                _ => MessageValue::Null,
+            },
+            Type::Word | Type::Uint => match data {
+                [a, b] => MessageValue::Int64(u16::from_be_bytes([*a, *b]) as i64),
+                _ => MessageValue::Null,
+            },
+            Type::Int => match data {
+                [a, b] => MessageValue::Int64(i16::from_be_bytes([*a, *b]) as i64),
+                _ => MessageValue::Null,
+            },
+            Type::Dword | Type::Udint => match data {
+                [a, b, c, d] => MessageValue::Int64(u32::from_be_bytes([*a, *b, *c, *d]) as i64),
+                _ => MessageValue::Null,
+            },
+            Type::Dint => match data {
+                [a, b, c, d] => MessageValue::Int64(i32::from_be_bytes([*a, *b, *c, *d]) as i64),
+                _ => MessageValue::Null,
+            },
+            Type::Real => match data {
+                [a, b, c, d] => MessageValue::Float64(f32::from_be_bytes([*a, *b, *c, *d]) as f64),
+                _ => MessageValue::Null,
+            },
+            Type::Lword | Type::Lint | Type::Ulint | Type::Lreal => {
+                let data: [u8; 8] = match data.try_into() {
+                    Ok(data) => data,
+                    Err(_) => return MessageValue::Null,
+                };
+                match self.typ {
+                    Type::Lreal => MessageValue::Float64(f64::from_be_bytes(data)),
+                    _ => MessageValue::Int64(i64::from_be_bytes(data)),
+                }
+            }
+            Type::String => {
+                if data.len() < 2 {
+                    return MessageValue::Null;
+                }
+                let len = (data[1] as usize).min(data[0] as usize).min(data.len() - 2);
+                match String::from_utf8(data[2..2 + len].to_vec()) {
+                    Ok(string) => MessageValue::String(string),
+                    Err(_) => MessageValue::Null,
+                }
+            }
+            Type::Bytes => MessageValue::Bytes(data.to_vec()),
+        }
+    }
+
+    pub fn encode(&self, value: serde_json::Value) -> Result<Vec<u8>> {
+        match self.typ {
+            Type::Bool => match value.as_bool() {
+                Some(value) => Ok(vec![value as u8]),
+                None => bail!("value is wrong"),
+            },
+            Type::Byte | Type::Usint => Ok(vec![encode_int::<u8>(&value)?]),
+            Type::Sint => Ok(encode_int::<i8>(&value)?.to_be_bytes().to_vec()),
+            Type::Char => match value.as_str() {
+                Some(value) if value.len() == 1 => Ok(value.as_bytes().to_vec()),
+                _ => bail!("value is wrong"),
+            },
+            Type::Word | Type::Uint => Ok(encode_int::<u16>(&value)?.to_be_bytes().to_vec()),
+            Type::Int => Ok(encode_int::<i16>(&value)?.to_be_bytes().to_vec()),
+            Type::Dword | Type::Udint => Ok(encode_int::<u32>(&value)?.to_be_bytes().to_vec()),
+            Type::Dint => Ok(encode_int::<i32>(&value)?.to_be_bytes().to_vec()),
+            Type::Lint => match value.as_i64() {
+                Some(value) => Ok(value.to_be_bytes().to_vec()),
+                None => bail!("value is wrong"),
+            },
+            Type::Lword | Type::Ulint => match value.as_u64() {
+                Some(value) => Ok(value.to_be_bytes().to_vec()),
+                None => bail!("value is wrong"),
+            },
+            Type::Real => match value.as_f64() {
+                Some(value) => {
+                    if value.abs() > f32::MAX as f64 {
+                        bail!("value is wrong, too big")
+                    }
+                    Ok((value as f32).to_be_bytes().to_vec())
+                }
+                None => bail!("value is wrong"),
+            },
+            Type::Lreal => match value.as_f64() {
+                Some(value) => Ok(value.to_be_bytes().to_vec()),
+                None => bail!("value is wrong"),
+            },
+            Type::String => match value.as_str() {
+                Some(value) => {
+                    let max_len = *self.len.as_ref().unwrap() as usize;
+                    let data = value.as_bytes();
+                    if data.len() > max_len {
+                        bail!("too long")
+                    }
+                    let mut new_data = vec![0; max_len + 2];
+                    new_data[0] = max_len as u8;
+                    new_data[1] = data.len() as u8;
+                    new_data[2..2 + data.len()].copy_from_slice(data);
+                    Ok(new_data)
+                }
+                None => bail!("value is wrong"),
+            },
+            Type::Bytes => {
+                let len = *self.len.as_ref().unwrap() as usize;
+                let data = match value {
+                    serde_json::Value::String(str) => match BASE64_STANDARD.decode(str) {
+                        Ok(data) => data,
+                        Err(e) => bail!(e),
+                    },
+                    serde_json::Value::Array(array) => {
+                        let mut data = vec![];
+                        for item in array.iter() {
+                            match item.as_u64() {
+                                Some(n) if n <= 255 => data.push(n as u8),
+                                Some(_) => bail!("数字大于255"),
+                                None => bail!("数据含有非数字"),
+                            }
+                        }
+                        data
+                    }
+                    _ => bail!("不支持的类型"),
+                };
+                if data.len() > len {
+                    bail!("值太长");
+                }
+                let mut new_data = vec![0; len];
+                new_data[..data.len()].copy_from_slice(&data);
+                Ok(new_data)
+            }
+        }
+    }
+}
+
+fn encode_int<T: TryFrom<i64>>(value: &serde_json::Value) -> Result<T> {
+    match value.as_i64() {
+        Some(value) => match value.try_into() {
+            Ok(value) => Ok(value),
+            Err(_) => bail!("value is wrong, out of range"),
+        },
+        None => bail!("value is wrong"),
+    }
+}
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-use crate::devices::device::modbus::{DataBits, Encode, LinkType, Mode, Parity, StopBits};
+use crate::devices::device::modbus::{
+    Coalesce, DataBits, Encode, LinkType, Mode, Parity, StopBits,
+};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct CustomizeConf {
@@ -35,6 +37,9 @@ pub struct TemplateConf {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub serial: Option<SerialTemplateConf>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coalesce: Option<Coalesce>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
@@ -45,6 +50,8 @@ pub struct EthernetTemplateConf {
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct SerialTemplateConf {
+    #[serde(default)]
+    pub mode: Mode,
     pub stop_bits: StopBits,
     pub baud_rate: u32,
     pub data_bits: DataBits,
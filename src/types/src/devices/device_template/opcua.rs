@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-use crate::devices::device::opcua::{AuthCertificate, AuthMethod, AuthUsername};
+use crate::devices::device::opcua::{
+    AuthCertificate, AuthMethod, AuthUsername, SecurityMode, SecurityPolicy,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CustomizeConf {
@@ -15,4 +17,14 @@ pub struct TemplateConf {
     pub auth_method: AuthMethod,
     pub auth_username: Option<AuthUsername>,
     pub auth_certificate: Option<AuthCertificate>,
-}
\ No newline at end of file
+
+    #[serde(default)]
+    pub security_policy: SecurityPolicy,
+    #[serde(default)]
+    pub security_mode: SecurityMode,
+    pub client_certificate: Option<AuthCertificate>,
+    #[serde(default)]
+    pub trusted_server_certs: Vec<String>,
+    #[serde(default)]
+    pub trust_unknown_server_certs: bool,
+}
@@ -15,6 +15,7 @@ pub enum DeviceType {
     Modbus,
     Opcua,
     Coap,
+    S7,
 }
 
 impl Into<i32> for DeviceType {
@@ -23,6 +24,7 @@ impl Into<i32> for DeviceType {
             DeviceType::Modbus => 1,
             DeviceType::Opcua => 2,
             DeviceType::Coap => 3,
+            DeviceType::S7 => 4,
         }
     }
 }
@@ -35,6 +37,7 @@ impl TryFrom<i32> for DeviceType {
             1 => Ok(DeviceType::Modbus),
             2 => Ok(DeviceType::Opcua),
             3 => Ok(DeviceType::Coap),
+            4 => Ok(DeviceType::S7),
             _ => bail!("未知协议类型: {}", value),
         }
     }
@@ -46,6 +49,7 @@ impl fmt::Display for DeviceType {
             DeviceType::Modbus => write!(f, "modbus"),
             DeviceType::Opcua => write!(f, "opcua"),
             DeviceType::Coap => write!(f, "coap"),
+            DeviceType::S7 => write!(f, "s7"),
         }
     }
 }
@@ -58,6 +62,7 @@ impl TryFrom<&str> for DeviceType {
             "modbus" => Ok(DeviceType::Modbus),
             "opcua" => Ok(DeviceType::Opcua),
             "coap" => Ok(DeviceType::Coap),
+            "s7" => Ok(DeviceType::S7),
             _ => bail!("未知协议类型: {}", value),
         }
     }
@@ -180,6 +185,9 @@ pub struct ReadSourceSinkResp {
     pub err: Option<String>,
     #[serde(flatten)]
     pub rule_ref_cnt: RuleRefCnt,
+    // 动作保留在磁盘队列中等待重发的消息数量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retain_depth: Option<usize>,
 }
 
 #[derive(Deserialize)]
@@ -78,7 +78,10 @@ pub struct MessageRetain {
     #[serde(rename = "type")]
     pub typ: MessageRetainType,
     pub count: Option<usize>,
+    // 秒，latest_time 为保留时长，disk 为消息的最长保留时间
     pub time: Option<u64>,
+    // MB，disk 模式下磁盘队列的最大占用空间
+    pub size: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
@@ -88,6 +91,8 @@ pub enum MessageRetainType {
     None,
     LatestCount,
     LatestTime,
+    // 保存至磁盘队列，重启后不丢失
+    Disk,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
@@ -1,28 +1,74 @@
-use std::net::SocketAddr;
+use std::{collections::HashMap, net::SocketAddr};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize)]
+// 内置mqtt服务器配置，未配置任何监听时不启动
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct MqttServerConf {
+    #[serde(default)]
     pub router: RouterConf,
-    pub v4: ServerSettings,
-    pub v5: ServerSettings,
+    // mqtt v3.1.1 监听
+    pub v4: Option<ServerSettings>,
+    // mqtt v5 监听
+    pub v5: Option<ServerSettings>,
+    // websocket 监听（mqtt v3.1.1）
+    pub ws: Option<ServerSettings>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct ServerSettings {
-    pub name: String,
     pub listen: SocketAddr,
+    pub tls: Option<TlsConf>,
+    #[serde(default = "default_next_connection_delay_ms")]
     pub next_connection_delay_ms: u64,
+    #[serde(default = "default_connection_timeout_ms")]
     pub connection_timeout_ms: u16,
+    #[serde(default = "default_max_payload_size")]
     pub max_payload_size: usize,
+    #[serde(default = "default_max_inflight_count")]
     pub max_inflight_count: usize,
+    // 用户名 -> 密码，为空时允许匿名连接
+    pub auth: Option<HashMap<String, String>>,
 }
 
-#[derive(Deserialize, Serialize)]
+// 证书及私钥均为文件路径
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TlsConf {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct RouterConf {
     pub max_connections: usize,
     pub max_outgoing_packet_count: u64,
     pub max_segment_size: usize,
     pub max_segment_count: usize,
 }
+
+impl Default for RouterConf {
+    fn default() -> Self {
+        Self {
+            max_connections: 10010,
+            max_outgoing_packet_count: 200,
+            max_segment_size: 104857600,
+            max_segment_count: 10,
+        }
+    }
+}
+
+fn default_next_connection_delay_ms() -> u64 {
+    1
+}
+
+fn default_connection_timeout_ms() -> u16 {
+    60000
+}
+
+fn default_max_payload_size() -> usize {
+    20480
+}
+
+fn default_max_inflight_count() -> usize {
+    100
+}
@@ -1,9 +1,16 @@
 use serde::{Deserialize, Serialize};
 
+use super::GroupBy;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Conf {
     #[serde(rename = "conf")]
     pub items: Vec<ItemConf>,
+    // 按分组各输出一条消息，分组的值写入输出消息的同名字段或元数据
+    #[serde(default)]
+    pub group_by: Vec<GroupBy>,
+    // 单个批次内的分组数上限，超出的分组被丢弃
+    pub max_groups: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use super::GroupBy;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Conf {
+    // 各字段之间为或的关系，任一字段的变化超出死区即转发消息
+    #[serde(rename = "conf", default)]
+    pub items: Vec<ItemConf>,
+    // ms，距上次转发超过该时长时，即使没有变化也转发
+    pub heartbeat: Option<u64>,
+    // 按分组分别保存上次转发的值
+    #[serde(default)]
+    pub group_by: Vec<GroupBy>,
+    pub max_groups: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ItemConf {
+    #[serde(rename = "type")]
+    pub typ: Type,
+    pub field: String,
+    // 死区大小，absolute 与 percent 时必填
+    pub value: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    // 值发生变化
+    Change,
+    // 与上次转发的值之差的绝对值超过死区
+    Absolute,
+    // 与上次转发的值相比变化的百分比超过死区
+    Percent,
+}
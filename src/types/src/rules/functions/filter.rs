@@ -2,8 +2,11 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Conf {
-    #[serde(rename = "conf")]
+    // 各条件之间为或的关系
+    #[serde(rename = "conf", default)]
     pub items: Vec<ItemConf>,
+    // 布尔表达式，与conf二选一
+    pub expr: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 pub mod aggregation;
+pub mod deadband;
 pub mod filter;
 pub mod window;
 
@@ -11,6 +12,21 @@ pub struct Conf {
     pub items: Vec<ItemConf>,
 }
 
+// 窗口与聚合的分组依据
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GroupBy {
+    #[serde(rename = "type")]
+    pub typ: GroupByType,
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupByType {
+    Field,
+    Metadata,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct ItemConf {
     #[serde(rename = "type")]
@@ -136,4 +152,12 @@ pub enum Type {
     TypeConversionFloat,
     TypeConversionInt,
     TypeConversionStr,
+
+    // 表达式
+    Expr,
+
+    // 有状态计算，按消息的 timestamp 元数据计算时间间隔
+    Delta,
+    Derivative,
+    Integral,
 }
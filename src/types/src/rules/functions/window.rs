@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::{GroupBy, GroupByType};
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Conf {
     #[serde(rename = "type")]
@@ -8,6 +10,13 @@ pub struct Conf {
     pub time_hopping: Option<TimeHopping>,
     pub time_session: Option<TimeSession>,
     pub count: Option<Count>,
+    // 为空时所有消息属于同一个分组
+    #[serde(default)]
+    pub group_by: Vec<GroupBy>,
+    // 同一窗口内的分组数上限，超出后新分组的消息被丢弃
+    pub max_groups: Option<usize>,
+    // 配置后时间窗口按消息自带的事件时间划分，否则按接收消息的处理时间
+    pub event_time: Option<EventTime>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -35,7 +44,9 @@ pub struct TimeHopping {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TimeSession {
+    // ms
     pub timeout: u64,
+    // ms
     pub max: u64,
 }
 
@@ -43,3 +54,41 @@ pub struct TimeSession {
 pub struct Count {
     pub count: u64,
 }
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EventTime {
+    // 事件时间所在的字段或元数据，消息默认带有毫秒级的 timestamp 元数据
+    #[serde(rename = "type")]
+    pub typ: GroupByType,
+    pub name: String,
+    #[serde(default)]
+    pub unit: TimestampUnit,
+    // ms，水位线落后于已收到的最大事件时间的时长
+    #[serde(default)]
+    pub allowed_lateness: u64,
+    #[serde(default)]
+    pub late_strategy: LateStrategy,
+    // ms，超过该时长未收到消息时输出所有未结束的窗口
+    pub idle_timeout: Option<u64>,
+}
+
+// 数值类型事件时间的单位，字符串类型按 RFC3339 解析
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampUnit {
+    S,
+    #[default]
+    Ms,
+    Us,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LateStrategy {
+    // 丢弃迟到的消息
+    #[default]
+    Drop,
+    // 迟到的消息带上值为true的 late 元数据，与窗口结果从同一输出发送，
+    // 所在的消息批同样带有 late 元数据，聚合节点原样输出而不对其聚合
+    Forward,
+}
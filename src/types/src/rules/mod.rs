@@ -3,7 +3,10 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
-use crate::Status;
+use crate::{
+    schema::{DecodeType, EncodeType},
+    Status,
+};
 
 pub mod apps;
 pub mod databoard;
@@ -24,6 +27,13 @@ pub struct CreateUpdateRuleReq {
     pub conf: Conf,
 }
 
+// 以SQL描述规则，编译为节点与边后存储
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CreateUpdateRuleSqlReq {
+    pub name: String,
+    pub sql: String,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct Conf {
     pub nodes: Vec<Node>,
@@ -54,10 +64,13 @@ pub enum NodeType {
     Aggregation,
     Filter,
     Computer,
+    Deadband,
     DeviceSink,
     AppSink,
     Databoard,
     BlackHole,
+    MqttServerSource,
+    MqttServerSink,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -84,6 +97,22 @@ pub struct AppSinkNode {
     pub sink_id: String,
 }
 
+// 直接订阅内置mqtt服务器上的主题，支持通配符
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MqttServerSourceNode {
+    pub topic: String,
+    pub decode_type: DecodeType,
+    pub schema_id: Option<String>,
+}
+
+// 直接发布到内置mqtt服务器上的主题
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MqttServerSinkNode {
+    pub topic: String,
+    pub encode_type: EncodeType,
+    pub schema_id: Option<String>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct DataboardNode {
     pub databoard_id: String,
@@ -37,10 +37,11 @@ impl TryFrom<i32> for SchemaType {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum DecodeType {
     Raw,
+    #[default]
     Json,
     Csv,
     Avro,
@@ -168,6 +169,7 @@ pub struct ListReferencesItem {
 pub enum ParentType {
     App,
     Device,
+    Rule,
 }
 
 impl Into<i32> for ParentType {
@@ -175,6 +177,7 @@ impl Into<i32> for ParentType {
         match self {
             ParentType::App => 1,
             ParentType::Device => 2,
+            ParentType::Rule => 3,
         }
     }
 }
@@ -186,6 +189,7 @@ impl TryFrom<i32> for ParentType {
         match value {
             1 => Ok(ParentType::App),
             2 => Ok(ParentType::Device),
+            3 => Ok(ParentType::Rule),
             _ => bail!("未知资源类型: {}", value),
         }
     }
@@ -196,6 +200,7 @@ impl TryFrom<i32> for ParentType {
 pub enum ResourceType {
     Source,
     Sink,
+    Node,
 }
 
 impl Into<i32> for ResourceType {
@@ -203,6 +208,7 @@ impl Into<i32> for ResourceType {
         match self {
             ResourceType::Source => 1,
             ResourceType::Sink => 2,
+            ResourceType::Node => 3,
         }
     }
 }
@@ -214,6 +220,7 @@ impl TryFrom<i32> for ResourceType {
         match value {
             1 => Ok(ResourceType::Source),
             2 => Ok(ResourceType::Sink),
+            3 => Ok(ResourceType::Node),
             _ => bail!("未知资源类型: {}", value),
         }
     }
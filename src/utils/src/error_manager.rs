@@ -120,9 +120,25 @@ impl ErrorManager {
         }
 
         match self.resource_type {
-            ResourceType::Device => todo!(),
-            ResourceType::DeviceSource => todo!(),
-            ResourceType::DeviceSink => todo!(),
+            ResourceType::Device => {
+                let _ = storage::device::device::update_status(
+                    &self.resource_id,
+                    types::Status::Running,
+                )
+                .await;
+                events::insert_connect_succeed(
+                    types::events::ResourceType::Device,
+                    &self.resource_id,
+                )
+                .await;
+            }
+            ResourceType::DeviceSource | ResourceType::DeviceSink => {
+                let _ = storage::device::source_sink::update_status(
+                    &self.resource_id,
+                    types::Status::Running,
+                )
+                .await;
+            }
             ResourceType::App => {
                 let _ =
                     storage::app::update_status(&self.resource_id, types::Status::Running).await;
@@ -136,7 +152,13 @@ impl ErrorManager {
                 )
                 .await;
             }
-            ResourceType::AppSink => todo!(),
+            ResourceType::AppSink => {
+                let _ = storage::app::source_sink::update_status(
+                    &self.resource_id,
+                    types::Status::Running,
+                )
+                .await;
+            }
         }
 
         if self.last_err.is_some() {